@@ -0,0 +1,63 @@
+use clap::{Parser, Subcommand};
+use shared_files::cli::CommonArgs;
+use shared_files::cli::{self, CliError, Validate};
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Packs a file into the fewest bits per byte its distinct values allow.
+    #[clap(alias = "c")]
+    Compress(CommonArgs),
+    /// Restores a file written by `compress`.
+    #[clap(alias = "d")]
+    Decompress(CommonArgs),
+}
+
+/// The main command line argument structure for the Bit Packing Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Bit Packing Utility.",
+    long_about = "A transform for low-cardinality data: if the input uses only K distinct byte values, every byte is replaced by its index in a table of those values, packed into ceil(log2 K) bits. Works well as a first step for DNA sequences, digit strings and telemetry enums.",
+    after_help = "
+    EXAMPLES:
+    # 1. Pack a DNA sequence (4 distinct values, 2 bits each)
+    bitpack_tool.exe compress genome.txt genome.ppcb
+
+    # 2. Pack and show statistics
+    bitpack_tool.exe c readings.bin readings.ppcb -s
+
+    # 3. Restore
+    bitpack_tool.exe d genome.ppcb genome.txt
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file exists and is a file.
+    /// 2. The parent directory for the output file exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        let common = match &self.command {
+            Commands::Compress(args) => args,
+            Commands::Decompress(args) => args,
+        };
+
+        // --- Input File Validation ---
+        cli::check_input(&common.input_file)?;
+
+        // --- Output Directory Validation ---
+        cli::check_output(&common.output_file)
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
@@ -0,0 +1,81 @@
+use clap::{Args, Parser, Subcommand};
+use purgepack_bwt::MAX_LEVEL;
+use shared_files::cli::CommonArgs;
+use shared_files::cli::{self, CliError, Validate};
+
+/// Arguments that only apply to compression.
+///
+/// Decompression reads these settings back from the file header.
+#[derive(Debug, Clone, Args)]
+pub struct CompressArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The block size in units of 100 000 bytes, as in bzip2. Larger blocks give the
+    /// sort more context and usually compress better, but take more time and memory
+    /// (about 24 bytes per input byte of the block while compressing).
+    #[arg(short, long, default_value_t = DEFAULT_LEVEL, value_parser = clap::value_parser!(u8).range(1..=MAX_LEVEL as i64))]
+    pub level: u8,
+}
+
+/// The level used when `--level` is not given.
+pub const DEFAULT_LEVEL: u8 = 9;
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Compresses a file block by block with BWT, move-to-front, run-length and Huffman coding.
+    #[clap(alias = "c")]
+    Compress(CompressArgs),
+    /// Restores a file written by `compress`.
+    #[clap(alias = "d")]
+    Decompress(CommonArgs),
+}
+
+/// The main command line argument structure for the BWT Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "BWT Block-Sorting Compressor Utility.",
+    long_about = "A bzip2-style compressor that splits the input into blocks and passes every block through the Burrows-Wheeler transform, move-to-front coding, run-length coding of zeros and a canonical Huffman code, writing a single framed file.",
+    after_help = "
+    EXAMPLES:
+    # 1. Compress with the default 900 000 byte blocks
+    bwt_tool.exe compress notes.txt notes.ppcb
+
+    # 2. Compress with 100 000 byte blocks to save memory and show statistics
+    bwt_tool.exe c notes.txt notes.ppcb --level 1 -s
+
+    # 3. Decompress
+    bwt_tool.exe d notes.ppcb restored.txt
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file exists and is a file.
+    /// 2. The parent directory for the output file exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        let common = match &self.command {
+            Commands::Compress(args) => &args.common,
+            Commands::Decompress(args) => args,
+        };
+
+        // --- Input File Validation ---
+        cli::check_input(&common.input_file)?;
+
+        // --- Output Directory Validation ---
+        cli::check_output(&common.output_file)
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
@@ -0,0 +1,347 @@
+//! A bzip2-style block-sorting compressor.
+//!
+//! The codec itself lives in the `purgepack_bwt` crate. This module handles the command
+//! line, the files and the statistics.
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+mod cli_parse;
+use purgepack_bwt::Bwt;
+use purgepack_bwt::{BlockSummary, FORMAT_VERSION, MODULE_ID};
+use shared_files::abi::{self, RawSeekReader, RawSeekWriter, ServiceResult};
+use shared_files::budget::MemoryBudget;
+use shared_files::cancel::{CancellableReader, is_cancelled_error};
+use shared_files::cli::{self, CliError};
+use shared_files::codec::{self, Compressor, Decompressor};
+use shared_files::core_header::{self};
+use shared_files::error::ExitStatus;
+use shared_files::events::{self, Event};
+use shared_files::output::{Overwrite, SafeWriter};
+use shared_files::progress::{self, ProgressReader};
+use shared_files::selftest::{SelfTestReport, SelfTestSink};
+use shared_files::stats::{DEFAULT_SAMPLE_INTERVAL, StatsFormat};
+use shared_files::{log_debug, log_error, log_info, log_warn};
+use std::ffi::c_char;
+
+/// The file extension for PurgePack Compressed Binary (PPCB) files.
+const FILE_EXTENSION: &str = "ppcb";
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("bwt", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Compress(args) => {
+                log_info!(
+                    "Compress: Input: {}, Output: {}, Level: {}",
+                    args.common.input_file.display(),
+                    args.common.output_file.display(),
+                    args.level
+                );
+                match compress_file(
+                    core,
+                    &args.common.input_file,
+                    args.common.output_file,
+                    args.level,
+                    args.common.stats.format(),
+                    args.common.overwrite.policy(),
+                ) {
+                    Ok(()) => log_info!("Compress: Success"),
+                    Err(e) if is_cancelled_error(&e) => {
+                        log_warn!("Compress: Cancelled");
+                        core_header::report_status(core, ExitStatus::Cancelled);
+                    }
+                    Err(e) => {
+                        log_error!("Compress: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Decompress(args) => {
+                log_info!(
+                    "Decompress: Input: {}, Output: {}",
+                    args.input_file.display(),
+                    args.output_file.display()
+                );
+                match decompress_file(
+                    core,
+                    &args.input_file,
+                    &args.output_file,
+                    args.stats.format(),
+                    args.overwrite.policy(),
+                ) {
+                    Ok(()) => log_info!("Decompress: Success"),
+                    Err(e) if is_cancelled_error(&e) => {
+                        log_warn!("Decompress: Cancelled");
+                        core_header::report_status(core, ExitStatus::Cancelled);
+                    }
+                    Err(e) => {
+                        log_error!("Decompress: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// Registers the pipeline step of the module with the core.
+#[unsafe(no_mangle)]
+extern "C" fn module_register(core: &core_header::CoreH) {
+    core_header::register_step(core, env!("CARGO_PKG_NAME"), pipeline_step);
+}
+
+/// Runs a step of a pipeline of the core, like `compress --level 9`, on the
+/// streams the core hands over instead of files.
+extern "C" fn pipeline_step(
+    core: &core_header::CoreH,
+    argc: usize,
+    argv: *const *const c_char,
+    input: &mut RawSeekReader,
+    output: &mut RawSeekWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    shared_files::log::init("bwt", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let args = unsafe { abi::args_from_c(argc, argv) };
+    abi::serve_step(core, input, output, result, |reader, writer| {
+        let mut reader = CancellableReader::new(reader, core);
+        match cli::parse_step::<cli_parse::CliArgs>(&args)?.command {
+            cli_parse::Commands::Compress(args) => {
+                let level = fit_level(core, args.level, codec::remaining_len(&mut reader)?)?;
+                Bwt { level }.compress_file(&mut reader, writer)
+            }
+            cli_parse::Commands::Decompress(_) => Bwt::default().decompress(&mut reader, writer),
+        }
+    })
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("bwt", core.log_level);
+    log_debug!("BWT module shutting down.");
+}
+
+/// Runs round trips of the codec on synthetic data for `purgepack test`.
+#[unsafe(no_mangle)]
+extern "C" fn module_selftest(_core: &core_header::CoreH, sink: &mut SelfTestSink) {
+    let mut report = SelfTestReport::new();
+    report.round_trip("level 1", &Bwt { level: 1 });
+    report.round_trip("default", &Bwt::default());
+    report.send(sink);
+}
+
+/// Compresses `input_file` into `output_file`.
+///
+/// # Arguments
+///
+/// * `input_file` - The path to the source file.
+/// * `output_file` - The path to the destination file. The `.ppcb` extension is added if
+///   it has no extension.
+/// * `level` - The block size in units of 100 000 bytes.
+/// * `stats` - The format to print statistics in, or `None` to skip them.
+/// * `overwrite` - What happens if the output file exists already.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+fn compress_file(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    mut output_file: PathBuf,
+    level: u8,
+    stats: Option<StatsFormat>,
+    overwrite: Overwrite,
+) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
+    if output_file.extension().is_none() {
+        output_file.set_extension(FILE_EXTENSION);
+        log_info!(
+            "Compress: Automatic extension '{}' placed on output file: {}",
+            FILE_EXTENSION,
+            output_file.display()
+        );
+    }
+    let input = File::open(input_file)?;
+    let original_len = input.metadata()?.len();
+    let level = fit_level(core, level, original_len)?;
+    let mut reader = BufReader::new(CancellableReader::new(
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress::terminal("Compress", Some(original_len)),
+        ),
+        core,
+    ));
+    let output_file = overwrite.resolve(&output_file)?;
+    let mut writer = BufWriter::new(SafeWriter::create(&output_file)?);
+
+    let summary = purgepack_bwt::compress(&mut reader, &mut writer, original_len, level)?;
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(
+            core,
+            main_timer,
+            summary,
+            &output,
+            original_len,
+            true,
+            format,
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns `level`, or the highest lower level whose blocks fit the memory limit of the
+/// core if the blocks of `level` do not.
+///
+/// # Errors
+///
+/// Returns a `MemoryLimit` error if not even the blocks of level 1 fit.
+fn fit_level(core: &core_header::CoreH, level: u8, original_len: u64) -> io::Result<u8> {
+    let budget = MemoryBudget::from_core(core);
+    let fitting = (1..=level)
+        .rev()
+        .find(|&level| budget.fits(purgepack_bwt::compress_memory(level, original_len)))
+        .unwrap_or(1);
+    budget.check(
+        purgepack_bwt::compress_memory(fitting, original_len),
+        "Sorting a block",
+    )?;
+    if fitting < level {
+        log_warn!(
+            "Compress: Level {} needs more memory than --max-memory allows, using level {}",
+            level,
+            fitting
+        );
+    }
+    Ok(fitting)
+}
+
+/// Restores the original data of `input_file` into `output_file`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// header or a block is invalid, the data is truncated or the restored data does not
+/// match the checksum.
+fn decompress_file(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    output_file: &Path,
+    stats: Option<StatsFormat>,
+    overwrite: Overwrite,
+) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
+    let input = File::open(input_file)?;
+    let compressed_len = input.metadata()?.len();
+    let mut reader = BufReader::new(CancellableReader::new(
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress::terminal("Decompress", Some(compressed_len)),
+        ),
+        core,
+    ));
+    let output_file = &overwrite.resolve(output_file)?;
+    let mut writer = BufWriter::new(SafeWriter::create(output_file)?);
+
+    let t_header = main_timer.start_section("Header Read");
+    let header = purgepack_bwt::read_header(&mut reader)?;
+    main_timer.add_section(t_header);
+
+    let summary = purgepack_bwt::decompress(&mut reader, &mut writer, &header)?;
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(
+            core,
+            main_timer,
+            summary,
+            &output,
+            compressed_len,
+            false,
+            format,
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the statistics of a finished operation and emits them as a `stats` event.
+///
+/// # Arguments
+///
+/// * `core` - The core the `stats` event is emitted to.
+/// * `main_timer` - The timer holding the sections of the operation.
+/// * `summary` - The number of blocks and the time spent in every stage of the pipeline.
+/// * `output` - The output file, whose length is the processed length.
+/// * `input_len` - The length of the input file.
+/// * `is_compression` - Whether the operation compressed the data.
+/// * `format` - The format to print the statistics in.
+fn print_statistics(
+    core: &core_header::CoreH,
+    main_timer: shared_files::stats::OptinalStatsTimer,
+    summary: BlockSummary,
+    output: &File,
+    input_len: u64,
+    is_compression: bool,
+    format: StatsFormat,
+) -> io::Result<()> {
+    let peak_memory = main_timer.peak_memory();
+    let throughput = main_timer.throughput();
+    let (total_duration, sections) = main_timer.end();
+    let output_len = output.metadata()?.len();
+    // The block counts are not part of the stats, so only the text report shows them.
+    if format == StatsFormat::Text {
+        println!("Blocks: {}", summary.blocks);
+        if summary.stored_blocks > 0 {
+            println!(
+                "Stored blocks: {} (already compressed or encrypted, stored without coding)",
+                summary.stored_blocks
+            );
+        }
+    }
+    let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
+        .algorithm_name("BWT Block-Sorting Compressor")
+        .algorithm_id(MODULE_ID)
+        .version_used(FORMAT_VERSION)
+        .original_len(input_len as usize)
+        .processed_len(output_len as usize)
+        .duration(total_duration)
+        .is_compression(is_compression)
+        .sections(sections)
+        .peak_memory(peak_memory)
+        .throughput(throughput)
+        .add_section("Burrows-Wheeler Transform", summary.times.bwt)
+        .add_section("Move-to-Front and Run-Length", summary.times.mtf)
+        .add_section("Huffman Coding", summary.times.huffman)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
+    println!("{}", calculated_stats.render(format));
+    events::emit(
+        core,
+        &Event::Stats {
+            stats: &calculated_stats,
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::container::{MAX_MEMORY_KIB, MAX_PASSES};
+use shared_files::cli::{self, CliError, OverwriteArgs, StatsArgs, Validate};
+
+#[derive(Debug, Clone, Args)]
+pub struct CommonArgs {
+    /// The path to the input file.
+    pub input_file: PathBuf,
+    /// The path where the output file will be written.
+    pub output_file: PathBuf,
+    /// Reads the secret from this file instead of prompting for a password, so scripts can
+    /// run without a terminal. Any file works; `keygen` writes a random one.
+    #[arg(short, long)]
+    pub key_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub stats: StatsArgs,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+}
+
+/// Arguments that only apply to encryption.
+///
+/// Decryption reads these settings back from the container header.
+#[derive(Debug, Clone, Args)]
+pub struct EncryptArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The memory the argon2id key derivation uses, in MiB. More memory makes guessing
+    /// the password slower, and decryption needs the same amount.
+    #[arg(long, default_value_t = DEFAULT_KDF_MEMORY, value_parser = clap::value_parser!(u32).range(MIN_KDF_MEMORY as i64..=(MAX_MEMORY_KIB / 1024) as i64))]
+    pub kdf_memory: u32,
+    /// The number of argon2id passes over that memory.
+    #[arg(long, default_value_t = DEFAULT_KDF_PASSES, value_parser = clap::value_parser!(u32).range(1..=MAX_PASSES as i64))]
+    pub kdf_passes: u32,
+}
+
+/// Arguments of the `keygen` command.
+#[derive(Debug, Clone, Args)]
+pub struct KeygenArgs {
+    /// The path where the key file will be written. An existing file is never replaced.
+    pub key_file: PathBuf,
+}
+
+/// The memory used when `--kdf-memory` is not given, in MiB (RFC 9106 recommends 64 MiB).
+pub const DEFAULT_KDF_MEMORY: u32 = 64;
+
+/// The smallest memory accepted by `--kdf-memory`, in MiB.
+pub const MIN_KDF_MEMORY: u32 = 8;
+
+/// The number of passes used when `--kdf-passes` is not given.
+pub const DEFAULT_KDF_PASSES: u32 = 3;
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Encrypts a file, such as the output of a compression module.
+    #[clap(alias = "e")]
+    Encrypt(EncryptArgs),
+    /// Restores a file written by `encrypt`.
+    #[clap(alias = "d")]
+    Decrypt(CommonArgs),
+    /// Writes a new random key file for `--key-file`.
+    #[clap(alias = "k")]
+    Keygen(KeygenArgs),
+}
+
+/// The main command line argument structure for the Crypt Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Password-Based Encryption Utility.",
+    long_about = "Encrypts any file, such as the output of another module, with XChaCha20-Poly1305 under a key derived from a password or key file with argon2id, and decrypts it again.",
+    after_help = "
+    EXAMPLES:
+    # 1. Encrypt a compressed file, prompting for a password
+    crypt_tool.exe encrypt notes.ppcb notes.ppce
+
+    # 2. Create a key file and use it from a script
+    crypt_tool.exe keygen backup.key
+    crypt_tool.exe e notes.ppcb notes.ppce --key-file backup.key
+
+    # 3. Decrypt
+    crypt_tool.exe d notes.ppce notes.ppcb --key-file backup.key
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file and the key file exist and are files.
+    /// 2. The parent directory for the output file exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        let common = match &self.command {
+            Commands::Encrypt(args) => &args.common,
+            Commands::Decrypt(args) => args,
+            Commands::Keygen(args) => return cli::check_output(&args.key_file),
+        };
+
+        // --- Input File Validation ---
+        cli::check_input(&common.input_file)?;
+        if let Some(key_file) = &common.key_file {
+            cli::check_input(key_file)?;
+        }
+
+        // --- Output Directory Validation ---
+        cli::check_output(&common.output_file)
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
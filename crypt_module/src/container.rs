@@ -0,0 +1,402 @@
+//! The encrypted container.
+//!
+//! A container is laid out as:
+//!
+//! ```text
+//! [magic: "PPCE"][module id: u8][format version: u8][flags: u8]
+//! [argon2id memory in KiB: u32 LE][passes: u32 LE][lanes: u32 LE][salt: 16 bytes]
+//! [nonce prefix: 19 bytes][chunk size: u32 LE][chunks ...]
+//! ```
+//!
+//! The data is encrypted in chunks of `chunk size` bytes with XChaCha20-Poly1305, so any
+//! file can be processed without holding it in memory. Following the STREAM construction,
+//! the 24-byte nonce of a chunk is the random nonce prefix, the chunk counter (u32 BE)
+//! and a byte that is 1 for the last chunk and 0 otherwise. Every chunk carries a 16-byte
+//! tag and authenticates the whole header as associated data, so changing the header,
+//! reordering, dropping or appending chunks, or truncating the file all make decryption
+//! fail. The last chunk may be empty, so even an empty file holds one chunk.
+
+use std::io::{self, Read, Write};
+
+use chacha20poly1305::{
+    Key, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+
+use crate::key::{KEY_LEN, KdfParams, SALT_LEN};
+
+/// Magic bytes to identify an encrypted container. PPCE stands for "PurgePack Crypt Envelope".
+const CONTAINER_MAGIC: [u8; 4] = *b"PPCE";
+/// Module ID (Algorithm Identifier) for the crypt module.
+pub(crate) const MODULE_ID: u8 = 0x05;
+/// The version of the header layout and payload format written by this build.
+pub(crate) const FORMAT_VERSION: u8 = 1;
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for
+/// format version + 1 byte for flags + 3 * 4 bytes for the argon2id parameters + 16 bytes
+/// for the salt + 19 bytes for the nonce prefix + 4 bytes for the chunk size).
+pub(crate) const HEADER_SIZE: usize = 58;
+/// The number of plaintext bytes per chunk written by this build.
+pub(crate) const CHUNK_SIZE: u32 = 64 * 1024;
+/// The length of the random part of the nonce in bytes.
+const NONCE_PREFIX_LEN: usize = 19;
+/// The length of the authentication tag of every chunk in bytes.
+const TAG_LEN: usize = 16;
+/// The flag set when the secret was read from a key file rather than a password.
+const FLAG_KEY_FILE: u8 = 1;
+/// The largest argon2id memory cost accepted when decrypting (4 GiB), so a crafted header
+/// cannot make decryption allocate without bound.
+pub(crate) const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+/// The largest number of argon2id passes accepted when decrypting.
+pub(crate) const MAX_PASSES: u32 = 64;
+/// The largest number of argon2id lanes accepted when decrypting.
+pub(crate) const MAX_LANES: u32 = 16;
+/// The range of chunk sizes accepted when decrypting.
+const CHUNK_SIZE_RANGE: std::ops::RangeInclusive<u32> = 1024..=16 * 1024 * 1024;
+
+/// The settings stored in the container header.
+pub(crate) struct ContainerHeader {
+    /// Whether the secret was read from a key file.
+    pub(crate) key_file: bool,
+    /// The argon2id cost parameters.
+    pub(crate) kdf: KdfParams,
+    /// The argon2id salt.
+    pub(crate) salt: [u8; SALT_LEN],
+    /// The random part of every chunk nonce.
+    pub(crate) nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    /// The number of plaintext bytes per chunk.
+    pub(crate) chunk_size: u32,
+}
+
+impl ContainerHeader {
+    /// Creates the header of a new container, with a fresh salt and nonce prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the operating system cannot provide random bytes.
+    pub(crate) fn new(key_file: bool, kdf: KdfParams) -> io::Result<Self> {
+        Ok(ContainerHeader {
+            key_file,
+            kdf,
+            salt: crate::key::random_bytes()?,
+            nonce_prefix: crate::key::random_bytes()?,
+            chunk_size: CHUNK_SIZE,
+        })
+    }
+
+    /// Returns the header as it is written to the container.
+    pub(crate) fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&CONTAINER_MAGIC);
+        bytes[4] = MODULE_ID;
+        bytes[5] = FORMAT_VERSION;
+        bytes[6] = if self.key_file { FLAG_KEY_FILE } else { 0 };
+        bytes[7..11].copy_from_slice(&self.kdf.memory_kib.to_le_bytes());
+        bytes[11..15].copy_from_slice(&self.kdf.passes.to_le_bytes());
+        bytes[15..19].copy_from_slice(&self.kdf.lanes.to_le_bytes());
+        bytes[19..35].copy_from_slice(&self.salt);
+        bytes[35..54].copy_from_slice(&self.nonce_prefix);
+        bytes[54..58].copy_from_slice(&self.chunk_size.to_le_bytes());
+        bytes
+    }
+
+    /// Parses and validates a header read from a container.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the bytes are not a container header, use a newer
+    /// format version, or hold parameters outside the accepted ranges.
+    pub(crate) fn parse(bytes: &[u8; HEADER_SIZE]) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if bytes[..4] != CONTAINER_MAGIC {
+            return Err(invalid(
+                "Invalid container magic number. This may not be a PurgePack Crypt Envelope (PPCE) file."
+                    .to_string(),
+            ));
+        }
+        if bytes[4] != MODULE_ID {
+            return Err(invalid(format!(
+                "Unsupported module ID: 0x{:02X}. Only 0x{:02X} (Crypt) is supported.",
+                bytes[4], MODULE_ID
+            )));
+        }
+        if bytes[5] == 0 || bytes[5] > FORMAT_VERSION {
+            return Err(invalid(format!(
+                "Unsupported format version {}. This build supports up to version {}.",
+                bytes[5], FORMAT_VERSION
+            )));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let kdf = KdfParams {
+            memory_kib: u32_at(7),
+            passes: u32_at(11),
+            lanes: u32_at(15),
+        };
+        if kdf.memory_kib > MAX_MEMORY_KIB
+            || !(1..=MAX_PASSES).contains(&kdf.passes)
+            || !(1..=MAX_LANES).contains(&kdf.lanes)
+        {
+            return Err(invalid(format!(
+                "Unsupported key derivation parameters: {} KiB, {} passes, {} lanes.",
+                kdf.memory_kib, kdf.passes, kdf.lanes
+            )));
+        }
+        let chunk_size = u32_at(54);
+        if !CHUNK_SIZE_RANGE.contains(&chunk_size) {
+            return Err(invalid(format!("Unsupported chunk size: {}.", chunk_size)));
+        }
+        Ok(ContainerHeader {
+            key_file: bytes[6] & FLAG_KEY_FILE != 0,
+            kdf,
+            salt: bytes[19..35].try_into().unwrap(),
+            nonce_prefix: bytes[35..54].try_into().unwrap(),
+            chunk_size,
+        })
+    }
+
+    /// Returns the nonce of chunk `index`.
+    fn nonce(&self, index: u64, last: bool) -> io::Result<XNonce> {
+        let counter = u32::try_from(index).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The data holds more chunks than the nonce counter can number.",
+            )
+        })?;
+        let mut nonce = XNonce::default();
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_LEN..NONCE_PREFIX_LEN + 4].copy_from_slice(&counter.to_be_bytes());
+        nonce[NONCE_PREFIX_LEN + 4] = last as u8;
+        Ok(nonce)
+    }
+}
+
+/// Reads up to `len` bytes into `buffer`, replacing its contents. Fewer bytes are only
+/// returned at the end of the stream.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    buffer.clear();
+    reader.take(len as u64).read_to_end(buffer)?;
+    Ok(())
+}
+
+/// Writes the header and the encrypted chunks of everything `reader` holds.
+///
+/// # Returns
+///
+/// Returns the number of chunks written.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+pub(crate) fn encrypt<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &ContainerHeader,
+    key: &[u8; KEY_LEN],
+) -> io::Result<u64> {
+    let header_bytes = header.to_bytes();
+    writer.write_all(&header_bytes)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let chunk_size = header.chunk_size as usize;
+
+    // Read one chunk ahead, so the last chunk is known when it is encrypted.
+    let mut chunk = Vec::with_capacity(chunk_size);
+    let mut next = Vec::with_capacity(chunk_size);
+    read_chunk(reader, &mut chunk, chunk_size)?;
+    let mut index = 0u64;
+    loop {
+        read_chunk(reader, &mut next, chunk_size)?;
+        let last = next.is_empty();
+        let payload = Payload {
+            msg: &chunk,
+            aad: &header_bytes,
+        };
+        let sealed = cipher
+            .encrypt(&header.nonce(index, last)?, payload)
+            .map_err(|_| io::Error::other("Encryption failed."))?;
+        writer.write_all(&sealed)?;
+        index += 1;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+    }
+    writer.flush()?;
+    Ok(index)
+}
+
+/// Decrypts the chunks following the header into `writer`.
+///
+/// Every chunk is authenticated before any of its bytes are written, but the chunks
+/// before a damaged one have been written by the time it is found.
+///
+/// # Arguments
+///
+/// * `reader` - The container, positioned right after the header.
+/// * `writer` - The destination of the decrypted data.
+/// * `header_bytes` - The header as read from the container.
+/// * `header` - The parsed header.
+/// * `key` - The cipher key.
+///
+/// # Returns
+///
+/// Returns the number of chunks read.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, and an `InvalidData` error if a
+/// chunk fails authentication: the secret is wrong, or the container was modified or
+/// truncated.
+pub(crate) fn decrypt<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header_bytes: &[u8; HEADER_SIZE],
+    header: &ContainerHeader,
+    key: &[u8; KEY_LEN],
+) -> io::Result<u64> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let sealed_size = header.chunk_size as usize + TAG_LEN;
+
+    let mut chunk = Vec::with_capacity(sealed_size);
+    let mut next = Vec::with_capacity(sealed_size);
+    read_chunk(reader, &mut chunk, sealed_size)?;
+    let mut index = 0u64;
+    loop {
+        read_chunk(reader, &mut next, sealed_size)?;
+        let last = next.is_empty();
+        let payload = Payload {
+            msg: &chunk,
+            aad: header_bytes,
+        };
+        let opened = cipher
+            .decrypt(&header.nonce(index, last)?, payload)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    if index == 0 {
+                        "Decryption failed: the password or key file is wrong, or the file is corrupted."
+                    } else {
+                        "Decryption failed: the file is corrupted or truncated."
+                    },
+                )
+            })?;
+        writer.write_all(&opened)?;
+        index += 1;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+    }
+    writer.flush()?;
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key;
+
+    /// Cheap key derivation parameters, so the tests run quickly.
+    const KDF: KdfParams = KdfParams {
+        memory_kib: 64,
+        passes: 1,
+        lanes: 1,
+    };
+
+    /// Encrypts `data` with `secret` in chunks of `chunk_size` bytes.
+    fn seal(data: &[u8], secret: &[u8], chunk_size: u32) -> Vec<u8> {
+        let header = ContainerHeader {
+            chunk_size,
+            ..ContainerHeader::new(false, KDF).unwrap()
+        };
+        let key = key::derive_key(secret, &header.salt, header.kdf).unwrap();
+        let mut container = Vec::new();
+        encrypt(&mut &data[..], &mut container, &header, &key).unwrap();
+        container
+    }
+
+    /// Decrypts `container` with `secret` the way the decrypt command does.
+    fn open(container: &[u8], secret: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = container;
+        let mut header_bytes = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header_bytes)?;
+        let header = ContainerHeader::parse(&header_bytes)?;
+        let key = key::derive_key(secret, &header.salt, header.kdf)?;
+        let mut output = Vec::new();
+        decrypt(&mut reader, &mut output, &header_bytes, &header, &key)?;
+        Ok(output)
+    }
+
+    /// Returns the data of `len` bytes the tests encrypt.
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 13 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn round_trips_empty_and_chunked_data() {
+        for len in [0, 1, 1024, 5000] {
+            let container = seal(&data(len), b"secret", 1024);
+            // Every chunk carries a tag, and empty data still gets a last, empty chunk.
+            let chunks = len.div_ceil(1024).max(1);
+            assert_eq!(container.len(), HEADER_SIZE + len + chunks * TAG_LEN);
+            assert_eq!(open(&container, b"secret").unwrap(), data(len));
+        }
+    }
+
+    #[test]
+    fn rejects_a_wrong_secret() {
+        let container = seal(&data(5000), b"secret", 1024);
+        let error = open(&container, b"Secret").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_a_tampered_header_ciphertext_or_tag() {
+        let container = seal(&data(5000), b"secret", 1024);
+        let sealed_size = 1024 + TAG_LEN;
+        // A flag of the header, the first byte of the salt and of the nonce prefix, the
+        // ciphertext and the tag of the first chunk, and the tag of the last one.
+        for position in [
+            6,
+            19,
+            35,
+            HEADER_SIZE,
+            HEADER_SIZE + 1024,
+            HEADER_SIZE + sealed_size + 500,
+            container.len() - 1,
+        ] {
+            let mut tampered = container.clone();
+            tampered[position] ^= 0x01;
+            let error = open(&tampered, b"secret").unwrap_err();
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::InvalidData,
+                "byte {}",
+                position
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_a_truncated_or_reordered_container() {
+        let container = seal(&data(5000), b"secret", 1024);
+        let sealed_size = 1024 + TAG_LEN;
+        // Within the header, within a chunk, and at the ends of the first and the last
+        // but one chunk, which then look like the last one.
+        for len in [
+            10,
+            HEADER_SIZE,
+            HEADER_SIZE + 100,
+            HEADER_SIZE + sealed_size,
+            HEADER_SIZE + 4 * sealed_size,
+            container.len() - 1,
+        ] {
+            assert!(open(&container[..len], b"secret").is_err(), "{} bytes", len);
+        }
+
+        let mut swapped = container[..HEADER_SIZE].to_vec();
+        swapped.extend_from_slice(&container[HEADER_SIZE + sealed_size..][..sealed_size]);
+        swapped.extend_from_slice(&container[HEADER_SIZE..][..sealed_size]);
+        swapped.extend_from_slice(&container[HEADER_SIZE + 2 * sealed_size..]);
+        assert_eq!(swapped.len(), container.len());
+        assert!(open(&swapped, b"secret").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! The secret and the cipher key.
+//!
+//! The secret is either a password typed at a prompt or the contents of a key file, so
+//! scripts can encrypt and decrypt without a terminal. Without an interactive terminal
+//! (see `shared_files::term`), there is no prompt, and a key file is needed. Either way
+//! the secret is stretched into the 256-bit cipher key with argon2id, using a random salt
+//! and cost parameters stored in the container header. The secret and the key are wiped
+//! from memory when dropped.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use argon2::{Argon2, Params, Version};
+use shared_files::error::PurgePackError;
+use shared_files::term::{self, Stream};
+use zeroize::Zeroizing;
+
+/// The length of the cipher key in bytes.
+pub(crate) const KEY_LEN: usize = 32;
+/// The length of the argon2id salt in bytes.
+pub(crate) const SALT_LEN: usize = 16;
+/// The length of a key file written by `keygen`.
+const KEY_FILE_LEN: usize = 32;
+
+/// The argon2id cost parameters.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KdfParams {
+    /// The memory cost in KiB.
+    pub(crate) memory_kib: u32,
+    /// The number of passes over the memory.
+    pub(crate) passes: u32,
+    /// The number of lanes.
+    pub(crate) lanes: u32,
+}
+
+/// Returns `N` bytes from the operating system's random number generator.
+pub(crate) fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Reads the secret from `key_file`, or prompts for a password if there is none.
+///
+/// # Arguments
+///
+/// * `key_file` - The path to the key file, if one was given.
+/// * `confirm` - Whether a prompted password must be typed twice, as when encrypting.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the key file cannot be read or there is no terminal to
+/// prompt on, and an `InvalidInput` error if the secret is empty or the two passwords
+/// differ.
+pub(crate) fn read_secret(
+    key_file: Option<&Path>,
+    confirm: bool,
+) -> io::Result<Zeroizing<Vec<u8>>> {
+    let prompt = |text: &str| {
+        // A prompt in a CI or cron job would wait for nobody, or fail in a confusing way.
+        if !term::interactive(Stream::Stderr) {
+            return Err(PurgePackError::InvalidInput(
+                "There is no terminal to prompt for a password on. Use --key-file instead."
+                    .to_string(),
+            )
+            .into());
+        }
+        rpassword::prompt_password(text).map(Zeroizing::new).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Failed to prompt for a password ({}). Use --key-file when there is no terminal.",
+                    e
+                ),
+            )
+        })
+    };
+    let secret = match key_file {
+        Some(key_file) => Zeroizing::new(fs::read(key_file)?),
+        None => {
+            let password = prompt("Password: ")?;
+            if confirm {
+                let again = prompt("Repeat password: ")?;
+                if *again != *password {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "The passwords do not match.",
+                    ));
+                }
+            }
+            Zeroizing::new(password.as_bytes().to_vec())
+        }
+    };
+    if secret.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The password or key file is empty.",
+        ));
+    }
+    Ok(secret)
+}
+
+/// Derives the cipher key from `secret` with argon2id.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if argon2 rejects the parameters.
+pub(crate) fn derive_key(
+    secret: &[u8],
+    salt: &[u8; SALT_LEN],
+    params: KdfParams,
+) -> io::Result<Zeroizing<[u8; KEY_LEN]>> {
+    let invalid = |e: argon2::Error| io::Error::new(io::ErrorKind::InvalidInput, e.to_string());
+    let params = Params::new(
+        params.memory_kib,
+        params.passes,
+        params.lanes,
+        Some(KEY_LEN),
+    )
+    .map_err(invalid)?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(secret, salt, &mut *key)
+        .map_err(invalid)?;
+    Ok(key)
+}
+
+/// Writes a new key file of random bytes to `path`.
+///
+/// On Unix the file is only readable and writable by its owner.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file already exists or cannot be written.
+pub(crate) fn generate_key_file(path: &Path) -> io::Result<()> {
+    let key = Zeroizing::new(random_bytes::<KEY_FILE_LEN>()?);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(&*key)?;
+    file.sync_all()
+}
@@ -0,0 +1,261 @@
+//! Password-based encryption of whole files.
+//!
+//! Any file can be encrypted, so the module works as a container layer over the output of
+//! the compression modules: compress first, then encrypt the `.ppcb` file (encrypted data
+//! does not compress). The secret is a prompted password or a key file (see the `key`
+//! module), and the data is encrypted in authenticated chunks (see the `container` module
+//! for the layout).
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read},
+    path::Path,
+};
+mod cli_parse;
+mod container;
+mod key;
+use container::{ContainerHeader, FORMAT_VERSION, MODULE_ID};
+use shared_files::abi;
+use shared_files::cli::{self, CliError};
+use shared_files::core_header::{self};
+use shared_files::events::{self, Event};
+use shared_files::output::SafeWriter;
+use shared_files::progress::{self, ProgressReader};
+use shared_files::stats::{DEFAULT_SAMPLE_INTERVAL, StatsFormat};
+use shared_files::{log_debug, log_error, log_info};
+use std::ffi::c_char;
+
+/// The file extension for PurgePack Crypt Envelope (PPCE) files.
+const FILE_EXTENSION: &str = "ppce";
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("crypt", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Encrypt(args) => {
+                log_info!(
+                    "Encrypt: Input: {}, Output: {}, Key: {}",
+                    args.common.input_file.display(),
+                    args.common.output_file.display(),
+                    key_source(args.common.key_file.as_deref())
+                );
+                match encrypt_file(core, &args) {
+                    Ok(()) => log_info!("Encrypt: Success"),
+                    Err(e) => {
+                        log_error!("Encrypt: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Decrypt(args) => {
+                log_info!(
+                    "Decrypt: Input: {}, Output: {}, Key: {}",
+                    args.input_file.display(),
+                    args.output_file.display(),
+                    key_source(args.key_file.as_deref())
+                );
+                match decrypt_file(core, &args) {
+                    Ok(()) => log_info!("Decrypt: Success"),
+                    Err(e) => {
+                        log_error!("Decrypt: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Keygen(args) => match key::generate_key_file(&args.key_file) {
+                Ok(()) => log_info!("Keygen: Key file written to {}", args.key_file.display()),
+                Err(e) => {
+                    log_error!("Keygen: {}", e);
+                    core_header::report_error(core, &e);
+                }
+            },
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("crypt", core.log_level);
+    log_debug!("Crypt module shutting down.");
+}
+
+/// Describes where the secret comes from, for the start message.
+fn key_source(key_file: Option<&Path>) -> String {
+    match key_file {
+        Some(key_file) => format!("key file {}", key_file.display()),
+        None => "password".to_string(),
+    }
+}
+
+/// Encrypts the input file of `args` into its output file.
+///
+/// The output is written through a [`SafeWriter`], so it only appears once encryption
+/// succeeded.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the secret cannot be read, or reading or writing fails.
+fn encrypt_file(core: &core_header::CoreH, args: &cli_parse::EncryptArgs) -> io::Result<()> {
+    let common = &args.common;
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(common.stats.enabled());
+    let mut output_file = common.output_file.clone();
+    if output_file.extension().is_none() {
+        output_file.set_extension(FILE_EXTENSION);
+        log_info!(
+            "Encrypt: Automatic extension '{}' placed on output file: {}",
+            FILE_EXTENSION,
+            output_file.display()
+        );
+    }
+    // Checked before the password prompt, so it is not asked for in vain.
+    let output_file = common.overwrite.policy().resolve(&output_file)?;
+    let secret = key::read_secret(common.key_file.as_deref(), true)?;
+    let header = ContainerHeader::new(
+        common.key_file.is_some(),
+        key::KdfParams {
+            memory_kib: args.kdf_memory * 1024,
+            passes: args.kdf_passes,
+            lanes: 1,
+        },
+    )?;
+
+    let t_kdf = main_timer.start_section("Key Derivation");
+    let key = key::derive_key(&secret, &header.salt, header.kdf)?;
+    main_timer.add_section(t_kdf);
+
+    let input = File::open(&common.input_file)?;
+    let input_len = input.metadata()?.len();
+    let mut reader = BufReader::new(ProgressReader::new(
+        main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+        progress::terminal("Encrypt", Some(input_len)),
+    ));
+    let mut writer = BufWriter::new(SafeWriter::create(&output_file)?);
+    let t_encryption = main_timer.start_section("Encryption");
+    let chunks = container::encrypt(&mut reader, &mut writer, &header, &key)?;
+    main_timer.add_section(t_encryption);
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = common.stats.format() {
+        print_statistics(core, main_timer, &output, input_len, chunks, true, format)?;
+    }
+    Ok(())
+}
+
+/// Decrypts the input file of `args` into its output file.
+///
+/// The output is written through a [`SafeWriter`], so no partial or unauthenticated data
+/// appears under its name.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the secret cannot be read or reading or writing fails, and
+/// an `InvalidData` error if the header is invalid or the data fails authentication.
+fn decrypt_file(core: &core_header::CoreH, args: &cli_parse::CommonArgs) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(args.stats.enabled());
+    let output_file = args.overwrite.policy().resolve(&args.output_file)?;
+    let input = File::open(&args.input_file)?;
+    let input_len = input.metadata()?.len();
+    let mut reader = BufReader::new(ProgressReader::new(
+        main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+        progress::terminal("Decrypt", Some(input_len)),
+    ));
+
+    let mut header_bytes = [0u8; container::HEADER_SIZE];
+    reader.read_exact(&mut header_bytes).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Failed to read the container header. File may be too short or corrupted.",
+        )
+    })?;
+    let header = ContainerHeader::parse(&header_bytes)?;
+    if header.key_file && args.key_file.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The file was encrypted with a key file. Pass it with --key-file.",
+        ));
+    }
+    let secret = key::read_secret(args.key_file.as_deref(), false)?;
+
+    let t_kdf = main_timer.start_section("Key Derivation");
+    let key = key::derive_key(&secret, &header.salt, header.kdf)?;
+    main_timer.add_section(t_kdf);
+
+    let mut writer = BufWriter::new(SafeWriter::create(&output_file)?);
+    let t_decryption = main_timer.start_section("Decryption");
+    let chunks = container::decrypt(&mut reader, &mut writer, &header_bytes, &header, &key)?;
+    main_timer.add_section(t_decryption);
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = args.stats.format() {
+        print_statistics(core, main_timer, &output, input_len, chunks, false, format)?;
+    }
+    Ok(())
+}
+
+/// Prints the statistics of a finished operation and emits them as a `stats` event.
+///
+/// # Arguments
+///
+/// * `core` - The core the `stats` event is emitted to.
+/// * `main_timer` - The timer holding the sections of the operation.
+/// * `output` - The output file, whose length is the processed length.
+/// * `input_len` - The length of the input file.
+/// * `chunks` - The number of chunks.
+/// * `is_compression` - Whether the operation encrypted the data.
+/// * `format` - The format to print the statistics in.
+fn print_statistics(
+    core: &core_header::CoreH,
+    main_timer: shared_files::stats::OptinalStatsTimer,
+    output: &File,
+    input_len: u64,
+    chunks: u64,
+    is_compression: bool,
+    format: StatsFormat,
+) -> io::Result<()> {
+    let peak_memory = main_timer.peak_memory();
+    let throughput = main_timer.throughput();
+    let (total_duration, sections) = main_timer.end();
+    let output_len = output.metadata()?.len();
+    if format == StatsFormat::Text {
+        println!("Chunks: {}", chunks);
+    }
+    let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
+        .algorithm_name("Argon2id + XChaCha20-Poly1305")
+        .algorithm_id(MODULE_ID)
+        .version_used(FORMAT_VERSION)
+        .original_len(input_len as usize)
+        .processed_len(output_len as usize)
+        .duration(total_duration)
+        .is_compression(is_compression)
+        .sections(sections)
+        .peak_memory(peak_memory)
+        .throughput(throughput)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
+    println!("{}", calculated_stats.render(format));
+    events::emit(
+        core,
+        &Event::Stats {
+            stats: &calculated_stats,
+        },
+    );
+    Ok(())
+}
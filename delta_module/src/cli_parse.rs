@@ -1,25 +1,191 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{
+    Args, Parser, Subcommand,
+    builder::{PossibleValuesParser, TypedValueParser},
+};
+use purgepack_delta::{MAX_BLOCK_SIZE, MAX_ORDER, MAX_PIXEL_SIZE};
+use shared_files::cli::{self, CliError, OverwriteArgs, StatsArgs, Validate};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Args)]
 pub struct CommonArgs {
     /// The path to the input file.
     pub input_file: PathBuf,
-    /// The path where the output file will be written.
+    /// The path where the output file will be written. Omitted with `--in-place`.
+    #[arg(required_unless_present = "in_place")]
+    pub output_file: Option<PathBuf>,
+    /// Replaces the input file instead of writing a separate output file. The result is
+    /// named after the input with the `.ppcb` extension added (transform) or removed (inverse).
+    /// The file is rewritten within itself through a small journal, so no second copy is
+    /// written; running the command again finishes an interrupted run. Only the delta and XOR
+    /// byte transforms without blocks or a post pass are supported.
+    #[arg(long, conflicts_with = "output_file")]
+    pub in_place: bool,
+    /// The statistics flags. The text statistics include the byte entropy of the input and
+    /// output files.
+    #[command(flatten)]
+    pub stats: StatsArgs,
+    /// The overwrite flags. With `transform`, `--force` also encodes an input that is
+    /// delta encoded already.
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+    /// The number of worker threads used to transform blocks in parallel (0 = one per CPU core).
+    /// Only files written with `--block-size` are processed in parallel.
+    #[arg(short = 'j', long, default_value_t = 0)]
+    pub threads: usize,
+    /// The size in bytes of the buffers the data is read and written through. Larger chunks
+    /// help fast disks, smaller ones save memory on small targets.
+    #[arg(long, default_value_t = DEFAULT_CHUNK_SIZE, value_parser = clap::value_parser!(u32).range(MIN_CHUNK_SIZE as i64..=MAX_CHUNK_SIZE as i64))]
+    pub chunk_size: u32,
+}
+
+/// Arguments that only apply to the forward transform.
+///
+/// The inverse transform reads these settings back from the file header.
+#[derive(Debug, Clone, Args)]
+pub struct TransformArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The order of the delta transform (1 = first-order, 2 = delta of deltas, ...).
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=MAX_ORDER as i64))]
+    pub order: u8,
+    /// Predicts each sample by linear extrapolation (`2 * prev - prev2`) and stores the residual.
+    /// The residual is the same as the second-order delta, so this is a shorthand for `--order 2`.
+    #[arg(long, conflicts_with_all = ["order", "xor", "image", "width", "timestamps"])]
+    pub linear: bool,
+    /// The distance in samples to the value each sample is differenced against
+    /// (e.g. 3 for interleaved RGB pixels, 2 for stereo audio with `--word-size 2`).
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub stride: u16,
+    /// The width of each sample in bytes (little-endian). The delta is taken between whole samples.
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = PossibleValuesParser::new(["1", "2", "4", "8"]).map(|s| s.parse::<u8>().unwrap())
+    )]
+    pub word_size: u8,
+    /// Zigzag-maps the residuals and stores them as LEB128 varints, so small residuals take a
+    /// single byte. This shrinks the output when used together with `--word-size` 2, 4 or 8.
+    #[arg(long)]
+    pub varint: bool,
+    /// Stores the first `--stride` samples (one full record) verbatim as the seeds of their
+    /// lanes, instead of differencing all but the first against the first sample.
+    #[arg(long, conflicts_with_all = ["image", "width", "timestamps"])]
+    pub seed_block: bool,
+    /// Combines each sample with the previous one using XOR instead of subtraction.
+    /// Useful for bit flags and floating-point data where neighbouring samples share their high bits.
+    #[arg(long)]
+    pub xor: bool,
+    /// Splits the data into independent blocks of this many bytes, each starting from its own
+    /// seed, so a block can be decoded without the blocks before it.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=MAX_BLOCK_SIZE as i64))]
+    pub block_size: Option<u32>,
+    /// The offset of the first byte to transform. The bytes before it are copied through unchanged.
+    #[arg(long, default_value_t = 0)]
+    pub offset: u64,
+    /// The number of bytes to transform, starting at `--offset` (default: up to the end of the file).
+    /// The bytes after the range are copied through unchanged.
+    #[arg(long)]
+    pub length: Option<u64>,
+    /// Writes only the transformed range and skips the bytes outside it.
+    #[arg(long, conflicts_with = "in_place")]
+    pub crop: bool,
+    /// Filters an uncompressed BMP or binary PGM/PPM image row by row with PNG-style predictors.
+    /// The image layout is read from the file header and only the pixel data is filtered.
+    #[arg(long, conflicts_with_all = ["order", "stride", "word_size", "varint", "xor", "offset", "length", "width"])]
+    pub image: bool,
+    /// Filters raw pixel data row by row with PNG-style predictors, using rows of this many pixels.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["order", "stride", "word_size", "varint", "xor"])]
+    pub width: Option<u32>,
+    /// The number of bytes (not bits) per pixel of the raw pixel data given with `--width`.
+    #[arg(long, default_value_t = 1, requires = "width", value_parser = clap::value_parser!(u8).range(1..=MAX_PIXEL_SIZE as i64))]
+    pub bpp: u8,
+    /// Stores the 64-bit little-endian timestamps of fixed-size records as delta-of-deltas with
+    /// varint residuals, which takes a single byte per record for regular intervals.
+    #[arg(long, conflicts_with_all = ["order", "stride", "word_size", "varint", "xor", "image", "width"])]
+    pub timestamps: bool,
+    /// The size in bytes of a record in timestamp mode.
+    #[arg(long, default_value_t = 8, requires = "timestamps", value_parser = clap::value_parser!(u32).range(8..))]
+    pub record_size: u32,
+    /// The offset in bytes of the timestamp inside a record in timestamp mode.
+    #[arg(long, default_value_t = 0, requires = "timestamps")]
+    pub timestamp_offset: u32,
+    /// A second pass over the transformed data, stored in the same file. `rle0` run-length
+    /// encodes the zero residuals, which make up most of the output for smooth data.
+    #[arg(long, default_value = "none", value_parser = PossibleValuesParser::new(["none", "rle0"]))]
+    pub post: String,
+    /// Writes a CSV histogram of the residual bytes to this file, to show whether the output is
+    /// concentrated near zero and worth entropy-coding afterwards. Requires `--stats`.
+    #[arg(long, requires = "stats", conflicts_with = "in_place")]
+    pub histogram: Option<PathBuf>,
+}
+
+/// Arguments of the `diff` command.
+#[derive(Debug, Clone, Args)]
+pub struct DiffArgs {
+    /// The path to the original file.
+    pub old_file: PathBuf,
+    /// The path to the modified file.
+    pub new_file: PathBuf,
+    /// The path where the patch will be written.
+    pub patch_file: PathBuf,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+}
+
+/// Arguments of the `patch` command.
+#[derive(Debug, Clone, Args)]
+pub struct PatchArgs {
+    /// The path to the original file the patch was created against.
+    pub old_file: PathBuf,
+    /// The path to the patch created with `diff`.
+    pub patch_file: PathBuf,
+    /// The path where the patched file will be written.
     pub output_file: PathBuf,
-    /// Enables statistics output.
-    #[arg(short, long)]
-    pub stats: bool,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
 }
+
+/// The default chunk size of `--chunk-size`, the buffer size of `BufReader` (8 KiB).
+pub const DEFAULT_CHUNK_SIZE: u32 = 8 * 1024;
+
+/// The smallest chunk size accepted by `--chunk-size` (512 bytes).
+pub const MIN_CHUNK_SIZE: u32 = 512;
+
+/// The largest chunk size accepted by `--chunk-size` (64 MiB).
+pub const MAX_CHUNK_SIZE: u32 = 64 << 20;
+
 /// The main operations available for the utility.
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Executes the forward or inverse Delta Transform on a file.
     #[clap(alias = "t")]
-    Transform(CommonArgs),
+    Transform(TransformArgs),
     /// Executes the inverse Delta Transform on a file.
     #[clap(alias = "i")]
     Inverse(CommonArgs),
+    /// Reads the header of the input file and applies the Inverse Delta Transform if it is
+    /// delta encoded, or the forward transform with the default settings otherwise.
+    #[clap(alias = "a")]
+    Auto(CommonArgs),
+    /// Creates a binary patch that turns the old file into the new file.
+    #[clap(alias = "d")]
+    Diff(DiffArgs),
+    /// Applies a patch created with `diff` to the old file.
+    #[clap(alias = "p")]
+    Patch(PatchArgs),
+}
+
+impl Commands {
+    /// Returns the input file of an in-place run if an earlier run on it was interrupted.
+    pub fn interrupted_input(&self) -> Option<&PathBuf> {
+        let args = match self {
+            Commands::Transform(args) => &args.common,
+            Commands::Inverse(args) | Commands::Auto(args) => args,
+            Commands::Diff(_) | Commands::Patch(_) => return None,
+        };
+        (args.in_place && crate::in_place::is_interrupted(&args.input_file))
+            .then_some(&args.input_file)
+    }
 }
 
 /// The main command line argument structure for the Delta Transform Utility.
@@ -47,6 +213,38 @@ pub enum Commands {
 
     # 4. Inverse Delta Transform
     delta_tool.exe i transformed.dt restored_data.bin
+
+    # 5. Second-order (delta of deltas) transform, useful for counters and sensor data
+    #    (the same as linear prediction, --linear)
+    delta_tool.exe t source.bin dest.dt --order 2
+
+    # 6. Channel-aware transform of interleaved RGB pixel data
+    delta_tool.exe t pixels.rgb dest.dt --stride 3
+
+    # 7. 32-bit counters with small residuals stored as varints (output is smaller than input)
+    delta_tool.exe t counters.bin dest.dt --word-size 4 --varint
+
+    # 8. Independent 1 MiB blocks that can be decoded on their own
+    delta_tool.exe t source.bin dest.dt --block-size 1048576
+
+    # 9. Transform only the payload section of a container, copying the rest through
+    delta_tool.exe t container.bin dest.dt --offset 512 --length 65536
+
+    # 10. PNG-style row filters for an image (or raw pixels with --width 640 --bpp 3)
+    delta_tool.exe t photo.bmp dest.dt --image
+
+    # 11. Binary patch between two versions of a file, and applying it
+    delta_tool.exe diff app_v1.bin app_v2.bin v1_to_v2.patch
+    delta_tool.exe patch app_v1.bin v1_to_v2.patch app_v2_restored.bin
+
+    # 12. Sensor log of 24-byte records with a 64-bit timestamp at offset 0
+    delta_tool.exe t sensor.log dest.dt --timestamps --record-size 24
+
+    # 13. Encode or decode, whichever the file needs (decided from its header)
+    delta_tool.exe auto some_file.ppcb restored.bin
+
+    # 14. Encode in place (some_file.bin is replaced by some_file.bin.ppcb)
+    delta_tool.exe t some_file.bin --in-place
 "
 )]
 pub struct CliArgs {
@@ -55,68 +253,45 @@ pub struct CliArgs {
     pub command: Commands,
 }
 
-impl CliArgs {
+impl Validate for CliArgs {
     /// Validates the command line arguments after parsing, specifically ensuring:
-    /// 1. The input file exists and is a file.
+    /// 1. The input files exist and are files.
     /// 2. The parent directory for the output file exists and is a directory.
-    pub fn validate(&self) -> Result<(), CliError> {
-        let common_args = match &self.command {
-            Commands::Transform(args) => args,
-            Commands::Inverse(args) => args,
+    fn validate(&self) -> Result<(), CliError> {
+        let (in_paths, out_path) = match &self.command {
+            Commands::Transform(args) => (
+                vec![&args.common.input_file],
+                args.common.output_file.as_ref(),
+            ),
+            Commands::Inverse(args) | Commands::Auto(args) => {
+                (vec![&args.input_file], args.output_file.as_ref())
+            }
+            Commands::Diff(args) => (vec![&args.old_file, &args.new_file], Some(&args.patch_file)),
+            Commands::Patch(args) => (
+                vec![&args.old_file, &args.patch_file],
+                Some(&args.output_file),
+            ),
         };
 
-        let in_path = &common_args.input_file;
-        let out_path = &common_args.output_file;
-
         // --- Input File Validation ---
-        if !in_path.exists() {
-            return Err(CliError::InputFileNotFound(in_path.clone()));
+        // The input of an interrupted in-place run has been renamed, the journal knows where.
+        if self.command.interrupted_input().is_some() {
+            return Ok(());
         }
-        if !in_path.is_file() {
-            return Err(CliError::InputNotFile(in_path.clone()));
+        for in_path in in_paths {
+            cli::check_input(in_path)?;
         }
 
         // --- Output Directory Validation ---
-        if let Some(parent) = out_path.parent() {
-            if !parent.exists() {
-                return Err(CliError::OutputParentDirNotFound(parent.to_path_buf()));
-            }
-            if !parent.is_dir() {
-                return Err(CliError::OutputParentNotDir(parent.to_path_buf()));
-            }
+        // An in-place output is written next to the input file.
+        match out_path {
+            Some(out_path) => cli::check_output(out_path),
+            None => Ok(()),
         }
-
-        Ok(())
-    }
-}
-
-/// Possible errors encountered during command line argument processing,
-/// file validation, or when executing the Delta Transform operations.
-#[derive(Debug)]
-pub enum CliError {
-    /// The specified input file could not be found.
-    InputFileNotFound(PathBuf),
-    /// The specified input path exists, but is not a file.
-    InputNotFile(PathBuf),
-    /// The parent directory for the output file does not exist.
-    OutputParentDirNotFound(PathBuf),
-    /// The parent path for the output file exists, but is not a directory.
-    OutputParentNotDir(PathBuf),
-    /// An error originating directly from the argument parsing library (clap).
-    ClapError(clap::Error),
-}
-
-/// Allows for seamless conversion of a `clap::Error` directly into a `CliError`.
-/// This is typically used when handling the result of `CliArgs::parse()`.
-impl From<clap::Error> for CliError {
-    fn from(error: clap::Error) -> Self {
-        CliError::ClapError(error)
     }
 }
 
 /// Allows for parsing command line arguments and validating them.
-pub fn parse_args(args: &Vec<String>) -> Result<CliArgs, CliError> {
-    let args = CliArgs::try_parse_from(args.iter().map(|s| s.as_ref() as &str))?;
-    args.validate()?;
-    Ok(args)
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
 }
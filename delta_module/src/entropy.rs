@@ -0,0 +1,62 @@
+//! Order-0 byte entropy of the input and output files.
+//!
+//! The entropy in bits per byte is the lower bound an order-0 entropy coder (such as the
+//! Huffman module) can reach on a file. Comparing it before and after the transform shows
+//! whether the delta pass makes the data easier to compress: the transform never changes
+//! the size much, but a lower entropy means a smaller result downstream.
+
+use shared_files::entropy::entropy;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Counts how often every byte value occurs in `len` bytes of `file`, starting at `start`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file cannot be opened or read.
+pub(crate) fn count_bytes(file: &Path, start: u64, len: u64) -> io::Result<[u64; 256]> {
+    let mut counts = [0u64; 256];
+    let mut input = File::open(file)?;
+    input.seek(SeekFrom::Start(start))?;
+    let mut range = input.take(len);
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = range.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            counts[byte as usize] += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Prints the entropy of the whole input and output files and the change between them.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if either file cannot be read.
+pub(crate) fn print_entropy(input_file: &Path, output_file: &Path) -> io::Result<()> {
+    let before = entropy(&count_bytes(input_file, 0, u64::MAX)?);
+    let after = entropy(&count_bytes(output_file, 0, u64::MAX)?);
+    let input_len = input_file.metadata()?.len();
+    let output_len = output_file.metadata()?.len();
+    let change = if before == 0.0 {
+        0.0
+    } else {
+        (after - before) / before * 100.0
+    };
+    println!(
+        "Entropy: input {:.3} bits/byte (~{} bytes order-0 coded), output {:.3} bits/byte (~{} bytes order-0 coded), change {:+.1}%",
+        before,
+        (before * input_len as f64 / 8.0).ceil() as u64,
+        after,
+        (after * output_len as f64 / 8.0).ceil() as u64,
+        change
+    );
+    Ok(())
+}
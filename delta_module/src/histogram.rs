@@ -0,0 +1,64 @@
+//! Residual histogram export.
+//!
+//! After a transform, the distribution of the residual bytes shows whether the output is
+//! concentrated near zero, and therefore worth passing through an entropy coder such as the
+//! Huffman module. The histogram is written as CSV with one row per byte value, read as a
+//! signed residual from -128 to 127:
+//!
+//! ```text
+//! residual,count,percent
+//! -128,12,0.0120
+//! ...
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::entropy::count_bytes;
+
+/// Counts the residual bytes of the payload stored in `output_file` and writes them to
+/// `csv_file`.
+///
+/// # Arguments
+///
+/// * `output_file` - The path of the transformed file.
+/// * `payload_start` - The offset of the first residual byte in the transformed file.
+/// * `payload_len` - The number of residual bytes.
+/// * `csv_file` - The path where the CSV histogram is written.
+///
+/// # Returns
+///
+/// Returns the share (0.0 to 1.0) of the residual bytes between -1 and 1.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading the transformed file or writing the CSV fails.
+pub(crate) fn write_residual_histogram(
+    output_file: &Path,
+    payload_start: u64,
+    payload_len: u64,
+    csv_file: &Path,
+) -> io::Result<f64> {
+    let counts = count_bytes(output_file, payload_start, payload_len)?;
+    let total: u64 = counts.iter().sum();
+    let percent = |count: u64| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / total as f64
+        }
+    };
+    let mut csv = BufWriter::new(File::create(csv_file)?);
+    writeln!(csv, "residual,count,percent")?;
+    for residual in i8::MIN..=i8::MAX {
+        let count = counts[residual as u8 as usize];
+        writeln!(csv, "{},{},{:.4}", residual, count, percent(count))?;
+    }
+    csv.flush()?;
+
+    let near_zero = counts[0] + counts[1] + counts[255];
+    Ok(percent(near_zero) / 100.0)
+}
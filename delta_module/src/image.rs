@@ -0,0 +1,175 @@
+//! Image mode: the row layout of the pixel data.
+//!
+//! The PNG-style row filters live in the `purgepack_delta` crate. The row layout is either
+//! given on the command line (`--width`/`--bpp`) or detected from the header of a BMP or
+//! binary PGM/PPM file, in which case only the pixel data is filtered.
+
+use std::io::{self, Read};
+
+use shared_files::error::PurgePackError;
+
+/// The number of bytes read from the start of the input to detect the image format.
+/// Comments in a PNM header may push the pixel data further out than this.
+const DETECTION_PREFIX_LEN: u64 = 4096;
+
+/// The row layout of the pixel data filtered in image mode.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ImageLayout {
+    /// The name of the detected image format, or `"raw"` for a layout given on the command line.
+    pub(crate) format: &'static str,
+    /// The number of pixels per row.
+    pub(crate) width: u32,
+    /// The number of bytes per pixel (the distance to the left neighbour of a byte).
+    pub(crate) pixel_size: u8,
+    /// The number of bytes per row, including any padding at the end of the row.
+    pub(crate) row_len: u32,
+    /// The offset and length of the pixel data in the file, for detected formats.
+    pub(crate) pixel_data: Option<(u64, u64)>,
+}
+
+/// Determines the row layout of the input for image mode.
+///
+/// # Arguments
+///
+/// * `input` - The input from its start, parsed when `detect` is set.
+/// * `detect` - Whether to detect the layout from a BMP or PGM/PPM header.
+/// * `width` - The number of pixels per row of a raw image, if given.
+/// * `pixel_size` - The number of bytes per pixel of a raw image.
+///
+/// # Returns
+///
+/// Returns `Ok(None)` if image mode was not requested.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the file is not a supported image or the row
+/// length does not fit in 32 bits.
+pub(crate) fn image_layout(
+    input: &mut dyn Read,
+    detect: bool,
+    width: Option<u32>,
+    pixel_size: u8,
+) -> io::Result<Option<ImageLayout>> {
+    if detect {
+        let mut prefix = Vec::new();
+        input.take(DETECTION_PREFIX_LEN).read_to_end(&mut prefix)?;
+        return match prefix.get(..2) {
+            Some(b"BM") => parse_bmp(&prefix).map(Some),
+            Some(b"P5") | Some(b"P6") => parse_pnm(&prefix).map(Some),
+            _ => Err(unsupported_image(
+                "Only uncompressed BMP and binary PGM/PPM (P5/P6) files can be detected. Use --width and --bpp for raw pixel data.",
+            )),
+        };
+    }
+    match width {
+        Some(width) => Ok(Some(ImageLayout {
+            format: "raw",
+            width,
+            pixel_size,
+            row_len: row_len(width as u64 * pixel_size as u64)?,
+            pixel_data: None,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Parses the header of an uncompressed BMP file.
+fn parse_bmp(prefix: &[u8]) -> io::Result<ImageLayout> {
+    if prefix.len() < 34 {
+        return Err(unsupported_image("The BMP header is truncated."));
+    }
+    let u32_at = |at: usize| {
+        u32::from_le_bytes([prefix[at], prefix[at + 1], prefix[at + 2], prefix[at + 3]])
+    };
+    let data_offset = u32_at(10) as u64;
+    let width = u32_at(18) as i32;
+    // A negative height marks a top-down image. The row order does not matter for filtering.
+    let height = (u32_at(22) as i32).unsigned_abs() as u64;
+    let bit_count = u16::from_le_bytes([prefix[28], prefix[29]]) as u64;
+    let compression = u32_at(30);
+
+    // 0 = BI_RGB and 3 = BI_BITFIELDS both store the pixels uncompressed.
+    if compression != 0 && compression != 3 {
+        return Err(unsupported_image(
+            "Compressed BMP images are not supported.",
+        ));
+    }
+    if width <= 0 || !matches!(bit_count, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(unsupported_image(
+            "The BMP header describes an unsupported pixel format.",
+        ));
+    }
+    // Rows are padded to a multiple of 4 bytes.
+    let row_len = row_len((bit_count * width as u64).div_ceil(32) * 4)?;
+    Ok(ImageLayout {
+        format: "BMP",
+        width: width as u32,
+        pixel_size: (bit_count / 8).max(1) as u8,
+        row_len,
+        pixel_data: Some((data_offset, row_len as u64 * height)),
+    })
+}
+
+/// Parses the header of a binary PGM (P5) or PPM (P6) file.
+fn parse_pnm(prefix: &[u8]) -> io::Result<ImageLayout> {
+    let truncated = || unsupported_image("The PGM/PPM header is truncated or malformed.");
+    let (format, channels) = if prefix[1] == b'5' {
+        ("PGM", 1)
+    } else {
+        ("PPM", 3)
+    };
+    let mut position = 2;
+    let mut fields = [0u64; 3];
+    for field in fields.iter_mut() {
+        // Skip the whitespace and comments before the field.
+        loop {
+            match prefix.get(position) {
+                Some(byte) if byte.is_ascii_whitespace() => position += 1,
+                Some(b'#') => {
+                    while prefix.get(position).is_some_and(|&byte| byte != b'\n') {
+                        position += 1;
+                    }
+                }
+                Some(_) => break,
+                None => return Err(truncated()),
+            }
+        }
+        let start = position;
+        while prefix.get(position).is_some_and(u8::is_ascii_digit) {
+            position += 1;
+        }
+        *field = std::str::from_utf8(&prefix[start..position])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(truncated)?;
+    }
+    // Exactly one whitespace byte separates the header from the pixel data.
+    if !prefix.get(position).is_some_and(u8::is_ascii_whitespace) {
+        return Err(truncated());
+    }
+    let [width, height, max_value] = fields;
+    if width == 0 || width > u32::MAX as u64 || max_value == 0 || max_value > 65535 {
+        return Err(truncated());
+    }
+    let pixel_size = channels * if max_value < 256 { 1 } else { 2 };
+    let row_len = row_len(width * pixel_size)?;
+    Ok(ImageLayout {
+        format,
+        width: width as u32,
+        pixel_size: pixel_size as u8,
+        row_len,
+        pixel_data: Some((position as u64 + 1, row_len as u64 * height)),
+    })
+}
+
+/// Checks that a row length fits in the 32-bit header field.
+fn row_len(row_len: u64) -> io::Result<u32> {
+    u32::try_from(row_len).map_err(|_| {
+        unsupported_image("The image rows are too long. A row may hold at most 4 GiB.")
+    })
+}
+
+/// Creates the error reported when the input cannot be processed as an image.
+fn unsupported_image(message: &str) -> io::Error {
+    PurgePackError::InvalidInput(message.to_string()).into()
+}
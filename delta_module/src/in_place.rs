@@ -0,0 +1,503 @@
+//! In-place transforms (`--in-place`): the file is rewritten within itself, so the disk
+//! never holds a second copy of its data.
+//!
+//! The byte transform keeps the length of the data, so every byte of the output lands a
+//! fixed distance from the byte of the input it comes from: the header is added in front
+//! when encoding and dropped when decoding. The file is renamed to a hidden work name next
+//! to it, then transformed in steps of [`STEP_LEN`] bytes that are read, transformed and
+//! written back at their new position. Before a step overwrites anything, a journal is
+//! committed next to the file holding the input bytes of the step, the bytes the step
+//! overwrites before they are read, and the last original bytes the state of the transform
+//! is rebuilt from (see [`ByteStream`]). It never holds more than a step and a few header
+//! sizes of data.
+//!
+//! ```text
+//! notes.txt -> .notes.txt.in-place (+ .notes.txt.journal) -> notes.txt.ppcb
+//! ```
+//!
+//! Once all steps are written, the file is cut to the length of the output and renamed to
+//! its final name, and the journal is removed. A run that is interrupted leaves the work
+//! file and the journal behind, never a half-transformed file under the input or output
+//! name. Running the same command again finds the journal, repeats the step it records and
+//! finishes the run with the settings of the journal.
+//!
+//! Only the delta and XOR byte transforms without blocks or a post pass can be done in
+//! place. The other settings change the length of the data by an amount that is only known
+//! once it is transformed, so they need a separate output file. The input is read once
+//! before it is touched: encoding computes the checksum for the header, decoding checks
+//! that the file restores to the data it was written from.
+
+use crate::{FILE_EXTENSION, RunOptions};
+use purgepack_delta::{ByteStream, DeltaHeader, DeltaParameters, HEADER_SIZE, Transform};
+use shared_files::checksum::{ChecksumReader, Crc32};
+use shared_files::core_header;
+use shared_files::error::PurgePackError;
+use shared_files::log_info;
+use shared_files::output::SafeWriter;
+use shared_files::progress;
+use shared_files::stats::OptinalStatsTimer;
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// The number of bytes transformed per step, between two commits of the journal.
+const STEP_LEN: usize = 4 << 20;
+/// The magic number of a journal.
+const JOURNAL_MAGIC: &[u8; 4] = b"PPDJ";
+/// The version of the journal layout.
+const JOURNAL_VERSION: u8 = 1;
+
+/// Everything needed to repeat the next step of an in-place run and finish it.
+///
+/// The body is the data after the header: the whole input when encoding, everything but
+/// the header when decoding. Body byte `b` is read from `in_base + b` and written to
+/// `out_base + b`.
+struct Journal {
+    /// The direction of the run.
+    transform_type: Transform,
+    /// The delta header: the one written in front of the output when encoding, the one
+    /// read from the input when decoding.
+    header: Vec<u8>,
+    /// The file name the output is renamed to, in the directory of the input.
+    final_name: String,
+    /// The offset of the body in the input.
+    in_base: u64,
+    /// The offset of the body in the output.
+    out_base: u64,
+    /// The length of the body.
+    body_len: u64,
+    /// The first body byte the next step writes.
+    position: u64,
+    /// The last original bytes of the transformed range before `position`.
+    history: Vec<u8>,
+    /// The input bytes of the body from `position` on that the next step needs.
+    pending: Vec<u8>,
+}
+
+/// Returns the work file and the journal of an in-place run on `input_file`.
+fn side_paths(input_file: &Path) -> (PathBuf, PathBuf) {
+    let name = input_file.file_name().unwrap_or_default();
+    let side_path = |suffix: &str| {
+        let mut side_name = OsString::from(".");
+        side_name.push(name);
+        side_name.push(suffix);
+        input_file.with_file_name(side_name)
+    };
+    (side_path(".in-place"), side_path(".journal"))
+}
+
+/// Returns `true` if an in-place run on `input_file` was interrupted and left its journal.
+pub(crate) fn is_interrupted(input_file: &Path) -> bool {
+    side_paths(input_file).1.exists()
+}
+
+/// Transforms `input_file` in place and renames it to the name with the `.ppcb` extension
+/// added (encoding) or removed (decoding).
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the settings change the length of the data, an
+/// `AlreadyExists` error if the overwrite policy refuses the final name, an `InvalidData`
+/// error if the input to decode is corrupted, or an `io::Error` if reading or writing
+/// fails. Errors before the first step leave the input as it is.
+pub(crate) fn transform(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    transform_type: Transform,
+    parameters: DeltaParameters,
+    range: crate::ByteRange,
+    options: &RunOptions,
+) -> io::Result<()> {
+    let mut main_timer = OptinalStatsTimer::new(options.stats.is_some());
+    let final_file = match transform_type {
+        Transform::Encode => {
+            let mut name = input_file.as_os_str().to_owned();
+            name.push(".");
+            name.push(FILE_EXTENSION);
+            PathBuf::from(name)
+        }
+        Transform::Decode => {
+            crate::check_decode_extension(input_file)?;
+            input_file.with_extension("")
+        }
+    };
+    let final_file = options.overwrite.resolve(&final_file)?;
+    let final_name = final_file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            PurgePackError::InvalidInput(format!(
+                "The output name of an in-place run must be valid UTF-8: {}",
+                final_file.display()
+            ))
+        })?
+        .to_string();
+
+    let t_check = main_timer.start_section("Input Check");
+    let original_len = fs::metadata(input_file)?.len();
+    let journal = match transform_type {
+        Transform::Encode => prepare_encode(input_file, parameters, range, final_name)?,
+        Transform::Decode => prepare_decode(input_file, final_name)?,
+    };
+    main_timer.add_section(t_check);
+    let parameters = purgepack_delta::read_header(&mut journal.header.as_slice())?.parameters;
+
+    let (work_file, journal_file) = side_paths(input_file);
+    journal.commit(&journal_file)?;
+    fs::rename(input_file, &work_file)?;
+
+    let t_process = main_timer.start_section("In-Place Processing");
+    rewrite(input_file, journal)?;
+    main_timer.add_section(t_process);
+    log_info!(
+        "In-place: {} replaced by {}",
+        input_file.display(),
+        final_file.display()
+    );
+    if let Some(format) = options.stats {
+        crate::print_statistics(
+            core,
+            main_timer,
+            &File::open(&final_file)?,
+            original_len as usize,
+            parameters,
+            transform_type,
+            format,
+        )?;
+    }
+    Ok(())
+}
+
+/// Finishes the interrupted in-place run on `input_file` with the settings of its journal.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the journal is damaged, a `NotFound` error if the
+/// work file is gone, or an `io::Error` if reading or writing fails.
+pub(crate) fn resume(input_file: &Path) -> io::Result<()> {
+    let (work_file, journal_file) = side_paths(input_file);
+    let journal = Journal::read(&journal_file)?;
+    let final_file = input_file.with_file_name(&journal.final_name);
+    log_info!(
+        "In-place: Resuming the interrupted run on {} at byte {} of {}",
+        input_file.display(),
+        journal.position,
+        journal.body_len
+    );
+    if !work_file.exists() {
+        if input_file.exists() {
+            // The run stopped between committing the journal and renaming the input.
+            fs::rename(input_file, &work_file)?;
+        } else if final_file.exists() {
+            // The run stopped after renaming the output, only the journal was left.
+            fs::remove_file(&journal_file)?;
+            return Ok(());
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "The work file of the interrupted run is missing: {}",
+                    work_file.display()
+                ),
+            ));
+        }
+    }
+    rewrite(input_file, journal)?;
+    log_info!(
+        "In-place: {} replaced by {}",
+        input_file.display(),
+        final_file.display()
+    );
+    Ok(())
+}
+
+/// Reads the input to encode once to compute its checksum, and returns the journal of the
+/// first step.
+fn prepare_encode(
+    input_file: &Path,
+    parameters: DeltaParameters,
+    range: crate::ByteRange,
+    final_name: String,
+) -> io::Result<Journal> {
+    check_supported(parameters)?;
+    let mut reader = ChecksumReader::new(BufReader::new(File::open(input_file)?), Crc32::new());
+    let file_len = io::copy(&mut reader, &mut io::sink())?;
+    let (offset, data_len) = crate::resolve_range(range, file_len)?;
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+    purgepack_delta::write_header(
+        &mut header,
+        &DeltaHeader {
+            parameters,
+            data_len,
+            range_offset: offset,
+            checksum: reader.hasher().value(),
+            baseline: false,
+        },
+    )?;
+    Journal::first(
+        input_file,
+        Transform::Encode,
+        header,
+        final_name,
+        0,
+        HEADER_SIZE,
+        file_len,
+    )
+}
+
+/// Reads the input to decode once to check that it restores to the data it was written
+/// from, and returns the journal of the first step.
+fn prepare_decode(input_file: &Path, final_name: String) -> io::Result<Journal> {
+    let mut reader = BufReader::new(File::open(input_file)?);
+    let file_len = reader.get_ref().metadata()?.len();
+    let mut prefix = Vec::with_capacity(HEADER_SIZE as usize);
+    (&mut reader).take(HEADER_SIZE).read_to_end(&mut prefix)?;
+    let mut rest = prefix.as_slice();
+    let header = purgepack_delta::read_header(&mut rest)?;
+    if header.baseline {
+        // The journal finds the transformed range through the lengths in the header.
+        return Err(PurgePackError::InvalidInput(
+            "--in-place does not restore files of the first release, which store no lengths. \
+             Write to an output file instead."
+                .to_string(),
+        )
+        .into());
+    }
+    check_supported(header.parameters)?;
+    let header_len = (prefix.len() - rest.len()) as u64;
+    prefix.truncate(header_len as usize);
+
+    reader.seek(SeekFrom::Start(header_len))?;
+    purgepack_delta::decompress(&mut reader, &mut io::sink(), &header)?;
+    Journal::first(
+        input_file,
+        Transform::Decode,
+        prefix,
+        final_name,
+        header_len,
+        0,
+        file_len - header_len,
+    )
+}
+
+/// Checks that the transform with `parameters` keeps the length of the data.
+fn check_supported(parameters: DeltaParameters) -> io::Result<()> {
+    if !ByteStream::supports(parameters) {
+        return Err(PurgePackError::InvalidInput(
+            "--in-place only supports the delta and XOR byte transforms without --block-size, \
+             --word-size, --varint, --post, --image, --width or --timestamps. Write to an \
+             output file instead."
+                .to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Runs the steps recorded in `journal` on the work file of `input_file` until the output
+/// is complete, then renames it to its final name and removes the journal.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails. The work file and the journal are
+/// left for the next run to resume then.
+fn rewrite(input_file: &Path, mut journal: Journal) -> io::Result<()> {
+    let (work_file, journal_file) = side_paths(input_file);
+    let header = purgepack_delta::read_header(&mut journal.header.as_slice())?;
+    let range_start = header.range_offset;
+    let range_end = header.range_offset + header.data_len;
+    let history_len = ByteStream::history_len(header.parameters);
+    // The bytes the output runs ahead of the input are overwritten before they are read,
+    // so every step keeps them back for the next one.
+    let ahead = journal.out_base.saturating_sub(journal.in_base) as usize;
+    let mut stream =
+        ByteStream::resume(header.parameters, journal.transform_type, &journal.history)?;
+    let mut progress = progress::terminal("In-place", Some(journal.body_len));
+    progress.advance(journal.position);
+
+    let mut file = OpenOptions::new().read(true).write(true).open(&work_file)?;
+    loop {
+        let position = journal.position;
+        let step_len = if position + journal.pending.len() as u64 == journal.body_len {
+            journal.pending.len()
+        } else {
+            journal.pending.len() - ahead
+        };
+        let input = &journal.pending[..step_len];
+
+        // Only the bytes inside the range are transformed, the others are copied through.
+        let start = range_start.clamp(position, position + step_len as u64) - position;
+        let end = range_end.clamp(position, position + step_len as u64) - position;
+        let (start, end) = (start as usize, end as usize);
+        let mut output = Vec::with_capacity(step_len);
+        output.extend_from_slice(&input[..start]);
+        output.extend(stream.transform(&input[start..end]));
+        output.extend_from_slice(&input[end..]);
+
+        if position == 0 && journal.out_base > 0 {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&journal.header)?;
+        }
+        file.seek(SeekFrom::Start(journal.out_base + position))?;
+        file.write_all(&output)?;
+        file.sync_data()?;
+        progress.advance(step_len as u64);
+
+        let original = match journal.transform_type {
+            Transform::Encode => &input[start..end],
+            Transform::Decode => &output[start..end],
+        };
+        journal.history.extend_from_slice(original);
+        let excess = journal.history.len().saturating_sub(history_len);
+        journal.history.drain(..excess);
+        journal.pending.drain(..step_len);
+        journal.position += step_len as u64;
+        if journal.position == journal.body_len {
+            break;
+        }
+
+        // The next input bytes have not been overwritten yet: the output of this step
+        // ends right where they start.
+        let read_from = journal.position + journal.pending.len() as u64;
+        let read_len = (journal.body_len - read_from).min(STEP_LEN as u64);
+        file.seek(SeekFrom::Start(journal.in_base + read_from))?;
+        (&mut file)
+            .take(read_len)
+            .read_to_end(&mut journal.pending)?;
+        if journal.position + journal.pending.len() as u64 != read_from + read_len {
+            return Err(PurgePackError::Truncated(format!(
+                "The work file {} ended early.",
+                work_file.display()
+            ))
+            .into());
+        }
+        journal.commit(&journal_file)?;
+    }
+
+    file.set_len(journal.out_base + journal.body_len)?;
+    file.sync_all()?;
+    drop(file);
+    progress.finish();
+    fs::rename(&work_file, input_file.with_file_name(&journal.final_name))?;
+    fs::remove_file(&journal_file)
+}
+
+impl Journal {
+    /// Reads the first step of the body of `input_file`, which is not touched yet.
+    fn first(
+        input_file: &Path,
+        transform_type: Transform,
+        header: Vec<u8>,
+        final_name: String,
+        in_base: u64,
+        out_base: u64,
+        body_len: u64,
+    ) -> io::Result<Self> {
+        let ahead = out_base.saturating_sub(in_base);
+        let mut file = File::open(input_file)?;
+        file.seek(SeekFrom::Start(in_base))?;
+        let mut pending = Vec::new();
+        file.take((STEP_LEN as u64 + ahead).min(body_len))
+            .read_to_end(&mut pending)?;
+        Ok(Journal {
+            transform_type,
+            header,
+            final_name,
+            in_base,
+            out_base,
+            body_len,
+            position: 0,
+            history: Vec::new(),
+            pending,
+        })
+    }
+
+    /// Writes the journal to `journal_file`, replacing the previous one atomically.
+    fn commit(&self, journal_file: &Path) -> io::Result<()> {
+        let mut data = Vec::with_capacity(64 + self.pending.len() + self.history.len());
+        data.extend_from_slice(JOURNAL_MAGIC);
+        data.push(JOURNAL_VERSION);
+        data.push(match self.transform_type {
+            Transform::Encode => 0,
+            Transform::Decode => 1,
+        });
+        for value in [self.in_base, self.out_base, self.body_len, self.position] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for field in [
+            self.header.as_slice(),
+            self.final_name.as_bytes(),
+            &self.history,
+            &self.pending,
+        ] {
+            data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            data.extend_from_slice(field);
+        }
+        data.extend_from_slice(&Crc32::of(&data).to_le_bytes());
+
+        let mut writer = SafeWriter::create(journal_file)?;
+        writer.write_all(&data)?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    /// Reads the journal at `journal_file`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the journal is damaged or was written by a newer
+    /// build, or an `io::Error` if it cannot be read.
+    fn read(journal_file: &Path) -> io::Result<Self> {
+        let data = fs::read(journal_file)?;
+        let damaged = || -> io::Error {
+            PurgePackError::InvalidHeader(format!(
+                "The journal {} is damaged.",
+                journal_file.display()
+            ))
+            .into()
+        };
+        let (body, checksum) = data.split_last_chunk::<4>().ok_or_else(damaged)?;
+        if !body.starts_with(JOURNAL_MAGIC) || Crc32::of(body) != u32::from_le_bytes(*checksum) {
+            return Err(damaged());
+        }
+        let mut fields = &body[JOURNAL_MAGIC.len()..];
+        let mut take = |len: usize| -> io::Result<&[u8]> {
+            let (field, rest) = fields.split_at_checked(len).ok_or_else(damaged)?;
+            fields = rest;
+            Ok(field)
+        };
+        if take(1)?[0] != JOURNAL_VERSION {
+            return Err(damaged());
+        }
+        let transform_type = match take(1)?[0] {
+            0 => Transform::Encode,
+            1 => Transform::Decode,
+            _ => return Err(damaged()),
+        };
+        let mut number =
+            || -> io::Result<u64> { Ok(u64::from_le_bytes(take(8)?.try_into().expect("8 bytes"))) };
+        let (in_base, out_base, body_len, position) = (number()?, number()?, number()?, number()?);
+        let mut field = || -> io::Result<Vec<u8>> {
+            let len = u32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+            Ok(take(len as usize)?.to_vec())
+        };
+        let header = field()?;
+        let final_name = String::from_utf8(field()?).map_err(|_| damaged())?;
+        let history = field()?;
+        let pending = field()?;
+        Ok(Journal {
+            transform_type,
+            header,
+            final_name,
+            in_base,
+            out_base,
+            body_len,
+            position,
+            history,
+            pending,
+        })
+    }
+}
@@ -1,470 +1,908 @@
+//! Delta transforms for sequential and structured data.
+//!
+//! The transforms themselves live in the `purgepack_delta` crate. This module handles the
+//! command line, the files, in-place replacement, the thread pool and the statistics.
 use std::{
-    fs::File,
-    io::{self, BufRead, Read, Write},
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
     path::{self},
 };
 mod cli_parse;
-use shared_files::core_header::{self};
+mod entropy;
+mod histogram;
+mod image;
+mod in_place;
+use purgepack_delta::{
+    Algorithm, Delta, DeltaParameters, HEADER_SIZE, MODULE_ID, PostPass, Transform,
+};
+use shared_files::abi::{self, RawReader, RawSeekReader, RawSeekWriter, RawWriter, ServiceResult};
+use shared_files::budget::MemoryBudget;
+use shared_files::cancel::CancellableReader;
+use shared_files::cli::{self, CliError};
+use shared_files::codec::{self, CodecStats, Compressor, Decompressor, ReadSeek, WriteSeek};
+use shared_files::core_header::{self, DELTA_DECODE_SERVICE, DELTA_ENCODE_SERVICE};
+use shared_files::error::PurgePackError;
+use shared_files::events::{self, Event};
+use shared_files::frame::{self, FrameHeader};
+use shared_files::output::{Overwrite, SafeWriter};
+use shared_files::progress::{self, ProgressReader};
+use shared_files::selftest::{SelfTestReport, SelfTestSink};
+use shared_files::stats::{DEFAULT_SAMPLE_INTERVAL, StatsFormat};
+use shared_files::{log_debug, log_error, log_info};
+use std::ffi::c_char;
 
-/// The direction of the transformation (Encode or Decode).
-#[derive(Debug, Clone, Copy)]
-enum Transform {
-    /// Applies delta encoding (current byte - previous byte). Used for Transformation.
-    Encode,
-    /// Applies delta decoding (current byte + previous byte). Used for inverse transformation.
-    Decode,
+/// The part of the input file that is transformed when encoding.
+///
+/// The bytes before and after the range are copied through unchanged, or left out of
+/// the output entirely when `crop` is set.
+#[derive(Debug, Clone, Copy, Default)]
+struct ByteRange {
+    /// The offset of the first transformed byte.
+    offset: u64,
+    /// The number of transformed bytes, or `None` to transform up to the end of the file.
+    length: Option<u64>,
+    /// Whether the bytes outside the range are skipped instead of copied through.
+    crop: bool,
 }
 
-/// Magic bytes to identify the PurgePack application. PPCB stands for "PurgePack Compressed Binary".
-const APPLICATION_MAGIC: [u8; 4] = *b"PPCB";
-/// Module ID (Algorithm Identifier) for the current First-Order Delta Encoding/Decoding.
-const MODULE_ID: u8 = 0x01;
-/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID).
-const HEADER_SIZE: u64 = 5;
-// The PurgePack header contains a magic number (4 bytes) and a module ID (1 byte).
-struct PurgePackHeader {
-    application_magic: [u8; 4],
-    module_id: u8,
+/// Settings of a single run that do not affect the output format.
+#[derive(Debug, Clone)]
+struct RunOptions {
+    /// The format statistics are printed in, or `None` if they are not calculated.
+    stats: Option<StatsFormat>,
+    /// The path of a CSV file to write the residual histogram to when encoding
+    /// with statistics enabled.
+    histogram: Option<path::PathBuf>,
+    /// The size in bytes of the read and write buffers.
+    chunk_size: usize,
+    /// What happens if the output file exists already.
+    overwrite: Overwrite,
 }
+
 // The file extension for PurgePack Compressed Binary (PPCB) files.
 const FILE_EXTENSION: &str = "ppcb";
 
+shared_files::export_module_abi!();
+
 /// The main entry point for the module when it is started.
 ///
 /// This function is responsible for:
 /// 1. Parsing and validating command-line arguments via the `cli_parse` module.
-/// 2. Determining the requested operation (Encode or Decode) based on the command.
-/// 3. Initiating the file processing via `start_proccessing_file`.
+/// 2. Determining the requested operation (Encode, Decode, Diff or Patch) based on the command,
+///    or on the header of the input file for `auto`.
+/// 3. Initiating the file processing via `start_proccessing_file` (or the `patch` module).
 /// 4. Handling and reporting any CLI parsing or file processing errors.
 #[unsafe(no_mangle)]
-extern "C" fn module_startup(_core: &core_header::CoreH, args: &mut Vec<String>) {
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("delta", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
     args.insert(0, "dummy_program_name".to_string());
     match cli_parse::parse_args(&args) {
-        Ok(args) => match args.command {
-            cli_parse::Commands::Transform(args) => {
-                println!(
-                    "Transform: Input: {}, Output: {}",
-                    args.input_file.display(),
-                    args.output_file.display()
-                );
-                println!(
-                    "Transform: Statistics: {}",
-                    if args.stats { "Enabled" } else { "Disabled" }
-                );
-                let transform_type = Transform::Encode;
-                match start_proccessing_file(
-                    args.input_file,
-                    args.output_file,
-                    transform_type,
-                    args.stats,
-                ) {
-                    Ok(()) => println!("Transform: Success"),
-                    Err(e) => println!("Transform: Error: {}", e),
-                }
-            }
-            cli_parse::Commands::Inverse(args) => {
-                println!(
-                    "Inverse: Input: {}, Output: {}",
-                    args.input_file.display(),
-                    args.output_file.display()
-                );
-                println!(
-                    "Inverse: Statistics: {}",
-                    if args.stats { "Enabled" } else { "Disabled" }
-                );
-                let transform_type = Transform::Decode;
-                match start_proccessing_file(
-                    args.input_file,
-                    args.output_file,
-                    transform_type,
-                    args.stats,
-                ) {
-                    Ok(()) => println!("Inverse: Success"),
-                    Err(e) => println!("Inverse: Error: {}", e),
+        Ok(args) => {
+            // A journal left by an interrupted in-place run is finished first, with its own settings.
+            if let Some(input_file) = args.command.interrupted_input() {
+                match in_place::resume(input_file) {
+                    Ok(()) => log_info!("Resume: Success"),
+                    Err(e) => {
+                        log_error!("Resume: {}", e);
+                        core_header::report_error(core, &e);
+                    }
                 }
+                return;
             }
-        },
-        Err(cli_parse::CliError::ClapError(e)) => {
-            println!("Error during argument parsing:");
-            eprintln!("{}", e);
-        }
-        Err(e) => {
-            println!("Error during argument validation:");
-            match e {
-                cli_parse::CliError::InputFileNotFound(path) => {
-                    println!("Error: Input file does not exist: {}", path.display());
+            match args.command {
+                cli_parse::Commands::Transform(args) => {
+                    log_info!(
+                        "Transform: Input: {}, Output: {}",
+                        args.common.input_file.display(),
+                        describe_output(args.common.output_file.as_deref())
+                    );
+                    if !args.common.overwrite.force
+                        && let Err(e) = check_not_encoded(&args.common.input_file)
+                    {
+                        log_error!("Transform: {}", e);
+                        core_header::report_error(core, &e);
+                        return;
+                    }
+                    log_info!(
+                        "Transform: Statistics: {}",
+                        if args.common.stats.enabled() {
+                            "Enabled"
+                        } else {
+                            "Disabled"
+                        }
+                    );
+                    let settings = File::open(&args.common.input_file)
+                        .and_then(|mut input| transform_settings(&args, &mut input));
+                    let (parameters, range) = match settings {
+                        Ok(settings) => settings,
+                        Err(e) => {
+                            log_error!("Transform: {}", e);
+                            core_header::report_error(core, &e);
+                            return;
+                        }
+                    };
+                    let transform_type = Transform::Encode;
+                    match with_thread_pool(args.common.threads, || {
+                        write_output(
+                            core,
+                            args.common.input_file,
+                            args.common.output_file,
+                            transform_type,
+                            parameters,
+                            range,
+                            RunOptions {
+                                stats: args.common.stats.format(),
+                                histogram: args.histogram,
+                                chunk_size: args.common.chunk_size as usize,
+                                overwrite: args.common.overwrite.policy(),
+                            },
+                        )
+                    }) {
+                        Ok(()) => log_info!("Transform: Success"),
+                        Err(e) => {
+                            log_error!("Transform: {}", e);
+                            core_header::report_error(core, &e);
+                        }
+                    }
                 }
-                cli_parse::CliError::InputNotFile(path) => {
-                    println!("Error: Input path is not a file: {}", path.display());
+                cli_parse::Commands::Inverse(args) => {
+                    log_info!(
+                        "Inverse: Input: {}, Output: {}",
+                        args.input_file.display(),
+                        describe_output(args.output_file.as_deref())
+                    );
+                    log_info!(
+                        "Inverse: Statistics: {}",
+                        if args.stats.enabled() {
+                            "Enabled"
+                        } else {
+                            "Disabled"
+                        }
+                    );
+                    let transform_type = Transform::Decode;
+                    // The parameters are read back from the header, so the defaults passed here are ignored.
+                    match with_thread_pool(args.threads, || {
+                        write_output(
+                            core,
+                            args.input_file,
+                            args.output_file,
+                            transform_type,
+                            DeltaParameters::default(),
+                            ByteRange::default(),
+                            RunOptions {
+                                stats: args.stats.format(),
+                                histogram: None,
+                                chunk_size: args.chunk_size as usize,
+                                overwrite: args.overwrite.policy(),
+                            },
+                        )
+                    }) {
+                        Ok(()) => log_info!("Inverse: Success"),
+                        Err(e) => {
+                            log_error!("Inverse: {}", e);
+                            core_header::report_error(core, &e);
+                        }
+                    }
                 }
-                cli_parse::CliError::OutputParentDirNotFound(path) => {
-                    println!(
-                        "Error: The output directory does not exist: {}",
-                        path.display()
+                cli_parse::Commands::Auto(args) => {
+                    log_info!(
+                        "Auto: Input: {}, Output: {}",
+                        args.input_file.display(),
+                        describe_output(args.output_file.as_deref())
                     );
-                    println!("Please ensure the directory is created: {}", path.display());
+                    let transform_type = match is_delta_encoded(&args.input_file) {
+                        Ok(true) => {
+                            log_info!("Auto: The input is delta encoded, applying Inverse");
+                            Transform::Decode
+                        }
+                        Ok(false) => {
+                            log_info!("Auto: The input is not delta encoded, applying Transform");
+                            Transform::Encode
+                        }
+                        Err(e) => {
+                            log_error!("Auto: {}", e);
+                            core_header::report_error(core, &e);
+                            return;
+                        }
+                    };
+                    // Encoding uses the default parameters, decoding reads them from the header.
+                    match with_thread_pool(args.threads, || {
+                        write_output(
+                            core,
+                            args.input_file,
+                            args.output_file,
+                            transform_type,
+                            DeltaParameters::default(),
+                            ByteRange::default(),
+                            RunOptions {
+                                stats: args.stats.format(),
+                                histogram: None,
+                                chunk_size: args.chunk_size as usize,
+                                overwrite: args.overwrite.policy(),
+                            },
+                        )
+                    }) {
+                        Ok(()) => log_info!("Auto: Success"),
+                        Err(e) => {
+                            log_error!("Auto: {}", e);
+                            core_header::report_error(core, &e);
+                        }
+                    }
                 }
-                cli_parse::CliError::OutputParentNotDir(path) => {
-                    println!(
-                        "Error: The parent path of the output file is not a directory: {}",
-                        path.display()
+                cli_parse::Commands::Diff(args) => {
+                    log_info!(
+                        "Diff: Old: {}, New: {}, Patch: {}",
+                        args.old_file.display(),
+                        args.new_file.display(),
+                        args.patch_file.display()
                     );
+                    match check_diff_memory(core, &args.old_file, &args.new_file).and_then(|()| {
+                        let patch_file = args.overwrite.policy().resolve(&args.patch_file)?;
+                        purgepack_delta::create_patch(&args.old_file, &args.new_file, &patch_file)
+                    }) {
+                        Ok(summary) => {
+                            log_info!(
+                                "Diff: {} copies ({} bytes), {} inserts ({} bytes), patch size {} bytes",
+                                summary.copies,
+                                summary.copied_bytes,
+                                summary.inserts,
+                                summary.inserted_bytes,
+                                summary.patch_len
+                            );
+                            log_info!("Diff: Success");
+                        }
+                        Err(e) => {
+                            log_error!("Diff: {}", e);
+                            core_header::report_error(core, &e);
+                        }
+                    }
                 }
-                _ => {
-                    eprintln!("Unhandled argument error: {:?}", e);
+                cli_parse::Commands::Patch(args) => {
+                    log_info!(
+                        "Patch: Old: {}, Patch: {}, Output: {}",
+                        args.old_file.display(),
+                        args.patch_file.display(),
+                        args.output_file.display()
+                    );
+                    match args.overwrite.policy().resolve(&args.output_file).and_then(
+                        |output_file| {
+                            purgepack_delta::apply_patch(
+                                &args.old_file,
+                                &args.patch_file,
+                                &output_file,
+                            )
+                        },
+                    ) {
+                        Ok(()) => log_info!("Patch: Success"),
+                        Err(e) => {
+                            log_error!("Patch: {}", e);
+                            core_header::report_error(core, &e);
+                        }
+                    }
                 }
             }
         }
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
     }
 }
 
+/// Checks that diffing `old_file` against `new_file`, which holds both in memory, stays
+/// within the memory limit of the core.
+fn check_diff_memory(
+    core: &core_header::CoreH,
+    old_file: &path::Path,
+    new_file: &path::Path,
+) -> io::Result<()> {
+    let needed = purgepack_delta::create_patch_memory(
+        fs::metadata(old_file)?.len(),
+        fs::metadata(new_file)?.len(),
+    );
+    MemoryBudget::from_core(core).check(needed, "Diffing the files")?;
+    Ok(())
+}
+
+/// Returns the parameters and the byte range of the forward transform `args` asks for,
+/// and logs them.
+///
+/// `input` is the input from its start, read to detect the layout of an image with
+/// `--image`.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the input is not a supported image or the
+/// timestamp layout is invalid, or an `io::Error` if reading fails.
+fn transform_settings(
+    args: &cli_parse::TransformArgs,
+    input: &mut dyn Read,
+) -> io::Result<(DeltaParameters, ByteRange)> {
+    // Predicting `2 * prev - prev2` leaves the residual
+    // `x - 2 * prev + prev2`, which is exactly the second-order delta.
+    let order = if args.linear { 2 } else { args.order };
+    log_info!(
+        "Transform: Order: {}, Stride: {}, Word Size: {}, Varint Residuals: {}",
+        order,
+        args.stride,
+        args.word_size,
+        if args.varint { "Enabled" } else { "Disabled" }
+    );
+    if args.xor {
+        log_info!("Transform: XOR Residuals: Enabled");
+    }
+    if args.linear {
+        log_info!("Transform: Predictor: Linear (2 * prev - prev2)");
+    }
+    if args.seed_block {
+        log_info!(
+            "Transform: Seed Block: {} samples stored verbatim",
+            args.stride
+        );
+    }
+    if let Some(block_size) = args.block_size {
+        log_info!("Transform: Block Size: {} bytes", block_size);
+    }
+    let image_layout = image::image_layout(input, args.image, args.width, args.bpp)?;
+    let (offset, length) = match image_layout.and_then(|layout| layout.pixel_data) {
+        Some((offset, length)) => (offset, Some(length)),
+        None => (args.offset, args.length),
+    };
+    if let Some(layout) = image_layout {
+        log_info!(
+            "Transform: Image: {}, {} pixels per row, {} bytes per pixel, {} bytes per row",
+            layout.format,
+            layout.width,
+            layout.pixel_size,
+            layout.row_len
+        );
+    }
+    if offset != 0 || length.is_some() {
+        log_info!(
+            "Transform: Range: offset {}, length {}{}",
+            offset,
+            length.map_or("to end of file".to_string(), |len| len.to_string()),
+            if args.crop { " (cropped)" } else { "" }
+        );
+    }
+    if args.timestamps {
+        purgepack_delta::check_timestamp_layout(args.record_size, args.timestamp_offset)?;
+        log_info!(
+            "Transform: Timestamps: {} byte records, timestamp at offset {}",
+            args.record_size,
+            args.timestamp_offset
+        );
+    }
+    let post_pass = match args.post.as_str() {
+        "rle0" => PostPass::Rle0,
+        _ => PostPass::None,
+    };
+    if post_pass == PostPass::Rle0 {
+        log_info!("Transform: Post Pass: Run-length coding of zero residuals");
+    }
+    let algorithm = if image_layout.is_some() {
+        Algorithm::RowFilter
+    } else if args.timestamps {
+        Algorithm::Timestamp
+    } else if args.xor {
+        Algorithm::Xor
+    } else {
+        Algorithm::Delta
+    };
+    let parameters = DeltaParameters {
+        algorithm,
+        order,
+        stride: args.stride,
+        word_size: args.word_size,
+        varint: args.varint,
+        block_size: args.block_size.unwrap_or(0),
+        row_len: image_layout.map_or(0, |layout| layout.row_len),
+        pixel_size: image_layout.map_or(1, |layout| layout.pixel_size),
+        record_size: args.record_size,
+        field_offset: args.timestamp_offset,
+        post_pass,
+        seed_block: args.seed_block,
+    };
+    let range = ByteRange {
+        offset,
+        length,
+        crop: args.crop,
+    };
+    Ok((parameters, range))
+}
+
+/// Registers the delta transform as a service for the other modules, and the pipeline
+/// step of the module with the core.
+#[unsafe(no_mangle)]
+extern "C" fn module_register(core: &core_header::CoreH) {
+    core_header::register_service(core, DELTA_ENCODE_SERVICE, delta_encode_service);
+    core_header::register_service(core, DELTA_DECODE_SERVICE, delta_decode_service);
+    core_header::register_step(core, env!("CARGO_PKG_NAME"), pipeline_step);
+}
+
+/// Applies the default delta transform to all data of `input`.
+extern "C" fn delta_encode_service(
+    input: &mut RawReader,
+    output: &mut RawWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    abi::serve(input, output, result, |reader, writer| {
+        Delta::default().compress(reader, writer)
+    })
+}
+
+/// Restores the data of a file written by `delta_encode_service`.
+extern "C" fn delta_decode_service(
+    input: &mut RawReader,
+    output: &mut RawWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    abi::serve(input, output, result, |reader, writer| {
+        Delta::default().decompress(reader, writer)
+    })
+}
+
+/// Runs a step of a pipeline of the core, like `transform --stride 2`, on the streams the
+/// core hands over instead of files. Only `transform` and `inverse` run as steps.
+extern "C" fn pipeline_step(
+    core: &core_header::CoreH,
+    argc: usize,
+    argv: *const *const c_char,
+    input: &mut RawSeekReader,
+    output: &mut RawSeekWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    shared_files::log::init("delta", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let args = unsafe { abi::args_from_c(argc, argv) };
+    abi::serve_step(core, input, output, result, |reader, writer| {
+        let mut reader = CancellableReader::new(reader, core);
+        match cli::parse_step::<cli_parse::CliArgs>(&args)?.command {
+            cli_parse::Commands::Transform(args) => transform_step(&args, &mut reader, writer),
+            cli_parse::Commands::Inverse(_) => Delta::default().decompress(&mut reader, writer),
+            _ => Err(PurgePackError::InvalidInput(
+                "Only transform and inverse run as steps of a pipeline.".to_string(),
+            )
+            .into()),
+        }
+    })
+}
+
+/// Applies the forward transform `args` asks for to the data of `reader` from its
+/// position on, like `start_proccessing_file` does to a file.
+///
+/// # Errors
+///
+/// Returns the error of `transform_settings` or `resolve_range`, or an `io::Error` if
+/// reading, writing or seeking fails.
+fn transform_step(
+    args: &cli_parse::TransformArgs,
+    reader: &mut dyn ReadSeek,
+    writer: &mut dyn WriteSeek,
+) -> io::Result<CodecStats> {
+    let start = reader.stream_position()?;
+    let (parameters, range) = transform_settings(args, reader)?;
+    reader.seek(SeekFrom::Start(start))?;
+    codec::compress_stream(reader, writer, |reader, writer, input_len| {
+        let (offset, data_len) = resolve_range(range, input_len)?;
+        // A cropped output starts directly with the transformed range.
+        let range_offset = if range.crop { 0 } else { offset };
+        if range.crop {
+            reader.seek(SeekFrom::Start(offset))?;
+        }
+        purgepack_delta::compress(
+            reader,
+            writer,
+            parameters,
+            data_len,
+            range_offset,
+            !range.crop,
+        )
+    })
+}
+
 /// The shutdown function for the module.
 #[unsafe(no_mangle)]
-extern "C" fn module_shutdown(_core: &core_header::CoreH) {
-    println!("Delta encoder module shutting down.");
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("delta", core.log_level);
+    log_debug!("Delta encoder module shutting down.");
+}
+
+/// Runs round trips of the codec on synthetic data for `purgepack test`.
+#[unsafe(no_mangle)]
+extern "C" fn module_selftest(_core: &core_header::CoreH, sink: &mut SelfTestSink) {
+    let mut report = SelfTestReport::new();
+    report.round_trip("default", &Delta::default());
+    let words = DeltaParameters {
+        word_size: 2,
+        varint: true,
+        ..DeltaParameters::default()
+    };
+    report.round_trip("16-bit varint", &Delta { parameters: words });
+    let blocks = DeltaParameters {
+        block_size: 4096,
+        ..DeltaParameters::default()
+    };
+    report.round_trip("blocks", &Delta { parameters: blocks });
+    report.send(sink);
+}
+
+/// Describes the output path for the start-up messages.
+fn describe_output(output_file: Option<&path::Path>) -> String {
+    output_file.map_or("(in place)".to_string(), |output_file| {
+        output_file.display().to_string()
+    })
+}
+
+/// Transforms `input_file` into `output_file`, or in place without an output file.
+///
+/// With an explicit `output_file` the output is written there by
+/// `start_proccessing_file`. Without one (`--in-place`), the input file is rewritten
+/// within itself and renamed to the input path with the `.ppcb` extension added
+/// (encoding) or removed (decoding); see the `in_place` module.
+///
+/// # Errors
+///
+/// Returns the error of `start_proccessing_file` or `in_place::transform`.
+fn write_output(
+    core: &core_header::CoreH,
+    input_file: path::PathBuf,
+    output_file: Option<path::PathBuf>,
+    transform_type: Transform,
+    parameters: DeltaParameters,
+    range: ByteRange,
+    options: RunOptions,
+) -> Result<(), io::Error> {
+    match output_file {
+        Some(output_file) => start_proccessing_file(
+            core,
+            input_file,
+            output_file,
+            transform_type,
+            parameters,
+            range,
+            options,
+        ),
+        None => in_place::transform(
+            core,
+            &input_file,
+            transform_type,
+            parameters,
+            range,
+            &options,
+        ),
+    }
+}
+
+/// Runs `operation` inside a rayon thread pool with the requested number of worker threads.
+///
+/// Block mode transforms its blocks on the pool the operation runs in, so this controls
+/// the parallelism of `block::transform_blocks`.
+///
+/// # Arguments
+///
+/// * `threads` - The number of worker threads, or 0 for one thread per CPU core.
+/// * `operation` - The work to run inside the pool.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the thread pool cannot be created, or the error of `operation`.
+fn with_thread_pool<F>(threads: usize, operation: F) -> Result<(), io::Error>
+where
+    F: FnOnce() -> Result<(), io::Error> + Send,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| io::Error::other(format!("Failed to create the thread pool: {}", e)))?;
+    pool.install(operation)
 }
 
-/// Initializes the file handles and coordinates the chunk-by-chunk delta transformation.
+/// Initializes the file handles and coordinates the delta transformation.
 ///
-/// This function opens the input and output files, handles the initial "seed" byte,
-/// and then loops, reading the input file in buffered chunks (`fill_buf`) and
-/// passing them to `transform_data_chunk`.
+/// This function opens the input and output files, resolves the range to transform or reads
+/// the header, and hands the streams to `purgepack_delta::compress` or
+/// `purgepack_delta::decompress`, which write the header, the payload and the copied bytes
+/// around it and check the CRC32 of the data.
 ///
 /// # Arguments
 ///
+/// * `core` - The core the statistics are emitted to as a `stats` event.
 /// * `input_file` - The path to the source file.
 /// * `output_file` - The path to the destination file.
 /// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
-/// * `stats` - A boolean flag for statistics calculation.
+/// * `parameters` - The order, stride, word size and residual coding of the delta transform
+///   to apply when encoding. When decoding, the parameters stored in the header are used instead.
+/// * `range` - The byte range of the input to transform when encoding. When decoding,
+///   the range stored in the header is used instead.
+/// * `options` - The statistics settings and the buffer size of the run.
 ///
 /// # Errors
 ///
 /// Returns an `io::Error` if file opening fails, reading/writing fails, or
-/// flushing the buffer fails.
+/// flushing the buffer fails, or an `InvalidData` error if the restored data does not
+/// match the checksum.
 fn start_proccessing_file(
+    core: &core_header::CoreH,
     input_file: path::PathBuf,
     mut output_file: path::PathBuf,
     transform_type: Transform,
-    stats: bool,
+    mut parameters: DeltaParameters,
+    range: ByteRange,
+    options: RunOptions,
 ) -> Result<(), io::Error> {
-    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats);
+    let stats = options.stats;
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
 
     if let Transform::Decode = transform_type {
-        let has_correct_extension = input_file.extension().map_or(false, |ext| {
-            ext.to_string_lossy().eq_ignore_ascii_case(FILE_EXTENSION)
-        });
-
-        if !has_correct_extension {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!(
-                    "Input file must have the '{}' extension for decoding. Found: {}",
-                    FILE_EXTENSION,
-                    input_file.display()
-                ),
-            ));
-        }
+        check_decode_extension(&input_file)?;
     }
 
     if let Transform::Encode = transform_type {
         // If the output path has no extension, append the required .ppcb extension.
         if output_file.extension().is_none() {
             output_file.set_extension(FILE_EXTENSION);
-            println!(
+            log_info!(
                 "Encode: Automatic extension '{}' placed on output file: {}",
                 FILE_EXTENSION,
                 output_file.display()
             );
         }
     }
-    let input = File::open(input_file)?;
+    let input = File::open(&input_file)?;
     let original_len = input.metadata()?.len() as usize;
-    let output = File::create(output_file)?;
-    let mut buff_reader = std::io::BufReader::new(input);
-    let mut buff_writer = std::io::BufWriter::new(output);
-    let mut previous_byte: u8;
+    let output_file = options.overwrite.resolve(&output_file)?;
+    let output = SafeWriter::create(&output_file)?;
+    let progress = match transform_type {
+        Transform::Encode => progress::terminal("Encode", Some(original_len as u64)),
+        Transform::Decode => progress::terminal("Decode", Some(original_len as u64)),
+    };
+    let mut buff_reader = std::io::BufReader::with_capacity(
+        options.chunk_size,
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress,
+        ),
+    );
+    let mut buff_writer = std::io::BufWriter::with_capacity(options.chunk_size, output);
     let t_header = main_timer.start_section("Header Read/Write");
-    match transform_type {
-        Transform::Encode => write_header(&mut buff_writer)?,
+    let (data_len, range_offset, header) = match transform_type {
+        Transform::Encode => {
+            let (offset, data_len) = resolve_range(range, original_len as u64)?;
+            // A cropped output starts directly with the transformed range.
+            let range_offset = if range.crop { 0 } else { offset };
+            if range.crop {
+                buff_reader.seek(SeekFrom::Start(offset))?;
+            }
+            (data_len, range_offset, None)
+        }
         Transform::Decode => {
-            // this variable might be usefull in the future if multiple versions present
-            let _module_id = read_and_validate_header(&mut buff_reader)?;
+            let header = purgepack_delta::read_header(&mut buff_reader)?;
+            parameters = header.parameters;
+            (header.data_len, header.range_offset, Some(header))
         }
-    }
+    };
 
     main_timer.add_section(t_header);
 
-    let t_seed = main_timer.start_section("Seed Byte Read/Write");
-    previous_byte = match set_delta_seed(&mut buff_reader, &mut buff_writer) {
-        Ok(Some(value)) => value,
-        Ok(None) => {
-            buff_writer.flush()?;
-            let (total_duration, sections) = main_timer.end();
-            if stats {
-                let output_len = buff_writer.get_ref().metadata()?.len() as usize;
-                let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
-                    .algorithm_name("First-Order Delta Transform")
-                    .algorithm_id(MODULE_ID)
-                    .version_used(1)
-                    .original_len(original_len)
-                    .processed_len(output_len)
-                    .duration(total_duration)
-                    .is_compression(matches!(transform_type, Transform::Encode))
-                    .sections(sections)
-                    .build()
-                    .unwrap_or_else(|e| panic!("Failed to build stats for empty file: {}", e));
-                println!("{}", calculated_stats);
-            }
-            return Ok(());
-        }
-        Err(e) => return Err(e),
+    let t_process = if parameters.block_size == 0 {
+        main_timer.start_section("Main Chunk Processing")
+    } else {
+        main_timer.start_section("Block Processing")
     };
-    main_timer.add_section(t_seed);
-    let t_process = main_timer.start_section("Main Chunk Processing");
-    loop {
-        let current_chunk = buff_reader.fill_buf()?;
-        let chunk_length = current_chunk.len();
-        if current_chunk.is_empty() {
-            break;
-        }
-        previous_byte = transform_data_chunk(
-            current_chunk,
+    match &header {
+        None => purgepack_delta::compress(
+            &mut buff_reader,
             &mut buff_writer,
-            previous_byte,
-            transform_type,
-        )?;
-        buff_reader.consume(chunk_length);
+            parameters,
+            data_len,
+            range_offset,
+            !range.crop,
+        )?,
+        Some(header) => purgepack_delta::decompress(&mut buff_reader, &mut buff_writer, header)?,
     }
     main_timer.add_section(t_process);
-    buff_writer.flush()?;
-    let (total_duration, sections) = main_timer.end();
-    if stats {
-        let output_len = buff_writer.get_ref().metadata()?.len() as usize;
-        let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
-            .algorithm_name("First-Order Delta Transform")
-            .algorithm_id(MODULE_ID)
-            .version_used(1)
-            .original_len(original_len)
-            .processed_len(output_len)
-            .duration(total_duration)
-            .is_compression(matches!(transform_type, Transform::Encode))
-            .sections(sections)
-            .build()
-            .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
-
-        println!("{}", calculated_stats);
+    let output = buff_writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(
+            core,
+            main_timer,
+            &output,
+            original_len,
+            parameters,
+            transform_type,
+            format,
+        )?;
+        // The entropy report has no machine-readable form, so it only follows the text stats.
+        if format == StatsFormat::Text {
+            entropy::print_entropy(&input_file, &output_file)?;
+        }
+        if let (Transform::Encode, Some(csv_file)) = (transform_type, options.histogram) {
+            // The residuals sit between the header plus the copied prefix and the copied suffix.
+            let payload_start = HEADER_SIZE + range_offset;
+            let suffix_len = if range.crop {
+                0
+            } else {
+                original_len as u64 - range_offset - data_len
+            };
+            let output_len = output.metadata()?.len();
+            let near_zero = histogram::write_residual_histogram(
+                &output_file,
+                payload_start,
+                output_len - payload_start - suffix_len,
+                &csv_file,
+            )?;
+            log_info!(
+                "Residual histogram written to {} ({:.1}% of the residual bytes are between -1 and 1)",
+                csv_file.display(),
+                near_zero * 100.0
+            );
+        }
     }
     Ok(())
 }
-/// Performs the delta encoding or decoding on a single chunk of data.
-///
-/// The transformation is done byte-by-byte, with the result of each step
-/// depending on the calculated value of the previous byte. The operation uses
-/// **wrapping arithmetic** (`wrapping_sub`/`wrapping_add`) to prevent panic on
-/// overflow/underflow. We treat the bytes as cyclic unsigned 8-bit integers (`u8`),
-/// where the valid range is $0$ to $255$. This means we avoid signed values;
-/// for example, a subtraction that results in $-3$ (like $12-15$) automatically wraps to $253$,
-/// and an addition that overflows $255$ automatically wraps back towards $0$.
-///
-/// # Arguments
+
+/// Checks that a file to decode has the `.ppcb` extension.
 ///
-/// * `data` - The slice of bytes to be transformed (either original data or deltas).
-/// * `buff_writer` - The buffered writer to output the results.
-/// * `previous_value` - The preceding value needed for the delta calculation (the seed).
-/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+/// # Errors
 ///
-/// # Returns
+/// Returns an `InvalidInput` error if the extension is missing or different.
+fn check_decode_extension(input_file: &path::Path) -> Result<(), io::Error> {
+    let has_correct_extension = input_file.extension().map_or(false, |ext| {
+        ext.to_string_lossy().eq_ignore_ascii_case(FILE_EXTENSION)
+    });
+
+    if !has_correct_extension {
+        return Err(PurgePackError::InvalidInput(format!(
+            "Input file must have the '{}' extension for decoding. Found: {}",
+            FILE_EXTENSION,
+            input_file.display()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Checks that the input file is not already a delta-encoded PurgePack file.
 ///
-/// The value of the last transformed byte, which serves as the seed for the
-/// subsequent call or data chunk.
+/// Encoding an encoded file again produces a file that decodes once, back to the encoded
+/// file, which is easily mistaken for the original.
 ///
 /// # Errors
 ///
-/// Returns an `io::Error` if writing the transformed data fails.
-/// /// ```rust
-/// use std::io::{self, Cursor, BufWriter, Write};
-///
-/// // Internal types and helper to test the logic without file creation.
-/// #[derive(Debug, Clone, Copy)]
-/// enum Transform { Encode, Decode }
-///
-/// fn transform_chunk_logic<W: Write>(
-///     data: &[u8],
-///     buff_writer: &mut BufWriter<W>,
-///     mut previous_value: u8,
-///     transform_type: Transform,
-/// ) -> io::Result<u8> {
-///     for &current_byte in data.iter() {
-///         let delta_change = match transform_type {
-///             Transform::Encode => current_byte.wrapping_sub(previous_value),
-///             Transform::Decode => current_byte.wrapping_add(previous_value),
-///         };
-///         buff_writer.write_all(&[delta_change])?;
-///
-///         match transform_type {
-///             Transform::Encode => { previous_value = current_byte; }
-///             Transform::Decode => previous_value = delta_change,
-///         }
-///     }
-///     Ok(previous_value)
-/// }
-///
-/// let original_data: Vec<u8> = vec![15, 12, 16];
-/// let initial_seed: u8 = 10;
-///
-/// // 1. Encode: [15, 12, 16] -> [5, 253, 4] (Delta bytes)
-/// let mut encoded_output = Cursor::new(Vec::new());
-/// let mut encoded_writer = BufWriter::new(&mut encoded_output);
-/// let final_seed_encode = transform_chunk_logic(
-///     &original_data,
-///     &mut encoded_writer,
-///     initial_seed,
-///     Transform::Encode,
-/// )?;
-/// encoded_writer.flush()?;
-/// let delta_bytes = encoded_output.into_inner();
-///
-/// assert_eq!(delta_bytes, vec![5, 253, 4]);
-/// assert_eq!(final_seed_encode, 16);
-///
-/// // 2. Decode: [5, 253, 4] -> [15, 12, 16] (Original bytes recovered)
-/// let mut decoded_output = Cursor::new(Vec::new());
-/// let mut decoded_writer = BufWriter::new(&mut decoded_output);
-/// let final_seed_decode = transform_chunk_logic(
-///     &delta_bytes,
-///     &mut decoded_writer,
-///     initial_seed,
-///     Transform::Decode,
-/// )?;
-/// decoded_writer.flush()?;
-/// let decoded_bytes = decoded_output.into_inner();
-///
-/// assert_eq!(decoded_bytes, original_data);
-/// assert_eq!(final_seed_decode, 16);
-/// # Ok::<(), io::Error>(())
-/// ```
-fn transform_data_chunk(
-    data: &[u8],
-    buff_writer: &mut std::io::BufWriter<File>,
-    mut previous_value: u8,
-    transform_type: Transform,
-) -> io::Result<u8> {
-    for &current_byte in data.iter() {
-        let delta_change = match transform_type {
-            Transform::Encode => current_byte.wrapping_sub(previous_value),
-            Transform::Decode => current_byte.wrapping_add(previous_value),
-        };
-        buff_writer.write_all(&[delta_change])?;
-
-        match transform_type {
-            Transform::Encode => {
-                previous_value = current_byte;
-            }
-            Transform::Decode => previous_value = delta_change,
-        }
+/// Returns an `InvalidInput` error if the file starts with the PurgePack magic number and
+/// the delta module ID, or an `io::Error` if the file cannot be read.
+fn check_not_encoded(input_file: &path::Path) -> Result<(), io::Error> {
+    if is_delta_encoded(input_file)? {
+        return Err(PurgePackError::InvalidInput(format!(
+                "The input file is already delta encoded: {}. Use 'inverse' to decode it, or --force to encode it again.",
+                input_file.display()
+            )).into());
     }
-
-    Ok(previous_value)
+    Ok(())
 }
 
-// Reads the first byte from the input stream and writes it directly to the output stream.
+/// Returns `true` if the file starts with the PurgePack magic number and the delta module ID.
 ///
-/// This first byte acts as the delta seed for the rest of the transformation process.
-///
-/// # Arguments
+/// # Errors
 ///
-/// * `buff_reader` - The buffered reader for the input file.
-/// * `buff_writer` - The buffered writer for the output file.
+/// Returns an `io::Error` if the file cannot be read.
+fn is_delta_encoded(input_file: &path::Path) -> Result<bool, io::Error> {
+    let mut prefix = Vec::with_capacity(frame::PREFIX_LEN);
+    File::open(input_file)?
+        .take(frame::PREFIX_LEN as u64)
+        .read_to_end(&mut prefix)?;
+    Ok(FrameHeader::parse(&prefix).is_ok_and(|header| header.module_id == MODULE_ID))
+}
+
+/// Checks a byte range against the length of the input file.
 ///
 /// # Returns
 ///
-/// Returns `Ok(Some(u8))` containing the seed byte, or `Ok(None)` if the input file
-/// was empty.
+/// Returns the offset and the length of the range, with an open-ended range extended to
+/// the end of the file.
 ///
 /// # Errors
 ///
-/// Returns an `io::Error` if reading or writing the seed byte fails, unless the
-/// error is `io::ErrorKind::UnexpectedEof` (which is treated as a successful end of file).
-fn set_delta_seed(
-    buff_reader: &mut std::io::BufReader<File>,
-    buff_writer: &mut std::io::BufWriter<File>,
-) -> Result<Option<u8>, io::Error> {
-    let mut seed = [0u8; 1];
-    match buff_reader.read_exact(&mut seed) {
-        Ok(_) => {
-            buff_writer.write_all(&seed)?;
-            Ok(Some(seed[0]))
-        }
-        Err(e) => {
-            if e.kind() == io::ErrorKind::UnexpectedEof {
-                Ok(None)
-            } else {
-                Err(e)
-            }
-        }
+/// Returns an `InvalidInput` error if the range does not lie within the file.
+fn resolve_range(range: ByteRange, file_len: u64) -> Result<(u64, u64), io::Error> {
+    let out_of_bounds = || {
+        PurgePackError::InvalidInput(format!(
+            "The range (offset {}, length {}) lies outside the input file of {} bytes.",
+            range.offset,
+            range
+                .length
+                .map_or("to end of file".to_string(), |len| len.to_string()),
+            file_len
+        ))
+        .into()
+    };
+    if range.offset > file_len {
+        return Err(out_of_bounds());
+    }
+    let length = range.length.unwrap_or(file_len - range.offset);
+    match range.offset.checked_add(length) {
+        Some(end) if end <= file_len => Ok((range.offset, length)),
+        _ => Err(out_of_bounds()),
     }
 }
-/// Writes the PurgePack header (Magic Number and Module ID) to the output stream.
+
+/// Stops the timer, prints the statistics of a finished Transform or Inverse run and
+/// emits them as a `stats` event.
+///
+/// The report covers the input and output sizes, the total duration and throughput,
+/// and the time spent in each recorded section (header, payload processing).
 ///
 /// # Arguments
 ///
-/// * `buff_writer` - The buffered writer for the output file.
+/// * `core` - The core the `stats` event is emitted to.
+/// * `main_timer` - The timer that recorded the sections of the run. It is consumed.
+/// * `output` - The fully flushed output file, used to determine the processed size.
+/// * `original_len` - The size of the input file in bytes.
+/// * `parameters` - The delta parameters used for the run.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+/// * `format` - The format to print the statistics in.
 ///
-/// # Returns
+/// # Errors
 ///
-/// Returns `Ok(())` if the header is successfully written, or an `io::Error` if
-/// writing the header fails.
-fn write_header(buff_writer: &mut std::io::BufWriter<File>) -> Result<(), io::Error> {
-    let header = PurgePackHeader {
-        application_magic: APPLICATION_MAGIC,
-        module_id: MODULE_ID,
-    };
-    buff_writer.write_all(&header.application_magic)?;
-    buff_writer.write_all(&[header.module_id])?;
+/// Returns an `io::Error` if the metadata of the output file cannot be read.
+fn print_statistics(
+    core: &core_header::CoreH,
+    main_timer: shared_files::stats::OptinalStatsTimer,
+    output: &File,
+    original_len: usize,
+    parameters: DeltaParameters,
+    transform_type: Transform,
+    format: StatsFormat,
+) -> Result<(), io::Error> {
+    let peak_memory = main_timer.peak_memory();
+    let throughput = main_timer.throughput();
+    let (total_duration, sections) = main_timer.end();
+    let output_len = output.metadata()?.len() as usize;
+    let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
+        .algorithm_name(algorithm_name(parameters))
+        .algorithm_id(MODULE_ID)
+        .version_used(1)
+        .original_len(original_len)
+        .processed_len(output_len)
+        .duration(total_duration)
+        .is_compression(matches!(transform_type, Transform::Encode))
+        .sections(sections)
+        .peak_memory(peak_memory)
+        .throughput(throughput)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
+
+    println!("{}", calculated_stats.render(format));
+    events::emit(
+        core,
+        &Event::Stats {
+            stats: &calculated_stats,
+        },
+    );
     Ok(())
 }
 
-/// Reads and validates the PurgePack header from the input stream.
-/// Also determines the correct module ID to use for decoding.
-///
-/// # Arguments
-///
-/// * `buff_reader` - The buffered reader for the input file.
-///
-/// # Returns
-///
-/// Returns `Ok(u8)` containing the module ID, or an `io::Error` if reading or validating the header fails.
-fn read_and_validate_header(buff_reader: &mut std::io::BufReader<File>) -> Result<u8, io::Error> {
-    let mut header_bytes = [0u8; HEADER_SIZE as usize];
-    buff_reader.read_exact(&mut header_bytes).map_err(|e| {
-        io::Error::new(
-            e.kind(),
-            "Failed to read PurgePack header. File may be too short or corrupted.",
-        )
-    })?;
-    let magic_number = [
-        header_bytes[0],
-        header_bytes[1],
-        header_bytes[2],
-        header_bytes[3],
-    ];
-    let module_id = header_bytes[4];
-    if magic_number != APPLICATION_MAGIC {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Invalid PurgePack magic number. This may not be a valid PurgePack Compressed Binary (PPCB) file.",
-        ));
+/// Returns the human-readable algorithm name reported in the statistics for the given parameters.
+fn algorithm_name(parameters: DeltaParameters) -> &'static str {
+    match parameters.algorithm {
+        Algorithm::RowFilter => return "Image Row Filter Transform (PNG-Style Predictors)",
+        Algorithm::Xor => return "XOR Delta Transform",
+        Algorithm::Timestamp => return "Timestamp Delta-of-Delta Transform (Varint Residuals)",
+        Algorithm::Delta => {}
     }
-
-    if module_id != MODULE_ID {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "Unsupported module ID: 0x{:02X}. Only 0x{:02X} (Delta V1) is supported.",
-                module_id, MODULE_ID
-            ),
-        ));
+    if parameters.varint {
+        return "Delta Transform (Zigzag Varint Residuals)";
+    }
+    match parameters.order {
+        1 => "First-Order Delta Transform",
+        2 => "Second-Order Delta Transform (Linear Prediction)",
+        _ => "Higher-Order Delta Transform",
     }
-
-    Ok(module_id)
 }
@@ -0,0 +1,118 @@
+//! Quick statistics over a sample of the input.
+//!
+//! Large files are not read completely: the statistics are computed over `sample_size`
+//! bytes taken from `WINDOWS` evenly spaced windows (the start, the middle and the end),
+//! so a header or a trailer alone does not decide the classification.
+
+use shared_files::entropy::entropy;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// The number of windows a large file is sampled from.
+const WINDOWS: u64 = 3;
+/// The strides tried for the delta statistics: single bytes, 16-bit samples, RGB pixels,
+/// 32-bit and 64-bit values.
+const STRIDES: [usize; 5] = [1, 2, 3, 4, 8];
+
+/// The statistics of a sample.
+#[derive(Debug)]
+pub(crate) struct SampleStats {
+    /// The number of bytes sampled.
+    pub(crate) len: u64,
+    /// The order-0 entropy of the bytes in bits per byte.
+    pub(crate) entropy: f64,
+    /// The share (0.0 to 1.0) of bytes that belong to printable text: printable ASCII,
+    /// whitespace or valid multi-byte UTF-8 characters.
+    pub(crate) text_share: f64,
+    /// Whether the text holds characters outside ASCII.
+    pub(crate) non_ascii: bool,
+    /// The share (0.0 to 1.0) of zero bytes.
+    pub(crate) zero_share: f64,
+    /// The share (0.0 to 1.0) of bytes equal to the byte before them.
+    pub(crate) run_share: f64,
+    /// The stride whose byte differences have the lowest entropy.
+    pub(crate) best_stride: usize,
+    /// The order-0 entropy of the byte differences at `best_stride`, in bits per byte.
+    pub(crate) delta_entropy: f64,
+}
+
+/// Reads the sample of `path`: the whole file if it is at most `sample_size` bytes long,
+/// and `WINDOWS` windows adding up to `sample_size` bytes otherwise.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if opening or reading the file fails.
+pub(crate) fn read_sample(path: &Path, sample_size: u64) -> io::Result<Vec<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    if file_len <= sample_size {
+        let mut whole = Vec::with_capacity(file_len as usize);
+        file.read_to_end(&mut whole)?;
+        return Ok(vec![whole]);
+    }
+    let window_len = sample_size / WINDOWS;
+    let last_start = file_len - window_len;
+    (0..WINDOWS)
+        .map(|window| {
+            file.seek(SeekFrom::Start(last_start * window / (WINDOWS - 1)))?;
+            let mut bytes = Vec::with_capacity(window_len as usize);
+            (&mut file).take(window_len).read_to_end(&mut bytes)?;
+            Ok(bytes)
+        })
+        .collect()
+}
+
+/// Computes the statistics of the sample `windows`.
+pub(crate) fn analyze(windows: &[Vec<u8>]) -> SampleStats {
+    let mut counts = [0u64; 256];
+    let mut delta_counts = [[0u64; 256]; STRIDES.len()];
+    let (mut text, mut runs) = (0u64, 0u64);
+    let mut non_ascii = false;
+    for window in windows {
+        for &byte in window {
+            counts[byte as usize] += 1;
+        }
+        runs += window.windows(2).filter(|pair| pair[0] == pair[1]).count() as u64;
+        for (stride_counts, &stride) in delta_counts.iter_mut().zip(&STRIDES) {
+            for (&current, &previous) in window.iter().skip(stride).zip(window) {
+                stride_counts[current.wrapping_sub(previous) as usize] += 1;
+            }
+        }
+        for chunk in window.utf8_chunks() {
+            for character in chunk.valid().chars() {
+                if !character.is_control() || matches!(character, '\t' | '\n' | '\r' | '\x0C') {
+                    text += character.len_utf8() as u64;
+                    non_ascii |= !character.is_ascii();
+                }
+            }
+        }
+    }
+
+    let len: u64 = counts.iter().sum();
+    let share = |count: u64| {
+        if len == 0 {
+            0.0
+        } else {
+            count as f64 / len as f64
+        }
+    };
+    let (best_stride, delta_entropy) = STRIDES
+        .iter()
+        .zip(&delta_counts)
+        .map(|(&stride, stride_counts)| (stride, entropy(stride_counts)))
+        .min_by(|(_, first), (_, second)| first.total_cmp(second))
+        .unwrap();
+    SampleStats {
+        len,
+        entropy: entropy(&counts),
+        text_share: share(text),
+        non_ascii,
+        zero_share: share(counts[0]),
+        run_share: share(runs),
+        best_stride,
+        delta_entropy,
+    }
+}
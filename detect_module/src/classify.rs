@@ -0,0 +1,219 @@
+//! Classification of a file and the PurgePack pipeline recommended for it.
+//!
+//! A recognized magic number decides the class, except for the two-byte signatures of BMP
+//! and Windows executables, which text files also start with often enough that the
+//! statistics win for a sample that reads as text. Without a signature the statistics
+//! are checked in order: text, numeric data that a delta transform helps, high entropy,
+//! sparse data, and generic binary data. Numeric data comes before high entropy because
+//! samples such as a ramp or an image use all byte values evenly, yet their differences
+//! are small.
+
+use crate::analysis::SampleStats;
+use shared_files::signature::{self, SignatureKind};
+
+/// The share of text bytes above which a sample is text.
+const TEXT_SHARE: f64 = 0.95;
+/// The entropy in bits per byte above which a sample is treated as incompressible.
+const HIGH_ENTROPY: f64 = 7.9;
+/// The number of bits per byte a delta transform must save for data to count as numeric.
+const DELTA_GAIN: f64 = 1.0;
+/// The share of zero bytes or repeated bytes above which a sample is sparse.
+const SPARSE_SHARE: f64 = 0.3;
+/// The length of the shortest magic number that decides the class even for text.
+const STRONG_MAGIC_LEN: usize = 3;
+
+/// The class of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileClass {
+    /// The file holds no data.
+    Empty,
+    /// The file was written by a PurgePack module.
+    PurgePack,
+    /// A general-purpose compressed file or archive.
+    Compressed,
+    /// An image, audio, video or document format that already compresses its content.
+    CompressedMedia,
+    /// An uncompressed image.
+    RawImage,
+    /// An executable or object file.
+    Executable,
+    /// Text.
+    Text,
+    /// Binary data with a high entropy, such as compressed or encrypted data.
+    HighEntropy,
+    /// Fixed-width numeric samples, such as audio, sensor readings or tables of integers.
+    Numeric,
+    /// Binary data dominated by zeros or runs of the same byte.
+    Sparse,
+    /// Binary data without any of the traits above.
+    Binary,
+}
+
+impl FileClass {
+    /// Returns the name of the class in the machine-readable output.
+    pub(crate) fn id(self) -> &'static str {
+        match self {
+            FileClass::Empty => "empty",
+            FileClass::PurgePack => "purgepack",
+            FileClass::Compressed => "compressed",
+            FileClass::CompressedMedia => "compressed-media",
+            FileClass::RawImage => "raw-image",
+            FileClass::Executable => "executable",
+            FileClass::Text => "text",
+            FileClass::HighEntropy => "high-entropy",
+            FileClass::Numeric => "numeric",
+            FileClass::Sparse => "sparse",
+            FileClass::Binary => "binary",
+        }
+    }
+}
+
+/// The classification of a file and the recommended pipeline.
+#[derive(Debug)]
+pub(crate) struct Classification {
+    /// The class of the file.
+    pub(crate) class: FileClass,
+    /// A description of the content, such as the name of the detected format.
+    pub(crate) detail: String,
+    /// Whether the class was decided by a magic number rather than by the statistics.
+    pub(crate) from_signature: bool,
+    /// The PurgePack module invocations to run in order, each followed by the input and
+    /// output paths. Empty if compressing the file is not recommended.
+    pub(crate) pipeline: Vec<String>,
+    /// A short explanation of the recommendation.
+    pub(crate) note: &'static str,
+}
+
+/// Classifies a file from the first bytes of the file `prefix` and the statistics of its
+/// sample.
+pub(crate) fn classify(prefix: &[u8], stats: &SampleStats) -> Classification {
+    let is_text = stats.text_share >= TEXT_SHARE;
+    if stats.len == 0 {
+        return Classification {
+            class: FileClass::Empty,
+            detail: "empty file".to_string(),
+            from_signature: false,
+            pipeline: Vec::new(),
+            note: "There is nothing to compress.",
+        };
+    }
+    if let Some(signature) = signature::identify(prefix)
+        && (signature.magic.len() >= STRONG_MAGIC_LEN || !is_text)
+    {
+        return classify_signature(prefix, signature.name, signature.kind);
+    }
+
+    let (class, detail, pipeline, note) = if is_text {
+        let encoding = if stats.non_ascii { "UTF-8" } else { "ASCII" };
+        (
+            FileClass::Text,
+            format!("{} text", encoding),
+            vec!["+bwt_module compress --level 9".to_string()],
+            "Block sorting suits text well. For the best ratio at about 1 MiB/s, use +range_coder_module compress --level 4 instead.",
+        )
+    } else if stats.entropy - stats.delta_entropy >= DELTA_GAIN {
+        let delta = if stats.best_stride == 1 {
+            "+delta_module transform".to_string()
+        } else {
+            format!("+delta_module transform --stride {}", stats.best_stride)
+        };
+        (
+            FileClass::Numeric,
+            format!("{}-byte samples", stats.best_stride),
+            vec![delta, "+range_coder_module compress --level 1".to_string()],
+            "Neighbouring values are close, so the delta transform turns them into small residuals for the entropy coder.",
+        )
+    } else if stats.entropy >= HIGH_ENTROPY {
+        (
+            FileClass::HighEntropy,
+            format!("{:.2} bits/byte", stats.entropy),
+            Vec::new(),
+            "The data looks compressed or encrypted already. Store it as it is.",
+        )
+    } else if stats.zero_share >= SPARSE_SHARE || stats.run_share >= SPARSE_SHARE {
+        (
+            FileClass::Sparse,
+            format!(
+                "{:.1}% zeros, {:.1}% repeated bytes",
+                stats.zero_share * 100.0,
+                stats.run_share * 100.0
+            ),
+            vec!["+fast_lz_module compress".to_string()],
+            "Runs and repeated blocks compress well with a fast LZ coder.",
+        )
+    } else {
+        (
+            FileClass::Binary,
+            format!("{:.2} bits/byte", stats.entropy),
+            vec!["+range_coder_module compress --level 2".to_string()],
+            "The context-mixing coder adapts to binary structure without a format-specific transform.",
+        )
+    };
+    Classification {
+        class,
+        detail,
+        from_signature: false,
+        pipeline,
+        note,
+    }
+}
+
+/// Classifies a file by the format its magic number identifies.
+fn classify_signature(prefix: &[u8], name: &'static str, kind: SignatureKind) -> Classification {
+    let (class, detail, pipeline, note) = match kind {
+        SignatureKind::PurgePack => {
+            let (module, restore) = match (name, prefix.get(4)) {
+                ("PurgePack Delta Patch", _) => ("Delta", None),
+                ("PurgePack Skippable Frame", _) => ("purgepack compress", None),
+                ("PurgePack Crypt Envelope", _) => ("Crypt", None),
+                (_, Some(0x01)) => ("Delta", Some("+delta_module inverse")),
+                (_, Some(0x02)) => ("Range Coder", Some("+range_coder_module decompress")),
+                (_, Some(0x03)) => ("BWT", Some("+bwt_module decompress")),
+                (_, Some(0x04)) => ("Fast LZ", Some("+fast_lz_module decompress")),
+                (_, Some(0x07)) => ("Bit Packing", Some("+bitpack_module decompress")),
+                (_, Some(0x08)) => ("Transpose", Some("+transpose_module inverse")),
+                _ => ("an unknown module", None),
+            };
+            (
+                FileClass::PurgePack,
+                format!("{} written by {}", name, module),
+                restore.map(str::to_string).into_iter().collect(),
+                "The file is PurgePack output already. The pipeline restores the original data.",
+            )
+        }
+        SignatureKind::Compressed => (
+            FileClass::Compressed,
+            name.to_string(),
+            Vec::new(),
+            "The file is compressed already. Store it as it is.",
+        ),
+        SignatureKind::CompressedMedia => (
+            FileClass::CompressedMedia,
+            name.to_string(),
+            Vec::new(),
+            "The format compresses its content already. Store it as it is.",
+        ),
+        SignatureKind::RawImage => (
+            FileClass::RawImage,
+            name.to_string(),
+            vec![
+                "+delta_module transform --image".to_string(),
+                "+range_coder_module compress --level 2".to_string(),
+            ],
+            "Predicting every pixel from its neighbours leaves small residuals for the entropy coder.",
+        ),
+        SignatureKind::Executable => (
+            FileClass::Executable,
+            name.to_string(),
+            vec!["+range_coder_module compress --level 2".to_string()],
+            "The context-mixing coder adapts to the mix of code and data in executables.",
+        ),
+    };
+    Classification {
+        class,
+        detail,
+        from_signature: true,
+        pipeline,
+        note,
+    }
+}
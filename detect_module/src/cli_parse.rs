@@ -0,0 +1,58 @@
+use clap::Parser;
+use shared_files::cli::{self, CliError, Validate};
+use std::path::PathBuf;
+
+/// The sample size used when `--sample-size` is not given.
+pub const DEFAULT_SAMPLE_SIZE: u64 = 1024 * 1024;
+
+/// The smallest sample size accepted by `--sample-size`.
+pub const MIN_SAMPLE_SIZE: u64 = 4096;
+
+/// The main command line argument structure for the Detect Utility.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "File Type Detection Utility.",
+    long_about = "Classifies files by their magic numbers and by quick statistics over a sample of their bytes (entropy, text share, runs and delta gain), and prints the PurgePack pipeline recommended for each.",
+    after_help = "
+    EXAMPLES:
+    # 1. Classify a file and print the recommended pipeline
+    detect_tool.exe notes.txt
+
+    # 2. Classify several files, sampling 4 MiB of each
+    detect_tool.exe photo.bmp samples.raw backup.zip --sample-size 4194304
+
+    # 3. Print key=value lines for scripts and other modules
+    detect_tool.exe samples.raw --porcelain
+"
+)]
+pub struct CliArgs {
+    /// The paths of the files to classify.
+    #[arg(required = true)]
+    pub input_files: Vec<PathBuf>,
+    /// The number of bytes the statistics are computed over. Larger files are sampled at
+    /// the start, the middle and the end.
+    #[arg(long, default_value_t = DEFAULT_SAMPLE_SIZE, value_parser = clap::value_parser!(u64).range(MIN_SAMPLE_SIZE..))]
+    pub sample_size: u64,
+    /// Prints the result as `key=value` lines, one block per file separated by an empty
+    /// line, with one `step=` line per module of the recommended pipeline.
+    #[arg(long)]
+    pub porcelain: bool,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring that every
+    /// input file exists and is a file.
+    fn validate(&self) -> Result<(), CliError> {
+        for input_file in &self.input_files {
+            cli::check_input(input_file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
@@ -0,0 +1,209 @@
+//! File-type detection and pipeline recommendation.
+//!
+//! Every input file is classified from its magic number (see the `signature` module) and
+//! from quick statistics over a sample of its bytes (see the `analysis` module), and the
+//! PurgePack pipeline recommended for its class is printed (see the `classify` module).
+//! The module only reads its inputs.
+//!
+//! With `--porcelain` the result is printed as `key=value` lines, so scripts and an
+//! automatic mode of the core can run the recommended steps:
+//!
+//! ```text
+//! file=samples.raw
+//! class=numeric
+//! detail=2-byte samples
+//! source=statistics
+//! entropy=7.412
+//! ...
+//! step=+delta_module transform --stride 2
+//! step=+range_coder_module compress --level 1
+//! ```
+//!
+//! The same report, without the `file` and `size` lines, is offered to the core and the
+//! other modules as the [`DETECT_SERVICE`] for data they stream in.
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+mod analysis;
+mod classify;
+mod cli_parse;
+use analysis::SampleStats;
+use classify::Classification;
+use shared_files::abi::{self, RawReader, RawWriter, ServiceResult};
+use shared_files::cli::{self, CliError};
+use shared_files::codec::CodecStats;
+use shared_files::core_header::{self, DETECT_SERVICE};
+use shared_files::{log_debug, log_error};
+use std::ffi::c_char;
+
+/// The number of leading bytes checked for magic numbers.
+const PREFIX_LEN: usize = 16;
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line and classifies every input file.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("detect", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => {
+            for (index, input_file) in args.input_files.iter().enumerate() {
+                if args.porcelain && index > 0 {
+                    println!();
+                }
+                if let Err(e) = detect_file(input_file, args.sample_size, args.porcelain) {
+                    log_error!("Detect: {}: {}", input_file.display(), e);
+                    core_header::report_error(core, &e);
+                }
+            }
+        }
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// Registers the classification as a service for the core and the other modules.
+#[unsafe(no_mangle)]
+extern "C" fn module_register(core: &core_header::CoreH) {
+    core_header::register_service(core, DETECT_SERVICE, detect_service);
+}
+
+/// Classifies the first [`DEFAULT_SAMPLE_SIZE`](cli_parse::DEFAULT_SAMPLE_SIZE) bytes of
+/// `input` and writes the report of `--porcelain` without the `file` and `size` lines.
+extern "C" fn detect_service(
+    input: &mut RawReader,
+    output: &mut RawWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    abi::serve(input, output, result, |reader, writer| {
+        let mut sample = Vec::new();
+        reader
+            .take(cli_parse::DEFAULT_SAMPLE_SIZE)
+            .read_to_end(&mut sample)?;
+        let input_len = sample.len() as u64;
+        let (stats, classification) = classify_sample(&[sample]);
+        let mut report = Vec::new();
+        write_report(&mut report, &stats, &classification)?;
+        writer.write_all(&report)?;
+        Ok(CodecStats {
+            input_len,
+            output_len: report.len() as u64,
+        })
+    })
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("detect", core.log_level);
+    log_debug!("Detect module shutting down.");
+}
+
+/// Classifies `input_file` and prints the result.
+///
+/// # Arguments
+///
+/// * `input_file` - The path to the file to classify.
+/// * `sample_size` - The number of bytes the statistics are computed over.
+/// * `porcelain` - Whether to print `key=value` lines instead of the readable report.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading the file fails.
+fn detect_file(input_file: &Path, sample_size: u64, porcelain: bool) -> io::Result<()> {
+    let file_len = input_file.metadata()?.len();
+    let windows = analysis::read_sample(input_file, sample_size)?;
+    let (stats, classification) = classify_sample(&windows);
+
+    if porcelain {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "file={}", input_file.display())?;
+        writeln!(stdout, "size={}", file_len)?;
+        return write_report(&mut stdout, &stats, &classification);
+    }
+
+    println!(
+        "Detect: File: {} ({} bytes, {} sampled)",
+        input_file.display(),
+        file_len,
+        stats.len
+    );
+    println!(
+        "Detect: Type: {} ({}), detected by {}",
+        classification.class.id(),
+        classification.detail,
+        source(&classification)
+    );
+    println!(
+        "Detect: Entropy: {:.2} bits/byte, Text: {:.1}%, Zeros: {:.1}%, Runs: {:.1}%, Best Delta: stride {} at {:.2} bits/byte",
+        stats.entropy,
+        stats.text_share * 100.0,
+        stats.zero_share * 100.0,
+        stats.run_share * 100.0,
+        stats.best_stride,
+        stats.delta_entropy
+    );
+    if classification.pipeline.is_empty() {
+        println!("Detect: Pipeline: none");
+    } else {
+        println!("Detect: Pipeline: {}", classification.pipeline.join(" -> "));
+    }
+    println!("Detect: Note: {}", classification.note);
+    Ok(())
+}
+
+/// Computes the statistics of the sample `windows` and classifies it.
+fn classify_sample(windows: &[Vec<u8>]) -> (SampleStats, Classification) {
+    let stats = analysis::analyze(windows);
+    let prefix = &windows[0][..windows[0].len().min(PREFIX_LEN)];
+    let classification = classify::classify(prefix, &stats);
+    (stats, classification)
+}
+
+/// Returns what decided the class, as printed in the report.
+fn source(classification: &Classification) -> &'static str {
+    if classification.from_signature {
+        "signature"
+    } else {
+        "statistics"
+    }
+}
+
+/// Writes the `key=value` lines of `--porcelain` after the `file` and `size` lines.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if writing fails.
+fn write_report(
+    writer: &mut dyn Write,
+    stats: &SampleStats,
+    classification: &Classification,
+) -> io::Result<()> {
+    writeln!(writer, "class={}", classification.class.id())?;
+    writeln!(writer, "detail={}", classification.detail)?;
+    writeln!(writer, "source={}", source(classification))?;
+    writeln!(writer, "sampled={}", stats.len)?;
+    writeln!(writer, "entropy={:.3}", stats.entropy)?;
+    writeln!(writer, "text_share={:.3}", stats.text_share)?;
+    writeln!(writer, "zero_share={:.3}", stats.zero_share)?;
+    writeln!(writer, "run_share={:.3}", stats.run_share)?;
+    writeln!(writer, "delta_stride={}", stats.best_stride)?;
+    writeln!(writer, "delta_entropy={:.3}", stats.delta_entropy)?;
+    writeln!(writer, "note={}", classification.note)?;
+    for step in &classification.pipeline {
+        writeln!(writer, "step={}", step)?;
+    }
+    Ok(())
+}
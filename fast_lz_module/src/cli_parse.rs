@@ -0,0 +1,82 @@
+use clap::{Args, Parser, Subcommand};
+use shared_files::cli::CommonArgs;
+use shared_files::cli::{self, CliError, Validate};
+
+/// Arguments that only apply to compression.
+///
+/// Decompression reads these settings back from the file header.
+#[derive(Debug, Clone, Args)]
+pub struct CompressArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The initial step between match probes (1 = probe every position). Higher values
+    /// skip more of the input, trading ratio for speed.
+    #[arg(short, long, default_value_t = DEFAULT_ACCELERATION, value_parser = clap::value_parser!(u32).range(1..=MAX_ACCELERATION as i64))]
+    pub acceleration: u32,
+}
+
+/// The acceleration used when `--acceleration` is not given.
+pub const DEFAULT_ACCELERATION: u32 = 1;
+
+/// The highest acceleration accepted by `--acceleration`.
+pub const MAX_ACCELERATION: u32 = 64;
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Compresses a file with the fast LZ77 coder.
+    #[clap(alias = "c")]
+    Compress(CompressArgs),
+    /// Restores a file written by `compress`.
+    #[clap(alias = "d")]
+    Decompress(CommonArgs),
+}
+
+/// The main command line argument structure for the Fast LZ Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Fast LZ Compressor Utility.",
+    long_about = "A speed-focused LZ77 compressor in the style of LZ4: a greedy match finder with a single hash probe per position, and byte-aligned sequences of literals and matches that decode with plain memory copies.",
+    after_help = "
+    EXAMPLES:
+    # 1. Compress
+    fast_lz_tool.exe compress server.log server.ppcb
+
+    # 2. Compress faster at a lower ratio and show statistics
+    fast_lz_tool.exe c server.log server.ppcb --acceleration 8 -s
+
+    # 3. Decompress
+    fast_lz_tool.exe d server.ppcb restored.log
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file exists and is a file.
+    /// 2. The parent directory for the output file exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        let common = match &self.command {
+            Commands::Compress(args) => &args.common,
+            Commands::Decompress(args) => args,
+        };
+
+        // --- Input File Validation ---
+        cli::check_input(&common.input_file)?;
+
+        // --- Output Directory Validation ---
+        cli::check_output(&common.output_file)
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
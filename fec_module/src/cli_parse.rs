@@ -0,0 +1,132 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::shard::MAX_SHARDS;
+use shared_files::cli::{self, CliError, OverwriteArgs, StatsArgs, Validate};
+
+/// Arguments of the `encode` command.
+#[derive(Debug, Clone, Args)]
+pub struct EncodeArgs {
+    /// The path to the file to protect.
+    pub input_file: PathBuf,
+    /// The directory the shard files are written to. Defaults to the directory of the
+    /// input file.
+    pub output_dir: Option<PathBuf>,
+    /// The number of data shards the file is spread over.
+    #[arg(short, long, default_value_t = DEFAULT_DATA_SHARDS, value_parser = clap::value_parser!(u8).range(1..))]
+    pub data_shards: u8,
+    /// The number of parity shards. Any shards up to this number can be lost or damaged
+    /// and the file can still be restored. The data grows by parity / data.
+    #[arg(short, long, default_value_t = DEFAULT_PARITY_SHARDS, value_parser = clap::value_parser!(u8).range(1..))]
+    pub parity_shards: u8,
+    /// The number of bytes per block. Damage is detected and repaired per block, so
+    /// smaller blocks lose less to scattered damage but add a 4-byte CRC32 more often.
+    #[arg(long, default_value_t = DEFAULT_BLOCK_SIZE, value_parser = clap::value_parser!(u32).range(MIN_BLOCK_SIZE as i64..=MAX_BLOCK_SIZE as i64))]
+    pub block_size: u32,
+    #[command(flatten)]
+    pub stats: StatsArgs,
+}
+
+/// Arguments of the `decode` command.
+#[derive(Debug, Clone, Args)]
+pub struct DecodeArgs {
+    /// The shard files that are left, in any order. Shards that are missing or belong to
+    /// another set are skipped.
+    #[arg(required = true)]
+    pub shard_files: Vec<PathBuf>,
+    /// The path where the restored file will be written.
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub stats: StatsArgs,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+}
+
+/// The number of data shards used when `--data-shards` is not given.
+pub const DEFAULT_DATA_SHARDS: u8 = 10;
+
+/// The number of parity shards used when `--parity-shards` is not given.
+pub const DEFAULT_PARITY_SHARDS: u8 = 4;
+
+/// The block size used when `--block-size` is not given (64 KiB).
+pub const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
+
+/// The smallest block size accepted by `--block-size`.
+pub const MIN_BLOCK_SIZE: u32 = 512;
+
+/// The largest block size accepted by `--block-size` (16 MiB).
+pub const MAX_BLOCK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Spreads a file over data shards and adds Reed-Solomon parity shards.
+    #[clap(alias = "e")]
+    Encode(EncodeArgs),
+    /// Restores the file from the shards that are left, repairing missing and damaged ones.
+    #[clap(alias = "d")]
+    Decode(DecodeArgs),
+}
+
+/// The main command line argument structure for the FEC Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Forward Error Correction Utility.",
+    long_about = "Wraps a file in Reed-Solomon coded shards, so it can be restored even when some shard files are lost or damaged.",
+    after_help = "
+    EXAMPLES:
+    # 1. Protect a compressed file with 10 data and 4 parity shards
+    fec_tool.exe encode backup.ppcb shards/
+
+    # 2. Survive the loss of any 2 of 6 shards
+    fec_tool.exe e backup.ppcb shards/ --data-shards 4 --parity-shards 2
+
+    # 3. Restore from the shards that are left
+    fec_tool.exe decode shards/backup.ppcb.*.ppfs -o backup.ppcb
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file exists and is a file.
+    /// 2. The output directory, or the parent directory of the output file, exists and is
+    ///    a directory.
+    /// 3. The set has at most `MAX_SHARDS` shards.
+    ///
+    /// Shard files are not checked, since missing shards are what decoding repairs.
+    fn validate(&self) -> Result<(), CliError> {
+        match &self.command {
+            Commands::Encode(args) => {
+                cli::check_input(&args.input_file)?;
+                if let Some(output_dir) = &args.output_dir {
+                    cli::check_dir(output_dir)?;
+                }
+                let total = args.data_shards as usize + args.parity_shards as usize;
+                if total > MAX_SHARDS {
+                    return Err(CliError::Invalid(format!(
+                        "A set can have at most {} shards, but {} were requested.",
+                        MAX_SHARDS, total
+                    )));
+                }
+            }
+            Commands::Decode(args) => {
+                cli::check_output(&args.output_file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
@@ -0,0 +1,627 @@
+//! Forward error correction with Reed-Solomon coded shards.
+//!
+//! `encode` spreads a file over `--data-shards` shard files and adds `--parity-shards`
+//! shard files of Reed-Solomon parity over GF(2^8). The file can be restored from any
+//! `data` of the shards, so up to `parity` shards may be lost.
+//!
+//! The data is coded in stripes: every stripe holds one block of `--block-size` bytes of
+//! every shard, and every block is followed by its CRC32. A damaged block is detected by
+//! its CRC32 and treated like a missing one, so `decode` repairs damage scattered over
+//! all the shards as long as no stripe has more than `parity` bad blocks. Every shard
+//! file starts with a header describing the set (see the `shard` module), so the shards
+//! can be passed to `decode` in any order and under any names.
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+mod cli_parse;
+mod shard;
+use reed_solomon_erasure::{ReedSolomon, galois_8};
+use shard::{BLOCK_CRC_LEN, FORMAT_VERSION, MODULE_ID, SET_ID_LEN, SHARD_HEADER_SIZE, ShardHeader};
+use shared_files::abi;
+use shared_files::checksum::{Crc32, Hasher};
+use shared_files::cli::{self, CliError};
+use shared_files::core_header::{self};
+use shared_files::events::{self, Event};
+use shared_files::output::SafeWriter;
+use shared_files::progress::{self, ProgressReader};
+use shared_files::stats::{DEFAULT_SAMPLE_INTERVAL, StatsFormat};
+use shared_files::{log_debug, log_error, log_info, log_warn};
+use std::ffi::c_char;
+
+/// The file extension for PurgePack FEC Shard (PPFS) files.
+const SHARD_EXTENSION: &str = "ppfs";
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("fec", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Encode(args) => {
+                log_info!(
+                    "Encode: Input: {}, Data shards: {}, Parity shards: {}",
+                    args.input_file.display(),
+                    args.data_shards,
+                    args.parity_shards
+                );
+                match encode_file(core, &args) {
+                    Ok(()) => log_info!("Encode: Success"),
+                    Err(e) => {
+                        log_error!("Encode: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Decode(args) => {
+                log_info!(
+                    "Decode: Shards: {}, Output: {}",
+                    args.shard_files.len(),
+                    args.output_file.display()
+                );
+                match decode_file(core, &args) {
+                    Ok(()) => log_info!("Decode: Success"),
+                    Err(e) => {
+                        log_error!("Decode: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("fec", core.log_level);
+    log_debug!("FEC module shutting down.");
+}
+
+/// Creates the Reed-Solomon codec for the set described by `header`.
+fn codec(header: &ShardHeader) -> io::Result<ReedSolomon<galois_8::Field>> {
+    ReedSolomon::new(header.data_shards as usize, header.parity_shards as usize)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))
+}
+
+/// Fills `buffer` from `reader` and returns the number of bytes read, which is only
+/// smaller than the buffer at the end of the stream.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Encodes the input file of `args` into a set of shard files.
+///
+/// The headers are written with a zero checksum first. Once all the data has been read,
+/// the CRC32 of the original data is filled in. The shards are written through
+/// [`SafeWriter`]s, so they only appear under their names once they are complete.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or if the input file changes
+/// length while it is encoded.
+fn encode_file(core: &core_header::CoreH, args: &cli_parse::EncodeArgs) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(args.stats.enabled());
+    let input = File::open(&args.input_file)?;
+    let original_len = input.metadata()?.len();
+    let mut reader = BufReader::new(ProgressReader::new(
+        main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+        progress::terminal("Encode", Some(original_len)),
+    ));
+    let file_name = args
+        .input_file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output_dir = match &args.output_dir {
+        Some(output_dir) => output_dir.as_path(),
+        None => args.input_file.parent().unwrap_or(Path::new("")),
+    };
+
+    let mut set_id = [0u8; SET_ID_LEN];
+    getrandom::fill(&mut set_id).map_err(|e| io::Error::other(e.to_string()))?;
+    let mut header = ShardHeader {
+        data_shards: args.data_shards,
+        parity_shards: args.parity_shards,
+        index: 0,
+        block_size: args.block_size,
+        original_len,
+        checksum: 0,
+        set_id,
+    };
+    let codec = codec(&header)?;
+
+    let mut shard_paths = Vec::with_capacity(header.total_shards());
+    let mut writers = Vec::with_capacity(header.total_shards());
+    for index in 0..header.total_shards() {
+        let path = output_dir.join(format!("{}.{:03}.{}", file_name, index, SHARD_EXTENSION));
+        let mut writer = BufWriter::new(SafeWriter::create(&path)?);
+        header.index = index as u8;
+        writer.write_all(&header.to_bytes())?;
+        shard_paths.push(path);
+        writers.push(writer);
+    }
+
+    let t_coding = main_timer.start_section("Reed-Solomon Coding");
+    let mut hasher = Crc32::new();
+    let mut stripe = vec![vec![0u8; header.block_size as usize]; header.total_shards()];
+    let mut read_len = 0u64;
+    for _ in 0..header.stripes() {
+        for block in &mut stripe[..header.data_shards as usize] {
+            let filled = read_full(&mut reader, block)?;
+            block[filled..].fill(0);
+            hasher.update(&block[..filled]);
+            read_len += filled as u64;
+        }
+        codec
+            .encode(&mut stripe)
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        for (writer, block) in writers.iter_mut().zip(&stripe) {
+            writer.write_all(block)?;
+            writer.write_all(&Crc32::of(block).to_le_bytes())?;
+        }
+    }
+    if read_len != original_len || read_full(&mut reader, &mut [0u8; 1])? != 0 {
+        return Err(io::Error::other(
+            "The input file changed length while it was encoded.",
+        ));
+    }
+
+    header.checksum = hasher.value();
+    let mut output_len = 0;
+    for (index, mut writer) in writers.into_iter().enumerate() {
+        header.index = index as u8;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&header.to_bytes())?;
+        output_len += writer.into_inner()?.commit()?.metadata()?.len();
+    }
+    main_timer.add_section(t_coding);
+
+    log_info!(
+        "Encode: Wrote {} shard files: {} ... {}",
+        shard_paths.len(),
+        shard_paths[0].display(),
+        shard_paths[shard_paths.len() - 1].display()
+    );
+    if let Some(format) = args.stats.format() {
+        print_statistics(core, main_timer, original_len, output_len, true, format);
+    }
+    Ok(())
+}
+
+/// Opens `path` and reads its shard header.
+fn open_shard(path: &Path) -> io::Result<(ShardHeader, BufReader<File>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut bytes = [0u8; SHARD_HEADER_SIZE];
+    reader.read_exact(&mut bytes).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Failed to read the shard header. File may be too short or corrupted.",
+        )
+    })?;
+    Ok((ShardHeader::parse(&bytes)?, reader))
+}
+
+/// Reads the next block of a shard into `block` and returns whether its CRC32 matches.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the shard ends before the block does.
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> io::Result<bool> {
+    let mut crc = [0u8; BLOCK_CRC_LEN];
+    reader.read_exact(block)?;
+    reader.read_exact(&mut crc)?;
+    Ok(Crc32::of(block) == u32::from_le_bytes(crc))
+}
+
+/// Restores the original file from the shard files of `args`.
+///
+/// The output is written through a [`SafeWriter`], so it only appears if the file could
+/// be restored.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, and an `InvalidData` error if no
+/// shard is usable, too few shards are left, a stripe has more bad blocks than parity
+/// shards, or the restored data does not match the checksum.
+fn decode_file(core: &core_header::CoreH, args: &cli_parse::DecodeArgs) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(args.stats.enabled());
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let output_file = args.overwrite.policy().resolve(&args.output_file)?;
+
+    // Every shard describes the whole set. The first usable one decides which set is
+    // restored, and shards of other sets are skipped.
+    let mut set: Option<ShardHeader> = None;
+    let mut slots: Vec<Option<BufReader<File>>> = Vec::new();
+    let mut input_len = 0;
+    for path in &args.shard_files {
+        let (header, reader) = match open_shard(path) {
+            Ok(shard) => shard,
+            Err(e) => {
+                log_warn!("Decode: Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let set = set.get_or_insert_with(|| {
+            slots.resize_with(header.total_shards(), || None);
+            header.clone()
+        });
+        if !set.same_set(&header) {
+            log_warn!(
+                "Decode: Skipping {}: it belongs to another set of shards.",
+                path.display()
+            );
+        } else if slots[header.index as usize].is_some() {
+            log_warn!(
+                "Decode: Skipping {}: shard {} was already given.",
+                path.display(),
+                header.index
+            );
+        } else {
+            input_len += reader.get_ref().metadata()?.len();
+            slots[header.index as usize] = Some(reader);
+        }
+    }
+    let Some(header) = set else {
+        return Err(invalid(
+            "None of the given files is a usable shard.".to_string(),
+        ));
+    };
+    let data_shards = header.data_shards as usize;
+    let present = slots.iter().filter(|slot| slot.is_some()).count();
+    log_info!(
+        "Decode: {} of {} shards present, {} needed.",
+        present,
+        header.total_shards(),
+        data_shards
+    );
+    if present < data_shards {
+        return Err(invalid(format!(
+            "Only {} shards are left, but {} are needed to restore the file.",
+            present, data_shards
+        )));
+    }
+
+    let mut writer = BufWriter::new(SafeWriter::create(&output_file)?);
+    let t_coding = main_timer.start_section("Reed-Solomon Decoding");
+    decode_stripes(&header, &mut slots, &mut writer)?;
+    main_timer.add_section(t_coding);
+    writer.into_inner()?.commit()?;
+
+    if let Some(format) = args.stats.format() {
+        print_statistics(
+            core,
+            main_timer,
+            input_len,
+            header.original_len,
+            false,
+            format,
+        );
+    }
+    Ok(())
+}
+
+/// Restores the stripes of the set described by `header` from the shards in `slots` and
+/// writes the original data to `writer`.
+fn decode_stripes<W: Write>(
+    header: &ShardHeader,
+    slots: &mut [Option<BufReader<File>>],
+    writer: &mut W,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let codec = codec(header)?;
+    let data_shards = header.data_shards as usize;
+    let block_size = header.block_size as usize;
+    let mut stripe = vec![(vec![0u8; block_size], false); header.total_shards()];
+    let mut hasher = Crc32::new();
+    let mut remaining = header.original_len;
+    let (mut damaged_blocks, mut repaired_stripes) = (0u64, 0u64);
+    for stripe_index in 0..header.stripes() {
+        for (slot, (block, intact)) in slots.iter_mut().zip(stripe.iter_mut()) {
+            *intact = match slot {
+                Some(reader) => match read_block(reader, block) {
+                    Ok(crc_matches) => {
+                        damaged_blocks += !crc_matches as u64;
+                        crc_matches
+                    }
+                    // A truncated shard is treated as lost from here on.
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        damaged_blocks += 1;
+                        *slot = None;
+                        false
+                    }
+                    Err(e) => return Err(e),
+                },
+                None => false,
+            };
+        }
+        if stripe[..data_shards].iter().any(|(_, intact)| !intact) {
+            codec.reconstruct_data(&mut stripe).map_err(|_| {
+                invalid(format!(
+                    "Stripe {} cannot be restored: fewer than {} of its blocks are intact.",
+                    stripe_index, data_shards
+                ))
+            })?;
+            repaired_stripes += 1;
+        }
+        for (block, _) in &stripe[..data_shards] {
+            let len = block.len().min(remaining as usize);
+            hasher.update(&block[..len]);
+            writer.write_all(&block[..len])?;
+            remaining -= len as u64;
+        }
+    }
+    writer.flush()?;
+
+    let checksum = hasher.value();
+    if checksum != header.checksum {
+        return Err(invalid(format!(
+            "Checksum mismatch: the restored data has CRC32 0x{:08X}, but 0x{:08X} was expected.",
+            checksum, header.checksum
+        )));
+    }
+    if repaired_stripes > 0 {
+        log_info!(
+            "Decode: Repaired {} stripes ({} damaged blocks in present shards).",
+            repaired_stripes,
+            damaged_blocks
+        );
+    }
+    Ok(())
+}
+
+/// Prints the statistics of a finished operation and emits them as a `stats` event.
+///
+/// # Arguments
+///
+/// * `core` - The core the `stats` event is emitted to.
+/// * `main_timer` - The timer holding the sections of the operation.
+/// * `input_len` - The length of the input file, or of all the shards read.
+/// * `output_len` - The length of all the shards written, or of the restored file.
+/// * `is_compression` - Whether the operation encoded the data.
+/// * `format` - The format to print the statistics in.
+fn print_statistics(
+    core: &core_header::CoreH,
+    main_timer: shared_files::stats::OptinalStatsTimer,
+    input_len: u64,
+    output_len: u64,
+    is_compression: bool,
+    format: StatsFormat,
+) {
+    let peak_memory = main_timer.peak_memory();
+    let throughput = main_timer.throughput();
+    let (total_duration, sections) = main_timer.end();
+    let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
+        .algorithm_name("Reed-Solomon FEC")
+        .algorithm_id(MODULE_ID)
+        .version_used(FORMAT_VERSION)
+        .original_len(input_len as usize)
+        .processed_len(output_len as usize)
+        .duration(total_duration)
+        .is_compression(is_compression)
+        .sections(sections)
+        .peak_memory(peak_memory)
+        .throughput(throughput)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
+    println!("{}", calculated_stats.render(format));
+    events::emit(
+        core,
+        &Event::Stats {
+            stats: &calculated_stats,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::abi::{ABI_VERSION, RawBuffer};
+    use shared_files::core_header::{CoreH, Service, StepService};
+    use shared_files::log::LogLevel;
+    use std::{fs, path::PathBuf, process};
+
+    extern "C" fn ping() {}
+
+    extern "C" fn acquire(len: usize) -> RawBuffer {
+        RawBuffer::from_vec(Vec::with_capacity(len))
+    }
+
+    extern "C" fn release(buffer: RawBuffer) {
+        // SAFETY: the buffers come from `acquire`.
+        drop(unsafe { buffer.into_vec() });
+    }
+
+    extern "C" fn register(_: *const c_char, _: Service) -> bool {
+        false
+    }
+
+    extern "C" fn find(_: *const c_char) -> Option<Service> {
+        None
+    }
+
+    extern "C" fn not_cancelled() -> bool {
+        false
+    }
+
+    extern "C" fn emit(_: *const c_char) {}
+
+    extern "C" fn report(_: u8) {}
+
+    extern "C" fn register_step(_: *const c_char, _: StepService) -> bool {
+        false
+    }
+
+    /// A core that offers nothing; the tests run without `--stats`, so it is not asked.
+    fn core() -> CoreH {
+        CoreH {
+            abi_version: ABI_VERSION,
+            size: std::mem::size_of::<CoreH>(),
+            ping_core_f: ping,
+            acquire_buffer_f: acquire,
+            release_buffer_f: release,
+            register_service_f: register,
+            find_service_f: find,
+            is_cancelled_f: not_cancelled,
+            emit_event_f: emit,
+            log_level: LogLevel::Warn,
+            max_memory: 0,
+            report_status_f: report,
+            register_step_f: register_step,
+        }
+    }
+
+    /// A directory in the temporary directory of the system, removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("purgepack-fec-{}-{}", process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            Dir(dir)
+        }
+
+        /// Encodes `data` into 4 data and 2 parity shards of 512-byte blocks and returns
+        /// the paths of the shards.
+        fn encode(&self, data: &[u8]) -> Vec<PathBuf> {
+            let input_file = self.0.join("data.bin");
+            fs::write(&input_file, data).unwrap();
+            let args = ["encode", "-d", "4", "-p", "2", "--block-size", "512"];
+            match parse(&args, &[input_file]).command {
+                cli_parse::Commands::Encode(args) => encode_file(&core(), &args).unwrap(),
+                cli_parse::Commands::Decode(_) => unreachable!(),
+            }
+            (0..6)
+                .map(|index| self.0.join(format!("data.bin.{:03}.ppfs", index)))
+                .collect()
+        }
+
+        /// Decodes `shards` and returns the restored data, checking that no output is
+        /// left behind on failure.
+        fn decode(&self, shards: &[PathBuf]) -> io::Result<Vec<u8>> {
+            let output_file = self.0.join("restored.bin");
+            let _ = fs::remove_file(&output_file);
+            let mut paths = shards.to_vec();
+            paths.extend(["-o".into(), output_file.clone()]);
+            let result = match parse(&["decode"], &paths).command {
+                cli_parse::Commands::Decode(args) => decode_file(&core(), &args),
+                cli_parse::Commands::Encode(_) => unreachable!(),
+            };
+            match result {
+                Ok(()) => Ok(fs::read(&output_file).unwrap()),
+                Err(e) => {
+                    assert!(!output_file.exists(), "{}", e);
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn parse(args: &[&str], paths: &[PathBuf]) -> cli_parse::CliArgs {
+        let args: Vec<String> = ["fec"]
+            .iter()
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .chain(paths.iter().map(|path| path.to_string_lossy().into_owned()))
+            .collect();
+        cli_parse::parse_args(&args).unwrap()
+    }
+
+    /// Three stripes of 4 blocks of 512 bytes, the last one only partly filled.
+    fn data() -> Vec<u8> {
+        (0..5000).map(|i| (i * 13 + i / 509) as u8).collect()
+    }
+
+    #[test]
+    fn restores_the_file_with_up_to_the_parity_shards_missing() {
+        let dir = Dir::new("missing");
+        let original = data();
+        let shards = dir.encode(&original);
+        // Every single shard and every pair, with the rest given in reverse order.
+        for first in 0..shards.len() {
+            for second in first..shards.len() {
+                let lost = [first, second];
+                let left: Vec<PathBuf> = (0..shards.len())
+                    .filter(|index| !lost.contains(index))
+                    .map(|index| shards[index].clone())
+                    .rev()
+                    .collect();
+                assert_eq!(dir.decode(&left).unwrap(), original, "lost {:?}", lost);
+            }
+        }
+        assert_eq!(dir.decode(&shards).unwrap(), original);
+    }
+
+    #[test]
+    fn refuses_to_restore_with_more_than_the_parity_shards_missing() {
+        let dir = Dir::new("too-many");
+        let shards = dir.encode(&data());
+        for lost in [[0, 1, 2], [3, 4, 5], [0, 2, 4]] {
+            let left: Vec<PathBuf> = (0..shards.len())
+                .filter(|index| !lost.contains(index))
+                .map(|index| shards[index].clone())
+                .collect();
+            let error = dir.decode(&left).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "lost {:?}", lost);
+        }
+    }
+
+    #[test]
+    fn repairs_damaged_blocks_up_to_the_parity_shards_per_stripe() {
+        let dir = Dir::new("damaged");
+        let original = data();
+        let shards = dir.encode(&original);
+        let block_len = 512 + BLOCK_CRC_LEN;
+        let damage = |index: usize, stripe: usize| {
+            let mut bytes = fs::read(&shards[index]).unwrap();
+            bytes[SHARD_HEADER_SIZE + stripe * block_len + 100] ^= 0x01;
+            fs::write(&shards[index], bytes).unwrap();
+        };
+
+        // Two bad blocks in every stripe, in different shards.
+        for (stripe, shards) in [[0, 5], [1, 2], [3, 4]].into_iter().enumerate() {
+            for index in shards {
+                damage(index, stripe);
+            }
+        }
+        assert_eq!(dir.decode(&shards).unwrap(), original);
+
+        // A third bad block in the first stripe is one more than the parity can repair.
+        damage(2, 0);
+        let error = dir.decode(&shards).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
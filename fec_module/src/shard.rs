@@ -0,0 +1,143 @@
+//! The shard file header.
+//!
+//! Every shard file starts with a header describing the whole set, so a set can be
+//! decoded from any subset of its files, in any order and under any names:
+//!
+//! ```text
+//! [magic: "PPFS"][module id: u8][format version: u8][data shards: u8][parity shards: u8]
+//! [shard index: u8][block size: u32 LE][original length: u64 LE][original CRC32: u32 LE]
+//! [set id: 8 bytes][header CRC32: u32 LE]
+//! ```
+//!
+//! The set id is random per encoding, so shards of two different sets are never mixed.
+//! The header CRC32 covers all the bytes before it.
+
+use shared_files::checksum::Crc32;
+use std::io;
+
+/// Magic bytes to identify a shard file. PPFS stands for "PurgePack FEC Shard".
+const SHARD_MAGIC: [u8; 4] = *b"PPFS";
+/// Module ID (Algorithm Identifier) for the FEC module.
+pub(crate) const MODULE_ID: u8 = 0x06;
+/// The version of the header layout and shard format written by this build.
+pub(crate) const FORMAT_VERSION: u8 = 1;
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for
+/// format version + 3 bytes for the shard counts and index + 4 bytes for the block size +
+/// 8 bytes for the original length + 4 bytes for the CRC32 of the original data + 8 bytes
+/// for the set id + 4 bytes for the CRC32 of the header).
+pub(crate) const SHARD_HEADER_SIZE: usize = 37;
+/// The length of the set id in bytes.
+pub(crate) const SET_ID_LEN: usize = 8;
+/// The length of the CRC32 that follows every block.
+pub(crate) const BLOCK_CRC_LEN: usize = 4;
+/// The largest number of shards in a set, the size of the GF(2^8) field.
+pub(crate) const MAX_SHARDS: usize = 256;
+
+/// The settings stored in the header of every shard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ShardHeader {
+    /// The number of data shards.
+    pub(crate) data_shards: u8,
+    /// The number of parity shards.
+    pub(crate) parity_shards: u8,
+    /// The position of this shard in the set. Data shards come first.
+    pub(crate) index: u8,
+    /// The number of data bytes per block.
+    pub(crate) block_size: u32,
+    /// The length in bytes of the original data.
+    pub(crate) original_len: u64,
+    /// The CRC32 of the original data.
+    pub(crate) checksum: u32,
+    /// The random id shared by all the shards of a set.
+    pub(crate) set_id: [u8; SET_ID_LEN],
+}
+
+impl ShardHeader {
+    /// Returns the header as it is written to the shard file.
+    pub(crate) fn to_bytes(&self) -> [u8; SHARD_HEADER_SIZE] {
+        let mut bytes = [0u8; SHARD_HEADER_SIZE];
+        bytes[..4].copy_from_slice(&SHARD_MAGIC);
+        bytes[4] = MODULE_ID;
+        bytes[5] = FORMAT_VERSION;
+        bytes[6] = self.data_shards;
+        bytes[7] = self.parity_shards;
+        bytes[8] = self.index;
+        bytes[9..13].copy_from_slice(&self.block_size.to_le_bytes());
+        bytes[13..21].copy_from_slice(&self.original_len.to_le_bytes());
+        bytes[21..25].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[25..33].copy_from_slice(&self.set_id);
+        let header_crc = Crc32::of(&bytes[..33]);
+        bytes[33..].copy_from_slice(&header_crc.to_le_bytes());
+        bytes
+    }
+
+    /// Parses and validates a header read from a shard file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the bytes are not a shard header, are damaged,
+    /// use a newer format version or describe an impossible set.
+    pub(crate) fn parse(bytes: &[u8; SHARD_HEADER_SIZE]) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if bytes[..4] != SHARD_MAGIC {
+            return Err(invalid(
+                "Invalid shard magic number. This may not be a PurgePack FEC Shard (PPFS) file."
+                    .to_string(),
+            ));
+        }
+        if Crc32::of(&bytes[..33]) != u32::from_le_bytes(bytes[33..].try_into().unwrap()) {
+            return Err(invalid("The shard header is damaged.".to_string()));
+        }
+        if bytes[4] != MODULE_ID {
+            return Err(invalid(format!(
+                "Unsupported module ID: 0x{:02X}. Only 0x{:02X} (FEC) is supported.",
+                bytes[4], MODULE_ID
+            )));
+        }
+        if bytes[5] == 0 || bytes[5] > FORMAT_VERSION {
+            return Err(invalid(format!(
+                "Unsupported format version {}. This build supports up to version {}.",
+                bytes[5], FORMAT_VERSION
+            )));
+        }
+        let header = ShardHeader {
+            data_shards: bytes[6],
+            parity_shards: bytes[7],
+            index: bytes[8],
+            block_size: u32::from_le_bytes(bytes[9..13].try_into().unwrap()),
+            original_len: u64::from_le_bytes(bytes[13..21].try_into().unwrap()),
+            checksum: u32::from_le_bytes(bytes[21..25].try_into().unwrap()),
+            set_id: bytes[25..33].try_into().unwrap(),
+        };
+        if header.data_shards == 0
+            || header.parity_shards == 0
+            || header.total_shards() > MAX_SHARDS
+            || header.index as usize >= header.total_shards()
+            || header.block_size == 0
+        {
+            return Err(invalid(
+                "The shard header describes an invalid set.".to_string(),
+            ));
+        }
+        Ok(header)
+    }
+
+    /// Returns the number of data and parity shards together.
+    pub(crate) fn total_shards(&self) -> usize {
+        self.data_shards as usize + self.parity_shards as usize
+    }
+
+    /// Returns the number of stripes, each holding one block of every shard.
+    pub(crate) fn stripes(&self) -> u64 {
+        self.original_len
+            .div_ceil(self.data_shards as u64 * self.block_size as u64)
+    }
+
+    /// Returns whether `other` belongs to the same set, differing only in the index.
+    pub(crate) fn same_set(&self, other: &ShardHeader) -> bool {
+        ShardHeader {
+            index: self.index,
+            ..other.clone()
+        } == *self
+    }
+}
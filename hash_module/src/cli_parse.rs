@@ -0,0 +1,112 @@
+use clap::{
+    Args, Parser, Subcommand,
+    builder::{PossibleValuesParser, TypedValueParser},
+};
+use shared_files::cli::{self, CliError, OverwriteArgs, Validate};
+use std::path::PathBuf;
+
+use crate::digest::Algorithm;
+
+/// Arguments of the `hash` command.
+#[derive(Debug, Clone, Args)]
+pub struct HashArgs {
+    /// The paths of the files to hash.
+    #[arg(required = true)]
+    pub input_files: Vec<PathBuf>,
+    /// The hash algorithm. CRC32 only detects accidental corruption; use SHA-256 or
+    /// BLAKE3 to guard against deliberate changes.
+    #[arg(
+        short,
+        long,
+        default_value = "sha256",
+        value_parser = PossibleValuesParser::new(Algorithm::ALL.map(Algorithm::name)).map(|s| Algorithm::from_name(&s).unwrap())
+    )]
+    pub algorithm: Algorithm,
+    /// Writes the manifest lines to this file as well, so it can be checked later with
+    /// `verify`.
+    #[arg(short, long)]
+    pub manifest: Option<PathBuf>,
+    /// Hashes the original data of PurgePack Compressed Binary (PPCB) files instead of
+    /// their compressed bytes. The data is restored in memory, never written to disk.
+    /// Other files are hashed as they are.
+    #[arg(short, long)]
+    pub content: bool,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+}
+
+/// Arguments of the `verify` command.
+#[derive(Debug, Clone, Args)]
+pub struct VerifyArgs {
+    /// The path to the manifest. Relative paths inside it are resolved against the
+    /// current directory, as with `sha256sum --check`.
+    pub manifest: PathBuf,
+    /// Checks the original data of PPCB files, for manifests written with `--content`.
+    #[arg(short, long)]
+    pub content: bool,
+}
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Hashes files and prints one manifest line per file.
+    #[clap(alias = "h")]
+    Hash(HashArgs),
+    /// Hashes the files listed in a manifest again and reports the ones that changed.
+    #[clap(alias = "v")]
+    Verify(VerifyArgs),
+}
+
+/// The main command line argument structure for the Hash Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Hashing and Integrity Utility.",
+    long_about = "Computes CRC32, SHA-256 or BLAKE3 digests of files, or of the original data inside PurgePack Compressed Binary (PPCB) files, and writes and checks manifests of them.",
+    after_help = "
+    EXAMPLES:
+    # 1. Print the SHA-256 of a file
+    hash_tool.exe hash notes.txt
+
+    # 2. Write a BLAKE3 manifest of several files
+    hash_tool.exe h notes.txt photo.bmp --algorithm blake3 --manifest files.b3
+
+    # 3. Hash what a compressed file restores, without decompressing it to disk
+    hash_tool.exe h notes.ppcb --content
+
+    # 4. Check the files of a manifest
+    hash_tool.exe verify files.b3
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. Every input file, or the manifest to verify, exists and is a file.
+    /// 2. The parent directory for the manifest to write exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        match &self.command {
+            Commands::Hash(args) => {
+                for input_file in &args.input_files {
+                    cli::check_input(input_file)?;
+                }
+                if let Some(manifest) = &args.manifest {
+                    cli::check_output(manifest)?;
+                }
+            }
+            Commands::Verify(args) => cli::check_input(&args.manifest)?,
+        }
+        Ok(())
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
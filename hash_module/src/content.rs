@@ -0,0 +1,106 @@
+//! Reading the bytes to hash: a file as it is, or the original data of a PPCB file.
+//!
+//! The original data is restored by the codec crates of the compression modules and fed
+//! straight into the hash, so nothing is written to disk. The codecs check the CRC32
+//! stored in the header while restoring, so a corrupted file is reported as an error
+//! rather than hashed. PPCB files of modules without a codec crate are rejected.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use shared_files::codec::Decompressor;
+use shared_files::frame::FrameHeader;
+use shared_files::progress::{self, ProgressReader};
+
+use crate::digest::{Algorithm, HashWriter};
+
+/// The digest of a file and what it was computed over.
+pub(crate) struct FileDigest {
+    /// The digest as lowercase hex.
+    pub(crate) digest: String,
+    /// The number of bytes hashed.
+    pub(crate) len: u64,
+    /// The name of the module whose format was restored, if the original data was hashed.
+    pub(crate) restored_by: Option<&'static str>,
+}
+
+/// Hashes `path` with `algorithm`.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file.
+/// * `algorithm` - The hash algorithm.
+/// * `content` - Whether to hash the original data of a PPCB file instead of its bytes.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading fails, or an `InvalidData` error if a PPCB file
+/// cannot be restored.
+pub(crate) fn hash_file(
+    path: &Path,
+    algorithm: Algorithm,
+    content: bool,
+) -> io::Result<FileDigest> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(ProgressReader::new(
+        file,
+        progress::terminal("Hash", Some(len)),
+    ));
+    let mut hasher = HashWriter::new(algorithm);
+    let prefix = reader.fill_buf()?;
+    let module_id = FrameHeader::parse(prefix)
+        .ok()
+        .filter(|_| content)
+        .map(|header| header.module_id);
+    let restored_by = match module_id {
+        Some(module_id) => Some(restore(&mut reader, &mut hasher, module_id)?),
+        None => {
+            io::copy(&mut reader, &mut hasher)?;
+            None
+        }
+    };
+    Ok(FileDigest {
+        len: hasher.len(),
+        digest: hasher.finish(),
+        restored_by,
+    })
+}
+
+/// Restores the PPCB file in `reader`, written by the module `module_id`, into `hasher`
+/// and returns the name of the module.
+fn restore(
+    reader: &mut impl Read,
+    hasher: &mut HashWriter,
+    module_id: u8,
+) -> io::Result<&'static str> {
+    let (name, codec): (&'static str, Box<dyn Decompressor>) = match module_id {
+        purgepack_delta::MODULE_ID => ("Delta", Box::new(purgepack_delta::Delta::default())),
+        purgepack_range_coder::MODULE_ID => (
+            "Range Coder",
+            Box::new(purgepack_range_coder::RangeCoder::default()),
+        ),
+        purgepack_bwt::MODULE_ID => ("BWT", Box::new(purgepack_bwt::Bwt::default())),
+        purgepack_fast_lz::MODULE_ID => ("Fast LZ", Box::new(purgepack_fast_lz::FastLz::default())),
+        purgepack_bitpack::MODULE_ID => ("Bit Packing", Box::new(purgepack_bitpack::BitPack)),
+        // The record size is read from the header.
+        purgepack_transpose::MODULE_ID => (
+            "Transpose",
+            Box::new(purgepack_transpose::Transpose { record_size: 1 }),
+        ),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The original data of PPCB files written by module ID 0x{:02X} cannot be restored in memory. Hash them without --content.",
+                    module_id
+                ),
+            ));
+        }
+    };
+    codec.decompress(reader, hasher)?;
+    Ok(name)
+}
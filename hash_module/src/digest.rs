@@ -0,0 +1,100 @@
+//! The hash algorithms and a writer that feeds everything written through it to one.
+
+use shared_files::checksum::{self, Blake3, Crc32, Hasher, Sha256};
+use std::io::{self, Write};
+
+/// A hash algorithm that can be computed over a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    /// CRC32 (IEEE), the checksum stored in PPCB headers. Detects accidental corruption only.
+    Crc32,
+    /// SHA-256.
+    Sha256,
+    /// BLAKE3 with a 256-bit output.
+    Blake3,
+}
+
+impl Algorithm {
+    /// Every algorithm, in the order they are listed on the command line.
+    pub(crate) const ALL: [Algorithm; 3] = [Algorithm::Crc32, Algorithm::Sha256, Algorithm::Blake3];
+
+    /// The name used on the command line.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Algorithm::Crc32 => "crc32",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// The tag used in manifest lines, as written by `sha256sum --tag` and `b3sum`.
+    pub(crate) fn tag(self) -> &'static str {
+        match self {
+            Algorithm::Crc32 => "CRC32",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Blake3 => "BLAKE3",
+        }
+    }
+
+    /// Returns the algorithm with the command line `name`.
+    pub(crate) fn from_name(name: &str) -> Option<Algorithm> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+    }
+
+    /// Returns the algorithm with the manifest `tag`.
+    pub(crate) fn from_tag(tag: &str) -> Option<Algorithm> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.tag() == tag)
+    }
+
+    /// Returns a new hasher computing the algorithm.
+    pub(crate) fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            Algorithm::Crc32 => Box::new(Crc32::new()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+            Algorithm::Blake3 => Box::new(Blake3::new()),
+        }
+    }
+}
+
+/// A writer that discards the bytes written through it and hashes them instead.
+pub(crate) struct HashWriter {
+    hasher: Box<dyn Hasher>,
+    len: u64,
+}
+
+impl HashWriter {
+    /// Creates a writer hashing with `algorithm`.
+    pub(crate) fn new(algorithm: Algorithm) -> Self {
+        HashWriter {
+            hasher: algorithm.hasher(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bytes hashed.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the digest as lowercase hex. A CRC32 is written big-endian, as `crc32`
+    /// and most tools print it.
+    pub(crate) fn finish(self) -> String {
+        checksum::to_hex(&self.hasher.digest())
+    }
+}
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
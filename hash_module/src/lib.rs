@@ -0,0 +1,201 @@
+//! File hashing and integrity checks.
+//!
+//! `hash` computes a CRC32, SHA-256 or BLAKE3 digest of every input file and prints it as
+//! a manifest line (see the `manifest` module), optionally collecting the lines in a
+//! manifest file. `verify` hashes the files listed in a manifest again and reports every
+//! one as OK, FAILED or MISSING.
+//!
+//! With `--content`, PPCB files are hashed by their original data, restored in memory
+//! (see the `content` module). The digest then equals the one of the uncompressed file,
+//! so a compressed copy can be checked against the original without extracting it.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+mod cli_parse;
+mod content;
+mod digest;
+mod manifest;
+use shared_files::abi;
+use shared_files::cli::{self, CliError};
+use shared_files::core_header::{self};
+use shared_files::{log_debug, log_error, log_info};
+use std::ffi::c_char;
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("hash", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Hash(args) => match hash_files(&args) {
+                Ok(()) => log_info!("Hash: Success"),
+                Err(e) => {
+                    log_error!("Hash: {}", e);
+                    core_header::report_error(core, &e);
+                }
+            },
+            cli_parse::Commands::Verify(args) => {
+                log_info!("Verify: Manifest: {}", args.manifest.display());
+                match verify_manifest(&args.manifest, args.content) {
+                    Ok(()) => log_info!("Verify: Success"),
+                    Err(e) => {
+                        log_error!("Verify: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("hash", core.log_level);
+    log_debug!("Hash module shutting down.");
+}
+
+/// Hashes every input file, prints its manifest line and writes the manifest if asked to.
+///
+/// A file that cannot be hashed is reported and left out of the manifest, and the other
+/// files are still hashed.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if writing the manifest fails or if any file could not be hashed.
+fn hash_files(args: &cli_parse::HashArgs) -> io::Result<()> {
+    // Checked first, so the files are not hashed in vain.
+    let manifest_file = args
+        .manifest
+        .as_deref()
+        .map(|manifest| args.overwrite.policy().resolve(manifest))
+        .transpose()?;
+    let mut lines = Vec::with_capacity(args.input_files.len());
+    for input_file in &args.input_files {
+        match content::hash_file(input_file, args.algorithm, args.content) {
+            Ok(file_digest) => {
+                let line = manifest::format_line(
+                    args.algorithm,
+                    &input_file.to_string_lossy(),
+                    &file_digest.digest,
+                );
+                println!("{}", line);
+                if let Some(module) = file_digest.restored_by {
+                    log_info!(
+                        "Hash: Hashed the {} bytes restored from the {} format.",
+                        file_digest.len,
+                        module
+                    );
+                }
+                lines.push(line);
+            }
+            Err(e) => log_error!("Hash: {}: {}", input_file.display(), e),
+        }
+    }
+
+    if let Some(manifest) = &manifest_file {
+        let mut writer = BufWriter::new(File::create(manifest)?);
+        for line in &lines {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+        log_info!(
+            "Hash: Manifest of {} files written to {}",
+            lines.len(),
+            manifest.display()
+        );
+    }
+    let failures = args.input_files.len() - lines.len();
+    if failures > 0 {
+        return Err(io::Error::other(format!(
+            "{} of {} files could not be hashed.",
+            failures,
+            args.input_files.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Hashes the files listed in `manifest` again and prints the result for every one.
+///
+/// # Arguments
+///
+/// * `manifest` - The path to the manifest.
+/// * `content` - Whether to hash the original data of PPCB files.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the manifest cannot be read, and an `InvalidData` error if
+/// it lists no files, holds malformed lines, or any file is missing or does not match.
+fn verify_manifest(manifest: &Path, content: bool) -> io::Result<()> {
+    let text = fs::read_to_string(manifest)?;
+    let (mut ok, mut failed, mut missing, mut malformed) = (0u64, 0u64, 0u64, 0u64);
+    for (index, line) in text.lines().enumerate() {
+        let entry = match manifest::parse_line(line) {
+            None => continue,
+            Some(Ok(entry)) => entry,
+            Some(Err(message)) => {
+                log_info!("Verify: Line {} is malformed: {}", index + 1, message);
+                malformed += 1;
+                continue;
+            }
+        };
+        if !entry.path.is_file() {
+            println!("{}: MISSING", entry.path.display());
+            missing += 1;
+            continue;
+        }
+        match content::hash_file(&entry.path, entry.algorithm, content) {
+            Ok(file_digest) if file_digest.digest == entry.digest => {
+                println!("{}: OK", entry.path.display());
+                ok += 1;
+            }
+            Ok(_) => {
+                println!("{}: FAILED", entry.path.display());
+                failed += 1;
+            }
+            Err(e) => {
+                println!("{}: FAILED ({})", entry.path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("Verify: {} OK, {} FAILED, {} MISSING", ok, failed, missing);
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    if malformed > 0 {
+        return Err(invalid(format!(
+            "The manifest holds {} malformed lines.",
+            malformed
+        )));
+    }
+    if failed + missing > 0 {
+        return Err(invalid(format!(
+            "{} of {} files do not match the manifest.",
+            failed + missing,
+            ok + failed + missing
+        )));
+    }
+    if ok == 0 {
+        return Err(invalid("The manifest lists no files.".to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! Manifest files: one line per file in the BSD tag format written by
+//! `sha256sum --tag` and `b3sum --tag`, so the manifests stay readable by other tools:
+//!
+//! ```text
+//! SHA256 (notes.txt) = 2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae
+//! ```
+//!
+//! Empty lines and lines starting with `#` are skipped.
+
+use std::path::PathBuf;
+
+use crate::digest::Algorithm;
+
+/// A line of a manifest.
+pub(crate) struct Entry {
+    /// The algorithm of the digest.
+    pub(crate) algorithm: Algorithm,
+    /// The path of the file, as it was given when hashing.
+    pub(crate) path: PathBuf,
+    /// The expected digest as lowercase hex.
+    pub(crate) digest: String,
+}
+
+/// Formats the manifest line for `path`.
+pub(crate) fn format_line(algorithm: Algorithm, path: &str, digest: &str) -> String {
+    format!("{} ({}) = {}", algorithm.tag(), path, digest)
+}
+
+/// Parses a manifest line.
+///
+/// # Returns
+///
+/// Returns `None` for lines to skip, and `Some(Err(message))` for malformed lines.
+pub(crate) fn parse_line(line: &str) -> Option<Result<Entry, String>> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() || line.starts_with('#') {
+        return None;
+    }
+    // The path may itself contain " (" or ") = ", so split at the outermost ones.
+    let parsed = line.split_once(" (").and_then(|(tag, rest)| {
+        let (path, digest) = rest.rsplit_once(") = ")?;
+        Some((tag, path, digest))
+    });
+    let Some((tag, path, digest)) = parsed else {
+        return Some(Err("expected `ALGORITHM (path) = digest`".to_string()));
+    };
+    let Some(algorithm) = Algorithm::from_tag(tag) else {
+        return Some(Err(format!("unknown algorithm `{}`", tag)));
+    };
+    if digest.is_empty() || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Some(Err(format!("`{}` is not a hex digest", digest)));
+    }
+    Some(Ok(Entry {
+        algorithm,
+        path: PathBuf::from(path),
+        digest: digest.to_ascii_lowercase(),
+    }))
+}
@@ -0,0 +1,963 @@
+//! Running the commands of the core on many files at once.
+//!
+//! `compress` and `x` take any number of files, directories and glob patterns like
+//! `*.log` or `data/**/*.csv`. The patterns are expanded by PurgePack itself, so they work
+//! the same in shells that leave them alone, like the Windows command prompt. A directory
+//! is walked recursively, and every file becomes a job of its own with its own output.
+//!
+//! The outputs are written next to their files, or with `--output-dir <DIR>` below `DIR`
+//! at the same relative path as their files, like `out/data/2024/a.csv.ppcb` for
+//! `data/2024/a.csv`. Missing directories are created.
+//!
+//! The inputs are kept unless `--rm` is given, or the configuration sets `keep = false`. Then an input is removed once its output
+//! is flushed to the disk and checked; `compress` restores the output and compares it with
+//! the input first.
+//!
+//! The jobs run on a pool of worker threads, one per CPU unless `-j` says otherwise.
+//! Symbolic links inside a directory are skipped unless `--follow-symlinks` is given;
+//! every directory is then visited once, so a link pointing back up the tree cannot loop.
+//! The same goes for the directories a pattern is matched in. A link named on the command
+//! line is always followed. After Ctrl-C, the files that did not start yet are skipped.
+//!
+//! The settings of the configuration file, and of the profile `--profile <NAME>` selects
+//! in it, apply where the command line sets nothing; see [`config`](crate::config).
+//! `PURGEPACK_JOBS` and `PURGEPACK_LEVEL` win over them; see [`overrides`].
+//!
+//! A run over several files keeps a [`Checkpoint`] of the files it finished, so a run that
+//! was interrupted can go on with `--resume` instead of starting over.
+//!
+//! Once all jobs ended, a table of the results and their totals is printed. With `--json`,
+//! standard output gets one JSON object per file instead, and the text goes to standard
+//! error, so scripts can read the results line by line:
+//!
+//! ```text
+//! {"input":"a.csv","output":"a.csv.ppcb","modules":["+delta_module transform"],
+//!  "input_len":1000,"output_len":312,"ratio":31.2,"duration_secs":0.05,"status":"done"}
+//! ```
+//!
+//! (One line per file; wrapped here.) `status` is `done`, `skipped` or `failed`, with the
+//! reason or error in `message`, or `estimated` for `compress --estimate`. `output` is
+//! `null` without an output, and `ratio` without an output or estimate.
+
+use crate::cancel;
+use crate::checkpoint::Checkpoint;
+use crate::config::{Config, Profile};
+use crate::exit_status;
+use serde::Serialize;
+use shared_files::error::ExitStatus;
+use shared_files::json;
+use shared_files::output::Overwrite;
+use shared_files::overrides;
+use shared_files::term::{self, Stream, Style};
+use shared_files::{log_error, log_info, log_warn};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io,
+    path::{Component, Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The options the commands working on files share.
+pub struct Options {
+    /// The files and directories to work on, with the patterns expanded.
+    pub inputs: Vec<PathBuf>,
+    /// The output of a single file, from `-o`.
+    pub output: Option<PathBuf>,
+    /// The directory the outputs are written to, from `--output-dir`.
+    pub output_dir: Option<PathBuf>,
+    /// Whether the inputs are removed once their output is written, from `--rm`.
+    pub remove_sources: bool,
+    /// What happens to outputs that exist already, from `--force` and `--suffix`.
+    pub overwrite: Overwrite,
+    /// The number of files worked on at the same time.
+    pub jobs: usize,
+    /// Whether symbolic links inside directories are followed.
+    pub follow_symlinks: bool,
+    /// Whether the results are written as JSON, from `--json`.
+    pub json: bool,
+    /// Whether the files finished by an interrupted run are skipped, from `--resume`.
+    pub resume: bool,
+    /// The settings of the configuration file and the profile from `--profile`.
+    pub profile: Profile,
+}
+
+impl Options {
+    /// Parses the arguments following the name of a command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message, ending with `usage`, if the arguments are invalid.
+    pub fn parse(args: &[String], usage: &str) -> Result<Self, String> {
+        Self::parse_with(args, usage, &Config::load()?, overrides::var)
+    }
+
+    /// Parses the arguments like [`Options::parse`], with the configuration `config` and
+    /// the environment variables `var` returns.
+    fn parse_with(
+        args: &[String],
+        usage: &str,
+        config: &Config,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let mut patterns = Vec::new();
+        let mut output = None;
+        let mut output_dir = None;
+        let mut remove_sources = None;
+        let mut overwrite = Overwrite::Refuse;
+        let mut jobs = None;
+        let mut follow_symlinks = false;
+        let mut json = false;
+        let mut resume = false;
+        let mut profile = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => match args.next() {
+                    Some(path) => output = Some(PathBuf::from(path)),
+                    None => return Err(format!("{} expects a path. {}", arg, usage)),
+                },
+                "-O" | "--output-dir" => match args.next() {
+                    Some(path) => output_dir = Some(PathBuf::from(path)),
+                    None => return Err(format!("{} expects a directory. {}", arg, usage)),
+                },
+                "-j" | "--jobs" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => jobs = Some(n),
+                    _ => return Err(format!("{} expects a positive number. {}", arg, usage)),
+                },
+                "--profile" => match args.next() {
+                    Some(name) => profile = Some(name.as_str()),
+                    None => return Err(format!("{} expects a name. {}", arg, usage)),
+                },
+                "--keep" => remove_sources = Some(false),
+                "--rm" => remove_sources = Some(true),
+                "-f" | "--force" => overwrite = Overwrite::Replace,
+                "--suffix" => overwrite = Overwrite::Suffix,
+                "-L" | "--follow-symlinks" => follow_symlinks = true,
+                "--json" => json = true,
+                "--resume" => resume = true,
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unexpected argument {}. {}", arg, usage));
+                }
+                _ => patterns.push(arg),
+            }
+        }
+
+        let mut profile = config.profile(profile)?;
+        profile.level =
+            overrides::parse_value(overrides::LEVEL, var(overrides::LEVEL))?.or(profile.level);
+        let env_jobs = overrides::parse_value(overrides::JOBS, var(overrides::JOBS))?;
+        if env_jobs == Some(0) {
+            return Err(format!("{} expects a positive number.", overrides::JOBS));
+        }
+        profile.jobs = env_jobs.or(profile.jobs);
+        let mut inputs = Vec::new();
+        for pattern in patterns {
+            inputs.extend(expand(pattern, follow_symlinks)?);
+        }
+        if inputs.is_empty() {
+            return Err(usage.to_string());
+        }
+        for input in &inputs {
+            if !input.exists() {
+                exit_status::report(ExitStatus::Io);
+            }
+            if !input.is_file() && !input.is_dir() {
+                return Err(format!("{} is not a file or directory.", input.display()));
+            }
+        }
+        if output.is_some() && (inputs.len() > 1 || inputs[0].is_dir()) {
+            return Err(
+                "-o names the output of a single file. Leave it out to write the \
+                 output of every file next to it."
+                    .to_string(),
+            );
+        }
+        if output.is_some() && output_dir.is_some() {
+            return Err(format!(
+                "-o and --output-dir cannot be used together. {}",
+                usage
+            ));
+        }
+
+        Ok(Options {
+            inputs,
+            output,
+            output_dir,
+            remove_sources: remove_sources.unwrap_or(profile.keep == Some(false)),
+            overwrite,
+            jobs: jobs
+                .or(profile.jobs)
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from)),
+            follow_symlinks,
+            json,
+            resume,
+            profile,
+        })
+    }
+
+    /// Opens the checkpoint of `command` for a run over `files` files, if there are several
+    /// or `--resume` was given. A checkpoint that cannot be opened is logged, and the run
+    /// goes on without it.
+    pub fn checkpoint(&self, command: &str, files: usize) -> Option<Checkpoint> {
+        if files <= 1 && !self.resume {
+            return None;
+        }
+        let directory = self.output_dir.as_deref().unwrap_or(Path::new("."));
+        let path = Checkpoint::path(directory, command);
+        if let Err(msg) = create_parent(&path) {
+            log_warn!("{}", msg);
+            return None;
+        }
+        Checkpoint::open(&path, self.resume)
+            .inspect_err(|msg| {
+                log_warn!("Failed to open the checkpoint {}: {}", path.display(), msg)
+            })
+            .ok()
+    }
+
+    /// Prints a line of text for people: to standard output, or to standard error with
+    /// `--json`, where standard output only gets the results.
+    pub fn print(&self, line: impl Display) {
+        print(self.json, line);
+    }
+
+    /// Returns where the output at `path`, next to an input, is written: at `path` itself,
+    /// or at the same relative path below the output directory.
+    ///
+    /// Only the names of the directories are kept, so absolute paths and `..` end up below
+    /// the output directory as well.
+    pub fn place(&self, path: &Path) -> PathBuf {
+        let Some(output_dir) = &self.output_dir else {
+            return path.to_path_buf();
+        };
+        let relative: PathBuf = path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        output_dir.join(relative)
+    }
+
+    /// Removes `input` if `--rm` was given, once `output` is flushed to the disk.
+    ///
+    /// The output must have been checked before, since the input is its only other copy.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the output cannot be flushed or the input cannot be removed.
+    pub fn remove_source(&self, input: &Path, output: &Path) -> Result<(), String> {
+        if !self.remove_sources {
+            return Ok(());
+        }
+        OpenOptions::new()
+            .write(true)
+            .open(output)
+            .and_then(|file| file.sync_all())
+            .map_err(|msg| format!("Failed to flush {}: {}", output.display(), msg))?;
+        fs::remove_file(input)
+            .map_err(|msg| format!("Failed to remove {}: {}", input.display(), msg))
+    }
+
+    /// Returns the files to work on: the files among the inputs, and the files inside the
+    /// directories among them for which `wanted` returns `true`, in the order of their
+    /// paths.
+    ///
+    /// Also returns the results of the entries that are left out, like symbolic links and
+    /// directories that cannot be read.
+    pub fn files(&self, wanted: impl Fn(&Path) -> bool) -> (Vec<PathBuf>, Vec<FileResult>) {
+        let (mut directories, mut files): (Vec<_>, Vec<_>) = self
+            .inputs
+            .iter()
+            .cloned()
+            .partition(|input| input.is_dir());
+        let mut left_out = Vec::new();
+        // An output directory inside an input is not searched for inputs.
+        let mut visited: Vec<_> = self
+            .output_dir
+            .iter()
+            .filter_map(|output_dir| output_dir.canonicalize().ok())
+            .collect();
+        while let Some(directory) = directories.pop() {
+            if !first_visit(&directory, &mut visited) {
+                continue;
+            }
+            let entries = match read_sorted(&directory) {
+                Ok(entries) => entries,
+                Err(msg) => {
+                    left_out.push(FileResult::failed(
+                        &directory,
+                        format!("Failed to read the directory: {}", msg),
+                    ));
+                    continue;
+                }
+            };
+
+            for path in entries {
+                if path.is_symlink() && !self.follow_symlinks {
+                    if path.is_dir() || wanted(&path) {
+                        left_out.push(FileResult::skipped(
+                            &path,
+                            "Symbolic link, use --follow-symlinks to follow it.",
+                        ));
+                    }
+                } else if path.is_dir() {
+                    directories.push(path);
+                } else if path.is_file() && wanted(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        // Overlapping inputs, like `data data/*.csv`, name some files twice.
+        files.sort();
+        files.dedup();
+        (files, left_out)
+    }
+}
+
+/// Returns `true` if `arg` holds wildcards.
+fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Returns the paths matching the glob pattern `arg`, or `arg` itself if it is no pattern
+/// or names an existing file.
+///
+/// `*` and `?` do not match `/` or a leading `.`, and `**` matches any number of
+/// directories. Names are compared like the file system of the platform does, so without
+/// regard to case on Windows.
+fn expand(arg: &str, follow_symlinks: bool) -> Result<Vec<PathBuf>, String> {
+    let path = PathBuf::from(arg);
+    if !is_pattern(arg) || path.exists() {
+        return Ok(vec![path]);
+    }
+
+    let pattern =
+        glob::Pattern::new(arg).map_err(|msg| format!("Invalid pattern {}: {}", arg, msg))?;
+    let options = glob::MatchOptions {
+        case_sensitive: !cfg!(windows),
+        require_literal_separator: true,
+        require_literal_leading_dot: true,
+    };
+
+    // Only the part of the tree the pattern can reach is walked: from its leading
+    // directories without wildcards, and no deeper than it has components unless it holds
+    // `**`.
+    let base: PathBuf = path
+        .components()
+        .take_while(|component| !is_pattern(&component.as_os_str().to_string_lossy()))
+        .collect();
+    let depth = if arg.contains("**") {
+        usize::MAX
+    } else {
+        path.components().count() - base.components().count()
+    };
+    let in_current_dir = base.as_os_str().is_empty();
+    let root = if in_current_dir {
+        PathBuf::from(".")
+    } else {
+        base
+    };
+
+    let mut matches = Vec::new();
+    let mut visited = Vec::new();
+    let mut directories = vec![(root, 0)];
+    while let Some((directory, level)) = directories.pop() {
+        if level == depth || !first_visit(&directory, &mut visited) {
+            continue;
+        }
+        for path in read_sorted(&directory).unwrap_or_default() {
+            // The pattern names the files in the current directory without `./`.
+            let path = match path.strip_prefix(".") {
+                Ok(relative) if in_current_dir => relative.to_path_buf(),
+                _ => path,
+            };
+            if pattern.matches_path_with(&path, options) {
+                matches.push(path.clone());
+            }
+            if path.is_dir() && (follow_symlinks || !path.is_symlink()) {
+                directories.push((path, level + 1));
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        return Err(format!("{} matches no file.", arg));
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Creates the missing directories of `path`.
+///
+/// # Errors
+///
+/// Returns a message if one cannot be created.
+pub fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent)
+            .map_err(|msg| format!("Failed to create {}: {}", parent.display(), msg)),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the paths of the entries of `directory`, sorted.
+fn read_sorted(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Returns `true` the first time it is called for `directory` with the same `visited`.
+///
+/// Canonical paths tell whether a followed link leads to a directory that was visited
+/// already.
+fn first_visit(directory: &Path, visited: &mut Vec<PathBuf>) -> bool {
+    let Ok(canonical) = directory.canonicalize() else {
+        return true;
+    };
+    if visited.contains(&canonical) {
+        return false;
+    }
+    visited.push(canonical);
+    true
+}
+
+/// What became of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The output was written.
+    Done,
+    /// The file was left alone, for the given reason.
+    Skipped(String),
+    /// Working on the file failed with the given message.
+    Failed(String),
+    /// The size of the output was estimated, without writing it.
+    Estimated,
+}
+
+/// The result of the job of one file.
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub input: PathBuf,
+    /// The output that was written, if any.
+    pub output: Option<PathBuf>,
+    pub outcome: Outcome,
+    /// The size of the input in bytes.
+    pub input_len: u64,
+    /// The size of the output in bytes, or 0 without output.
+    pub output_len: u64,
+    /// The module steps that ran on the file, in the order they ran.
+    pub steps: Vec<String>,
+    /// The time the job of the file took.
+    pub elapsed: Duration,
+}
+
+impl FileResult {
+    /// Returns the result of a file whose output was written to `output`.
+    pub fn done(input: &Path, output: &Path) -> Self {
+        FileResult {
+            input: input.to_path_buf(),
+            output: Some(output.to_path_buf()),
+            outcome: Outcome::Done,
+            input_len: fs::metadata(input).map_or(0, |metadata| metadata.len()),
+            output_len: fs::metadata(output).map_or(0, |metadata| metadata.len()),
+            steps: Vec::new(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Sets the module steps that ran on the file.
+    pub fn with_steps(mut self, steps: Vec<String>) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Returns the result of a file that was left alone.
+    pub fn skipped(input: &Path, reason: impl Into<String>) -> Self {
+        Self::without_output(input, Outcome::Skipped(reason.into()))
+    }
+
+    /// Returns the result of a file that failed.
+    pub fn failed(input: &Path, msg: impl Into<String>) -> Self {
+        Self::without_output(input, Outcome::Failed(msg.into()))
+    }
+
+    fn without_output(input: &Path, outcome: Outcome) -> Self {
+        FileResult {
+            input: input.to_path_buf(),
+            output: None,
+            outcome,
+            input_len: fs::metadata(input)
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map_or(0, |metadata| metadata.len()),
+            output_len: 0,
+            steps: Vec::new(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Returns the result of an estimate of `output_len` bytes for `input`.
+    pub fn estimated(input: &Path, output_len: u64, steps: Vec<String>) -> Self {
+        FileResult {
+            output_len,
+            steps,
+            ..Self::without_output(input, Outcome::Estimated)
+        }
+    }
+
+    /// Returns the result as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        let (status, message) = match &self.outcome {
+            Outcome::Done => ("done", None),
+            Outcome::Estimated => ("estimated", None),
+            Outcome::Skipped(reason) => ("skipped", Some(reason.as_str())),
+            Outcome::Failed(msg) => ("failed", Some(msg.as_str())),
+        };
+        let json = JsonResult {
+            input: self.input.to_string_lossy(),
+            output: self.output.as_ref().map(|output| output.to_string_lossy()),
+            modules: &self.steps,
+            input_len: self.input_len,
+            output_len: self.output_len,
+            // Rounded to two decimals, like the table and `ls --json` show it.
+            ratio: (self.output.is_some() || self.outcome == Outcome::Estimated)
+                .then(|| (ratio(self.input_len, self.output_len) * 100.0).round() / 100.0),
+            duration_secs: self.elapsed.as_secs_f64(),
+            status,
+            message,
+        };
+        json::to_json_line(&json)
+    }
+}
+
+/// The JSON object of a [`FileResult`], written with `--json`.
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    input: Cow<'a, str>,
+    output: Option<Cow<'a, str>>,
+    modules: &'a [String],
+    input_len: u64,
+    output_len: u64,
+    ratio: Option<f64>,
+    duration_secs: f64,
+    status: &'static str,
+    message: Option<&'a str>,
+}
+
+/// Runs `work` on every file on `jobs` worker threads and returns the results in the
+/// order of `files`.
+///
+/// A file for which `work` returns an error counts as failed, and the error is logged
+/// with `verb` in front, like `Compress`. The files the `checkpoint` lists as finished are
+/// not worked on again, and the files that are done are added to it.
+pub fn run(
+    verb: &str,
+    files: &[PathBuf],
+    jobs: usize,
+    checkpoint: Option<&Checkpoint>,
+    work: impl Fn(&Path) -> Result<FileResult, String> + Sync,
+) -> Vec<FileResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; files.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = files.get(index) else {
+                        break;
+                    };
+                    // Once Ctrl-C was pressed, the files that did not start yet are left alone.
+                    let finished = checkpoint.and_then(|checkpoint| checkpoint.finished(input));
+                    let result = if let Some(output) = finished {
+                        FileResult::done(input, output)
+                    } else if cancel::is_cancelled() {
+                        FileResult::skipped(input, "Cancelled.")
+                    } else {
+                        let timer = Instant::now();
+                        let mut result = work(input).unwrap_or_else(|msg| {
+                            log_error!("{}: {}", verb, msg);
+                            FileResult::failed(input, msg)
+                        });
+                        result.elapsed = timer.elapsed();
+                        if let Some(checkpoint) = checkpoint
+                            && let Err(msg) = checkpoint.record(&result)
+                        {
+                            log_warn!("{}: Failed to update the checkpoint: {}", verb, msg);
+                        }
+                        result
+                    };
+                    results
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Prints `results`, with `verb` in front like `Compress`, and returns how the run ended,
+/// see [`status`].
+///
+/// A single file gets one line. Several files get a table, one row per file, and the
+/// totals of the run, which took `elapsed`. With `json`, the lines go to standard error,
+/// and standard output gets the JSON object of every result.
+pub fn finish(verb: &str, results: &mut [FileResult], elapsed: Duration, json: bool) -> ExitStatus {
+    results.sort_by(|a, b| a.input.cmp(&b.input));
+    let failed = count(results, |outcome| matches!(outcome, Outcome::Failed(_)));
+    if json {
+        for result in results.iter() {
+            println!("{}", result.to_json());
+        }
+    }
+    let say = |line: String| print(json, line);
+    let stream = stream(json);
+
+    if let [result] = results {
+        match &result.outcome {
+            Outcome::Done | Outcome::Estimated => {
+                say(format!("{}: {}", verb, describe(result, stream)))
+            }
+            Outcome::Skipped(reason) => say(format!(
+                "{}: Skipped {}: {}",
+                verb,
+                result.input.display(),
+                reason
+            )),
+            Outcome::Failed(_) => {}
+        }
+    } else {
+        say(format!(
+            "{:<8} {:>12} {:>12} {:>8}  File",
+            "Status", "Before", "After", "Ratio"
+        ));
+        for result in results.iter() {
+            let (status, reason) = match &result.outcome {
+                Outcome::Done | Outcome::Estimated => {
+                    let ratio = ratio(result.input_len, result.output_len);
+                    say(format!(
+                        "{} {:>12} {:>12} {}  {}",
+                        term::paint(stream, Style::Good, format!("{:<8}", "done")),
+                        result.input_len,
+                        result.output_len,
+                        term::ratio(stream, ratio, format!("{:>7.2}%", ratio)),
+                        describe_path(result)
+                    ));
+                    continue;
+                }
+                Outcome::Skipped(reason) => (term::paint(stream, Style::Warn, "skipped "), reason),
+                Outcome::Failed(msg) => (term::paint(stream, Style::Error, "failed  "), msg),
+            };
+            say(format!(
+                "{} {:>12} {:>12} {:>8}  {}: {}",
+                status,
+                result.input_len,
+                "-",
+                "-",
+                result.input.display(),
+                reason
+            ));
+        }
+
+        let done: Vec<_> = results
+            .iter()
+            .filter(|result| result.outcome == Outcome::Done)
+            .collect();
+        let input_len: u64 = done.iter().map(|result| result.input_len).sum();
+        let output_len: u64 = done.iter().map(|result| result.output_len).sum();
+        let ratio = ratio(input_len, output_len);
+        say(format!(
+            "{}: {} files, {} done, {} skipped, {} failed: {} -> {} bytes ({}) in {:.2} s",
+            verb,
+            results.len(),
+            done.len(),
+            count(results, |outcome| matches!(outcome, Outcome::Skipped(_))),
+            failed,
+            input_len,
+            output_len,
+            term::ratio(stream, ratio, format!("{:.2}%", ratio)),
+            elapsed.as_secs_f64()
+        ));
+    }
+
+    if failed == 0 && !cancel::is_cancelled() {
+        log_info!("{}: Success", verb);
+    }
+    status(results.len(), failed)
+}
+
+/// Returns how a run over `total` files ended, of which `failed` failed:
+/// [`ExitStatus::Partial`] if some of them failed and others did not, or the status the
+/// failures reported if all of them failed.
+pub fn status(total: usize, failed: usize) -> ExitStatus {
+    if cancel::is_cancelled() {
+        ExitStatus::Cancelled
+    } else if failed == 0 {
+        ExitStatus::Success
+    } else if failed < total {
+        ExitStatus::Partial
+    } else {
+        // Failures of the core that no one reported, like unreadable files, are I/O errors.
+        match exit_status::worst() {
+            ExitStatus::Success => ExitStatus::Io,
+            status => status,
+        }
+    }
+}
+
+/// Prints `line` to standard output, or to standard error if `json` keeps standard output
+/// for the results.
+pub fn print(json: bool, line: impl Display) {
+    if json {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+fn count(results: &[FileResult], filter: impl Fn(&Outcome) -> bool) -> usize {
+    results
+        .iter()
+        .filter(|result| filter(&result.outcome))
+        .count()
+}
+
+/// Returns the size of the output in percent of the size of the input.
+pub fn ratio(input_len: u64, output_len: u64) -> f64 {
+    output_len as f64 / input_len.max(1) as f64 * 100.0
+}
+
+/// Returns the stream [`print`] writes to.
+pub fn stream(json: bool) -> Stream {
+    if json { Stream::Stderr } else { Stream::Stdout }
+}
+
+/// Returns `input -> output` of a result.
+fn describe_path(result: &FileResult) -> String {
+    match &result.output {
+        Some(output) => format!("{} -> {}", result.input.display(), output.display()),
+        None => result.input.display().to_string(),
+    }
+}
+
+/// Returns the line of a single file that was done, to print to `stream`.
+fn describe(result: &FileResult, stream: Stream) -> String {
+    let ratio = ratio(result.input_len, result.output_len);
+    format!(
+        "{}: {} -> {} bytes ({})",
+        describe_path(result),
+        result.input_len,
+        result.output_len,
+        term::ratio(stream, ratio, format!("{:.2}%", ratio))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// A directory tree in the temporary directory of the system, removed when dropped.
+    struct Tree(PathBuf);
+
+    impl Tree {
+        /// Creates the empty files at the relative `paths` below a new directory.
+        fn new(name: &str, paths: &[&str]) -> Self {
+            let root = env::temp_dir().join(format!("purgepack-batch-{}-{}", process::id(), name));
+            for path in paths {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"").unwrap();
+            }
+            Tree(root)
+        }
+
+        fn path(&self, relative: &str) -> PathBuf {
+            self.0.join(relative)
+        }
+
+        /// Returns the arguments naming `relative` below the tree.
+        fn arg(&self, relative: &str) -> String {
+            self.path(relative).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Parses `args` with the configuration `config` and the variables `vars`.
+    fn parse(args: &[&str], config: &str, vars: &[(&str, &str)]) -> Result<Options, String> {
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+        let config = Config::parse(config).unwrap();
+        Options::parse_with(&args, "usage", &config, |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn expands_patterns_below_their_directory() {
+        let tree = Tree::new(
+            "expand",
+            &[
+                "a.txt",
+                "b.txt",
+                ".hidden.txt",
+                "c.csv",
+                "sub/d.txt",
+                "sub/deep/e.txt",
+            ],
+        );
+        let expanded = |pattern: &str| expand(&tree.arg(pattern), false);
+
+        assert_eq!(
+            expanded("*.txt").unwrap(),
+            [tree.path("a.txt"), tree.path("b.txt")]
+        );
+        assert_eq!(expanded("?.csv").unwrap(), [tree.path("c.csv")]);
+        assert_eq!(
+            expanded("**/*.txt").unwrap(),
+            [
+                tree.path("a.txt"),
+                tree.path("b.txt"),
+                tree.path("sub/d.txt"),
+                tree.path("sub/deep/e.txt")
+            ]
+        );
+        assert_eq!(
+            expanded("sub/*").unwrap(),
+            [tree.path("sub/d.txt"), tree.path("sub/deep")]
+        );
+        assert!(expanded("*.log").unwrap_err().contains("matches no file"));
+        assert!(expanded("[").unwrap_err().contains("Invalid pattern"));
+    }
+
+    #[test]
+    fn keeps_paths_that_are_no_pattern_or_exist() {
+        let tree = Tree::new("literal", &["[1].txt", "1.txt"]);
+        assert_eq!(
+            expand(&tree.arg("[1].txt"), false).unwrap(),
+            [tree.path("[1].txt")]
+        );
+        // A missing path is kept too; the caller reports it.
+        assert_eq!(
+            expand(&tree.arg("missing.txt"), false).unwrap(),
+            [tree.path("missing.txt")]
+        );
+    }
+
+    #[test]
+    fn parses_the_options() {
+        let tree = Tree::new("options", &["a.txt", "data/b.txt"]);
+        let (file, directory, out) = (tree.arg("a.txt"), tree.arg("data"), tree.arg("out"));
+
+        let options = parse(
+            &[
+                &file, &directory, "-O", &out, "--rm", "--suffix", "-L", "--json",
+            ],
+            "",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(options.inputs, [tree.path("a.txt"), tree.path("data")]);
+        assert_eq!(options.output_dir, Some(tree.path("out")));
+        assert!(options.remove_sources && options.follow_symlinks && options.json);
+        assert!(!options.resume);
+        assert_eq!(options.overwrite, Overwrite::Suffix);
+        assert_eq!(
+            options.place(Path::new("../data/b.txt")),
+            tree.path("out/data/b.txt")
+        );
+
+        let options = parse(&[&file, "-o", &out, "--force"], "", &[]).unwrap();
+        assert_eq!(options.output, Some(tree.path("out")));
+        assert_eq!(options.overwrite, Overwrite::Replace);
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let tree = Tree::new("invalid", &["a.txt", "b.txt"]);
+        let (a, b) = (tree.arg("a.txt"), tree.arg("b.txt"));
+        let error = |args: &[&str]| match parse(args, "", &[]) {
+            Ok(_) => panic!("{:?} was accepted", args),
+            Err(msg) => msg,
+        };
+
+        assert_eq!(error(&[]), "usage");
+        assert!(error(&[&a, "--fast"]).starts_with("Unexpected argument --fast."));
+        assert!(error(&[&a, "-j", "0"]).starts_with("-j expects a positive number."));
+        assert!(error(&[&a, "-o"]).starts_with("-o expects a path."));
+        assert!(error(&[&a, &b, "-o", "x"]).starts_with("-o names the output of a single file."));
+        assert!(error(&[&a, "-o", "x", "-O", "y"]).contains("cannot be used together"));
+        assert!(error(&[&tree.arg("c.txt")]).ends_with("is not a file or directory."));
+        assert!(error(&[&a, "--profile", "fast"]).contains("needs a purgepack.conf"));
+    }
+
+    #[test]
+    fn reports_an_input_that_does_not_exist_as_an_io_error() {
+        let tree = Tree::new("missing", &["a.txt"]);
+        exit_status::unreported(|| {
+            assert!(parse(&[&tree.arg("a.txt"), &tree.arg("b.txt")], "", &[]).is_err());
+            assert!(exit_status::worst() >= ExitStatus::Io);
+        });
+    }
+
+    #[test]
+    fn prefers_the_command_line_to_the_environment_to_the_configuration() {
+        let tree = Tree::new("precedence", &["a.txt"]);
+        let a = tree.arg("a.txt");
+        let config = "jobs = 2\nlevel = 5\nkeep = false\n[fast]\njobs = 6\n";
+        let env = [(overrides::JOBS, "3"), (overrides::LEVEL, "7")];
+
+        let options = parse(&[&a], config, &[]).unwrap();
+        assert_eq!((options.jobs, options.profile.level), (2, Some(5)));
+        assert!(options.remove_sources);
+        let options = parse(&[&a, "--profile", "fast"], config, &[]).unwrap();
+        assert_eq!(options.jobs, 6);
+
+        let options = parse(&[&a, "--profile", "fast"], config, &env).unwrap();
+        assert_eq!((options.jobs, options.profile.level), (3, Some(7)));
+
+        let options = parse(&[&a, "-j", "4", "--keep"], config, &env).unwrap();
+        assert_eq!(options.jobs, 4);
+        assert!(!options.remove_sources);
+
+        assert!(parse(&[&a], config, &[(overrides::JOBS, "0")]).is_err());
+        assert!(parse(&[&a], config, &[(overrides::LEVEL, "high")]).is_err());
+    }
+
+    #[test]
+    fn rounds_the_ratio_of_the_json_result_to_two_decimals() {
+        let mut result = FileResult::estimated(Path::new("a.txt"), 1000, Vec::new());
+        result.input_len = 3000;
+        assert!(result.to_json().contains(r#""ratio":33.33,"#));
+        result.input_len = 8;
+        assert!(result.to_json().contains(r#""ratio":12500.0,"#));
+        assert!(
+            FileResult::failed(Path::new("a.txt"), "gone")
+                .to_json()
+                .contains(r#""ratio":null,"#)
+        );
+    }
+}
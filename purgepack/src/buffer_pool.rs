@@ -0,0 +1,66 @@
+//! The buffer pool the core offers to the modules through `CoreH`.
+//!
+//! Modules run one after another in the same process, so a large buffer released by one
+//! module can be handed to the next instead of being freed and allocated again. Buffers
+//! are allocated in power-of-two sizes, which lets a released buffer serve any later
+//! request of about the same size.
+
+use shared_files::abi::RawBuffer;
+use std::sync::Mutex;
+
+/// Buffers smaller than this are not worth keeping and are allocated as requested.
+const MIN_POOLED_CAPACITY: usize = 64 * 1024;
+/// The largest number of bytes kept in the pool. Buffers released beyond it are freed.
+const MAX_POOLED_BYTES: usize = 1024 * 1024 * 1024;
+
+/// The released buffers, waiting to be acquired again.
+static POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// Returns an empty buffer with a capacity of at least `len` bytes, reusing a released
+/// buffer if one is large enough.
+pub fn acquire(len: usize) -> Vec<u8> {
+    if len < MIN_POOLED_CAPACITY {
+        return Vec::with_capacity(len);
+    }
+
+    let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+    let best = pool
+        .iter()
+        .enumerate()
+        .filter(|(_, buffer)| buffer.capacity() >= len)
+        .min_by_key(|(_, buffer)| buffer.capacity())
+        .map(|(i, _)| i);
+
+    match best {
+        Some(i) => pool.swap_remove(i),
+        None => Vec::with_capacity(len.checked_next_power_of_two().unwrap_or(len)),
+    }
+}
+
+/// Takes `buffer` back into the pool, or frees it if it is small or the pool is full.
+pub fn release(mut buffer: Vec<u8>) {
+    if buffer.capacity() < MIN_POOLED_CAPACITY {
+        return;
+    }
+
+    let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+    let pooled_bytes: usize = pool.iter().map(|buffer| buffer.capacity()).sum();
+    if pooled_bytes + buffer.capacity() > MAX_POOLED_BYTES {
+        return;
+    }
+
+    buffer.clear();
+    pool.push(buffer);
+}
+
+/// [`acquire`] for the modules, as `CoreH::acquire_buffer_f`.
+pub extern "C" fn acquire_raw(len: usize) -> RawBuffer {
+    RawBuffer::from_vec(acquire(len))
+}
+
+/// [`release`] for the modules, as `CoreH::release_buffer_f`.
+pub extern "C" fn release_raw(buffer: RawBuffer) {
+    // SAFETY: the modules only release buffers of `RawBuffer::from_vec`, and use the
+    // system allocator like the core.
+    release(unsafe { buffer.into_vec() })
+}
@@ -0,0 +1,51 @@
+//! Cancellation of the running modules on Ctrl-C.
+//!
+//! The first Ctrl-C sets a flag the modules poll through `CoreH`, so they can stop at the
+//! next chunk and remove their partial output. A second Ctrl-C ends the process at once.
+
+use std::{
+    io,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Whether the user asked to cancel the running operation.
+static CANCELLED: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+
+/// Returns `true` once the user pressed Ctrl-C.
+pub extern "C" fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+/// Installs the handler that sets the cancellation flag on SIGINT.
+#[cfg(unix)]
+pub fn install_handler() -> io::Result<()> {
+    use signal_hook::{consts::SIGINT, flag};
+
+    // Registered first, so it only sees the flag set by an earlier Ctrl-C.
+    flag::register_conditional_shutdown(SIGINT, 130, Arc::clone(&CANCELLED))?;
+    flag::register(SIGINT, Arc::clone(&CANCELLED))?;
+    Ok(())
+}
+
+/// Installs the handler that sets the cancellation flag on CTRL_C and CTRL_BREAK.
+#[cfg(windows)]
+pub fn install_handler() -> io::Result<()> {
+    use windows::Win32::System::Console::SetConsoleCtrlHandler;
+
+    unsafe { SetConsoleCtrlHandler(Some(console_ctrl_handler), true) }.map_err(io::Error::other)
+}
+
+/// Sets the cancellation flag on the first CTRL_C or CTRL_BREAK. Later events are passed
+/// on to the default handler, which ends the process.
+#[cfg(windows)]
+unsafe extern "system" fn console_ctrl_handler(ctrl_type: u32) -> windows::core::BOOL {
+    use windows::Win32::System::Console::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+        return false.into();
+    }
+    (!CANCELLED.swap(true, Ordering::Relaxed)).into()
+}
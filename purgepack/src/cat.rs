@@ -0,0 +1,117 @@
+//! The `cat` command, which writes the content of files to standard output.
+//!
+//! ```text
+//! purgepack cat <FILE>...
+//! ```
+//!
+//! PurgePack output is restored like `purgepack x` restores it, and other files are
+//! written as they are, so a mix of compressed and plain logs can be piped into `grep`
+//! in one go. The bytes are written unchanged, so binary content is safe to redirect.
+//!
+//! The modules restore from file to file, so the restored data passes through the
+//! temporary directory of the system and is removed as soon as it is written out; nothing
+//! is written next to the files. The log stays on standard error.
+//!
+//! A file that cannot be restored is skipped with an error, and the others are still
+//! written. A reader that stops early, like `head`, ends the command without an error.
+//! The command ends with the worst status of the files, like `purgepack x`.
+
+use crate::exit_status;
+use crate::extract;
+use crate::pipeline::{Command, TempFiles};
+use shared_files::core_header::CoreH;
+use shared_files::error::ExitStatus;
+use shared_files::log_error;
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "cat";
+
+/// The usage of the command.
+const USAGE: &str = "Usage: purgepack cat <FILE>...";
+
+/// The files to write out and the outcome of the command.
+pub struct CatRun {
+    files: Vec<PathBuf>,
+    exit_status: ExitStatus,
+}
+
+impl CatRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        let mut files = Vec::new();
+        for arg in args {
+            if arg.starts_with('-') {
+                return Err(format!("Unexpected argument {}. {}", arg, USAGE));
+            }
+            files.push(PathBuf::from(arg));
+        }
+        if files.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        Ok(CatRun {
+            files,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Returns the path of the content of `path`: the path itself, or the file
+    /// `temp_files` restored PurgePack output to.
+    fn content(core: &CoreH, path: &Path, temp_files: &mut TempFiles) -> Result<PathBuf, String> {
+        if !path.is_file() {
+            return Err(format!("{} is not a file.", path.display()));
+        }
+        if !extract::is_output(path) {
+            return Ok(path.to_path_buf());
+        }
+        let restored = temp_files.create();
+        extract::restore(core, path, &restored)?;
+        Ok(restored)
+    }
+}
+
+impl Command for CatRun {
+    fn run(&mut self, core: &CoreH) {
+        for path in &self.files {
+            let name = format!("purgepack-cat-{}", process::id());
+            let mut temp_files = TempFiles::new(&env::temp_dir().join(name));
+            let content = match Self::content(core, path, &mut temp_files) {
+                Ok(content) => content,
+                Err(msg) => {
+                    log_error!("Cat: {}", msg);
+                    exit_status::report(ExitStatus::Io);
+                    continue;
+                }
+            };
+
+            let mut out = io::stdout().lock();
+            let written = File::open(&content)
+                .and_then(|mut file| io::copy(&mut file, &mut out))
+                .and_then(|_| out.flush());
+            match written {
+                Ok(()) => {}
+                // The reader is gone, so nothing more can be written.
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+                Err(msg) => {
+                    log_error!("Cat: Failed to write {}: {}", path.display(), msg);
+                    exit_status::report(ExitStatus::Io);
+                }
+            }
+        }
+        self.exit_status = exit_status::worst();
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
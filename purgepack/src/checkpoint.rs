@@ -0,0 +1,131 @@
+//! Checkpoints of the commands working on many files, for `--resume`.
+//!
+//! While `compress` or `x` works on several files, it appends a line to a checkpoint file
+//! for every file it finished: the paths and sizes of the input and the output, and the
+//! CRC32 of the output. The file is `.purgepack-<COMMAND>.checkpoint` in the output
+//! directory, or in the current directory without `--output-dir`, and it is removed once
+//! the run ended without failures.
+//!
+//! After an interrupted run, the same command with `--resume` reads the checkpoint and
+//! does not work on a file again if its input still has the recorded size and its output
+//! still has the recorded size and CRC32. Without `--resume`, the checkpoint of an earlier
+//! run is discarded.
+
+use crate::batch::FileResult;
+use serde::{Deserialize, Serialize};
+use shared_files::checksum::{ChecksumReader, Crc32};
+use shared_files::log_info;
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A file finished by a run, as one line of JSON in the checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    input: PathBuf,
+    input_len: u64,
+    output: PathBuf,
+    output_len: u64,
+    crc32: u32,
+}
+
+/// The checkpoint of a running command.
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// The files finished by the interrupted run, by input, with `--resume`.
+    finished: HashMap<PathBuf, Entry>,
+}
+
+impl Checkpoint {
+    /// Returns the path of the checkpoint of `command` in `directory`.
+    pub fn path(directory: &Path, command: &str) -> PathBuf {
+        directory.join(format!(".purgepack-{}.checkpoint", command))
+    }
+
+    /// Opens the checkpoint at `path`. With `resume`, the files it lists are kept and new
+    /// ones are appended; otherwise it starts empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the checkpoint cannot be read or written.
+    pub fn open(path: &Path, resume: bool) -> io::Result<Self> {
+        let mut finished = HashMap::new();
+        if resume && path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // A line cut short when the run was killed is left out.
+                if let Ok(entry) = serde_json::from_str::<Entry>(&line?) {
+                    finished.insert(entry.input.clone(), entry);
+                }
+            }
+            log_info!(
+                "Resume: {} files were finished by the interrupted run",
+                finished.len()
+            );
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(path)?;
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            finished,
+        })
+    }
+
+    /// Returns the output of `input` if the interrupted run finished it and neither the
+    /// input nor the output changed since.
+    pub fn finished(&self, input: &Path) -> Option<&Path> {
+        let entry = self.finished.get(input)?;
+        let unchanged = fs::metadata(input).is_ok_and(|metadata| metadata.len() == entry.input_len)
+            && fs::metadata(&entry.output).is_ok_and(|metadata| metadata.len() == entry.output_len)
+            && crc32(&entry.output).is_ok_and(|crc| crc == entry.crc32);
+        unchanged.then_some(entry.output.as_path())
+    }
+
+    /// Records a file with a written output, so a resumed run skips it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output cannot be read or the checkpoint cannot be written.
+    pub fn record(&self, result: &FileResult) -> io::Result<()> {
+        let Some(output) = &result.output else {
+            return Ok(());
+        };
+        let entry = Entry {
+            input: result.input.clone(),
+            input_len: result.input_len,
+            output: output.clone(),
+            output_len: result.output_len,
+            crc32: crc32(output)?,
+        };
+        let line = serde_json::to_string(&entry).map_err(io::Error::other)?;
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        writeln!(file, "{}", line)?;
+        file.flush()
+    }
+
+    /// Removes the checkpoint, once the run ended without failures.
+    pub fn remove(self) {
+        drop(self.file);
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Returns the CRC32 of the file at `path`.
+fn crc32(path: &Path) -> io::Result<u32> {
+    let mut reader = ChecksumReader::new(File::open(path)?, Crc32::new());
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.hasher().value())
+}
@@ -0,0 +1,156 @@
+//! The `compare` command, which tells whether two files hold the same content.
+//!
+//! ```text
+//! purgepack compare <A> <B> [-n <N>] [--raw]
+//! ```
+//!
+//! PurgePack output is restored before it is compared, like `purgepack x` restores it, so
+//! a compressed file can be compared with its original, or two outputs written with other
+//! modules with each other. The restored data goes to the temporary directory of the
+//! system and is removed afterwards; nothing is written next to the files. With `--raw`,
+//! the bytes of the files are compared as they are.
+//!
+//! The first `N` differing offsets, 10 unless `-n` says otherwise, are printed with the
+//! bytes around them in hex (see [`shared_files::compare`]). The command ends with
+//! [`ExitStatus::Success`] if the contents are the same, and [`ExitStatus::Corrupt`] if
+//! they differ.
+
+use crate::exit_status;
+use crate::extract;
+use crate::pipeline::{Command, TempFiles};
+use shared_files::compare;
+use shared_files::core_header::CoreH;
+use shared_files::error::ExitStatus;
+use shared_files::log_error;
+use shared_files::term::Stream;
+use std::{
+    env,
+    fs::File,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "compare";
+
+/// The usage of the command.
+const USAGE: &str = "Usage: purgepack compare <A> <B> [-n <N>] [--raw]";
+
+/// The number of differing offsets printed without `-n`.
+const DEFAULT_LIMIT: usize = 10;
+
+/// The files to compare and the outcome of the command.
+pub struct CompareRun {
+    left: PathBuf,
+    right: PathBuf,
+    /// The number of differing offsets printed, from `-n`.
+    limit: usize,
+    /// Whether PurgePack output is compared as it is, from `--raw`.
+    raw: bool,
+    exit_status: ExitStatus,
+}
+
+impl CompareRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let mut limit = DEFAULT_LIMIT;
+        let mut raw = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-n" | "--limit" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => limit = n,
+                    None => return Err(format!("{} expects a number. {}", arg, USAGE)),
+                },
+                "--raw" => raw = true,
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unexpected argument {}. {}", arg, USAGE));
+                }
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [left, right] = <[PathBuf; 2]>::try_from(paths).map_err(|_| USAGE.to_string())?;
+        for path in [&left, &right] {
+            if !path.exists() {
+                exit_status::report(ExitStatus::Io);
+            }
+            if !path.is_file() {
+                return Err(format!("{} is not a file.", path.display()));
+            }
+        }
+        Ok(CompareRun {
+            left,
+            right,
+            limit,
+            raw,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Returns the path of the content of `path`: the path itself, or the file
+    /// `temp_files` restored PurgePack output to.
+    fn content(
+        &self,
+        core: &CoreH,
+        path: &Path,
+        temp_files: &mut TempFiles,
+    ) -> Result<PathBuf, String> {
+        if self.raw || !extract::is_output(path) {
+            return Ok(path.to_path_buf());
+        }
+        let restored = temp_files.create();
+        extract::restore(core, path, &restored)?;
+        Ok(restored)
+    }
+
+    /// Compares the contents and returns how the command ended, or why it failed.
+    fn compare(&self, core: &CoreH) -> Result<ExitStatus, String> {
+        let name = format!("purgepack-compare-{}", process::id());
+        let mut temp_files = TempFiles::new(&env::temp_dir().join(name));
+        let left = self.content(core, &self.left, &mut temp_files)?;
+        let right = self.content(core, &self.right, &mut temp_files)?;
+
+        let open = |path: &Path| {
+            File::open(path).map_err(|msg| format!("Failed to open {}: {}", path.display(), msg))
+        };
+        let comparison = compare::compare(open(&left)?, open(&right)?, self.limit)
+            .map_err(|msg| format!("Failed to compare the files: {}", msg))?;
+        println!(
+            "{}",
+            comparison.report(
+                &self.left.display().to_string(),
+                &self.right.display().to_string(),
+                Stream::Stdout
+            )
+        );
+        Ok(if comparison.equal() {
+            ExitStatus::Success
+        } else {
+            ExitStatus::Corrupt
+        })
+    }
+}
+
+impl Command for CompareRun {
+    fn run(&mut self, core: &CoreH) {
+        self.exit_status = self.compare(core).unwrap_or_else(|msg| {
+            log_error!("Compare: {}", msg);
+            // Failures of the core that no one reported, like unreadable files, are I/O
+            // errors.
+            match exit_status::worst() {
+                ExitStatus::Success => ExitStatus::Io,
+                status => status,
+            }
+        });
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
@@ -0,0 +1,286 @@
+//! The `compress` command, which picks the modules for a file itself.
+//!
+//! ```text
+//! purgepack compress <PATH>... [-o <OUTPUT>] [-O <DIR>] [--keep|--rm] [--force|--suffix]
+//!     [-j <N>] [--follow-symlinks] [--json] [--resume] [--profile <NAME>] [--verify]
+//!     [--estimate]
+//! ```
+//!
+//! The detect module classifies every file through its `detect` service, and the
+//! recommended pipeline (see [`pipeline`](crate::pipeline)) runs on it, so no `+module`
+//! argument groups are needed. The result is written to `<FILE>.ppcb`, or below the
+//! directory of `-O`, unless `-o` names another path. It starts with the [`Metadata`] of
+//! the pipeline that `purgepack x` restores it with, which also keeps the name and the
+//! permissions of the file. Files that are PurgePack output already, or that would not get
+//! smaller, like archives and media, are left alone, and an output that turns out larger
+//! than its input is removed again.
+//!
+//! The `modules` and `level` of the configuration replace the recommended pipeline and
+//! its levels; see [`config`](crate::config). With `--verify`, or `verify = true`, every
+//! output is restored and compared with its input, as it is before `--rm` removes one.
+//!
+//! The paths may be files, directories, which are compressed file by file, and glob
+//! patterns; see [`batch`](crate::batch). With `--estimate`, nothing is written, and a
+//! table of the sizes the outputs would have is printed instead; see
+//! [`estimate`](crate::estimate).
+
+use crate::batch::{self, FileResult, Options, Outcome};
+use crate::estimate;
+use crate::exit_status;
+use crate::extract;
+use crate::pipeline::{self, Command, Metadata, Report, TempFiles};
+use shared_files::core_header::{self, CoreH};
+use shared_files::error::ExitStatus;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "compress";
+
+/// The usage of the command.
+const USAGE: &str = concat!(
+    "Usage: purgepack compress <PATH>... [-o <OUTPUT>] [-O <DIR>] [--keep|--rm] ",
+    "[--force|--suffix] [-j <N>] [--follow-symlinks] [--json] [--resume] ",
+    "[--profile <NAME>] [--verify] [--estimate]"
+);
+
+/// The files to compress and the outcome of the command.
+pub struct CompressRun {
+    options: Options,
+    /// Whether the outputs are only estimated, from `--estimate`.
+    estimate: bool,
+    /// Whether every output is restored and compared with its input, from `--verify`.
+    verify: bool,
+    exit_status: ExitStatus,
+}
+
+impl CompressRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        let flag = |name: &str| args.iter().any(|arg| arg == name);
+        let (estimate, verify) = (flag("--estimate"), flag("--verify"));
+        let args: Vec<_> = args
+            .iter()
+            .filter(|arg| *arg != "--estimate" && *arg != "--verify")
+            .cloned()
+            .collect();
+        let options = Options::parse(&args, USAGE)?;
+        let verify = verify || options.profile.verify == Some(true);
+        if estimate && options.remove_sources {
+            return Err("--estimate writes no output, so --rm cannot be used with it.".to_string());
+        }
+        Ok(CompressRun {
+            options,
+            estimate,
+            verify,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Returns the path to write the output of `input` to.
+    fn output_path(&self, input: &Path) -> PathBuf {
+        if let Some(output) = &self.options.output {
+            return output.clone();
+        }
+        let mut name = self.options.place(input).into_os_string();
+        name.push(core_header::FILE_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    /// Compresses `input`, or returns why it failed.
+    fn compress(&self, core: &CoreH, input: &Path) -> Result<FileResult, String> {
+        let output = self
+            .options
+            .overwrite
+            .resolve(&self.output_path(input))
+            .map_err(|msg| msg.to_string())?;
+
+        let mode = pipeline::permissions(input);
+        let result = compress_file(core, &self.options, input, input, &output, mode)?;
+        if result.outcome != Outcome::Done {
+            return Ok(result);
+        }
+        if self.verify || self.options.remove_sources {
+            verify(core, input, &output)?;
+        }
+        if self.options.remove_sources {
+            self.options.remove_source(input, &output)?;
+        }
+        Ok(result)
+    }
+
+    /// Estimates the size of the output of `input`, or returns why it failed.
+    fn estimate(&self, core: &CoreH, input: &Path) -> Result<FileResult, String> {
+        let report = pipeline::detect(input, 0)?;
+        if let Some(reason) = skip_reason(&report) {
+            return Ok(FileResult::skipped(input, reason));
+        }
+        let steps = self.options.profile.steps(report.steps());
+        let output_len = estimate::estimate(core, input, &steps)?;
+        Ok(FileResult::estimated(input, output_len, steps))
+    }
+}
+
+/// Compresses the file at `input` to `output` with the pipeline the detect module
+/// recommends for it, or the one the profile of `options` sets.
+///
+/// `original` is the path the file is known by, which the messages name and whose file
+/// name the [`Metadata`] records along with `mode`. Returns a skipped result if the file
+/// is left alone or its output would be larger, which is removed again.
+///
+/// # Errors
+///
+/// Returns a message if the file cannot be classified or the pipeline fails.
+pub fn compress_file(
+    core: &CoreH,
+    options: &Options,
+    input: &Path,
+    original: &Path,
+    output: &Path,
+    mode: Option<u32>,
+) -> Result<FileResult, String> {
+    let report = pipeline::detect(input, 0)?;
+    if let Some(reason) = skip_reason(&report) {
+        return Ok(FileResult::skipped(input, reason));
+    }
+    let steps = options.profile.steps(report.steps());
+    options.print(format!(
+        "Compress: {}: {} ({}): {}",
+        original.display(),
+        report.value("class"),
+        report.value("detail"),
+        steps.join(" -> ")
+    ));
+
+    let metadata = Metadata {
+        name: original
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        mode,
+        steps: steps.clone(),
+    };
+    batch::create_parent(output)?;
+    pipeline::run(core, &steps, input, 0, output, Some(&metadata))?;
+
+    let result = FileResult::done(input, output).with_steps(steps);
+    if result.output_len > result.input_len {
+        fs::remove_file(output)
+            .map_err(|msg| format!("Failed to remove {}: {}", output.display(), msg))?;
+        return Ok(FileResult::skipped(
+            input,
+            format!(
+                "The output would grow it from {} to {} bytes.",
+                result.input_len, result.output_len
+            ),
+        ));
+    }
+    Ok(result)
+}
+
+/// Returns why a file with the detect `report` is left alone, or `None` if it is
+/// compressed.
+fn skip_reason(report: &Report) -> Option<&str> {
+    if report.value("class") == "purgepack" {
+        Some("PurgePack output already.")
+    } else if report.steps().is_empty() {
+        Some(report.value("note"))
+    } else {
+        None
+    }
+}
+
+impl Command for CompressRun {
+    fn run(&mut self, core: &CoreH) {
+        let timer = Instant::now();
+        let (files, mut results) = self.options.files(|_| true);
+        if self.estimate {
+            results.extend(batch::run(
+                "Estimate",
+                &files,
+                self.options.jobs,
+                None,
+                |input| self.estimate(core, input),
+            ));
+            self.exit_status = estimate::finish(&mut results, timer.elapsed(), self.options.json);
+            return;
+        }
+        let checkpoint = self.options.checkpoint(COMMAND, files.len());
+        results.extend(batch::run(
+            "Compress",
+            &files,
+            self.options.jobs,
+            checkpoint.as_ref(),
+            |input| self.compress(core, input),
+        ));
+        self.exit_status =
+            batch::finish("Compress", &mut results, timer.elapsed(), self.options.json);
+        if self.exit_status == ExitStatus::Success
+            && let Some(checkpoint) = checkpoint
+        {
+            checkpoint.remove();
+        }
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
+
+/// Restores `output` and checks that it equals `input`, before `input` is removed. An
+/// output that does not restore to its input is removed instead.
+fn verify(core: &CoreH, input: &Path, output: &Path) -> Result<(), String> {
+    let mut temp_files = TempFiles::new(output);
+    let restored = temp_files.create();
+    let checked = extract::restore(core, output, &restored).and_then(|_| {
+        same_content(input, &restored)
+            .map_err(|msg| format!("Failed to compare the restored data: {}", msg))
+    });
+    if checked == Ok(true) {
+        return Ok(());
+    }
+
+    let _ = fs::remove_file(output);
+    exit_status::report(ExitStatus::Corrupt);
+    Err(format!(
+        "{} was removed and {} kept, since the check of the output failed: {}",
+        output.display(),
+        input.display(),
+        checked
+            .err()
+            .as_deref()
+            .unwrap_or("The restored data differs.")
+    ))
+}
+
+/// Returns `true` if the files at `a` and `b` hold the same bytes.
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    loop {
+        let chunk = a.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(true);
+        }
+        let len = chunk.len();
+        let mut other = vec![0; len];
+        b.read_exact(&mut other)?;
+        if chunk != other.as_slice() {
+            return Ok(false);
+        }
+        a.consume(len);
+    }
+}
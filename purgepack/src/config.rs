@@ -0,0 +1,250 @@
+//! The configuration file and its profiles, selected with `--profile`.
+//!
+//! The core reads `purgepack.conf` from the current directory, next to the modules folder,
+//! or else from `purgepack/purgepack.conf` in the configuration directory of the user:
+//! `$XDG_CONFIG_HOME` or `~/.config`, and `%APPDATA%` on Windows. Without the file, the
+//! commands behave as if it were empty.
+//!
+//! The settings before the first `[name]` line apply to every run of `compress` and `x`.
+//! Every `[name]` line starts a profile, whose settings apply on top of them when
+//! `--profile <name>` is given. The options on the command line win over both, and so do
+//! the environment variables like `PURGEPACK_LEVEL`; see
+//! [`overrides`](shared_files::overrides).
+//!
+//! ```text
+//! # Used by every run.
+//! jobs = 4
+//!
+//! [fast]
+//! modules = +fast_lz_module compress
+//!
+//! [archive]
+//! level = 9
+//! verify = true
+//! keep = false
+//! ```
+//!
+//! The settings are:
+//!
+//! * `modules`: the pipeline `compress` runs instead of the recommended one, as steps
+//!   separated by `->`, like `+delta_module transform -> +range_coder_module compress`.
+//!   Only the codec modules offer pipeline steps: delta, transpose, bitpack, BWT, the
+//!   range coder and fast LZ.
+//! * `level`: the `--level` of every step that has one.
+//! * `verify`: with `true`, `compress` restores every output and compares it with its
+//!   input, like `--verify`. Outputs are always checked before `--rm` removes an input.
+//! * `keep`: with `false`, the inputs are removed, like `--rm`.
+//! * `jobs`: the number of files worked on at the same time, like `-j`.
+//!
+//! Empty lines and lines starting with `#` are ignored.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The file name of the configuration file.
+pub const FILE_NAME: &str = "purgepack.conf";
+
+/// Settings of the configuration file, each of them unset unless the file sets it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The steps `compress` runs instead of the recommended pipeline.
+    pub modules: Option<Vec<String>>,
+    /// The `--level` of the steps.
+    pub level: Option<u32>,
+    /// Whether every output of `compress` is checked.
+    pub verify: Option<bool>,
+    /// Whether the inputs are kept.
+    pub keep: Option<bool>,
+    /// The number of files worked on at the same time.
+    pub jobs: Option<usize>,
+}
+
+impl Profile {
+    /// Sets the settings `other` sets, keeping the others.
+    fn merge(&mut self, other: &Profile) {
+        if other.modules.is_some() {
+            self.modules.clone_from(&other.modules);
+        }
+        self.level = other.level.or(self.level);
+        self.verify = other.verify.or(self.verify);
+        self.keep = other.keep.or(self.keep);
+        self.jobs = other.jobs.or(self.jobs);
+    }
+
+    /// Returns the steps to run instead of the `recommended` ones: the `modules` of the
+    /// profile if it sets them, with the `--level` of every step set to its `level`.
+    pub fn steps(&self, recommended: Vec<String>) -> Vec<String> {
+        let steps = self.modules.clone().unwrap_or(recommended);
+        let Some(level) = self.level else {
+            return steps;
+        };
+        steps
+            .into_iter()
+            .map(|step| {
+                let mut words: Vec<_> = step.split_whitespace().map(str::to_string).collect();
+                if let Some(i) = words.iter().position(|word| word == "--level")
+                    && i + 1 < words.len()
+                {
+                    words[i + 1] = level.to_string();
+                }
+                words.join(" ")
+            })
+            .collect()
+    }
+}
+
+/// The configuration file: the settings for every run and the profiles.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The file the configuration was read from, if there is one.
+    path: Option<PathBuf>,
+    defaults: Profile,
+    profiles: HashMap<String, Profile>,
+}
+
+impl Config {
+    /// Reads the configuration file, or returns an empty configuration if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the file cannot be read or holds an invalid line.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = find() else {
+            return Ok(Config::default());
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|msg| format!("Failed to read {}: {}", path.display(), msg))?;
+        let mut config =
+            Config::parse(&text).map_err(|msg| format!("{}: {}", path.display(), msg))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// Parses the text of a configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the first invalid line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut profile = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |msg: String| format!("line {}: {}", number + 1, msg);
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(invalid("A profile needs a name.".to_string()));
+                }
+                config.profiles.entry(name.to_string()).or_default();
+                profile = Some(name.to_string());
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(format!("Expected key = value, not {}", line)));
+            };
+            let settings = match &profile {
+                Some(name) => config.profiles.get_mut(name).expect("added above"),
+                None => &mut config.defaults,
+            };
+            set(settings, key.trim(), value.trim()).map_err(invalid)?;
+        }
+        Ok(config)
+    }
+
+    /// Returns the settings for a run with the profile `name`, or without a profile.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if there is no profile `name`.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, String> {
+        let mut settings = self.defaults.clone();
+        if let Some(name) = name {
+            let Some(profile) = self.profiles.get(name) else {
+                let mut names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+                names.sort();
+                return Err(match &self.path {
+                    Some(path) if !names.is_empty() => format!(
+                        "{} has no profile {}. Its profiles are: {}",
+                        path.display(),
+                        name,
+                        names.join(", ")
+                    ),
+                    Some(path) => format!("{} has no profiles.", path.display()),
+                    None => format!(
+                        "The profile {} needs a {} configuration file.",
+                        name, FILE_NAME
+                    ),
+                });
+            };
+            settings.merge(profile);
+        }
+        Ok(settings)
+    }
+}
+
+/// Sets the setting `key` of `profile` to `value`.
+fn set(profile: &mut Profile, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "modules" => {
+            let steps: Vec<_> = value
+                .split("->")
+                .map(str::trim)
+                .filter(|step| !step.is_empty())
+                .map(str::to_string)
+                .collect();
+            if steps.is_empty() || steps.iter().any(|step| !step.starts_with('+')) {
+                return Err(format!(
+                    "modules expects steps like +range_coder_module compress, not {}",
+                    value
+                ));
+            }
+            profile.modules = Some(steps);
+        }
+        "level" => profile.level = Some(parse(key, value, "a number")?),
+        "verify" => profile.verify = Some(parse(key, value, "true or false")?),
+        "keep" => profile.keep = Some(parse(key, value, "true or false")?),
+        "jobs" => match parse(key, value, "a positive number")? {
+            0 => return Err("jobs expects a positive number, not 0".to_string()),
+            jobs => profile.jobs = Some(jobs),
+        },
+        _ => return Err(format!("Unknown setting {}", key)),
+    }
+    Ok(())
+}
+
+/// Parses the `value` of the setting `key`, which should be `expected`.
+fn parse<T: std::str::FromStr>(key: &str, value: &str, expected: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects {}, not {}", key, expected, value))
+}
+
+/// Returns the path of the configuration file, or `None` if there is none.
+fn find() -> Option<PathBuf> {
+    let local = PathBuf::from(FILE_NAME);
+    if local.is_file() {
+        return Some(local);
+    }
+    let user = user_dir()?.join("purgepack").join(FILE_NAME);
+    user.is_file().then_some(user)
+}
+
+/// Returns the configuration directory of the user.
+fn user_dir() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    if cfg!(windows) {
+        return var("APPDATA").map(PathBuf::from);
+    }
+    var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))
+}
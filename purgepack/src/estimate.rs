@@ -0,0 +1,165 @@
+//! The `--estimate` option of `compress`, a dry run predicting the size of the outputs.
+//!
+//! ```text
+//! purgepack compress --estimate <PATH>... [-j <N>] [--follow-symlinks] [--json]
+//! ```
+//!
+//! Every file is classified like `compress` does it, and the recommended pipeline runs on
+//! a sample of it: the whole file if it is small, or [`SAMPLE_CHUNKS`] chunks spread over
+//! it. The size of the compressed sample, scaled to the size of the file, is the estimate.
+//! The samples are compressed in the temporary directory of the system, so nothing is
+//! written next to the inputs.
+//!
+//! Data that changes along a file, like an archive with a text index at its end, can
+//! compress better or worse than its sample, so the table only tells what to expect.
+
+use crate::batch::{self, FileResult, Outcome};
+use crate::cancel;
+use crate::pipeline::{self, TempFiles};
+use shared_files::core_header::CoreH;
+use shared_files::error::ExitStatus;
+use shared_files::log_info;
+use shared_files::term::{self, Style};
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// The number of chunks sampled from a large file.
+pub const SAMPLE_CHUNKS: u64 = 4;
+
+/// The length of a sampled chunk.
+const CHUNK_LEN: u64 = 256 * 1024;
+
+/// Numbers the estimates, so jobs running at the same time use distinct temporary files.
+static NEXT_ESTIMATE: AtomicUsize = AtomicUsize::new(0);
+
+/// Estimates the size `steps` compress `input` to.
+///
+/// # Errors
+///
+/// Returns a message if the sample cannot be written or a step fails.
+pub fn estimate(core: &CoreH, input: &Path, steps: &[String]) -> Result<u64, String> {
+    let input_len = fs::metadata(input)
+        .map_err(|msg| format!("Failed to read {}: {}", input.display(), msg))?
+        .len();
+    let name = format!(
+        "purgepack-estimate-{}-{}",
+        process::id(),
+        NEXT_ESTIMATE.fetch_add(1, Ordering::Relaxed)
+    );
+    let mut temp_files = TempFiles::new(&env::temp_dir().join(name));
+
+    let (sample, sample_len) = if input_len <= SAMPLE_CHUNKS * CHUNK_LEN {
+        (input.to_path_buf(), input_len)
+    } else {
+        let sample = temp_files.create();
+        write_sample(input, input_len, &sample)
+            .map_err(|msg| format!("Failed to sample {}: {}", input.display(), msg))?;
+        (sample, SAMPLE_CHUNKS * CHUNK_LEN)
+    };
+
+    let output = temp_files.create();
+    pipeline::run(core, steps, &sample, 0, &output, None)?;
+    let output_len = fs::metadata(&output)
+        .map_err(|msg| format!("Failed to read {}: {}", output.display(), msg))?
+        .len();
+    Ok((output_len as f64 * input_len as f64 / sample_len.max(1) as f64).round() as u64)
+}
+
+/// Writes [`SAMPLE_CHUNKS`] chunks spread evenly over the `input_len` bytes of `input` to
+/// `sample`.
+fn write_sample(input: &Path, input_len: u64, sample: &Path) -> io::Result<()> {
+    let mut input = File::open(input)?;
+    let mut sample = File::create(sample)?;
+    let stride = (input_len - CHUNK_LEN) / (SAMPLE_CHUNKS - 1);
+    let mut chunk = vec![0; CHUNK_LEN as usize];
+    for index in 0..SAMPLE_CHUNKS {
+        input.seek(SeekFrom::Start(index * stride))?;
+        input.read_exact(&mut chunk)?;
+        sample.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+/// Prints the estimates in `results`, and their totals, and returns how the run ended.
+///
+/// Skipped files count with their own size in the totals, since `compress` leaves them
+/// alone. With `json`, the text goes to standard error and standard output gets the JSON
+/// object of every result, with the status `estimated`.
+pub fn finish(results: &mut [FileResult], elapsed: Duration, json: bool) -> ExitStatus {
+    results.sort_by(|a, b| a.input.cmp(&b.input));
+    if json {
+        for result in results.iter() {
+            println!("{}", result.to_json());
+        }
+    }
+    let say = |line: String| batch::print(json, line);
+    let stream = batch::stream(json);
+
+    say(format!(
+        "{:<9} {:>12} {:>12} {:>8}  File",
+        "Status", "Before", "Estimate", "Ratio"
+    ));
+    let (mut input_len, mut output_len, mut failed) = (0, 0, 0);
+    for result in results.iter() {
+        let (status, estimate, detail) = match &result.outcome {
+            Outcome::Estimated => (
+                term::paint(stream, Style::Good, format!("{:<9}", "estimated")),
+                result.output_len,
+                format!("{}: {}", result.input.display(), result.steps.join(" -> ")),
+            ),
+            Outcome::Skipped(reason) => (
+                term::paint(stream, Style::Warn, format!("{:<9}", "skipped")),
+                result.input_len,
+                format!("{}: {}", result.input.display(), reason),
+            ),
+            Outcome::Failed(msg) => {
+                failed += 1;
+                say(format!(
+                    "{} {:>12} {:>12} {:>8}  {}: {}",
+                    term::paint(stream, Style::Error, format!("{:<9}", "failed")),
+                    result.input_len,
+                    "-",
+                    "-",
+                    result.input.display(),
+                    msg
+                ));
+                continue;
+            }
+            // `compress --estimate` writes no output.
+            Outcome::Done => continue,
+        };
+        input_len += result.input_len;
+        output_len += estimate;
+        let ratio = batch::ratio(result.input_len, estimate);
+        say(format!(
+            "{} {:>12} {:>12} {}  {}",
+            status,
+            result.input_len,
+            estimate,
+            term::ratio(stream, ratio, format!("{:>7.2}%", ratio)),
+            detail
+        ));
+    }
+    let ratio = batch::ratio(input_len, output_len);
+    say(format!(
+        "Estimate: {} files: {} -> ~{} bytes ({}), saving ~{} bytes, in {:.2} s",
+        results.len(),
+        input_len,
+        output_len,
+        term::ratio(stream, ratio, format!("{:.2}%", ratio)),
+        input_len.saturating_sub(output_len),
+        elapsed.as_secs_f64()
+    ));
+
+    if failed == 0 && !cancel::is_cancelled() {
+        log_info!("Estimate: Success");
+    }
+    batch::status(results.len(), failed)
+}
@@ -0,0 +1,41 @@
+//! The event stream of `+core --events <PATH>`.
+//!
+//! Modules and the core write newline-delimited JSON events to it through `CoreH`, so
+//! wrappers can follow the progress while the human-readable output goes to stdout.
+
+use shared_files::{abi, log_warn};
+use std::{
+    ffi::c_char,
+    fs::{File, OpenOptions},
+    io::{self, LineWriter, Write},
+    sync::Mutex,
+};
+
+/// The file the events are written to, once `--events` opened it.
+static SINK: Mutex<Option<LineWriter<File>>> = Mutex::new(None);
+
+/// Opens `path` for the events. An existing file is appended to, so `/dev/fd/<N>` and
+/// named pipes work as well.
+pub fn open(path: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(LineWriter::new(file));
+    Ok(())
+}
+
+/// Writes `line` as one event. Does nothing without `--events`.
+pub fn emit(line: &str) {
+    let mut sink = SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(writer) = sink.as_mut()
+        && let Err(msg) = writeln!(writer, "{}", line)
+    {
+        // A reader that went away should not stop the compression.
+        log_warn!("Failed to write an event, disabling --events: {}", msg);
+        *sink = None;
+    }
+}
+
+/// [`emit`] for the modules, as `CoreH::emit_event_f`.
+pub extern "C" fn emit_raw(line: *const c_char) {
+    // SAFETY: the modules pass a C string, see `core_header::emit_event`.
+    emit(&unsafe { abi::from_c_str(line) })
+}
@@ -0,0 +1,44 @@
+//! The exit code of the process.
+//!
+//! The modules report their failures through `CoreH`, the core reports its own, and the
+//! process exits with the highest [`ExitStatus`] reported, so a script can tell a usage
+//! error from damaged data or a cancelled run.
+
+use shared_files::error::ExitStatus;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The highest exit code reported so far.
+static WORST: AtomicU8 = AtomicU8::new(0);
+
+/// Records that an operation failed with `status`.
+pub fn report(status: ExitStatus) {
+    WORST.fetch_max(status.code(), Ordering::Relaxed);
+}
+
+/// Records the [`ExitStatus`] code a module reported. Unknown codes count as I/O errors.
+pub extern "C" fn report_raw(status: u8) {
+    report(ExitStatus::from_code(status).unwrap_or(ExitStatus::Io));
+}
+
+/// Runs `f` and discards the statuses reported meanwhile.
+pub fn unreported(f: impl FnOnce()) {
+    let before = WORST.load(Ordering::Relaxed);
+    f();
+    WORST.store(before, Ordering::Relaxed);
+}
+
+/// Returns the highest status reported so far, or [`ExitStatus::Success`].
+pub fn worst() -> ExitStatus {
+    ExitStatus::from_code(WORST.load(Ordering::Relaxed)).unwrap_or(ExitStatus::Io)
+}
+
+/// Ends the process with the code of `status`.
+pub fn exit(status: ExitStatus) -> ! {
+    std::process::exit(status.code().into())
+}
+
+/// Ends the process with the code of `status`, or of the status reported so far if it is
+/// worse, like [`Io`](ExitStatus::Io) for an input that does not exist among the arguments.
+pub fn exit_at_least(status: ExitStatus) -> ! {
+    exit(worst().max(status))
+}
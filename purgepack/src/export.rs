@@ -0,0 +1,303 @@
+//! The `tar` command, which exports files and PurgePack outputs as a standard `tar`
+//! archive, so existing tools can read them.
+//!
+//! ```text
+//! purgepack tar <PATH>... [-o <ARCHIVE>] [--follow-symlinks]
+//! ```
+//!
+//! The paths may be files, directories, which are walked recursively, and glob patterns;
+//! see [`batch`](crate::batch). PurgePack output is restored like `purgepack x` restores
+//! it, and stored under the path `x` would restore it to, like `data/a.csv` for
+//! `data/a.csv.ppcb`, with the permissions its metadata recorded. Other files are stored
+//! as they are, like `purgepack cat` writes them, so a tree that `compress` left some
+//! files alone in comes out whole. Absolute paths and `..` are left out of the paths of
+//! the entries, like `tar` does. The directories in front of every entry are stored too,
+//! with the permissions of the directories they come from.
+//!
+//! The archive is POSIX ustar, see [`tar`](crate::tar), written to `-o` or to standard
+//! output, so it can be piped into `tar -x` or anything else that reads `tar`. The data
+//! is restored to the temporary directory of the system one file at a time, and removed
+//! once it is stored.
+//!
+//! A file that cannot be restored is left out with an error, and the others are still
+//! stored. The command ends with the worst status of the files, like `purgepack x`.
+//! `purgepack untar` is the way back; see [`import`](crate::import).
+
+use crate::batch::{Options, Outcome};
+use crate::exit_status;
+use crate::extract;
+use crate::list;
+use crate::pipeline::{self, Command, TempFiles};
+use crate::tar::TarWriter;
+use shared_files::core_header::CoreH;
+use shared_files::error::ExitStatus;
+use shared_files::output::SafeWriter;
+use shared_files::{log_error, log_info, log_warn};
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, IsTerminal, Write},
+    path::{Component, Path, PathBuf},
+    process,
+    time::UNIX_EPOCH,
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "tar";
+
+/// The usage of the command.
+const USAGE: &str = "Usage: purgepack tar <PATH>... [-o <ARCHIVE>] [--follow-symlinks]";
+
+/// The permissions of entries whose permissions are not known.
+const DEFAULT_MODE: u32 = 0o644;
+
+/// The permissions of directories whose permissions are not known.
+const DEFAULT_DIR_MODE: u32 = 0o755;
+
+/// The files to export and the outcome of the command.
+pub struct ExportRun {
+    options: Options,
+    /// The archive to write, from `-o`, or `None` for standard output.
+    archive: Option<PathBuf>,
+    exit_status: ExitStatus,
+}
+
+impl ExportRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        // `-o` names the archive, not the output of a single file, so it is not handed on.
+        let mut archive = None;
+        let mut listed = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => match args.next() {
+                    Some(path) => archive = Some(PathBuf::from(path)),
+                    None => return Err(format!("{} expects a path. {}", arg, USAGE)),
+                },
+                "-L" | "--follow-symlinks" => listed.push(arg.clone()),
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unexpected argument {}. {}", arg, USAGE));
+                }
+                _ => listed.push(arg.clone()),
+            }
+        }
+        if archive.is_none() && io::stdout().is_terminal() {
+            return Err(format!(
+                "A tar archive is not written to a terminal. Use -o or a pipe. {}",
+                USAGE
+            ));
+        }
+        Ok(ExportRun {
+            options: Options::parse(&listed, USAGE)?,
+            archive,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Writes the archive of `files` to `writer` and returns it, with the number of
+    /// entries stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive cannot be written. Files that cannot be read are
+    /// only logged.
+    fn export<W: Write>(
+        &self,
+        core: &CoreH,
+        files: &[PathBuf],
+        writer: W,
+    ) -> io::Result<(W, usize)> {
+        let mut tar = TarWriter::new(writer);
+        let mut directories = HashSet::new();
+        let mut entries = HashSet::new();
+        for path in files {
+            let is_output = extract::is_output(path);
+            let entry = if is_output {
+                relative(&list::entry_path(path))
+            } else {
+                relative(path)
+            };
+            // Files sort before their outputs, so a file that was kept wins over its output.
+            if !entries.insert(entry.clone()) {
+                log_warn!(
+                    "Tar: Skipped {}: {} is in the archive already.",
+                    path.display(),
+                    entry.display()
+                );
+                continue;
+            }
+
+            let name = format!("purgepack-tar-{}", process::id());
+            let mut temp_files = TempFiles::new(&env::temp_dir().join(name));
+            let file = match Self::content(core, path, is_output, &mut temp_files) {
+                Ok(file) => file,
+                Err(msg) => {
+                    log_error!("Tar: {}", msg);
+                    exit_status::report(ExitStatus::Io);
+                    entries.remove(&entry);
+                    continue;
+                }
+            };
+
+            add_directories(&mut tar, &entry, path, &mut directories)?;
+            let size = file.content.metadata()?.len();
+            tar.append_file(
+                &tar_path(&entry),
+                file.mode,
+                file.mtime,
+                size,
+                &mut BufReader::new(file.content),
+            )?;
+        }
+        Ok((tar.finish()?, entries.len()))
+    }
+
+    /// Returns the content of the file at `path`, restored by `temp_files` if it is
+    /// PurgePack output.
+    fn content(
+        core: &CoreH,
+        path: &Path,
+        is_output: bool,
+        temp_files: &mut TempFiles,
+    ) -> Result<Stored, String> {
+        let open = |content: &Path| {
+            File::open(content)
+                .map_err(|msg| format!("Failed to open {}: {}", content.display(), msg))
+        };
+        let mtime = modified(path);
+        if !is_output {
+            return Ok(Stored {
+                mode: pipeline::permissions(path).unwrap_or(DEFAULT_MODE),
+                mtime,
+                content: open(path)?,
+            });
+        }
+
+        let restored = temp_files.create();
+        extract::restore(core, path, &restored)?;
+        let (metadata, _) = extract::read_metadata(path)?;
+        Ok(Stored {
+            mode: metadata
+                .and_then(|metadata| metadata.mode)
+                .unwrap_or(DEFAULT_MODE),
+            mtime,
+            content: open(&restored)?,
+        })
+    }
+}
+
+impl Command for ExportRun {
+    fn run(&mut self, core: &CoreH) {
+        let (files, left_out) = self.options.files(|_| true);
+        for result in &left_out {
+            match &result.outcome {
+                Outcome::Failed(msg) => {
+                    log_error!("Tar: {}: {}", result.input.display(), msg);
+                    exit_status::report(ExitStatus::Io);
+                }
+                Outcome::Skipped(reason) => {
+                    log_warn!("Tar: Skipped {}: {}", result.input.display(), reason)
+                }
+                Outcome::Done | Outcome::Estimated => {}
+            }
+        }
+
+        let written = match &self.archive {
+            Some(archive) => SafeWriter::create(archive).and_then(|file| {
+                let (writer, stored) = self.export(core, &files, BufWriter::new(file))?;
+                writer.into_inner().map_err(|e| e.into_error())?.commit()?;
+                Ok(stored)
+            }),
+            None => self
+                .export(core, &files, io::stdout().lock())
+                .map(|(_, stored)| stored),
+        };
+        match written {
+            Ok(stored) => log_info!(
+                "Tar: {} of {} files stored in {}",
+                stored,
+                files.len(),
+                self.archive
+                    .as_deref()
+                    .map_or("standard output".into(), Path::to_string_lossy)
+            ),
+            Err(msg) => {
+                log_error!("Tar: Failed to write the archive: {}", msg);
+                exit_status::report(ExitStatus::Io);
+            }
+        }
+        self.exit_status = exit_status::worst();
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
+
+/// A file on its way into the archive.
+struct Stored {
+    mode: u32,
+    mtime: u64,
+    content: File,
+}
+
+/// Appends the directories in front of `entry` that are not in the archive yet, taking
+/// their permissions from the directories in front of `path`, where the entry comes from.
+fn add_directories<W: Write>(
+    tar: &mut TarWriter<W>,
+    entry: &Path,
+    path: &Path,
+    directories: &mut HashSet<PathBuf>,
+) -> io::Result<()> {
+    let mut source = path.parent();
+    let mut missing = Vec::new();
+    for directory in entry.ancestors().skip(1) {
+        // The directories are added outermost first, so the ones in front of a directory
+        // that was added were added too.
+        if directory.as_os_str().is_empty() || directories.contains(directory) {
+            break;
+        }
+        missing.push((directory, source));
+        source = source.and_then(Path::parent);
+    }
+    for (directory, source) in missing.into_iter().rev() {
+        let mode = source
+            .and_then(pipeline::permissions)
+            .unwrap_or(DEFAULT_DIR_MODE);
+        let mtime = source.map_or(0, modified);
+        tar.append_dir(&tar_path(directory), mode, mtime)?;
+        directories.insert(directory.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Returns `path` without its root and `..`, like `tar` stores it.
+fn relative(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+/// Returns `path` with `/` between the directories, as the archive names it.
+fn tar_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns the time of the last modification of `path` in seconds since the Unix epoch,
+/// or 0 if it is not known.
+fn modified(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
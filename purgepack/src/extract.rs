@@ -0,0 +1,270 @@
+//! The `x` command, which restores a file without being told how it was written.
+//!
+//! ```text
+//! purgepack x <PATH>... [-o <OUTPUT>] [-O <DIR>] [--keep|--rm] [--force|--suffix]
+//!     [-j <N>] [--follow-symlinks] [--json] [--resume] [--profile <NAME>]
+//! ```
+//!
+//! The frames of every file are read. The [`Metadata`] that `compress` writes in front of
+//! its output gives the original file name and the number of steps, and the header of
+//! every module frame tells which module wrote it. The detect module knows the inverse step
+//! of every module, so the frames are restored one after another, outermost first, until
+//! the data is no PurgePack output any more.
+//!
+//! Without `-o`, the result is written next to the input, or below the directory of `-O`,
+//! under the original name from the metadata or, for the output of a single module, under
+//! the name of the input without its `.ppcb` extension. It gets the permissions the
+//! metadata recorded for the original file.
+//!
+//! The paths may be files, directories and glob patterns; see [`batch`](crate::batch). Only
+//! the files inside a directory with the `.ppcb` extension are restored.
+
+use crate::batch::{self, FileResult, Options};
+use crate::exit_status;
+use crate::pipeline::{self, Command, Metadata, TempFiles};
+use shared_files::core_header::{self, CoreH};
+use shared_files::error::ExitStatus;
+use shared_files::frame::{self, Frame, FrameReader};
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "x";
+
+/// The usage of the command.
+const USAGE: &str = concat!(
+    "Usage: purgepack x <PATH>... [-o <OUTPUT>] [-O <DIR>] [--keep|--rm] ",
+    "[--force|--suffix] [-j <N>] [--follow-symlinks] [--json] [--resume] ",
+    "[--profile <NAME>]"
+);
+
+/// The most frames restored from one file, so data that keeps restoring to PurgePack
+/// output cannot keep the command busy forever.
+const MAX_LAYERS: usize = 16;
+
+/// The files to restore and the outcome of the command.
+pub struct ExtractRun {
+    options: Options,
+    exit_status: ExitStatus,
+}
+
+impl ExtractRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        Ok(ExtractRun {
+            options: Options::parse(args, USAGE)?,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Returns the path to restore `input` to.
+    fn output_path(&self, input: &Path, metadata: Option<&Metadata>) -> PathBuf {
+        if let Some(output) = &self.options.output {
+            return output.clone();
+        }
+
+        // Only the file name is used, so the metadata cannot point outside the directory.
+        let original_name = metadata.and_then(|metadata| Path::new(&metadata.name).file_name());
+        let next_to_input = if let Some(name) = original_name {
+            input.with_file_name(name)
+        } else if has_extension(input) {
+            input.with_extension("")
+        } else {
+            let mut name = input.to_path_buf().into_os_string();
+            name.push(".out");
+            PathBuf::from(name)
+        };
+        self.options.place(&next_to_input)
+    }
+
+    /// Restores `input`, or returns why it failed.
+    fn extract(&self, core: &CoreH, input: &Path) -> Result<FileResult, String> {
+        let (metadata, _) = read_metadata(input)?;
+        let output = self.output_path(input, metadata.as_ref());
+        if output == input {
+            exit_status::report(ExitStatus::Usage);
+            return Err(format!(
+                "{} would be replaced by its own content. Use -o to name another output.",
+                output.display()
+            ));
+        }
+        let output = self
+            .options
+            .overwrite
+            .resolve(&output)
+            .map_err(|msg| msg.to_string())?;
+
+        batch::create_parent(&output)?;
+        let frames = restore(core, input, &output)?;
+        let descriptions: Vec<_> = frames
+            .iter()
+            .map(|(detail, steps)| format!("{}: {}", detail, steps.join(" -> ")))
+            .collect();
+        self.options.print(format!(
+            "Extract: {}: {}",
+            input.display(),
+            descriptions.join(", then ")
+        ));
+        // The modules check the data they restore, so the output needs no further check.
+        let steps = frames.into_iter().flat_map(|(_, steps)| steps).collect();
+        let result = FileResult::done(input, &output).with_steps(steps);
+        self.options.remove_source(input, &output)?;
+        // The output is flushed before, which a read-only file would not allow.
+        if let Some(mode) = metadata.and_then(|metadata| metadata.mode) {
+            pipeline::set_permissions(&output, mode)?;
+        }
+        Ok(result)
+    }
+}
+
+impl Command for ExtractRun {
+    fn run(&mut self, core: &CoreH) {
+        let timer = Instant::now();
+        // Inside a directory, only the files with the extension of PurgePack files are
+        // restored.
+        let (files, mut results) = self.options.files(has_extension);
+        let checkpoint = self.options.checkpoint(COMMAND, files.len());
+        results.extend(batch::run(
+            "Extract",
+            &files,
+            self.options.jobs,
+            checkpoint.as_ref(),
+            |input| self.extract(core, input),
+        ));
+        self.exit_status =
+            batch::finish("Extract", &mut results, timer.elapsed(), self.options.json);
+        if self.exit_status == ExitStatus::Success
+            && let Some(checkpoint) = checkpoint
+        {
+            checkpoint.remove();
+        }
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
+
+/// Returns `true` if `path` has the extension of PurgePack files.
+pub fn has_extension(path: &Path) -> bool {
+    let extension = core_header::FILE_EXTENSION.trim_start_matches('.');
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Restores the PurgePack output at `input` to `output`.
+///
+/// Returns the detail and the steps of every frame that was restored, outermost first,
+/// like `BWT` and `+bwt_module decompress`.
+///
+/// # Errors
+///
+/// Returns a message if `input` is no PurgePack output or a step fails.
+pub fn restore(
+    core: &CoreH,
+    input: &Path,
+    output: &Path,
+) -> Result<Vec<(String, Vec<String>)>, String> {
+    let (metadata, offset) = read_metadata(input)?;
+
+    // The first layer is read behind the skippable frames, every other one from the start
+    // of the intermediate file the layer before wrote.
+    let mut temp_files = TempFiles::new(output);
+    let mut current = input.to_path_buf();
+    let mut offset = offset;
+
+    let expected_layers = metadata.as_ref().map(|metadata| metadata.steps.len());
+    let mut frames = Vec::new();
+    while expected_layers != Some(frames.len()) {
+        let report = pipeline::detect(&current, offset)?;
+        if report.value("class") != "purgepack" {
+            if expected_layers.is_some() || frames.is_empty() {
+                exit_status::report(ExitStatus::Corrupt);
+                return Err(format!(
+                    "{} is not PurgePack output, or it is corrupted.",
+                    input.display()
+                ));
+            }
+            break;
+        }
+
+        let steps = report.steps();
+        if steps.is_empty() {
+            exit_status::report(ExitStatus::Corrupt);
+            return Err(format!("{} cannot be restored.", report.value("detail")));
+        }
+        if frames.len() == MAX_LAYERS {
+            exit_status::report(ExitStatus::Corrupt);
+            return Err(format!(
+                "{} holds more than {} frames inside each other.",
+                input.display(),
+                MAX_LAYERS
+            ));
+        }
+
+        let layer_output = temp_files.create();
+        pipeline::run(core, &steps, &current, offset, &layer_output, None)?;
+        frames.push((report.value("detail").to_string(), steps));
+        current = layer_output;
+        offset = 0;
+    }
+
+    if current == input {
+        exit_status::report(ExitStatus::Corrupt);
+        return Err(format!("{} holds no frame to restore.", input.display()));
+    }
+    fs::rename(&current, output)
+        .map_err(|msg| format!("Failed to write {}: {}", output.display(), msg))?;
+    Ok(frames)
+}
+
+/// Returns `true` if the file at `path` is PurgePack output. Unlike a failed restore, a
+/// file that is not counts for nothing in the exit code.
+pub fn is_output(path: &Path) -> bool {
+    let mut output = false;
+    exit_status::unreported(|| output = read_metadata(path).is_ok());
+    output
+}
+
+/// Reads the skippable frames at the start of the file at `path`.
+///
+/// Returns the [`Metadata`] of the first skippable frame that holds it, and the offset of
+/// the first module frame.
+pub fn read_metadata(path: &Path) -> Result<(Option<Metadata>, u64), String> {
+    let file =
+        File::open(path).map_err(|msg| format!("Failed to open {}: {}", path.display(), msg))?;
+    let mut frames = FrameReader::new(BufReader::new(file));
+    let mut metadata = None;
+    let mut offset = 0;
+    loop {
+        match frames.next_frame() {
+            Ok(Some(Frame::Skippable(payload))) => {
+                offset += (frame::SKIPPABLE_MAGIC.len() + 4 + payload.len()) as u64;
+                if metadata.is_none() {
+                    metadata = Metadata::parse(&payload);
+                }
+            }
+            Ok(Some(Frame::Module(_))) => return Ok((metadata, offset)),
+            Ok(None) => {
+                exit_status::report(ExitStatus::Corrupt);
+                return Err(format!("{} holds no PurgePack frame.", path.display()));
+            }
+            Err(msg) => {
+                exit_status::report(ExitStatus::of(&msg));
+                return Err(format!(
+                    "{} is not PurgePack output: {}",
+                    path.display(),
+                    msg
+                ));
+            }
+        }
+    }
+}
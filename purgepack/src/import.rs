@@ -0,0 +1,253 @@
+//! The `untar` command, which imports a standard `tar` archive into PurgePack outputs.
+//!
+//! ```text
+//! purgepack untar <ARCHIVE> [-O <DIR>] [--force|--suffix] [--json] [--profile <NAME>]
+//! ```
+//!
+//! Every regular file of the archive is compressed like `purgepack compress` compresses
+//! it, to `<DIR>/<PATH>.ppcb`, where `PATH` is its path inside the archive, and its
+//! permissions are recorded in the metadata of the output, so `purgepack x` restores them.
+//! The files `compress` would leave alone, like media and PurgePack output, are written to
+//! `<DIR>/<PATH>` as they are. Without `-O`, the current directory is used.
+//!
+//! The directories of the archive are created with their permissions, which are applied
+//! once all files are written, so a read-only directory can still be filled. Links and
+//! other special entries are skipped, and an entry whose path is absolute or leaves the
+//! directory with `..` fails, so an archive cannot write anywhere else.
+//!
+//! The archive may be POSIX ustar, pax or GNU tar, see [`tar`](crate::tar), and is read
+//! from a file, one entry at a time: the content of every file passes through a temporary
+//! file next to its output, which is removed once it is compressed.
+//!
+//! Once the archive is read, a table of the results is printed, like `compress` prints it,
+//! and the command ends with the same statuses. A damaged archive counts as a failed entry,
+//! and the entries before the damage are kept. `purgepack tar` is the way back; see
+//! [`export`](crate::export).
+
+use crate::batch::{self, FileResult, Options, Outcome};
+use crate::cancel;
+use crate::compress;
+use crate::exit_status;
+use crate::pipeline::{self, Command, TempFiles};
+use crate::tar::{Entry, Kind, TarReader};
+use shared_files::core_header::{self, CoreH};
+use shared_files::error::ExitStatus;
+use shared_files::{log_error, log_warn};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "untar";
+
+/// The usage of the command.
+const USAGE: &str = concat!(
+    "Usage: purgepack untar <ARCHIVE> [-O <DIR>] [--force|--suffix] [--json] ",
+    "[--profile <NAME>]"
+);
+
+/// The archive to import and the outcome of the command.
+pub struct ImportRun {
+    /// The options, with the archive as the only input.
+    options: Options,
+    exit_status: ExitStatus,
+}
+
+impl ImportRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        // Only the options of `compress` that make sense for an archive are handed on.
+        let mut listed = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-f" | "--force" | "--suffix" | "--json" => listed.push(arg.clone()),
+                "-O" | "--output-dir" | "--profile" => {
+                    listed.push(arg.clone());
+                    listed.extend(args.next().cloned());
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unexpected argument {}. {}", arg, USAGE));
+                }
+                _ => listed.push(arg.clone()),
+            }
+        }
+        let options = Options::parse(&listed, USAGE)?;
+        if options.inputs.len() != 1 || !options.inputs[0].is_file() {
+            return Err(format!("Name one tar archive to import. {}", USAGE));
+        }
+        Ok(ImportRun {
+            options,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Returns the directory the archive is imported into.
+    fn directory(&self) -> &Path {
+        self.options.output_dir.as_deref().unwrap_or(Path::new("."))
+    }
+
+    /// Imports the current entry of `reader`, or returns why it failed.
+    ///
+    /// Directories get no result; they are created, and added to `directories` with their
+    /// permissions.
+    fn import(
+        &self,
+        core: &CoreH,
+        reader: &mut TarReader<impl Read>,
+        entry: &Entry,
+        directories: &mut Vec<(PathBuf, u32)>,
+    ) -> Result<Option<FileResult>, String> {
+        let shown = Path::new(&entry.path);
+        let Some(relative) = entry.relative_path() else {
+            exit_status::report(ExitStatus::Corrupt);
+            return Err(format!(
+                "{} would be written outside of {}.",
+                entry.path,
+                self.directory().display()
+            ));
+        };
+        let path = self.directory().join(relative);
+        match entry.kind {
+            Kind::File => {}
+            Kind::Directory => {
+                fs::create_dir_all(&path)
+                    .map_err(|msg| format!("Failed to create {}: {}", path.display(), msg))?;
+                directories.push((path, entry.mode));
+                return Ok(None);
+            }
+            Kind::Other(kind) => {
+                return Ok(Some(FileResult::skipped(
+                    shown,
+                    format!(
+                        "Only regular files and directories are imported, not {}.",
+                        describe(kind)
+                    ),
+                )));
+            }
+        }
+
+        let mut output = path.clone().into_os_string();
+        output.push(core_header::FILE_EXTENSION);
+        let output = self
+            .options
+            .overwrite
+            .resolve(Path::new(&output))
+            .map_err(|msg| msg.to_string())?;
+        batch::create_parent(&output)?;
+
+        let mut temp_files = TempFiles::new(&output);
+        let content = temp_files.create();
+        let mut writer = File::create(&content)
+            .map(BufWriter::new)
+            .map_err(|msg| format!("Failed to create {}: {}", content.display(), msg))?;
+        reader
+            .read_content(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|msg| {
+                exit_status::report(ExitStatus::of(&msg));
+                format!("Failed to read {}: {}", entry.path, msg)
+            })?;
+        drop(writer);
+
+        let mut result = compress::compress_file(
+            core,
+            &self.options,
+            &content,
+            shown,
+            &output,
+            Some(entry.mode),
+        )?;
+        if let Outcome::Skipped(reason) = &result.outcome {
+            // The file is kept as it is, like `compress` leaves it next to its outputs.
+            let kept = self
+                .options
+                .overwrite
+                .resolve(&path)
+                .map_err(|msg| msg.to_string())?;
+            fs::rename(&content, &kept)
+                .map_err(|msg| format!("Failed to write {}: {}", kept.display(), msg))?;
+            pipeline::set_permissions(&kept, entry.mode)?;
+            result = FileResult::skipped(&kept, format!("{} Written as it is.", reason));
+        }
+        result.input = shown.to_path_buf();
+        Ok(Some(result))
+    }
+}
+
+impl Command for ImportRun {
+    fn run(&mut self, core: &CoreH) {
+        let timer = Instant::now();
+        let archive = &self.options.inputs[0];
+        let mut reader = match File::open(archive) {
+            Ok(file) => TarReader::new(BufReader::new(file)),
+            Err(msg) => {
+                log_error!("Import: Failed to open {}: {}", archive.display(), msg);
+                self.exit_status = ExitStatus::Io;
+                return;
+            }
+        };
+
+        let mut results = Vec::new();
+        let mut directories = Vec::new();
+        while !cancel::is_cancelled() {
+            let entry = match reader.next_entry() {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(msg) => {
+                    exit_status::report(ExitStatus::of(&msg));
+                    log_error!("Import: Failed to read {}: {}", archive.display(), msg);
+                    results.push(FileResult::failed(archive, msg.to_string()));
+                    break;
+                }
+            };
+
+            let started = Instant::now();
+            match self.import(core, &mut reader, &entry, &mut directories) {
+                Ok(Some(mut result)) => {
+                    result.elapsed = started.elapsed();
+                    results.push(result);
+                }
+                Ok(None) => {}
+                Err(msg) => {
+                    log_error!("Import: {}", msg);
+                    let mut result = FileResult::failed(Path::new(&entry.path), msg);
+                    result.input_len = entry.size;
+                    results.push(result);
+                }
+            }
+        }
+
+        // The innermost directories come last, and their permissions are applied first,
+        // so a read-only directory does not keep the ones inside it from changing.
+        for (directory, mode) in directories.iter().rev() {
+            if let Err(msg) = pipeline::set_permissions(directory, *mode) {
+                log_warn!("Import: {}", msg);
+            }
+        }
+        self.exit_status =
+            batch::finish("Import", &mut results, timer.elapsed(), self.options.json);
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
+
+/// Returns what an entry with the type flag `kind` is, like `a symbolic link`.
+fn describe(kind: u8) -> String {
+    match kind {
+        b'1' => "a hard link".to_string(),
+        b'2' => "a symbolic link".to_string(),
+        b'3' | b'4' => "a device".to_string(),
+        b'6' => "a FIFO".to_string(),
+        _ => format!("an entry of type {}", char::from(kind)),
+    }
+}
@@ -0,0 +1,325 @@
+//! The `ls` command, which lists what PurgePack outputs hold.
+//!
+//! ```text
+//! purgepack ls <PATH>... [--long] [--json] [-j <N>] [--follow-symlinks]
+//! ```
+//!
+//! PurgePack has no container of several files: every output of `compress` holds one file,
+//! so the outputs work as the entries of an archive, and a directory of them as the
+//! archive. `purgepack tar` turns such a directory into a standard `tar` archive; see
+//! [`export`](crate::export). The paths may be files, directories and glob patterns, like for `x`; see
+//! [`batch`](crate::batch). Inside a directory, only the files with the `.ppcb` extension
+//! are listed.
+//!
+//! Every entry gets a row: the size of the original file, the size of the output, the
+//! CRC32 of the original content, the codecs of the frames, outermost first, and the path
+//! the entry is restored to by `x`. The outputs keep neither the size nor the checksum of
+//! their content, so every entry is restored to the temporary directory of the system to
+//! measure it, and removed again; nothing is written next to the outputs.
+//!
+//! `--long` adds the ratio, the output holding the entry and the steps that restore every
+//! frame. With `--json`, standard output gets one JSON object per entry instead:
+//!
+//! ```text
+//! {"path":"logs/a.txt","archive":"logs/a.txt.ppcb","size":192259,"compressed_size":40203,
+//!  "ratio":20.91,"crc32":"81105a5d","codecs":["Range Coder"],
+//!  "steps":["+range_coder_module decompress"]}
+//! ```
+//!
+//! (One line per entry; wrapped here.)
+
+use crate::batch::{self, FileResult, Options, Outcome};
+use crate::extract;
+use crate::pipeline::{Command, TempFiles};
+use serde::Serialize;
+use shared_files::checksum::{ChecksumReader, Crc32};
+use shared_files::core_header::CoreH;
+use shared_files::error::ExitStatus;
+use shared_files::json;
+use shared_files::term;
+use std::{
+    borrow::Cow,
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "ls";
+
+/// The usage of the command.
+const USAGE: &str = concat!(
+    "Usage: purgepack ls <PATH>... [--long] [--json] [-j <N>] ",
+    "[--follow-symlinks]"
+);
+
+/// Numbers the restores, so jobs running at the same time use distinct temporary files.
+static NEXT_ENTRY: AtomicUsize = AtomicUsize::new(0);
+
+/// A file held by a PurgePack output.
+#[derive(Debug, Clone)]
+struct Entry {
+    /// The path `x` restores the entry to.
+    path: PathBuf,
+    /// The output holding the entry.
+    archive: PathBuf,
+    /// The size of the original file.
+    size: u64,
+    /// The size of the output.
+    compressed_size: u64,
+    /// The CRC32 of the original content.
+    crc32: u32,
+    /// The detail and the restoring steps of every frame, outermost first.
+    frames: Vec<(String, Vec<String>)>,
+}
+
+impl Entry {
+    /// Returns the codecs of the frames, outermost first, like `BWT` for the detail
+    /// `PurgePack Compressed Binary written by BWT`.
+    fn codecs(&self) -> Vec<&str> {
+        self.frames
+            .iter()
+            .map(|(detail, _)| {
+                detail
+                    .rsplit_once(" written by ")
+                    .map_or(detail.as_str(), |(_, module)| module)
+            })
+            .collect()
+    }
+
+    /// Returns the entry as a single line of JSON.
+    fn to_json(&self) -> String {
+        let json = JsonEntry {
+            path: self.path.to_string_lossy(),
+            archive: self.archive.to_string_lossy(),
+            size: self.size,
+            compressed_size: self.compressed_size,
+            ratio: (batch::ratio(self.size, self.compressed_size) * 100.0).round() / 100.0,
+            crc32: format!("{:08x}", self.crc32),
+            codecs: self.codecs(),
+            steps: self
+                .frames
+                .iter()
+                .flat_map(|(_, steps)| steps.iter().map(String::as_str))
+                .collect(),
+        };
+        json::to_json_line(&json)
+    }
+}
+
+/// The JSON object of an [`Entry`], written with `--json`.
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    path: Cow<'a, str>,
+    archive: Cow<'a, str>,
+    size: u64,
+    compressed_size: u64,
+    ratio: f64,
+    crc32: String,
+    codecs: Vec<&'a str>,
+    steps: Vec<&'a str>,
+}
+
+/// The outputs to list and the outcome of the command.
+pub struct ListRun {
+    options: Options,
+    /// Whether the ratio, the output and the steps are listed too, from `--long`.
+    long: bool,
+    exit_status: ExitStatus,
+}
+
+impl ListRun {
+    /// Parses the arguments following the name of the command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the arguments are invalid.
+    pub fn new(args: &[String]) -> Result<Self, String> {
+        // Only the options of `x` that make sense for a listing are handed on.
+        let mut long = false;
+        let mut listed = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-l" | "--long" => long = true,
+                "--json" | "-L" | "--follow-symlinks" => listed.push(arg.clone()),
+                "-j" | "--jobs" => {
+                    listed.push(arg.clone());
+                    listed.extend(args.next().cloned());
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unexpected argument {}. {}", arg, USAGE));
+                }
+                _ => listed.push(arg.clone()),
+            }
+        }
+        Ok(ListRun {
+            options: Options::parse(&listed, USAGE)?,
+            long,
+            exit_status: ExitStatus::Success,
+        })
+    }
+
+    /// Restores `archive` to a temporary file and returns its entry.
+    fn entry(core: &CoreH, archive: &Path) -> Result<Entry, String> {
+        let name = format!(
+            "purgepack-ls-{}-{}",
+            process::id(),
+            NEXT_ENTRY.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut temp_files = TempFiles::new(&env::temp_dir().join(name));
+        let content = temp_files.create();
+        let frames = extract::restore(core, archive, &content)?;
+
+        let mut reader = ChecksumReader::new(
+            File::open(&content)
+                .map_err(|msg| format!("Failed to open {}: {}", content.display(), msg))?,
+            Crc32::new(),
+        );
+        let size = io::copy(&mut reader, &mut io::sink())
+            .map_err(|msg| format!("Failed to read {}: {}", content.display(), msg))?;
+        let compressed_size = fs::metadata(archive)
+            .map_err(|msg| format!("Failed to read {}: {}", archive.display(), msg))?
+            .len();
+
+        Ok(Entry {
+            path: entry_path(archive),
+            archive: archive.to_path_buf(),
+            size,
+            compressed_size,
+            crc32: reader.hasher().value(),
+            frames,
+        })
+    }
+
+    /// Prints `entries` as a table, or as JSON with `--json`.
+    fn print(&self, entries: &[Entry]) {
+        if self.options.json {
+            for entry in entries {
+                println!("{}", entry.to_json());
+            }
+            return;
+        }
+
+        let stream = batch::stream(false);
+        let ratio_header = if self.long { "    Ratio" } else { "" };
+        let codecs: Vec<String> = entries
+            .iter()
+            .map(|entry| entry.codecs().join(" + "))
+            .collect();
+        // The column is as wide as its widest value, so long chains of codecs stay aligned.
+        let codec_width = codecs
+            .iter()
+            .map(|codecs| codecs.chars().count())
+            .fold("Codec".len(), usize::max);
+        println!(
+            "{:>12} {:>12}{}  {:<8}  {:<codec_width$}  Path",
+            "Size", "Compressed", ratio_header, "CRC32", "Codec"
+        );
+        for (entry, codecs) in entries.iter().zip(&codecs) {
+            let ratio = if self.long {
+                let ratio = batch::ratio(entry.size, entry.compressed_size);
+                format!(
+                    " {}",
+                    term::ratio(stream, ratio, format!("{:>7.2}%", ratio))
+                )
+            } else {
+                String::new()
+            };
+            println!(
+                "{:>12} {:>12}{}  {:08x}  {:<codec_width$}  {}",
+                entry.size,
+                entry.compressed_size,
+                ratio,
+                entry.crc32,
+                codecs,
+                entry.path.display()
+            );
+            if self.long {
+                println!("    in {}", entry.archive.display());
+                for (i, (detail, steps)) in entry.frames.iter().enumerate() {
+                    println!("    {}. {}: {}", i + 1, detail, steps.join(" -> "));
+                }
+            }
+        }
+    }
+}
+
+impl Command for ListRun {
+    fn run(&mut self, core: &CoreH) {
+        let (files, mut results) = self.options.files(extract::has_extension);
+        let entries = Mutex::new(Vec::new());
+        results.extend(batch::run(
+            "List",
+            &files,
+            self.options.jobs,
+            None,
+            |archive| {
+                let entry = Self::entry(core, archive)?;
+                entries
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push(entry);
+                Ok(FileResult::done(archive, archive))
+            },
+        ));
+
+        let mut entries = entries
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries.sort_by(|a, b| a.archive.cmp(&b.archive));
+        self.print(&entries);
+
+        let failed = results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Failed(_)))
+            .count();
+        if entries.len() > 1 || failed > 0 {
+            let size: u64 = entries.iter().map(|entry| entry.size).sum();
+            let compressed_size: u64 = entries.iter().map(|entry| entry.compressed_size).sum();
+            let ratio = batch::ratio(size, compressed_size);
+            batch::print(
+                self.options.json,
+                format!(
+                    "List: {} entries, {} failed: {} -> {} bytes ({})",
+                    entries.len(),
+                    failed,
+                    size,
+                    compressed_size,
+                    term::ratio(
+                        batch::stream(self.options.json),
+                        ratio,
+                        format!("{:.2}%", ratio)
+                    )
+                ),
+            );
+        }
+        self.exit_status = batch::status(results.len(), failed);
+    }
+
+    fn exit_status(&self) -> ExitStatus {
+        self.exit_status
+    }
+}
+
+/// Returns the path `x` restores the output at `archive` to: the original name from its
+/// metadata, or its own name without the `.ppcb` extension.
+pub fn entry_path(archive: &Path) -> PathBuf {
+    let metadata = extract::read_metadata(archive)
+        .ok()
+        .and_then(|(metadata, _)| metadata);
+    // Only the file name is used, like `x` does, so the metadata cannot point elsewhere.
+    match metadata
+        .as_ref()
+        .and_then(|metadata| Path::new(&metadata.name).file_name())
+    {
+        Some(name) => archive.with_file_name(name),
+        None => archive.with_extension(""),
+    }
+}
@@ -1,12 +1,43 @@
 use core::fmt;
-use std::env::{args};
+use std::env::{self, args};
 use std::{error::Error};
 use std::{collections::HashMap, path::PathBuf};
+use std::time::Instant;
 #[cfg(target_os = "linux")]
 use libloading::Library;
 #[cfg(target_os = "linux")]
 use libloading::Symbol;
+use shared_files::abi::{self, CArgs};
+use shared_files::budget;
 use shared_files::core_header;
+use shared_files::error::ExitStatus;
+use shared_files::events::Event;
+use shared_files::log::{self, LogLevel};
+use shared_files::overrides;
+use shared_files::term;
+use shared_files::selftest::SelfTestFn;
+use shared_files::{log_error, log_info, log_warn};
+mod batch;
+mod buffer_pool;
+mod cancel;
+mod cat;
+mod checkpoint;
+mod compare;
+mod compress;
+mod config;
+mod estimate;
+mod events;
+mod exit_status;
+mod export;
+mod extract;
+mod import;
+mod list;
+mod pipeline;
+mod selftest;
+mod service_registry;
+mod stats_diff;
+mod tar;
+mod versions;
 #[cfg(target_os = "windows")]
 use windows::{
     Win32::{
@@ -44,6 +75,8 @@ impl Error for ModuleError {}
 fn load_modules_windows(
     core: &core_header::CoreH,
     seperated_args: &HashMap<String, Vec<String>>,
+    selftest: &mut Option<selftest::SelfTestRun>,
+    command: &mut Option<Box<dyn pipeline::Command>>,
 ) -> Result<HashMap<PathBuf, HMODULE>, ModuleError> {
     use std::{collections::HashMap, fs, path::PathBuf};
 
@@ -120,6 +153,8 @@ fn load_modules_windows(
     let mut failed_modules: usize = 0;
     let mut dll_table: HashMap<PathBuf, HMODULE> = HashMap::new();
 
+    let mut libraries: Vec<(PathBuf, HMODULE)> = Vec::new();
+
     for module in dll_name.iter().enumerate() {
         unsafe {
             let handle;
@@ -130,14 +165,36 @@ fn load_modules_windows(
                 }
                 Err(msg) => {
                     failed_modules += 1;
-                    println!("Failed to load library!: {}", msg);
+                    log_error!("Failed to load library!: {}", msg);
                     continue;
                 }
             }
 
             if handle.is_invalid() {
                 failed_modules += 1;
-                println!("Failed to load library!");
+                log_error!("Failed to load library!");
+                continue;
+            }
+
+            let func_name_c =
+                std::ffi::CString::new("module_abi_version").expect("CString::new failed");
+            let func_ptr = GetProcAddress(handle, PCSTR(func_name_c.as_ptr() as *const u8));
+
+            let Some(func) = func_ptr else {
+                failed_modules += 1;
+                log_error!("{:?} does not export module_abi_version, it was built for an older core!",
+                    readable_dll_path[module.0]);
+                let _ = FreeLibrary(handle);
+                continue;
+            };
+
+            let version_fn: abi::AbiVersionFn = std::mem::transmute(func);
+            let version = version_fn();
+            if version != abi::ABI_VERSION {
+                failed_modules += 1;
+                log_error!("{:?} was built for module ABI version {}, but the core needs version {}!",
+                    readable_dll_path[module.0], version, abi::ABI_VERSION);
+                let _ = FreeLibrary(handle);
                 continue;
             }
 
@@ -147,32 +204,86 @@ fn load_modules_windows(
 
             if func_ptr.is_none() {
                 failed_modules += 1;
-                println!("Did not find startup function!");
+                log_error!("Did not find startup function!");
+                let _ = FreeLibrary(handle);
                 continue;
             }
 
-            let startup_fn: extern "C" fn(core: &core_header::CoreH, args: &mut Vec<String>) =
-                std::mem::transmute(func_ptr);
-            let mut module_args: Vec<String>;
+            libraries.push((readable_dll_path[module.0].clone(), handle));
+        }
+    }
 
-            let module_name = format!("+{}", readable_dll_path[module.0].file_stem().unwrap()
-                .to_str().unwrap());
+    // Every module registers its services before any module starts, so a module can use
+    // the services of the modules loaded after it.
+    for (_module_path, handle) in libraries.iter() {
+        unsafe {
+            let func_name_c =
+                std::ffi::CString::new("module_register").expect("CString::new failed");
+            let func_ptr = GetProcAddress(*handle, PCSTR(func_name_c.as_ptr() as *const u8));
 
-            if let Some(args) = seperated_args.get(&module_name) {
-                module_args = args.clone();
+            if func_ptr.is_none() {
+                continue;
             }
-            else {
-                module_args = Vec::new();
+
+            let register_fn: abi::RegisterFn = std::mem::transmute(func_ptr);
+
+            register_fn(core);
+        }
+    }
+
+    for (module_path, handle) in libraries {
+        unsafe {
+            if let Some(selftest) = selftest.as_mut() {
+                let func_name_c =
+                    std::ffi::CString::new("module_selftest").expect("CString::new failed");
+                let func_ptr = GetProcAddress(handle, PCSTR(func_name_c.as_ptr() as *const u8));
+                let selftest_fn: Option<SelfTestFn> = func_ptr.map(|func| std::mem::transmute(func));
+
+                let module_name = format!("+{}", module_path.file_stem().unwrap()
+                    .to_str().unwrap());
+                selftest.run_module(&module_name, selftest_fn, core);
+
+                dll_table.insert(module_path, handle);
+                continue;
             }
 
-            startup_fn(&core, &mut module_args);
+            let func_name_c =
+                std::ffi::CString::new("module_startup").expect("CString::new failed");
+            let func_ptr = GetProcAddress(handle, PCSTR(func_name_c.as_ptr() as *const u8));
+
+            let startup_fn: abi::StartupFn = std::mem::transmute(func_ptr);
+
+            let module_name = format!("+{}", module_path.file_stem().unwrap()
+                .to_str().unwrap());
+
+            // The command runs the modules itself, once all of them are loaded.
+            if command.is_some() {
+                dll_table.insert(module_path, handle);
+                continue;
+            }
 
-            dll_table.insert(readable_dll_path[module.0].clone(), handle);
+            let module_args = CArgs::new(seperated_args.get(&module_name).map(Vec::as_slice)
+                .unwrap_or_default());
+
+            // A module that was not named on the command line is started without arguments,
+            // so its failure does not count for the exit code.
+            if seperated_args.contains_key(&module_name) {
+                run_module(&module_name, || startup_fn(core, module_args.argc(), module_args.argv()));
+            } else {
+                exit_status::unreported(|| run_module(&module_name,
+                    || startup_fn(core, module_args.argc(), module_args.argv())));
+            }
+
+            dll_table.insert(module_path, handle);
         }
     }
 
+    if let Some(command) = command.as_mut() {
+        command.run(core);
+    }
+
     if failed_modules > 0 {
-        println!("Failed to load {} module(s)!", failed_modules);
+        log_warn!("Failed to load {} module(s)!", failed_modules);
     }
 
     return Ok(dll_table.clone());
@@ -182,6 +293,8 @@ fn load_modules_windows(
 fn load_modules_linux(
     core: &core_header::CoreH,
     seperated_args: &HashMap<String, Vec<String>>,
+    selftest: &mut Option<selftest::SelfTestRun>,
+    command: &mut Option<Box<dyn pipeline::Command>>,
 ) -> Result<HashMap<PathBuf, Library>, ModuleError> {
     use std::{collections::HashMap, fs, path::PathBuf};
 
@@ -247,6 +360,8 @@ fn load_modules_linux(
     let mut failed_modules: usize = 0;
     let mut library_table: HashMap<PathBuf, Library> = HashMap::new();
 
+    let mut libraries: Vec<(PathBuf, Library)> = Vec::new();
+
     for module in library_names {
         unsafe {
             let library;
@@ -255,41 +370,94 @@ fn load_modules_linux(
                 Ok(data) => library = data,
                 Err(msg) => {
                     failed_modules += 1;
-                    println!("Failed to load library!: {}", msg);
+                    log_error!("Failed to load library!: {}", msg);
+                    continue;
+                }
+            }
+
+            match library.get::<abi::AbiVersionFn>(b"module_abi_version\0") {
+                Ok(version_fn) if version_fn() == abi::ABI_VERSION => {}
+                Ok(version_fn) => {
+                    failed_modules += 1;
+                    log_error!("{:?} was built for module ABI version {}, but the core needs version {}!",
+                        module, version_fn(), abi::ABI_VERSION);
+                    continue;
+                }
+                Err(msg) => {
+                    failed_modules += 1;
+                    log_error!("{:?} does not export module_abi_version, it was built for an older core: {}",
+                        module, msg);
                     continue;
                 }
             }
 
-            let startup_fn: Symbol<extern "C" fn(core: &core_header::CoreH, args: &mut Vec<String>)>;
-            let mut module_args: Vec<String>;
+            if let Err(msg) = library.get::<abi::StartupFn>(b"module_startup\0") {
+                failed_modules += 1;
+                log_error!("Did not find startup function: {}", msg);
+                continue;
+            }
+
+            libraries.push((module, library));
+        }
+    }
+
+    // Every module registers its services before any module starts, so a module can use
+    // the services of the modules loaded after it.
+    for (_module, library) in libraries.iter() {
+        unsafe {
+            let register_fn: Symbol<abi::RegisterFn> =
+                match library.get(b"module_register\0") {
+                    Ok(func) => func,
+                    Err(_) => continue,
+                };
 
+            register_fn(core);
+        }
+    }
+
+    for (module, library) in libraries {
+        unsafe {
             let module_name = format!("+{}", module.file_stem().unwrap().to_str().unwrap()
                 .strip_prefix("lib").unwrap());
 
-            if let Some(args) = seperated_args.get(&module_name) {
-                module_args = args.clone();
-            }
-            else {
-                module_args = Vec::new();
+            if let Some(selftest) = selftest.as_mut() {
+                let selftest_fn = library.get::<SelfTestFn>(b"module_selftest\0").ok().map(|func| *func);
+                selftest.run_module(&module_name, selftest_fn, core);
+
+                library_table.insert(module, library);
+                continue;
             }
 
-            match library.get(b"module_startup\0") {
-                Ok(func) => startup_fn = func,
-                Err(msg) => {
-                    failed_modules += 1;
-                    println!("Did not find startup function: {}", msg);
-                    continue;
-                }
+            let startup_fn: Symbol<abi::StartupFn> = library.get(b"module_startup\0").unwrap();
+
+            // The command runs the modules itself, once all of them are loaded.
+            if command.is_some() {
+                library_table.insert(module, library);
+                continue;
             }
 
-            startup_fn(&core, &mut module_args);
+            let module_args = CArgs::new(seperated_args.get(&module_name).map(Vec::as_slice)
+                .unwrap_or_default());
+
+            // A module that was not named on the command line is started without arguments,
+            // so its failure does not count for the exit code.
+            if seperated_args.contains_key(&module_name) {
+                run_module(&module_name, || startup_fn(core, module_args.argc(), module_args.argv()));
+            } else {
+                exit_status::unreported(|| run_module(&module_name,
+                    || startup_fn(core, module_args.argc(), module_args.argv())));
+            }
 
             library_table.insert(module, library);
         }
     }
 
+    if let Some(command) = command.as_mut() {
+        command.run(core);
+    }
+
     if failed_modules > 0 {
-        println!("Failed to load {} module(s)!", failed_modules);
+        log_warn!("Failed to load {} module(s)!", failed_modules);
     }
 
     return Ok(library_table);
@@ -310,22 +478,24 @@ fn unload_modules_windows(
 
             if func_ptr.is_none() {
                 failed_modules += 1;
-                println!("Did not find shutdown function!");
+                log_error!("Did not find shutdown function!");
                 continue;
             }
 
-            let shutdown_fn: extern "system" fn(core: &core_header::CoreH) =
-                std::mem::transmute(func_ptr);
+            let shutdown_fn: abi::ShutdownFn = std::mem::transmute(func_ptr);
 
             shutdown_fn(core);
         }
     }
 
+    // The services point into the modules, so they must not outlive them.
+    service_registry::clear();
+
     for (module_path, handle) in dll_table.iter() {
         unsafe {
             if let Err(msg) = FreeLibrary(*handle) {
                 failed_modules += 1;
-                println!("Failed to unload library {:?}: {:?}", module_path, msg);
+                log_error!("Failed to unload library {:?}: {:?}", module_path, msg);
                 continue;
             }
         }
@@ -336,7 +506,7 @@ fn unload_modules_windows(
             "All modules failed to unload!"
         )));
     } else if failed_modules > 0 {
-        println!("Failed to unload {:?} module(s)!", failed_modules)
+        log_warn!("Failed to unload {:?} module(s)!", failed_modules)
     }
     Ok(())
 }
@@ -350,13 +520,13 @@ fn unload_modules_linux(
 
     for (_module_path, handle) in library_table.iter() {
         unsafe {
-            let shutdown_fn: Symbol<extern "C" fn(core: &core_header::CoreH)>;
+            let shutdown_fn: Symbol<abi::ShutdownFn>;
 
             match handle.get(b"module_shutdown\0") {
                 Ok(func) => shutdown_fn = func,
                 Err(msg) => {
                     failed_modules += 1;
-                    println!("Did not find shutdown function: {}", msg);
+                    log_error!("Did not find shutdown function: {}", msg);
                     continue;
                 }
             }
@@ -365,6 +535,9 @@ fn unload_modules_linux(
         }
     }
 
+    // The services point into the modules, so they must not outlive them.
+    service_registry::clear();
+
     let len = library_table.len();
 
     for _i in 0..len {
@@ -373,7 +546,7 @@ fn unload_modules_linux(
 
         if let Err(msg) = handle.close() {
             failed_modules += 1;
-            println!("Failed to unload library {:?}: {:?}", key, msg);
+            log_error!("Failed to unload library {:?}: {:?}", key, msg);
             continue;
         }
     }
@@ -383,29 +556,170 @@ fn unload_modules_linux(
             "All modules failed to unload!"
         )));
     } else if failed_modules > 0 {
-        println!("Failed to unload {:?} module(s)!", failed_modules)
+        log_warn!("Failed to unload {:?} module(s)!", failed_modules)
     }
     Ok(())
 }
 
-fn ping_core() {
-    println!("Pinged core!");
+/// Runs the startup function or a pipeline step of the module `module_name` ("+name"),
+/// wrapped in its `module_started` and `module_finished` events, and returns its result.
+fn run_module<T>(module_name: &str, startup: impl FnOnce() -> T) -> T {
+    let module = module_name.trim_start_matches('+');
+    events::emit(&Event::ModuleStarted { module }.to_json());
+    let timer = Instant::now();
+
+    let result = startup();
+
+    let elapsed_secs = timer.elapsed().as_secs_f64();
+    events::emit(&Event::ModuleFinished { module, elapsed_secs }.to_json());
+    result
+}
+
+extern "C" fn ping_core() {
+    log_info!("Pinged core!");
+}
+
+/// Removes `--color <WHEN>` or `--color=<WHEN>` from `args`, wherever it stands, and hands
+/// the choice on to the modules in `PURGEPACK_COLOR`, which also checks a choice that was
+/// made there already.
+fn take_color(args: &mut Vec<String>) -> Result<(), String> {
+    let position = args.iter().position(|arg| arg == "--color" || arg.starts_with("--color="));
+    let choice = match position {
+        Some(i) => {
+            let arg = args.remove(i);
+            match arg.strip_prefix("--color=") {
+                Some(value) => value.to_string(),
+                None if i < args.len() => args.remove(i),
+                None => return Err("--color expects auto, always or never".to_string()),
+            }
+        }
+        None => match overrides::var(overrides::COLOR) {
+            Some(value) => {
+                return check_color(&value).map_err(|msg| format!("{}: {}", overrides::COLOR, msg));
+            }
+            None => return Ok(()),
+        },
+    };
+    check_color(&choice)
+}
+
+/// Parses the color `choice` and hands it on in `PURGEPACK_COLOR`.
+fn check_color(choice: &str) -> Result<(), String> {
+    let choice: term::ColorChoice = choice.parse()?;
+
+    // SAFETY: no other thread runs yet, and the modules are not loaded.
+    unsafe { env::set_var(overrides::COLOR, choice.to_string()) };
+    Ok(())
 }
 
 fn main() {
-    let args = args().collect::<Vec<_>>();
+    log::init("core", LogLevel::default());
+    let mut args = args().collect::<Vec<_>>();
+
+    if let Err(msg) = take_color(&mut args) {
+        log_error!("{}", msg);
+        exit_status::exit(ExitStatus::Usage);
+    }
+
+    if args.get(1).is_some_and(|arg| arg == stats_diff::COMMAND) {
+        exit_status::exit(stats_diff::run(&args[2..]));
+    }
+
+    if args.get(1).is_some_and(|arg| arg == versions::FLAG) {
+        exit_status::exit(versions::run());
+    }
+
+    // The self-tests and the commands start the modules themselves, so there are no module
+    // arguments to separate.
+    let mut selftest = None;
+    let mut command: Option<Box<dyn pipeline::Command>> = None;
+    let mut module_args = &args[..];
+    if args.get(1).is_some_and(|arg| arg == selftest::COMMAND) {
+        selftest = Some(selftest::SelfTestRun::new(&args[2..]));
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == compress::COMMAND) {
+        match compress::CompressRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == compare::COMMAND) {
+        match compare::CompareRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == cat::COMMAND) {
+        match cat::CatRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == list::COMMAND) {
+        match list::ListRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == export::COMMAND) {
+        match export::ExportRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == import::COMMAND) {
+        match import::ImportRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+    else if args.get(1).is_some_and(|arg| arg == extract::COMMAND) {
+        match extract::ExtractRun::new(&args[2..]) {
+            Ok(run) => command = Some(Box::new(run)),
+            Err(msg) => {
+                log_error!("{}", msg);
+                exit_status::exit_at_least(ExitStatus::Usage);
+            }
+        }
+        module_args = &args[..1];
+    }
+
     let mut seperated_args = HashMap::new();
     let mut last_main_arg = "";
 
-    for (i, arg) in args.iter().enumerate() {
+    for (i, arg) in module_args.iter().enumerate() {
         if i == 0 && !arg.contains('+') {
             continue;
         }
 
         if i == 1 && !arg.contains('+') {
-            println!("Wrong argument format provided");
-            println!("{arg}");
-            return;
+            log_error!("Wrong argument format provided: {arg}");
+            exit_status::exit(ExitStatus::Usage);
         }
         else if i == 1 {
             last_main_arg = arg;
@@ -422,42 +736,130 @@ fn main() {
         }
     }
 
+    let mut log_level = match overrides::log_level() {
+        Ok(level) => level.unwrap_or_default(),
+        Err(msg) => {
+            log_error!("{}", msg);
+            exit_status::exit(ExitStatus::Usage);
+        }
+    };
+    if let Some(core_args) = seperated_args.get("+core")
+        && let Some(i) = core_args.iter().position(|arg| arg == "--log-level")
+    {
+        match core_args.get(i + 1).map(|level| level.parse()) {
+            Some(Ok(level)) => log_level = level,
+            Some(Err(msg)) => {
+                log_error!("{}", msg);
+                exit_status::exit(ExitStatus::Usage);
+            }
+            None => {
+                log_error!("--log-level expects error, warn, info or debug");
+                exit_status::exit(ExitStatus::Usage);
+            }
+        }
+    }
+    log::init("core", log_level);
+
+    if let Some(core_args) = seperated_args.get("+core")
+        && let Some(i) = core_args.iter().position(|arg| arg == "--events")
+    {
+        let Some(path) = core_args.get(i + 1) else {
+            log_error!("--events expects a path, like /dev/fd/3");
+            exit_status::exit(ExitStatus::Usage);
+        };
+        if let Err(msg) = events::open(path) {
+            log_error!("Failed to open the event stream {}: {}", path, msg);
+            exit_status::exit(ExitStatus::Io);
+        }
+    }
+
+    let mut max_memory = 0;
+    if let Some(core_args) = seperated_args.get("+core")
+        && let Some(i) = core_args.iter().position(|arg| arg == "--max-memory")
+    {
+        match core_args.get(i + 1).map(|size| budget::parse_size(size)) {
+            Some(Ok(0)) => {
+                log_error!("--max-memory must be at least 1 byte");
+                exit_status::exit(ExitStatus::Usage);
+            }
+            Some(Ok(size)) => max_memory = size,
+            Some(Err(msg)) => {
+                log_error!("--max-memory: {}", msg);
+                exit_status::exit(ExitStatus::Usage);
+            }
+            None => {
+                log_error!("--max-memory expects a size, like 512M or 4G");
+                exit_status::exit(ExitStatus::Usage);
+            }
+        }
+    }
+
     if let Some(core_args) = seperated_args.get("+core") {
         if core_args.contains(&String::from("ping")) {
             ping_core();
         }
     }
 
+    if let Err(msg) = cancel::install_handler() {
+        log_warn!("Failed to install the Ctrl-C handler: {}", msg);
+    }
+
     let core_header = core_header::CoreH {
+        abi_version: abi::ABI_VERSION,
+        size: std::mem::size_of::<core_header::CoreH>(),
         ping_core_f: ping_core,
+        acquire_buffer_f: buffer_pool::acquire_raw,
+        release_buffer_f: buffer_pool::release_raw,
+        register_service_f: service_registry::register_raw,
+        find_service_f: service_registry::find_raw,
+        is_cancelled_f: cancel::is_cancelled,
+        emit_event_f: events::emit_raw,
+        log_level,
+        max_memory,
+        report_status_f: exit_status::report_raw,
+        register_step_f: service_registry::register_step_raw,
     };
 
     let modules;
     #[cfg(target_os = "windows")]
-    match load_modules_windows(&core_header, &seperated_args) {
+    match load_modules_windows(&core_header, &seperated_args, &mut selftest, &mut command) {
         Ok(data) => modules = data,
         Err(msg) => {
-            println!("{:?}", msg);
-            return;
+            log_error!("{}", msg);
+            exit_status::exit(ExitStatus::Io);
         }
     }
 
     #[cfg(target_os = "linux")]
-    match load_modules_linux(&core_header, &seperated_args) {
+    match load_modules_linux(&core_header, &seperated_args, &mut selftest, &mut command) {
         Ok(data) => modules = data,
         Err(msg) => {
-            println!("{:?}", msg);
-            return;
+            log_error!("{}", msg);
+            exit_status::exit(ExitStatus::Io);
         }
     }
 
     #[cfg(target_os = "windows")]
     if let Err(msg) = unload_modules_windows(&core_header, modules) {
-        println!("{:?}", msg);
+        log_error!("{}", msg);
     }
 
     #[cfg(target_os = "linux")]
     if let Err(msg) = unload_modules_linux(&core_header, modules) {
-        println!("{:?}", msg);
+        log_error!("{}", msg);
+    }
+
+    let mut status = exit_status::worst();
+    if let Some(selftest) = selftest {
+        status = selftest.finish();
+    }
+    if let Some(command) = command {
+        status = command.exit_status();
+    }
+    if cancel::is_cancelled() {
+        status = ExitStatus::Cancelled;
+    }
+    if status != ExitStatus::Success {
+        exit_status::exit(status);
     }
 }
@@ -0,0 +1,340 @@
+//! Module pipelines, run by the commands of the core that pick the modules themselves.
+//!
+//! A pipeline is a list of steps like `+delta_module transform --stride 2`, as the detect
+//! module recommends them. Every step reads the output of the step before it through the
+//! [`StepService`] its module registered: the core opens the files and hands the step
+//! seekable streams and the arguments after the module name, never a path. The codecs
+//! stream the data through [`Compressor::compress_file`](shared_files::codec::Compressor)
+//! and [`Decompressor`](shared_files::codec::Decompressor), so no step holds a whole file.
+//! The intermediate files are written next to the output and removed when the pipeline
+//! ends.
+//!
+//! The output of a pipeline may start with a skippable frame holding its [`Metadata`]:
+//! the name and the permissions of the original file and the steps that produced it.
+
+use crate::service_registry;
+use shared_files::abi;
+use shared_files::core_header::{self, CoreH, DETECT_SERVICE, StepService};
+use shared_files::error::ExitStatus;
+use shared_files::frame;
+use shared_files::output::SafeWriter;
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The first line of the skippable frame holding the [`Metadata`].
+const METADATA_TAG: &str = "purgepack-pipeline";
+
+/// A command of the core that runs modules itself, like `compress`.
+///
+/// The loaders start no module for it. Once every module is loaded and registered its
+/// services and pipeline steps, they call [`Command::run`].
+pub trait Command {
+    /// Runs the command with the services and pipeline steps of the loaded modules.
+    fn run(&mut self, core: &CoreH);
+
+    /// Returns how the command ended, the exit code of the process.
+    fn exit_status(&self) -> ExitStatus;
+}
+
+/// What a pipeline records about its output, in a skippable frame in front of it.
+///
+/// The payload is text: [`METADATA_TAG`] on the first line, followed by `name=`, `mode=`
+/// with the permission bits in octal if they are known, and one `step=` line per step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// The file name of the original file, without its directory.
+    pub name: String,
+    /// The permission bits of the original file, like `0o644`, where the platform has
+    /// them.
+    pub mode: Option<u32>,
+    /// The steps that produced the output, in the order they ran.
+    pub steps: Vec<String>,
+}
+
+impl Metadata {
+    /// Returns the payload of the skippable frame.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload = format!("{}\nname={}\n", METADATA_TAG, self.name);
+        if let Some(mode) = self.mode {
+            payload.push_str(&format!("mode={:o}\n", mode));
+        }
+        for step in &self.steps {
+            payload.push_str(&format!("step={}\n", step));
+        }
+        payload.into_bytes()
+    }
+
+    /// Parses the payload of a skippable frame, or returns `None` if it holds something
+    /// else.
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let payload = String::from_utf8_lossy(payload);
+        let mut lines = payload.lines();
+        if lines.next()? != METADATA_TAG {
+            return None;
+        }
+
+        let mut metadata = Metadata {
+            name: String::new(),
+            mode: None,
+            steps: Vec::new(),
+        };
+        for (key, value) in lines.filter_map(|line| line.split_once('=')) {
+            match key {
+                "name" => metadata.name = value.to_string(),
+                "mode" => metadata.mode = u32::from_str_radix(value, 8).ok(),
+                "step" => metadata.steps.push(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(metadata)
+    }
+}
+
+/// Returns the permission bits of the file or directory at `path`, or `None` where the
+/// platform has none or they cannot be read.
+pub fn permissions(path: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path)
+            .ok()
+            .map(|metadata| metadata.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Gives the file or directory at `path` the permission bits `mode`. Without them, only
+/// the lack of write permission carries over, as a read-only file.
+///
+/// # Errors
+///
+/// Returns a message if the permissions cannot be changed.
+pub fn set_permissions(path: &Path, mode: u32) -> Result<(), String> {
+    #[cfg(unix)]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt;
+        fs::Permissions::from_mode(mode & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let permissions = {
+        let mut permissions = fs::metadata(path)
+            .map_err(|msg| format!("Failed to read {}: {}", path.display(), msg))?
+            .permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        permissions
+    };
+    fs::set_permissions(path, permissions).map_err(|msg| {
+        format!(
+            "Failed to set the permissions of {}: {}",
+            path.display(),
+            msg
+        )
+    })
+}
+
+/// The `key=value` report of the detect service about a file.
+pub struct Report(Vec<(String, String)>);
+
+impl Report {
+    /// Returns the value of `key`, or an empty string if the report does not have it.
+    pub fn value(&self, key: &str) -> &str {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map_or("", |(_, value)| value.as_str())
+    }
+
+    /// Returns the steps of the recommended pipeline.
+    pub fn steps(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(key, _)| key == "step")
+            .map(|(_, step)| step.clone())
+            .collect()
+    }
+}
+
+/// Classifies the data of the file at `path` from `offset` on with the detect service of
+/// the detect module.
+///
+/// # Errors
+///
+/// Returns a message if the detect module is not loaded or the file cannot be read.
+pub fn detect(path: &Path, offset: u64) -> Result<Report, String> {
+    let service = service_registry::find(DETECT_SERVICE).ok_or_else(|| {
+        "The detect module is not in the modules folder, so the file cannot be classified."
+            .to_string()
+    })?;
+    let mut input = File::open(path)
+        .and_then(|mut file| file.seek(SeekFrom::Start(offset)).map(|_| file))
+        .map_err(|msg| format!("Failed to open {}: {}", path.display(), msg))?;
+    let mut report = Vec::new();
+    abi::call_service(service, &mut input, &mut report)
+        .map_err(|msg| format!("Failed to classify {}: {}", path.display(), msg))?;
+
+    Ok(Report(
+        String::from_utf8_lossy(&report)
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    ))
+}
+
+/// Runs `steps` in order on the data of `input` from `offset` on and writes the output of
+/// the last step to `output`, behind a skippable frame holding `metadata` if there is one.
+///
+/// The first step reads `input`, every other step the intermediate file the step before
+/// it wrote, and the last step writes to `output`. The file at `output` is only replaced
+/// once every step succeeded.
+///
+/// # Errors
+///
+/// Returns a message if there are no steps, a step names a module that is not loaded or
+/// offers no pipeline step, a file cannot be opened or written, or a step fails.
+pub fn run(
+    core: &CoreH,
+    steps: &[String],
+    input: &Path,
+    offset: u64,
+    output: &Path,
+    metadata: Option<&Metadata>,
+) -> Result<(), String> {
+    if steps.is_empty() {
+        return Err("The pipeline has no steps.".to_string());
+    }
+    // Every module is looked up first, so a pipeline that cannot run writes nothing.
+    let services = steps
+        .iter()
+        .map(|step| step_service(step).map(|service| (step, service)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let write_error = |msg: io::Error| format!("Failed to write {}: {}", output.display(), msg);
+    let mut temp_files = TempFiles::new(output);
+    let mut reader = BufReader::new(
+        File::open(input)
+            .and_then(|mut file| file.seek(SeekFrom::Start(offset)).map(|_| file))
+            .map_err(|msg| format!("Failed to open {}: {}", input.display(), msg))?,
+    );
+    let mut writer = BufWriter::new(SafeWriter::create(output).map_err(write_error)?);
+    if let Some(metadata) = metadata {
+        frame::write_skippable(&mut writer, &metadata.to_payload()).map_err(write_error)?;
+    }
+
+    for (i, (step, service)) in services.iter().enumerate() {
+        if i + 1 == services.len() {
+            run_step(core, step, *service, &mut reader, &mut writer)?;
+            break;
+        }
+        let path = temp_files.create();
+        let mut step_output = BufWriter::new(
+            File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map_err(|msg| format!("Failed to create {}: {}", path.display(), msg))?,
+        );
+        run_step(core, step, *service, &mut reader, &mut step_output)?;
+        let step_output = step_output
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|mut file| file.rewind().map(|()| file))
+            .map_err(|msg| format!("Failed to write {}: {}", path.display(), msg))?;
+        reader = BufReader::new(step_output);
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .and_then(SafeWriter::commit)
+        .map_err(write_error)?;
+    Ok(())
+}
+
+/// Returns the pipeline step of the module `step` names.
+///
+/// # Errors
+///
+/// Returns a message if the module is not loaded or offers no pipeline step.
+fn step_service(step: &str) -> Result<StepService, String> {
+    let module = step.split_whitespace().next().unwrap_or_default();
+    service_registry::find_step(module.trim_start_matches('+')).ok_or_else(|| {
+        format!(
+            "The step \"{}\" needs {}, which is not in the modules folder or offers no pipeline step.",
+            step, module
+        )
+    })
+}
+
+/// Runs `step` through `service` from the position of `reader` to its end, and writes its
+/// output to `writer`.
+///
+/// # Errors
+///
+/// Returns a message if the step fails.
+fn run_step(
+    core: &CoreH,
+    step: &str,
+    service: StepService,
+    reader: &mut BufReader<File>,
+    writer: &mut (impl Write + Seek),
+) -> Result<(), String> {
+    let mut words = step.split_whitespace();
+    let module = words.next().unwrap_or_default();
+    let args: Vec<String> = words.map(str::to_string).collect();
+    crate::run_module(module, || {
+        abi::call_step(service, core, &args, reader, writer)
+    })
+    .and_then(|_| writer.flush())
+    .map_err(|msg| format!("The step \"{}\" failed: {}", step, msg))
+}
+
+/// The intermediate files of a pipeline, removed when it is dropped.
+pub struct TempFiles {
+    output: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl TempFiles {
+    /// Creates no files yet. They are named after `output`.
+    pub fn new(output: &Path) -> Self {
+        TempFiles {
+            output: output.to_path_buf(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Returns the path of a new intermediate file next to the output, with the extension
+    /// of PurgePack files.
+    pub fn create(&mut self) -> PathBuf {
+        let mut name = OsString::from(".");
+        name.push(self.output.file_name().unwrap_or_default());
+        name.push(format!(
+            ".{}-step{}{}",
+            process::id(),
+            self.paths.len(),
+            core_header::FILE_EXTENSION
+        ));
+        let path = self.output.with_file_name(name);
+        self.paths.push(path.clone());
+        path
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! The `test` command, which runs the self-tests of the modules.
+//!
+//! `purgepack test [MODULE ...]` loads the modules like a normal run, but calls their
+//! `module_selftest` export instead of `module_startup` and prints the checks of every
+//! module. Without module names all modules are tested. Modules without a self-test are
+//! reported as skipped, so a missing export is not mistaken for a passing module.
+
+use shared_files::core_header;
+use shared_files::error::ExitStatus;
+use shared_files::log_warn;
+use shared_files::selftest::{SelfTestFn, SelfTestReport, SelfTestSink};
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "test";
+
+/// The modules to test and the results so far.
+pub struct SelfTestRun {
+    /// The names of the modules to test, without the `+`. Empty to test all modules.
+    selected: Vec<String>,
+    /// The names of the modules that were found.
+    found: Vec<String>,
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl SelfTestRun {
+    /// Creates a run testing the modules named in `args` (with or without the `+`), or
+    /// all modules if `args` is empty.
+    pub fn new(args: &[String]) -> Self {
+        SelfTestRun {
+            selected: args
+                .iter()
+                .map(|arg| arg.trim_start_matches('+').to_string())
+                .collect(),
+            found: Vec::new(),
+            passed: 0,
+            failed: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Runs the self-test of the module `module_name` ("+name") if it was selected, and
+    /// prints its checks.
+    pub fn run_module(
+        &mut self,
+        module_name: &str,
+        selftest: Option<SelfTestFn>,
+        core: &core_header::CoreH,
+    ) {
+        let module = module_name.trim_start_matches('+');
+        if !self.selected.is_empty() && !self.selected.iter().any(|name| name == module) {
+            return;
+        }
+        self.found.push(module.to_string());
+
+        let Some(selftest) = selftest else {
+            println!("{}: skipped, the module has no self-test", module);
+            self.skipped += 1;
+            return;
+        };
+        let mut report = SelfTestReport::new();
+        let mut sink = SelfTestSink::new(&mut report);
+        crate::run_module(module_name, || selftest(core, &mut sink));
+
+        let failed = report.failed().count();
+        if failed == 0 {
+            println!("{}: {} checks passed", module, report.checks().len());
+            self.passed += 1;
+        } else {
+            println!(
+                "{}: {} of {} checks failed",
+                module,
+                failed,
+                report.checks().len()
+            );
+            for check in report.failed() {
+                println!(
+                    "  FAILED {}: {}",
+                    check.name,
+                    check.failure.as_deref().unwrap_or_default()
+                );
+            }
+            self.failed += 1;
+        }
+    }
+
+    /// Prints the summary of the run and returns how it ended: [`ExitStatus::Corrupt`] if
+    /// a module did not restore its data, or [`ExitStatus::Usage`] if a selected module
+    /// was not found.
+    pub fn finish(self) -> ExitStatus {
+        let missing: Vec<_> = self
+            .selected
+            .iter()
+            .filter(|name| !self.found.contains(name))
+            .collect();
+        for name in &missing {
+            log_warn!("No module named {} was found", name);
+        }
+        println!(
+            "Self-test: {} passed, {} failed, {} skipped",
+            self.passed, self.failed, self.skipped
+        );
+        if self.failed > 0 {
+            ExitStatus::Corrupt
+        } else if !missing.is_empty() {
+            ExitStatus::Usage
+        } else {
+            ExitStatus::Success
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! The registry of the services the modules offer to each other through `CoreH`, and of
+//! the pipeline steps they offer to the core.
+
+use shared_files::abi;
+use shared_files::core_header::{Service, StepService};
+use std::{collections::HashMap, ffi::c_char, sync::Mutex};
+
+/// The registered services by name.
+static SERVICES: Mutex<Option<HashMap<String, Service>>> = Mutex::new(None);
+
+/// The registered pipeline steps by module name, without the `+`.
+static STEPS: Mutex<Option<HashMap<String, StepService>>> = Mutex::new(None);
+
+/// Registers `service` under `name`, unless a service is already registered under it.
+///
+/// # Returns
+///
+/// Returns `true` if the service was registered.
+pub fn register(name: &str, service: Service) -> bool {
+    let mut services = SERVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let services = services.get_or_insert_with(HashMap::new);
+    if services.contains_key(name) {
+        return false;
+    }
+
+    services.insert(name.to_string(), service);
+    true
+}
+
+/// Returns the service registered under `name`.
+pub fn find(name: &str) -> Option<Service> {
+    let services = SERVICES.lock().unwrap_or_else(|e| e.into_inner());
+    services.as_ref()?.get(name).copied()
+}
+
+/// [`register`] for the modules, as `CoreH::register_service_f`.
+pub extern "C" fn register_raw(name: *const c_char, service: Service) -> bool {
+    // SAFETY: the modules pass a C string, see `core_header::register_service`.
+    register(&unsafe { abi::from_c_str(name) }, service)
+}
+
+/// [`find`] for the modules, as `CoreH::find_service_f`.
+pub extern "C" fn find_raw(name: *const c_char) -> Option<Service> {
+    // SAFETY: the modules pass a C string, see `core_header::find_service`.
+    find(&unsafe { abi::from_c_str(name) })
+}
+
+/// Registers `step` as the pipeline step of `module`, unless it has one already.
+///
+/// # Returns
+///
+/// Returns `true` if the step was registered.
+pub fn register_step(module: &str, step: StepService) -> bool {
+    let mut steps = STEPS.lock().unwrap_or_else(|e| e.into_inner());
+    let steps = steps.get_or_insert_with(HashMap::new);
+    if steps.contains_key(module) {
+        return false;
+    }
+
+    steps.insert(module.to_string(), step);
+    true
+}
+
+/// Returns the pipeline step of `module`, like `bwt_module`.
+pub fn find_step(module: &str) -> Option<StepService> {
+    let steps = STEPS.lock().unwrap_or_else(|e| e.into_inner());
+    steps.as_ref()?.get(module).copied()
+}
+
+/// [`register_step`] for the modules, as `CoreH::register_step_f`.
+pub extern "C" fn register_step_raw(module: *const c_char, step: StepService) -> bool {
+    // SAFETY: the modules pass a C string, see `core_header::register_step`.
+    register_step(&unsafe { abi::from_c_str(module) }, step)
+}
+
+/// Removes all services and steps. Called before the modules offering them are unloaded.
+pub fn clear() {
+    *SERVICES.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    *STEPS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
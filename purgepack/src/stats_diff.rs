@@ -0,0 +1,46 @@
+//! The `stats-diff` command, which compares two statistics reports.
+//!
+//! `purgepack stats-diff <BEFORE> <AFTER>` reads two reports written with
+//! `--stats --stats-format json` and prints how the ratio, the speed and every section
+//! changed, for tracking regressions across versions.
+
+use shared_files::error::ExitStatus;
+use shared_files::log_error;
+use shared_files::stats::CompressionStats;
+use std::fs;
+
+/// The name of the command, given instead of the first module.
+pub const COMMAND: &str = "stats-diff";
+
+/// Runs the command with the arguments following its name and returns how it ended.
+pub fn run(args: &[String]) -> ExitStatus {
+    let [before, after] = args else {
+        log_error!("Usage: purgepack {} <BEFORE.json> <AFTER.json>", COMMAND);
+        return ExitStatus::Usage;
+    };
+
+    let (before, after) = match (read_stats(before), read_stats(after)) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(status), _) | (_, Err(status)) => return status,
+    };
+    println!("{}", before.compare(&after));
+    ExitStatus::Success
+}
+
+/// Reads the statistics report at `path`, logging why it cannot be read.
+fn read_stats(path: &str) -> Result<CompressionStats, ExitStatus> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(msg) => {
+            log_error!("Failed to read {}: {}", path, msg);
+            return Err(ExitStatus::Io);
+        }
+    };
+    match CompressionStats::from_json(json.trim()) {
+        Ok(stats) => Ok(stats),
+        Err(msg) => {
+            log_error!("{} is not a JSON statistics report: {}", path, msg);
+            Err(ExitStatus::Corrupt)
+        }
+    }
+}
@@ -0,0 +1,662 @@
+//! Reading and writing the `tar` format, so PurgePack outputs can be exchanged with the
+//! tools that read and write it.
+//!
+//! [`TarWriter`] writes POSIX ustar archives: a 512-byte header in front of every entry,
+//! its content padded to a multiple of 512 bytes, and two empty blocks at the end. Paths
+//! that do not fit the `name` and `prefix` fields, and sizes beyond 8 GiB, get a pax
+//! extended header in front of their entry, which every tool of the last decades reads.
+//!
+//! [`TarReader`] reads the archives of GNU tar, bsdtar and other writers as well: ustar,
+//! pax extended headers for the path and the size, GNU long names and sizes in base 256.
+//! Only regular files and directories are taken apart; the other entries, like links and
+//! devices, are returned with their type so the caller can skip them.
+
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// The size of a header and the unit the content is padded to.
+const BLOCK: usize = 512;
+
+/// The largest size the 12-byte octal field holds.
+const MAX_OCTAL_SIZE: u64 = 0o77777777777;
+
+/// The range of the fields of a header.
+const NAME: (usize, usize) = (0, 100);
+const MODE: (usize, usize) = (100, 108);
+const UID: (usize, usize) = (108, 116);
+const GID: (usize, usize) = (116, 124);
+const SIZE: (usize, usize) = (124, 136);
+const MTIME: (usize, usize) = (136, 148);
+const CHECKSUM: (usize, usize) = (148, 156);
+const TYPE: usize = 156;
+const MAGIC: (usize, usize) = (257, 265);
+const PREFIX: (usize, usize) = (345, 500);
+
+/// The magic and version of a POSIX ustar header.
+const USTAR_MAGIC: &[u8; 8] = b"ustar\x0000";
+
+/// The kind of an entry, from the type flag of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A regular file, with content.
+    File,
+    /// A directory.
+    Directory,
+    /// Any other entry, like a link or a device, with its type flag.
+    Other(u8),
+}
+
+/// An entry of an archive, without its content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The path as the archive names it, with `/` between the directories.
+    pub path: String,
+    pub kind: Kind,
+    /// The permission bits.
+    pub mode: u32,
+    /// The size of the content in bytes.
+    pub size: u64,
+    /// The time of the last modification, in seconds since the Unix epoch.
+    pub mtime: u64,
+}
+
+impl Entry {
+    /// Returns the path of the entry relative to the directory the archive is unpacked
+    /// into, or `None` if it would leave that directory, being absolute or holding `..`.
+    pub fn relative_path(&self) -> Option<PathBuf> {
+        let path = Path::new(&self.path);
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => relative.push(name),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        (!relative.as_os_str().is_empty()).then_some(relative)
+    }
+}
+
+/// Writes entries to a `tar` archive.
+pub struct TarWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(inner: W) -> Self {
+        TarWriter { inner }
+    }
+
+    /// Appends a directory at `path`, with `/` between the directories.
+    pub fn append_dir(&mut self, path: &str, mode: u32, mtime: u64) -> io::Result<()> {
+        let path = format!("{}/", path.trim_end_matches('/'));
+        self.append_header(&path, b'5', mode, 0, mtime)
+    }
+
+    /// Appends a regular file at `path` with the `size` bytes `content` holds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `UnexpectedEof` error if `content` ends before `size` bytes.
+    pub fn append_file(
+        &mut self,
+        path: &str,
+        mode: u32,
+        mtime: u64,
+        size: u64,
+        content: &mut dyn Read,
+    ) -> io::Result<()> {
+        self.append_header(path, b'0', mode, size, mtime)?;
+        let copied = io::copy(&mut content.take(size), &mut self.inner)?;
+        if copied != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "The content of {} ended after {} of {} bytes.",
+                    path, copied, size
+                ),
+            ));
+        }
+        self.pad(size)
+    }
+
+    /// Writes the end of the archive and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&[0; 2 * BLOCK])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Writes the header of an entry, behind a pax extended header if the path or the
+    /// size do not fit.
+    fn append_header(
+        &mut self,
+        path: &str,
+        kind: u8,
+        mode: u32,
+        size: u64,
+        mtime: u64,
+    ) -> io::Result<()> {
+        let split = split_path(path);
+        let mut records = Vec::new();
+        if split.is_none() {
+            records.extend(pax_record("path", path));
+        }
+        if size > MAX_OCTAL_SIZE {
+            records.extend(pax_record("size", &size.to_string()));
+        }
+        if !records.is_empty() {
+            let name = format!("PaxHeaders/{}", truncate(path, NAME.1 - 11));
+            let header = header(("", &name), b'x', 0o644, records.len() as u64, mtime);
+            self.inner.write_all(&header)?;
+            self.inner.write_all(&records)?;
+            self.pad(records.len() as u64)?;
+        }
+
+        let (prefix, name) = split.unwrap_or(("", truncate(path, NAME.1)));
+        let size = if size > MAX_OCTAL_SIZE { 0 } else { size };
+        self.inner
+            .write_all(&header((prefix, name), kind, mode, size, mtime))
+    }
+
+    /// Pads content of `len` bytes to a whole block.
+    fn pad(&mut self, len: u64) -> io::Result<()> {
+        let padding = (BLOCK - (len % BLOCK as u64) as usize) % BLOCK;
+        self.inner.write_all(&[0; BLOCK][..padding])
+    }
+}
+
+/// Returns a ustar header with the path split into `prefix` and `name`.
+fn header((prefix, name): (&str, &str), kind: u8, mode: u32, size: u64, mtime: u64) -> [u8; BLOCK] {
+    let mut header = [0; BLOCK];
+    header[NAME.0..NAME.0 + name.len()].copy_from_slice(name.as_bytes());
+    header[PREFIX.0..PREFIX.0 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    write_octal(&mut header[MODE.0..MODE.1], u64::from(mode & 0o7777));
+    write_octal(&mut header[UID.0..UID.1], 0);
+    write_octal(&mut header[GID.0..GID.1], 0);
+    write_octal(&mut header[SIZE.0..SIZE.1], size);
+    write_octal(&mut header[MTIME.0..MTIME.1], mtime.min(MAX_OCTAL_SIZE));
+    header[TYPE] = kind;
+    header[MAGIC.0..MAGIC.1].copy_from_slice(USTAR_MAGIC);
+    seal(&mut header);
+    header
+}
+
+/// Writes the checksum of `header` into it.
+fn seal(header: &mut [u8; BLOCK]) {
+    let checksum = checksum(header);
+    header[CHECKSUM.0..CHECKSUM.1].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+}
+
+/// Splits `path` into the `prefix` and `name` fields, or returns `None` if it does not fit.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME.1 {
+        return Some(("", path));
+    }
+    // The prefix ends at a `/`, which is left out, and the name keeps the rest.
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| {
+            prefix.len() <= PREFIX.1 - PREFIX.0 && name.len() <= NAME.1 && !name.is_empty()
+        })
+}
+
+/// Returns the first bytes of `path` that fit into `len` bytes, at a character boundary.
+fn truncate(path: &str, len: usize) -> &str {
+    let mut end = path.len().min(len);
+    while !path.is_char_boundary(end) {
+        end -= 1;
+    }
+    &path[..end]
+}
+
+/// Returns a pax record, `<length> <key>=<value>\n`, whose length counts itself.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    format!("{} {}={}\n", len, key, value).into_bytes()
+}
+
+/// Writes `value` as octal digits, padded with zeros and ended by a NUL.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+/// Returns the checksum of `header`: the sum of its bytes, with the checksum field
+/// counted as spaces.
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| {
+            if (CHECKSUM.0..CHECKSUM.1).contains(&i) {
+                u64::from(b' ')
+            } else {
+                u64::from(byte)
+            }
+        })
+        .sum()
+}
+
+/// Reads the entries of a `tar` archive one after another.
+pub struct TarReader<R: Read> {
+    inner: R,
+    /// The bytes of the content of the current entry that were not read yet.
+    content_left: u64,
+    /// The padding behind the content of the current entry.
+    padding: u64,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        TarReader {
+            inner,
+            content_left: 0,
+            padding: 0,
+        }
+    }
+
+    /// Returns the next entry, skipping what is left of the content of the current one,
+    /// or `None` at the end of the archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if a header is damaged, and an `UnexpectedEof` error
+    /// if the archive ends in the middle of an entry.
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        let mut long_path = None;
+        let mut long_size = None;
+        loop {
+            self.skip_rest()?;
+            let Some(header) = self.read_header()? else {
+                return Ok(None);
+            };
+            let size = match long_size.take() {
+                Some(size) => size,
+                None => parse_number(&header[SIZE.0..SIZE.1])?,
+            };
+            self.content_left = size;
+            self.padding = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+
+            match header[TYPE] {
+                // A pax extended header applies to the entry that follows it.
+                b'x' => {
+                    let records = self.read_small_content()?;
+                    for (key, value) in parse_pax(&records)? {
+                        match key.as_str() {
+                            "path" => long_path = Some(value),
+                            "size" => {
+                                long_size = Some(value.parse().map_err(|_| {
+                                    invalid(format!("The pax size {} is no number.", value))
+                                })?)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                // A GNU long name holds the path of the entry that follows it.
+                b'L' => {
+                    let name = self.read_small_content()?;
+                    long_path = Some(String::from_utf8_lossy(until_nul(&name)).into_owned());
+                }
+                // Global pax headers and GNU long link names do not concern the path.
+                b'g' | b'K' => {}
+                kind => {
+                    let path = long_path.take().unwrap_or_else(|| header_path(&header));
+                    let kind = match kind {
+                        b'5' => Kind::Directory,
+                        // Old archives mark directories by the `/` at the end of the name.
+                        b'0' | b'\0' if path.ends_with('/') => Kind::Directory,
+                        b'0' | b'\0' | b'7' => Kind::File,
+                        _ => Kind::Other(kind),
+                    };
+                    return Ok(Some(Entry {
+                        path,
+                        kind,
+                        mode: parse_number(&header[MODE.0..MODE.1])? as u32 & 0o7777,
+                        size,
+                        mtime: parse_number(&header[MTIME.0..MTIME.1])?,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Copies the content of the current entry to `writer`, and returns its size.
+    pub fn read_content(&mut self, writer: &mut dyn Write) -> io::Result<u64> {
+        let len = self.content_left;
+        let copied = io::copy(&mut (&mut self.inner).take(len), writer)?;
+        self.content_left -= copied;
+        if copied != len {
+            return Err(truncated());
+        }
+        Ok(copied)
+    }
+
+    /// Reads the content of an extended header, which is held in memory.
+    fn read_small_content(&mut self) -> io::Result<Vec<u8>> {
+        // Paths are far smaller; anything larger is a damaged header.
+        const MAX_LEN: u64 = 1 << 20;
+        if self.content_left > MAX_LEN {
+            return Err(invalid(format!(
+                "An extended header of {} bytes is too large.",
+                self.content_left
+            )));
+        }
+        let mut content = Vec::new();
+        self.read_content(&mut content)?;
+        Ok(content)
+    }
+
+    /// Skips the content of the current entry that was not read, and its padding.
+    fn skip_rest(&mut self) -> io::Result<()> {
+        let len = self.content_left + self.padding;
+        let skipped = io::copy(&mut (&mut self.inner).take(len), &mut io::sink())?;
+        (self.content_left, self.padding) = (0, 0);
+        if skipped != len {
+            return Err(truncated());
+        }
+        Ok(())
+    }
+
+    /// Reads the next header, or returns `None` at the end of the archive: an empty block,
+    /// or the end of the data between two entries.
+    fn read_header(&mut self) -> io::Result<Option<[u8; BLOCK]>> {
+        let mut header = [0; BLOCK];
+        let mut filled = 0;
+        while filled < BLOCK {
+            match self.inner.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(truncated()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(None);
+        }
+
+        let recorded = parse_number(&header[CHECKSUM.0..CHECKSUM.1]).ok();
+        // Some old writers summed the bytes as signed values.
+        let signed: i64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| {
+                if (CHECKSUM.0..CHECKSUM.1).contains(&i) {
+                    i64::from(b' ')
+                } else {
+                    i64::from(byte as i8)
+                }
+            })
+            .sum();
+        if recorded != Some(checksum(&header)) && recorded.map(|sum| sum as i64) != Some(signed) {
+            return Err(invalid(
+                "A header of the tar archive is damaged.".to_string(),
+            ));
+        }
+        Ok(Some(header))
+    }
+}
+
+/// Returns the path of a header: its name, behind the prefix of a ustar header.
+fn header_path(header: &[u8; BLOCK]) -> String {
+    let name = String::from_utf8_lossy(until_nul(&header[NAME.0..NAME.1]));
+    let prefix = until_nul(&header[PREFIX.0..PREFIX.1]);
+    // GNU tar uses the prefix field for other data, and writes `ustar  ` as its magic.
+    if &header[MAGIC.0..MAGIC.1] != USTAR_MAGIC || prefix.is_empty() {
+        return name.into_owned();
+    }
+    format!("{}/{}", String::from_utf8_lossy(prefix), name)
+}
+
+/// Parses a numeric field: octal digits between spaces and NULs, or a big-endian number in
+/// base 256 if the high bit of the first byte is set.
+fn parse_number(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|&byte| byte & 0x80 != 0) {
+        if field[0] & 0x40 != 0 {
+            return Err(invalid(
+                "A header of the tar archive holds a negative number.".to_string(),
+            ));
+        }
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x3f), |value, &byte| {
+                value
+                    .checked_mul(256)
+                    .map(|value| value | u64::from(byte))
+                    .ok_or_else(|| invalid("A number of the tar archive is too large.".to_string()))
+            });
+    }
+
+    let digits = String::from_utf8_lossy(until_nul(field));
+    let digits = digits.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| {
+        invalid(format!(
+            "A header of the tar archive holds {} instead of a number.",
+            digits
+        ))
+    })
+}
+
+/// Parses the records of a pax extended header into pairs of key and value.
+fn parse_pax(mut records: &[u8]) -> io::Result<Vec<(String, String)>> {
+    let damaged = || invalid("A pax header of the tar archive is damaged.".to_string());
+    let mut pairs = Vec::new();
+    while !records.is_empty() {
+        let space = records
+            .iter()
+            .position(|&byte| byte == b' ')
+            .ok_or_else(damaged)?;
+        let len: usize = std::str::from_utf8(&records[..space])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .filter(|&len| len > space + 1 && len <= records.len())
+            .ok_or_else(damaged)?;
+        let record = &records[space + 1..len];
+        let record = record.strip_suffix(b"\n").ok_or_else(damaged)?;
+        let equals = record
+            .iter()
+            .position(|&byte| byte == b'=')
+            .ok_or_else(damaged)?;
+        pairs.push((
+            String::from_utf8_lossy(&record[..equals]).into_owned(),
+            String::from_utf8_lossy(&record[equals + 1..]).into_owned(),
+        ));
+        records = &records[len..];
+    }
+    Ok(pairs)
+}
+
+/// Returns the bytes of `field` before the first NUL.
+fn until_nul(field: &[u8]) -> &[u8] {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    &field[..end]
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "The tar archive ends in the middle of an entry.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files and directories of `entries` to an archive; directories have no
+    /// content.
+    fn archive(entries: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+        let mut writer = TarWriter::new(Vec::new());
+        for (path, content) in entries {
+            match content {
+                Some(content) => writer
+                    .append_file(
+                        path,
+                        0o640,
+                        1_700_000_000,
+                        content.len() as u64,
+                        &mut &content[..],
+                    )
+                    .unwrap(),
+                None => writer.append_dir(path, 0o750, 1_700_000_000).unwrap(),
+            }
+        }
+        writer.finish().unwrap()
+    }
+
+    /// Reads every entry of `archive` with its content.
+    fn read_all(archive: &[u8]) -> io::Result<Vec<(Entry, Vec<u8>)>> {
+        let mut reader = TarReader::new(archive);
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            let mut content = Vec::new();
+            reader.read_content(&mut content)?;
+            entries.push((entry, content));
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn round_trips_files_and_directories() {
+        let deep = format!("{}/{}", "d".repeat(120), "f".repeat(90));
+        let long = "x".repeat(300);
+        let data: Vec<u8> = (0..1500u32).map(|i| (i % 251) as u8).collect();
+        let bytes = archive(&[
+            ("logs", None),
+            ("logs/a.txt", Some(b"hello")),
+            ("logs/empty", Some(b"")),
+            (&deep, Some(&data)),
+            (&long, Some(b"long")),
+        ]);
+        assert_eq!(bytes.len() % BLOCK, 0);
+
+        let entries = read_all(&bytes).unwrap();
+        let paths: Vec<_> = entries
+            .iter()
+            .map(|(entry, _)| entry.path.as_str())
+            .collect();
+        assert_eq!(paths, ["logs/", "logs/a.txt", "logs/empty", &deep, &long]);
+        assert_eq!(entries[0].0.kind, Kind::Directory);
+        assert_eq!(entries[0].0.mode, 0o750);
+        assert_eq!(entries[1].0.kind, Kind::File);
+        assert_eq!(entries[1].0.mode, 0o640);
+        assert_eq!(entries[1].0.mtime, 1_700_000_000);
+        assert_eq!(entries[1].1, b"hello");
+        assert_eq!(entries[3].1, data);
+        assert_eq!(entries[4].1, b"long");
+    }
+
+    #[test]
+    fn skips_content_that_is_not_read() {
+        let bytes = archive(&[("a", Some(&[7; 700])), ("b", Some(b"b"))]);
+        let mut reader = TarReader::new(&bytes[..]);
+        assert_eq!(reader.next_entry().unwrap().unwrap().path, "a");
+        let b = reader.next_entry().unwrap().unwrap();
+        assert_eq!((b.path.as_str(), b.size), ("b", 1));
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_gnu_long_names_and_base_256_sizes() {
+        let name = "n".repeat(150);
+        let mut long_name = header(("", "././@LongLink"), b'L', 0o644, name.len() as u64 + 1, 0);
+        // GNU tar writes `ustar  ` as its magic.
+        long_name[MAGIC.0..MAGIC.1].copy_from_slice(b"ustar  \0");
+        seal(&mut long_name);
+        let mut content = name.clone().into_bytes();
+        content.push(0);
+        content.resize(BLOCK, 0);
+
+        let mut file = header(("", &name[..100]), b'0', 0o600, 0, 0);
+        file[SIZE.0..SIZE.1].copy_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+        seal(&mut file);
+
+        let mut bytes = [long_name.to_vec(), content, file.to_vec()].concat();
+        bytes.extend(b"abc");
+        bytes.resize(bytes.len() + BLOCK - 3 + 2 * BLOCK, 0);
+        let entries = read_all(&bytes).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0.path, name);
+        assert_eq!(entries[0].1, b"abc");
+    }
+
+    #[test]
+    fn writes_pax_sizes_beyond_the_octal_field() {
+        let mut writer = TarWriter::new(Vec::new());
+        writer
+            .append_header("big", b'0', 0o644, MAX_OCTAL_SIZE + 1, 0)
+            .unwrap();
+        let bytes = writer.inner;
+        assert_eq!(bytes[TYPE], b'x');
+        assert_eq!(
+            parse_number(&bytes[BLOCK + SIZE.0..BLOCK + SIZE.1]).unwrap(),
+            0
+        );
+
+        let mut reader = TarReader::new(&bytes[..]);
+        let entry = reader.next_entry().unwrap().unwrap();
+        assert_eq!(
+            (entry.path.as_str(), entry.size),
+            ("big", MAX_OCTAL_SIZE + 1)
+        );
+    }
+
+    #[test]
+    fn pax_records_count_their_own_length() {
+        for value in ["", "a", &"v".repeat(95), &"v".repeat(994)] {
+            let record = pax_record("path", value);
+            let (len, _) = std::str::from_utf8(&record)
+                .unwrap()
+                .split_once(' ')
+                .unwrap();
+            assert_eq!(len.parse::<usize>().unwrap(), record.len());
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_archives() {
+        let mut bytes = archive(&[("a.txt", Some(b"hello"))]);
+        bytes[0] ^= 1;
+        let e = read_all(&bytes).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let bytes = archive(&[("a.txt", Some(&[1; 600]))]);
+        let e = read_all(&bytes[..BLOCK + 300]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn keeps_paths_inside_the_target_directory() {
+        let entry = |path: &str| Entry {
+            path: path.to_string(),
+            kind: Kind::File,
+            mode: 0o644,
+            size: 0,
+            mtime: 0,
+        };
+        assert_eq!(
+            entry("./a/b.txt").relative_path(),
+            Some(PathBuf::from("a/b.txt"))
+        );
+        assert_eq!(entry("a/").relative_path(), Some(PathBuf::from("a")));
+        assert_eq!(entry("/etc/passwd").relative_path(), None);
+        assert_eq!(entry("a/../../b").relative_path(), None);
+        assert_eq!(entry("./").relative_path(), None);
+    }
+}
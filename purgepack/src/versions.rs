@@ -0,0 +1,142 @@
+//! The `--version-all` flag, which reports the versions of the core and every module.
+//!
+//! `purgepack --version-all` prints the version of the core and the module ABI it needs,
+//! followed by one line per library in the modules folder: its name, the package version
+//! from its `module_manifest` export and the ABI version it was built with. Modules are
+//! only loaded, never started, so modules built for another ABI are listed too, which is
+//! what a bug report needs.
+
+use libloading::Library;
+use shared_files::abi::{self, AbiVersionFn, ManifestFn};
+use shared_files::error::ExitStatus;
+use shared_files::log_error;
+use std::{env::consts, fs, path::Path};
+
+/// The flag, given instead of the first module.
+pub const FLAG: &str = "--version-all";
+
+/// The folder the loaders read the modules from.
+const MODULES_FOLDER: &str = "modules";
+
+/// What a module library tells about itself.
+struct ModuleVersion {
+    name: String,
+    version: String,
+    abi_version: Option<u32>,
+    problem: Option<String>,
+}
+
+/// Prints the versions and returns how the command ended.
+pub fn run() -> ExitStatus {
+    println!(
+        "purgepack {} (module ABI {}, {} {})",
+        env!("CARGO_PKG_VERSION"),
+        abi::ABI_VERSION,
+        consts::OS,
+        consts::ARCH
+    );
+
+    let entries = match fs::read_dir(MODULES_FOLDER) {
+        Ok(entries) => entries,
+        Err(msg) => {
+            log_error!("Failed to read the modules folder: {}", msg);
+            return ExitStatus::Io;
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(consts::DLL_EXTENSION))
+        })
+        .collect();
+    paths.sort();
+
+    if paths.is_empty() {
+        println!("No modules in {}", MODULES_FOLDER);
+        return ExitStatus::Success;
+    }
+
+    let modules: Vec<_> = paths.iter().map(|path| inspect(path)).collect();
+    let name_width = modules
+        .iter()
+        .map(|module| module.name.len())
+        .max()
+        .unwrap_or(0);
+    let version_width = modules
+        .iter()
+        .map(|module| module.version.len())
+        .max()
+        .unwrap_or(0);
+    for module in &modules {
+        let abi_version = module
+            .abi_version
+            .map_or("?".to_string(), |version| version.to_string());
+        let line = format!(
+            "  {:name_width$}  {:version_width$}  ABI {}",
+            module.name, module.version, abi_version
+        );
+        match &module.problem {
+            Some(problem) => println!("{}  ({})", line, problem),
+            None => println!("{}", line.trim_end()),
+        }
+    }
+    ExitStatus::Success
+}
+
+/// Loads the library at `path` and reads its exports, without starting the module.
+fn inspect(path: &Path) -> ModuleVersion {
+    let stem = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let mut module = ModuleVersion {
+        name: stem.strip_prefix("lib").unwrap_or(&stem).to_string(),
+        version: "?".to_string(),
+        abi_version: None,
+        problem: None,
+    };
+
+    // SAFETY: loading a module runs no code of it besides its initializers, like the
+    // loaders do before they check the ABI version.
+    let library = match unsafe { Library::new(path) } {
+        Ok(library) => library,
+        Err(msg) => {
+            module.problem = Some(format!("failed to load: {}", msg));
+            return module;
+        }
+    };
+
+    // SAFETY: the exports have the types `abi` documents for them.
+    unsafe {
+        match library.get::<AbiVersionFn>(b"module_abi_version\0") {
+            Ok(version_fn) => module.abi_version = Some(version_fn()),
+            Err(_) => module.problem = Some("no module_abi_version export".to_string()),
+        }
+        if let Ok(manifest_fn) = library.get::<ManifestFn>(b"module_manifest\0") {
+            let manifest = abi::from_c_str(manifest_fn());
+            for (key, value) in manifest.lines().filter_map(|line| line.split_once('=')) {
+                match key {
+                    "name" => module.name = value.to_string(),
+                    "version" => module.version = value.to_string(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if module.problem.is_none()
+        && let Some(version) = module.abi_version
+        && version != abi::ABI_VERSION
+    {
+        module.problem = Some(format!(
+            "not loaded, the core needs ABI {}",
+            abi::ABI_VERSION
+        ));
+    }
+    module
+}
@@ -0,0 +1,436 @@
+//! The codec of the bit-packing module: a transform for data that uses only a few
+//! distinct byte values.
+//!
+//! If the input uses only K distinct byte values, every byte is replaced by its index in
+//! a table of those values and the indices are packed into ⌈log2 K⌉ bits each. DNA
+//! sequences (4 values, 2 bits), digit strings (10 values, 4 bits) or telemetry enums
+//! shrink by a fixed factor before any entropy coding takes place.
+//!
+//! A file is laid out as:
+//!
+//! ```text
+//! [magic: "PPCB"][module id: u8][format version: u8][original length: u64 LE]
+//! [CRC32: u32 LE][symbol count: u16 LE][symbol table: symbol count bytes][packed indices ...]
+//! ```
+//!
+//! The symbol table lists the distinct values in ascending order. The indices are packed
+//! least significant bit first, and the last byte is padded with zero bits. Every index
+//! takes at least one bit, even with a single distinct value, so the packed indices are
+//! never shorter than an eighth of the original length. A header claiming more data than
+//! the file holds is therefore rejected, instead of being restored without limit.
+//!
+//! The codec lives in its own crate so other modules can restore PPCB files of this
+//! format in memory, without going through the module entry points.
+use shared_files::checksum::{Crc32, Hasher};
+use shared_files::codec::{
+    self, CodecStats, Compressor, Counter, Decompressor, ReadSeek, WriteSeek,
+};
+use shared_files::error::PurgePackError;
+use shared_files::frame::FrameFormat;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// Module ID (Algorithm Identifier) for the bit-packing module.
+pub const MODULE_ID: u8 = 0x07;
+/// The version of the header layout and payload format written by this build.
+pub const FORMAT_VERSION: u8 = 1;
+/// The frame format of the bit packing module.
+const FORMAT: FrameFormat = FrameFormat::new(MODULE_ID, "Bit Packing", FORMAT_VERSION);
+/// The size of the fixed part of the header in bytes (4 bytes for magic + 1 byte for
+/// module ID + 1 byte for format version + 8 bytes for the original length + 4 bytes for
+/// the CRC32 of the original data + 2 bytes for the symbol count). The symbol table
+/// follows it.
+const HEADER_SIZE: usize = 20;
+
+/// The header of a bit-packed file.
+pub struct BitPackHeader {
+    /// The length in bytes of the original data.
+    pub original_len: u64,
+    /// The CRC32 of the original data.
+    pub checksum: u32,
+    /// The distinct byte values of the original data, in ascending order.
+    pub symbols: Vec<u8>,
+}
+
+impl BitPackHeader {
+    /// Returns the number of bits every index is packed into, at least one.
+    pub fn bits_per_symbol(&self) -> u32 {
+        match self.symbols.len() {
+            0..=2 => 1,
+            count => usize::BITS - (count - 1).leading_zeros(),
+        }
+    }
+
+    /// Returns the length in bytes of the packed indices.
+    pub fn packed_len(&self) -> u64 {
+        (self.original_len as u128 * self.bits_per_symbol() as u128)
+            .div_ceil(8)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Checks that a file of `file_len` bytes, starting with this header, holds the packed
+    /// indices.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the packed indices would not fit, which means the
+    /// original length in the header is corrupted.
+    pub fn check_file_len(&self, file_len: u64) -> io::Result<()> {
+        let payload_len = file_len.saturating_sub((HEADER_SIZE + self.symbols.len()) as u64);
+        if self.packed_len() > payload_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The header claims {} bytes of original data, which take {} packed bytes, but only {} follow. The file may be corrupted.",
+                    self.original_len,
+                    self.packed_len(),
+                    payload_len
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Packs everything `reader` holds into `writer` and returns the header written.
+///
+/// The input is read twice: once to collect the distinct values and the checksum, and
+/// once to pack it.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the original data, positioned at its start.
+/// * `writer` - The destination, positioned at the start of the output.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or if the input changes between
+/// the two passes.
+pub fn compress<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<BitPackHeader> {
+    let start = reader.stream_position()?;
+    let mut seen = [false; 256];
+    let mut hasher = Crc32::new();
+    let mut original_len = 0u64;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+        for &byte in &buffer[..read] {
+            seen[byte as usize] = true;
+        }
+        original_len += read as u64;
+    }
+    let header = BitPackHeader {
+        original_len,
+        checksum: hasher.value(),
+        symbols: (0..=u8::MAX).filter(|&byte| seen[byte as usize]).collect(),
+    };
+    write_header(writer, &header)?;
+
+    let mut indices = [0u8; 256];
+    for (index, &symbol) in header.symbols.iter().enumerate() {
+        indices[symbol as usize] = index as u8;
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    let mut packer = BitWriter::new(writer, header.bits_per_symbol());
+    let mut packed_len = 0u64;
+    for byte in BufReader::new(reader).bytes() {
+        let byte = byte?;
+        if !seen[byte as usize] {
+            return Err(changed_input());
+        }
+        packer.write(indices[byte as usize])?;
+        packed_len += 1;
+    }
+    if packed_len != original_len {
+        return Err(changed_input());
+    }
+    packer.finish()?;
+    Ok(header)
+}
+
+/// The error reported when the input changes while it is packed.
+fn changed_input() -> io::Error {
+    io::Error::other("The input changed while it was packed.")
+}
+
+/// Restores the original data of the packed indices following the header into `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The packed data, positioned right after the header.
+/// * `writer` - The destination of the original data.
+/// * `header` - The header read by [`read_header`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if an
+/// index lies outside the symbol table, the data is truncated or the restored data does
+/// not match the checksum.
+pub fn decompress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &BitPackHeader,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let bits = header.bits_per_symbol();
+    let mask = (1u32 << bits) - 1;
+    let mut packed = BufReader::new(reader.take(header.packed_len())).bytes();
+    let mut writer = BufWriter::new(writer);
+    let mut hasher = Crc32::new();
+    let mut restored = Vec::with_capacity(64 * 1024);
+    let (mut bit_buffer, mut bit_count) = (0u32, 0u32);
+    for _ in 0..header.original_len {
+        if bit_count < bits {
+            let byte = packed.next().transpose()?.ok_or_else(|| {
+                invalid(
+                    "The packed data is truncated. The file may be incomplete or corrupted."
+                        .to_string(),
+                )
+            })?;
+            bit_buffer |= (byte as u32) << bit_count;
+            bit_count += 8;
+        }
+        let index = (bit_buffer & mask) as usize;
+        bit_buffer >>= bits;
+        bit_count -= bits;
+        let symbol = *header.symbols.get(index).ok_or_else(|| {
+            invalid(format!(
+                "Symbol index {} lies outside the table of {} symbols. The file may be corrupted.",
+                index,
+                header.symbols.len()
+            ))
+        })?;
+        restored.push(symbol);
+        if restored.len() == restored.capacity() {
+            hasher.update(&restored);
+            writer.write_all(&restored)?;
+            restored.clear();
+        }
+    }
+    hasher.update(&restored);
+    writer.write_all(&restored)?;
+    writer.flush()?;
+
+    let checksum = hasher.value();
+    if checksum != header.checksum {
+        return Err(invalid(format!(
+            "Checksum mismatch: the restored data has CRC32 0x{:08X}, but 0x{:08X} was expected. The file may be corrupted.",
+            checksum, header.checksum
+        )));
+    }
+    Ok(())
+}
+
+/// Packs indices of a fixed width into bytes, least significant bit first.
+struct BitWriter<'a, W: Write> {
+    inner: BufWriter<&'a mut W>,
+    bits: u32,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a, W: Write> BitWriter<'a, W> {
+    fn new(inner: &'a mut W, bits: u32) -> Self {
+        BitWriter {
+            inner: BufWriter::new(inner),
+            bits,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Appends the lowest `bits` bits of `index`.
+    fn write(&mut self, index: u8) -> io::Result<()> {
+        self.bit_buffer |= (index as u32) << self.bit_count;
+        self.bit_count += self.bits;
+        while self.bit_count >= 8 {
+            self.inner.write_all(&[self.bit_buffer as u8])?;
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+        Ok(())
+    }
+
+    /// Writes the last partial byte, padded with zero bits, and flushes.
+    fn finish(mut self) -> io::Result<()> {
+        if self.bit_count > 0 {
+            self.inner.write_all(&[self.bit_buffer as u8])?;
+        }
+        self.inner.flush()
+    }
+}
+
+/// Writes the header.
+fn write_header<W: Write>(writer: &mut W, header: &BitPackHeader) -> io::Result<()> {
+    FORMAT.write_prefix(writer)?;
+    writer.write_all(&header.original_len.to_le_bytes())?;
+    writer.write_all(&header.checksum.to_le_bytes())?;
+    writer.write_all(&(header.symbols.len() as u16).to_le_bytes())?;
+    writer.write_all(&header.symbols)
+}
+
+/// Reads and validates the header, including the symbol table.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the file is too short, or an `InvalidData` error
+/// if it was not written by this module, uses a newer format version or holds an invalid symbol table.
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<BitPackHeader> {
+    let bytes: [u8; HEADER_SIZE] = FORMAT.read_header(reader)?;
+    let original_len = u64::from_le_bytes(bytes[6..14].try_into().unwrap());
+    let symbol_count = u16::from_le_bytes(bytes[18..20].try_into().unwrap()) as usize;
+    if symbol_count > 256 || (symbol_count == 0) != (original_len == 0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Invalid symbol count {} for {} bytes of original data.",
+                symbol_count, original_len
+            ),
+        ));
+    }
+    let mut symbols = vec![0u8; symbol_count];
+    reader.read_exact(&mut symbols).map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            PurgePackError::Truncated(
+                "Failed to read PurgePack header. File may be too short or corrupted.".to_string(),
+            )
+            .into()
+        } else {
+            e
+        }
+    })?;
+    if symbols.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The symbol table is not in strictly ascending order. The file may be corrupted.",
+        ));
+    }
+    Ok(BitPackHeader {
+        original_len,
+        checksum: u32::from_le_bytes(bytes[14..18].try_into().unwrap()),
+        symbols,
+    })
+}
+
+/// The bit-packing codec, for use through the `Compressor` and `Decompressor` traits of
+/// `shared_files::codec`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BitPack;
+
+impl Compressor for BitPack {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        codec::compress_in_memory(reader, writer, |input, output| {
+            compress(&mut io::Cursor::new(input), output).map(|_| ())
+        })
+    }
+
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        codec::compress_stream(reader, writer, |reader, writer, _input_len| {
+            compress(reader, writer).map(|_| ())
+        })
+    }
+}
+
+impl Decompressor for BitPack {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut reader = Counter::new(reader);
+        let mut writer = Counter::new(writer);
+        let header = read_header(&mut reader)?;
+        decompress(&mut reader, &mut writer, &header)?;
+        Ok(CodecStats {
+            input_len: reader.count(),
+            output_len: writer.count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    /// Packs `input` and returns the file.
+    fn packed(input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        compress(&mut io::Cursor::new(input), &mut output).unwrap();
+        output
+    }
+
+    /// Restores `file`, checking the header against its length first.
+    fn restored(file: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = file;
+        let header = read_header(&mut reader)?;
+        header.check_file_len(file.len() as u64)?;
+        let mut output = Vec::new();
+        decompress(&mut reader, &mut output, &header)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn round_trips_single_and_several_symbols() {
+        for input in [&b""[..], b"aaaaaaaaaaa", b"ACGTTGCAACGT", b"0123456789"] {
+            assert_eq!(restored(&packed(input)).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_length_of_single_symbol() {
+        let mut file = packed(&[b'a'; 100]);
+        file[6..14].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = restored(&file).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // Without the length of the file, decoding stops at the end of the packed indices.
+        let mut reader = &file[..];
+        let header = read_header(&mut reader).unwrap();
+        let mut output = Vec::new();
+        let error = decompress(&mut reader, &mut output, &header).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(output.len() <= 8 * file.len());
+    }
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &BitPack);
+        report.streams("default", &BitPack);
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        let text = selftest::sample("text").unwrap();
+        selftest::rejects_damage(&BitPack, &text, |len| vec![0, len / 2, len - 1]).unwrap();
+    }
+
+    #[test]
+    fn packs_all_byte_values_at_full_width() {
+        // 129 symbols and more need all 8 bits, 128 fit in 7.
+        for (count, bits) in [(128, 7), (129, 8), (256, 8)] {
+            let input: Vec<u8> = (0..10_000).map(|i| (i * 7 % count) as u8).collect();
+            let file = packed(&input);
+            let header = read_header(&mut &file[..]).unwrap();
+            assert_eq!(header.symbols.len(), count);
+            assert_eq!(header.bits_per_symbol(), bits);
+            assert_eq!(
+                file.len() as u64,
+                (HEADER_SIZE + count) as u64 + header.packed_len()
+            );
+            assert_eq!(restored(&file).unwrap(), input, "{} symbols", count);
+        }
+    }
+}
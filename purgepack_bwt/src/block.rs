@@ -0,0 +1,147 @@
+//! Compression of a single block through the whole pipeline.
+//!
+//! A compressed block is laid out as:
+//!
+//! ```text
+//! [block length: u32 LE][primary index: u32 LE][payload length: u32 LE][payload ...]
+//! ```
+//!
+//! The payload is a bit stream holding the Huffman code lengths followed by the coded
+//! symbols of the block, padded with zero bits to a whole byte.
+//!
+//! A block whose primary index is `STORED_BLOCK` was found to be already compressed or
+//! encrypted and holds its bytes as they are (format version 2 and later).
+
+use shared_files::bitio::{BitReader, BitWriter};
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{bwt, huffman, mtf};
+
+/// The size of the block header in bytes.
+const BLOCK_HEADER_SIZE: usize = 12;
+/// The primary index marking a block stored without coding. A real primary index is
+/// always smaller than the block length.
+const STORED_BLOCK: u32 = u32::MAX;
+
+/// The time spent in every stage of the pipeline, summed over all blocks.
+#[derive(Debug, Default)]
+pub struct StageTimes {
+    /// The Burrows-Wheeler transform.
+    pub bwt: Duration,
+    /// Move-to-front and zero-run coding.
+    pub mtf: Duration,
+    /// Building the Huffman code and coding the symbols.
+    pub huffman: Duration,
+}
+
+/// Writes `block` as it is, marked as stored. Returns the number of bytes written.
+pub(crate) fn write_stored_block<W: Write>(writer: &mut W, block: &[u8]) -> io::Result<u64> {
+    writer.write_all(&(block.len() as u32).to_le_bytes())?;
+    writer.write_all(&STORED_BLOCK.to_le_bytes())?;
+    writer.write_all(&(block.len() as u32).to_le_bytes())?;
+    writer.write_all(block)?;
+    Ok((BLOCK_HEADER_SIZE + block.len()) as u64)
+}
+
+/// Compresses `block` and writes it. Returns the number of bytes written.
+pub(crate) fn write_block<W: Write>(
+    writer: &mut W,
+    block: &[u8],
+    times: &mut StageTimes,
+) -> io::Result<u64> {
+    let start = Instant::now();
+    let (last, primary) = bwt::forward(block);
+    times.bwt += start.elapsed();
+
+    let start = Instant::now();
+    let symbols = mtf::encode(&last);
+    times.mtf += start.elapsed();
+
+    let start = Instant::now();
+    let mut frequencies = [0u64; mtf::ALPHABET_SIZE];
+    for &symbol in &symbols {
+        frequencies[symbol as usize] += 1;
+    }
+    let lengths = huffman::code_lengths(&frequencies);
+    let mut bits = BitWriter::new();
+    huffman::encode(&mut bits, &symbols, &lengths);
+    let payload = bits.into_bytes();
+    times.huffman += start.elapsed();
+
+    writer.write_all(&(block.len() as u32).to_le_bytes())?;
+    writer.write_all(&primary.to_le_bytes())?;
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    Ok((BLOCK_HEADER_SIZE + payload.len()) as u64)
+}
+
+/// Reads and restores the next block, which holds at most `max_len` bytes.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading fails, or an `InvalidData` error if the block is
+/// malformed.
+pub(crate) fn read_block<R: Read>(
+    reader: &mut R,
+    max_len: u32,
+    times: &mut StageTimes,
+) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut header = [0u8; BLOCK_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let (block_len, primary, payload_len) = (u32_at(0), u32_at(4), u32_at(8));
+    if block_len == 0 || block_len > max_len {
+        return Err(invalid(
+            "A block length is outside the block size stored in the header.",
+        ));
+    }
+    let mut payload = Vec::new();
+    reader.take(payload_len as u64).read_to_end(&mut payload)?;
+    if payload.len() != payload_len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if primary == STORED_BLOCK {
+        if payload_len != block_len {
+            return Err(invalid("A stored block does not match its block length."));
+        }
+        return Ok(payload);
+    }
+
+    let start = Instant::now();
+    let mut bits = BitReader::new(&payload);
+    let decoder = huffman::read_lengths(&mut bits, mtf::ALPHABET_SIZE)
+        .and_then(|lengths| huffman::Decoder::new(&lengths))
+        .ok_or_else(|| invalid("A block holds an invalid Huffman code."))?;
+    let symbols = decode_symbols(&decoder, &mut bits)
+        .ok_or_else(|| invalid("A block holds malformed Huffman-coded data."))?;
+    times.huffman += start.elapsed();
+
+    let start = Instant::now();
+    let mut symbols = symbols.into_iter();
+    let last = mtf::decode(|| symbols.next(), block_len as usize)
+        .filter(|last| last.len() == block_len as usize)
+        .ok_or_else(|| invalid("A block does not restore to its stored length."))?;
+    times.mtf += start.elapsed();
+
+    let start = Instant::now();
+    let block = bwt::inverse(&last, primary)
+        .ok_or_else(|| invalid("A block holds an out-of-range primary index."))?;
+    times.bwt += start.elapsed();
+    Ok(block)
+}
+
+/// Reads the symbols of a block up to and including `END_OF_BLOCK`.
+fn decode_symbols(decoder: &huffman::Decoder, bits: &mut BitReader) -> Option<Vec<u16>> {
+    let mut symbols = Vec::new();
+    loop {
+        let symbol = decoder.decode(bits)?;
+        symbols.push(symbol);
+        if symbol == mtf::END_OF_BLOCK {
+            return Some(symbols);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Burrows-Wheeler transform of a block.
+//!
+//! All rotations of the block are sorted, and the transform is the last byte of every
+//! rotation in sorted order, together with the position of the unrotated block (the
+//! primary index). Bytes that are followed by the same context end up next to each other,
+//! so the output has long runs of the same few bytes.
+//!
+//! The rotations are sorted by prefix doubling: after round `k` every rotation is ranked
+//! by its first `2^k` bytes, which takes `O(n log^2 n)` time even for highly repetitive
+//! blocks that make a plain comparison sort quadratic.
+
+/// Returns the transform of `block` and its primary index.
+pub(crate) fn forward(block: &[u8]) -> (Vec<u8>, u32) {
+    let n = block.len();
+    if n == 0 {
+        return (Vec::new(), 0);
+    }
+    let mut rank: Vec<u32> = block.iter().map(|&byte| byte as u32).collect();
+    // Every rotation with its rank by the first `2 * width` bytes, as one sortable key.
+    let mut keyed: Vec<(u64, u32)> = vec![(0, 0); n];
+    let mut width = 1;
+    loop {
+        for (start, entry) in keyed.iter_mut().enumerate() {
+            let key = ((rank[start] as u64) << 32) | rank[(start + width) % n] as u64;
+            *entry = (key, start as u32);
+        }
+        keyed.sort_unstable();
+        let mut current_rank = 0;
+        for row in 0..n {
+            if row > 0 && keyed[row].0 != keyed[row - 1].0 {
+                current_rank += 1;
+            }
+            rank[keyed[row].1 as usize] = current_rank;
+        }
+        if current_rank as usize == n - 1 || width >= n {
+            break;
+        }
+        width *= 2;
+    }
+
+    let mut primary = 0;
+    let last = keyed
+        .iter()
+        .map(|&(_, rotation)| rotation)
+        .enumerate()
+        .map(|(row, rotation)| {
+            if rotation == 0 {
+                primary = row as u32;
+            }
+            block[(rotation as usize + n - 1) % n]
+        })
+        .collect();
+    (last, primary)
+}
+
+/// Restores the block from its transform `last` and primary index.
+///
+/// Returns `None` if the primary index is out of range.
+pub(crate) fn inverse(last: &[u8], primary: u32) -> Option<Vec<u8>> {
+    let n = last.len();
+    if n == 0 {
+        return Some(Vec::new());
+    }
+    if primary as usize >= n {
+        return None;
+    }
+    // `starts[c]` is the first row whose rotation starts with byte `c`.
+    let mut starts = [0usize; 256];
+    for &byte in last {
+        starts[byte as usize] += 1;
+    }
+    let mut total = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = total;
+        total += count;
+    }
+    // `next[row]` is the row of the rotation that starts one byte later.
+    let mut next = vec![0u32; n];
+    for (row, &byte) in last.iter().enumerate() {
+        next[starts[byte as usize]] = row as u32;
+        starts[byte as usize] += 1;
+    }
+
+    let mut block = Vec::with_capacity(n);
+    let mut row = next[primary as usize] as usize;
+    for _ in 0..n {
+        block.push(last[row]);
+        row = next[row] as usize;
+    }
+    Some(block)
+}
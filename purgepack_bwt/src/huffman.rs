@@ -0,0 +1,175 @@
+//! Canonical Huffman coding of the symbols of a block.
+//!
+//! Every block gets its own code, built from the symbol frequencies of that block and
+//! limited to `MAX_CODE_LEN` bits. A canonical code is fully described by the code length
+//! of every symbol: the codes are assigned in order of length and then symbol, so only
+//! the lengths are stored in front of the coded symbols. They take one bit per symbol of
+//! the alphabet, telling whether the symbol is used, and `LENGTH_BITS` bits per used
+//! symbol.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use shared_files::bitio::{BitReader, BitWriter};
+
+/// The longest code assigned to a symbol.
+pub(crate) const MAX_CODE_LEN: u8 = 20;
+/// The number of bits a stored code length takes.
+const LENGTH_BITS: u32 = 5;
+
+/// Returns the code length of every symbol from its frequency. Symbols with a frequency of
+/// zero get the length 0 and no code.
+pub(crate) fn code_lengths(frequencies: &[u64]) -> Vec<u8> {
+    let mut frequencies = frequencies.to_vec();
+    loop {
+        let lengths = unlimited_code_lengths(&frequencies);
+        if lengths.iter().all(|&len| len <= MAX_CODE_LEN) {
+            return lengths;
+        }
+        // Flatten the distribution, as bzip2 does, until the longest code fits.
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency = *frequency / 2 + 1;
+        }
+    }
+}
+
+/// Returns the code lengths of a Huffman code for `frequencies`, without a length limit.
+fn unlimited_code_lengths(frequencies: &[u64]) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let used: Vec<usize> = (0..frequencies.len())
+        .filter(|&symbol| frequencies[symbol] > 0)
+        .collect();
+    if used.len() == 1 {
+        lengths[used[0]] = 1;
+        return lengths;
+    }
+
+    // Merge the two lightest trees until one is left. `parents` holds the parent of every
+    // node, the leaves first in the order of `used`.
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = used
+        .iter()
+        .enumerate()
+        .map(|(node, &symbol)| Reverse((frequencies[symbol], node)))
+        .collect();
+    let mut parents = vec![0usize; used.len()];
+    while heap.len() > 1 {
+        let Reverse((first_weight, first)) = heap.pop().unwrap();
+        let Reverse((second_weight, second)) = heap.pop().unwrap();
+        let parent = parents.len();
+        parents.push(0);
+        parents[first] = parent;
+        parents[second] = parent;
+        heap.push(Reverse((first_weight + second_weight, parent)));
+    }
+
+    // The root is the last node. Every node is one level below its parent, and parents
+    // are created after their children, so a single backwards pass computes the depths.
+    let mut depths = vec![0u8; parents.len()];
+    for node in (0..parents.len().saturating_sub(1)).rev() {
+        depths[node] = depths[parents[node]].saturating_add(1);
+    }
+    for (leaf, &symbol) in used.iter().enumerate() {
+        lengths[symbol] = depths[leaf];
+    }
+    lengths
+}
+
+/// Returns the canonical code of every symbol from its code length.
+pub(crate) fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let mut codes = vec![0u32; lengths.len()];
+    let mut code = 0u32;
+    for len in 1..=MAX_CODE_LEN {
+        for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == len) {
+            codes[symbol] = code;
+            code += 1;
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+/// Writes the code lengths followed by `symbols` coded with them.
+pub(crate) fn encode(writer: &mut BitWriter, symbols: &[u16], lengths: &[u8]) {
+    for &len in lengths {
+        writer.write_bit((len > 0) as u8);
+    }
+    for &len in lengths.iter().filter(|&&len| len > 0) {
+        writer.write_value(len as u64, LENGTH_BITS);
+    }
+    let codes = canonical_codes(lengths);
+    for &symbol in symbols {
+        writer.write_value(
+            codes[symbol as usize] as u64,
+            lengths[symbol as usize] as u32,
+        );
+    }
+}
+
+/// Reads the code lengths of an alphabet of `alphabet_size` symbols written by [`encode`].
+///
+/// Returns `None` if the bits run out.
+pub(crate) fn read_lengths(reader: &mut BitReader, alphabet_size: usize) -> Option<Vec<u8>> {
+    let mut lengths = Vec::with_capacity(alphabet_size);
+    for _ in 0..alphabet_size {
+        lengths.push(reader.read_bit().ok()?);
+    }
+    for len in lengths.iter_mut().filter(|len| **len > 0) {
+        *len = reader.read_value(LENGTH_BITS).ok()? as u8;
+    }
+    Some(lengths)
+}
+
+/// Reads symbols coded with the canonical code described by a list of code lengths.
+pub(crate) struct Decoder {
+    /// The number of codes of every length.
+    counts: [u32; MAX_CODE_LEN as usize + 1],
+    /// The symbols in canonical order: by code length, then by symbol.
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    /// Creates a decoder for the code described by `lengths`.
+    ///
+    /// Returns `None` if a length exceeds `MAX_CODE_LEN` or the lengths do not describe a
+    /// valid prefix code.
+    pub(crate) fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0u32; MAX_CODE_LEN as usize + 1];
+        for &len in lengths {
+            *counts.get_mut(len as usize)? += 1;
+        }
+        counts[0] = 0;
+        // Kraft inequality: the codes must fit in the code space.
+        let mut available: i64 = 1;
+        for &count in &counts[1..] {
+            available = available * 2 - count as i64;
+            if available < 0 {
+                return None;
+            }
+        }
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for len in 1..=MAX_CODE_LEN {
+            symbols.extend(
+                (0..lengths.len())
+                    .filter(|&symbol| lengths[symbol] == len)
+                    .map(|symbol| symbol as u16),
+            );
+        }
+        Some(Decoder { counts, symbols })
+    }
+
+    /// Reads the next symbol, or returns `None` if the bits run out or form no code.
+    pub(crate) fn decode(&self, reader: &mut BitReader) -> Option<u16> {
+        // `first` is the first code of the current length and `index` the position of its
+        // symbol in `symbols`.
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0u32);
+        for &count in &self.counts[1..] {
+            code |= reader.read_bit().ok()? as u32;
+            if code.wrapping_sub(first) < count {
+                return Some(self.symbols[(index + code.wrapping_sub(first)) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
@@ -0,0 +1,340 @@
+//! The codec of the BWT module: a bzip2-style block-sorting compressor.
+//!
+//! The input is split into blocks of `level` times 100 000 bytes, and every block is
+//! passed through the whole pipeline on its own:
+//!
+//! 1. `bwt` - the Burrows-Wheeler transform groups bytes that share a context.
+//! 2. `mtf` - move-to-front coding turns those groups into runs of zeros and small
+//!    numbers, and the runs of zeros are shortened to a few run-length symbols.
+//! 3. `huffman` - a canonical Huffman code built for the block codes the symbols.
+//!
+//! The blocks follow the header in a single file (see the `block` module for the layout).
+//! Blocks that `shared_files::incompressible` reports as already compressed or encrypted
+//! skip the pipeline and are stored as they are.
+//!
+//! The codec lives in its own crate so other modules can restore PPCB files of this
+//! format in memory, without going through the module entry points.
+use shared_files::checksum::{Crc32, Hasher};
+use shared_files::codec::{
+    self, CodecStats, Compressor, Counter, Decompressor, ReadSeek, WriteSeek,
+};
+use shared_files::frame::FrameFormat;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+mod block;
+mod bwt;
+mod huffman;
+mod mtf;
+mod stream;
+
+pub use block::StageTimes;
+pub use stream::{PpDecoder, PpEncoder};
+
+/// Module ID (Algorithm Identifier) for the BWT module.
+pub const MODULE_ID: u8 = 0x03;
+/// The version of the header layout and payload format written by this build.
+pub const FORMAT_VERSION: u8 = 2;
+/// The frame format of the BWT module.
+const FORMAT: FrameFormat = FrameFormat::new(MODULE_ID, "BWT", FORMAT_VERSION);
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for
+/// format version + 1 byte for the level + 8 bytes for the original length + 4 bytes for the
+/// CRC32 of the original data).
+const HEADER_SIZE: usize = 19;
+/// The offset of the original length in the header. `PpEncoder` writes it once all the
+/// data has been written.
+const LENGTH_OFFSET: u64 = 7;
+/// The offset of the checksum in the header. It is written once all the data has been read.
+const CHECKSUM_OFFSET: u64 = 15;
+/// The highest level, and so the largest block size.
+pub const MAX_LEVEL: u8 = 9;
+/// The number of bytes per block for every level.
+const BLOCK_UNIT: u32 = 100_000;
+
+/// The settings and checksum stored in the header.
+pub struct BwtHeader {
+    /// The block size in units of 100 000 bytes.
+    pub level: u8,
+    /// The length in bytes of the original data.
+    pub original_len: u64,
+    /// The CRC32 of the original data.
+    pub checksum: u32,
+}
+
+/// What coding or restoring the blocks took, for the statistics.
+#[derive(Debug, Default)]
+pub struct BlockSummary {
+    /// The number of blocks.
+    pub blocks: u64,
+    /// The number of blocks stored without coding because they were incompressible.
+    pub stored_blocks: u64,
+    /// The time spent in every stage of the pipeline.
+    pub times: StageTimes,
+}
+
+/// Compresses everything `reader` holds into `writer`.
+///
+/// The header is written with a zero checksum first. Once all the blocks have been
+/// written, the CRC32 of the original data is filled in.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the original data.
+/// * `writer` - The destination, positioned at the start of the output.
+/// * `original_len` - The length in bytes of the original data.
+/// * `level` - The block size in units of 100 000 bytes, from 1 to `MAX_LEVEL`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+pub fn compress<R: Read, W: Write + Seek>(
+    reader: &mut R,
+    writer: &mut W,
+    original_len: u64,
+    level: u8,
+) -> io::Result<BlockSummary> {
+    write_header(writer, level, original_len, 0)?;
+
+    let mut hasher = Crc32::new();
+    let mut summary = BlockSummary::default();
+    let mut block = Vec::with_capacity(block_size(level) as usize);
+    loop {
+        block.clear();
+        reader
+            .take(block_size(level) as u64)
+            .read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        hasher.update(&block);
+        write_block(writer, &block, &mut summary)?;
+    }
+    writer.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    writer.write_all(&hasher.value().to_le_bytes())?;
+    writer.flush()?;
+    Ok(summary)
+}
+
+/// Returns an estimate of the memory in bytes [`compress`] needs at `level` for
+/// `original_len` bytes of input: the block, the ranks and sort keys of its rotations and
+/// the coded block, about 26 bytes per byte of the largest block.
+pub fn compress_memory(level: u8, original_len: u64) -> u64 {
+    (block_size(level) as u64).min(original_len) * 26
+}
+
+/// Restores the original data of the blocks following the header into `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The compressed data, positioned right after the header.
+/// * `writer` - The destination of the original data.
+/// * `header` - The header read by [`read_header`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if a
+/// block is invalid, the data is truncated or the restored data does not match the
+/// checksum.
+pub fn decompress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &BwtHeader,
+) -> io::Result<BlockSummary> {
+    let mut hasher = Crc32::new();
+    let mut summary = BlockSummary::default();
+    let mut restored_len = 0u64;
+    while restored_len < header.original_len {
+        let block = block::read_block(reader, block_size(header.level), &mut summary.times)
+            .map_err(truncated)?;
+        restored_len += block.len() as u64;
+        check_restored_len(restored_len, header)?;
+        hasher.update(&block);
+        writer.write_all(&block)?;
+        summary.blocks += 1;
+    }
+    writer.flush()?;
+    check_checksum(hasher.value(), header)?;
+    Ok(summary)
+}
+
+/// Writes `block` through the pipeline, or stores it as it is if it is already
+/// compressed or encrypted, and counts it in `summary`.
+fn write_block<W: Write>(
+    writer: &mut W,
+    block: &[u8],
+    summary: &mut BlockSummary,
+) -> io::Result<()> {
+    if shared_files::incompressible::is_incompressible(block) {
+        block::write_stored_block(writer, block)?;
+        summary.stored_blocks += 1;
+    } else {
+        block::write_block(writer, block, &mut summary.times)?;
+    }
+    summary.blocks += 1;
+    Ok(())
+}
+
+/// Maps a truncated read to an `InvalidData` error that names the cause.
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The compressed data is truncated. The file may be incomplete or corrupted.",
+        )
+    } else {
+        e
+    }
+}
+
+/// Checks that the blocks restored so far do not exceed the original length.
+fn check_restored_len(restored_len: u64, header: &BwtHeader) -> io::Result<()> {
+    if restored_len > header.original_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The blocks restore more data than the original length stored in the header.",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the CRC32 of the restored data against the header.
+fn check_checksum(checksum: u32, header: &BwtHeader) -> io::Result<()> {
+    if checksum != header.checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Checksum mismatch: the restored data has CRC32 0x{:08X}, but 0x{:08X} was expected. The file may be corrupted.",
+                checksum, header.checksum
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the block size in bytes for `level`.
+fn block_size(level: u8) -> u32 {
+    level as u32 * BLOCK_UNIT
+}
+
+/// Writes the header.
+fn write_header<W: Write>(
+    writer: &mut W,
+    level: u8,
+    original_len: u64,
+    checksum: u32,
+) -> io::Result<()> {
+    FORMAT.write_prefix(writer)?;
+    writer.write_all(&[level])?;
+    writer.write_all(&original_len.to_le_bytes())?;
+    writer.write_all(&checksum.to_le_bytes())
+}
+
+/// Reads and validates the header.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the file is too short, or an `InvalidData` error
+/// if it was not written by this module, uses a newer format version or an unsupported level.
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<BwtHeader> {
+    let bytes: [u8; HEADER_SIZE] = FORMAT.read_header(reader)?;
+    let level = bytes[6];
+    if level == 0 || level > MAX_LEVEL {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported level: {}. Supported levels are 1 to {}.",
+                level, MAX_LEVEL
+            ),
+        ));
+    }
+    Ok(BwtHeader {
+        level,
+        original_len: u64::from_le_bytes(bytes[7..15].try_into().unwrap()),
+        checksum: u32::from_le_bytes(bytes[15..19].try_into().unwrap()),
+    })
+}
+
+/// The BWT codec with its settings, for use through the `Compressor` and `Decompressor`
+/// traits of `shared_files::codec`.
+#[derive(Debug, Clone, Copy)]
+pub struct Bwt {
+    /// The block size in units of 100 000 bytes, from 1 to `MAX_LEVEL`.
+    pub level: u8,
+}
+
+impl Default for Bwt {
+    /// Returns the settings the BWT module uses by default.
+    fn default() -> Self {
+        Bwt { level: MAX_LEVEL }
+    }
+}
+
+impl Compressor for Bwt {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        codec::compress_in_memory(reader, writer, |input, output| {
+            compress(&mut &input[..], output, input.len() as u64, self.level).map(|_| ())
+        })
+    }
+
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        codec::compress_stream(reader, writer, |reader, writer, input_len| {
+            compress(reader, writer, input_len, self.level).map(|_| ())
+        })
+    }
+}
+
+impl Decompressor for Bwt {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut reader = Counter::new(reader);
+        let mut writer = Counter::new(writer);
+        let header = read_header(&mut reader)?;
+        decompress(&mut reader, &mut writer, &header)?;
+        Ok(CodecStats {
+            input_len: reader.count(),
+            output_len: writer.count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &Bwt::default());
+        report.streams("default", &Bwt::default());
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        let text = selftest::sample("text").unwrap();
+        selftest::rejects_damage(&Bwt::default(), &text, |len| vec![0, len / 2, len - 1]).unwrap();
+    }
+
+    #[test]
+    fn codes_a_single_repeated_byte() {
+        // All rotations of such a block are equal, so the sort has no byte to order them
+        // by, and the last block of 250 000 bytes is shorter than the others.
+        for len in [1, 2, 3, 250_000] {
+            let data = vec![b'a'; len];
+            let mut file = Vec::new();
+            Bwt { level: 1 }
+                .compress(&mut &data[..], &mut file)
+                .unwrap();
+            assert!(file.len() < 1_000, "{} bytes", len);
+
+            let mut restored = Vec::new();
+            Bwt { level: 1 }
+                .decompress(&mut &file[..], &mut restored)
+                .unwrap();
+            assert_eq!(restored, data, "{} bytes", len);
+        }
+    }
+}
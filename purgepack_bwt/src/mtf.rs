@@ -0,0 +1,92 @@
+//! Move-to-front coding and zero-run coding of the transformed block.
+//!
+//! Move-to-front replaces every byte by its position in a list of recently used bytes and
+//! then moves it to the front, turning the runs of the transform into runs of zeros and
+//! the few frequent bytes into small numbers. As in bzip2, runs of zeros are then written
+//! as the run length in bijective base 2 with the digits `RUN_A` (1) and `RUN_B` (2),
+//! the other positions `v` as the symbol `v + 1`, and the block ends with `END_OF_BLOCK`.
+
+/// The symbol of a bijective base-2 digit with the value 1.
+pub(crate) const RUN_A: u16 = 0;
+/// The symbol of a bijective base-2 digit with the value 2.
+pub(crate) const RUN_B: u16 = 1;
+/// The symbol that ends a block.
+pub(crate) const END_OF_BLOCK: u16 = 257;
+/// The number of symbols: the two run digits, the positions 1 to 255 and the end of block.
+pub(crate) const ALPHABET_SIZE: usize = 258;
+
+/// Move-to-front and zero-run codes `data` into symbols, ending with `END_OF_BLOCK`.
+pub(crate) fn encode(data: &[u8]) -> Vec<u16> {
+    let mut order: [u8; 256] = std::array::from_fn(|index| index as u8);
+    let mut symbols = Vec::with_capacity(data.len() / 2 + 1);
+    let mut zeros: u64 = 0;
+    for &byte in data {
+        let position = order.iter().position(|&entry| entry == byte).unwrap();
+        if position == 0 {
+            zeros += 1;
+            continue;
+        }
+        write_run(&mut symbols, zeros);
+        zeros = 0;
+        order.copy_within(0..position, 1);
+        order[0] = byte;
+        symbols.push(position as u16 + 1);
+    }
+    write_run(&mut symbols, zeros);
+    symbols.push(END_OF_BLOCK);
+    symbols
+}
+
+/// Writes a run of `zeros` zeros as bijective base-2 digits, least significant first.
+fn write_run(symbols: &mut Vec<u16>, mut zeros: u64) {
+    while zeros > 0 {
+        if zeros & 1 == 1 {
+            symbols.push(RUN_A);
+            zeros = (zeros - 1) / 2;
+        } else {
+            symbols.push(RUN_B);
+            zeros = (zeros - 2) / 2;
+        }
+    }
+}
+
+/// Restores the data from the symbols produced by [`encode`], reading symbols from
+/// `next_symbol` until `END_OF_BLOCK`.
+///
+/// Returns `None` if the data would grow beyond `max_len` bytes or `next_symbol` fails.
+pub(crate) fn decode<F>(mut next_symbol: F, max_len: usize) -> Option<Vec<u8>>
+where
+    F: FnMut() -> Option<u16>,
+{
+    let mut order: [u8; 256] = std::array::from_fn(|index| index as u8);
+    let mut data = Vec::with_capacity(max_len);
+    let mut zeros: u64 = 0;
+    let mut weight: u64 = 1;
+    loop {
+        let symbol = next_symbol()?;
+        if symbol == RUN_A || symbol == RUN_B {
+            zeros = zeros.checked_add(weight.checked_mul(symbol as u64 + 1)?)?;
+            weight = weight.checked_mul(2)?;
+            continue;
+        }
+        if zeros > 0 {
+            if (data.len() as u64).checked_add(zeros)? > max_len as u64 {
+                return None;
+            }
+            data.resize(data.len() + zeros as usize, order[0]);
+            zeros = 0;
+            weight = 1;
+        }
+        if symbol == END_OF_BLOCK {
+            return Some(data);
+        }
+        if data.len() == max_len {
+            return None;
+        }
+        let position = (symbol - 1) as usize;
+        let byte = order[position];
+        order.copy_within(0..position, 1);
+        order[0] = byte;
+        data.push(byte);
+    }
+}
@@ -0,0 +1,245 @@
+//! Streaming adapters over the BWT format.
+//!
+//! [`PpEncoder`] sorts and codes the data written to it one block at a time, as soon as a
+//! block is full, and [`PpDecoder`] restores the data of a compressed stream as it is
+//! read. The header holds the original length and the CRC32, which are only known at the
+//! end, so the encoder needs a seekable output to fill them in when it is finished.
+
+use shared_files::checksum::{Crc32, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    BlockSummary, BwtHeader, LENGTH_OFFSET, MAX_LEVEL, block, block_size, check_checksum,
+    check_restored_len, read_header, truncated, write_block, write_header,
+};
+
+/// A writer that compresses the data written to it into the BWT format.
+///
+/// Call [`PpEncoder::finish`] to write the last block and fill in the header. Dropping
+/// the encoder does the same, but ignores any error.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Cursor, Read, Write};
+///
+/// let mut encoder = purgepack_bwt::PpEncoder::new(Cursor::new(Vec::new()), 1).unwrap();
+/// encoder.write_all(b"banana banana banana").unwrap();
+/// let compressed = encoder.finish().unwrap().into_inner();
+///
+/// let mut decoder = purgepack_bwt::PpDecoder::new(&compressed[..]).unwrap();
+/// let mut restored = Vec::new();
+/// decoder.read_to_end(&mut restored).unwrap();
+/// assert_eq!(restored, b"banana banana banana");
+/// ```
+pub struct PpEncoder<W: Write + Seek> {
+    /// The output. It is only taken by `finish`.
+    writer: Option<W>,
+    /// The position of the header in the output.
+    start: u64,
+    /// The number of bytes per block.
+    block_size: usize,
+    /// The data of the block being filled.
+    block: Vec<u8>,
+    summary: BlockSummary,
+    hasher: Crc32,
+    original_len: u64,
+}
+
+impl<W: Write + Seek> PpEncoder<W> {
+    /// Writes the header to `writer` and returns an encoder that compresses into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination, positioned where the compressed data starts.
+    /// * `level` - The block size in units of 100 000 bytes, from 1 to `MAX_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if the level is out of range, or an `io::Error` if
+    /// writing the header fails.
+    pub fn new(mut writer: W, level: u8) -> io::Result<Self> {
+        if level == 0 || level > MAX_LEVEL {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid level {}. The level must be between 1 and {}.",
+                    level, MAX_LEVEL
+                ),
+            ));
+        }
+        let start = writer.stream_position()?;
+        write_header(&mut writer, level, 0, 0)?;
+        let block_size = block_size(level) as usize;
+        Ok(PpEncoder {
+            writer: Some(writer),
+            start,
+            block_size,
+            block: Vec::with_capacity(block_size),
+            summary: BlockSummary::default(),
+            hasher: Crc32::new(),
+            original_len: 0,
+        })
+    }
+
+    /// Returns a reference to the output.
+    pub fn get_ref(&self) -> &W {
+        self.writer
+            .as_ref()
+            .expect("the writer is only taken by finish")
+    }
+
+    /// Returns what coding the blocks written so far took.
+    pub fn summary(&self) -> &BlockSummary {
+        &self.summary
+    }
+
+    /// Writes the last block, fills in the original length and the CRC32 in the header
+    /// and returns the output, positioned after the compressed data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing or seeking fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_footer()?;
+        Ok(self
+            .writer
+            .take()
+            .expect("the writer is only taken by finish"))
+    }
+
+    /// Codes and writes the block being filled, if it holds any data.
+    fn write_pending_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken by finish");
+        write_block(writer, &self.block, &mut self.summary)?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes the last block and fills in the header.
+    fn write_footer(&mut self) -> io::Result<()> {
+        self.write_pending_block()?;
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken by finish");
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(self.start + LENGTH_OFFSET))?;
+        writer.write_all(&self.original_len.to_le_bytes())?;
+        writer.write_all(&self.hasher.value().to_le_bytes())?;
+        writer.seek(SeekFrom::Start(end))?;
+        writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Write for PpEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.block_size - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        self.hasher.update(&buf[..len]);
+        self.original_len += len as u64;
+        if self.block.len() == self.block_size {
+            self.write_pending_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the output. The block being filled is kept until it is full or the
+    /// encoder is finished, so flushing does not shorten the blocks.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("the writer is only taken by finish")
+            .flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for PpEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_footer();
+        }
+    }
+}
+
+/// A reader that restores the original data of a stream in the BWT format.
+///
+/// The CRC32 of the restored data is checked once the last block has been read. A
+/// mismatch is reported as an `InvalidData` error instead of the end of the data.
+pub struct PpDecoder<R: Read> {
+    reader: R,
+    header: BwtHeader,
+    summary: BlockSummary,
+    /// The original data of the current block.
+    block: Vec<u8>,
+    /// The number of bytes of `block` already returned.
+    position: usize,
+    restored_len: u64,
+    hasher: Crc32,
+}
+
+impl<R: Read> PpDecoder<R> {
+    /// Reads the header from `reader` and returns a decoder for the data following it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the header is invalid.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let header = read_header(&mut reader)?;
+        Ok(PpDecoder {
+            reader,
+            header,
+            summary: BlockSummary::default(),
+            block: Vec::new(),
+            position: 0,
+            restored_len: 0,
+            hasher: Crc32::new(),
+        })
+    }
+
+    /// Returns the header of the stream.
+    pub fn header(&self) -> &BwtHeader {
+        &self.header
+    }
+
+    /// Returns what restoring the blocks read so far took.
+    pub fn summary(&self) -> &BlockSummary {
+        &self.summary
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for PpDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.block.len() {
+            if self.restored_len == self.header.original_len {
+                return check_checksum(self.hasher.value(), &self.header).map(|_| 0);
+            }
+            self.block = block::read_block(
+                &mut self.reader,
+                block_size(self.header.level),
+                &mut self.summary.times,
+            )
+            .map_err(truncated)?;
+            self.restored_len += self.block.len() as u64;
+            check_restored_len(self.restored_len, &self.header)?;
+            self.hasher.update(&self.block);
+            self.summary.blocks += 1;
+            self.position = 0;
+        }
+        let len = buf.len().min(self.block.len() - self.position);
+        buf[..len].copy_from_slice(&self.block[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,178 @@
+//! Block mode: the data is split into blocks of `block_size` untransformed bytes and each
+//! block is transformed independently, starting from its own seed.
+//!
+//! Because no block depends on the state left behind by the previous one, any block can
+//! be decoded on its own. Every block is prefixed with the length of its transformed
+//! payload, so a reader can skip from block to block without decoding them:
+//!
+//! ```text
+//! [encoded length: u32 LE][block payload] [encoded length: u32 LE][block payload] ...
+//! ```
+//!
+//! The untransformed length of every block is implied by the block size and the total data
+//! length stored in the header: all blocks are `block_size` bytes except the last one.
+//!
+//! Blocks are transformed in parallel on a rayon thread pool. The input is read in batches
+//! of a few blocks per worker thread, the batch is transformed concurrently and the results
+//! are written back in their original order, so the output is identical to a sequential run.
+
+use rayon::prelude::*;
+use shared_files::error::PurgePackError;
+use std::io::{self, Read, Write};
+
+use crate::{DeltaParameters, Transform, transform_stream};
+
+/// The number of blocks read into memory per worker thread before a batch is transformed.
+const BLOCKS_PER_THREAD: usize = 2;
+
+/// A block read from the input, waiting to be transformed.
+struct PendingBlock {
+    /// The offset of the block in the untransformed data.
+    offset: u64,
+    /// The untransformed length of the block.
+    block_len: u64,
+    /// The block as read from the input (untransformed when encoding, transformed when decoding).
+    data: Vec<u8>,
+}
+
+/// Transforms the payload as a sequence of independent blocks.
+///
+/// # Arguments
+///
+/// * `reader` - The reader, positioned right after the header.
+/// * `writer` - The writer, positioned right after the header.
+/// * `parameters` - The delta parameters. `parameters.block_size` must not be 0.
+/// * `data_len` - The length in bytes of the untransformed data.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if a
+/// block is truncated or does not decode to its expected length.
+pub(crate) fn transform_blocks<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    transform_type: Transform,
+) -> io::Result<()> {
+    let block_size = parameters.block_size as u64;
+    let batch_len = rayon::current_num_threads() * BLOCKS_PER_THREAD;
+    let mut offset = 0;
+
+    while offset < data_len {
+        let mut batch = Vec::with_capacity(batch_len);
+        while batch.len() < batch_len && offset < data_len {
+            let block_len = (data_len - offset).min(block_size);
+            batch.push(read_block(reader, offset, block_len, transform_type)?);
+            offset += block_len;
+        }
+
+        let results: Vec<io::Result<Vec<u8>>> = batch
+            .par_iter()
+            .map(|block| transform_block(block, parameters, transform_type))
+            .collect();
+
+        for result in results {
+            let output_block = result?;
+            if let Transform::Encode = transform_type {
+                writer.write_all(&(output_block.len() as u32).to_le_bytes())?;
+            }
+            writer.write_all(&output_block)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the next block from the input.
+///
+/// When encoding, `block_len` untransformed bytes are read. When decoding, the length
+/// prefix is read first and then the transformed payload of that length.
+fn read_block<R: Read>(
+    reader: &mut R,
+    offset: u64,
+    block_len: u64,
+    transform_type: Transform,
+) -> io::Result<PendingBlock> {
+    let mut data = Vec::new();
+    match transform_type {
+        Transform::Encode => {
+            reader.take(block_len).read_to_end(&mut data)?;
+            if data.len() as u64 != block_len {
+                return Err(PurgePackError::Truncated(
+                    "The input file ended before all blocks were read.".to_string(),
+                )
+                .into());
+            }
+        }
+        Transform::Decode => {
+            let mut encoded_len = [0u8; 4];
+            reader
+                .read_exact(&mut encoded_len)
+                .map_err(|e| truncated_block(e, offset))?;
+            let encoded_len = u32::from_le_bytes(encoded_len) as u64;
+
+            reader.take(encoded_len).read_to_end(&mut data)?;
+            if data.len() as u64 != encoded_len {
+                return Err(truncated_block(io::ErrorKind::UnexpectedEof.into(), offset));
+            }
+        }
+    }
+    Ok(PendingBlock {
+        offset,
+        block_len,
+        data,
+    })
+}
+
+/// Transforms a single block in memory and returns the result.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if a decoded block is truncated or does not have
+/// its expected length.
+fn transform_block(
+    block: &PendingBlock,
+    parameters: DeltaParameters,
+    transform_type: Transform,
+) -> io::Result<Vec<u8>> {
+    let mut output_block = Vec::with_capacity(block.block_len as usize);
+    transform_stream(
+        &mut block.data.as_slice(),
+        &mut output_block,
+        parameters,
+        block.block_len,
+        transform_type,
+    )?;
+    if matches!(transform_type, Transform::Decode) && output_block.len() as u64 != block.block_len {
+        return Err(PurgePackError::corrupt_at(
+            block.offset,
+            format!(
+                "The block at offset {} decoded to {} bytes instead of {}.",
+                block.offset,
+                output_block.len(),
+                block.block_len
+            ),
+        )
+        .into());
+    }
+    Ok(output_block)
+}
+
+/// Converts an unexpected end of file while reading a block into an `InvalidData` error
+/// that names the offset of the block in the untransformed data.
+fn truncated_block(e: io::Error, offset: u64) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        PurgePackError::corrupt_at(
+            offset,
+            format!(
+                "The block at offset {} is truncated. The file may be incomplete or corrupted.",
+                offset
+            ),
+        )
+        .into()
+    } else {
+        e
+    }
+}
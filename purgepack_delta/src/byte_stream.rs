@@ -0,0 +1,162 @@
+//! The plain byte transform as a stream that can be resumed at any position.
+//!
+//! [`compress`](crate::compress) and [`decompress`](crate::decompress) carry the state of
+//! the transform from chunk to chunk internally, so a run that stops halfway has to start
+//! over. A [`ByteStream`] instead rebuilds its state from the original bytes just before
+//! the position it resumes at, which lets a caller that rewrites a file in place keep only
+//! those bytes in its journal.
+//!
+//! The state only depends on the last `order` samples of every lane, so
+//! [`ByteStream::history_len`] bytes are enough: the seeds, then `order * stride` bytes
+//! that give every lane `order` samples.
+
+use std::io;
+
+use crate::{Algorithm, DeltaParameters, DeltaState, PostPass, Transform, transform_data_chunk};
+
+/// The size-preserving byte transform (word size 1, no varint residuals), applied chunk by
+/// chunk to a single stream.
+pub struct ByteStream {
+    parameters: DeltaParameters,
+    transform_type: Transform,
+    /// The seeds read so far, until the stream has all of them.
+    seeds: Vec<u8>,
+    /// The state of the transform, once the seeds are complete.
+    state: Option<DeltaState<u8>>,
+}
+
+impl ByteStream {
+    /// Returns whether `parameters` describe a stream a `ByteStream` can transform: the
+    /// delta or XOR byte transform as a single stream, without a post pass.
+    pub fn supports(parameters: DeltaParameters) -> bool {
+        parameters.is_byte_transform()
+            && parameters.block_size == 0
+            && parameters.post_pass == PostPass::None
+            && matches!(parameters.algorithm, Algorithm::Delta | Algorithm::Xor)
+    }
+
+    /// Returns the number of original bytes before a position that determine the state of
+    /// the transform there.
+    pub fn history_len(parameters: DeltaParameters) -> usize {
+        parameters.seed_len() as usize + parameters.order as usize * parameters.stride as usize
+    }
+
+    /// Starts a stream at the position that follows `history`.
+    ///
+    /// `history` holds the original (untransformed) bytes before that position: all of
+    /// them, or at least the last [`ByteStream::history_len`]. An empty history starts the
+    /// stream at its beginning.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if [`ByteStream::supports`] rejects `parameters`.
+    pub fn resume(
+        parameters: DeltaParameters,
+        transform_type: Transform,
+        history: &[u8],
+    ) -> io::Result<Self> {
+        if !Self::supports(parameters) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Only the delta and XOR byte transforms without blocks or a post pass can be resumed.",
+            ));
+        }
+        let history = &history[history.len().saturating_sub(Self::history_len(parameters))..];
+        let mut stream = ByteStream {
+            parameters,
+            transform_type,
+            seeds: Vec::new(),
+            state: None,
+        };
+        // Both directions leave the same state behind for the same original bytes, so the
+        // history is replayed through the encoder.
+        stream.apply(history, Transform::Encode, &mut io::sink())?;
+        Ok(stream)
+    }
+
+    /// Transforms the next bytes of the stream and returns the result, which has the same
+    /// length.
+    pub fn transform(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len());
+        self.apply(data, self.transform_type, &mut output)
+            .expect("writing to a Vec does not fail");
+        output
+    }
+
+    /// Transforms `data` in the direction `transform_type` into `writer`. The seeds are
+    /// passed through verbatim.
+    fn apply<W: io::Write>(
+        &mut self,
+        mut data: &[u8],
+        transform_type: Transform,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        if self.state.is_none() {
+            let missing = self.parameters.seed_len() as usize - self.seeds.len();
+            let (seeds, rest) = data.split_at(missing.min(data.len()));
+            self.seeds.extend_from_slice(seeds);
+            writer.write_all(seeds)?;
+            if self.seeds.len() < self.parameters.seed_len() as usize {
+                return Ok(());
+            }
+            self.state = Some(DeltaState::new(&self.seeds, self.parameters));
+            data = rest;
+        }
+        let state = self.state.as_mut().expect("the seeds are complete");
+        transform_data_chunk(data, writer, state, transform_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HEADER_SIZE, compress_slice};
+
+    /// Returns smooth data with some noise, so every level of the transform matters.
+    fn sample_data() -> Vec<u8> {
+        (0..3000u32)
+            .map(|i| (i * i / 7 + (i * 31) % 5) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn resumed_streams_match_the_whole_transform() {
+        let data = sample_data();
+        for parameters in [
+            DeltaParameters::default(),
+            DeltaParameters {
+                order: 3,
+                stride: 4,
+                ..DeltaParameters::default()
+            },
+            DeltaParameters {
+                algorithm: Algorithm::Xor,
+                order: 2,
+                stride: 3,
+                seed_block: true,
+                ..DeltaParameters::default()
+            },
+        ] {
+            let encoded =
+                compress_slice(&data, parameters).unwrap()[HEADER_SIZE as usize..].to_vec();
+            for position in [0, 1, 2, 5, 13, 40, 1999] {
+                let mut encoder =
+                    ByteStream::resume(parameters, Transform::Encode, &data[..position]).unwrap();
+                assert_eq!(encoder.transform(&data[position..]), encoded[position..]);
+                let mut decoder =
+                    ByteStream::resume(parameters, Transform::Decode, &data[..position]).unwrap();
+                assert_eq!(decoder.transform(&encoded[position..]), data[position..]);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_transforms_that_change_the_length() {
+        let parameters = DeltaParameters {
+            word_size: 2,
+            varint: true,
+            ..DeltaParameters::default()
+        };
+        assert!(ByteStream::resume(parameters, Transform::Encode, &[]).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Image mode: PNG-style row filters for raw pixel data.
+//!
+//! A plain byte delta only looks at the previous byte, which for an image is the same
+//! channel of the pixel to the left at best. Image mode instead splits the data into
+//! scanlines and predicts every byte from its neighbours to the left (`a`), above (`b`)
+//! and above-left (`c`), using the five filters defined by PNG:
+//!
+//! | Filter  | Prediction                        |
+//! |---------|-----------------------------------|
+//! | None    | 0                                 |
+//! | Sub     | `a`                               |
+//! | Up      | `b`                               |
+//! | Average | `(a + b) / 2`                     |
+//! | Paeth   | whichever of `a`, `b`, `c` is closest to `a + b - c` |
+//!
+//! The filter is chosen per row when encoding (the one with the smallest sum of absolute
+//! residuals) and stored in front of the row, so the payload is laid out as:
+//!
+//! ```text
+//! [filter: u8][row_len filtered bytes] [filter: u8][row_len filtered bytes] ...
+//! ```
+//!
+//! The last row is shorter if the data length is not a multiple of the row length.
+//!
+//! The row layout (row length and pixel size) is stored with the delta parameters.
+
+use shared_files::error::PurgePackError;
+use std::io::{self, Read, Write};
+
+use crate::{DeltaParameters, Transform};
+
+/// Row filter byte: the row is stored unchanged.
+const FILTER_NONE: u8 = 0;
+/// Row filter byte: each byte is predicted from the byte one pixel to the left.
+const FILTER_SUB: u8 = 1;
+/// Row filter byte: each byte is predicted from the byte above it.
+const FILTER_UP: u8 = 2;
+/// Row filter byte: each byte is predicted from the average of the left and upper bytes.
+const FILTER_AVERAGE: u8 = 3;
+/// Row filter byte: each byte is predicted with the Paeth predictor.
+const FILTER_PAETH: u8 = 4;
+/// The number of row filters. Filter bytes from this value upwards are invalid.
+const FILTER_COUNT: u8 = 5;
+
+/// The Paeth predictor: returns whichever of `a` (left), `b` (above) and `c` (above-left)
+/// is closest to `a + b - c`, preferring `a`, then `b`.
+///
+/// # Examples
+///
+/// ```ignore
+/// assert_eq!(paeth(10, 20, 10), 20); // a horizontal edge: predict from above
+/// assert_eq!(paeth(20, 10, 10), 20); // a vertical edge: predict from the left
+/// ```
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let estimate = a as i16 + b as i16 - c as i16;
+    let distance_a = (estimate - a as i16).abs();
+    let distance_b = (estimate - b as i16).abs();
+    let distance_c = (estimate - c as i16).abs();
+    if distance_a <= distance_b && distance_a <= distance_c {
+        a
+    } else if distance_b <= distance_c {
+        b
+    } else {
+        c
+    }
+}
+
+/// Returns the prediction of `filter` for a byte with the neighbours `a` (left), `b` (above)
+/// and `c` (above-left).
+fn predict(filter: u8, a: u8, b: u8, c: u8) -> u8 {
+    match filter {
+        FILTER_SUB => a,
+        FILTER_UP => b,
+        FILTER_AVERAGE => ((a as u16 + b as u16) / 2) as u8,
+        FILTER_PAETH => paeth(a, b, c),
+        _ => 0,
+    }
+}
+
+/// Returns the neighbours `a`, `b` and `c` of the byte at `index`. Neighbours outside the
+/// image (left of the first pixel, above the first row) are 0.
+fn neighbours(row: &[u8], previous_row: &[u8], index: usize, pixel_size: usize) -> (u8, u8, u8) {
+    let b = previous_row.get(index).copied().unwrap_or(0);
+    if index < pixel_size {
+        return (0, b, 0);
+    }
+    let a = row[index - pixel_size];
+    let c = previous_row.get(index - pixel_size).copied().unwrap_or(0);
+    (a, b, c)
+}
+
+/// Filters `row` with `filter` into `filtered`.
+fn filter_row(
+    filter: u8,
+    row: &[u8],
+    previous_row: &[u8],
+    pixel_size: usize,
+    filtered: &mut Vec<u8>,
+) {
+    filtered.clear();
+    filtered.extend(row.iter().enumerate().map(|(index, &value)| {
+        let (a, b, c) = neighbours(row, previous_row, index, pixel_size);
+        value.wrapping_sub(predict(filter, a, b, c))
+    }));
+}
+
+/// Reverses `filter_row` in place. Every byte is restored before it is used as the left
+/// neighbour of the bytes after it.
+fn unfilter_row(filter: u8, row: &mut [u8], previous_row: &[u8], pixel_size: usize) {
+    for index in 0..row.len() {
+        let (a, b, c) = neighbours(row, previous_row, index, pixel_size);
+        row[index] = row[index].wrapping_add(predict(filter, a, b, c));
+    }
+}
+
+/// The cost of a filtered row: the sum of its bytes read as signed residuals, which is
+/// the heuristic PNG encoders use to pick a filter.
+fn filter_cost(filtered: &[u8]) -> u64 {
+    filtered
+        .iter()
+        .map(|&value| (value as i8).unsigned_abs() as u64)
+        .sum()
+}
+
+/// Transforms the payload in image mode (see the module documentation for the layout).
+///
+/// # Arguments
+///
+/// * `reader` - The reader, positioned right after the header.
+/// * `writer` - The writer, positioned right after the header.
+/// * `parameters` - The delta parameters, including the row length and pixel size.
+/// * `data_len` - The length in bytes of the unfiltered data.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// filtered data is truncated or names an unknown filter.
+pub(crate) fn transform_rows<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    transform_type: Transform,
+) -> io::Result<()> {
+    let row_len = parameters.row_len as u64;
+    let pixel_size = parameters.pixel_size as usize;
+    let mut row = Vec::new();
+    let mut previous_row = Vec::new();
+    let mut filtered = Vec::new();
+    let mut best_filtered = Vec::new();
+    let mut offset = 0;
+
+    let truncated = |e: io::Error| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            PurgePackError::corrupt(
+                "The filtered image data is truncated. The file may be incomplete or corrupted.",
+            )
+            .into()
+        } else {
+            e
+        }
+    };
+
+    while offset < data_len {
+        row.resize((data_len - offset).min(row_len) as usize, 0);
+        match transform_type {
+            Transform::Encode => {
+                reader.read_exact(&mut row)?;
+                let mut best_filter = FILTER_NONE;
+                let mut best_cost = u64::MAX;
+                for filter in FILTER_NONE..FILTER_COUNT {
+                    filter_row(filter, &row, &previous_row, pixel_size, &mut filtered);
+                    let cost = filter_cost(&filtered);
+                    if cost < best_cost {
+                        best_filter = filter;
+                        best_cost = cost;
+                        std::mem::swap(&mut filtered, &mut best_filtered);
+                    }
+                }
+                writer.write_all(&[best_filter])?;
+                writer.write_all(&best_filtered)?;
+            }
+            Transform::Decode => {
+                let mut filter = [0u8; 1];
+                reader.read_exact(&mut filter).map_err(truncated)?;
+                if filter[0] >= FILTER_COUNT {
+                    return Err(PurgePackError::corrupt_at(
+                        offset,
+                        format!(
+                            "Unknown row filter 0x{:02X} in the row at offset {}.",
+                            filter[0], offset
+                        ),
+                    )
+                    .into());
+                }
+                reader.read_exact(&mut row).map_err(truncated)?;
+                unfilter_row(filter[0], &mut row, &previous_row, pixel_size);
+                writer.write_all(&row)?;
+            }
+        }
+        offset += row.len() as u64;
+        std::mem::swap(&mut row, &mut previous_row);
+    }
+
+    Ok(())
+}
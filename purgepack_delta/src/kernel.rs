@@ -0,0 +1,149 @@
+//! Vectorized kernels of the first-order byte transform.
+//!
+//! In a first-order transform every byte depends only on the byte `stride` positions before
+//! it, so the bytes of a chunk can be processed 16 at a time:
+//!
+//! * Encode: `out[i] = data[i] - data[i - stride]` only reads the input, so every vector
+//!   is independent of the others.
+//! * Decode: `out[i] = data[i] + out[i - stride]` reads earlier output. With a stride of at
+//!   least 16 the source vector is complete before it is needed. With a stride of 1 every
+//!   vector is a prefix sum, computed in four shift-and-add steps and then offset by the
+//!   last restored byte.
+//!
+//! On x86_64 the kernels use SSE2, which every x86_64 CPU supports. Other targets, and the
+//! decode strides the vector code does not cover, use the scalar loops.
+
+use crate::{DeltaState, Transform};
+
+/// The number of bytes processed per vector.
+#[cfg(target_arch = "x86_64")]
+const VECTOR_LEN: usize = 16;
+
+/// Applies the first-order transform to `data` and returns the result, updating the state
+/// of every lane for the next chunk.
+///
+/// The state must hold a single level (`order` 1) of plain (non-XOR) differences.
+pub(crate) fn transform_order1(
+    data: &[u8],
+    delta_state: &mut DeltaState<u8>,
+    transform_type: Transform,
+) -> Vec<u8> {
+    let stride = delta_state.stride;
+    let len = data.len();
+    let head = stride.min(len);
+    let mut transformed = vec![0u8; len];
+
+    // The first `stride` bytes are combined with the last bytes of the previous chunk.
+    for index in 0..head {
+        let previous = delta_state.previous_values[(delta_state.lane + index) % stride];
+        transformed[index] = match transform_type {
+            Transform::Encode => data[index].wrapping_sub(previous),
+            Transform::Decode => data[index].wrapping_add(previous),
+        };
+    }
+    match transform_type {
+        Transform::Encode => encode_body(data, &mut transformed, stride),
+        Transform::Decode => decode_body(data, &mut transformed, stride),
+    }
+
+    // Remember the last original byte of every lane touched by this chunk.
+    let original = match transform_type {
+        Transform::Encode => data,
+        Transform::Decode => &transformed[..],
+    };
+    for (index, &value) in original.iter().enumerate().skip(len - head) {
+        delta_state.previous_values[(delta_state.lane + index) % stride] = value;
+    }
+    delta_state.lane = (delta_state.lane + len) % stride;
+    transformed
+}
+
+/// Encodes `data[stride..]` into `transformed[stride..]`.
+fn encode_body(data: &[u8], transformed: &mut [u8], stride: usize) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: SSE2 is part of the x86_64 baseline, so it is available on every x86_64 CPU.
+    let start = unsafe { sse2::encode(data, transformed, stride) };
+    #[cfg(not(target_arch = "x86_64"))]
+    let start = stride;
+    for index in start..data.len() {
+        transformed[index] = data[index].wrapping_sub(data[index - stride]);
+    }
+}
+
+/// Decodes `data[stride..]` into `transformed[stride..]`. `transformed[..stride]` must
+/// already hold the restored bytes.
+fn decode_body(data: &[u8], transformed: &mut [u8], stride: usize) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: SSE2 is part of the x86_64 baseline, so it is available on every x86_64 CPU.
+    let start = unsafe { sse2::decode(data, transformed, stride) };
+    #[cfg(not(target_arch = "x86_64"))]
+    let start = stride;
+    for index in start..data.len() {
+        transformed[index] = data[index].wrapping_add(transformed[index - stride]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use super::VECTOR_LEN;
+    use std::arch::x86_64::{
+        __m128i, _mm_add_epi8, _mm_loadu_si128, _mm_set1_epi8, _mm_slli_si128, _mm_storeu_si128,
+        _mm_sub_epi8,
+    };
+
+    /// Loads 16 bytes starting at `bytes[index]`.
+    fn load(bytes: &[u8], index: usize) -> __m128i {
+        let vector = &bytes[index..index + VECTOR_LEN];
+        // SAFETY: `vector` is exactly 16 bytes long and `loadu` has no alignment requirement.
+        unsafe { _mm_loadu_si128(vector.as_ptr() as *const __m128i) }
+    }
+
+    /// Stores 16 bytes starting at `bytes[index]`.
+    fn store(bytes: &mut [u8], index: usize, value: __m128i) {
+        let vector = &mut bytes[index..index + VECTOR_LEN];
+        // SAFETY: `vector` is exactly 16 bytes long and `storeu` has no alignment requirement.
+        unsafe { _mm_storeu_si128(vector.as_mut_ptr() as *mut __m128i, value) }
+    }
+
+    /// Encodes whole vectors from `stride` on and returns the index of the first byte left
+    /// for the scalar loop.
+    #[target_feature(enable = "sse2")]
+    pub(super) fn encode(data: &[u8], transformed: &mut [u8], stride: usize) -> usize {
+        let mut index = stride;
+        while index + VECTOR_LEN <= data.len() {
+            let difference = _mm_sub_epi8(load(data, index), load(data, index - stride));
+            store(transformed, index, difference);
+            index += VECTOR_LEN;
+        }
+        index
+    }
+
+    /// Decodes whole vectors from `stride` on and returns the index of the first byte left
+    /// for the scalar loop. Strides from 2 to 15 are left to the scalar loop entirely.
+    #[target_feature(enable = "sse2")]
+    pub(super) fn decode(data: &[u8], transformed: &mut [u8], stride: usize) -> usize {
+        let mut index = stride;
+        if stride == 1 {
+            while index + VECTOR_LEN <= data.len() {
+                // Prefix sum of the 16 residuals: after the steps with shifts of 1, 2, 4 and 8
+                // bytes, every lane holds the sum of itself and all the lanes before it.
+                let mut sum = load(data, index);
+                sum = _mm_add_epi8(sum, _mm_slli_si128::<1>(sum));
+                sum = _mm_add_epi8(sum, _mm_slli_si128::<2>(sum));
+                sum = _mm_add_epi8(sum, _mm_slli_si128::<4>(sum));
+                sum = _mm_add_epi8(sum, _mm_slli_si128::<8>(sum));
+                let carry = _mm_set1_epi8(transformed[index - 1] as i8);
+                store(transformed, index, _mm_add_epi8(sum, carry));
+                index += VECTOR_LEN;
+            }
+        } else if stride >= VECTOR_LEN {
+            while index + VECTOR_LEN <= data.len() {
+                // The source vector ends at or before `index`, so it is already restored.
+                let restored = _mm_add_epi8(load(data, index), load(transformed, index - stride));
+                store(transformed, index, restored);
+                index += VECTOR_LEN;
+            }
+        }
+        index
+    }
+}
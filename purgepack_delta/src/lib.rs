@@ -0,0 +1,1187 @@
+//! The codec of the delta module: delta, XOR, image row filter and timestamp transforms.
+//!
+//! The transforms replace every sample by its difference to a prediction, which turns
+//! smooth or slowly changing data (audio, sensor readings, counters, images) into small
+//! residuals that the entropy coders of the later stages compress far better. The
+//! settings of a transform are held by [`DeltaParameters`] and stored in the header, so
+//! [`decompress`] needs nothing but the file.
+//!
+//! The streaming functions [`compress`] and [`decompress`] work on readers and writers,
+//! [`compress_slice`] and [`decompress_slice`] on data held in memory, and
+//! [`ByteStream`] resumes the byte transform at any position of a stream. The binary
+//! diff/patch of the `patch` module is available as [`create_patch`] and [`apply_patch`].
+//!
+//! The codec lives in its own crate so other programs and modules can use the transforms
+//! without going through the module entry points.
+use shared_files::checksum::{ChecksumReader, ChecksumWriter, Crc32};
+use shared_files::codec::{
+    self, CodecStats, Compressor, Counter, Decompressor, ReadSeek, WriteSeek,
+};
+use shared_files::error::PurgePackError;
+use shared_files::frame::{self, FrameFormat, FrameHeader};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+mod block;
+mod byte_stream;
+mod image;
+mod kernel;
+mod patch;
+mod residual;
+mod timestamp;
+mod zero_run;
+
+pub use byte_stream::ByteStream;
+pub use patch::{PatchSummary, apply_patch, create_patch, create_patch_memory};
+pub use timestamp::check_layout as check_timestamp_layout;
+
+/// The direction of the transformation (Encode or Decode).
+#[derive(Debug, Clone, Copy)]
+pub enum Transform {
+    /// Applies delta encoding (current byte - previous byte). Used for Transformation.
+    Encode,
+    /// Applies delta decoding (current byte + previous byte). Used for inverse transformation.
+    Decode,
+}
+
+/// Module ID (Algorithm Identifier) for the Delta Encoding/Decoding module.
+pub const MODULE_ID: u8 = 0x01;
+/// The version of the header layout and payload format written by this build.
+/// Files with a higher version were written by a newer build and are rejected. Version 1
+/// is the layout of the first release, which wrote no version; see [`read_header`].
+pub const FORMAT_VERSION: u8 = 2;
+/// The frame format of the delta module.
+const FORMAT: FrameFormat = FrameFormat::new(MODULE_ID, "Delta V1", FORMAT_VERSION);
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for format
+/// version + 1 byte for algorithm + 1 byte for order + 2 bytes for stride + 1 byte for word
+/// size + 1 byte for residual coding + 8 bytes for data length + 4 bytes for block size +
+/// 8 bytes for range offset + 4 bytes for row length + 1 byte for pixel size + 4 bytes for the
+/// checksum + 4 bytes for record size + 4 bytes for timestamp offset + 1 byte for post pass +
+/// 1 byte for seed mode).
+pub const HEADER_SIZE: u64 = 51;
+/// The size of the header of the first release (4 bytes for magic + 1 byte for module ID).
+/// The payload follows it directly, without a format version.
+const BASELINE_HEADER_SIZE: usize = 5;
+/// The highest byte after the module ID that is read as a format version. Files of the
+/// first release have the first byte of their payload there, so any other byte marks one.
+const NEWEST_VERSION_BYTE: u8 = 0x0F;
+/// The offset of the checksum in the header. The checksum is written after the payload,
+/// once all the data has been read.
+const CHECKSUM_OFFSET: u64 = 37;
+// The PurgePack header contains a magic number (4 bytes), a module ID (1 byte), the format
+// version (1 byte), the parameters of the delta transform (algorithm: 1 byte, order: 1 byte,
+// stride: 2 bytes little-endian, word size: 1 byte, residual coding: 1 byte), the length of
+// the untransformed data (8 bytes little-endian), the block size (4 bytes little-endian,
+// 0 = a single stream), the number of untransformed bytes stored before the transformed range
+// (8 bytes little-endian), the image row layout (row length: 4 bytes little-endian,
+// 0 = not an image; pixel size: 1 byte), the CRC32 of the data restored by the inverse
+// transform (4 bytes little-endian), the record layout of timestamp mode (record size and
+// timestamp offset: 4 bytes little-endian each), the post pass (1 byte) and the seed mode
+// (1 byte).
+/// The settings, lengths and checksum stored in the header.
+pub struct DeltaHeader {
+    /// The parameters of the transform.
+    pub parameters: DeltaParameters,
+    /// The length in bytes of the transformed range of the data.
+    pub data_len: u64,
+    /// The number of untransformed bytes stored before the transformed range.
+    pub range_offset: u64,
+    /// The CRC32 of the data restored by the inverse transform.
+    pub checksum: u32,
+    /// `true` for a file of the first release, whose header holds only the magic number
+    /// and the module ID. Its payload is a first-order byte delta up to the end of the file,
+    /// without lengths or checksum, so both lengths and the checksum above are 0.
+    pub baseline: bool,
+}
+
+/// The algorithm a payload is transformed with.
+///
+/// The algorithm is stored as a single byte in the header. Its id is the index into
+/// `Algorithm::ALL`, so new algorithms can be appended without changing the header layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Every sample is replaced by its wrapping difference to the previous sample.
+    #[default]
+    Delta,
+    /// Every sample is replaced by its XOR with the previous sample.
+    Xor,
+    /// Image rows are filtered with PNG-style predictors (see the `image` module).
+    RowFilter,
+    /// Timestamps inside fixed-size records are stored as varint delta-of-deltas
+    /// (see the `timestamp` module).
+    Timestamp,
+}
+
+impl Algorithm {
+    /// The algorithms known to this build, indexed by their id.
+    const ALL: [Algorithm; 4] = [
+        Algorithm::Delta,
+        Algorithm::Xor,
+        Algorithm::RowFilter,
+        Algorithm::Timestamp,
+    ];
+
+    /// Returns the id stored in the header for this algorithm.
+    fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the algorithm with the given id, or `None` if it is unknown to this build.
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+/// A second pass applied to the transformed payload, inside the same file.
+///
+/// Like the algorithm, the post pass is stored as its index into `PostPass::ALL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PostPass {
+    /// The payload is stored as the transform writes it.
+    #[default]
+    None,
+    /// Runs of zero bytes are run-length encoded (see the `zero_run` module).
+    Rle0,
+}
+
+impl PostPass {
+    /// The post passes known to this build, indexed by their id.
+    const ALL: [PostPass; 2] = [PostPass::None, PostPass::Rle0];
+
+    /// Returns the id stored in the header for this post pass.
+    fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the post pass with the given id, or `None` if it is unknown to this build.
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+/// Seed mode byte for a single seed sample at the start of every stream.
+const SEED_SAMPLE: u8 = 0x00;
+/// Seed mode byte for one seed sample per lane (`stride` samples, one full record).
+const SEED_BLOCK: u8 = 0x01;
+
+/// Residual coding byte for residuals stored with the full sample width.
+const RESIDUAL_RAW: u8 = 0x00;
+/// Residual coding byte for zigzag-mapped LEB128 varint residuals.
+const RESIDUAL_VARINT: u8 = 0x01;
+
+/// The settings of the delta transform. They are chosen by the caller when encoding and
+/// read back from the header when decoding.
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaParameters {
+    /// The algorithm the payload is transformed with.
+    pub algorithm: Algorithm,
+    /// How many times the delta is applied (1 = first-order, 2 = delta of deltas, ...).
+    pub order: u8,
+    /// The distance in samples between a sample and the sample it is differenced against.
+    pub stride: u16,
+    /// The width of a sample in bytes (1, 2, 4 or 8, little-endian).
+    pub word_size: u8,
+    /// Whether the residuals are zigzag-mapped and stored as LEB128 varints.
+    pub varint: bool,
+    /// The number of untransformed bytes per independently decodable block,
+    /// or 0 to transform the data as a single stream.
+    pub block_size: u32,
+    /// The number of bytes per image row filtered with PNG-style row filters,
+    /// or 0 to apply the plain delta transform.
+    pub row_len: u32,
+    /// The number of bytes per pixel of the image (the distance to the left neighbour of a byte).
+    pub pixel_size: u8,
+    /// The number of bytes per record in timestamp mode.
+    pub record_size: u32,
+    /// The offset of the 64-bit timestamp inside a record in timestamp mode.
+    pub field_offset: u32,
+    /// The pass applied to the transformed payload.
+    pub post_pass: PostPass,
+    /// Whether every stream starts with one seed sample per lane instead of a single seed.
+    pub seed_block: bool,
+}
+
+impl Default for DeltaParameters {
+    fn default() -> Self {
+        DeltaParameters {
+            algorithm: Algorithm::Delta,
+            order: 1,
+            stride: 1,
+            word_size: 1,
+            varint: false,
+            block_size: 0,
+            row_len: 0,
+            pixel_size: 1,
+            record_size: 8,
+            field_offset: 0,
+            post_pass: PostPass::None,
+            seed_block: false,
+        }
+    }
+}
+
+impl DeltaParameters {
+    /// Returns `true` if the payload uses the plain size-preserving byte transform,
+    /// which is processed chunk by chunk with `transform_data_chunk`.
+    fn is_byte_transform(&self) -> bool {
+        self.word_size == 1 && !self.varint
+    }
+
+    /// Returns the number of seed samples stored verbatim at the start of every stream.
+    fn seed_len(&self) -> u64 {
+        if self.seed_block {
+            self.stride as u64
+        } else {
+            1
+        }
+    }
+}
+
+/// The running state of the delta transform, carried from one data chunk to the next.
+///
+/// `T` is the sample type: `u8` for the byte transform, `u64` for word mode.
+struct DeltaState<T> {
+    /// The previous value of every level of the transform for every lane,
+    /// stored as `order` rows of `stride` lanes.
+    previous_values: Vec<T>,
+    /// The number of lanes (the stride of the transform).
+    stride: usize,
+    /// The lane of the next byte, i.e. its position in the stream modulo the stride.
+    lane: usize,
+    /// Whether samples are combined with XOR instead of subtraction.
+    xor: bool,
+}
+
+impl<T: Copy> DeltaState<T> {
+    /// Creates the state that follows the seed samples. `seeds` must not be empty.
+    ///
+    /// Samples before the start of the stream are treated as equal to the seed of their
+    /// lane, so every level of a lane starts from that seed. Lanes without a seed of their
+    /// own (all but the first with a single seed) use the first seed. The seeds occupy the
+    /// positions up to `seeds.len() - 1`, so the next position is the first transformed sample.
+    fn new(seeds: &[T], parameters: DeltaParameters) -> Self {
+        let stride = parameters.stride as usize;
+        let lane_seeds: Vec<T> = (0..stride)
+            .map(|lane| *seeds.get(lane).unwrap_or(&seeds[0]))
+            .collect();
+        DeltaState {
+            previous_values: lane_seeds.repeat(parameters.order as usize),
+            stride,
+            lane: seeds.len() % stride,
+            xor: parameters.algorithm == Algorithm::Xor,
+        }
+    }
+}
+
+/// The highest delta order.
+pub const MAX_ORDER: u8 = 8;
+/// The largest block size of block mode (1 GiB).
+pub const MAX_BLOCK_SIZE: u32 = 1 << 30;
+/// The largest pixel size in bytes of image mode (four 16-bit channels).
+pub const MAX_PIXEL_SIZE: u8 = 8;
+
+/// Transforms the data `reader` holds into `writer`.
+///
+/// The header is written with a zero checksum first. Once all the data has been read,
+/// the CRC32 of the data restored by the inverse transform is filled in.
+///
+/// # Arguments
+///
+/// * `reader` - The source, positioned at the first byte stored in the output: the start
+///   of the untransformed prefix, or the start of the transformed range if there is none.
+/// * `writer` - The destination, positioned at the start of the output.
+/// * `parameters` - The parameters of the transform.
+/// * `data_len` - The length in bytes of the transformed range of the data.
+/// * `range_offset` - The number of untransformed bytes stored before the transformed range.
+/// * `copy_suffix` - Whether the rest of the input after the range is copied through.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// input ends before the transformed range does.
+pub fn compress<R: BufRead, W: Write + Seek>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    range_offset: u64,
+    copy_suffix: bool,
+) -> io::Result<()> {
+    let header = DeltaHeader {
+        parameters,
+        data_len,
+        range_offset,
+        checksum: 0,
+        baseline: false,
+    };
+    write_header(writer, &header)?;
+    let mut checksum_reader = ChecksumReader::new(reader, Crc32::new());
+    transform_payload(
+        &mut checksum_reader,
+        writer,
+        parameters,
+        data_len,
+        range_offset,
+        copy_suffix,
+        Transform::Encode,
+    )?;
+    writer.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    writer.write_all(&checksum_reader.hasher().value().to_le_bytes())?;
+    writer.flush()
+}
+
+/// Restores the data following the header into `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The transformed data, positioned right after the header.
+/// * `writer` - The destination of the restored data.
+/// * `header` - The header read by [`read_header`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// data is truncated or the restored data does not match the checksum.
+pub fn decompress<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &DeltaHeader,
+) -> io::Result<()> {
+    if header.baseline {
+        return decompress_baseline(reader, writer);
+    }
+    let mut checksum_writer = ChecksumWriter::new(&mut *writer, Crc32::new());
+    transform_payload(
+        reader,
+        &mut checksum_writer,
+        header.parameters,
+        header.data_len,
+        header.range_offset,
+        true,
+        Transform::Decode,
+    )?;
+    let checksum = checksum_writer.hasher().value();
+    if checksum != header.checksum {
+        return Err(PurgePackError::ChecksumMismatch {
+            expected: header.checksum,
+            actual: checksum,
+        }
+        .into());
+    }
+    writer.flush()
+}
+
+/// Restores the payload of a file of the first release: the first byte is stored as it
+/// is, and every other byte as its wrapping difference to the byte before it.
+fn decompress_baseline<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<()> {
+    let mut previous = 0u8;
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let restored: Vec<u8> = chunk
+            .iter()
+            .map(|&delta| {
+                previous = delta.wrapping_add(previous);
+                previous
+            })
+            .collect();
+        writer.write_all(&restored)?;
+        let len = chunk.len();
+        reader.consume(len);
+    }
+    writer.flush()
+}
+
+/// Transforms all of `data` with `parameters` and returns the output, header included.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the transform fails, for example because timestamp mode is
+/// given a record layout that does not fit.
+pub fn compress_slice(data: &[u8], parameters: DeltaParameters) -> io::Result<Vec<u8>> {
+    let mut output = Cursor::new(Vec::with_capacity(HEADER_SIZE as usize + data.len()));
+    compress(
+        &mut &data[..],
+        &mut output,
+        parameters,
+        data.len() as u64,
+        0,
+        false,
+    )?;
+    Ok(output.into_inner())
+}
+
+/// Restores the data of a transformed file held in `data`.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the header is invalid, the data is truncated or the
+/// restored data does not match the checksum.
+pub fn decompress_slice(mut data: &[u8]) -> io::Result<Vec<u8>> {
+    let header = read_header(&mut data)?;
+    let original_len = header
+        .range_offset
+        .checked_add(header.data_len)
+        .ok_or_else(|| {
+            PurgePackError::InvalidHeader(
+                "The lengths in the header add up to more than a file can hold. The file may be corrupted."
+                    .to_string(),
+            )
+        })?;
+    // A damaged header may claim any length, so no more than the input holds is reserved
+    // up front; the output grows past it where the residuals were coded smaller.
+    let mut output = Vec::with_capacity(original_len.min(data.len() as u64) as usize);
+    decompress(&mut data, &mut output, &header)?;
+    Ok(output)
+}
+
+/// The delta transform with its settings, for use through the `Compressor` and
+/// `Decompressor` traits of `shared_files::codec`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delta {
+    /// The settings used when encoding. Decoding uses the settings stored in the header.
+    pub parameters: DeltaParameters,
+}
+
+impl Compressor for Delta {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        codec::compress_in_memory(reader, writer, |input, output| {
+            compress(
+                &mut &input[..],
+                output,
+                self.parameters,
+                input.len() as u64,
+                0,
+                false,
+            )
+        })
+    }
+
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        codec::compress_stream(reader, writer, |reader, writer, input_len| {
+            compress(reader, writer, self.parameters, input_len, 0, false)
+        })
+    }
+}
+
+impl Decompressor for Delta {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut reader = BufReader::new(Counter::new(reader));
+        let mut writer = Counter::new(writer);
+        let header = read_header(&mut reader)?;
+        decompress(&mut reader, &mut writer, &header)?;
+        Ok(CodecStats {
+            input_len: reader.get_ref().count(),
+            output_len: writer.count(),
+        })
+    }
+}
+
+/// Transforms everything after the header: the untransformed bytes before the range,
+/// the payload and, if `copy_suffix` is set, the untransformed bytes after it.
+///
+/// # Arguments
+///
+/// * `reader` - The reader, positioned right after the header (or at the start of the
+///   range when a cropped output is encoded).
+/// * `writer` - The writer, positioned right after the header.
+/// * `parameters` - The delta parameters.
+/// * `data_len` - The length in bytes of the transformed range of the data.
+/// * `range_offset` - The number of untransformed bytes stored before the transformed range.
+/// * `copy_suffix` - Whether the rest of the input after the range is copied through.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// input ends early.
+fn transform_payload<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    range_offset: u64,
+    copy_suffix: bool,
+    transform_type: Transform,
+) -> Result<(), io::Error> {
+    let copied = io::copy(&mut reader.take(range_offset), writer)?;
+    if copied != range_offset {
+        return Err(PurgePackError::corrupt_at(
+            copied,
+            "The file ended before the start of the transformed range.",
+        )
+        .into());
+    }
+
+    match (parameters.post_pass, transform_type) {
+        (PostPass::None, _) => {
+            transform_range(reader, writer, parameters, data_len, transform_type)?;
+        }
+        (PostPass::Rle0, Transform::Encode) => {
+            let mut zero_run_writer = zero_run::ZeroRunWriter::new(&mut *writer);
+            transform_range(
+                reader,
+                &mut zero_run_writer,
+                parameters,
+                data_len,
+                transform_type,
+            )?;
+            zero_run_writer.finish()?;
+        }
+        (PostPass::Rle0, Transform::Decode) => {
+            let mut zero_run_reader = zero_run::ZeroRunReader::new(&mut *reader);
+            transform_range(
+                &mut zero_run_reader,
+                writer,
+                parameters,
+                data_len,
+                transform_type,
+            )?;
+        }
+    }
+    if copy_suffix {
+        io::copy(reader, writer)?;
+    }
+    Ok(())
+}
+
+/// Transforms the range as a single stream or as independent blocks.
+fn transform_range<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    transform_type: Transform,
+) -> Result<(), io::Error> {
+    if parameters.block_size == 0 {
+        transform_stream(reader, writer, parameters, data_len, transform_type)
+    } else {
+        block::transform_blocks(reader, writer, parameters, data_len, transform_type)
+    }
+}
+
+/// Transforms one payload stream: the seed followed by the rest of the data.
+///
+/// The plain byte transform writes the seed bytes with `set_delta_seed` and then loops,
+/// reading the input in buffered chunks (`fill_buf`) and passing them to
+/// `transform_data_chunk`, stopping after `data_len` bytes. Image mode is handled by
+/// `image::transform_rows`, timestamp mode by `timestamp::transform_records` and word mode
+/// by `residual::transform_words`.
+///
+/// # Arguments
+///
+/// * `reader` - The buffered reader, positioned at the start of the payload.
+/// * `writer` - The writer for the transformed payload.
+/// * `parameters` - The parameters of the delta transform.
+/// * `data_len` - The length in bytes of the untransformed data in this stream.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// input ends before `data_len` bytes have been processed.
+fn transform_stream<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    transform_type: Transform,
+) -> Result<(), io::Error> {
+    match parameters.algorithm {
+        Algorithm::RowFilter => {
+            return image::transform_rows(reader, writer, parameters, data_len, transform_type);
+        }
+        Algorithm::Timestamp => {
+            return timestamp::transform_records(
+                reader,
+                writer,
+                parameters,
+                data_len,
+                transform_type,
+            );
+        }
+        Algorithm::Delta | Algorithm::Xor => {}
+    }
+    if !parameters.is_byte_transform() {
+        return residual::transform_words(reader, writer, parameters, data_len, transform_type);
+    }
+
+    // The byte transform has the same length in both directions, so the stream ends
+    // after exactly `data_len` bytes.
+    let mut reader = reader.take(data_len);
+    let seed_len = parameters.seed_len().min(data_len);
+    let seeds = set_delta_seed(&mut reader, writer, seed_len)?;
+    if seeds.len() as u64 != seed_len {
+        return Err(truncated_stream(seeds.len() as u64, data_len));
+    }
+    if seeds.is_empty() {
+        return Ok(());
+    }
+    let mut delta_state = DeltaState::new(&seeds, parameters);
+    let mut processed = seed_len;
+    loop {
+        let current_chunk = reader.fill_buf()?;
+        let chunk_length = current_chunk.len();
+        if current_chunk.is_empty() {
+            break;
+        }
+        transform_data_chunk(current_chunk, writer, &mut delta_state, transform_type)?;
+        reader.consume(chunk_length);
+        processed += chunk_length as u64;
+    }
+    if processed != data_len {
+        return Err(truncated_stream(processed, data_len));
+    }
+    Ok(())
+}
+
+/// Creates the error reported when a stream ends after `processed` of `data_len` bytes.
+fn truncated_stream(processed: u64, data_len: u64) -> io::Error {
+    PurgePackError::corrupt_at(
+        processed,
+        format!(
+            "The data ended after {} of {} bytes. The file may be incomplete or corrupted.",
+            processed, data_len
+        ),
+    )
+    .into()
+}
+
+/// Performs the delta encoding or decoding on a single chunk of data.
+///
+/// The transformation is done byte-by-byte, with the result of each step
+/// depending on the calculated value of the previous byte. The operation uses
+/// **wrapping arithmetic** (`wrapping_sub`/`wrapping_add`) to prevent panic on
+/// overflow/underflow. We treat the bytes as cyclic unsigned 8-bit integers (`u8`),
+/// where the valid range is $0$ to $255$. This means we avoid signed values;
+/// for example, a subtraction that results in $-3$ (like $12-15$) automatically wraps to $253$,
+/// and an addition that overflows $255$ automatically wraps back towards $0$.
+///
+/// Higher orders are applied as a cascade of first-order transforms: an order-2
+/// encode takes the delta of the deltas, and the matching decode integrates twice.
+///
+/// With a stride greater than one, each byte is differenced against the byte `stride`
+/// positions earlier instead of the immediately preceding one. The stream is split into
+/// `stride` interleaved lanes (e.g. the R, G and B channels of a pixel), and every level
+/// of every lane keeps its own previous value.
+///
+/// # Arguments
+///
+/// * `data` - The slice of bytes to be transformed (either original data or deltas).
+/// * `buff_writer` - The buffered writer to output the results.
+/// * `delta_state` - The preceding value of every level and lane of the transform (the seeds)
+///   and the lane of the first byte in `data`. It is updated in place so it can be carried
+///   into the next data chunk.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if writing the transformed data fails.
+///
+/// ```rust
+/// use std::io::{self, Cursor, BufWriter, Write};
+///
+/// // Internal types and helper to test the logic without file creation.
+/// #[derive(Debug, Clone, Copy)]
+/// enum Transform { Encode, Decode }
+///
+/// fn transform_chunk_logic<W: Write>(
+///     data: &[u8],
+///     buff_writer: &mut BufWriter<W>,
+///     previous_values: &mut [u8],
+///     stride: usize,
+///     mut lane: usize,
+///     transform_type: Transform,
+/// ) -> io::Result<()> {
+///     let mut transformed = Vec::with_capacity(data.len());
+///     for &current_byte in data.iter() {
+///         let mut value = current_byte;
+///         let levels = previous_values.iter_mut().skip(lane).step_by(stride);
+///         match transform_type {
+///             Transform::Encode => {
+///                 for previous in levels {
+///                     let delta_change = value.wrapping_sub(*previous);
+///                     *previous = value;
+///                     value = delta_change;
+///                 }
+///             }
+///             Transform::Decode => {
+///                 for previous in levels.rev() {
+///                     value = value.wrapping_add(*previous);
+///                     *previous = value;
+///                 }
+///             }
+///         }
+///         transformed.push(value);
+///         lane = (lane + 1) % stride;
+///     }
+///     buff_writer.write_all(&transformed)
+/// }
+///
+/// let original_data: Vec<u8> = vec![15, 12, 16];
+/// let initial_seed: u8 = 10;
+///
+/// // 1. First-order encode: [15, 12, 16] -> [5, 253, 4] (Delta bytes)
+/// let mut encoded_output = Cursor::new(Vec::new());
+/// let mut encoded_writer = BufWriter::new(&mut encoded_output);
+/// let mut seeds = [initial_seed];
+/// transform_chunk_logic(&original_data, &mut encoded_writer, &mut seeds, 1, 0, Transform::Encode)?;
+/// encoded_writer.flush()?;
+/// drop(encoded_writer);
+/// let delta_bytes = encoded_output.into_inner();
+///
+/// assert_eq!(delta_bytes, vec![5, 253, 4]);
+/// assert_eq!(seeds, [16]);
+///
+/// // 2. First-order decode: [5, 253, 4] -> [15, 12, 16] (Original bytes recovered)
+/// let mut decoded_output = Cursor::new(Vec::new());
+/// let mut decoded_writer = BufWriter::new(&mut decoded_output);
+/// let mut seeds = [initial_seed];
+/// transform_chunk_logic(&delta_bytes, &mut decoded_writer, &mut seeds, 1, 0, Transform::Decode)?;
+/// decoded_writer.flush()?;
+/// drop(decoded_writer);
+///
+/// assert_eq!(decoded_output.into_inner(), original_data);
+///
+/// // 3. Second-order encode of a linear ramp leaves only zeros after the first step.
+/// let ramp: Vec<u8> = vec![10, 13, 16, 19, 22];
+/// let mut encoded_output = Cursor::new(Vec::new());
+/// let mut encoded_writer = BufWriter::new(&mut encoded_output);
+/// let mut seeds = [ramp[0]; 2];
+/// transform_chunk_logic(&ramp[1..], &mut encoded_writer, &mut seeds, 1, 0, Transform::Encode)?;
+/// encoded_writer.flush()?;
+/// drop(encoded_writer);
+/// let delta_bytes = encoded_output.into_inner();
+///
+/// assert_eq!(delta_bytes, vec![249, 0, 0, 0]);
+///
+/// // 4. Second-order decode integrates twice to restore the ramp.
+/// let mut decoded_output = Cursor::new(Vec::new());
+/// let mut decoded_writer = BufWriter::new(&mut decoded_output);
+/// let mut seeds = [ramp[0]; 2];
+/// transform_chunk_logic(&delta_bytes, &mut decoded_writer, &mut seeds, 1, 0, Transform::Decode)?;
+/// decoded_writer.flush()?;
+/// drop(decoded_writer);
+///
+/// assert_eq!(decoded_output.into_inner(), ramp[1..].to_vec());
+///
+/// // 5. A stride of 3 differences each RGB channel against the same channel of the
+/// //    previous pixel, so a smooth gradient per channel becomes small constant deltas.
+/// let pixels: Vec<u8> = vec![100, 50, 200, 101, 52, 203, 102, 54, 206];
+/// let mut encoded_output = Cursor::new(Vec::new());
+/// let mut encoded_writer = BufWriter::new(&mut encoded_output);
+/// let mut seeds = [0u8; 3];
+/// transform_chunk_logic(&pixels, &mut encoded_writer, &mut seeds, 3, 0, Transform::Encode)?;
+/// encoded_writer.flush()?;
+/// drop(encoded_writer);
+///
+/// assert_eq!(encoded_output.into_inner()[3..], [1, 2, 3, 1, 2, 3]);
+/// # Ok::<(), io::Error>(())
+/// ```
+fn transform_data_chunk<W: Write>(
+    data: &[u8],
+    buff_writer: &mut W,
+    delta_state: &mut DeltaState<u8>,
+    transform_type: Transform,
+) -> io::Result<()> {
+    let stride = delta_state.stride;
+    // A single level of plain differences has a vectorized kernel.
+    if delta_state.previous_values.len() == stride && !delta_state.xor {
+        let transformed = kernel::transform_order1(data, delta_state, transform_type);
+        return buff_writer.write_all(&transformed);
+    }
+    // The whole chunk is transformed into a buffer first and written with a single call.
+    let mut transformed = Vec::with_capacity(data.len());
+    for &current_byte in data.iter() {
+        let mut value = current_byte;
+        // The previous values of this lane, one per level of the transform.
+        let levels = delta_state
+            .previous_values
+            .iter_mut()
+            .skip(delta_state.lane)
+            .step_by(stride);
+        match (transform_type, delta_state.xor) {
+            (Transform::Encode, false) => {
+                for previous in levels {
+                    let delta_change = value.wrapping_sub(*previous);
+                    *previous = value;
+                    value = delta_change;
+                }
+            }
+            (Transform::Decode, false) => {
+                for previous in levels.rev() {
+                    value = value.wrapping_add(*previous);
+                    *previous = value;
+                }
+            }
+            // XOR is its own inverse, only the order of the levels differs.
+            (Transform::Encode, true) => {
+                for previous in levels {
+                    let delta_change = value ^ *previous;
+                    *previous = value;
+                    value = delta_change;
+                }
+            }
+            (Transform::Decode, true) => {
+                for previous in levels.rev() {
+                    value ^= *previous;
+                    *previous = value;
+                }
+            }
+        }
+        transformed.push(value);
+        delta_state.lane = (delta_state.lane + 1) % stride;
+    }
+
+    buff_writer.write_all(&transformed)
+}
+
+// Reads the first bytes from the input stream and writes them directly to the output stream.
+///
+/// These first bytes act as the delta seeds for the rest of the transformation process:
+/// a single byte, or one byte per lane in seed block mode.
+///
+/// # Arguments
+///
+/// * `buff_reader` - The buffered reader for the input file.
+/// * `buff_writer` - The buffered writer for the output file.
+/// * `seed_len` - The number of seed bytes to copy.
+///
+/// # Returns
+///
+/// Returns the seed bytes, which are fewer than `seed_len` if the input ends early
+/// (none if it is empty).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing the seed bytes fails.
+fn set_delta_seed<R: Read, W: Write>(
+    buff_reader: &mut R,
+    buff_writer: &mut W,
+    seed_len: u64,
+) -> Result<Vec<u8>, io::Error> {
+    let mut seeds = Vec::with_capacity(seed_len as usize);
+    buff_reader.take(seed_len).read_to_end(&mut seeds)?;
+    buff_writer.write_all(&seeds)?;
+    Ok(seeds)
+}
+/// Writes the PurgePack header (Magic Number, Module ID and delta parameters) to the output stream.
+///
+/// # Arguments
+///
+/// * `buff_writer` - The writer for the output.
+/// * `header` - The parameters, lengths and checksum to store.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the header is successfully written, or an `io::Error` if
+/// writing the header fails.
+pub fn write_header<W: Write>(buff_writer: &mut W, header: &DeltaHeader) -> io::Result<()> {
+    let residual_coding = if header.parameters.varint {
+        RESIDUAL_VARINT
+    } else {
+        RESIDUAL_RAW
+    };
+    FORMAT.write_prefix(buff_writer)?;
+    buff_writer.write_all(&[header.parameters.algorithm.id(), header.parameters.order])?;
+    buff_writer.write_all(&header.parameters.stride.to_le_bytes())?;
+    buff_writer.write_all(&[header.parameters.word_size, residual_coding])?;
+    buff_writer.write_all(&header.data_len.to_le_bytes())?;
+    buff_writer.write_all(&header.parameters.block_size.to_le_bytes())?;
+    buff_writer.write_all(&header.range_offset.to_le_bytes())?;
+    buff_writer.write_all(&header.parameters.row_len.to_le_bytes())?;
+    buff_writer.write_all(&[header.parameters.pixel_size])?;
+    buff_writer.write_all(&header.checksum.to_le_bytes())?;
+    buff_writer.write_all(&header.parameters.record_size.to_le_bytes())?;
+    buff_writer.write_all(&header.parameters.field_offset.to_le_bytes())?;
+    buff_writer.write_all(&[header.parameters.post_pass.id()])?;
+    let seed_mode = if header.parameters.seed_block {
+        SEED_BLOCK
+    } else {
+        SEED_SAMPLE
+    };
+    buff_writer.write_all(&[seed_mode])?;
+    Ok(())
+}
+
+/// Reads and validates the PurgePack header from the input stream.
+/// Also determines the delta parameters to use for decoding and the length of the original data.
+///
+/// The first release wrote only the magic number and the module ID, and the payload right
+/// after them. The byte following the module ID is a format version if it is between 2
+/// and `NEWEST_VERSION_BYTE`, and the first byte of such a payload otherwise; it is left
+/// in `buff_reader` then, and the header is returned with `baseline` set. A file of the
+/// first release whose data starts with a byte in that range cannot be told apart from a
+/// newer file and is rejected.
+///
+/// # Arguments
+///
+/// * `buff_reader` - The reader, positioned at the start of the file.
+///
+/// # Returns
+///
+/// Returns the parameters, lengths and checksum stored in the header, or an `io::Error`
+/// if reading or validating the header fails.
+pub fn read_header<R: BufRead>(buff_reader: &mut R) -> io::Result<DeltaHeader> {
+    let read_error = |e: io::Error| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            PurgePackError::Truncated(
+                "Failed to read PurgePack header. File may be too short or corrupted.".to_string(),
+            )
+            .into()
+        } else {
+            e
+        }
+    };
+    // The format version is only looked at first: files of the first release have none,
+    // and their payload starts right after the module ID.
+    let mut prefix = [0u8; frame::PREFIX_LEN];
+    buff_reader
+        .read_exact(&mut prefix[..BASELINE_HEADER_SIZE])
+        .map_err(read_error)?;
+    let next = buff_reader.fill_buf()?.first().copied();
+    let format_version = next.filter(|&byte| (2..=NEWEST_VERSION_BYTE).contains(&byte));
+    prefix[BASELINE_HEADER_SIZE] = format_version.unwrap_or(FORMAT_VERSION);
+    FORMAT.check(FrameHeader::parse(&prefix)?)?;
+    if format_version.is_none() {
+        return Ok(DeltaHeader {
+            parameters: DeltaParameters::default(),
+            data_len: 0,
+            range_offset: 0,
+            checksum: 0,
+            baseline: true,
+        });
+    }
+    buff_reader.consume(1);
+
+    let mut header_bytes = [0u8; HEADER_SIZE as usize - frame::PREFIX_LEN];
+    buff_reader
+        .read_exact(&mut header_bytes)
+        .map_err(read_error)?;
+    let algorithm_id = header_bytes[0];
+    let order = header_bytes[1];
+    let stride = u16::from_le_bytes([header_bytes[2], header_bytes[3]]);
+    let word_size = header_bytes[4];
+    let residual_coding = header_bytes[5];
+    let mut data_len_bytes = [0u8; 8];
+    data_len_bytes.copy_from_slice(&header_bytes[6..14]);
+    let data_len = u64::from_le_bytes(data_len_bytes);
+    let block_size = u32::from_le_bytes([
+        header_bytes[14],
+        header_bytes[15],
+        header_bytes[16],
+        header_bytes[17],
+    ]);
+    let mut range_offset_bytes = [0u8; 8];
+    range_offset_bytes.copy_from_slice(&header_bytes[18..26]);
+    let range_offset = u64::from_le_bytes(range_offset_bytes);
+    let row_len = u32::from_le_bytes([
+        header_bytes[26],
+        header_bytes[27],
+        header_bytes[28],
+        header_bytes[29],
+    ]);
+    let pixel_size = header_bytes[30];
+    let checksum = u32::from_le_bytes([
+        header_bytes[31],
+        header_bytes[32],
+        header_bytes[33],
+        header_bytes[34],
+    ]);
+    let record_size = u32::from_le_bytes([
+        header_bytes[35],
+        header_bytes[36],
+        header_bytes[37],
+        header_bytes[38],
+    ]);
+    let field_offset = u32::from_le_bytes([
+        header_bytes[39],
+        header_bytes[40],
+        header_bytes[41],
+        header_bytes[42],
+    ]);
+    let post_pass_id = header_bytes[43];
+    let seed_mode = header_bytes[44];
+
+    let algorithm = Algorithm::from_id(algorithm_id).ok_or_else(|| {
+        PurgePackError::InvalidHeader(format!(
+                "Unsupported algorithm: 0x{:02X}. The file may have been written by a newer build of the delta module.",
+                algorithm_id
+            ))
+    })?;
+    let post_pass = PostPass::from_id(post_pass_id).ok_or_else(|| {
+        PurgePackError::InvalidHeader(format!(
+                "Unsupported post pass: 0x{:02X}. The file may have been written by a newer build of the delta module.",
+                post_pass_id
+            ))
+    })?;
+    let seed_block = match seed_mode {
+        SEED_SAMPLE => false,
+        SEED_BLOCK => true,
+        _ => {
+            return Err(PurgePackError::InvalidHeader(format!(
+                "Unsupported seed mode: 0x{:02X}.",
+                seed_mode
+            ))
+            .into());
+        }
+    };
+    if algorithm == Algorithm::RowFilter && row_len == 0 {
+        return Err(PurgePackError::InvalidHeader(
+            "Invalid image row length: 0. The file may be corrupted.".to_string(),
+        )
+        .into());
+    }
+
+    if order == 0 || order > MAX_ORDER {
+        return Err(PurgePackError::InvalidHeader(format!(
+            "Unsupported delta order: {}. Supported orders are 1 to {}.",
+            order, MAX_ORDER
+        ))
+        .into());
+    }
+
+    if stride == 0 {
+        return Err(PurgePackError::InvalidHeader(
+            "Invalid delta stride: 0. The stride must be at least 1.".to_string(),
+        )
+        .into());
+    }
+
+    if !matches!(word_size, 1 | 2 | 4 | 8) {
+        return Err(PurgePackError::InvalidHeader(format!(
+            "Unsupported word size: {}. Supported word sizes are 1, 2, 4 and 8.",
+            word_size
+        ))
+        .into());
+    }
+
+    let varint = match residual_coding {
+        RESIDUAL_RAW => false,
+        RESIDUAL_VARINT => true,
+        _ => {
+            return Err(PurgePackError::InvalidHeader(format!(
+                "Unsupported residual coding: 0x{:02X}.",
+                residual_coding
+            ))
+            .into());
+        }
+    };
+
+    if block_size > MAX_BLOCK_SIZE {
+        return Err(PurgePackError::InvalidHeader(format!(
+            "Unsupported block size: {} bytes. The maximum is {} bytes.",
+            block_size, MAX_BLOCK_SIZE
+        ))
+        .into());
+    }
+
+    if pixel_size == 0 || pixel_size > MAX_PIXEL_SIZE {
+        return Err(PurgePackError::InvalidHeader(format!(
+            "Unsupported pixel size: {} bytes. Supported pixel sizes are 1 to {} bytes.",
+            pixel_size, MAX_PIXEL_SIZE
+        ))
+        .into());
+    }
+
+    if algorithm == Algorithm::Timestamp {
+        timestamp::check_layout(record_size, field_offset)
+            .map_err(|e| PurgePackError::InvalidHeader(e.to_string()))?;
+    }
+
+    Ok(DeltaHeader {
+        parameters: DeltaParameters {
+            algorithm,
+            order,
+            stride,
+            word_size,
+            varint,
+            block_size,
+            row_len,
+            pixel_size,
+            record_size,
+            field_offset,
+            post_pass,
+            seed_block,
+        },
+        data_len,
+        range_offset,
+        checksum,
+        baseline: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &Delta::default());
+        report.streams("default", &Delta::default());
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        let text = selftest::sample("text").unwrap();
+        selftest::rejects_damage(&Delta::default(), &text, |len| vec![0, len / 2, len - 1])
+            .unwrap();
+    }
+
+    #[test]
+    fn restores_files_of_the_first_release() {
+        // The magic number, the module ID and the first-order deltas of "Delta", the
+        // first byte stored as it is, as the first release wrote them.
+        let file = b"PPCB\x01\x44\x21\x07\x08\xED";
+        let header = read_header(&mut &file[..]).unwrap();
+        assert!(header.baseline);
+        assert_eq!(decompress_slice(file).unwrap(), b"Delta");
+
+        let mut restored = Vec::new();
+        Delta::default()
+            .decompress(&mut &file[..], &mut restored)
+            .unwrap();
+        assert_eq!(restored, b"Delta");
+
+        // Data starting with 0 or 1, and no data at all.
+        assert_eq!(decompress_slice(b"PPCB\x01\x01\x00").unwrap(), [1, 1]);
+        assert_eq!(decompress_slice(b"PPCB\x01\x00\xFF").unwrap(), [0, 0xFF]);
+        assert!(decompress_slice(b"PPCB\x01").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_format_versions() {
+        let mut file = compress_slice(b"Delta", DeltaParameters::default()).unwrap();
+        assert!(!read_header(&mut &file[..]).unwrap().baseline);
+        file[5] = FORMAT_VERSION + 1;
+        let error = decompress_slice(&file).unwrap_err();
+        assert!(matches!(
+            PurgePackError::find(&error),
+            Some(PurgePackError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_lengths_beyond_the_file_in_memory() {
+        let text = selftest::sample("text").unwrap();
+        let file = compress_slice(&text, DeltaParameters::default()).unwrap();
+        assert_eq!(decompress_slice(&file).unwrap(), text);
+
+        // Lengths far beyond the file, and lengths that overflow once they are added.
+        for (data_len, range_offset) in [(1 << 40, 0u64), (u64::MAX, 0), (u64::MAX, 1)] {
+            let mut damaged = file.clone();
+            damaged[12..20].copy_from_slice(&data_len.to_le_bytes());
+            damaged[24..32].copy_from_slice(&range_offset.to_le_bytes());
+            let error = decompress_slice(&damaged).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", data_len);
+        }
+    }
+}
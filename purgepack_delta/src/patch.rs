@@ -0,0 +1,390 @@
+//! Binary diff/patch mode: describes a new file as a sequence of instructions that copy
+//! ranges of an old file or insert literal bytes.
+//!
+//! The old file is indexed by the hash of every `MATCH_LEN`-byte block at a block-aligned
+//! offset. The new file is then scanned with a rolling hash of the same width. When the
+//! window matches a block of the old file, the match is extended in both directions and
+//! emitted as a copy; the bytes between two copies are emitted as an insert.
+//!
+//! A patch file is laid out as:
+//!
+//! ```text
+//! [magic: "PPDP"][module id: u8][old length: u64 LE][old CRC32: u32 LE]
+//! [new length: u64 LE][new CRC32: u32 LE][instructions ...]
+//! ```
+//!
+//! Every instruction starts with an opcode byte followed by LEB128 varints:
+//!
+//! * `OP_INSERT` - the length, then that many literal bytes.
+//! * `OP_COPY` - the zigzag-coded distance from the end of the previous copy to the start
+//!   of this one in the old file, then the length.
+//!
+//! The instructions end once they have produced the new length. Both files are held in
+//! memory while a patch is created or applied.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use shared_files::checksum::Crc32;
+use shared_files::error::PurgePackError;
+use shared_files::output::SafeWriter;
+use shared_files::varint::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+
+use crate::MODULE_ID;
+
+/// Magic bytes identifying a patch file. PPDP stands for "PurgePack Delta Patch".
+const PATCH_MAGIC: [u8; 4] = *b"PPDP";
+/// The size of the patch header in bytes (4 bytes for magic + 1 byte for module ID
+/// + 8 + 4 bytes for the old length and CRC32 + 8 + 4 bytes for the new length and CRC32).
+const PATCH_HEADER_SIZE: usize = 29;
+/// Opcode of an instruction that inserts literal bytes.
+const OP_INSERT: u8 = 0x00;
+/// Opcode of an instruction that copies a range of the old file.
+const OP_COPY: u8 = 0x01;
+/// The number of bytes a match must cover before it is emitted as a copy.
+const MATCH_LEN: usize = 32;
+/// The multiplier of the polynomial rolling hash.
+const HASH_BASE: u64 = 0x100000001B3;
+
+/// A summary of a created patch, reported to the user.
+#[derive(Debug, Default)]
+pub struct PatchSummary {
+    /// The number of copy instructions.
+    pub copies: u64,
+    /// The number of bytes covered by copy instructions.
+    pub copied_bytes: u64,
+    /// The number of insert instructions.
+    pub inserts: u64,
+    /// The number of literal bytes stored in insert instructions.
+    pub inserted_bytes: u64,
+    /// The size of the patch file in bytes.
+    pub patch_len: u64,
+}
+
+/// Returns the rolling hash of `window`.
+fn hash_window(window: &[u8]) -> u64 {
+    window.iter().fold(0u64, |hash, &byte| {
+        hash.wrapping_mul(HASH_BASE).wrapping_add(byte as u64)
+    })
+}
+
+/// Writes the instructions turning `old` into `new` and updates `summary`.
+fn write_instructions<W: Write>(
+    writer: &mut W,
+    old: &[u8],
+    new: &[u8],
+    summary: &mut PatchSummary,
+) -> io::Result<()> {
+    let mut index = HashMap::new();
+    for (block, window) in old.chunks_exact(MATCH_LEN).enumerate() {
+        // Keep the first block with a given hash.
+        index
+            .entry(hash_window(window))
+            .or_insert(block * MATCH_LEN);
+    }
+    // The weight of the byte leaving the window.
+    let outgoing_weight = (1..MATCH_LEN).fold(1u64, |weight, _| weight.wrapping_mul(HASH_BASE));
+
+    let mut literal_start = 0;
+    let mut copy_end: u64 = 0;
+    let mut position = 0;
+    let mut hash = new.get(..MATCH_LEN).map_or(0, hash_window);
+    while position + MATCH_LEN <= new.len() {
+        let candidate = index.get(&hash).copied().filter(|&old_position| {
+            old[old_position..][..MATCH_LEN] == new[position..][..MATCH_LEN]
+        });
+        let Some(old_position) = candidate else {
+            if position + MATCH_LEN < new.len() {
+                hash = hash
+                    .wrapping_sub((new[position] as u64).wrapping_mul(outgoing_weight))
+                    .wrapping_mul(HASH_BASE)
+                    .wrapping_add(new[position + MATCH_LEN] as u64);
+            }
+            position += 1;
+            continue;
+        };
+
+        // Extend the match backwards into the pending literals and then forwards.
+        let backward = old[..old_position]
+            .iter()
+            .rev()
+            .zip(new[literal_start..position].iter().rev())
+            .take_while(|(old_byte, new_byte)| old_byte == new_byte)
+            .count();
+        let forward = old[old_position + MATCH_LEN..]
+            .iter()
+            .zip(&new[position + MATCH_LEN..])
+            .take_while(|(old_byte, new_byte)| old_byte == new_byte)
+            .count();
+        let copy_start = (old_position - backward) as u64;
+        let copy_len = (backward + MATCH_LEN + forward) as u64;
+
+        write_insert(writer, &new[literal_start..position - backward], summary)?;
+        writer.write_all(&[OP_COPY])?;
+        write_varint(writer, zigzag_encode(copy_start as i64 - copy_end as i64))?;
+        write_varint(writer, copy_len)?;
+        summary.copies += 1;
+        summary.copied_bytes += copy_len;
+
+        copy_end = copy_start + copy_len;
+        position += MATCH_LEN + forward;
+        literal_start = position;
+        if position + MATCH_LEN <= new.len() {
+            hash = hash_window(&new[position..][..MATCH_LEN]);
+        }
+    }
+    write_insert(writer, &new[literal_start..], summary)
+}
+
+/// Writes an insert instruction for `literals`, unless there are none.
+fn write_insert<W: Write>(
+    writer: &mut W,
+    literals: &[u8],
+    summary: &mut PatchSummary,
+) -> io::Result<()> {
+    if literals.is_empty() {
+        return Ok(());
+    }
+    writer.write_all(&[OP_INSERT])?;
+    write_varint(writer, literals.len() as u64)?;
+    writer.write_all(literals)?;
+    summary.inserts += 1;
+    summary.inserted_bytes += literals.len() as u64;
+    Ok(())
+}
+
+/// Returns an estimate of the memory in bytes [`create_patch`] needs for an old file of
+/// `old_len` bytes and a new file of `new_len` bytes: both files and the index of the old
+/// file, which takes about as much memory as the old file itself.
+pub fn create_patch_memory(old_len: u64, new_len: u64) -> u64 {
+    old_len.saturating_mul(2).saturating_add(new_len)
+}
+
+/// Creates a patch that turns `old_file` into `new_file`.
+///
+/// # Arguments
+///
+/// * `old_file` - The path to the file the patch is applied to.
+/// * `new_file` - The path to the file the patch reproduces.
+/// * `patch_file` - The path where the patch is written.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading either file or writing the patch fails.
+pub fn create_patch(
+    old_file: &Path,
+    new_file: &Path,
+    patch_file: &Path,
+) -> io::Result<PatchSummary> {
+    let old = fs::read(old_file)?;
+    let new = fs::read(new_file)?;
+    let mut summary = PatchSummary::default();
+
+    let mut buff_writer = BufWriter::new(SafeWriter::create(patch_file)?);
+    write_patch(&mut buff_writer, &old, &new, &mut summary)?;
+    let patch = buff_writer.into_inner()?.commit()?;
+
+    summary.patch_len = patch.metadata()?.len();
+    Ok(summary)
+}
+
+/// Writes the header and the instructions of the patch turning `old` into `new`.
+fn write_patch<W: Write>(
+    writer: &mut W,
+    old: &[u8],
+    new: &[u8],
+    summary: &mut PatchSummary,
+) -> io::Result<()> {
+    writer.write_all(&PATCH_MAGIC)?;
+    writer.write_all(&[MODULE_ID])?;
+    writer.write_all(&(old.len() as u64).to_le_bytes())?;
+    writer.write_all(&Crc32::of(old).to_le_bytes())?;
+    writer.write_all(&(new.len() as u64).to_le_bytes())?;
+    writer.write_all(&Crc32::of(new).to_le_bytes())?;
+    write_instructions(writer, old, new, summary)
+}
+
+/// Applies a patch created by [`create_patch`] to `old_file` and writes the result.
+///
+/// # Arguments
+///
+/// * `old_file` - The path to the file the patch was created against.
+/// * `patch_file` - The path to the patch.
+/// * `output_file` - The path where the patched file is written.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// patch is malformed, was created against a different old file, or does not reproduce
+/// the new file exactly.
+pub fn apply_patch(old_file: &Path, patch_file: &Path, output_file: &Path) -> io::Result<()> {
+    let new = patched(&fs::read(old_file)?, &fs::read(patch_file)?)?;
+    let mut output = SafeWriter::create(output_file)?;
+    output.write_all(&new)?;
+    output.commit()?;
+    Ok(())
+}
+
+/// Returns the new file `patch` turns `old` into.
+///
+/// The lengths in the header are not trusted: the output grows as the instructions
+/// produce it, and must end up exactly as long as the header says.
+fn patched(old: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: String| io::Error::from(PurgePackError::corrupt(message));
+
+    if patch.len() < PATCH_HEADER_SIZE || patch[..4] != PATCH_MAGIC {
+        return Err(PurgePackError::InvalidHeader(
+            "Invalid patch magic number. This may not be a PurgePack Delta Patch (PPDP) file."
+                .to_string(),
+        )
+        .into());
+    }
+    if patch[4] != MODULE_ID {
+        return Err(PurgePackError::InvalidHeader(format!(
+            "Unsupported module ID: 0x{:02X}. Only 0x{:02X} (Delta V1) is supported.",
+            patch[4], MODULE_ID
+        ))
+        .into());
+    }
+    let u64_at = |at: usize| u64::from_le_bytes(patch[at..at + 8].try_into().unwrap());
+    let u32_at = |at: usize| u32::from_le_bytes(patch[at..at + 4].try_into().unwrap());
+    let (old_len, old_checksum) = (u64_at(5), u32_at(13));
+    let (new_len, new_checksum) = (u64_at(17), u32_at(25));
+
+    if old.len() as u64 != old_len || Crc32::of(old) != old_checksum {
+        return Err(invalid(format!(
+            "The patch was created against a different file. Expected {} bytes with CRC32 0x{:08X}.",
+            old_len, old_checksum
+        )));
+    }
+
+    let truncated = |e: io::Error| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            invalid("The patch is truncated. The file may be incomplete or corrupted.".to_string())
+        } else {
+            e
+        }
+    };
+    let mut instructions = &patch[PATCH_HEADER_SIZE..];
+    // A damaged header may claim any length, so at most what the old file and the
+    // instructions could hold without repeats is reserved up front.
+    let mut new = Vec::with_capacity(new_len.min((old.len() + patch.len()) as u64) as usize);
+    let mut copy_end: u64 = 0;
+    while (new.len() as u64) < new_len {
+        let mut opcode = [0u8; 1];
+        instructions.read_exact(&mut opcode).map_err(truncated)?;
+        match opcode[0] {
+            OP_INSERT => {
+                let len = read_varint(&mut instructions).map_err(truncated)?;
+                let literals = instructions
+                    .get(..len as usize)
+                    .ok_or_else(|| truncated(io::ErrorKind::UnexpectedEof.into()))?;
+                new.extend_from_slice(literals);
+                instructions = &instructions[len as usize..];
+            }
+            OP_COPY => {
+                let distance = zigzag_decode(read_varint(&mut instructions).map_err(truncated)?);
+                let len = read_varint(&mut instructions).map_err(truncated)?;
+                let range = copy_end
+                    .checked_add_signed(distance)
+                    .and_then(|start| Some(start..start.checked_add(len)?))
+                    .filter(|range| range.end <= old_len)
+                    .ok_or_else(|| {
+                        invalid("A copy instruction lies outside the old file.".to_string())
+                    })?;
+                new.extend_from_slice(&old[range.start as usize..range.end as usize]);
+                copy_end = range.end;
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "Unknown patch instruction 0x{:02X}.",
+                    opcode[0]
+                )));
+            }
+        }
+    }
+
+    if new.len() as u64 != new_len || !instructions.is_empty() {
+        return Err(invalid(format!(
+            "The instructions produce {} bytes and leave {} bytes unread, but the header claims {} bytes. The patch may be corrupted.",
+            new.len(),
+            instructions.len(),
+            new_len
+        )));
+    }
+    if Crc32::of(&new) != new_checksum {
+        return Err(invalid(
+            "Checksum mismatch: the patched data does not match the new file. The patch may be corrupted."
+                .to_string(),
+        ));
+    }
+    Ok(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an old file, a new file made of moved, changed and repeated parts of it,
+    /// and the patch between them.
+    fn files() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let old: Vec<u8> = (0..20_000u32).map(|i| (i * 7 + i / 100) as u8).collect();
+        let mut new = old[5_000..12_000].to_vec();
+        new.extend_from_slice(b"some inserted bytes");
+        new.extend_from_slice(&old[..8_000]);
+        new.extend_from_slice(&old[..8_000]);
+        let mut patch = Vec::new();
+        write_patch(&mut patch, &old, &new, &mut PatchSummary::default()).unwrap();
+        (old, new, patch)
+    }
+
+    #[test]
+    fn restores_the_new_file() {
+        let (old, new, patch) = files();
+        assert!(patch.len() < 200);
+        assert_eq!(patched(&old, &patch).unwrap(), new);
+    }
+
+    #[test]
+    fn rejects_a_truncated_patch() {
+        let (old, _, patch) = files();
+        for len in [
+            0,
+            PATCH_HEADER_SIZE - 1,
+            PATCH_HEADER_SIZE,
+            patch.len() / 2,
+            patch.len() - 1,
+        ] {
+            let error = patched(&old, &patch[..len]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_a_patch_with_a_flipped_bit() {
+        let (old, _, patch) = files();
+        for position in 0..patch.len() {
+            for bit in [0, 7] {
+                let mut damaged = patch.clone();
+                damaged[position] ^= 1 << bit;
+                let error = patched(&old, &damaged).unwrap_err();
+                assert_eq!(
+                    error.kind(),
+                    io::ErrorKind::InvalidData,
+                    "bit {} of byte {}",
+                    bit,
+                    position
+                );
+            }
+        }
+
+        // The highest bit of the new length claims far more than memory holds.
+        let mut damaged = patch;
+        damaged[24] ^= 0x80;
+        assert!(patched(&old, &damaged).is_err());
+    }
+}
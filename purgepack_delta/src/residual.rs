@@ -0,0 +1,177 @@
+//! Word-sized delta transform with an optional zigzag + varint residual stage.
+//!
+//! The plain byte transform is size-preserving and only useful as a pre-pass for another
+//! compressor. When the input is made of wider samples (16-bit audio, 32-bit counters,
+//! 64-bit timestamps, ...) the residuals of neighbouring samples are usually small signed
+//! numbers. Mapping them to unsigned values with zigzag coding and writing them as LEB128
+//! varints stores each small residual in a single byte, which makes the output genuinely
+//! smaller than the input.
+//!
+//! The payload produced in word mode is laid out as:
+//!
+//! 1. The seed sample, or one seed sample per lane in seed block mode, stored verbatim
+//!    (`word_size` bytes each, little-endian).
+//! 2. One residual per remaining full sample, stored either raw (`word_size` bytes,
+//!    little-endian) or as a zigzag-mapped LEB128 varint.
+//! 3. The trailing bytes that do not fill a whole sample, stored verbatim.
+
+use shared_files::error::PurgePackError;
+use shared_files::varint::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+use std::io::{self, Read, Write};
+
+use crate::{DeltaParameters, DeltaState, Transform};
+
+/// Interprets the lowest `width` bytes of `value` as a two's complement number and
+/// sign-extends it to 64 bits.
+///
+/// # Examples
+///
+/// ```ignore
+/// assert_eq!(sign_extend(0xFFFF, 2), -1);
+/// assert_eq!(sign_extend(0x7FFF, 2), 32767);
+/// assert_eq!(sign_extend(0x80, 1), -128);
+/// ```
+pub(crate) fn sign_extend(value: u64, width: usize) -> i64 {
+    let shift = 64 - 8 * width as u32;
+    ((value << shift) as i64) >> shift
+}
+
+/// Reads one little-endian sample of `width` bytes.
+fn read_sample<R: Read>(buff_reader: &mut R, width: usize) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    buff_reader.read_exact(&mut bytes[..width])?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Writes the lowest `width` bytes of `value` in little-endian order.
+fn write_sample<W: Write>(buff_writer: &mut W, value: u64, width: usize) -> io::Result<()> {
+    buff_writer.write_all(&value.to_le_bytes()[..width])
+}
+
+impl DeltaState<u64> {
+    /// Runs one sample through every level of the forward transform of its lane and
+    /// returns the residual, truncated to the sample width by `mask`.
+    fn encode(&mut self, sample: u64, mask: u64) -> u64 {
+        let mut value = sample;
+        for previous in self
+            .previous_values
+            .iter_mut()
+            .skip(self.lane)
+            .step_by(self.stride)
+        {
+            let delta_change = if self.xor {
+                value ^ *previous
+            } else {
+                value.wrapping_sub(*previous) & mask
+            };
+            *previous = value;
+            value = delta_change;
+        }
+        self.lane = (self.lane + 1) % self.stride;
+        value
+    }
+
+    /// Integrates one residual through every level of its lane (in reverse order) and
+    /// returns the restored sample, truncated to the sample width by `mask`.
+    fn decode(&mut self, residual: u64, mask: u64) -> u64 {
+        let mut value = residual;
+        for previous in self
+            .previous_values
+            .iter_mut()
+            .skip(self.lane)
+            .step_by(self.stride)
+            .rev()
+        {
+            value = if self.xor {
+                value ^ *previous
+            } else {
+                value.wrapping_add(*previous) & mask
+            };
+            *previous = value;
+        }
+        self.lane = (self.lane + 1) % self.stride;
+        value
+    }
+}
+
+/// Transforms the payload in word mode (see the module documentation for the layout).
+///
+/// # Arguments
+///
+/// * `buff_reader` - The buffered reader, positioned right after the header.
+/// * `buff_writer` - The buffered writer, positioned right after the header.
+/// * `parameters` - The delta parameters, including the sample width and residual coding.
+/// * `data_len` - The length in bytes of the untransformed data.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails. A stream that ends before all
+/// samples described by `data_len` have been decoded is reported as `InvalidData`.
+pub(crate) fn transform_words<R: Read, W: Write>(
+    buff_reader: &mut R,
+    buff_writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    transform_type: Transform,
+) -> io::Result<()> {
+    let width = parameters.word_size as usize;
+    let mask = if width == 8 {
+        u64::MAX
+    } else {
+        (1u64 << (8 * width)) - 1
+    };
+    let full_samples = data_len / width as u64;
+    let tail_len = data_len % width as u64;
+
+    let truncated = |e: io::Error| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            PurgePackError::corrupt(
+                "The transformed data is truncated. The file may be incomplete or corrupted.",
+            )
+            .into()
+        } else {
+            e
+        }
+    };
+
+    if full_samples > 0 {
+        let seed_len = parameters.seed_len().min(full_samples);
+        let mut seeds = Vec::with_capacity(seed_len as usize);
+        for _ in 0..seed_len {
+            let seed = read_sample(buff_reader, width).map_err(truncated)?;
+            write_sample(buff_writer, seed, width)?;
+            seeds.push(seed);
+        }
+        let mut delta_state = DeltaState::new(&seeds, parameters);
+
+        for _ in seed_len..full_samples {
+            match transform_type {
+                Transform::Encode => {
+                    let sample = read_sample(buff_reader, width)?;
+                    let residual = delta_state.encode(sample, mask);
+                    if parameters.varint {
+                        write_varint(buff_writer, zigzag_encode(sign_extend(residual, width)))?;
+                    } else {
+                        write_sample(buff_writer, residual, width)?;
+                    }
+                }
+                Transform::Decode => {
+                    let residual = if parameters.varint {
+                        zigzag_decode(read_varint(buff_reader).map_err(truncated)?) as u64 & mask
+                    } else {
+                        read_sample(buff_reader, width).map_err(truncated)?
+                    };
+                    let sample = delta_state.decode(residual, mask);
+                    write_sample(buff_writer, sample, width)?;
+                }
+            }
+        }
+    }
+
+    let copied = io::copy(&mut buff_reader.take(tail_len), buff_writer)?;
+    if copied != tail_len {
+        return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Timestamp mode: delta-of-delta coding of 64-bit timestamps inside fixed-size records.
+//!
+//! Timestamps written at a (nearly) regular interval have a (nearly) constant delta, so the
+//! difference between two consecutive deltas is zero most of the time. Like Gorilla, this
+//! mode stores that delta-of-delta, zigzag-mapped and written as an LEB128 varint, which
+//! takes a single byte whenever the interval stays the same or jitters slightly.
+//!
+//! The input is a sequence of records of `record_size` bytes, each holding a little-endian
+//! 64-bit timestamp at `field_offset`. The other fields of a record are copied verbatim,
+//! so the payload is laid out as:
+//!
+//! 1. The first record, stored verbatim.
+//! 2. For every following record: the delta-of-delta of its timestamp as a varint,
+//!    followed by the other fields of the record.
+//! 3. The trailing bytes that do not fill a whole record, stored verbatim.
+
+use shared_files::error::PurgePackError;
+use shared_files::varint::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+use std::io::{self, Read, Write};
+
+use crate::{DeltaParameters, Transform};
+
+/// The width of a timestamp in bytes.
+const TIMESTAMP_LEN: u32 = 8;
+
+/// Checks that a record of `record_size` bytes can hold a timestamp at `field_offset`.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the timestamp does not fit inside the record.
+pub fn check_layout(record_size: u32, field_offset: u32) -> io::Result<()> {
+    if field_offset
+        .checked_add(TIMESTAMP_LEN)
+        .is_none_or(|field_end| field_end > record_size)
+    {
+        return Err(PurgePackError::InvalidInput(format!(
+            "A 64-bit timestamp at offset {} does not fit in a record of {} bytes.",
+            field_offset, record_size
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Transforms the payload in timestamp mode (see the module documentation for the layout).
+///
+/// # Arguments
+///
+/// * `reader` - The reader, positioned right after the header.
+/// * `writer` - The writer, positioned right after the header.
+/// * `parameters` - The delta parameters, including the record size and timestamp offset.
+/// * `data_len` - The length in bytes of the untransformed data.
+/// * `transform_type` - The direction of the operation (`Encode` or `Decode`).
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails. A stream that ends before all
+/// records described by `data_len` have been decoded is reported as `InvalidData`.
+pub(crate) fn transform_records<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    parameters: DeltaParameters,
+    data_len: u64,
+    transform_type: Transform,
+) -> io::Result<()> {
+    let record_size = parameters.record_size as u64;
+    let field_start = parameters.field_offset as usize;
+    let field_end = field_start + TIMESTAMP_LEN as usize;
+    let records = data_len / record_size;
+    let tail_len = data_len % record_size;
+
+    let truncated = |e: io::Error| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            PurgePackError::corrupt(
+                "The transformed records are truncated. The file may be incomplete or corrupted.",
+            )
+            .into()
+        } else {
+            e
+        }
+    };
+
+    let mut record = vec![0u8; record_size as usize];
+    let mut previous_timestamp: u64 = 0;
+    let mut previous_delta: u64 = 0;
+    for index in 0..records {
+        match transform_type {
+            Transform::Encode => {
+                reader.read_exact(&mut record)?;
+                let timestamp =
+                    u64::from_le_bytes(record[field_start..field_end].try_into().unwrap());
+                if index == 0 {
+                    writer.write_all(&record)?;
+                } else {
+                    let delta = timestamp.wrapping_sub(previous_timestamp);
+                    let delta_of_delta = delta.wrapping_sub(previous_delta) as i64;
+                    write_varint(writer, zigzag_encode(delta_of_delta))?;
+                    writer.write_all(&record[..field_start])?;
+                    writer.write_all(&record[field_end..])?;
+                    previous_delta = delta;
+                }
+                previous_timestamp = timestamp;
+            }
+            Transform::Decode => {
+                if index == 0 {
+                    reader.read_exact(&mut record).map_err(truncated)?;
+                } else {
+                    let delta_of_delta = zigzag_decode(read_varint(reader).map_err(truncated)?);
+                    previous_delta = previous_delta.wrapping_add(delta_of_delta as u64);
+                    let timestamp = previous_timestamp.wrapping_add(previous_delta);
+                    record[field_start..field_end].copy_from_slice(&timestamp.to_le_bytes());
+                    reader
+                        .read_exact(&mut record[..field_start])
+                        .map_err(truncated)?;
+                    reader
+                        .read_exact(&mut record[field_end..])
+                        .map_err(truncated)?;
+                }
+                previous_timestamp =
+                    u64::from_le_bytes(record[field_start..field_end].try_into().unwrap());
+                writer.write_all(&record)?;
+            }
+        }
+    }
+
+    let copied = io::copy(&mut reader.take(tail_len), writer)?;
+    if copied != tail_len {
+        return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Zero-run post pass (`--post rle0`): run-length coding of the zero bytes of the payload.
+//!
+//! The delta of smooth data is mostly zeros, but the transform itself keeps the size of the
+//! data. This pass shortens the payload inside the same file: every run of zero bytes is
+//! replaced by a single `0x00` byte followed by the run length minus one as an LEB128
+//! varint, and all other bytes are stored as they are. A single zero therefore takes two
+//! bytes, while a run of up to 128 zeros also takes two.
+//!
+//! The pass sits between the transform and the file, so it applies to everything the
+//! transform writes for the range, including the block lengths of block mode.
+
+use shared_files::varint::{read_varint, write_varint};
+use std::io::{self, BufRead, Read, Write};
+
+/// The zeros handed out by `ZeroRunReader::fill_buf` while a run is expanded.
+static ZEROS: [u8; 4096] = [0; 4096];
+
+/// A writer that replaces the runs of zero bytes written through it with run tokens.
+///
+/// The last run is only written by [`ZeroRunWriter::finish`] or a flush, so that a run
+/// split across several writes is still stored as one token.
+pub(crate) struct ZeroRunWriter<W: Write> {
+    inner: W,
+    run: u64,
+}
+
+impl<W: Write> ZeroRunWriter<W> {
+    /// Wraps `inner`.
+    pub(crate) fn new(inner: W) -> Self {
+        ZeroRunWriter { inner, run: 0 }
+    }
+
+    /// Writes the pending run of zeros, if any.
+    fn write_run(&mut self) -> io::Result<()> {
+        if self.run > 0 {
+            self.inner.write_all(&[0])?;
+            write_varint(&mut self.inner, self.run - 1)?;
+            self.run = 0;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending run of zeros. Must be called once all the data is written.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.write_run()
+    }
+}
+
+impl<W: Write> Write for ZeroRunWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let zeros = rest.iter().take_while(|&&byte| byte == 0).count();
+            self.run += zeros as u64;
+            rest = &rest[zeros..];
+            if rest.is_empty() {
+                break;
+            }
+            self.write_run()?;
+            let literals = rest
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(rest.len());
+            self.inner.write_all(&rest[..literals])?;
+            rest = &rest[literals..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_run()?;
+        self.inner.flush()
+    }
+}
+
+/// A reader that expands the run tokens written by [`ZeroRunWriter`].
+///
+/// It only reads a token from `inner` once the bytes before it have been consumed, so
+/// `inner` is left right after the last token the caller needed.
+pub(crate) struct ZeroRunReader<R: BufRead> {
+    inner: R,
+    pending_zeros: u64,
+}
+
+impl<R: BufRead> ZeroRunReader<R> {
+    /// Wraps `inner`.
+    pub(crate) fn new(inner: R) -> Self {
+        ZeroRunReader {
+            inner,
+            pending_zeros: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Read for ZeroRunReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for ZeroRunReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pending_zeros == 0 && self.inner.fill_buf()?.first() == Some(&0) {
+            self.inner.consume(1);
+            self.pending_zeros = read_varint(&mut self.inner)?.saturating_add(1);
+        }
+        if self.pending_zeros > 0 {
+            let len = self.pending_zeros.min(ZEROS.len() as u64) as usize;
+            return Ok(&ZEROS[..len]);
+        }
+        // Hand out the literals up to the next run token.
+        let buffer = self.inner.fill_buf()?;
+        let literals = buffer
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(buffer.len());
+        Ok(&buffer[..literals])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pending_zeros > 0 {
+            self.pending_zeros -= amt as u64;
+        } else {
+            self.inner.consume(amt);
+        }
+    }
+}
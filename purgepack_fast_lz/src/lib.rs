@@ -0,0 +1,344 @@
+//! The codec of the fast LZ module: a speed-focused LZ77 compressor in the style of LZ4.
+//!
+//! The input is split into blocks of `BLOCK_SIZE` bytes that are coded independently by
+//! the `lz` module. Every block is framed as:
+//!
+//! ```text
+//! [block length: u32 LE][stored length: u32 LE][stored bytes ...]
+//! ```
+//!
+//! If the highest bit of the stored length is set, coding did not make the block smaller
+//! and its bytes are stored as they are, so incompressible data grows by 8 bytes per
+//! block at most. Blocks that `shared_files::incompressible` reports as already
+//! compressed or encrypted are stored without being coded at all.
+//!
+//! The codec lives in its own crate so other modules can restore PPCB files of this
+//! format in memory, without going through the module entry points.
+use shared_files::checksum::{Crc32, Hasher};
+use shared_files::codec::{
+    self, CodecStats, Compressor, Counter, Decompressor, ReadSeek, WriteSeek,
+};
+use shared_files::frame::FrameFormat;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+mod lz;
+mod stream;
+
+pub use stream::{PpDecoder, PpEncoder};
+
+/// Module ID (Algorithm Identifier) for the fast LZ module.
+pub const MODULE_ID: u8 = 0x04;
+/// The version of the header layout and payload format written by this build.
+pub const FORMAT_VERSION: u8 = 1;
+/// The frame format of the fast LZ module.
+const FORMAT: FrameFormat = FrameFormat::new(MODULE_ID, "Fast LZ", FORMAT_VERSION);
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for
+/// format version + 8 bytes for the original length + 4 bytes for the CRC32 of the
+/// original data).
+const HEADER_SIZE: usize = 18;
+/// The offset of the original length in the header. `PpEncoder` writes it once all the
+/// data has been written.
+const LENGTH_OFFSET: u64 = 6;
+/// The offset of the checksum in the header. It is written once all the data has been read.
+const CHECKSUM_OFFSET: u64 = 14;
+/// The number of bytes per block.
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+/// The bit of the stored length that marks a block stored without coding.
+const STORED_FLAG: u32 = 1 << 31;
+
+/// The length and checksum stored in the header.
+pub struct FastLzHeader {
+    /// The length in bytes of the original data.
+    pub original_len: u64,
+    /// The CRC32 of the original data.
+    pub checksum: u32,
+}
+
+/// Compresses everything `reader` holds into `writer`.
+///
+/// The header is written with a zero checksum first. Once all the blocks have been
+/// written, the CRC32 of the original data is filled in.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the original data.
+/// * `writer` - The destination, positioned at the start of the output.
+/// * `original_len` - The length in bytes of the original data.
+/// * `acceleration` - The initial step between match probes.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+pub fn compress<R: Read, W: Write + Seek>(
+    reader: &mut R,
+    writer: &mut W,
+    original_len: u64,
+    acceleration: u32,
+) -> io::Result<()> {
+    write_header(writer, original_len, 0)?;
+
+    let mut hasher = Crc32::new();
+    let mut match_finder = lz::MatchFinder::new();
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    let mut coded = Vec::with_capacity(BLOCK_SIZE);
+    loop {
+        block.clear();
+        reader.take(BLOCK_SIZE as u64).read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        hasher.update(&block);
+        write_block(writer, &mut match_finder, &block, &mut coded, acceleration)?;
+    }
+    writer.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    writer.write_all(&hasher.value().to_le_bytes())?;
+    writer.flush()
+}
+
+/// Restores the original data of the blocks following the header into `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The compressed data, positioned right after the header.
+/// * `writer` - The destination of the original data.
+/// * `header` - The header read by [`read_header`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if a
+/// block is invalid, the data is truncated or the restored data does not match the
+/// checksum.
+pub fn decompress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &FastLzHeader,
+) -> io::Result<()> {
+    let mut hasher = Crc32::new();
+    let mut stored = Vec::with_capacity(BLOCK_SIZE);
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    let mut restored_len = 0u64;
+    while restored_len < header.original_len {
+        read_block(reader, &mut stored, &mut block)?;
+        restored_len += block.len() as u64;
+        check_restored_len(restored_len, header)?;
+        hasher.update(&block);
+        writer.write_all(&block)?;
+    }
+    writer.flush()?;
+    check_checksum(hasher.value(), header)
+}
+
+/// Codes `block` and writes it with its lengths. The block is stored as it is if it is
+/// already compressed or encrypted, or if coding does not make it smaller.
+fn write_block<W: Write>(
+    writer: &mut W,
+    match_finder: &mut lz::MatchFinder,
+    block: &[u8],
+    coded: &mut Vec<u8>,
+    acceleration: u32,
+) -> io::Result<()> {
+    // Already compressed or encrypted data is stored without searching it for matches.
+    let coded_smaller = !shared_files::incompressible::is_incompressible(block) && {
+        match_finder.compress(block, coded, acceleration);
+        coded.len() < block.len()
+    };
+    writer.write_all(&(block.len() as u32).to_le_bytes())?;
+    if coded_smaller {
+        writer.write_all(&(coded.len() as u32).to_le_bytes())?;
+        writer.write_all(coded)
+    } else {
+        writer.write_all(&(block.len() as u32 | STORED_FLAG).to_le_bytes())?;
+        writer.write_all(block)
+    }
+}
+
+/// Reads the next block and restores its original data into `block`. `stored` holds the
+/// coded bytes of the block.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading fails, or an `InvalidData` error if the block is
+/// invalid or truncated.
+fn read_block<R: Read>(
+    reader: &mut R,
+    stored: &mut Vec<u8>,
+    block: &mut Vec<u8>,
+) -> io::Result<()> {
+    let truncated = |e: io::Error| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The compressed data is truncated. The file may be incomplete or corrupted.",
+            )
+        } else {
+            e
+        }
+    };
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut lengths = [0u8; 8];
+    reader.read_exact(&mut lengths).map_err(truncated)?;
+    let block_len = u32::from_le_bytes(lengths[..4].try_into().unwrap()) as usize;
+    let stored_len = u32::from_le_bytes(lengths[4..].try_into().unwrap());
+    if block_len == 0 || block_len > BLOCK_SIZE {
+        return Err(invalid(
+            "A block length is outside the block size of this format.",
+        ));
+    }
+    stored.clear();
+    reader
+        .take((stored_len & !STORED_FLAG) as u64)
+        .read_to_end(stored)?;
+    if stored.len() != (stored_len & !STORED_FLAG) as usize {
+        return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+    }
+    if stored_len & STORED_FLAG != 0 {
+        if stored.len() != block_len {
+            return Err(invalid("A stored block does not match its block length."));
+        }
+        std::mem::swap(stored, block);
+        Ok(())
+    } else {
+        lz::decompress(stored, block, block_len)
+            .ok_or_else(|| invalid("A block holds malformed LZ sequences."))
+    }
+}
+
+/// Checks that the blocks restored so far do not exceed the original length.
+fn check_restored_len(restored_len: u64, header: &FastLzHeader) -> io::Result<()> {
+    if restored_len > header.original_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The blocks restore more data than the original length stored in the header.",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the CRC32 of the restored data against the header.
+fn check_checksum(checksum: u32, header: &FastLzHeader) -> io::Result<()> {
+    if checksum != header.checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Checksum mismatch: the restored data has CRC32 0x{:08X}, but 0x{:08X} was expected. The file may be corrupted.",
+                checksum, header.checksum
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the header.
+fn write_header<W: Write>(writer: &mut W, original_len: u64, checksum: u32) -> io::Result<()> {
+    FORMAT.write_prefix(writer)?;
+    writer.write_all(&original_len.to_le_bytes())?;
+    writer.write_all(&checksum.to_le_bytes())
+}
+
+/// Reads and validates the header.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the file is too short, was not written by this
+/// module or uses a newer format version.
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<FastLzHeader> {
+    let bytes: [u8; HEADER_SIZE] = FORMAT.read_header(reader)?;
+    Ok(FastLzHeader {
+        original_len: u64::from_le_bytes(bytes[6..14].try_into().unwrap()),
+        checksum: u32::from_le_bytes(bytes[14..18].try_into().unwrap()),
+    })
+}
+
+/// The fast LZ codec with its settings, for use through the `Compressor` and
+/// `Decompressor` traits of `shared_files::codec`.
+#[derive(Debug, Clone, Copy)]
+pub struct FastLz {
+    /// The initial step between match probes.
+    pub acceleration: u32,
+}
+
+impl Default for FastLz {
+    /// Returns the settings the fast LZ module uses by default.
+    fn default() -> Self {
+        FastLz { acceleration: 1 }
+    }
+}
+
+impl Compressor for FastLz {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        codec::compress_in_memory(reader, writer, |input, output| {
+            compress(
+                &mut &input[..],
+                output,
+                input.len() as u64,
+                self.acceleration,
+            )
+        })
+    }
+
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        codec::compress_stream(reader, writer, |reader, writer, input_len| {
+            compress(reader, writer, input_len, self.acceleration)
+        })
+    }
+}
+
+impl Decompressor for FastLz {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut reader = Counter::new(reader);
+        let mut writer = Counter::new(writer);
+        let header = read_header(&mut reader)?;
+        decompress(&mut reader, &mut writer, &header)?;
+        Ok(CodecStats {
+            input_len: reader.count(),
+            output_len: writer.count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &FastLz::default());
+        report.streams("default", &FastLz::default());
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        let text = selftest::sample("text").unwrap();
+        selftest::rejects_damage(&FastLz::default(), &text, |len| vec![0, len / 2, len - 1])
+            .unwrap();
+    }
+
+    #[test]
+    fn restores_matches_overlapping_their_own_output() {
+        // A pattern repeated right after itself is coded as a match whose offset is the
+        // period and shorter than the match, so the match copies bytes it produces. The
+        // periods below, at and above the chunk size of the copy take its different paths.
+        for period in (1..=20).chain([31, 32, 33]) {
+            let pattern: Vec<u8> = (0..period as u8).map(|i| i.wrapping_mul(37)).collect();
+            let data: Vec<u8> = pattern.iter().copied().cycle().take(10_001).collect();
+            let mut file = Vec::new();
+            FastLz::default()
+                .compress(&mut &data[..], &mut file)
+                .unwrap();
+            assert!(file.len() < data.len() / 10, "period {}", period);
+
+            let mut restored = Vec::new();
+            FastLz::default()
+                .decompress(&mut &file[..], &mut restored)
+                .unwrap();
+            assert_eq!(restored, data, "period {}", period);
+        }
+    }
+}
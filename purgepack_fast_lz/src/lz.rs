@@ -0,0 +1,268 @@
+//! LZ77 coding of a block with a greedy single-probe match finder.
+//!
+//! A block is coded as a sequence of sequences, each made of a run of literal bytes
+//! followed by a match that copies earlier output:
+//!
+//! ```text
+//! [token: u8][literal length extension ...][literals ...][offset: u16 LE][match length extension ...]
+//! ```
+//!
+//! The high nibble of the token holds the literal length and the low nibble the match
+//! length minus `MIN_MATCH`. A nibble of 15 means the length continues in the extension
+//! bytes, which are added to it until a byte below 255. The last sequence of a block has
+//! only literals and ends the block.
+//!
+//! The encoder keeps one position per hash of four bytes and takes the first match it
+//! finds, skipping ahead faster the longer no match turns up, so incompressible data
+//! passes through quickly. The decoder only copies bytes, without any modelling.
+
+/// The shortest match that is coded.
+pub(crate) const MIN_MATCH: usize = 4;
+/// The largest distance of a match, limited by the 16-bit offset.
+const MAX_OFFSET: usize = u16::MAX as usize;
+/// The number of bits of the hash of four bytes.
+const HASH_BITS: u32 = 14;
+/// The multiplier of the hash, the golden ratio as a 32-bit fraction.
+const HASH_MULTIPLIER: u32 = 2_654_435_761;
+/// The number of failed probes after which the step between probes grows by one.
+const SKIP_SHIFT: u32 = 6;
+/// The nibble value that continues a length in extension bytes.
+const LENGTH_CONTINUES: usize = 15;
+/// The number of bytes the decoder copies at once for short literal runs and matches.
+const WIDE_COPY: usize = 16;
+
+/// The reusable hash table of the encoder.
+pub(crate) struct MatchFinder {
+    /// The position plus one of the last four bytes with every hash, or 0.
+    table: Box<[u32; 1 << HASH_BITS]>,
+}
+
+impl MatchFinder {
+    /// Creates an empty match finder.
+    pub(crate) fn new() -> Self {
+        MatchFinder {
+            table: Box::new([0; 1 << HASH_BITS]),
+        }
+    }
+
+    /// Codes `block` into `output`, which is cleared first.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The bytes to code. Blocks are coded independently of each other.
+    /// * `output` - The buffer receiving the sequences.
+    /// * `acceleration` - The initial step between probes. Higher values skip more
+    ///   positions, trading ratio for speed.
+    pub(crate) fn compress(&mut self, block: &[u8], output: &mut Vec<u8>, acceleration: u32) {
+        output.clear();
+        self.table.fill(0);
+        let read_u32 = |at: usize| u32::from_le_bytes(block[at..at + 4].try_into().unwrap());
+        let hash = |value: u32| (value.wrapping_mul(HASH_MULTIPLIER) >> (32 - HASH_BITS)) as usize;
+
+        let mut anchor = 0;
+        let mut position = 0;
+        let mut misses = acceleration << SKIP_SHIFT;
+        while position + MIN_MATCH <= block.len() {
+            let value = read_u32(position);
+            let slot = hash(value);
+            let candidate = self.table[slot] as usize;
+            self.table[slot] = position as u32 + 1;
+            if candidate == 0
+                || position + 1 - candidate > MAX_OFFSET
+                || read_u32(candidate - 1) != value
+            {
+                position += (misses >> SKIP_SHIFT) as usize;
+                misses += 1;
+                continue;
+            }
+
+            // Extend the match backwards into the pending literals and then forwards.
+            let mut source = candidate - 1;
+            let mut start = position;
+            while start > anchor && source > 0 && block[start - 1] == block[source - 1] {
+                start -= 1;
+                source -= 1;
+            }
+            let len = (position - start)
+                + MIN_MATCH
+                + common_prefix_len(
+                    &block[position + MIN_MATCH..],
+                    &block[candidate - 1 + MIN_MATCH..],
+                );
+            write_sequence(output, &block[anchor..start], start - source, len);
+
+            position = start + len;
+            anchor = position;
+            // Index a position inside the match, so the next probe can find what follows it.
+            if position + MIN_MATCH <= block.len() {
+                self.table[hash(read_u32(position - 2))] = (position - 2) as u32 + 1;
+            }
+            misses = acceleration << SKIP_SHIFT;
+        }
+        write_literals(output, &block[anchor..]);
+    }
+}
+
+/// Returns the number of leading bytes `current` and `earlier` have in common, comparing
+/// eight bytes at a time.
+fn common_prefix_len(current: &[u8], earlier: &[u8]) -> usize {
+    let mut len = 0;
+    for (current_word, earlier_word) in current.chunks_exact(8).zip(earlier.chunks_exact(8)) {
+        let difference = u64::from_le_bytes(current_word.try_into().unwrap())
+            ^ u64::from_le_bytes(earlier_word.try_into().unwrap());
+        if difference != 0 {
+            return len + (difference.trailing_zeros() / 8) as usize;
+        }
+        len += 8;
+    }
+    len + current[len..]
+        .iter()
+        .zip(&earlier[len..])
+        .take_while(|(current, earlier)| current == earlier)
+        .count()
+}
+
+/// Writes a sequence of `literals` followed by a match.
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], offset: usize, len: usize) {
+    let match_code = len - MIN_MATCH;
+    output.push(
+        ((literals.len().min(LENGTH_CONTINUES) as u8) << 4)
+            | match_code.min(LENGTH_CONTINUES) as u8,
+    );
+    write_extension(output, literals.len());
+    output.extend_from_slice(literals);
+    output.extend_from_slice(&(offset as u16).to_le_bytes());
+    write_extension(output, match_code);
+}
+
+/// Writes the last sequence of a block, which holds only `literals`.
+fn write_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    output.push((literals.len().min(LENGTH_CONTINUES) as u8) << 4);
+    write_extension(output, literals.len());
+    output.extend_from_slice(literals);
+}
+
+/// Writes the part of a length above the nibble as extension bytes.
+fn write_extension(output: &mut Vec<u8>, len: usize) {
+    if len < LENGTH_CONTINUES {
+        return;
+    }
+    let mut remaining = len - LENGTH_CONTINUES;
+    while remaining >= 255 {
+        output.push(255);
+        remaining -= 255;
+    }
+    output.push(remaining as u8);
+}
+
+/// Restores a block of `block_len` bytes from its sequences into `output`.
+///
+/// Literal runs and matches are copied in chunks of `WIDE_COPY` bytes where possible. The
+/// last chunk may write past their end into bytes that are overwritten later, so `output`
+/// keeps `WIDE_COPY` spare bytes past the block and is truncated at the end.
+///
+/// Returns `None` if the sequences are malformed, refer to data before the start of the
+/// block or do not restore exactly `block_len` bytes.
+pub(crate) fn decompress(input: &[u8], output: &mut Vec<u8>, block_len: usize) -> Option<()> {
+    output.resize(block_len + WIDE_COPY, 0);
+    let mut written = 0;
+    let mut position = 0;
+    loop {
+        let token = *input.get(position)? as usize;
+        position += 1;
+
+        let literal_len = read_length(input, &mut position, token >> 4)?;
+        let literal_end = position.checked_add(literal_len)?;
+        if literal_end > input.len() || written + literal_len > block_len {
+            return None;
+        }
+        if literal_end + WIDE_COPY <= input.len() {
+            wide_copy(&input[position..], &mut output[written..], literal_len);
+        } else {
+            output[written..written + literal_len].copy_from_slice(&input[position..literal_end]);
+        }
+        written += literal_len;
+        position = literal_end;
+        if position == input.len() {
+            output.truncate(written);
+            return (written == block_len).then_some(());
+        }
+
+        let offset =
+            u16::from_le_bytes(input.get(position..position + 2)?.try_into().unwrap()) as usize;
+        position += 2;
+        let len = read_length(input, &mut position, token & 0x0F)? + MIN_MATCH;
+        if offset == 0 || offset > written || written + len > block_len {
+            return None;
+        }
+        copy_match(output, written - offset, written, len);
+        written += len;
+    }
+}
+
+/// Reads a length that starts with the nibble `nibble` and may continue in extension
+/// bytes.
+fn read_length(input: &[u8], position: &mut usize, nibble: usize) -> Option<usize> {
+    let mut len = nibble;
+    if nibble == LENGTH_CONTINUES {
+        loop {
+            let byte = *input.get(*position)?;
+            *position += 1;
+            len += byte as usize;
+            if byte < 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+/// Copies at least `len` bytes from `source` to `destination` in chunks of `WIDE_COPY`
+/// bytes. Both slices must hold `len` rounded up to a whole chunk.
+fn wide_copy(source: &[u8], destination: &mut [u8], len: usize) {
+    let mut copied = 0;
+    loop {
+        let chunk: [u8; WIDE_COPY] = source[copied..copied + WIDE_COPY].try_into().unwrap();
+        destination[copied..copied + WIDE_COPY].copy_from_slice(&chunk);
+        copied += WIDE_COPY;
+        if copied >= len {
+            break;
+        }
+    }
+}
+
+/// Copies a match of `len` bytes from `source` to `destination` within `output`.
+///
+/// With an offset of at least `WIDE_COPY`, every chunk only reads bytes that are already
+/// final, even when the match overlaps the bytes it produces. A shorter offset repeats a
+/// short pattern: the first repetitions of at least `WIDE_COPY` bytes are copied byte by
+/// byte, and the rest is copied from them in chunks.
+fn copy_match(output: &mut [u8], source: usize, destination: usize, len: usize) {
+    let offset = destination - source;
+    if offset >= WIDE_COPY {
+        copy_chunks(output, source, destination, len);
+        return;
+    }
+    let period = offset * WIDE_COPY.div_ceil(offset);
+    for index in 0..period.min(len) {
+        output[destination + index] = output[source + index];
+    }
+    if len > period {
+        copy_chunks(output, destination, destination + period, len - period);
+    }
+}
+
+/// Copies at least `len` bytes from `source` to `destination` within `output` in chunks
+/// of `WIDE_COPY` bytes. `destination` must lie at least `WIDE_COPY` bytes after `source`.
+fn copy_chunks(output: &mut [u8], source: usize, destination: usize, len: usize) {
+    let mut copied = 0;
+    loop {
+        let from = source + copied;
+        let chunk: [u8; WIDE_COPY] = output[from..from + WIDE_COPY].try_into().unwrap();
+        output[destination + copied..][..WIDE_COPY].copy_from_slice(&chunk);
+        copied += WIDE_COPY;
+        if copied >= len {
+            break;
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Streaming adapters over the fast LZ format.
+//!
+//! [`PpEncoder`] compresses the data written to it one block at a time, as soon as a
+//! block is full, and [`PpDecoder`] restores the data of a compressed stream as it is
+//! read. The header holds the original length and the CRC32, which are only known at the
+//! end, so the encoder needs a seekable output to fill them in when it is finished.
+
+use shared_files::checksum::{Crc32, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    BLOCK_SIZE, FastLzHeader, LENGTH_OFFSET, check_checksum, check_restored_len, lz, read_block,
+    read_header, write_block, write_header,
+};
+
+/// A writer that compresses the data written to it into the fast LZ format.
+///
+/// Call [`PpEncoder::finish`] to write the last block and fill in the header. Dropping
+/// the encoder does the same, but ignores any error.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Cursor, Read, Write};
+///
+/// let mut encoder = purgepack_fast_lz::PpEncoder::new(Cursor::new(Vec::new()), 1).unwrap();
+/// encoder.write_all(b"hello hello hello hello").unwrap();
+/// let compressed = encoder.finish().unwrap().into_inner();
+///
+/// let mut decoder = purgepack_fast_lz::PpDecoder::new(&compressed[..]).unwrap();
+/// let mut restored = Vec::new();
+/// decoder.read_to_end(&mut restored).unwrap();
+/// assert_eq!(restored, b"hello hello hello hello");
+/// ```
+pub struct PpEncoder<W: Write + Seek> {
+    /// The output. It is only taken by `finish`.
+    writer: Option<W>,
+    /// The position of the header in the output.
+    start: u64,
+    acceleration: u32,
+    match_finder: lz::MatchFinder,
+    /// The data of the block being filled.
+    block: Vec<u8>,
+    coded: Vec<u8>,
+    hasher: Crc32,
+    original_len: u64,
+}
+
+impl<W: Write + Seek> PpEncoder<W> {
+    /// Writes the header to `writer` and returns an encoder that compresses into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination, positioned where the compressed data starts.
+    /// * `acceleration` - The initial step between match probes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing the header fails.
+    pub fn new(mut writer: W, acceleration: u32) -> io::Result<Self> {
+        let start = writer.stream_position()?;
+        write_header(&mut writer, 0, 0)?;
+        Ok(PpEncoder {
+            writer: Some(writer),
+            start,
+            acceleration,
+            match_finder: lz::MatchFinder::new(),
+            block: Vec::with_capacity(BLOCK_SIZE),
+            coded: Vec::with_capacity(BLOCK_SIZE),
+            hasher: Crc32::new(),
+            original_len: 0,
+        })
+    }
+
+    /// Returns a reference to the output.
+    pub fn get_ref(&self) -> &W {
+        self.writer
+            .as_ref()
+            .expect("the writer is only taken by finish")
+    }
+
+    /// Writes the last block, fills in the original length and the CRC32 in the header
+    /// and returns the output, positioned after the compressed data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing or seeking fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_footer()?;
+        Ok(self
+            .writer
+            .take()
+            .expect("the writer is only taken by finish"))
+    }
+
+    /// Codes and writes the block being filled, if it holds any data.
+    fn write_pending_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken by finish");
+        write_block(
+            writer,
+            &mut self.match_finder,
+            &self.block,
+            &mut self.coded,
+            self.acceleration,
+        )?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes the last block and fills in the header.
+    fn write_footer(&mut self) -> io::Result<()> {
+        self.write_pending_block()?;
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken by finish");
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(self.start + LENGTH_OFFSET))?;
+        writer.write_all(&self.original_len.to_le_bytes())?;
+        writer.write_all(&self.hasher.value().to_le_bytes())?;
+        writer.seek(SeekFrom::Start(end))?;
+        writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Write for PpEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        self.hasher.update(&buf[..len]);
+        self.original_len += len as u64;
+        if self.block.len() == BLOCK_SIZE {
+            self.write_pending_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the output. The block being filled is kept until it is full or the
+    /// encoder is finished, so flushing does not shorten the blocks.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("the writer is only taken by finish")
+            .flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for PpEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_footer();
+        }
+    }
+}
+
+/// A reader that restores the original data of a stream in the fast LZ format.
+///
+/// The CRC32 of the restored data is checked once the last block has been read. A
+/// mismatch is reported as an `InvalidData` error instead of the end of the data.
+pub struct PpDecoder<R: Read> {
+    reader: R,
+    header: FastLzHeader,
+    stored: Vec<u8>,
+    /// The original data of the current block.
+    block: Vec<u8>,
+    /// The number of bytes of `block` already returned.
+    position: usize,
+    restored_len: u64,
+    hasher: Crc32,
+}
+
+impl<R: Read> PpDecoder<R> {
+    /// Reads the header from `reader` and returns a decoder for the data following it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the header is invalid.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let header = read_header(&mut reader)?;
+        Ok(PpDecoder {
+            reader,
+            header,
+            stored: Vec::new(),
+            block: Vec::new(),
+            position: 0,
+            restored_len: 0,
+            hasher: Crc32::new(),
+        })
+    }
+
+    /// Returns the header of the stream.
+    pub fn header(&self) -> &FastLzHeader {
+        &self.header
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for PpDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.block.len() {
+            if self.restored_len == self.header.original_len {
+                return check_checksum(self.hasher.value(), &self.header).map(|_| 0);
+            }
+            read_block(&mut self.reader, &mut self.stored, &mut self.block)?;
+            self.restored_len += self.block.len() as u64;
+            check_restored_len(self.restored_len, &self.header)?;
+            self.hasher.update(&self.block);
+            self.position = 0;
+        }
+        let len = buf.len().min(self.block.len() - self.position);
+        buf[..len].copy_from_slice(&self.block[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,430 @@
+//! The codec of the Huffman module: canonical Huffman coding of a byte buffer.
+//!
+//! `compress` counts the byte frequencies, builds a Huffman tree, turns its code lengths
+//! into canonical codes and writes the coded data. `decompress` rebuilds the same codes
+//! from the stored code lengths and decodes the bit stream.
+//!
+//! The coded data is laid out as a single bit stream, most significant bit first:
+//!
+//! ```text
+//! [table length: u32 BE][data length in bits: u32 BE]
+//! [table length x (byte: u8, code length: u8)][coded data ...]
+//! ```
+//!
+//! The last byte is padded with zero bits. Empty input produces an empty table and no
+//! data, and input with a single distinct byte value codes it with one bit per byte.
+//!
+//! The codec lives in its own crate so other programs can use it on buffers in memory,
+//! without going through the module entry points.
+//!
+//! # Examples
+//!
+//! ```
+//! let data = b"abracadabra".repeat(10);
+//! let compressed = purgepack_huffman::compress(&data).unwrap();
+//! assert!(compressed.len() < data.len());
+//! assert_eq!(purgepack_huffman::decompress(&compressed).unwrap(), data);
+//! ```
+use shared_files::bitio::{BitReader, BitWriter};
+use shared_files::codec::{CodecStats, Compressor, Decompressor};
+use shared_files::error::PurgePackError;
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{self, Read, Write},
+};
+
+/// The longest code length accepted when decoding. The frequencies of at most
+/// `u32::MAX` bytes cannot produce a deeper tree.
+const MAX_CODE_LENGTH: usize = 64;
+
+/// A node in the decoding tree used for canonical Huffman decoding.
+#[derive(Debug)]
+struct DecodeNode {
+    left: Option<Box<DecodeNode>>,
+    right: Option<Box<DecodeNode>>,
+    byte: Option<u8>,
+}
+
+impl DecodeNode {
+    /// Creates a new empty `DecodeNode`.
+    fn new() -> Self {
+        DecodeNode {
+            left: None,
+            right: None,
+            byte: None,
+        }
+    }
+
+    /// Inserts a (bit-code, byte) pair into the decoding tree.
+    ///
+    /// * `code` is a slice of bits (`0` or `1`) representing the path from the root:
+    ///   `0` means go left, `1` means go right.
+    /// * `byte` is the value stored at the leaf corresponding to that code.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut root = DecodeNode::new();
+    /// root.insert(&[0, 1, 0], 42u8);
+    /// ```
+    fn insert(&mut self, code: &[u8], byte: u8) {
+        let mut node = self;
+        for &bit in code {
+            node = if bit == 0 {
+                node.left.get_or_insert_with(|| Box::new(DecodeNode::new()))
+            } else {
+                node.right
+                    .get_or_insert_with(|| Box::new(DecodeNode::new()))
+            };
+        }
+        node.byte = Some(byte);
+    }
+}
+
+/// Builds a decoding tree from an array of optional codes for each byte value.
+///
+/// * `codes` is an array of length 256 (one entry per possible `u8` value),
+///   where each `Option<Vec<u8>>` is the bit-code assigned to that byte (or `None` if unused).
+fn build_decoding_tree(codes: &[Option<Vec<u8>>; 256]) -> DecodeNode {
+    let mut root = DecodeNode::new();
+
+    for (byte, code_opt) in codes.iter().enumerate() {
+        if let Some(code) = code_opt {
+            root.insert(code, byte as u8);
+        }
+    }
+
+    root
+}
+
+/// Decodes `data_len` bits from `reader` using the provided decoding tree.
+/// Returns the decoded bytes in a `Vec<u8>`.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the data is truncated, or an `InvalidData` error if
+/// the bits do not follow a code of the tree.
+fn decode_canonical(
+    reader: &mut BitReader,
+    data_len: u32,
+    root: &DecodeNode,
+) -> io::Result<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut node = root;
+
+    for _ in 0..data_len {
+        let next = if reader.read_bit()? == 0 {
+            node.left.as_deref()
+        } else {
+            node.right.as_deref()
+        };
+        node = next.ok_or_else(|| {
+            PurgePackError::corrupt(
+                "The Huffman-coded data contains a code that is not in the code table.",
+            )
+        })?;
+
+        if let Some(b) = node.byte {
+            result.push(b);
+            node = root;
+        }
+    }
+
+    Ok(result)
+}
+
+/// A node used to build the Huffman tree for frequency encoding.
+#[derive(Debug, Eq)]
+struct Node {
+    left: Option<Box<Node>>,
+    right: Option<Box<Node>>,
+    num: u64,
+    byte: Option<u8>,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.num == other.num
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.num.cmp(&other.num)
+    }
+}
+
+/// Calculates the frequency of each possible byte value in the given buffer.
+/// Returns a `[u64; 256]` array where element i counts occurrences of byte i.
+///
+/// # Examples
+///
+/// ```ignore
+/// let freqs = calculate_byte_frequencies(&[0u8, 255u8, 0u8]);
+/// assert_eq!(freqs[0], 2);
+/// assert_eq!(freqs[255], 1);
+/// ```
+fn calculate_byte_frequencies(buffer: &[u8]) -> [u64; 256] {
+    let mut frequencies = [0u64; 256];
+    for &byte in buffer {
+        frequencies[byte as usize] += 1;
+    }
+    frequencies
+}
+
+/// Builds the Huffman tree from the given frequency counts.
+///
+/// Returns the root node of the Huffman tree, or `None` if all counts are zero.
+fn generate_huffman_tree(frequencies: &[u64; 256]) -> Option<Box<Node>> {
+    let mut heap = BinaryHeap::new();
+
+    for (byte, &freq) in frequencies.iter().enumerate() {
+        if freq > 0 {
+            heap.push(Reverse(Box::new(Node {
+                left: None,
+                right: None,
+                num: freq,
+                byte: Some(byte as u8),
+            })));
+        }
+    }
+
+    while heap.len() > 1 {
+        let Reverse(node1) = heap.pop()?;
+        let Reverse(node2) = heap.pop()?;
+
+        heap.push(Reverse(Box::new(Node {
+            num: node1.num + node2.num,
+            left: Some(node1),
+            right: Some(node2),
+            byte: None,
+        })));
+    }
+
+    heap.pop().map(|Reverse(root)| root)
+}
+
+/// Traverses the Huffman tree to find the code length of each byte value.
+/// Returns the `(byte, length)` pairs of the bytes that occur, in ascending byte order.
+///
+/// A tree that consists of a single leaf gives its byte a length of 1, so that every
+/// occurrence still takes a bit in the coded data.
+fn generate_code_lengths(root: &Node) -> Vec<(u8, usize)> {
+    let mut lengths = [0usize; 256];
+
+    fn traverse(node: &Node, depth: usize, lengths: &mut [usize; 256]) {
+        if let Some(b) = node.byte {
+            lengths[b as usize] = depth.max(1);
+            return;
+        }
+        if let Some(ref left) = node.left {
+            traverse(left, depth + 1, lengths);
+        }
+        if let Some(ref right) = node.right {
+            traverse(right, depth + 1, lengths);
+        }
+    }
+
+    traverse(root, 0, &mut lengths);
+    lengths
+        .iter()
+        .enumerate()
+        .filter(|&(_, &length)| length > 0)
+        .map(|(byte, &length)| (byte as u8, length))
+        .collect()
+}
+
+/// Given a slice of `(byte, length)` pairs, generates canonical Huffman codes:
+/// an array of 256 `Option<Vec<u8>>`, where each entry is either `None` (unused byte)
+/// or `Some(code_bits)`.
+///
+/// The lengths must lie between 1 and `MAX_CODE_LENGTH`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let codes = generate_canonical_codes(&[(0u8, 3), (5u8, 3), (10u8, 4)]);
+/// assert!(codes[0].is_some());
+/// ```
+fn generate_canonical_codes(byte_length_pairs: &[(u8, usize)]) -> [Option<Vec<u8>>; 256] {
+    let mut codes: [Option<Vec<u8>>; 256] = std::array::from_fn(|_| None);
+
+    let mut sorted = byte_length_pairs.to_vec();
+    sorted.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+    let mut current_code: u64 = 0;
+    let mut prev_length: usize = 0;
+
+    for &(byte, length) in &sorted {
+        current_code = current_code
+            .checked_shl((length - prev_length) as u32)
+            .unwrap_or(0);
+
+        let mut canonical_code = Vec::with_capacity(length);
+        for i in (0..length).rev() {
+            canonical_code.push(((current_code >> i) & 1) as u8);
+        }
+
+        codes[byte as usize] = Some(canonical_code);
+        current_code = current_code.wrapping_add(1);
+        prev_length = length;
+    }
+
+    codes
+}
+
+/// Compresses `data` with canonical Huffman coding.
+///
+/// # Returns
+///
+/// Returns the code table followed by the coded data, in the layout described in the
+/// crate documentation.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the coded data does not fit the 32-bit bit count of
+/// the format (roughly 512 MiB of coded data).
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let frequencies = calculate_byte_frequencies(data);
+    let code_lengths = match generate_huffman_tree(&frequencies) {
+        Some(root) => generate_code_lengths(&root),
+        None => Vec::new(),
+    };
+    let codes = generate_canonical_codes(&code_lengths);
+
+    let data_bits: u64 = code_lengths
+        .iter()
+        .map(|&(byte, length)| frequencies[byte as usize] * length as u64)
+        .sum();
+    let data_len = u32::try_from(data_bits).map_err(|_| {
+        PurgePackError::InvalidInput(format!(
+            "The coded data would take {} bits, more than the {} bits the format can store.",
+            data_bits,
+            u32::MAX
+        ))
+    })?;
+
+    let mut writer = BitWriter::new();
+    writer.write_value(code_lengths.len() as u64, 32);
+    writer.write_value(data_len as u64, 32);
+    for &(byte, length) in &code_lengths {
+        writer.write_value(byte as u64, 8);
+        writer.write_value(length as u64, 8);
+    }
+    for &byte in data {
+        // Every byte of the input has a code, since its frequency is not zero.
+        if let Some(code) = &codes[byte as usize] {
+            writer.write_bits(code);
+        }
+    }
+    Ok(writer.into_bytes())
+}
+
+/// Restores the data compressed by `compress`.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the data is truncated, or an `InvalidData` error if
+/// the code table is invalid or the coded data does not match it.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: String| io::Error::from(PurgePackError::corrupt(message));
+    let mut reader = BitReader::new(data);
+
+    let table_len = reader.read_value(32)?;
+    let data_len = reader.read_value(32)? as u32;
+    if table_len > 256 {
+        return Err(invalid(format!(
+            "The code table lists {} byte values, more than the 256 that exist.",
+            table_len
+        )));
+    }
+
+    let mut byte_lengths = Vec::with_capacity(table_len as usize);
+    for _ in 0..table_len {
+        let byte = reader.read_value(8)? as u8;
+        let length = reader.read_value(8)? as usize;
+        if length == 0 || length > MAX_CODE_LENGTH {
+            return Err(invalid(format!(
+                "The code length {} of byte {} is not between 1 and {}.",
+                length, byte, MAX_CODE_LENGTH
+            )));
+        }
+        byte_lengths.push((byte, length));
+    }
+
+    let codes = generate_canonical_codes(&byte_lengths);
+    let decoding_root = build_decoding_tree(&codes);
+    decode_canonical(&mut reader, data_len, &decoding_root)
+}
+
+/// The Huffman codec, for use through the `Compressor` and `Decompressor` traits of
+/// `shared_files::codec`.
+///
+/// The code table is built from the whole input and the format keeps no blocks, so the
+/// codec holds all data in memory, and `compress_file` is the default that calls
+/// `compress`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Huffman;
+
+impl Compressor for Huffman {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        let output = compress(&input)?;
+        writer.write_all(&output)?;
+        Ok(CodecStats {
+            input_len: input.len() as u64,
+            output_len: output.len() as u64,
+        })
+    }
+}
+
+impl Decompressor for Huffman {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        let output = decompress(&input)?;
+        writer.write_all(&output)?;
+        Ok(CodecStats {
+            input_len: input.len() as u64,
+            output_len: output.len() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &Huffman);
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        // The format has no checksum, so damage inside the coded data only changes what
+        // is restored. Damage to the table or the lengths is caught.
+        let text = selftest::sample("text").unwrap();
+        selftest::rejects_damage(&Huffman, &text, |_| vec![0, 5, 9]).unwrap();
+
+        let mut file = Vec::new();
+        Huffman.compress(&mut &text[..], &mut file).unwrap();
+        let middle = file.len() / 2;
+        file[middle] ^= 0x55;
+        let mut restored = Vec::new();
+        let _ = Huffman.decompress(&mut &file[..], &mut restored);
+        assert_ne!(restored, text);
+    }
+}
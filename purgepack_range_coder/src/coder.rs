@@ -0,0 +1,116 @@
+//! Binary arithmetic (range) coder.
+//!
+//! The coder keeps the current interval as two 32-bit bounds `x1..=x2`. Every bit splits
+//! the interval in proportion to the predicted probability that the bit is 1, and the
+//! half that matches the bit becomes the new interval. Once the leading byte of both
+//! bounds is the same it can no longer change, so it is shifted out to the output.
+//!
+//! Probabilities are 12-bit: 1 to 4095 out of 4096.
+
+use std::io::{self, Read, Write};
+
+/// Splits the interval `x1..=x2` at the point given by the probability `p1` of a 1 bit.
+fn split(x1: u32, x2: u32, p1: u16) -> u32 {
+    x1 + ((x2 - x1) >> 12) * p1 as u32
+}
+
+/// Encodes bits into a byte stream.
+pub(crate) struct Encoder<W: Write> {
+    writer: W,
+    x1: u32,
+    x2: u32,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Creates an encoder that writes to `writer`.
+    pub(crate) fn new(writer: W) -> Self {
+        Encoder {
+            writer,
+            x1: 0,
+            x2: u32::MAX,
+        }
+    }
+
+    /// Encodes `bit` (0 or 1), which is 1 with the probability `p1` (1 to 4095).
+    pub(crate) fn encode(&mut self, bit: u8, p1: u16) -> io::Result<()> {
+        let xmid = split(self.x1, self.x2, p1);
+        if bit != 0 {
+            self.x2 = xmid;
+        } else {
+            self.x1 = xmid + 1;
+        }
+        while (self.x1 ^ self.x2) & 0xFF00_0000 == 0 {
+            self.writer.write_all(&[(self.x2 >> 24) as u8])?;
+            self.x1 <<= 8;
+            self.x2 = (self.x2 << 8) | 0xFF;
+        }
+        Ok(())
+    }
+
+    /// Returns a reference to the output.
+    pub(crate) fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Writes the four bytes that pin down the final interval and returns the output.
+    /// Must be called once all bits are encoded.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&self.x1.to_be_bytes())?;
+        Ok(self.writer)
+    }
+}
+
+/// Decodes the bits written by [`Encoder`].
+pub(crate) struct Decoder<R: Read> {
+    reader: R,
+    x1: u32,
+    x2: u32,
+    x: u32,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Creates a decoder that reads from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the first four bytes cannot be read.
+    pub(crate) fn new(mut reader: R) -> io::Result<Self> {
+        let mut first = [0u8; 4];
+        reader.read_exact(&mut first)?;
+        Ok(Decoder {
+            reader,
+            x1: 0,
+            x2: u32::MAX,
+            x: u32::from_be_bytes(first),
+        })
+    }
+
+    /// Returns the underlying reader.
+    pub(crate) fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Decodes the next bit, which is 1 with the probability `p1` (1 to 4095).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the stream ends early.
+    pub(crate) fn decode(&mut self, p1: u16) -> io::Result<u8> {
+        let xmid = split(self.x1, self.x2, p1);
+        let bit = if self.x <= xmid {
+            self.x2 = xmid;
+            1
+        } else {
+            self.x1 = xmid + 1;
+            0
+        };
+        while (self.x1 ^ self.x2) & 0xFF00_0000 == 0 {
+            let mut next = [0u8; 1];
+            self.reader.read_exact(&mut next)?;
+            self.x1 <<= 8;
+            self.x2 = (self.x2 << 8) | 0xFF;
+            self.x = (self.x << 8) | next[0] as u32;
+        }
+        Ok(bit)
+    }
+}
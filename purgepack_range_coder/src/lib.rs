@@ -0,0 +1,318 @@
+//! The codec of the range coder module: a context-mixing compressor built on a binary
+//! range coder.
+//!
+//! Every bit of the input is predicted by the `model` module, which mixes context models
+//! of orders 0 to `level`, and coded with that probability by the `coder` module. The
+//! better the prediction, the fewer bits the coder spends, so text and other data with
+//! strong byte-to-byte dependencies compress far better than with a plain order-0
+//! entropy coder such as the Huffman module.
+//!
+//! The codec lives in its own crate so other modules can restore PPCB files of this
+//! format in memory, without going through the module entry points.
+use shared_files::checksum::{Crc32, Hasher};
+use shared_files::codec::{
+    self, CodecStats, Compressor, Counter, Decompressor, ReadSeek, WriteSeek,
+};
+use shared_files::frame::FrameFormat;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+mod coder;
+mod model;
+mod stream;
+
+pub use stream::{PpDecoder, PpEncoder};
+
+/// Module ID (Algorithm Identifier) for the range coder module.
+pub const MODULE_ID: u8 = 0x02;
+/// The version of the header layout and payload format written by this build.
+pub const FORMAT_VERSION: u8 = 1;
+/// The frame format of the range coder module.
+const FORMAT: FrameFormat = FrameFormat::new(MODULE_ID, "Range Coder", FORMAT_VERSION);
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for
+/// format version + 1 byte for level + 8 bytes for the original length + 4 bytes for the
+/// CRC32 of the original data).
+const HEADER_SIZE: usize = 19;
+/// The offset of the original length in the header. `PpEncoder` writes it once all the
+/// data has been written.
+const LENGTH_OFFSET: u64 = 7;
+/// The offset of the checksum in the header. It is written once all the data has been read.
+const CHECKSUM_OFFSET: u64 = 15;
+/// The highest context order that can be mixed into the prediction.
+pub const MAX_LEVEL: u8 = 4;
+
+/// The settings and checksum stored in the header.
+pub struct RangeCoderHeader {
+    /// The highest context order mixed into the prediction.
+    pub level: u8,
+    /// The length in bytes of the original data.
+    pub original_len: u64,
+    /// The CRC32 of the original data.
+    pub checksum: u32,
+}
+
+/// Compresses everything `reader` holds into `writer`.
+///
+/// The header is written with a zero checksum first. Once all the data has been coded,
+/// the CRC32 of the original data is filled in.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the original data.
+/// * `writer` - The destination, positioned at the start of the output.
+/// * `original_len` - The length in bytes of the original data.
+/// * `level` - The highest context order mixed into the prediction, up to `MAX_LEVEL`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+pub fn compress<R: BufRead, W: Write + Seek>(
+    reader: &mut R,
+    writer: &mut W,
+    original_len: u64,
+    level: u8,
+) -> io::Result<()> {
+    write_header(writer, level, original_len, 0)?;
+
+    let mut hasher = Crc32::new();
+    let mut predictor = model::Predictor::new(level);
+    let mut encoder = coder::Encoder::new(&mut *writer);
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        hasher.update(chunk);
+        for &byte in chunk {
+            encode_byte(&mut encoder, &mut predictor, byte)?;
+        }
+        let len = chunk.len();
+        reader.consume(len);
+    }
+    encoder.finish()?;
+    writer.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    writer.write_all(&hasher.value().to_le_bytes())?;
+    writer.flush()
+}
+
+/// Restores the original data following the header into `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The compressed data, positioned right after the header.
+/// * `writer` - The destination of the original data.
+/// * `header` - The header read by [`read_header`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// data is truncated or the restored data does not match the checksum.
+pub fn decompress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &RangeCoderHeader,
+) -> io::Result<()> {
+    let mut hasher = Crc32::new();
+    let mut predictor = model::Predictor::new(header.level);
+    if header.original_len > 0 {
+        let mut decoder = coder::Decoder::new(reader).map_err(truncated)?;
+        let mut buffer = Vec::with_capacity(64 * 1024);
+        for _ in 0..header.original_len {
+            buffer.push(decode_byte(&mut decoder, &mut predictor)?);
+            if buffer.len() == buffer.capacity() {
+                hasher.update(&buffer);
+                writer.write_all(&buffer)?;
+                buffer.clear();
+            }
+        }
+        hasher.update(&buffer);
+        writer.write_all(&buffer)?;
+    }
+    writer.flush()?;
+    check_checksum(hasher.value(), header)
+}
+
+/// Codes the bits of `byte`, most significant first, and updates the model with them.
+fn encode_byte<W: Write>(
+    encoder: &mut coder::Encoder<W>,
+    predictor: &mut model::Predictor,
+    byte: u8,
+) -> io::Result<()> {
+    for shift in (0..8).rev() {
+        let bit = (byte >> shift) & 1;
+        encoder.encode(bit, predictor.p1())?;
+        predictor.update(bit);
+    }
+    Ok(())
+}
+
+/// Decodes the next byte and updates the model with its bits.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the data is truncated.
+fn decode_byte<R: Read>(
+    decoder: &mut coder::Decoder<R>,
+    predictor: &mut model::Predictor,
+) -> io::Result<u8> {
+    let mut byte = 0u8;
+    for _ in 0..8 {
+        let bit = decoder.decode(predictor.p1()).map_err(truncated)?;
+        predictor.update(bit);
+        byte = (byte << 1) | bit;
+    }
+    Ok(byte)
+}
+
+/// Maps a truncated read to an `InvalidData` error that names the cause.
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The compressed data is truncated. The file may be incomplete or corrupted.",
+        )
+    } else {
+        e
+    }
+}
+
+/// Checks the CRC32 of the restored data against the header.
+fn check_checksum(checksum: u32, header: &RangeCoderHeader) -> io::Result<()> {
+    if checksum != header.checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Checksum mismatch: the restored data has CRC32 0x{:08X}, but 0x{:08X} was expected. The file may be corrupted.",
+                checksum, header.checksum
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the header.
+fn write_header<W: Write>(
+    writer: &mut W,
+    level: u8,
+    original_len: u64,
+    checksum: u32,
+) -> io::Result<()> {
+    FORMAT.write_prefix(writer)?;
+    writer.write_all(&[level])?;
+    writer.write_all(&original_len.to_le_bytes())?;
+    writer.write_all(&checksum.to_le_bytes())
+}
+
+/// Reads and validates the header.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the file is too short, or an `InvalidData` error
+/// if it was not written by this module, uses a newer format version or an unsupported level.
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<RangeCoderHeader> {
+    let bytes: [u8; HEADER_SIZE] = FORMAT.read_header(reader)?;
+    let level = bytes[6];
+    if level > MAX_LEVEL {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported level: {}. Supported levels are 0 to {}.",
+                level, MAX_LEVEL
+            ),
+        ));
+    }
+    Ok(RangeCoderHeader {
+        level,
+        original_len: u64::from_le_bytes(bytes[7..15].try_into().unwrap()),
+        checksum: u32::from_le_bytes(bytes[15..19].try_into().unwrap()),
+    })
+}
+
+/// The range coder with its settings, for use through the `Compressor` and `Decompressor`
+/// traits of `shared_files::codec`.
+#[derive(Debug, Clone, Copy)]
+pub struct RangeCoder {
+    /// The highest context order mixed into the prediction, up to `MAX_LEVEL`.
+    pub level: u8,
+}
+
+impl Default for RangeCoder {
+    /// Returns the settings the range coder module uses by default.
+    fn default() -> Self {
+        RangeCoder { level: 2 }
+    }
+}
+
+impl Compressor for RangeCoder {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        codec::compress_in_memory(reader, writer, |input, output| {
+            compress(&mut &input[..], output, input.len() as u64, self.level)
+        })
+    }
+
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        codec::compress_stream(reader, writer, |reader, writer, input_len| {
+            compress(reader, writer, input_len, self.level)
+        })
+    }
+}
+
+impl Decompressor for RangeCoder {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut reader = Counter::new(reader);
+        let mut writer = Counter::new(writer);
+        let header = read_header(&mut reader)?;
+        decompress(&mut reader, &mut writer, &header)?;
+        Ok(CodecStats {
+            input_len: reader.count(),
+            output_len: writer.count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &RangeCoder::default());
+        report.streams("default", &RangeCoder::default());
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        // The last bytes the encoder flushes only narrow the final range, so damage there
+        // may go unnoticed; the payload from its first byte on is checked.
+        let text = selftest::sample("text").unwrap();
+        selftest::rejects_damage(&RangeCoder::default(), &text, |len| {
+            vec![0, HEADER_SIZE, len / 2]
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn codes_empty_input_at_every_level() {
+        for level in 0..=MAX_LEVEL {
+            let coder = RangeCoder { level };
+            let mut file = Vec::new();
+            coder.compress(&mut &[][..], &mut file).unwrap();
+            assert_eq!(read_header(&mut &file[..]).unwrap().original_len, 0);
+
+            let mut restored = Vec::new();
+            coder.decompress(&mut &file[..], &mut restored).unwrap();
+            assert!(restored.is_empty(), "level {}", level);
+            assert!(
+                coder
+                    .decompress(&mut &file[..HEADER_SIZE - 1], &mut Vec::new())
+                    .is_err()
+            );
+        }
+    }
+}
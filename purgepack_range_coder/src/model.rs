@@ -0,0 +1,206 @@
+//! Context-mixing bit model.
+//!
+//! Every byte is coded as eight binary decisions, most significant bit first. For every
+//! decision, one adaptive probability is looked up per context order: order 0 uses only
+//! the bits of the current byte seen so far, order 1 also the previous byte, and order
+//! `n` the previous `n` bytes (hashed into a fixed-size table from order 2 on).
+//!
+//! The predictions are combined by a mixer in the logistic domain: each probability is
+//! "stretched" (`ln(p / (1 - p))`), the stretched values are summed with learned weights
+//! and the sum is "squashed" back into a probability. After every bit the weights are
+//! nudged towards the models that predicted it well, so the mixer learns which orders to
+//! trust for the data at hand. The weight set is selected by the bits of the current byte.
+
+/// The number of bits of the hashed tables of orders 2 and above.
+const HASH_BITS: u32 = 22;
+/// The number of low bits of a counter that hold the number of updates it has seen.
+const COUNT_BITS: u32 = 10;
+/// The highest update count. A counter that has seen `n` updates moves `1 / (n + 1.5)` of
+/// the way towards the coded bit, so new contexts learn fast and old ones settle, but
+/// never slower than this limit allows.
+const COUNT_LIMIT: u32 = 12;
+/// The learning rate shift of the mixer.
+const MIXER_SHIFT: u32 = 11;
+/// The initial mixer weight of every input (0.3 in 16.16 fixed point).
+const INITIAL_WEIGHT: i32 = 20_000;
+/// The stretched value of the bias input of the mixer.
+const BIAS: i32 = 256;
+
+/// Maps a value of the logistic domain (-2047 to 2047, in units of 1/256) to a 12-bit
+/// probability: `4096 / (1 + e^(-d / 256))`, interpolated from a table.
+fn squash(d: i32) -> i32 {
+    const TABLE: [i32; 33] = [
+        1, 2, 3, 6, 10, 16, 27, 45, 73, 120, 194, 310, 488, 747, 1101, 1546, 2047, 2549, 2994,
+        3348, 3607, 3785, 3901, 3975, 4024, 4050, 4068, 4079, 4085, 4089, 4092, 4093, 4094,
+    ];
+    if d > 2047 {
+        return 4095;
+    }
+    if d < -2047 {
+        return 1;
+    }
+    let weight = d & 127;
+    let index = ((d >> 7) + 16) as usize;
+    (TABLE[index] * (128 - weight) + TABLE[index + 1] * weight + 64) >> 7
+}
+
+/// Predicts the bits of a byte stream from the preceding bytes.
+pub(crate) struct Predictor {
+    /// The inverse of `squash` for every 12-bit probability.
+    stretch: Vec<i16>,
+    /// One table of counters per context order: a 22-bit probability above a
+    /// `COUNT_BITS`-bit update count.
+    tables: Vec<Vec<u32>>,
+    /// `65536 / (n + 1.5)` for every update count `n`.
+    rates: Vec<u32>,
+    /// The slot of the current bit in every table.
+    slots: Vec<usize>,
+    /// The hash of the preceding bytes of every order, fixed for the current byte.
+    byte_hashes: Vec<u32>,
+    /// The first slot of the 16-slot bucket of the current nibble in every hashed table.
+    /// Keeping the four bits of a nibble in one bucket saves most of the cache misses.
+    buckets: Vec<usize>,
+    /// The bits of the current nibble seen so far, behind a leading 1 (1 to 15).
+    nibble_context: usize,
+    /// The last eight bytes, the most recent in the low byte.
+    history: u64,
+    /// The bits of the current byte seen so far, behind a leading 1 (1 to 255).
+    bit_context: usize,
+    /// The stretched predictions of the models, followed by the bias.
+    inputs: Vec<i32>,
+    /// 256 weight sets of `inputs.len()` weights, selected by `bit_context`.
+    weights: Vec<i32>,
+    /// The last mixed prediction.
+    prediction: i32,
+}
+
+impl Predictor {
+    /// Creates a predictor that mixes the models of orders 0 to `max_order`.
+    pub(crate) fn new(max_order: u8) -> Self {
+        let orders = max_order as usize + 1;
+        let mut stretch = vec![0i16; 4096];
+        let mut next = 0;
+        for d in -2047..=2047 {
+            let p = squash(d) as usize;
+            for slot in &mut stretch[next..=p] {
+                *slot = d as i16;
+            }
+            next = p + 1;
+        }
+        for slot in &mut stretch[next..] {
+            *slot = 2047;
+        }
+
+        let tables = (0..orders)
+            .map(|order| {
+                let len = match order {
+                    0 => 1 << 8,
+                    1 => 1 << 16,
+                    _ => 1 << HASH_BITS,
+                };
+                vec![1u32 << 31; len]
+            })
+            .collect();
+        let mut predictor = Predictor {
+            stretch,
+            tables,
+            rates: (0..=COUNT_LIMIT).map(|n| 131_072 / (2 * n + 3)).collect(),
+            slots: vec![0; orders],
+            byte_hashes: vec![0; orders],
+            buckets: vec![0; orders],
+            nibble_context: 1,
+            history: 0,
+            bit_context: 1,
+            inputs: vec![0; orders + 1],
+            weights: vec![INITIAL_WEIGHT; 256 * (orders + 1)],
+            prediction: 2048,
+        };
+        predictor.select_buckets();
+        predictor.select_slots();
+        predictor
+    }
+
+    /// Returns the probability (1 to 4095 out of 4096) that the next bit is 1.
+    pub(crate) fn p1(&self) -> u16 {
+        self.prediction as u16
+    }
+
+    /// Trains the models and the mixer on the coded `bit` and predicts the next one.
+    pub(crate) fn update(&mut self, bit: u8) {
+        let target = (bit as i32) << 12;
+        let error = target - self.prediction;
+        let weights =
+            &mut self.weights[self.bit_context * self.inputs.len()..][..self.inputs.len()];
+        for (weight, &input) in weights.iter_mut().zip(&self.inputs) {
+            *weight += (input * error) >> MIXER_SHIFT;
+        }
+        let target: i64 = if bit != 0 { (1 << 22) - 1 } else { 0 };
+        for (table, &slot) in self.tables.iter_mut().zip(&self.slots) {
+            let counter = &mut table[slot];
+            let count = *counter & ((1 << COUNT_BITS) - 1);
+            let probability = (*counter >> COUNT_BITS) as i64;
+            let step = ((target - probability) * self.rates[count as usize] as i64) >> 16;
+            *counter = (((probability + step) as u32) << COUNT_BITS) | (count + 1).min(COUNT_LIMIT);
+        }
+
+        self.bit_context = (self.bit_context << 1) | bit as usize;
+        self.nibble_context = (self.nibble_context << 1) | bit as usize;
+        if self.bit_context >= 256 {
+            self.history = (self.history << 8) | (self.bit_context & 0xFF) as u64;
+            self.bit_context = 1;
+            self.hash_history();
+        }
+        if self.nibble_context >= 16 {
+            self.nibble_context = 1;
+            self.select_buckets();
+        }
+        self.select_slots();
+    }
+
+    /// Hashes the preceding bytes of every order once a byte is complete.
+    fn hash_history(&mut self) {
+        let mut hash: u32 = 0;
+        for order in 1..self.byte_hashes.len() {
+            let byte = (self.history >> (8 * (order - 1))) as u8;
+            hash = (hash ^ byte as u32)
+                .wrapping_mul(0x9E37_79B1)
+                .rotate_left(5)
+                ^ order as u32;
+            self.byte_hashes[order] = hash;
+        }
+    }
+
+    /// Selects the buckets of the next nibble in the hashed tables, from the preceding
+    /// bytes and the bits of the current byte before the nibble.
+    fn select_buckets(&mut self) {
+        for order in 2..self.buckets.len() {
+            let hash = self.byte_hashes[order]
+                .wrapping_add((self.bit_context as u32).wrapping_mul(0x2545_F491));
+            self.buckets[order] = (hash >> (32 - HASH_BITS)) as usize & !15;
+        }
+    }
+
+    /// Looks up the slot of the next bit in every table and mixes their predictions.
+    fn select_slots(&mut self) {
+        let context = self.bit_context;
+        for order in 0..self.slots.len() {
+            self.slots[order] = match order {
+                0 => context,
+                1 => ((self.history as usize & 0xFF) << 8) | context,
+                _ => self.buckets[order] + self.nibble_context,
+            };
+            let probability = self.tables[order][self.slots[order]] >> (COUNT_BITS + 10);
+            self.inputs[order] = self.stretch[probability as usize] as i32;
+        }
+        let bias = self.inputs.len() - 1;
+        self.inputs[bias] = BIAS;
+
+        let weights = &self.weights[context * self.inputs.len()..][..self.inputs.len()];
+        let dot: i64 = weights
+            .iter()
+            .zip(&self.inputs)
+            .map(|(&weight, &input)| weight as i64 * input as i64)
+            .sum();
+        self.prediction = squash((dot >> 16) as i32).clamp(1, 4095);
+    }
+}
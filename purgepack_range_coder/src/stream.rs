@@ -0,0 +1,217 @@
+//! Streaming adapters over the range coder format.
+//!
+//! [`PpEncoder`] models and codes every byte as soon as it is written, and [`PpDecoder`]
+//! restores the data of a compressed stream as it is read. The header holds the original
+//! length and the CRC32, which are only known at the end, so the encoder needs a seekable
+//! output to fill them in when it is finished.
+
+use shared_files::checksum::{Crc32, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    LENGTH_OFFSET, MAX_LEVEL, RangeCoderHeader, check_checksum, coder, decode_byte, encode_byte,
+    model, read_header, truncated, write_header,
+};
+
+/// A writer that compresses the data written to it into the range coder format.
+///
+/// The coder writes single bytes to the output, so a file should be wrapped in a
+/// `BufWriter`. Call [`PpEncoder::finish`] to flush the coder and fill in the header.
+/// Dropping the encoder does the same, but ignores any error.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Cursor, Read, Write};
+///
+/// let mut encoder = purgepack_range_coder::PpEncoder::new(Cursor::new(Vec::new()), 2).unwrap();
+/// encoder.write_all(b"to be or not to be").unwrap();
+/// let compressed = encoder.finish().unwrap().into_inner();
+///
+/// let mut decoder = purgepack_range_coder::PpDecoder::new(&compressed[..]).unwrap();
+/// let mut restored = Vec::new();
+/// decoder.read_to_end(&mut restored).unwrap();
+/// assert_eq!(restored, b"to be or not to be");
+/// ```
+pub struct PpEncoder<W: Write + Seek> {
+    /// The coder and the output. It is only taken by `finish`.
+    encoder: Option<coder::Encoder<W>>,
+    /// The position of the header in the output.
+    start: u64,
+    predictor: model::Predictor,
+    hasher: Crc32,
+    original_len: u64,
+}
+
+impl<W: Write + Seek> PpEncoder<W> {
+    /// Writes the header to `writer` and returns an encoder that compresses into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination, positioned where the compressed data starts.
+    /// * `level` - The highest context order mixed into the prediction, up to `MAX_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if the level is out of range, or an `io::Error` if
+    /// writing the header fails.
+    pub fn new(mut writer: W, level: u8) -> io::Result<Self> {
+        if level > MAX_LEVEL {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid level {}. The level must be between 0 and {}.",
+                    level, MAX_LEVEL
+                ),
+            ));
+        }
+        let start = writer.stream_position()?;
+        write_header(&mut writer, level, 0, 0)?;
+        Ok(PpEncoder {
+            encoder: Some(coder::Encoder::new(writer)),
+            start,
+            predictor: model::Predictor::new(level),
+            hasher: Crc32::new(),
+            original_len: 0,
+        })
+    }
+
+    /// Returns a reference to the output.
+    pub fn get_ref(&self) -> &W {
+        self.encoder
+            .as_ref()
+            .expect("the coder is only taken by finish")
+            .get_ref()
+    }
+
+    /// Flushes the coder, fills in the original length and the CRC32 in the header and
+    /// returns the output, positioned after the compressed data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing or seeking fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_footer()
+    }
+
+    /// Flushes the coder and fills in the header.
+    fn write_footer(&mut self) -> io::Result<W> {
+        let encoder = self
+            .encoder
+            .take()
+            .expect("the coder is only taken by finish");
+        let mut writer = encoder.finish()?;
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(self.start + LENGTH_OFFSET))?;
+        writer.write_all(&self.original_len.to_le_bytes())?;
+        writer.write_all(&self.hasher.value().to_le_bytes())?;
+        writer.seek(SeekFrom::Start(end))?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> Write for PpEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let encoder = self
+            .encoder
+            .as_mut()
+            .expect("the coder is only taken by finish");
+        for &byte in buf {
+            encode_byte(encoder, &mut self.predictor, byte)?;
+        }
+        self.hasher.update(buf);
+        self.original_len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    /// Does nothing. The bytes of the final interval are only written by `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Drop for PpEncoder<W> {
+    fn drop(&mut self) {
+        if self.encoder.is_some() {
+            let _ = self.write_footer();
+        }
+    }
+}
+
+/// The source of a `PpDecoder`. The coder reads its first bytes when it is created, so
+/// it is only created if there is data to restore.
+enum Source<R: Read> {
+    Empty(R),
+    Coded(coder::Decoder<R>),
+}
+
+/// A reader that restores the original data of a stream in the range coder format.
+///
+/// The CRC32 of the restored data is checked once the last byte has been read. A
+/// mismatch is reported as an `InvalidData` error instead of the end of the data.
+pub struct PpDecoder<R: Read> {
+    source: Source<R>,
+    header: RangeCoderHeader,
+    predictor: model::Predictor,
+    restored_len: u64,
+    hasher: Crc32,
+}
+
+impl<R: Read> PpDecoder<R> {
+    /// Reads the header from `reader` and returns a decoder for the data following it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the header is invalid or the data is truncated.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let header = read_header(&mut reader)?;
+        let source = if header.original_len > 0 {
+            Source::Coded(coder::Decoder::new(reader).map_err(truncated)?)
+        } else {
+            Source::Empty(reader)
+        };
+        Ok(PpDecoder {
+            source,
+            predictor: model::Predictor::new(header.level),
+            header,
+            restored_len: 0,
+            hasher: Crc32::new(),
+        })
+    }
+
+    /// Returns the header of the stream.
+    pub fn header(&self) -> &RangeCoderHeader {
+        &self.header
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        match self.source {
+            Source::Empty(reader) => reader,
+            Source::Coded(decoder) => decoder.into_inner(),
+        }
+    }
+}
+
+impl<R: Read> Read for PpDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.header.original_len - self.restored_len;
+        let len = (buf.len() as u64).min(remaining) as usize;
+        if len == 0 {
+            if remaining == 0 && !buf.is_empty() {
+                check_checksum(self.hasher.value(), &self.header)?;
+            }
+            return Ok(0);
+        }
+        let Source::Coded(decoder) = &mut self.source else {
+            unreachable!("a stream with data always has a coder");
+        };
+        for byte in &mut buf[..len] {
+            *byte = decode_byte(decoder, &mut self.predictor)?;
+        }
+        self.hasher.update(&buf[..len]);
+        self.restored_len += len as u64;
+        Ok(len)
+    }
+}
@@ -0,0 +1,358 @@
+//! The codec of the transpose module: a columnar transform for arrays of fixed-size
+//! records.
+//!
+//! Struct arrays, tables and sensor logs store the same field at the same offset of every
+//! record. Reading the data column by column instead of record by record puts the bytes
+//! of a field next to each other, so the slowly changing high bytes of counters or the
+//! repeated flags of a table turn into long runs that later stages compress far better.
+//!
+//! The input is split into blocks of whole records, at most `BLOCK_SIZE` bytes each, and
+//! every block is transposed on its own: first byte 0 of every record of the block, then
+//! byte 1, and so on. The bytes after the last whole record are stored as they are. A
+//! file is laid out as:
+//!
+//! ```text
+//! [magic: "PPCB"][module id: u8][format version: u8][original length: u64 LE]
+//! [CRC32: u32 LE][record size: u32 LE][transposed blocks ...][trailing bytes ...]
+//! ```
+//!
+//! The transform does not change the length of the data. The codec lives in its own
+//! crate so other modules can restore PPCB files of this format in memory, without going
+//! through the module entry points.
+use shared_files::checksum::{Crc32, Hasher};
+use shared_files::codec::{
+    self, CodecStats, Compressor, Counter, Decompressor, ReadSeek, WriteSeek,
+};
+use shared_files::frame::FrameFormat;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+mod stream;
+
+pub use stream::{PpDecoder, PpEncoder};
+
+/// Module ID (Algorithm Identifier) for the transpose module.
+pub const MODULE_ID: u8 = 0x08;
+/// The version of the header layout and payload format written by this build.
+pub const FORMAT_VERSION: u8 = 1;
+/// The frame format of the transpose module.
+const FORMAT: FrameFormat = FrameFormat::new(MODULE_ID, "Transpose", FORMAT_VERSION);
+/// The size of the header in bytes (4 bytes for magic + 1 byte for module ID + 1 byte for
+/// format version + 8 bytes for the original length + 4 bytes for the CRC32 of the
+/// original data + 4 bytes for the record size).
+const HEADER_SIZE: usize = 22;
+/// The offset of the original length in the header. `PpEncoder` writes it once all the
+/// data has been written.
+const LENGTH_OFFSET: u64 = 6;
+/// The offset of the checksum in the header. It is written once all the data has been read.
+const CHECKSUM_OFFSET: u64 = 14;
+/// The largest number of bytes per block. A block always holds at least one record.
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+/// The largest record size accepted.
+pub const MAX_RECORD_SIZE: u32 = 1024 * 1024;
+
+/// The header of a transposed file.
+pub struct TransposeHeader {
+    /// The length in bytes of the original data.
+    pub original_len: u64,
+    /// The CRC32 of the original data.
+    pub checksum: u32,
+    /// The size in bytes of a record.
+    pub record_size: u32,
+}
+
+/// Returns the number of records transposed together in a block.
+fn records_per_block(record_size: u32) -> u64 {
+    (BLOCK_SIZE / record_size as usize).max(1) as u64
+}
+
+/// Transposes everything `reader` holds into `writer`.
+///
+/// The header is written with a zero checksum first. Once all the data has been read,
+/// the CRC32 of the original data is filled in.
+///
+/// # Arguments
+///
+/// * `reader` - The source of the original data.
+/// * `writer` - The destination, positioned at the start of the output.
+/// * `original_len` - The length in bytes of the original data.
+/// * `record_size` - The size in bytes of a record, from 1 to [`MAX_RECORD_SIZE`].
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the record size is out of range, and an
+/// `io::Error` if reading or writing fails or the input is shorter than `original_len`.
+pub fn compress<R: Read, W: Write + Seek>(
+    reader: &mut R,
+    writer: &mut W,
+    original_len: u64,
+    record_size: u32,
+) -> io::Result<()> {
+    check_record_size(record_size)?;
+    let header = TransposeHeader {
+        original_len,
+        checksum: 0,
+        record_size,
+    };
+    write_header(writer, &header)?;
+
+    let mut hasher = Crc32::new();
+    transform_blocks(reader, writer, &header, &mut hasher, false)?;
+    writer.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+    writer.write_all(&hasher.value().to_le_bytes())?;
+    writer.flush()
+}
+
+/// Restores the original data of the blocks following the header into `writer`.
+///
+/// # Arguments
+///
+/// * `reader` - The transposed data, positioned right after the header.
+/// * `writer` - The destination of the original data.
+/// * `header` - The header read by [`read_header`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// data is truncated or the restored data does not match the checksum.
+pub fn decompress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &TransposeHeader,
+) -> io::Result<()> {
+    let mut hasher = Crc32::new();
+    transform_blocks(reader, writer, header, &mut hasher, true).map_err(truncated)?;
+    writer.flush()?;
+    check_checksum(hasher.value(), header)
+}
+
+/// Checks that `record_size` lies between 1 and `MAX_RECORD_SIZE`.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if it does not.
+fn check_record_size(record_size: u32) -> io::Result<()> {
+    if record_size == 0 || record_size > MAX_RECORD_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The record size must be between 1 and {} bytes.",
+                MAX_RECORD_SIZE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Maps a truncated read to an `InvalidData` error that names the cause.
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The transposed data is truncated. The file may be incomplete or corrupted.",
+        )
+    } else {
+        e
+    }
+}
+
+/// Checks the CRC32 of the restored data against the header.
+fn check_checksum(checksum: u32, header: &TransposeHeader) -> io::Result<()> {
+    if checksum != header.checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Checksum mismatch: the restored data has CRC32 0x{:08X}, but 0x{:08X} was expected. The file may be corrupted.",
+                checksum, header.checksum
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the blocks and trailing bytes described by `header`, transposes every block
+/// and writes the result. `hasher` is updated with the original data, which is the input
+/// when transposing and the output when `restore` is set.
+fn transform_blocks<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    header: &TransposeHeader,
+    hasher: &mut Crc32,
+    restore: bool,
+) -> io::Result<()> {
+    let record_size = header.record_size as u64;
+    let mut records = header.original_len / record_size;
+    let block_records = records_per_block(header.record_size);
+    let mut block = vec![0u8; (block_records.min(records) * record_size) as usize];
+    let mut output = block.clone();
+    while records > 0 {
+        let rows = block_records.min(records);
+        let len = (rows * record_size) as usize;
+        reader.read_exact(&mut block[..len])?;
+        transpose_records(
+            &block[..len],
+            &mut output[..len],
+            record_size as usize,
+            restore,
+        );
+        hasher.update(if restore {
+            &output[..len]
+        } else {
+            &block[..len]
+        });
+        writer.write_all(&output[..len])?;
+        records -= rows;
+    }
+
+    let tail_len = header.original_len % record_size;
+    let mut tail = vec![0u8; tail_len as usize];
+    reader.read_exact(&mut tail)?;
+    hasher.update(&tail);
+    writer.write_all(&tail)
+}
+
+/// Transposes the whole records in `block` into `output`, or restores them if `restore`
+/// is set.
+fn transpose_records(block: &[u8], output: &mut [u8], record_size: usize, restore: bool) {
+    let rows = block.len() / record_size;
+    if restore {
+        // The columns of a transposed block are its rows, so transposing them again
+        // restores the records.
+        transpose(block, output, record_size, rows);
+    } else {
+        transpose(block, output, rows, record_size);
+    }
+}
+
+/// Writes the `rows` x `columns` matrix stored row by row in `input` column by column
+/// into `output`.
+fn transpose(input: &[u8], output: &mut [u8], rows: usize, columns: usize) {
+    for (column, output_column) in output.chunks_exact_mut(rows).enumerate() {
+        for (row, byte) in output_column.iter_mut().enumerate() {
+            *byte = input[row * columns + column];
+        }
+    }
+}
+
+/// Writes the header.
+fn write_header<W: Write>(writer: &mut W, header: &TransposeHeader) -> io::Result<()> {
+    FORMAT.write_prefix(writer)?;
+    writer.write_all(&header.original_len.to_le_bytes())?;
+    writer.write_all(&header.checksum.to_le_bytes())?;
+    writer.write_all(&header.record_size.to_le_bytes())
+}
+
+/// Reads and validates the header.
+///
+/// # Errors
+///
+/// Returns an `UnexpectedEof` error if the file is too short, or an `InvalidData` error
+/// if it was not written by this module, uses a newer format version or holds an invalid record size.
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<TransposeHeader> {
+    let bytes: [u8; HEADER_SIZE] = FORMAT.read_header(reader)?;
+    let record_size = u32::from_le_bytes(bytes[18..22].try_into().unwrap());
+    if record_size == 0 || record_size > MAX_RECORD_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Invalid record size {}. The file may be corrupted.",
+                record_size
+            ),
+        ));
+    }
+    Ok(TransposeHeader {
+        original_len: u64::from_le_bytes(bytes[6..14].try_into().unwrap()),
+        checksum: u32::from_le_bytes(bytes[14..18].try_into().unwrap()),
+        record_size,
+    })
+}
+
+/// The transpose transform with its settings, for use through the `Compressor` and
+/// `Decompressor` traits of `shared_files::codec`.
+#[derive(Debug, Clone, Copy)]
+pub struct Transpose {
+    /// The size in bytes of a record, from 1 to `MAX_RECORD_SIZE`. Decoding uses the
+    /// record size stored in the header instead.
+    pub record_size: u32,
+}
+
+impl Compressor for Transpose {
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        codec::compress_in_memory(reader, writer, |input, output| {
+            compress(
+                &mut &input[..],
+                output,
+                input.len() as u64,
+                self.record_size,
+            )
+        })
+    }
+
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        codec::compress_stream(reader, writer, |reader, writer, input_len| {
+            compress(reader, writer, input_len, self.record_size)
+        })
+    }
+}
+
+impl Decompressor for Transpose {
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+        let mut reader = Counter::new(reader);
+        let mut writer = Counter::new(writer);
+        let header = read_header(&mut reader)?;
+        decompress(&mut reader, &mut writer, &header)?;
+        Ok(CodecStats {
+            input_len: reader.count(),
+            output_len: writer.count(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::selftest::{self, SelfTestReport};
+
+    #[test]
+    fn passes_the_self_test_checks() {
+        let mut report = SelfTestReport::new();
+        report.round_trip("default", &Transpose { record_size: 4 });
+        report.streams("default", &Transpose { record_size: 4 });
+        let failed: Vec<_> = report.failed().collect();
+        assert!(failed.is_empty(), "{:?}", failed);
+    }
+
+    #[test]
+    fn rejects_damaged_and_truncated_input() {
+        let text = selftest::sample("text").unwrap();
+        let codec = Transpose { record_size: 4 };
+        selftest::rejects_damage(&codec, &text, |len| vec![0, len / 2, len - 1]).unwrap();
+    }
+
+    #[test]
+    fn stores_the_bytes_after_the_last_record_as_they_are() {
+        let mut file = Vec::new();
+        Transpose { record_size: 3 }
+            .compress(&mut &b"abcdefgh"[..], &mut file)
+            .unwrap();
+        assert_eq!(&file[HEADER_SIZE..], b"adbecfgh");
+
+        for record_size in [2, 3, 7, 16] {
+            let codec = Transpose { record_size };
+            for len in [1, record_size as usize - 1, 10 * record_size as usize + 1] {
+                let data: Vec<u8> = (0..len).map(|i| (i * 31 % 251) as u8).collect();
+                let mut file = Vec::new();
+                codec.compress(&mut &data[..], &mut file).unwrap();
+                let mut restored = Vec::new();
+                codec.decompress(&mut &file[..], &mut restored).unwrap();
+                assert_eq!(
+                    restored, data,
+                    "{} bytes of records of {}",
+                    len, record_size
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Streaming adapters over the transpose format.
+//!
+//! [`PpEncoder`] transposes the data written to it one block at a time, as soon as a
+//! block of records is full, and [`PpDecoder`] restores the records of a transposed
+//! stream as they are read. The header holds the original length and the CRC32, which
+//! are only known at the end, so the encoder needs a seekable output to fill them in when
+//! it is finished.
+
+use shared_files::checksum::{Crc32, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    LENGTH_OFFSET, TransposeHeader, check_checksum, check_record_size, read_header,
+    records_per_block, transpose_records, truncated, write_header,
+};
+
+/// A writer that transposes the records written to it.
+///
+/// Call [`PpEncoder::finish`] to write the last records and the trailing bytes and fill
+/// in the header. Dropping the encoder does the same, but ignores any error.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Cursor, Read, Write};
+///
+/// let mut encoder = purgepack_transpose::PpEncoder::new(Cursor::new(Vec::new()), 4).unwrap();
+/// encoder.write_all(&[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 9]).unwrap();
+/// let transposed = encoder.finish().unwrap().into_inner();
+/// assert_eq!(transposed[22..], [1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9]);
+///
+/// let mut decoder = purgepack_transpose::PpDecoder::new(&transposed[..]).unwrap();
+/// let mut restored = Vec::new();
+/// decoder.read_to_end(&mut restored).unwrap();
+/// assert_eq!(restored, [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 9]);
+/// ```
+pub struct PpEncoder<W: Write + Seek> {
+    /// The output. It is only taken by `finish`.
+    writer: Option<W>,
+    /// The position of the header in the output.
+    start: u64,
+    record_size: usize,
+    /// The number of bytes of a full block.
+    block_len: usize,
+    /// The data of the block being filled.
+    block: Vec<u8>,
+    output: Vec<u8>,
+    hasher: Crc32,
+    original_len: u64,
+}
+
+impl<W: Write + Seek> PpEncoder<W> {
+    /// Writes the header to `writer` and returns an encoder that transposes into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination, positioned where the transposed data starts.
+    /// * `record_size` - The size in bytes of a record, from 1 to [`MAX_RECORD_SIZE`](crate::MAX_RECORD_SIZE).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if the record size is out of range, or an
+    /// `io::Error` if writing the header fails.
+    pub fn new(mut writer: W, record_size: u32) -> io::Result<Self> {
+        check_record_size(record_size)?;
+        let start = writer.stream_position()?;
+        write_header(
+            &mut writer,
+            &TransposeHeader {
+                original_len: 0,
+                checksum: 0,
+                record_size,
+            },
+        )?;
+        let block_len = (records_per_block(record_size) * record_size as u64) as usize;
+        Ok(PpEncoder {
+            writer: Some(writer),
+            start,
+            record_size: record_size as usize,
+            block_len,
+            block: Vec::with_capacity(block_len),
+            output: vec![0u8; block_len],
+            hasher: Crc32::new(),
+            original_len: 0,
+        })
+    }
+
+    /// Returns a reference to the output.
+    pub fn get_ref(&self) -> &W {
+        self.writer
+            .as_ref()
+            .expect("the writer is only taken by finish")
+    }
+
+    /// Writes the last records and the trailing bytes, fills in the original length and
+    /// the CRC32 in the header and returns the output, positioned after the data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing or seeking fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_footer()?;
+        Ok(self
+            .writer
+            .take()
+            .expect("the writer is only taken by finish"))
+    }
+
+    /// Transposes and writes the whole records of the block being filled, followed by
+    /// the bytes after the last whole record.
+    fn write_pending_block(&mut self) -> io::Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken by finish");
+        let len = self.block.len() / self.record_size * self.record_size;
+        if len > 0 {
+            transpose_records(
+                &self.block[..len],
+                &mut self.output[..len],
+                self.record_size,
+                false,
+            );
+            writer.write_all(&self.output[..len])?;
+        }
+        writer.write_all(&self.block[len..])?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes the last block and fills in the header.
+    fn write_footer(&mut self) -> io::Result<()> {
+        self.write_pending_block()?;
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken by finish");
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(self.start + LENGTH_OFFSET))?;
+        writer.write_all(&self.original_len.to_le_bytes())?;
+        writer.write_all(&self.hasher.value().to_le_bytes())?;
+        writer.seek(SeekFrom::Start(end))?;
+        writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Write for PpEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.block_len - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        self.hasher.update(&buf[..len]);
+        self.original_len += len as u64;
+        if self.block.len() == self.block_len {
+            self.write_pending_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the output. The block being filled is kept until it is full or the
+    /// encoder is finished, as the format only allows a shorter block at the end.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("the writer is only taken by finish")
+            .flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for PpEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_footer();
+        }
+    }
+}
+
+/// A reader that restores the records of a transposed stream.
+///
+/// The CRC32 of the restored data is checked once the last byte has been read. A
+/// mismatch is reported as an `InvalidData` error instead of the end of the data.
+pub struct PpDecoder<R: Read> {
+    reader: R,
+    header: TransposeHeader,
+    /// The number of whole records not read yet.
+    records: u64,
+    /// Whether the bytes after the last whole record have been read.
+    tail_read: bool,
+    block: Vec<u8>,
+    /// The original data of the current block.
+    output: Vec<u8>,
+    /// The number of bytes of `output` already returned.
+    position: usize,
+    hasher: Crc32,
+}
+
+impl<R: Read> PpDecoder<R> {
+    /// Reads the header from `reader` and returns a decoder for the data following it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the header is invalid.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let header = read_header(&mut reader)?;
+        Ok(PpDecoder {
+            reader,
+            records: header.original_len / header.record_size as u64,
+            header,
+            tail_read: false,
+            block: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+            hasher: Crc32::new(),
+        })
+    }
+
+    /// Returns the header of the stream.
+    pub fn header(&self) -> &TransposeHeader {
+        &self.header
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads and restores the next block of records, or the trailing bytes once all
+    /// records have been read.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if the end of the data has been reached.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let record_size = self.header.record_size as u64;
+        if self.records > 0 {
+            let rows = records_per_block(self.header.record_size).min(self.records);
+            let len = (rows * record_size) as usize;
+            self.block.resize(len, 0);
+            self.output.resize(len, 0);
+            self.reader.read_exact(&mut self.block).map_err(truncated)?;
+            transpose_records(&self.block, &mut self.output, record_size as usize, true);
+            self.records -= rows;
+        } else if !self.tail_read {
+            let tail_len = (self.header.original_len % record_size) as usize;
+            self.output.resize(tail_len, 0);
+            self.reader
+                .read_exact(&mut self.output)
+                .map_err(truncated)?;
+            self.tail_read = true;
+        } else {
+            return Ok(false);
+        }
+        self.hasher.update(&self.output);
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for PpDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if !self.read_block()? {
+                check_checksum(self.hasher.value(), &self.header)?;
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.output.len() - self.position);
+        buf[..len].copy_from_slice(&self.output[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
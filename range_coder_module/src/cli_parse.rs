@@ -0,0 +1,81 @@
+use clap::{Args, Parser, Subcommand};
+use purgepack_range_coder::MAX_LEVEL;
+use shared_files::cli::CommonArgs;
+use shared_files::cli::{self, CliError, Validate};
+
+/// Arguments that only apply to compression.
+///
+/// Decompression reads these settings back from the file header.
+#[derive(Debug, Clone, Args)]
+pub struct CompressArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The highest context order mixed into the prediction (0 = byte frequencies only,
+    /// 1 = also the previous byte, ...). Higher levels compress text better but are slower
+    /// and use more memory (16 MiB per order from order 2 on).
+    #[arg(short, long, default_value_t = DEFAULT_LEVEL, value_parser = clap::value_parser!(u8).range(0..=MAX_LEVEL as i64))]
+    pub level: u8,
+}
+
+/// The level used when `--level` is not given.
+pub const DEFAULT_LEVEL: u8 = 2;
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Compresses a file with the context-mixing model and the range coder.
+    #[clap(alias = "c")]
+    Compress(CompressArgs),
+    /// Restores a file written by `compress`.
+    #[clap(alias = "d")]
+    Decompress(CommonArgs),
+}
+
+/// The main command line argument structure for the Range Coder Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Context-Mixing Range Coder Utility.",
+    long_about = "A compressor that predicts every bit from the preceding bytes with a mix of context models of several orders, and codes it with a binary range coder.",
+    after_help = "
+    EXAMPLES:
+    # 1. Compress with the default level (orders 0 to 2)
+    range_tool.exe compress notes.txt notes.ppcb
+
+    # 2. Compress text as tightly as possible and show statistics
+    range_tool.exe c notes.txt notes.ppcb --level 4 -s
+
+    # 3. Decompress
+    range_tool.exe d notes.ppcb restored.txt
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file exists and is a file.
+    /// 2. The parent directory for the output file exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        let common = match &self.command {
+            Commands::Compress(args) => &args.common,
+            Commands::Decompress(args) => args,
+        };
+
+        // --- Input File Validation ---
+        cli::check_input(&common.input_file)?;
+
+        // --- Output Directory Validation ---
+        cli::check_output(&common.output_file)
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
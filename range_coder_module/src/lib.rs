@@ -0,0 +1,305 @@
+//! A context-mixing compressor built on a binary range coder.
+//!
+//! The codec itself lives in the `purgepack_range_coder` crate. This module handles the
+//! command line, the files and the statistics.
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+mod cli_parse;
+use purgepack_range_coder::RangeCoder;
+use purgepack_range_coder::{FORMAT_VERSION, MODULE_ID};
+use shared_files::abi::{self, RawSeekReader, RawSeekWriter, ServiceResult};
+use shared_files::cancel::{CancellableReader, is_cancelled_error};
+use shared_files::cli::{self, CliError};
+use shared_files::codec::{Compressor, Decompressor};
+use shared_files::core_header::{self};
+use shared_files::error::ExitStatus;
+use shared_files::events::{self, Event};
+use shared_files::output::{Overwrite, SafeWriter};
+use shared_files::progress::{self, ProgressReader};
+use shared_files::selftest::{SelfTestReport, SelfTestSink};
+use shared_files::stats::{DEFAULT_SAMPLE_INTERVAL, StatsFormat};
+use shared_files::{log_debug, log_error, log_info, log_warn};
+use std::ffi::c_char;
+
+/// The file extension for PurgePack Compressed Binary (PPCB) files.
+const FILE_EXTENSION: &str = "ppcb";
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("range_coder", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Compress(args) => {
+                log_info!(
+                    "Compress: Input: {}, Output: {}, Level: {}",
+                    args.common.input_file.display(),
+                    args.common.output_file.display(),
+                    args.level
+                );
+                match compress_file(
+                    core,
+                    &args.common.input_file,
+                    args.common.output_file,
+                    args.level,
+                    args.common.stats.format(),
+                    args.common.overwrite.policy(),
+                ) {
+                    Ok(()) => log_info!("Compress: Success"),
+                    Err(e) if is_cancelled_error(&e) => {
+                        log_warn!("Compress: Cancelled");
+                        core_header::report_status(core, ExitStatus::Cancelled);
+                    }
+                    Err(e) => {
+                        log_error!("Compress: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Decompress(args) => {
+                log_info!(
+                    "Decompress: Input: {}, Output: {}",
+                    args.input_file.display(),
+                    args.output_file.display()
+                );
+                match decompress_file(
+                    core,
+                    &args.input_file,
+                    &args.output_file,
+                    args.stats.format(),
+                    args.overwrite.policy(),
+                ) {
+                    Ok(()) => log_info!("Decompress: Success"),
+                    Err(e) if is_cancelled_error(&e) => {
+                        log_warn!("Decompress: Cancelled");
+                        core_header::report_status(core, ExitStatus::Cancelled);
+                    }
+                    Err(e) => {
+                        log_error!("Decompress: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// Registers the pipeline step of the module with the core.
+#[unsafe(no_mangle)]
+extern "C" fn module_register(core: &core_header::CoreH) {
+    core_header::register_step(core, env!("CARGO_PKG_NAME"), pipeline_step);
+}
+
+/// Runs a step of a pipeline of the core, like `compress --level 2`, on the
+/// streams the core hands over instead of files.
+extern "C" fn pipeline_step(
+    core: &core_header::CoreH,
+    argc: usize,
+    argv: *const *const c_char,
+    input: &mut RawSeekReader,
+    output: &mut RawSeekWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    shared_files::log::init("range_coder", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let args = unsafe { abi::args_from_c(argc, argv) };
+    abi::serve_step(core, input, output, result, |reader, writer| {
+        let mut reader = CancellableReader::new(reader, core);
+        match cli::parse_step::<cli_parse::CliArgs>(&args)?.command {
+            cli_parse::Commands::Compress(args) => {
+                RangeCoder { level: args.level }.compress_file(&mut reader, writer)
+            }
+            cli_parse::Commands::Decompress(_) => {
+                RangeCoder::default().decompress(&mut reader, writer)
+            }
+        }
+    })
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("range_coder", core.log_level);
+    log_debug!("Range coder module shutting down.");
+}
+
+/// Runs round trips of the codec on synthetic data for `purgepack test`.
+#[unsafe(no_mangle)]
+extern "C" fn module_selftest(_core: &core_header::CoreH, sink: &mut SelfTestSink) {
+    let mut report = SelfTestReport::new();
+    for level in 0..=purgepack_range_coder::MAX_LEVEL {
+        report.round_trip(&format!("level {}", level), &RangeCoder { level });
+    }
+    report.send(sink);
+}
+
+/// Compresses `input_file` into `output_file`.
+///
+/// # Arguments
+///
+/// * `input_file` - The path to the source file.
+/// * `output_file` - The path to the destination file. The `.ppcb` extension is added if
+///   it has no extension.
+/// * `level` - The highest context order mixed into the prediction.
+/// * `stats` - The format to print statistics in, or `None` to skip them.
+/// * `overwrite` - What happens if the output file exists already.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+fn compress_file(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    mut output_file: PathBuf,
+    level: u8,
+    stats: Option<StatsFormat>,
+    overwrite: Overwrite,
+) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
+    if output_file.extension().is_none() {
+        output_file.set_extension(FILE_EXTENSION);
+        log_info!(
+            "Compress: Automatic extension '{}' placed on output file: {}",
+            FILE_EXTENSION,
+            output_file.display()
+        );
+    }
+    let input = File::open(input_file)?;
+    let original_len = input.metadata()?.len();
+    let mut reader = BufReader::new(CancellableReader::new(
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress::terminal("Compress", Some(original_len)),
+        ),
+        core,
+    ));
+    let output_file = overwrite.resolve(&output_file)?;
+    let mut writer = BufWriter::new(SafeWriter::create(&output_file)?);
+
+    let t_coding = main_timer.start_section("Modelling and Coding");
+    purgepack_range_coder::compress(&mut reader, &mut writer, original_len, level)?;
+    main_timer.add_section(t_coding);
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(core, main_timer, &output, original_len, level, true, format)?;
+    }
+    Ok(())
+}
+
+/// Restores the original data of `input_file` into `output_file`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// header is invalid, the data is truncated or the restored data does not match the
+/// checksum.
+fn decompress_file(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    output_file: &Path,
+    stats: Option<StatsFormat>,
+    overwrite: Overwrite,
+) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
+    let input = File::open(input_file)?;
+    let compressed_len = input.metadata()?.len();
+    let mut reader = BufReader::new(CancellableReader::new(
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress::terminal("Decompress", Some(compressed_len)),
+        ),
+        core,
+    ));
+    let output_file = &overwrite.resolve(output_file)?;
+    let mut writer = BufWriter::new(SafeWriter::create(output_file)?);
+
+    let t_header = main_timer.start_section("Header Read");
+    let header = purgepack_range_coder::read_header(&mut reader)?;
+    main_timer.add_section(t_header);
+
+    let t_coding = main_timer.start_section("Modelling and Decoding");
+    purgepack_range_coder::decompress(&mut reader, &mut writer, &header)?;
+    main_timer.add_section(t_coding);
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(
+            core,
+            main_timer,
+            &output,
+            compressed_len,
+            header.level,
+            false,
+            format,
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the statistics of a finished operation and emits them as a `stats` event.
+///
+/// # Arguments
+///
+/// * `core` - The core the `stats` event is emitted to.
+/// * `main_timer` - The timer holding the sections of the operation.
+/// * `output` - The output file, whose length is the processed length.
+/// * `input_len` - The length of the input file.
+/// * `level` - The level the data was compressed with.
+/// * `is_compression` - Whether the operation compressed the data.
+/// * `format` - The format to print the statistics in.
+fn print_statistics(
+    core: &core_header::CoreH,
+    main_timer: shared_files::stats::OptinalStatsTimer,
+    output: &File,
+    input_len: u64,
+    level: u8,
+    is_compression: bool,
+    format: StatsFormat,
+) -> io::Result<()> {
+    let peak_memory = main_timer.peak_memory();
+    let throughput = main_timer.throughput();
+    let (total_duration, sections) = main_timer.end();
+    let output_len = output.metadata()?.len();
+    println!("Model: orders 0 to {} mixed", level);
+    let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
+        .algorithm_name("Context-Mixing Range Coder")
+        .algorithm_id(MODULE_ID)
+        .version_used(FORMAT_VERSION)
+        .original_len(input_len as usize)
+        .processed_len(output_len as usize)
+        .duration(total_duration)
+        .is_compression(is_compression)
+        .sections(sections)
+        .peak_memory(peak_memory)
+        .throughput(throughput)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
+    println!("{}", calculated_stats.render(format));
+    events::emit(
+        core,
+        &Event::Stats {
+            stats: &calculated_stats,
+        },
+    );
+    Ok(())
+}
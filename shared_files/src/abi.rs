@@ -0,0 +1,604 @@
+//! The binary interface between the core and the modules.
+//!
+//! The core and the modules are separate dynamic libraries that may be built with
+//! different compiler versions, or written in other languages. Everything that crosses
+//! the boundary therefore has a fixed layout: `repr(C)` structs, `extern "C"` functions,
+//! raw pointers with lengths and NUL-terminated strings. `Vec`, `&str` and trait objects
+//! only live on either side and are converted by the helpers here and in
+//! [`core_header`](crate::core_header).
+//!
+//! # Exports
+//!
+//! A module exports these unmangled `extern "C"` functions:
+//!
+//! * `module_abi_version`, of type [`AbiVersionFn`], through [`export_module_abi!`]
+//!   (required).
+//! * `module_manifest`, of type [`ManifestFn`], through [`export_module_abi!`] (optional, so
+//!   modules built before it still load).
+//! * `module_startup`, of type [`StartupFn`] (required).
+//! * `module_register`, of type [`RegisterFn`], to register services and pipeline steps
+//!   (optional).
+//! * `module_shutdown`, of type [`ShutdownFn`] (required).
+//! * `module_selftest`, of type [`SelfTestFn`](crate::selftest::SelfTestFn) (optional).
+//!
+//! ```rust,ignore
+//! shared_files::export_module_abi!();
+//!
+//! #[unsafe(no_mangle)]
+//! extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+//!     // SAFETY: the core passes `argc` valid C strings.
+//!     let args = unsafe { abi::args_from_c(argc, argv) };
+//!     ...
+//! }
+//! ```
+//!
+//! # Versioning
+//!
+//! [`ABI_VERSION`] is raised whenever the layout of [`CoreH`] or the signature of an
+//! export changes incompatibly. The core calls `module_abi_version` before anything else
+//! and skips modules built for another version, or without the export, instead of calling
+//! into them. New fields are only ever appended to `CoreH`, whose `size` field tells a
+//! module which of them the core provides.
+
+use crate::codec::{CodecStats, ReadSeek, WriteSeek};
+use crate::core_header::{self, CoreH, Service, StepService};
+use std::{
+    borrow::Cow,
+    ffi::{CStr, CString, c_char, c_void},
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr, slice,
+};
+
+/// The version of the interface described by this module and [`CoreH`].
+pub const ABI_VERSION: u32 = 1;
+
+/// The type of the `module_abi_version` export: returns the [`ABI_VERSION`] the module was
+/// built with.
+pub type AbiVersionFn = extern "C" fn() -> u32;
+/// The type of the `module_manifest` export: returns a static C string of `key=value`
+/// lines describing the module, at least `name=` and `version=` of its package.
+pub type ManifestFn = extern "C" fn() -> *const c_char;
+/// The type of the `module_startup` export: runs the module with its `argc` arguments,
+/// given as an array of C strings.
+pub type StartupFn = extern "C" fn(core: &CoreH, argc: usize, argv: *const *const c_char);
+/// The type of the `module_register` export, called on every module before any starts.
+pub type RegisterFn = extern "C" fn(core: &CoreH);
+/// The type of the `module_shutdown` export, called before the module is unloaded.
+pub type ShutdownFn = extern "C" fn(core: &CoreH);
+
+/// Exports `module_abi_version`, which tells the core the [`ABI_VERSION`] the module was
+/// built with, and `module_manifest`, which names the package and version of the module
+/// from its `Cargo.toml`. Every module must invoke it once.
+#[macro_export]
+macro_rules! export_module_abi {
+    () => {
+        /// Returns the version of the module interface this module was built with.
+        #[unsafe(no_mangle)]
+        extern "C" fn module_abi_version() -> u32 {
+            $crate::abi::ABI_VERSION
+        }
+
+        /// Returns the manifest of the module, see [`ManifestFn`]($crate::abi::ManifestFn).
+        #[unsafe(no_mangle)]
+        extern "C" fn module_manifest() -> *const ::std::ffi::c_char {
+            concat!(
+                "name=",
+                env!("CARGO_PKG_NAME"),
+                "\nversion=",
+                env!("CARGO_PKG_VERSION"),
+                "\n\0"
+            )
+            .as_ptr()
+            .cast()
+        }
+    };
+}
+
+/// Returns `s` as a C string. NUL bytes, which C strings cannot hold, are dropped.
+///
+/// ```rust
+/// use shared_files::abi::to_c_string;
+///
+/// assert_eq!(to_c_string("a\0b").as_bytes(), b"ab");
+/// ```
+pub fn to_c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).expect("the NUL bytes were removed")
+}
+
+/// Returns the text of the C string `s`, replacing invalid UTF-8.
+///
+/// # Safety
+///
+/// `s` must point to a NUL-terminated string that stays valid for `'a`.
+pub unsafe fn from_c_str<'a>(s: *const c_char) -> Cow<'a, str> {
+    unsafe { CStr::from_ptr(s) }.to_string_lossy()
+}
+
+/// Arguments as an array of C strings, as the core passes them to `module_startup`.
+///
+/// ```rust
+/// use shared_files::abi::{CArgs, args_from_c};
+///
+/// let args = CArgs::new(&["-i".to_string(), "input.txt".to_string()]);
+/// // SAFETY: `args` outlives the call.
+/// let restored = unsafe { args_from_c(args.argc(), args.argv()) };
+/// assert_eq!(restored, ["-i", "input.txt"]);
+/// ```
+#[derive(Debug)]
+pub struct CArgs {
+    strings: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+impl CArgs {
+    /// Converts `args` with [`to_c_string`].
+    pub fn new(args: &[String]) -> Self {
+        let strings: Vec<CString> = args.iter().map(|arg| to_c_string(arg)).collect();
+        let pointers = strings.iter().map(|arg| arg.as_ptr()).collect();
+        CArgs { strings, pointers }
+    }
+
+    /// Returns the number of arguments.
+    pub fn argc(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns the array of the arguments, valid as long as `self`.
+    pub fn argv(&self) -> *const *const c_char {
+        self.pointers.as_ptr()
+    }
+}
+
+/// Converts the arguments a module receives in `module_startup`.
+///
+/// # Safety
+///
+/// `argv` must point to `argc` valid NUL-terminated strings, or `argc` must be 0.
+pub unsafe fn args_from_c(argc: usize, argv: *const *const c_char) -> Vec<String> {
+    if argc == 0 || argv.is_null() {
+        return Vec::new();
+    }
+
+    unsafe { slice::from_raw_parts(argv, argc) }
+        .iter()
+        .map(|&arg| unsafe { from_c_str(arg) }.into_owned())
+        .collect()
+}
+
+/// A byte buffer handed between the core and a module, like from
+/// [`acquire_buffer`](crate::core_header::acquire_buffer).
+///
+/// The memory comes from the global allocator of the side that created the buffer. The
+/// default allocator of Rust is the system allocator on both sides, so the other side may
+/// turn the buffer back into a `Vec` with [`RawBuffer::into_vec`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct RawBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl RawBuffer {
+    /// Takes over the memory of `buffer`.
+    pub fn from_vec(buffer: Vec<u8>) -> Self {
+        let mut buffer = ManuallyDrop::new(buffer);
+        RawBuffer {
+            ptr: buffer.as_mut_ptr(),
+            len: buffer.len(),
+            capacity: buffer.capacity(),
+        }
+    }
+
+    /// Turns the buffer back into a `Vec`.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must come from [`RawBuffer::from_vec`], on a side that uses the same
+    /// allocator.
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        unsafe { Vec::from_raw_parts(self.ptr, self.len, self.capacity) }
+    }
+}
+
+/// A reader passed across the boundary, like to a [`Service`]. It implements `Read`.
+#[repr(C)]
+pub struct RawReader<'a> {
+    ctx: *mut c_void,
+    /// Reads up to `len` bytes into `buf` and returns their number, 0 at the end of the
+    /// input, or -1 if reading failed.
+    read_f: extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize,
+    _inner: PhantomData<&'a mut ()>,
+}
+
+/// A writer passed across the boundary, like to a [`Service`]. It implements `Write`.
+#[repr(C)]
+pub struct RawWriter<'a> {
+    ctx: *mut c_void,
+    /// Writes up to `len` bytes of `buf` and returns their number, or -1 if writing failed.
+    write_f: extern "C" fn(ctx: *mut c_void, buf: *const u8, len: usize) -> isize,
+    /// Flushes the writer and returns `false` if that failed.
+    flush_f: extern "C" fn(ctx: *mut c_void) -> bool,
+    _inner: PhantomData<&'a mut ()>,
+}
+
+/// A seekable reader passed across the boundary, like to a [`StepService`]. It implements
+/// `Read` and `Seek`.
+#[repr(C)]
+pub struct RawSeekReader<'a> {
+    ctx: *mut c_void,
+    /// Reads like [`RawReader`].
+    read_f: extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize,
+    /// Seeks like `Seek::seek` and returns the new position, or -1 if seeking failed.
+    /// `whence` is 0, 1 or 2 for `SeekFrom::Start`, `SeekFrom::Current` and
+    /// `SeekFrom::End`.
+    seek_f: extern "C" fn(ctx: *mut c_void, whence: u8, offset: i64) -> i64,
+    _inner: PhantomData<&'a mut ()>,
+}
+
+/// A seekable writer passed across the boundary, like to a [`StepService`]. It implements
+/// `Write` and `Seek`.
+#[repr(C)]
+pub struct RawSeekWriter<'a> {
+    ctx: *mut c_void,
+    /// Writes like [`RawWriter`].
+    write_f: extern "C" fn(ctx: *mut c_void, buf: *const u8, len: usize) -> isize,
+    /// Flushes like [`RawWriter`].
+    flush_f: extern "C" fn(ctx: *mut c_void) -> bool,
+    /// Seeks like [`RawSeekReader`].
+    seek_f: extern "C" fn(ctx: *mut c_void, whence: u8, offset: i64) -> i64,
+    _inner: PhantomData<&'a mut ()>,
+}
+
+/// A reader or writer behind one of the raw streams, with the error it failed with. Only
+/// a generic error crosses the boundary, so the side that owns the stream keeps the
+/// original error to report it.
+struct Endpoint<'a, T: ?Sized> {
+    inner: &'a mut T,
+    error: Option<io::Error>,
+}
+
+impl<'a, T: ?Sized> Endpoint<'a, T> {
+    fn new(inner: &'a mut T) -> Self {
+        Endpoint { inner, error: None }
+    }
+
+    /// Returns the result of `f`, or `None` if it failed, keeping the error.
+    fn call<V>(&mut self, mut f: impl FnMut(&mut T) -> io::Result<V>) -> Option<V> {
+        loop {
+            match f(&mut *self.inner) {
+                Ok(value) => return Some(value),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+extern "C" fn read_endpoint<T: Read + ?Sized>(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize {
+    // SAFETY: `ctx` is the endpoint the reader was created from, which outlives it, and
+    // the caller passes a buffer of `len` bytes.
+    let endpoint = unsafe { &mut *(ctx as *mut Endpoint<'_, T>) };
+    let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+    endpoint
+        .call(|inner| inner.read(buf))
+        .map_or(-1, |len| len as isize)
+}
+
+extern "C" fn write_endpoint<T: Write + ?Sized>(
+    ctx: *mut c_void,
+    buf: *const u8,
+    len: usize,
+) -> isize {
+    // SAFETY: as in `read_endpoint`.
+    let endpoint = unsafe { &mut *(ctx as *mut Endpoint<'_, T>) };
+    let buf = unsafe { slice::from_raw_parts(buf, len) };
+    endpoint
+        .call(|inner| inner.write(buf))
+        .map_or(-1, |len| len as isize)
+}
+
+extern "C" fn flush_endpoint<T: Write + ?Sized>(ctx: *mut c_void) -> bool {
+    // SAFETY: as in `read_endpoint`.
+    let endpoint = unsafe { &mut *(ctx as *mut Endpoint<'_, T>) };
+    endpoint.call(|inner| inner.flush()).is_some()
+}
+
+extern "C" fn seek_endpoint<T: Seek + ?Sized>(ctx: *mut c_void, whence: u8, offset: i64) -> i64 {
+    // SAFETY: as in `read_endpoint`.
+    let endpoint = unsafe { &mut *(ctx as *mut Endpoint<'_, T>) };
+    let pos = match whence {
+        0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset),
+        _ => SeekFrom::End(offset),
+    };
+    endpoint
+        .call(|inner| inner.seek(pos))
+        .and_then(|position| i64::try_from(position).ok())
+        .unwrap_or(-1)
+}
+
+/// Seeks a raw stream through its `seek_f`.
+fn seek_raw(
+    seek_f: extern "C" fn(*mut c_void, u8, i64) -> i64,
+    ctx: *mut c_void,
+    pos: SeekFrom,
+) -> io::Result<u64> {
+    // An offset from the start beyond `i64::MAX` wraps, and `seek_endpoint` wraps it back.
+    let (whence, offset) = match pos {
+        SeekFrom::Start(offset) => (0, offset as i64),
+        SeekFrom::Current(offset) => (1, offset),
+        SeekFrom::End(offset) => (2, offset),
+    };
+    u64::try_from(seek_f(ctx, whence, offset))
+        .map_err(|_| io::Error::other("Seeking in the stream of the caller failed."))
+}
+
+impl<'a> RawReader<'a> {
+    fn new(endpoint: &'a mut Endpoint<'_, dyn Read + '_>) -> Self {
+        RawReader {
+            ctx: ptr::from_mut(endpoint).cast(),
+            read_f: read_endpoint::<dyn Read + '_>,
+            _inner: PhantomData,
+        }
+    }
+}
+
+impl<'a> RawWriter<'a> {
+    fn new(endpoint: &'a mut Endpoint<'_, dyn Write + '_>) -> Self {
+        RawWriter {
+            ctx: ptr::from_mut(endpoint).cast(),
+            write_f: write_endpoint::<dyn Write + '_>,
+            flush_f: flush_endpoint::<dyn Write + '_>,
+            _inner: PhantomData,
+        }
+    }
+}
+
+impl<'a> RawSeekReader<'a> {
+    fn new(endpoint: &'a mut Endpoint<'_, dyn ReadSeek + '_>) -> Self {
+        RawSeekReader {
+            ctx: ptr::from_mut(endpoint).cast(),
+            read_f: read_endpoint::<dyn ReadSeek + '_>,
+            seek_f: seek_endpoint::<dyn ReadSeek + '_>,
+            _inner: PhantomData,
+        }
+    }
+}
+
+impl<'a> RawSeekWriter<'a> {
+    fn new(endpoint: &'a mut Endpoint<'_, dyn WriteSeek + '_>) -> Self {
+        RawSeekWriter {
+            ctx: ptr::from_mut(endpoint).cast(),
+            write_f: write_endpoint::<dyn WriteSeek + '_>,
+            flush_f: flush_endpoint::<dyn WriteSeek + '_>,
+            seek_f: seek_endpoint::<dyn WriteSeek + '_>,
+            _inner: PhantomData,
+        }
+    }
+}
+
+impl Read for RawReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = (self.read_f)(self.ctx, buf.as_mut_ptr(), buf.len());
+        usize::try_from(read).map_err(|_| io::Error::other("Reading from the caller failed."))
+    }
+}
+
+impl Write for RawWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = (self.write_f)(self.ctx, buf.as_ptr(), buf.len());
+        usize::try_from(written).map_err(|_| io::Error::other("Writing to the caller failed."))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if (self.flush_f)(self.ctx) {
+            Ok(())
+        } else {
+            Err(io::Error::other(
+                "Flushing the output of the caller failed.",
+            ))
+        }
+    }
+}
+
+impl Read for RawSeekReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = (self.read_f)(self.ctx, buf.as_mut_ptr(), buf.len());
+        usize::try_from(read).map_err(|_| io::Error::other("Reading from the caller failed."))
+    }
+}
+
+impl Seek for RawSeekReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_raw(self.seek_f, self.ctx, pos)
+    }
+}
+
+impl Write for RawSeekWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = (self.write_f)(self.ctx, buf.as_ptr(), buf.len());
+        usize::try_from(written).map_err(|_| io::Error::other("Writing to the caller failed."))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if (self.flush_f)(self.ctx) {
+            Ok(())
+        } else {
+            Err(io::Error::other(
+                "Flushing the output of the caller failed.",
+            ))
+        }
+    }
+}
+
+impl Seek for RawSeekWriter<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_raw(self.seek_f, self.ctx, pos)
+    }
+}
+
+/// Where a [`Service`] leaves its result.
+#[repr(C)]
+pub struct ServiceResult<'a> {
+    /// The byte counts, set by a service that succeeded.
+    pub stats: CodecStats,
+    /// Receives the error message of a service that failed.
+    pub error: RawWriter<'a>,
+}
+
+/// Runs `service` on the side of the module offering it: the body of the `extern "C"`
+/// function registered as a [`Service`].
+///
+/// ```rust,ignore
+/// extern "C" fn encode_service(
+///     input: &mut RawReader,
+///     output: &mut RawWriter,
+///     result: &mut ServiceResult,
+/// ) -> bool {
+///     abi::serve(input, output, result, |reader, writer| Delta::default().compress(reader, writer))
+/// }
+/// ```
+///
+/// # Returns
+///
+/// Returns `true` if `service` succeeded. Otherwise its error is written to the result.
+pub fn serve(
+    input: &mut RawReader,
+    output: &mut RawWriter,
+    result: &mut ServiceResult,
+    service: impl FnOnce(&mut dyn Read, &mut dyn Write) -> io::Result<CodecStats>,
+) -> bool {
+    finish(result, service(input, output))
+}
+
+/// Runs `step` on the side of the module offering it: the body of the `extern "C"`
+/// function registered as a [`StepService`]. The arguments are converted like in
+/// `module_startup`.
+///
+/// ```rust,ignore
+/// extern "C" fn pipeline_step(
+///     core: &CoreH,
+///     argc: usize,
+///     argv: *const *const c_char,
+///     input: &mut RawSeekReader,
+///     output: &mut RawSeekWriter,
+///     result: &mut ServiceResult,
+/// ) -> bool {
+///     // SAFETY: the core passes `argc` valid C strings.
+///     let args = unsafe { abi::args_from_c(argc, argv) };
+///     abi::serve_step(core, input, output, result, |reader, writer| run_step(core, &args, reader, writer))
+/// }
+/// ```
+///
+/// # Returns
+///
+/// Returns `true` if `step` succeeded. Otherwise its error is written to the result and
+/// reported to the core with [`report_error`](crate::core_header::report_error), like a
+/// module reports the errors of `module_startup`.
+pub fn serve_step(
+    core: &CoreH,
+    input: &mut RawSeekReader,
+    output: &mut RawSeekWriter,
+    result: &mut ServiceResult,
+    step: impl FnOnce(&mut dyn ReadSeek, &mut dyn WriteSeek) -> io::Result<CodecStats>,
+) -> bool {
+    let outcome = step(input, output);
+    if let Err(e) = &outcome {
+        core_header::report_error(core, e);
+    }
+    finish(result, outcome)
+}
+
+/// Leaves the outcome of a service in `result` and returns whether it succeeded.
+fn finish(result: &mut ServiceResult, outcome: io::Result<CodecStats>) -> bool {
+    match outcome {
+        Ok(stats) => {
+            result.stats = stats;
+            true
+        }
+        Err(msg) => {
+            let _ = write!(result.error, "{}", msg);
+            false
+        }
+    }
+}
+
+/// Runs `service` on all data of `reader` and writes the result to `writer`, on the side
+/// of the module using it.
+///
+/// # Errors
+///
+/// Returns the error of `reader` or `writer` if one of them failed, or else an
+/// `io::Error` with the message of the service.
+pub fn call_service(
+    service: Service,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> io::Result<CodecStats> {
+    let mut input = Endpoint::new(reader);
+    let mut output = Endpoint::new(writer);
+    call(|result| {
+        service(
+            &mut RawReader::new(&mut input),
+            &mut RawWriter::new(&mut output),
+            result,
+        )
+    })
+    .map_err(|message| input.error.or(output.error).unwrap_or(message))
+}
+
+/// Runs `step` with `args` on the data of `reader` from its position to its end and
+/// writes the result to `writer` from its position, on the side of the core.
+///
+/// # Errors
+///
+/// Returns the error of `reader` or `writer` if one of them failed, or else an
+/// `io::Error` with the message of the step.
+pub fn call_step(
+    step: StepService,
+    core: &CoreH,
+    args: &[String],
+    reader: &mut dyn ReadSeek,
+    writer: &mut dyn WriteSeek,
+) -> io::Result<CodecStats> {
+    let args = CArgs::new(args);
+    let mut input = Endpoint::new(reader);
+    let mut output = Endpoint::new(writer);
+    call(|result| {
+        step(
+            core,
+            args.argc(),
+            args.argv(),
+            &mut RawSeekReader::new(&mut input),
+            &mut RawSeekWriter::new(&mut output),
+            result,
+        )
+    })
+    .map_err(|message| input.error.or(output.error).unwrap_or(message))
+}
+
+/// Calls a service through `f` with a fresh result, and returns its byte counts, or the
+/// error message it wrote if it failed.
+fn call(f: impl FnOnce(&mut ServiceResult) -> bool) -> io::Result<CodecStats> {
+    let mut message = Vec::new();
+    let mut message_endpoint = Endpoint::new(&mut message as &mut dyn Write);
+    let mut result = ServiceResult {
+        stats: CodecStats::default(),
+        error: RawWriter::new(&mut message_endpoint),
+    };
+
+    let succeeded = f(&mut result);
+    let stats = result.stats;
+    if succeeded {
+        return Ok(stats);
+    }
+    Err(io::Error::other(
+        String::from_utf8_lossy(&message).into_owned(),
+    ))
+}
@@ -0,0 +1,337 @@
+//! Bit-level output and input on buffers in memory, most significant bit first.
+//!
+//! [`BitWriter`] collects bits into a `Vec<u8>` and pads the last byte with zero bits,
+//! and [`BitReader`] reads them back from a byte slice. The first bit written is the
+//! highest bit of the first byte, so values written with [`BitWriter::write_value`] read
+//! back in the same order as they appear in a hex dump.
+//!
+//! ```rust
+//! use shared_files::bitio::{BitReader, BitWriter};
+//!
+//! let mut writer = BitWriter::new();
+//! writer.write_value(0b101, 3);
+//! writer.write_bits(&[1, 1, 0]);
+//! writer.write_value(0xABCD, 16);
+//! let bytes = writer.into_bytes();
+//! assert_eq!(bytes, [0xBA, 0xAF, 0x34]);
+//!
+//! let mut reader = BitReader::new(&bytes);
+//! assert_eq!(reader.read_value(3).unwrap(), 0b101);
+//! assert_eq!(reader.read_value(3).unwrap(), 0b110);
+//! assert_eq!(reader.read_value(16).unwrap(), 0xABCD);
+//! // The two padding bits of the last byte are still there to be read.
+//! assert_eq!(reader.bits_left(), 2);
+//! ```
+
+use crate::error::PurgePackError;
+use std::io;
+
+/// Collects bits into a buffer in memory, most significant bit first.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    buffer: Vec<u8>,
+    current_byte: u8,
+    bit_pos: u8,
+}
+
+impl BitWriter {
+    /// Creates a new `BitWriter` with an empty buffer.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitWriter;
+    ///
+    /// let writer = BitWriter::new();
+    /// assert_eq!(writer.bit_len(), 0);
+    /// assert!(writer.into_bytes().is_empty());
+    /// ```
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `BitWriter` with room for `bytes` bytes before it reallocates.
+    pub fn with_capacity(bytes: usize) -> Self {
+        Self::from_vec(Vec::with_capacity(bytes))
+    }
+
+    /// Creates a `BitWriter` that appends its bits after the bytes already in `buffer`,
+    /// like a header written before the bit stream.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitWriter;
+    ///
+    /// let mut writer = BitWriter::from_vec(b"PP".to_vec());
+    /// writer.write_bit(1);
+    /// assert_eq!(writer.bit_len(), 17);
+    /// assert_eq!(writer.into_bytes(), [b'P', b'P', 0x80]);
+    /// ```
+    pub fn from_vec(buffer: Vec<u8>) -> Self {
+        Self {
+            buffer,
+            current_byte: 0,
+            bit_pos: 0,
+        }
+    }
+
+    /// Writes a single bit. Any non-zero value is treated as 1.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitWriter;
+    ///
+    /// let mut writer = BitWriter::new();
+    /// for bit in [1, 0, 0, 0, 0, 0, 0, 1, 7] {
+    ///     writer.write_bit(bit);
+    /// }
+    /// assert_eq!(writer.into_bytes(), [0b1000_0001, 0b1000_0000]);
+    /// ```
+    pub fn write_bit(&mut self, bit: u8) {
+        if bit != 0 {
+            self.current_byte |= 1 << (7 - self.bit_pos);
+        }
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.buffer.push(self.current_byte);
+            self.current_byte = 0;
+            self.bit_pos = 0;
+        }
+    }
+
+    /// Writes the lowest `count` bits of `value`, most significant bit first. Higher bits
+    /// of `value` are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is more than 64.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitWriter;
+    ///
+    /// let mut writer = BitWriter::new();
+    /// writer.write_value(0xFFF0, 4);
+    /// writer.write_value(u64::MAX, 64);
+    /// writer.write_value(1, 0);
+    /// assert_eq!(writer.bit_len(), 68);
+    /// assert_eq!(writer.into_bytes(), [0x0F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF0]);
+    /// ```
+    pub fn write_value(&mut self, value: u64, count: u32) {
+        assert!(count <= 64, "cannot write {} bits of a u64", count);
+        for i in (0..count).rev() {
+            self.write_bit(((value >> i) & 1) as u8);
+        }
+    }
+
+    /// Writes a slice of bits, one bit per element. Any non-zero element is treated as 1.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitWriter;
+    ///
+    /// let mut writer = BitWriter::new();
+    /// writer.write_bits(&[1, 0, 1, 1, 0]);
+    /// assert_eq!(writer.into_bytes(), [0b1011_0000]);
+    /// ```
+    pub fn write_bits(&mut self, bits: &[u8]) {
+        for &b in bits {
+            self.write_bit(b);
+        }
+    }
+
+    /// Returns the number of bits in the buffer, including the ones of a partial last
+    /// byte.
+    pub fn bit_len(&self) -> u64 {
+        self.buffer.len() as u64 * 8 + self.bit_pos as u64
+    }
+
+    /// Pads the last byte with zero bits and returns the buffer.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        if self.bit_pos > 0 {
+            self.buffer.push(self.current_byte);
+        }
+        self.buffer
+    }
+}
+
+/// Reads bits from a byte slice, most significant bit first.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    buffer: &'a [u8],
+    byte_pos: usize,
+    bit_pos: u8,
+}
+
+impl<'a> BitReader<'a> {
+    /// Creates a new `BitReader` positioned at the first bit of `buffer`.
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Self {
+            buffer,
+            byte_pos: 0,
+            bit_pos: 0,
+        }
+    }
+
+    /// Reads the next bit.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PurgePackError::Truncated`] error, of kind `UnexpectedEof`, if all the
+    /// bits of the buffer have been read.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitReader;
+    /// use std::io;
+    ///
+    /// let mut reader = BitReader::new(&[0b0100_0000]);
+    /// assert_eq!(reader.read_bit().unwrap(), 0);
+    /// assert_eq!(reader.read_bit().unwrap(), 1);
+    /// for _ in 0..6 {
+    ///     assert_eq!(reader.read_bit().unwrap(), 0);
+    /// }
+    /// let error = reader.read_bit().unwrap_err();
+    /// assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    /// ```
+    pub fn read_bit(&mut self) -> io::Result<u8> {
+        let Some(&byte) = self.buffer.get(self.byte_pos) else {
+            return Err(PurgePackError::Truncated(
+                "The bit stream ended before all of its bits were read.".to_string(),
+            )
+            .into());
+        };
+        let bit = (byte >> (7 - self.bit_pos)) & 1;
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.bit_pos = 0;
+            self.byte_pos += 1;
+        }
+        Ok(bit)
+    }
+
+    /// Reads `count` bits, most significant bit first, into a value.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PurgePackError::Truncated`] error if fewer than `count` bits are left.
+    /// The bits that were left have been consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is more than 64.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitReader;
+    ///
+    /// let bytes = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0, 0xFF];
+    /// let mut reader = BitReader::new(&bytes);
+    /// assert_eq!(reader.read_value(0).unwrap(), 0);
+    /// assert_eq!(reader.read_value(64).unwrap(), 0x1234_5678_9ABC_DEF0);
+    /// assert_eq!(reader.read_value(4).unwrap(), 0xF);
+    /// assert!(reader.read_value(5).is_err());
+    /// assert_eq!(reader.bits_left(), 0);
+    /// ```
+    ///
+    /// Every width from 0 to 64 bits reads back what was written, at every alignment:
+    ///
+    /// ```rust
+    /// use shared_files::bitio::{BitReader, BitWriter};
+    ///
+    /// let mask = |count: u32| u64::MAX.checked_shr(64 - count).unwrap_or(0);
+    /// let pattern = 0xA5C3_0F69_96F0_3C5A_u64;
+    /// for offset in 0..8 {
+    ///     let mut writer = BitWriter::new();
+    ///     writer.write_value(u64::MAX, offset);
+    ///     for count in 0..=64 {
+    ///         writer.write_value(pattern.rotate_left(count), count);
+    ///     }
+    ///     let bytes = writer.into_bytes();
+    ///
+    ///     let mut reader = BitReader::new(&bytes);
+    ///     assert_eq!(reader.read_value(offset).unwrap(), mask(offset));
+    ///     for count in 0..=64 {
+    ///         let expected = pattern.rotate_left(count) & mask(count);
+    ///         assert_eq!(reader.read_value(count).unwrap(), expected);
+    ///     }
+    ///     assert!(reader.bits_left() < 8);
+    /// }
+    /// ```
+    pub fn read_value(&mut self, count: u32) -> io::Result<u64> {
+        assert!(count <= 64, "cannot read {} bits into a u64", count);
+        let mut value = 0u64;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    /// Returns the number of bits read so far.
+    pub fn bits_read(&self) -> u64 {
+        self.byte_pos as u64 * 8 + self.bit_pos as u64
+    }
+
+    /// Returns the number of bits left in the buffer, including the padding bits of the
+    /// last byte.
+    ///
+    /// ```rust
+    /// use shared_files::bitio::BitReader;
+    ///
+    /// let mut reader = BitReader::new(&[0xFF, 0x00]);
+    /// assert_eq!(reader.bits_left(), 16);
+    /// reader.read_value(3).unwrap();
+    /// assert_eq!((reader.bits_read(), reader.bits_left()), (3, 13));
+    /// ```
+    pub fn bits_left(&self) -> u64 {
+        self.buffer.len() as u64 * 8 - self.bits_read()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_past_the_end_fails_with_truncated() {
+        let mut reader = BitReader::new(&[0xA5]);
+        assert_eq!(reader.read_value(8).unwrap(), 0xA5);
+        let error = reader.read_bit().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(matches!(
+            PurgePackError::find(&error),
+            Some(PurgePackError::Truncated(_))
+        ));
+        assert!(BitReader::new(&[]).read_value(1).is_err());
+    }
+
+    #[test]
+    fn reading_a_value_past_the_end_consumes_the_bits_left() {
+        let mut reader = BitReader::new(&[0xFF, 0xFF]);
+        reader.read_value(3).unwrap();
+        let error = reader.read_value(14).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.bits_left(), 0);
+        assert_eq!(reader.bits_read(), 16);
+    }
+
+    #[test]
+    fn widths_above_32_bits_round_trip() {
+        let values = [(1u64 << 32, 33), (0x1_2345_6789_ABCD, 49), (u64::MAX, 64)];
+        let mut writer = BitWriter::new();
+        for (value, count) in values {
+            writer.write_value(value, count);
+        }
+        let bytes = writer.into_bytes();
+
+        let mut reader = BitReader::new(&bytes);
+        for (value, count) in values {
+            assert_eq!(reader.read_value(count).unwrap(), value);
+        }
+        assert!(reader.read_value(33).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot write 65 bits of a u64")]
+    fn writing_more_than_64_bits_panics() {
+        BitWriter::new().write_value(0, 65);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot read 65 bits into a u64")]
+    fn reading_more_than_64_bits_panics() {
+        let _ = BitReader::new(&[0; 16]).read_value(65);
+    }
+}
@@ -0,0 +1,118 @@
+//! The memory limit set with `+core --max-memory`.
+//!
+//! Modules that hold a large part of their input in memory ask the [`MemoryBudget`]
+//! before allocating. If the operation does not fit, they fall back to a mode that needs
+//! less memory, like a smaller block size, or fail with
+//! [`PurgePackError::MemoryLimit`] instead of being killed by the system halfway through:
+//!
+//! ```rust
+//! use shared_files::budget::MemoryBudget;
+//! use shared_files::error::PurgePackError;
+//!
+//! let budget = MemoryBudget::new(64 << 20);
+//! assert!(budget.check(16 << 20, "Reading the input").is_ok());
+//!
+//! let error = budget.check(1 << 30, "Reading the input").unwrap_err();
+//! assert!(matches!(error, PurgePackError::MemoryLimit { needed, .. } if needed == 1 << 30));
+//! assert_eq!(
+//!     error.to_string(),
+//!     "Reading the input needs about 1.00 GiB, more than the memory limit of 64.00 MiB set with --max-memory."
+//! );
+//! ```
+//!
+//! The sizes are estimates of the largest buffers an operation holds at once, not exact
+//! accounting, so the limit should leave some room below the memory actually available.
+
+use crate::core_header::CoreH;
+use crate::error::PurgePackError;
+
+/// The memory an operation may use, from `+core --max-memory`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryBudget {
+    limit: Option<u64>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `limit` bytes.
+    pub const fn new(limit: u64) -> Self {
+        MemoryBudget { limit: Some(limit) }
+    }
+
+    /// Creates a budget without a limit, which every operation fits.
+    pub const fn unlimited() -> Self {
+        MemoryBudget { limit: None }
+    }
+
+    /// Returns the budget given to the core with `--max-memory`.
+    pub fn from_core(core: &CoreH) -> Self {
+        MemoryBudget {
+            limit: (core.max_memory != 0).then_some(core.max_memory),
+        }
+    }
+
+    /// Returns the limit in bytes, or `None` without a limit.
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// Returns `true` if an operation needing `bytes` bytes stays within the limit.
+    ///
+    /// ```rust
+    /// use shared_files::budget::MemoryBudget;
+    ///
+    /// assert!(MemoryBudget::new(1024).fits(1024));
+    /// assert!(!MemoryBudget::new(1024).fits(1025));
+    /// assert!(MemoryBudget::unlimited().fits(u64::MAX));
+    /// ```
+    pub fn fits(&self, bytes: u64) -> bool {
+        self.limit.is_none_or(|limit| bytes <= limit)
+    }
+
+    /// Checks that an operation needing `bytes` bytes stays within the limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PurgePackError::MemoryLimit`], naming the operation by `purpose`, if it
+    /// does not.
+    pub fn check(&self, bytes: u64, purpose: &str) -> Result<(), PurgePackError> {
+        match self.limit {
+            Some(limit) if bytes > limit => Err(PurgePackError::MemoryLimit {
+                needed: bytes,
+                limit,
+                purpose: purpose.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses a size given on the command line: a number of bytes with an optional `K`, `M`
+/// or `G` suffix for KiB, MiB and GiB.
+///
+/// ```rust
+/// use shared_files::budget::parse_size;
+///
+/// assert_eq!(parse_size("1048576"), Ok(1 << 20));
+/// assert_eq!(parse_size("512K"), Ok(512 << 10));
+/// assert_eq!(parse_size("2g"), Ok(2 << 30));
+/// assert_eq!(parse_size("0"), Ok(0));
+/// assert!(parse_size("1.5M").is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns a message for the user if `value` is not a size or does not fit into a `u64`.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (digits, shift) = match value.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&value[..index], 10),
+        Some((index, 'm' | 'M')) => (&value[..index], 20),
+        Some((index, 'g' | 'G')) => (&value[..index], 30),
+        _ => (value, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|size| size.checked_mul(1u64 << shift))
+        .ok_or_else(|| format!("`{}` is not a size such as 1048576, 512K or 25M", value))
+}
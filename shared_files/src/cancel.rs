@@ -0,0 +1,77 @@
+//! Cancellation of running operations.
+//!
+//! The core sets a cancellation flag when the user presses Ctrl-C. Modules read their
+//! input through a [`CancellableReader`], which fails with a
+//! [`PurgePackError::Cancelled`] error once the flag is set, so the chunk loops of the
+//! codecs stop at the next chunk. The output, written through a
+//! [`SafeWriter`](crate::output::SafeWriter), is dropped without being committed, so no
+//! partial file is left, and the module reports the operation as cancelled.
+//!
+//! The reader also reports how much of the input was read as `progress` events (see
+//! [`events`](crate::events)).
+
+use crate::core_header::{self, CoreH};
+use crate::error::PurgePackError;
+use crate::events::{self, Event, PROGRESS_INTERVAL};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    time::Instant,
+};
+
+/// Returns `true` if `error` was caused by a cancellation.
+pub fn is_cancelled_error(error: &io::Error) -> bool {
+    matches!(PurgePackError::find(error), Some(PurgePackError::Cancelled))
+}
+
+/// A reader that fails with a [`PurgePackError::Cancelled`] error once the core has been cancelled.
+///
+/// The flag is checked on every read, so the reader should be wrapped in a `BufReader`
+/// (or read in chunks) rather than read byte by byte. Every [`PROGRESS_INTERVAL`] it
+/// emits a `progress` event with the number of bytes read so far.
+pub struct CancellableReader<'a, R> {
+    inner: R,
+    core: &'a CoreH,
+    bytes_read: u64,
+    next_progress: Instant,
+}
+
+impl<'a, R> CancellableReader<'a, R> {
+    /// Wraps `inner` so reads fail once `core` has been cancelled.
+    pub fn new(inner: R, core: &'a CoreH) -> Self {
+        Self {
+            inner,
+            core,
+            bytes_read: 0,
+            next_progress: Instant::now() + PROGRESS_INTERVAL,
+        }
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for CancellableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if core_header::is_cancelled(self.core) {
+            return Err(PurgePackError::Cancelled.into());
+        }
+        let len = self.inner.read(buf)?;
+        self.bytes_read += len as u64;
+
+        let now = Instant::now();
+        if now >= self.next_progress {
+            self.next_progress = now + PROGRESS_INTERVAL;
+            let bytes_read = self.bytes_read;
+            events::emit(self.core, &Event::Progress { bytes_read });
+        }
+        Ok(len)
+    }
+}
+
+impl<R: Seek> Seek for CancellableReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
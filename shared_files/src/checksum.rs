@@ -0,0 +1,241 @@
+//! Checksums and cryptographic hashes behind one streaming interface.
+//!
+//! Every algorithm implements [`Hasher`], so code that only passes bytes through, like
+//! [`ChecksumReader`] and [`ChecksumWriter`], works with any of them. Each algorithm is
+//! behind a feature, so a crate only pulls in the dependencies of the ones it uses:
+//!
+//! * `crc32`: [`Crc32`], the CRC32 (IEEE) stored in the headers of the codecs. Detects
+//!   accidental corruption only.
+//! * `sha256`: [`Sha256`].
+//! * `blake3`: [`Blake3`] with a 256-bit output.
+//!
+//! ```rust
+//! # #[cfg(feature = "crc32")] {
+//! use shared_files::checksum::{self, ChecksumWriter, Crc32, Hasher};
+//! use std::io::Write;
+//!
+//! let mut writer = ChecksumWriter::new(Vec::new(), Crc32::new());
+//! writer.write_all(b"123456789").unwrap();
+//! assert_eq!(writer.hasher().value(), 0xCBF4_3926);
+//! assert_eq!(checksum::to_hex(&writer.hasher().digest()), "cbf43926");
+//! # }
+//! ```
+
+use std::io::{self, BufRead, Read, Write};
+
+/// A checksum or hash computed over a stream of bytes.
+pub trait Hasher {
+    /// Adds `data` to the hashed bytes.
+    fn update(&mut self, data: &[u8]);
+    /// Returns the digest of the bytes hashed so far. Hashing can go on afterwards.
+    fn digest(&self) -> Vec<u8>;
+    /// Forgets the bytes hashed so far.
+    fn reset(&mut self);
+}
+
+impl<H: Hasher + ?Sized> Hasher for Box<H> {
+    fn update(&mut self, data: &[u8]) {
+        (**self).update(data)
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        (**self).digest()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Returns `digest` as lowercase hex, as hash tools print it.
+///
+/// ```rust
+/// assert_eq!(shared_files::checksum::to_hex(&[0x00, 0xAB, 0x7F]), "00ab7f");
+/// ```
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The CRC32 (IEEE) of the bytes, as stored in the headers of the codecs.
+#[cfg(feature = "crc32")]
+#[derive(Debug, Clone, Default)]
+pub struct Crc32(crc32fast::Hasher);
+
+#[cfg(feature = "crc32")]
+impl Crc32 {
+    /// Creates a CRC32 of no bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the CRC32 of the bytes hashed so far.
+    pub fn value(&self) -> u32 {
+        self.0.clone().finalize()
+    }
+
+    /// Returns the CRC32 of `data`.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "crc32")]
+    /// assert_eq!(shared_files::checksum::Crc32::of(b"123456789"), 0xCBF4_3926);
+    /// ```
+    pub fn of(data: &[u8]) -> u32 {
+        crc32fast::hash(data)
+    }
+}
+
+/// The digest is the CRC32 in big-endian byte order, as `crc32` and most tools print it.
+#[cfg(feature = "crc32")]
+impl Hasher for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        self.value().to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+}
+
+/// SHA-256.
+#[cfg(feature = "sha256")]
+#[derive(Debug, Clone, Default)]
+pub struct Sha256(sha2::Sha256);
+
+#[cfg(feature = "sha256")]
+impl Sha256 {
+    /// Creates a SHA-256 of no bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl Hasher for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(&mut self.0, data)
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        sha2::Digest::finalize(self.0.clone()).to_vec()
+    }
+
+    fn reset(&mut self) {
+        sha2::Digest::reset(&mut self.0)
+    }
+}
+
+/// BLAKE3 with a 256-bit output.
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Default)]
+pub struct Blake3(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl Blake3 {
+    /// Creates a BLAKE3 of no bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Hasher for Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// A reader that hashes all the bytes read through it.
+///
+/// Through `BufRead`, bytes are hashed when they are consumed, not when they are
+/// buffered, so bytes left in the buffer are not part of the digest.
+pub struct ChecksumReader<R, H> {
+    inner: R,
+    hasher: H,
+}
+
+impl<R, H: Hasher> ChecksumReader<R, H> {
+    /// Wraps `inner`. Only the bytes read from now on are hashed.
+    pub fn new(inner: R, hasher: H) -> Self {
+        ChecksumReader { inner, hasher }
+    }
+
+    /// Returns the hasher, to read the digest of the bytes read so far.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the wrapped reader and the hasher.
+    pub fn into_parts(self) -> (R, H) {
+        (self.inner, self.hasher)
+    }
+}
+
+impl<R: Read, H: Hasher> Read for ChecksumReader<R, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead, H: Hasher> BufRead for ChecksumReader<R, H> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The bytes being consumed are buffered already, so `fill_buf` returns them
+        // without reading.
+        if let Ok(buffered) = self.inner.fill_buf() {
+            self.hasher.update(&buffered[..amt]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// A writer that hashes all the bytes written through it.
+pub struct ChecksumWriter<W, H> {
+    inner: W,
+    hasher: H,
+}
+
+impl<W, H: Hasher> ChecksumWriter<W, H> {
+    /// Wraps `inner`. Only the bytes written from now on are hashed.
+    pub fn new(inner: W, hasher: H) -> Self {
+        ChecksumWriter { inner, hasher }
+    }
+
+    /// Returns the hasher, to read the digest of the bytes written so far.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the wrapped writer and the hasher.
+    pub fn into_parts(self) -> (W, H) {
+        (self.inner, self.hasher)
+    }
+}
+
+impl<W: Write, H: Hasher> Write for ChecksumWriter<W, H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
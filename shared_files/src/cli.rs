@@ -0,0 +1,288 @@
+//! Command line arguments shared by the modules.
+//!
+//! Only compiled with the `cli` feature. Modules define their own `clap` parser and
+//! reuse the pieces here, so the same flags behave the same everywhere:
+//!
+//! * [`CommonArgs`] for an input file, an output file, the statistics flags and the
+//!   overwrite flags.
+//! * [`StatsArgs`] for `--stats` and `--stats-format` alone, flattened into modules with
+//!   other file arguments.
+//! * [`OverwriteArgs`] for `--force` and `--suffix`, which decide what happens to an
+//!   output file that exists already (see [`Overwrite`]). Without them, it is kept and the
+//!   module fails.
+//! * [`check_input`], [`check_output`] and [`check_dir`] for the path validation, which
+//!   report a [`CliError`].
+//! * [`parse_args`], which parses and then runs the [`Validate`] checks of a module. An
+//!   argument like `--level` that is not given is taken from its environment variable,
+//!   like `PURGEPACK_LEVEL`; see [`overrides`](crate::overrides).
+//! * [`parse_step`], which parses the arguments of a pipeline step, which come without
+//!   paths.
+//!
+//! # Example
+//!
+//! ```rust
+//! use clap::Parser;
+//! use shared_files::cli::{self, CliError, CommonArgs, Validate};
+//!
+//! #[derive(Parser, Debug)]
+//! struct CliArgs {
+//!     #[command(flatten)]
+//!     common: CommonArgs,
+//! }
+//!
+//! impl Validate for CliArgs {
+//!     fn validate(&self) -> Result<(), CliError> {
+//!         cli::check_input(&self.common.input_file)?;
+//!         cli::check_output(&self.common.output_file)
+//!     }
+//! }
+//!
+//! let args = ["tool", "missing.txt", "out.ppcb", "-s"].map(String::from);
+//! let error = cli::parse_args::<CliArgs>(&args).unwrap_err();
+//! assert_eq!(error.to_string(), "Input file does not exist: missing.txt");
+//! ```
+
+use crate::error::{ExitStatus, PurgePackError};
+use crate::output::Overwrite;
+use crate::overrides;
+use crate::stats::StatsFormat;
+use clap::{Args, Command, Parser};
+use std::{
+    error::Error,
+    fmt, io, iter,
+    path::{Path, PathBuf},
+};
+
+/// The statistics flags: `--stats` and `--stats-format`.
+#[derive(Debug, Clone, Args)]
+pub struct StatsArgs {
+    /// Enables statistics output.
+    #[arg(short, long)]
+    pub stats: bool,
+    /// The format of the statistics: text, json or csv.
+    #[arg(long, value_name = "FORMAT", default_value_t = StatsFormat::Text, requires = "stats")]
+    pub stats_format: StatsFormat,
+}
+
+impl StatsArgs {
+    /// Returns `true` if `--stats` was given.
+    pub fn enabled(&self) -> bool {
+        self.stats
+    }
+
+    /// Returns the format to print the statistics in, or `None` without `--stats`.
+    pub fn format(&self) -> Option<StatsFormat> {
+        self.stats.then_some(self.stats_format)
+    }
+}
+
+/// The overwrite flags: `--force` and `--suffix`.
+#[derive(Debug, Clone, Args)]
+pub struct OverwriteArgs {
+    /// Replaces the output file if it exists already.
+    #[arg(short, long, conflicts_with = "suffix")]
+    pub force: bool,
+    /// Writes to a new name like `out.1.ppcb` if the output file exists already.
+    #[arg(long)]
+    pub suffix: bool,
+}
+
+impl OverwriteArgs {
+    /// Returns the policy the flags select.
+    pub fn policy(&self) -> Overwrite {
+        if self.force {
+            Overwrite::Replace
+        } else if self.suffix {
+            Overwrite::Suffix
+        } else {
+            Overwrite::Refuse
+        }
+    }
+}
+
+/// The arguments of an operation that turns one file into another.
+#[derive(Debug, Clone, Args)]
+pub struct CommonArgs {
+    /// The path to the input file.
+    pub input_file: PathBuf,
+    /// The path where the output file will be written.
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub stats: StatsArgs,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+}
+
+/// Possible errors encountered during command line argument processing and file validation.
+#[derive(Debug)]
+pub enum CliError {
+    /// The specified input file could not be found.
+    InputFileNotFound(PathBuf),
+    /// The specified input path exists, but is not a file.
+    InputNotFile(PathBuf),
+    /// The output directory, or the parent directory of the output file, does not exist.
+    OutputParentDirNotFound(PathBuf),
+    /// The output directory path, or the parent path of the output file, exists but is
+    /// not a directory.
+    OutputParentNotDir(PathBuf),
+    /// A check specific to the module failed, described by the message.
+    Invalid(String),
+    /// An error originating directly from the argument parsing library (clap).
+    ClapError(clap::Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::InputFileNotFound(path) => {
+                write!(f, "Input file does not exist: {}", path.display())
+            }
+            CliError::InputNotFile(path) => {
+                write!(f, "Input path is not a file: {}", path.display())
+            }
+            CliError::OutputParentDirNotFound(path) => {
+                write!(f, "The output directory does not exist: {}", path.display())
+            }
+            CliError::OutputParentNotDir(path) => {
+                write!(
+                    f,
+                    "The output directory is not a directory: {}",
+                    path.display()
+                )
+            }
+            CliError::Invalid(msg) => write!(f, "{}", msg),
+            CliError::ClapError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl CliError {
+    /// Returns the [`ExitStatus`] a module reports for the error: an input file that does
+    /// not exist is an I/O error, like it is to the core, and the others are usage errors.
+    ///
+    /// ```
+    /// use shared_files::cli::CliError;
+    /// use shared_files::error::ExitStatus;
+    ///
+    /// let missing = CliError::InputFileNotFound("missing.txt".into());
+    /// assert_eq!(missing.exit_status(), ExitStatus::Io);
+    /// assert_eq!(CliError::InputNotFile(".".into()).exit_status(), ExitStatus::Usage);
+    /// ```
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            CliError::InputFileNotFound(_) => ExitStatus::Io,
+            CliError::ClapError(e) => clap_exit_status(e),
+            _ => ExitStatus::Usage,
+        }
+    }
+}
+
+/// Returns the [`ExitStatus`] a module reports for a `clap::Error`: clap returns `--help`
+/// and `--version` as errors too, which are no failure.
+pub fn clap_exit_status(error: &clap::Error) -> ExitStatus {
+    if error.use_stderr() {
+        ExitStatus::Usage
+    } else {
+        ExitStatus::Success
+    }
+}
+
+/// Allows for seamless conversion of a `clap::Error` directly into a `CliError`.
+impl From<clap::Error> for CliError {
+    fn from(error: clap::Error) -> Self {
+        CliError::ClapError(error)
+    }
+}
+
+/// The checks a module runs on its arguments after they were parsed.
+pub trait Validate {
+    /// Checks the parsed arguments, typically that the input files exist and the output
+    /// can be written.
+    fn validate(&self) -> Result<(), CliError>;
+}
+
+/// Parses `args` (starting with the program name) into `T` and validates them.
+///
+/// The arguments in [`overrides::FLAGS`] that `args` leave out are taken from their
+/// environment variables, before the defaults of `T`.
+pub fn parse_args<T: Parser + Validate>(args: &[String]) -> Result<T, CliError> {
+    let matches = with_overrides(T::command()).try_get_matches_from(args)?;
+    let args = T::from_arg_matches(&matches)?;
+    args.validate()?;
+    Ok(args)
+}
+
+/// Parses the arguments of a pipeline step (see
+/// [`StepService`](crate::core_header::StepService)) into `T`, like [`parse_args`].
+///
+/// The step reads and writes the streams of the core instead of files, so its arguments
+/// have no paths: `-` stands in for the input and the output path of `T`, and the module
+/// must not open them. The [`Validate`] checks, which are about the paths, are skipped.
+///
+/// # Errors
+///
+/// Returns a [`PurgePackError::InvalidInput`] error with the first line of the message of
+/// clap if the arguments are invalid.
+pub fn parse_step<T: Parser>(args: &[String]) -> io::Result<T> {
+    let args = iter::once("step")
+        .chain(args.iter().map(String::as_str))
+        .chain(["-", "-"]);
+    with_overrides(T::command())
+        .try_get_matches_from(args)
+        .and_then(|matches| T::from_arg_matches(&matches))
+        .map_err(|e| {
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            PurgePackError::InvalidInput(message.trim_start_matches("error: ").to_string()).into()
+        })
+}
+
+/// Attaches the environment variables of [`overrides::FLAGS`] that are set to the
+/// arguments of `command` and its subcommands with the same long names.
+fn with_overrides(command: Command) -> Command {
+    command
+        .mut_args(|arg| {
+            let var = overrides::FLAGS
+                .iter()
+                .find(|(long, _)| arg.get_long() == Some(*long));
+            // clap would take an empty variable for an empty value.
+            match var {
+                Some((_, var)) if overrides::var(var).is_some() => arg.env(var),
+                _ => arg,
+            }
+        })
+        .mut_subcommands(with_overrides)
+}
+
+/// Checks that `input_file` exists and is a file.
+pub fn check_input(input_file: &Path) -> Result<(), CliError> {
+    if !input_file.exists() {
+        return Err(CliError::InputFileNotFound(input_file.to_path_buf()));
+    }
+    if !input_file.is_file() {
+        return Err(CliError::InputNotFile(input_file.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Checks that the parent directory of `output_file` exists and is a directory. A bare
+/// file name is written to the current directory and always passes.
+pub fn check_output(output_file: &Path) -> Result<(), CliError> {
+    match output_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => check_dir(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `dir` exists and is a directory.
+pub fn check_dir(dir: &Path) -> Result<(), CliError> {
+    if !dir.exists() {
+        return Err(CliError::OutputParentDirNotFound(dir.to_path_buf()));
+    }
+    if !dir.is_dir() {
+        return Err(CliError::OutputParentNotDir(dir.to_path_buf()));
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Common traits of the compression codecs.
+//!
+//! Each codec crate (`purgepack_fast_lz`, `purgepack_bwt`, ...) implements [`Compressor`]
+//! and [`Decompressor`] for a small settings type, so a caller can pick a codec at runtime
+//! and work with it through a trait object:
+//!
+//! ```rust,ignore
+//! let codec: Box<dyn Compressor> = match name {
+//!     "fast_lz" => Box::new(purgepack_fast_lz::FastLz::default()),
+//!     "bwt" => Box::new(purgepack_bwt::Bwt::default()),
+//!     _ => return Err(...),
+//! };
+//! let stats = codec.compress(&mut input, &mut output)?;
+//! println!("{} -> {} bytes", stats.input_len, stats.output_len);
+//! ```
+//!
+//! The streaming functions of the codec crates need the length of the input up front and
+//! a seekable output to fill in the header checksum. [`Compressor::compress`] accepts any
+//! `Read` and `Write`, so it may hold the input or the output in memory.
+//! [`Compressor::compress_file`] takes a seekable input and output instead, like files,
+//! and streams them through the codec without holding them. Decompression streams with
+//! any `Read` and `Write`.
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+/// A seekable input, like a file, for [`Compressor::compress_file`].
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// A seekable output, like a file, for [`Compressor::compress_file`].
+pub trait WriteSeek: Write + Seek {}
+
+impl<T: Write + Seek + ?Sized> WriteSeek for T {}
+
+/// The byte counts of a finished compression or decompression.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CodecStats {
+    /// The number of bytes read from the input.
+    pub input_len: u64,
+    /// The number of bytes written to the output.
+    pub output_len: u64,
+}
+
+/// A codec that turns original data into its compressed form.
+pub trait Compressor {
+    /// Compresses all data `reader` holds into `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if reading or writing fails, or if the codec cannot
+    /// compress the data with its settings.
+    fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats>;
+
+    /// Compresses the data of `reader` from its position to its end into `writer`, which
+    /// starts at its position too.
+    ///
+    /// Codecs that need the length of the input or seek back in the output stream the data
+    /// here. The default implementation calls [`Compressor::compress`], for codecs that
+    /// hold all data in memory anyway.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if reading, writing or seeking fails, or if the codec cannot
+    /// compress the data with its settings.
+    fn compress_file(
+        &self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn WriteSeek,
+    ) -> io::Result<CodecStats> {
+        self.compress(reader, writer)
+    }
+}
+
+/// A codec that restores original data from its compressed form.
+pub trait Decompressor {
+    /// Restores the compressed data `reader` holds into `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+    /// compressed data is corrupted or was written by another codec.
+    fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats>;
+}
+
+/// Reads all data of `reader`, compresses it with `compress` into a buffer in memory and
+/// copies the buffer to `writer`.
+///
+/// This is the usual way to implement [`Compressor`] on top of a codec that needs the
+/// input length up front and a seekable output.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or the error of `compress`.
+pub fn compress_in_memory<F>(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    compress: F,
+) -> io::Result<CodecStats>
+where
+    F: FnOnce(&[u8], &mut Cursor<Vec<u8>>) -> io::Result<()>,
+{
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    let mut output = Cursor::new(Vec::new());
+    compress(&input, &mut output)?;
+    writer.write_all(output.get_ref())?;
+    Ok(CodecStats {
+        input_len: input.len() as u64,
+        output_len: output.get_ref().len() as u64,
+    })
+}
+
+/// Returns the number of bytes of `reader` from its position to its end, and leaves it at
+/// its position.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if seeking fails.
+pub fn remaining_len(reader: &mut dyn ReadSeek) -> io::Result<u64> {
+    let position = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+    Ok(end.saturating_sub(position))
+}
+
+/// Compresses the data of `reader` from its position to its end with `compress`, which
+/// gets the length of the data, and returns the byte counts.
+///
+/// This is the usual way to implement [`Compressor::compress_file`] on top of the
+/// streaming function of a codec. [`Offset`] turns the positions `reader` and `writer`
+/// are at into their start, so codecs that seek back to the start of the input or to
+/// their header in the output find them there.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading, writing or seeking fails, or the error of `compress`.
+pub fn compress_stream<F>(
+    reader: &mut dyn ReadSeek,
+    writer: &mut dyn WriteSeek,
+    compress: F,
+) -> io::Result<CodecStats>
+where
+    F: FnOnce(
+        &mut io::BufReader<Offset<&mut dyn ReadSeek>>,
+        &mut Offset<&mut dyn WriteSeek>,
+        u64,
+    ) -> io::Result<()>,
+{
+    let input_len = remaining_len(reader)?;
+    let mut writer = Offset::new(writer)?;
+    compress(
+        &mut io::BufReader::new(Offset::new(reader)?),
+        &mut writer,
+        input_len,
+    )?;
+    writer.flush()?;
+    Ok(CodecStats {
+        input_len,
+        output_len: writer.seek_end()?,
+    })
+}
+
+/// A seekable reader or writer whose start is the position the inner one was at when it
+/// was wrapped: `SeekFrom::Start(0)` seeks there.
+pub struct Offset<T> {
+    inner: T,
+    start: u64,
+}
+
+impl<T: Seek> Offset<T> {
+    /// Wraps `inner`, which starts at its current position.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the position cannot be read.
+    pub fn new(mut inner: T) -> io::Result<Self> {
+        let start = inner.stream_position()?;
+        Ok(Offset { inner, start })
+    }
+
+    /// Seeks to the end and returns the number of bytes from the start to there, the length
+    /// of the data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if seeking fails.
+    pub fn seek_end(&mut self) -> io::Result<u64> {
+        self.seek(SeekFrom::End(0))
+    }
+}
+
+impl<R: Read> Read for Offset<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<W: Write> Write for Offset<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for Offset<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(self.start + offset),
+            relative => relative,
+        };
+        let position = self.inner.seek(pos)?;
+        position.checked_sub(self.start).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seeked before the start of the stream.",
+            )
+        })
+    }
+}
+
+/// A reader or writer that counts the bytes passing through it.
+///
+/// # Examples
+///
+/// ```
+/// use shared_files::codec::Counter;
+/// use std::io::Write;
+///
+/// let mut counter = Counter::new(Vec::new());
+/// counter.write_all(b"abc").unwrap();
+/// assert_eq!(counter.count(), 3);
+/// ```
+pub struct Counter<T> {
+    inner: T,
+    count: u64,
+}
+
+impl<T> Counter<T> {
+    /// Wraps `inner` with a count of zero.
+    pub fn new(inner: T) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    /// Returns the number of bytes read or written so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the wrapped reader or writer.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
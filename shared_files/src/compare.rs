@@ -0,0 +1,235 @@
+//! Byte comparison of two inputs, reporting where they differ.
+//!
+//! [`compare`] reads both inputs once, side by side, and keeps the first differing
+//! offsets, along with the bytes around them, so a round trip that went wrong can be
+//! tracked down to the first byte it got wrong:
+//!
+//! ```rust
+//! use shared_files::compare;
+//! use std::io::Cursor;
+//!
+//! let original = Cursor::new(b"hello world".to_vec());
+//! let restored = Cursor::new(b"hello w0rld!".to_vec());
+//! let comparison = compare::compare(original, restored, 10).unwrap();
+//! assert!(!comparison.equal());
+//! assert_eq!(comparison.differing, 1);
+//! assert_eq!(comparison.differences[0].offset, 7);
+//! assert_eq!(comparison.right_len - comparison.left_len, 1);
+//! ```
+//!
+//! [`Comparison::report`] renders the result as text, with a hex dump of [`CONTEXT`] bytes
+//! on both sides of every difference.
+
+use crate::term::{self, Stream, Style};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+/// The number of bytes shown before and after a differing byte.
+pub const CONTEXT: u64 = 8;
+
+/// A differing byte and the bytes around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// The offset of the byte in both inputs.
+    pub offset: u64,
+    /// The offset the context starts at, [`CONTEXT`] bytes before `offset` or at 0.
+    pub context_start: u64,
+    /// The bytes of the left input from `context_start` on.
+    pub left: Vec<u8>,
+    /// The bytes of the right input from `context_start` on.
+    pub right: Vec<u8>,
+}
+
+/// The outcome of [`compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// The length of the left input.
+    pub left_len: u64,
+    /// The length of the right input.
+    pub right_len: u64,
+    /// The number of differing bytes in the part both inputs have.
+    pub differing: u64,
+    /// The first differing bytes, at most the limit passed to [`compare`].
+    pub differences: Vec<Difference>,
+}
+
+impl Comparison {
+    /// Returns `true` if both inputs hold the same bytes.
+    pub fn equal(&self) -> bool {
+        self.differing == 0 && self.left_len == self.right_len
+    }
+
+    /// Renders the comparison as text for `stream`, naming the inputs `left_name` and
+    /// `right_name`. The differing bytes are marked with brackets, and colored on a
+    /// terminal.
+    pub fn report(&self, left_name: &str, right_name: &str, stream: Stream) -> String {
+        if self.equal() {
+            return format!(
+                "{} and {} are identical ({} bytes)",
+                left_name, right_name, self.left_len
+            );
+        }
+
+        let mut lines = Vec::new();
+        let width = left_name.len().max(right_name.len());
+        for difference in &self.differences {
+            lines.push(format!(
+                "Offset {} (0x{:08x}):",
+                difference.offset, difference.offset
+            ));
+            for (name, bytes) in [
+                (left_name, &difference.left),
+                (right_name, &difference.right),
+            ] {
+                lines.push(format!(
+                    "  {:width$}  {:08x}  {}",
+                    name,
+                    difference.context_start,
+                    hex_line(difference, bytes, stream)
+                ));
+            }
+        }
+        if self.differing > self.differences.len() as u64 {
+            lines.push(format!(
+                "... {} more differing bytes",
+                self.differing - self.differences.len() as u64
+            ));
+        }
+        if self.left_len != self.right_len {
+            let (longer, shorter) = if self.left_len > self.right_len {
+                (left_name, right_name)
+            } else {
+                (right_name, left_name)
+            };
+            lines.push(format!(
+                "{} ends at offset {}, {} has {} more bytes",
+                shorter,
+                self.left_len.min(self.right_len),
+                longer,
+                self.left_len.abs_diff(self.right_len)
+            ));
+        }
+        lines.push(format!(
+            "{} and {} differ: {} differing bytes, {} and {} bytes long",
+            left_name, right_name, self.differing, self.left_len, self.right_len
+        ));
+        lines.join("\n")
+    }
+}
+
+/// Returns the hex dump of `bytes`, the context of `difference` from one input, followed
+/// by its printable characters. The differing byte is set in brackets.
+fn hex_line(difference: &Difference, bytes: &[u8], stream: Stream) -> String {
+    let marked = (difference.offset - difference.context_start) as usize;
+    let mut hex = String::new();
+    for i in 0..=(2 * CONTEXT as usize) {
+        let cell = match bytes.get(i) {
+            Some(byte) if i == marked => term::paint(stream, Style::Bad, format!("[{:02x}]", byte)),
+            Some(byte) => format!(" {:02x} ", byte),
+            None if i == marked => "[--]".to_string(),
+            None => "    ".to_string(),
+        };
+        hex.push_str(&cell);
+    }
+    let text: String = bytes
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{} |{}|", hex, text)
+}
+
+/// Compares `left` and `right` byte by byte, keeping the first `limit` differing bytes.
+///
+/// Both inputs are read once from start to end, and then once more around the kept
+/// differences, so they must be seekable.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading either input fails.
+pub fn compare<L: Read + Seek, R: Read + Seek>(
+    left: L,
+    right: R,
+    limit: usize,
+) -> io::Result<Comparison> {
+    let mut left = BufReader::new(left);
+    let mut right = BufReader::new(right);
+    let mut offsets = Vec::new();
+    let mut differing = 0;
+    let mut offset = 0;
+    let mut left_chunk = vec![0; 64 * 1024];
+    let mut right_chunk = vec![0; 64 * 1024];
+    let (left_len, right_len) = loop {
+        let left_read = read_full(&mut left, &mut left_chunk)?;
+        let right_read = read_full(&mut right, &mut right_chunk)?;
+        let common = left_read.min(right_read);
+        for (i, (a, b)) in left_chunk[..common]
+            .iter()
+            .zip(&right_chunk[..common])
+            .enumerate()
+        {
+            if a != b {
+                differing += 1;
+                if offsets.len() < limit {
+                    offsets.push(offset + i as u64);
+                }
+            }
+        }
+        offset += common as u64;
+        if left_read < left_chunk.len() || right_read < right_chunk.len() {
+            // One input ended: the rest of the other one only counts for its length.
+            let left_len = offset + (left_read - common) as u64 + drain(&mut left)?;
+            let right_len = offset + (right_read - common) as u64 + drain(&mut right)?;
+            break (left_len, right_len);
+        }
+    };
+
+    let mut differences = Vec::new();
+    for offset in offsets {
+        let context_start = offset.saturating_sub(CONTEXT);
+        differences.push(Difference {
+            offset,
+            context_start,
+            left: read_at(&mut left, context_start)?,
+            right: read_at(&mut right, context_start)?,
+        });
+    }
+    Ok(Comparison {
+        left_len,
+        right_len,
+        differing,
+        differences,
+    })
+}
+
+/// Fills `buffer` as far as `reader` goes and returns the number of bytes read.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Reads `reader` to its end and returns the number of bytes read.
+fn drain(reader: &mut impl Read) -> io::Result<u64> {
+    io::copy(reader, &mut io::sink())
+}
+
+/// Reads the context of a difference from `reader`: up to `2 * CONTEXT + 1` bytes from
+/// `start` on.
+fn read_at(reader: &mut (impl Read + Seek), start: u64) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    reader.take(2 * CONTEXT + 1).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
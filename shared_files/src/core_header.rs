@@ -1,9 +1,160 @@
+use crate::abi::{
+    self, RawBuffer, RawReader, RawSeekReader, RawSeekWriter, RawWriter, ServiceResult,
+};
+use crate::error::ExitStatus;
+use crate::log::LogLevel;
+use std::{ffi::c_char, io, mem};
+
 pub const FILE_EXTENSION: &'static str = ".ppcb";
 
+/// The name of the service that applies the default delta transform.
+pub const DELTA_ENCODE_SERVICE: &str = "delta-encode";
+/// The name of the service that restores data transformed by [`DELTA_ENCODE_SERVICE`].
+pub const DELTA_DECODE_SERVICE: &str = "delta-decode";
+/// The name of the service that classifies the data at the start of its input and writes
+/// the `key=value` report of `+detect_module --porcelain`, with one `step=` line per
+/// module of the recommended pipeline.
+pub const DETECT_SERVICE: &str = "detect";
+
+/// A service a module offers to the other modules: it reads all data of the input,
+/// processes it and writes the result to the output.
+///
+/// The module offering it implements it with [`abi::serve`], and the modules using it
+/// call it with [`abi::call_service`]. It returns `false` if it failed.
+pub type Service = extern "C" fn(
+    input: &mut RawReader,
+    output: &mut RawWriter,
+    result: &mut ServiceResult,
+) -> bool;
+
+/// The pipeline step of a module: runs the module with its `argc` arguments, given as an
+/// array of C strings like to `module_startup`, on the data of the input from its position
+/// to its end, and writes the result to the output from its position.
+///
+/// The core runs the steps of `compress`, `x` and `estimate` through them, from file to
+/// file, without handing the modules any paths. The arguments are those of a step like
+/// `+bwt_module compress --level 9` without the module name and the paths. The module
+/// implements it with [`abi::serve_step`] and registers it with [`register_step`]; the
+/// core calls it with [`abi::call_step`]. It returns `false` if it failed.
+pub type StepService = extern "C" fn(
+    core: &CoreH,
+    argc: usize,
+    argv: *const *const c_char,
+    input: &mut RawSeekReader,
+    output: &mut RawSeekWriter,
+    result: &mut ServiceResult,
+) -> bool;
+
+/// The functions and settings the core hands to every module.
+///
+/// The layout is part of the module interface, see [`abi`]. Fields are only appended, so
+/// `abi_version` and `size` stay the first fields in every version.
+#[repr(C)]
 pub struct CoreH {
-    pub ping_core_f: fn(),
+    /// The [`ABI_VERSION`](abi::ABI_VERSION) the core was built with.
+    pub abi_version: u32,
+    /// The size of this struct in bytes, as the core knows it.
+    pub size: usize,
+    pub ping_core_f: extern "C" fn(),
+    pub acquire_buffer_f: extern "C" fn(len: usize) -> RawBuffer,
+    pub release_buffer_f: extern "C" fn(buffer: RawBuffer),
+    pub register_service_f: extern "C" fn(name: *const c_char, service: Service) -> bool,
+    pub find_service_f: extern "C" fn(name: *const c_char) -> Option<Service>,
+    pub is_cancelled_f: extern "C" fn() -> bool,
+    pub emit_event_f: extern "C" fn(line: *const c_char),
+    /// The most verbose log level the modules should write, from `--log-level`.
+    pub log_level: LogLevel,
+    /// The memory limit in bytes from `--max-memory`, or 0 without a limit. Modules
+    /// consult it through [`MemoryBudget`](crate::budget::MemoryBudget).
+    pub max_memory: u64,
+    /// Takes the [`ExitStatus`] code of a failed operation, see [`report_status`].
+    pub report_status_f: extern "C" fn(status: u8),
+    /// Takes the pipeline step of a module, see [`register_step`].
+    pub register_step_f: extern "C" fn(module: *const c_char, step: StepService) -> bool,
 }
 
 pub fn ping_core(core: &CoreH) {
     (core.ping_core_f)()
 }
+
+/// Returns an empty buffer with a capacity of at least `len` bytes from the buffer pool
+/// of the core.
+///
+/// Large buffers released by a module are handed to the modules running after it, so
+/// hand the buffer back with [`release_buffer`] once it is no longer needed.
+pub fn acquire_buffer(core: &CoreH, len: usize) -> Vec<u8> {
+    // SAFETY: the core hands out buffers of `RawBuffer::from_vec`.
+    unsafe { (core.acquire_buffer_f)(len).into_vec() }
+}
+
+/// Hands `buffer` back to the buffer pool of the core. Its content is discarded.
+pub fn release_buffer(core: &CoreH, buffer: Vec<u8>) {
+    (core.release_buffer_f)(RawBuffer::from_vec(buffer))
+}
+
+/// Registers `service` under `name` so other modules can look it up with [`find_service`].
+///
+/// Services should be registered from `module_register`, which the core calls on every
+/// module before starting any of them.
+///
+/// # Returns
+///
+/// Returns `false` if another module already registered a service under `name`. The
+/// service registered first is kept.
+pub fn register_service(core: &CoreH, name: &str, service: Service) -> bool {
+    (core.register_service_f)(abi::to_c_string(name).as_ptr(), service)
+}
+
+/// Returns the service registered under `name`, or `None` if no loaded module offers it.
+pub fn find_service(core: &CoreH, name: &str) -> Option<Service> {
+    (core.find_service_f)(abi::to_c_string(name).as_ptr())
+}
+
+/// Returns `true` once the user asked to cancel the running operation (Ctrl-C).
+///
+/// Modules should check it between chunks, or read through a
+/// [`CancellableReader`](crate::cancel::CancellableReader), and stop early.
+pub fn is_cancelled(core: &CoreH) -> bool {
+    (core.is_cancelled_f)()
+}
+
+/// Writes `line` to the event stream of the core, if `--events` gave one.
+///
+/// `line` must be a single line of JSON; see [`events`](crate::events) for the events
+/// modules should emit with [`events::emit`](crate::events::emit).
+pub fn emit_event(core: &CoreH, line: &str) {
+    (core.emit_event_f)(abi::to_c_string(line).as_ptr())
+}
+
+/// Reports to the core that an operation of the module failed with `status`, so the
+/// process exits with a code telling why. See [`ExitStatus`].
+///
+/// Cores built before the field was appended to [`CoreH`] do not get the report.
+pub fn report_status(core: &CoreH, status: ExitStatus) {
+    let end = mem::offset_of!(CoreH, report_status_f) + mem::size_of::<extern "C" fn(u8)>();
+    if core.size >= end {
+        (core.report_status_f)(status.code())
+    }
+}
+
+/// Reports to the core that an operation of the module failed with `error`, with the
+/// status [`ExitStatus::of`] picks for it.
+pub fn report_error(core: &CoreH, error: &io::Error) {
+    report_status(core, ExitStatus::of(error));
+}
+
+/// Registers `step` as the pipeline step of the module `module`, the name of its library
+/// without the `+`, like `bwt_module`. The core runs the steps of its pipelines through
+/// them.
+///
+/// Steps should be registered from `module_register`, like services.
+///
+/// # Returns
+///
+/// Returns `false` if another module already registered a step under `module`, or the
+/// core was built before the field was appended to [`CoreH`] and runs no steps.
+pub fn register_step(core: &CoreH, module: &str, step: StepService) -> bool {
+    let end = mem::offset_of!(CoreH, register_step_f)
+        + mem::size_of::<extern "C" fn(*const c_char, StepService) -> bool>();
+    core.size >= end && (core.register_step_f)(abi::to_c_string(module).as_ptr(), step)
+}
@@ -0,0 +1,39 @@
+//! The order-0 entropy of bytes, in bits per byte.
+//!
+//! The entropy is the lower bound an order-0 entropy coder, like the Huffman module, can
+//! reach. The detect module classifies samples by it, the delta module reports it before
+//! and after its transform, and the codecs store blocks with a high entropy as they are.
+//!
+//! ```rust
+//! use shared_files::entropy;
+//!
+//! assert_eq!(entropy::entropy(&entropy::byte_counts(b"aaaa")), 0.0);
+//! assert_eq!(entropy::entropy(&entropy::byte_counts(b"abab")), 1.0);
+//! assert_eq!(entropy::entropy(&[0; 256]), 0.0);
+//! ```
+
+/// Counts how often every byte value occurs in `bytes`.
+pub fn byte_counts(bytes: &[u8]) -> [u64; 256] {
+    let mut counts = [0u64; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
+    }
+    counts
+}
+
+/// Returns the Shannon entropy in bits per byte of the byte value counts `counts`, or 0
+/// if they are all zero.
+pub fn entropy(counts: &[u64; 256]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let probability = count as f64 / total as f64;
+            -probability * probability.log2()
+        })
+        .sum()
+}
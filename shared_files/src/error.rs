@@ -0,0 +1,228 @@
+//! The error type shared by the codecs and modules.
+//!
+//! The codecs keep returning `io::Result`, since they read and write through `Read` and
+//! `Write`. Failures they detect themselves are created as a [`PurgePackError`] and
+//! converted into an `io::Error` with the matching [`io::ErrorKind`], so callers can tell
+//! a corrupt file from a cancelled operation or an unsupported version with
+//! [`PurgePackError::find`] instead of matching on message text:
+//!
+//! ```rust
+//! use shared_files::error::PurgePackError;
+//! use std::io;
+//!
+//! fn decode() -> io::Result<()> {
+//!     Err(PurgePackError::UnsupportedVersion { found: 3, supported: 2 }.into())
+//! }
+//!
+//! let error = decode().unwrap_err();
+//! assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+//! assert!(matches!(
+//!     PurgePackError::find(&error),
+//!     Some(PurgePackError::UnsupportedVersion { found: 3, .. })
+//! ));
+//! ```
+//!
+//! How an operation ended is reported to the core as an [`ExitStatus`], which becomes the
+//! exit code of the process.
+
+use crate::stats::format_bytes;
+use std::io;
+use thiserror::Error;
+
+/// A failure of a PurgePack operation.
+#[derive(Debug, Error)]
+pub enum PurgePackError {
+    /// Reading or writing failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The header is not one this codec wrote: a wrong magic number, another module's
+    /// ID or a field out of range.
+    #[error("{0}")]
+    InvalidHeader(String),
+    /// The file uses a format version newer than this build supports.
+    #[error(
+        "The file uses format version {found}, which is newer than this build supports (up to version {supported}). Please update PurgePack."
+    )]
+    UnsupportedVersion {
+        /// The version stored in the file.
+        found: u8,
+        /// The newest version this build reads.
+        supported: u8,
+    },
+    /// The coded data does not decode.
+    #[error("{message}")]
+    CorruptData {
+        /// The position in the input the damage was found at, if it is known.
+        offset: Option<u64>,
+        /// What is wrong with the data.
+        message: String,
+    },
+    /// The input ended before all of the data the format announces.
+    #[error("{0}")]
+    Truncated(String),
+    /// The restored data does not match the checksum stored with it.
+    #[error(
+        "Checksum mismatch: the restored data has CRC32 0x{actual:08X}, but 0x{expected:08X} was expected. The file may be corrupted."
+    )]
+    ChecksumMismatch {
+        /// The checksum stored in the file.
+        expected: u32,
+        /// The checksum of the restored data.
+        actual: u32,
+    },
+    /// The input or the settings cannot be processed, like a range outside the file.
+    #[error("{0}")]
+    InvalidInput(String),
+    /// The operation needs more memory than the limit set with `--max-memory`.
+    #[error(
+        "{purpose} needs about {}, more than the memory limit of {} set with --max-memory.",
+        format_bytes(*needed as usize),
+        format_bytes(*limit as usize)
+    )]
+    MemoryLimit {
+        /// The memory the operation needs, in bytes.
+        needed: u64,
+        /// The limit, in bytes.
+        limit: u64,
+        /// What the memory is needed for, like "Huffman coding".
+        purpose: String,
+    },
+    /// The user cancelled the operation (Ctrl-C).
+    #[error("The operation was cancelled")]
+    Cancelled,
+}
+
+impl PurgePackError {
+    /// Creates a [`PurgePackError::CorruptData`] error for damage at `offset`.
+    pub fn corrupt_at(offset: u64, message: impl Into<String>) -> Self {
+        PurgePackError::CorruptData {
+            offset: Some(offset),
+            message: message.into(),
+        }
+    }
+
+    /// Creates a [`PurgePackError::CorruptData`] error for damage at an unknown position.
+    pub fn corrupt(message: impl Into<String>) -> Self {
+        PurgePackError::CorruptData {
+            offset: None,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the `io::ErrorKind` the error has once converted into an `io::Error`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            PurgePackError::Io(e) => e.kind(),
+            PurgePackError::InvalidHeader(_)
+            | PurgePackError::UnsupportedVersion { .. }
+            | PurgePackError::CorruptData { .. }
+            | PurgePackError::ChecksumMismatch { .. } => io::ErrorKind::InvalidData,
+            PurgePackError::Truncated(_) => io::ErrorKind::UnexpectedEof,
+            PurgePackError::InvalidInput(_) => io::ErrorKind::InvalidInput,
+            PurgePackError::MemoryLimit { .. } => io::ErrorKind::OutOfMemory,
+            // Not `Interrupted`: `read_exact` and friends retry on that.
+            PurgePackError::Cancelled => io::ErrorKind::Other,
+        }
+    }
+
+    /// Returns the [`PurgePackError`] inside `error`, or `None` if the error came from
+    /// somewhere else, like the file system.
+    pub fn find(error: &io::Error) -> Option<&PurgePackError> {
+        error.get_ref()?.downcast_ref::<PurgePackError>()
+    }
+}
+
+impl From<PurgePackError> for io::Error {
+    fn from(error: PurgePackError) -> Self {
+        match error {
+            PurgePackError::Io(e) => e,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+impl PurgePackError {
+    /// Returns the [`ExitStatus`] a module reports when it fails with this error.
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            PurgePackError::Io(e) => ExitStatus::of(e),
+            PurgePackError::InvalidHeader(_)
+            | PurgePackError::UnsupportedVersion { .. }
+            | PurgePackError::CorruptData { .. }
+            | PurgePackError::Truncated(_)
+            | PurgePackError::ChecksumMismatch { .. } => ExitStatus::Corrupt,
+            PurgePackError::InvalidInput(_) => ExitStatus::Usage,
+            PurgePackError::MemoryLimit { .. } => ExitStatus::Io,
+            PurgePackError::Cancelled => ExitStatus::Cancelled,
+        }
+    }
+}
+
+/// How a run of PurgePack ended, and the exit code of the process.
+///
+/// | Code | Status |
+/// |------|--------|
+/// | 0    | [`Success`](ExitStatus::Success) |
+/// | 1    | [`Usage`](ExitStatus::Usage): invalid arguments |
+/// | 2    | [`Io`](ExitStatus::Io): a file could not be read or written, or does not exist |
+/// | 3    | [`Corrupt`](ExitStatus::Corrupt): the data is damaged or no PurgePack output |
+/// | 4    | [`Partial`](ExitStatus::Partial): some files of a batch failed, others did not |
+/// | 130  | [`Cancelled`](ExitStatus::Cancelled): Ctrl-C, like shells report SIGINT |
+///
+/// When several operations fail, the process exits with the highest code among them.
+///
+/// ```rust
+/// use shared_files::error::{ExitStatus, PurgePackError};
+/// use std::io;
+///
+/// let error: io::Error = PurgePackError::corrupt("bad block").into();
+/// assert_eq!(ExitStatus::of(&error), ExitStatus::Corrupt);
+/// assert_eq!(ExitStatus::of(&io::Error::from(io::ErrorKind::NotFound)), ExitStatus::Io);
+/// assert_eq!(ExitStatus::Cancelled.code(), 130);
+/// assert_eq!(ExitStatus::from_code(3), Some(ExitStatus::Corrupt));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum ExitStatus {
+    #[default]
+    Success = 0,
+    Usage = 1,
+    Io = 2,
+    Corrupt = 3,
+    Partial = 4,
+    Cancelled = 130,
+}
+
+impl ExitStatus {
+    /// Returns the status of a failure with `error`: the status of the
+    /// [`PurgePackError`] inside it, or [`Corrupt`](ExitStatus::Corrupt) for invalid or
+    /// truncated data and [`Io`](ExitStatus::Io) for anything else.
+    pub fn of(error: &io::Error) -> Self {
+        if let Some(error) = PurgePackError::find(error) {
+            return error.exit_status();
+        }
+        match error.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ExitStatus::Corrupt,
+            _ => ExitStatus::Io,
+        }
+    }
+
+    /// Returns the exit code of the status.
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the status with the exit code `code`, or `None` if no status has it.
+    pub fn from_code(code: u8) -> Option<Self> {
+        [
+            ExitStatus::Success,
+            ExitStatus::Usage,
+            ExitStatus::Io,
+            ExitStatus::Corrupt,
+            ExitStatus::Partial,
+            ExitStatus::Cancelled,
+        ]
+        .into_iter()
+        .find(|status| status.code() == code)
+    }
+}
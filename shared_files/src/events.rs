@@ -0,0 +1,68 @@
+//! Machine-readable events for wrappers and GUIs.
+//!
+//! With `+core --events <PATH>`, the core writes one JSON object per line (NDJSON) to
+//! `PATH` while the human-readable output still goes to standard output and standard
+//! error. On Unix, `/dev/fd/<N>` writes to a descriptor inherited from the caller. Every
+//! event has an `"event"` field naming its kind:
+//!
+//! ```text
+//! {"event":"module_started","module":"fast_lz_module"}
+//! {"event":"progress","bytes_read":8388608}
+//! {"event":"stats","stats":{"algorithm_name":"Fast LZ",...}}
+//! {"event":"module_finished","module":"fast_lz_module","elapsed_secs":0.412}
+//! ```
+//!
+//! Modules run one after another, so the events between `module_started` and
+//! `module_finished` belong to that module. Modules emit events with [`emit`]; without
+//! `--events` the core discards them.
+
+use crate::core_header::{self, CoreH};
+use crate::json;
+use crate::stats::CompressionStats;
+use serde::Serialize;
+use std::time::Duration;
+
+/// The time between two `progress` events of a [`CancellableReader`].
+///
+/// [`CancellableReader`]: crate::cancel::CancellableReader
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// An event written to the event stream.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The core is about to start a module.
+    ModuleStarted {
+        /// The name of the module, as used on the command line without the `+`.
+        module: &'a str,
+    },
+    /// A module returned from its startup function.
+    ModuleFinished {
+        /// The name of the module, as used on the command line without the `+`.
+        module: &'a str,
+        /// The time the module ran for, in seconds.
+        elapsed_secs: f64,
+    },
+    /// The running module has read `bytes_read` bytes of its input so far.
+    Progress {
+        /// The number of bytes read.
+        bytes_read: u64,
+    },
+    /// The statistics of an operation of the running module, written with `--stats`.
+    Stats {
+        /// The statistics, in the format of [`CompressionStats::to_json`].
+        stats: &'a CompressionStats,
+    },
+}
+
+impl Event<'_> {
+    /// Serializes the event as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        json::to_json_line(self)
+    }
+}
+
+/// Writes `event` to the event stream of the core.
+pub fn emit(core: &CoreH, event: &Event) {
+    core_header::emit_event(core, &event.to_json());
+}
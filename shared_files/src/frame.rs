@@ -0,0 +1,322 @@
+//! The frame layout shared by the files of the modules.
+//!
+//! Every file a compression or transform module writes is a frame. It starts with the
+//! same six bytes, followed by the header fields and the payload of the module:
+//!
+//! ```text
+//! [magic: "PPCB"][module id: u8][format version: u8][module header ...][payload ...]
+//! ```
+//!
+//! A codec describes its frame with a [`FrameFormat`] and writes and checks the prefix
+//! through it, so a file of another module, a truncated header or a newer format version
+//! are reported the same way by every module.
+//!
+//! Frames can be concatenated. Between them, a skippable frame holds data that readers
+//! which do not understand it pass over, like a comment or an index:
+//!
+//! ```text
+//! [magic: "PPSK"][payload length: u32 LE][payload ...]
+//! ```
+//!
+//! [`FrameReader`] walks the frames of a stream:
+//!
+//! ```rust
+//! use shared_files::frame::{self, Frame, FrameFormat, FrameReader};
+//! use std::io::{Read, Write};
+//!
+//! const FORMAT: FrameFormat = FrameFormat::new(0x7F, "Example", 1);
+//!
+//! // A frame of the example module: the prefix, a one-byte length and the payload.
+//! let mut file = Vec::new();
+//! frame::write_skippable(&mut file, b"written by the docs").unwrap();
+//! FORMAT.write_prefix(&mut file).unwrap();
+//! file.write_all(&[3, b'a', b'b', b'c']).unwrap();
+//!
+//! let mut frames = FrameReader::new(file.as_slice());
+//! let Some(Frame::Skippable(comment)) = frames.next_frame().unwrap() else { panic!() };
+//! assert_eq!(comment, b"written by the docs");
+//! let Some(Frame::Module(header)) = frames.next_frame().unwrap() else { panic!() };
+//! assert_eq!(header.module_id, 0x7F);
+//!
+//! // The codec reads the frame from its first byte.
+//! let mut body = frames.frame_body();
+//! let bytes: [u8; 7] = FORMAT.read_header(&mut body).unwrap();
+//! let mut payload = vec![0; bytes[6] as usize];
+//! body.read_exact(&mut payload).unwrap();
+//! drop(body);
+//! assert_eq!(payload, b"abc");
+//! assert!(frames.next_frame().unwrap().is_none());
+//! ```
+
+use crate::error::PurgePackError;
+use std::io::{self, Cursor, Read, Write};
+
+/// Magic bytes to identify the PurgePack application. PPCB stands for "PurgePack Compressed Binary".
+pub const MAGIC: [u8; 4] = *b"PPCB";
+/// Magic bytes of a skippable frame.
+pub const SKIPPABLE_MAGIC: [u8; 4] = *b"PPSK";
+/// The size of the prefix every frame starts with: the magic, the module ID and the
+/// format version.
+pub const PREFIX_LEN: usize = 6;
+
+/// The module and format version read from the prefix of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// The ID of the module that wrote the frame.
+    pub module_id: u8,
+    /// The version of the header layout and payload format of the frame.
+    pub format_version: u8,
+}
+
+impl FrameHeader {
+    /// Parses the prefix at the start of `bytes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PurgePackError::Truncated`] if `bytes` is shorter than the prefix, or
+    /// [`PurgePackError::InvalidHeader`] if it does not start with the magic number.
+    pub fn parse(bytes: &[u8]) -> Result<Self, PurgePackError> {
+        if bytes.len() < PREFIX_LEN {
+            return Err(header_truncated());
+        }
+        if bytes[..4] != MAGIC {
+            return Err(PurgePackError::InvalidHeader(
+                "Invalid PurgePack magic number. This may not be a valid PurgePack Compressed Binary (PPCB) file."
+                    .to_string(),
+            ));
+        }
+        Ok(FrameHeader {
+            module_id: bytes[4],
+            format_version: bytes[5],
+        })
+    }
+}
+
+/// The frame format of a module: the module ID it writes and the newest format version
+/// it reads.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameFormat {
+    /// The ID of the module.
+    pub module_id: u8,
+    /// The name of the module, used in error messages.
+    pub name: &'static str,
+    /// The version written by this build, and the newest one it reads.
+    pub format_version: u8,
+}
+
+impl FrameFormat {
+    /// Creates the format of the module `module_id`.
+    pub const fn new(module_id: u8, name: &'static str, format_version: u8) -> Self {
+        FrameFormat {
+            module_id,
+            name,
+            format_version,
+        }
+    }
+
+    /// Writes the prefix of a frame of the current format version.
+    pub fn write_prefix<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[self.module_id, self.format_version])
+    }
+
+    /// Checks that `header` belongs to a frame of this module that this build can read.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PurgePackError::InvalidHeader`] for a frame of another module or format
+    /// version 0, or [`PurgePackError::UnsupportedVersion`] for a newer format version.
+    pub fn check(&self, header: FrameHeader) -> Result<(), PurgePackError> {
+        if header.module_id != self.module_id {
+            return Err(PurgePackError::InvalidHeader(format!(
+                "Unsupported module ID: 0x{:02X}. Only 0x{:02X} ({}) is supported.",
+                header.module_id, self.module_id, self.name
+            )));
+        }
+        if header.format_version > self.format_version {
+            return Err(PurgePackError::UnsupportedVersion {
+                found: header.format_version,
+                supported: self.format_version,
+            });
+        }
+        if header.format_version == 0 {
+            return Err(PurgePackError::InvalidHeader(
+                "Invalid format version: 0. The file may be corrupted.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads the first `N` bytes of a frame, at least the prefix, and checks the prefix
+    /// with [`FrameFormat::check`]. The header fields of the module follow at
+    /// [`PREFIX_LEN`].
+    ///
+    /// ```rust
+    /// use shared_files::error::PurgePackError;
+    /// use shared_files::frame::FrameFormat;
+    ///
+    /// const FORMAT: FrameFormat = FrameFormat::new(0x7F, "Example", 2);
+    ///
+    /// let header: [u8; 8] = FORMAT.read_header(&mut &b"PPCB\x7F\x02\xAB\xCD"[..]).unwrap();
+    /// assert_eq!(header[6..], [0xAB, 0xCD]);
+    ///
+    /// let newer = FORMAT.read_header::<8, _>(&mut &b"PPCB\x7F\x03\xAB\xCD"[..]).unwrap_err();
+    /// assert!(matches!(
+    ///     PurgePackError::find(&newer),
+    ///     Some(PurgePackError::UnsupportedVersion { found: 3, supported: 2 })
+    /// ));
+    /// let other = FORMAT.read_header::<8, _>(&mut &b"PPCB\x01\x01\xAB\xCD"[..]).unwrap_err();
+    /// assert!(other.to_string().starts_with("Unsupported module ID: 0x01."));
+    /// let short = FORMAT.read_header::<8, _>(&mut &b"PPCB\x7F"[..]).unwrap_err();
+    /// assert_eq!(short.kind(), std::io::ErrorKind::UnexpectedEof);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PurgePackError::Truncated`] if the input ends within the `N` bytes, or
+    /// the errors of [`FrameHeader::parse`] and [`FrameFormat::check`].
+    pub fn read_header<const N: usize, R: Read + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        reader.read_exact(&mut bytes).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                header_truncated().into()
+            } else {
+                e
+            }
+        })?;
+        self.check(FrameHeader::parse(&bytes)?)?;
+        Ok(bytes)
+    }
+}
+
+/// Creates the error reported when the input ends within a header.
+fn header_truncated() -> PurgePackError {
+    PurgePackError::Truncated(
+        "Failed to read PurgePack header. File may be too short or corrupted.".to_string(),
+    )
+}
+
+/// Writes a skippable frame holding `payload`.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if `payload` is 4 GiB or larger, or an `io::Error` if
+/// writing fails.
+pub fn write_skippable<W: Write + ?Sized>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len()).map_err(|_| {
+        PurgePackError::InvalidInput(format!(
+            "A skippable frame holds at most {} bytes, not {}.",
+            u32::MAX,
+            payload.len()
+        ))
+    })?;
+    writer.write_all(&SKIPPABLE_MAGIC)?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+/// A frame found by a [`FrameReader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// A frame written by a module. Its contents are read with
+    /// [`FrameReader::frame_body`].
+    Module(FrameHeader),
+    /// The payload of a skippable frame.
+    Skippable(Vec<u8>),
+}
+
+/// Reads the frames of a stream of concatenated frames, one after another.
+///
+/// After a [`Frame::Module`], the caller reads the whole frame through
+/// [`FrameReader::frame_body`] before asking for the next one. The codecs read exactly
+/// to the end of their frame when given a plain `Read`, but a codec that buffers its
+/// input may read past it.
+pub struct FrameReader<R> {
+    reader: R,
+    prefix: [u8; PREFIX_LEN],
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Creates a reader positioned at the start of the first frame of `reader`.
+    pub fn new(reader: R) -> Self {
+        FrameReader {
+            reader,
+            prefix: [0; PREFIX_LEN],
+        }
+    }
+
+    /// Reads the start of the next frame, or returns `None` at the end of the stream.
+    /// A skippable frame is read whole.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PurgePackError::Truncated`] if the stream ends within a prefix or a
+    /// skippable frame, [`PurgePackError::InvalidHeader`] if it holds something other
+    /// than a frame, or an `io::Error` if reading fails.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut magic = [0u8; 4];
+        let mut filled = 0;
+        while filled < magic.len() {
+            match self.reader.read(&mut magic[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(header_truncated().into()),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let truncated = |e: io::Error| -> io::Error {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                header_truncated().into()
+            } else {
+                e
+            }
+        };
+        if magic == SKIPPABLE_MAGIC {
+            let mut len = [0u8; 4];
+            self.reader.read_exact(&mut len).map_err(truncated)?;
+            let len = u32::from_le_bytes(len) as u64;
+            let mut payload = Vec::new();
+            self.reader.by_ref().take(len).read_to_end(&mut payload)?;
+            if payload.len() as u64 != len {
+                return Err(PurgePackError::Truncated(format!(
+                    "A skippable frame of {} bytes ends after {} bytes.",
+                    len,
+                    payload.len()
+                ))
+                .into());
+            }
+            return Ok(Some(Frame::Skippable(payload)));
+        }
+
+        self.prefix[..4].copy_from_slice(&magic);
+        self.reader
+            .read_exact(&mut self.prefix[4..])
+            .map_err(truncated)?;
+        Ok(Some(Frame::Module(FrameHeader::parse(&self.prefix)?)))
+    }
+
+    /// Returns a reader over the frame last returned as a [`Frame::Module`], starting
+    /// with its prefix, to hand to the codec of its module.
+    pub fn frame_body(&mut self) -> impl Read + '_ {
+        Cursor::new(self.prefix).chain(&mut self.reader)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = io::Result<Frame>;
+
+    /// Returns the next frame, like [`FrameReader::next_frame`].
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
@@ -0,0 +1,56 @@
+//! Detection of data that is already compressed or encrypted.
+//!
+//! Coding such data costs time and, because of the framing, makes it slightly larger.
+//! Codecs that work in blocks call [`is_incompressible`] on every block and store the
+//! blocks it reports as they are, without running the coder on them.
+//!
+//! A block is reported when every sampled window of [`WINDOW_SIZE`] bytes has an order-0
+//! entropy of at least [`ENTROPY_THRESHOLD`] bits per byte, the signature of random,
+//! encrypted or entropy-coded data. A block that starts with the magic number of a
+//! compressed format (JPEG, PNG, ZIP, gzip and others) only needs
+//! [`SIGNATURE_ENTROPY_THRESHOLD`], since the headers and markers of those formats
+//! lower the order-0 entropy a little even though the rest cannot be compressed. The
+//! formats are those of [`signature`] whose content is compressed already.
+//! Sampling the whole block keeps a compressed file that is followed by plain data in
+//! the same block from being stored.
+
+use crate::{entropy, signature};
+
+/// The number of bytes the entropy is measured over.
+pub const WINDOW_SIZE: usize = 4096;
+/// The number of windows sampled from a block, spread evenly over it.
+pub const SAMPLED_WINDOWS: usize = 16;
+/// The entropy in bits per byte every window must reach. Random data measures about
+/// 7.95 bits per byte over 4096 bytes.
+pub const ENTROPY_THRESHOLD: f64 = 7.9;
+/// The entropy every window must reach if the block starts with a known signature.
+pub const SIGNATURE_ENTROPY_THRESHOLD: f64 = 7.6;
+
+/// Returns whether `block` starts with the magic number of a compressed format.
+pub fn has_compressed_signature(block: &[u8]) -> bool {
+    signature::identify(block).is_some_and(|signature| signature.kind.is_compressed())
+}
+
+/// Returns whether `block` looks like compressed or encrypted data that is not worth
+/// coding. Blocks shorter than [`WINDOW_SIZE`] are never reported, because the entropy
+/// of so few bytes says too little.
+pub fn is_incompressible(block: &[u8]) -> bool {
+    if block.len() < WINDOW_SIZE {
+        return false;
+    }
+    let threshold = if has_compressed_signature(block) {
+        SIGNATURE_ENTROPY_THRESHOLD
+    } else {
+        ENTROPY_THRESHOLD
+    };
+    let windows = (block.len() / WINDOW_SIZE).min(SAMPLED_WINDOWS);
+    let spacing = if windows > 1 {
+        (block.len() - WINDOW_SIZE) / (windows - 1)
+    } else {
+        0
+    };
+    (0..windows).all(|window| {
+        let start = window * spacing;
+        entropy::entropy(&entropy::byte_counts(&block[start..start + WINDOW_SIZE])) >= threshold
+    })
+}
@@ -0,0 +1,27 @@
+//! JSON lines, the format of `--json`, `--events` and the stats exports.
+//!
+//! ```rust
+//! use shared_files::json;
+//!
+//! #[derive(serde::Serialize)]
+//! struct Entry<'a> {
+//!     path: &'a str,
+//!     len: u64,
+//! }
+//!
+//! let line = json::to_json_line(&Entry { path: "a.txt", len: 3 });
+//! assert_eq!(line, r#"{"path":"a.txt","len":3}"#);
+//! ```
+
+use serde::Serialize;
+
+/// Serializes `value` as a single line of JSON, without the line break.
+///
+/// # Panics
+///
+/// Panics if `value` cannot be serialized. Only maps with keys that are not strings and
+/// `Serialize` implementations that return an error fail, and PurgePack writes neither:
+/// its JSON objects hold plain numbers, strings and lists.
+pub fn to_json_line<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string(value).expect("PurgePack writes only serializable values")
+}
@@ -1,2 +1,27 @@
+pub mod abi;
+pub mod bitio;
+pub mod budget;
+pub mod cancel;
+pub mod checksum;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod codec;
+pub mod compare;
 pub mod core_header;
+pub mod entropy;
+pub mod error;
+pub mod events;
+pub mod frame;
+pub mod incompressible;
+pub mod json;
+pub mod log;
+#[cfg(feature = "memory-stats")]
+pub mod memory;
+pub mod output;
+pub mod overrides;
+pub mod progress;
+pub mod selftest;
+pub mod signature;
 pub mod stats;
+pub mod term;
+pub mod varint;
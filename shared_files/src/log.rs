@@ -0,0 +1,186 @@
+//! A small leveled logging facility for the core and the modules.
+//!
+//! Every message is written to standard error with the time of day (UTC), its level and
+//! the name of the module that wrote it:
+//!
+//! ```text
+//! [14:02:31.518 INFO  fast_lz] Compress: Success
+//! ```
+//!
+//! The core reads the level from `+core --log-level <error|warn|info|debug>` and hands it
+//! to the modules in `CoreH`. Every module links its own copy of this crate, so each
+//! module calls [`init`] with its name and the level of the core when it starts, and then
+//! logs with the [`log_error!`](crate::log_error), [`log_warn!`](crate::log_warn),
+//! [`log_info!`](crate::log_info) and [`log_debug!`](crate::log_debug) macros.
+//!
+//! On a terminal, errors are red and warnings yellow; see [`term`](crate::term).
+//!
+//! Logging is meant for progress and diagnostics. The results a module produces, such as
+//! hashes or statistics, are still printed to standard output.
+
+use crate::term::{self, Stream, Style};
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The severity of a log message. A message is written if its level is at most the
+/// configured level.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    /// An operation failed.
+    Error = 1,
+    /// Something unexpected happened, but the operation went on.
+    Warn = 2,
+    /// The progress and the result of an operation.
+    #[default]
+    Info = 3,
+    /// Details that help tracking down a problem.
+    Debug = 4,
+}
+
+impl LogLevel {
+    /// Converts the stored value back into a level.
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        };
+        // Honor the width, so the columns of the log line up.
+        f.pad(name)
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!(
+                "Invalid log level '{}'. Expected error, warn, info or debug.",
+                s
+            )),
+        }
+    }
+}
+
+/// The most verbose level written.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+/// The name of the module (or "core") the messages are attributed to.
+static TARGET: OnceLock<&'static str> = OnceLock::new();
+
+/// Sets the name messages are attributed to and the most verbose level written.
+///
+/// The name can only be set once. Later calls only change the level. Modules call it in
+/// `module_shutdown` as well as in `module_startup`, since a command of the core may load
+/// a module and shut it down without starting it.
+pub fn init(target: &'static str, level: LogLevel) {
+    let _ = TARGET.set(target);
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns the most verbose level written.
+pub fn level() -> LogLevel {
+    LogLevel::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns `true` if messages of `level` are written.
+pub fn enabled(level: LogLevel) -> bool {
+    level <= self::level()
+}
+
+/// Writes a message of `level`, if the level is enabled. Use the `log_*` macros instead
+/// of calling this directly.
+pub fn write(level: LogLevel, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+
+    let since_midnight = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        % (24 * 60 * 60 * 1000);
+    let (hours, minutes) = (since_midnight / 3_600_000, since_midnight / 60_000 % 60);
+    let (seconds, millis) = (since_midnight / 1000 % 60, since_midnight % 1000);
+    let target = TARGET.get().copied().unwrap_or("unknown");
+    let level = match level {
+        LogLevel::Error => term::paint(Stream::Stderr, Style::Error, format!("{:<5}", level)),
+        LogLevel::Warn => term::paint(Stream::Stderr, Style::Warn, format!("{:<5}", level)),
+        LogLevel::Info => format!("{:<5}", level),
+        LogLevel::Debug => term::paint(Stream::Stderr, Style::Dim, format!("{:<5}", level)),
+    };
+
+    // A failed write to standard error cannot be reported anywhere.
+    let _ = writeln!(
+        io::stderr().lock(),
+        "[{:02}:{:02}:{:02}.{:03} {} {}] {}",
+        hours,
+        minutes,
+        seconds,
+        millis,
+        level,
+        target,
+        args
+    );
+}
+
+/// Logs a message at the [`Error`](crate::log::LogLevel::Error) level. Takes the same
+/// arguments as `format!`.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+/// Logs a message at the [`Warn`](crate::log::LogLevel::Warn) level. Takes the same
+/// arguments as `format!`.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Warn, format_args!($($arg)*))
+    };
+}
+
+/// Logs a message at the [`Info`](crate::log::LogLevel::Info) level. Takes the same
+/// arguments as `format!`.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+/// Logs a message at the [`Debug`](crate::log::LogLevel::Debug) level. Takes the same
+/// arguments as `format!`.
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
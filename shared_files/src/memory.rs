@@ -0,0 +1,77 @@
+//! Measurement of the peak memory use of the process, for the statistics.
+//!
+//! Only compiled with the `memory-stats` feature. The peak is the largest resident set
+//! size (the physical memory in use) the process reached. [`StatsTimer`] resets it when
+//! it starts, so the peak it reports belongs to the timed operation:
+//!
+//! * On Linux the peak is read from `VmHWM` in `/proc/self/status` and reset through
+//!   `/proc/self/clear_refs`.
+//! * On Windows the peak working set cannot be reset, so it is the peak of the whole
+//!   process so far.
+//! * On other platforms no peak is reported.
+//!
+//! The core and all modules share one process, so memory still held by a module that ran
+//! earlier counts towards the peak of the next one.
+//!
+//! [`StatsTimer`]: crate::stats::StatsTimer
+
+/// Starts a new measurement: the peak is set back to the memory in use right now.
+pub fn reset_peak() {
+    platform::reset_peak()
+}
+
+/// Returns the peak memory use in bytes since the last [`reset_peak`], or `None` if it
+/// cannot be measured on this platform.
+pub fn peak_memory() -> Option<usize> {
+    platform::peak_memory()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+
+    pub fn reset_peak() {
+        // Writing 5 resets the peak resident set size (VmHWM). Without it the peak covers
+        // the whole process, which is still a useful upper bound.
+        let _ = fs::write("/proc/self/clear_refs", "5");
+    }
+
+    pub fn peak_memory() -> Option<usize> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let kib = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse::<usize>()
+            .ok()?;
+        Some(kib * 1024)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows::Win32::System::{
+        ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+        Threading::GetCurrentProcess,
+    };
+
+    pub fn reset_peak() {}
+
+    pub fn peak_memory() -> Option<usize> {
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) }.ok()?;
+        Some(counters.PeakWorkingSetSize)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    pub fn reset_peak() {}
+
+    pub fn peak_memory() -> Option<usize> {
+        None
+    }
+}
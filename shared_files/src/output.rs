@@ -0,0 +1,228 @@
+//! Atomic writing of output files.
+//!
+//! A [`SafeWriter`] writes to a temporary file in the directory of the output and renames
+//! it to the output path only once [`SafeWriter::commit`] is called. A run that fails, is
+//! cancelled or is killed never leaves a truncated file under the output name that looks
+//! like a valid one: the output is either complete or not there, and an existing file at
+//! the output path stays untouched until the rename.
+//!
+//! ```rust
+//! use shared_files::output::SafeWriter;
+//! use std::io::Write;
+//!
+//! let path = std::env::temp_dir().join("purgepack-safe-writer-doc.txt");
+//! let mut writer = SafeWriter::create(&path).unwrap();
+//! writer.write_all(b"complete").unwrap();
+//! assert!(!path.exists());
+//! writer.commit().unwrap();
+//! assert_eq!(std::fs::read(&path).unwrap(), b"complete");
+//!
+//! // Dropped without `commit`, the temporary file is removed and the output kept as is.
+//! let mut writer = SafeWriter::create(&path).unwrap();
+//! writer.write_all(b"trunc").unwrap();
+//! drop(writer);
+//! assert_eq!(std::fs::read(&path).unwrap(), b"complete");
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+//!
+//! Whether an existing file may be replaced at all is decided by the [`Overwrite`] policy
+//! the user picked, before the output is created.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// What happens to an output file that exists already.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    /// The output is not written. The default, so no file is lost by mistake.
+    #[default]
+    Refuse,
+    /// The existing file is replaced (`--force`).
+    Replace,
+    /// The output is written to the first free name with a number before the extension,
+    /// like `notes.1.ppcb` for `notes.ppcb` (`--suffix`).
+    Suffix,
+}
+
+impl Overwrite {
+    /// Returns the path to write the output meant for `path` to. A new name is logged.
+    ///
+    /// ```rust
+    /// use shared_files::output::Overwrite;
+    ///
+    /// let dir = std::env::temp_dir().join("purgepack-overwrite-doc");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("notes.ppcb");
+    /// std::fs::write(&path, b"old").unwrap();
+    ///
+    /// assert!(Overwrite::Refuse.resolve(&path).is_err());
+    /// assert_eq!(Overwrite::Replace.resolve(&path).unwrap(), path);
+    /// assert_eq!(Overwrite::Suffix.resolve(&path).unwrap(), dir.join("notes.1.ppcb"));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AlreadyExists` error if `path` exists and the policy is
+    /// [`Overwrite::Refuse`].
+    pub fn resolve(self, path: &Path) -> io::Result<PathBuf> {
+        if !path.exists() {
+            return Ok(path.to_path_buf());
+        }
+
+        match self {
+            Overwrite::Refuse => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "The output file exists already: {}. Use --force to replace it or --suffix \
+                     to write to a new name.",
+                    path.display()
+                ),
+            )),
+            Overwrite::Replace => Ok(path.to_path_buf()),
+            Overwrite::Suffix => {
+                let stem = path.file_stem().unwrap_or_default();
+                let free = (1..)
+                    .map(|n| {
+                        let mut name = stem.to_os_string();
+                        name.push(format!(".{}", n));
+                        if let Some(extension) = path.extension() {
+                            name.push(".");
+                            name.push(extension);
+                        }
+                        path.with_file_name(name)
+                    })
+                    .find(|candidate| !candidate.exists())
+                    .expect("there is a free name");
+                crate::log_info!(
+                    "The output file {} exists already, writing to {} instead",
+                    path.display(),
+                    free.display()
+                );
+                Ok(free)
+            }
+        }
+    }
+}
+
+/// The number of temporary files created so far, to keep their names apart.
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// A file that is written under a temporary name and renamed to its path on
+/// [`SafeWriter::commit`].
+///
+/// Dropping the writer without committing removes the temporary file. The writer is not
+/// buffered, so wrap it in a `BufWriter` for small writes.
+#[derive(Debug)]
+pub struct SafeWriter {
+    /// The temporary file. Only `None` while the writer is committed or dropped, since
+    /// Windows neither renames nor removes a file that is still open.
+    file: Option<File>,
+    path: PathBuf,
+    temp_path: PathBuf,
+    committed: bool,
+}
+
+impl SafeWriter {
+    /// Creates a temporary file next to `path` to write the output to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the temporary file cannot be created, like when the
+    /// directory does not exist or is not writable.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file_name = path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The output path has no file name: {}", path.display()),
+            )
+        })?;
+        let mut temp_name = OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(
+            ".{}-{}.tmp",
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        Ok(SafeWriter {
+            file: Some(file),
+            path: path.to_path_buf(),
+            temp_path,
+            committed: false,
+        })
+    }
+
+    /// Returns the path the output is renamed to on commit.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the temporary file, to read its metadata.
+    pub fn get_ref(&self) -> &File {
+        self.file
+            .as_ref()
+            .expect("the file is open until the writer is consumed")
+    }
+
+    /// Returns the temporary file to write to.
+    fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("the file is open until the writer is consumed")
+    }
+
+    /// Syncs the output to disk and renames it to its path, replacing any file there.
+    ///
+    /// Returns the output file, opened for reading, like for statistics about the output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if syncing or renaming fails. The temporary file is removed
+    /// then, and the file at the output path is left as it was.
+    pub fn commit(mut self) -> io::Result<File> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+        File::open(&self.path)
+    }
+}
+
+impl Write for SafeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Seek for SafeWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file().seek(pos)
+    }
+}
+
+impl Drop for SafeWriter {
+    fn drop(&mut self) {
+        self.file = None;
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
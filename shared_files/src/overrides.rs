@@ -0,0 +1,118 @@
+//! Environment variables standing in for flags.
+//!
+//! A flag that is not on the command line is taken from its variable, so a script or a CI
+//! job can set, say, the level once for every run. The order of precedence is always:
+//!
+//! 1. the flag on the command line,
+//! 2. the environment variable,
+//! 3. the configuration file of the core (`purgepack.conf`),
+//! 4. the default of the flag.
+//!
+//! | Variable              | Stands for                                              |
+//! |-----------------------|---------------------------------------------------------|
+//! | `PURGEPACK_LEVEL`     | `--level` of every module and of the `compress` steps   |
+//! | `PURGEPACK_JOBS`      | `-j` of `compress` and `x`, and `--jobs`/`--threads`    |
+//! | `PURGEPACK_LOG_LEVEL` | `+core --log-level`                                     |
+//! | `PURGEPACK_QUIET`     | `+core --log-level error`, if set to anything but `0`   |
+//! | `PURGEPACK_COLOR`     | `--color`; see [`term`](crate::term)                    |
+//!
+//! [`INTERACTIVE`] stands for no flag: with `0` it turns the progress bars, emoji and
+//! prompts off, and with `1` on, whether there is a terminal or not.
+//!
+//! The modules get the variables through [`cli::parse_args`](crate::cli::parse_args),
+//! which attaches them to the arguments with the long names in [`FLAGS`], and the log
+//! level through `CoreH`. An empty variable counts as unset.
+
+use crate::log::LogLevel;
+use std::{env, str::FromStr};
+
+/// The variable standing in for `--level`.
+pub const LEVEL: &str = "PURGEPACK_LEVEL";
+/// The variable standing in for `--jobs`.
+pub const JOBS: &str = "PURGEPACK_JOBS";
+/// The variable standing in for `+core --log-level`.
+pub const LOG_LEVEL: &str = "PURGEPACK_LOG_LEVEL";
+/// The variable that limits the log to errors.
+pub const QUIET: &str = "PURGEPACK_QUIET";
+/// The variable standing in for `--color`.
+pub const COLOR: &str = "PURGEPACK_COLOR";
+/// The variable deciding whether the output is interactive; see
+/// [`term::interactive`](crate::term::interactive).
+pub const INTERACTIVE: &str = "PURGEPACK_INTERACTIVE";
+
+/// The long names of the module arguments and the variables standing in for them.
+pub const FLAGS: &[(&str, &str)] = &[("level", LEVEL), ("jobs", JOBS), ("threads", JOBS)];
+
+/// Returns the value of the variable `name`, or `None` if it is unset or empty.
+pub fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Parses the value of the variable `name`, or returns `None` if it is unset.
+///
+/// # Errors
+///
+/// Returns a message naming the variable if its value does not parse.
+pub fn parse<T: FromStr>(name: &str) -> Result<Option<T>, String> {
+    parse_value(name, var(name))
+}
+
+/// Parses `value`, the value of the variable `name` or `None` if it is unset, like
+/// [`parse`] does.
+///
+/// ```rust
+/// use shared_files::overrides::{self, LEVEL};
+///
+/// assert_eq!(overrides::parse_value::<u32>(LEVEL, Some(" 7 ".to_string())), Ok(Some(7)));
+/// assert_eq!(overrides::parse_value::<u32>(LEVEL, None), Ok(None));
+/// assert!(overrides::parse_value::<u32>(LEVEL, Some("high".to_string())).is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns a message naming the variable if `value` does not parse.
+pub fn parse_value<T: FromStr>(name: &str, value: Option<String>) -> Result<Option<T>, String> {
+    value
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("{} has the invalid value {}", name, value))
+        })
+        .transpose()
+}
+
+/// Returns `false` for the values that turn a switch like [`QUIET`] off.
+///
+/// ```rust
+/// use shared_files::overrides;
+///
+/// assert!(overrides::is_on("1"));
+/// assert!(overrides::is_on("yes"));
+/// assert!(!overrides::is_on("0"));
+/// assert!(!overrides::is_on("False"));
+/// ```
+pub fn is_on(value: &str) -> bool {
+    !matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "0" | "false" | "no" | "off"
+    )
+}
+
+/// Returns the log level the variables ask for: [`LOG_LEVEL`], or errors only with
+/// [`QUIET`], or `None` if neither is set.
+///
+/// # Errors
+///
+/// Returns a message if [`LOG_LEVEL`] is no log level.
+pub fn log_level() -> Result<Option<LogLevel>, String> {
+    if let Some(level) = var(LOG_LEVEL) {
+        return level
+            .parse()
+            .map(Some)
+            .map_err(|msg| format!("{}: {}", LOG_LEVEL, msg));
+    }
+    Ok(var(QUIET)
+        .is_some_and(|value| is_on(&value))
+        .then_some(LogLevel::Error))
+}
@@ -0,0 +1,220 @@
+//! Progress reporting for long-running operations.
+//!
+//! Modules report how far an operation has come through the [`Progress`] trait. The
+//! usual way is to wrap the input file in a [`ProgressReader`], which advances the
+//! progress by every byte read, so the chunk loops of the codecs report progress without
+//! knowing about it:
+//!
+//! ```rust
+//! use shared_files::progress::{self, ProgressReader};
+//! use std::io::Read;
+//!
+//! let input = vec![0u8; 4096];
+//! let progress = progress::terminal("Compress", Some(input.len() as u64));
+//! let mut reader = ProgressReader::new(input.as_slice(), progress);
+//! let mut buffer = Vec::new();
+//! reader.read_to_end(&mut buffer).unwrap();
+//! ```
+//!
+//! [`terminal`] returns a [`TerminalProgress`] bar on standard error, or a
+//! [`NoProgress`] that discards everything when standard error is not
+//! [`interactive`](crate::term::interactive) or info messages are not logged. Machine-readable progress goes through the `progress`
+//! events instead (see [`events`](crate::events)).
+
+use crate::events::PROGRESS_INTERVAL;
+use crate::log::{self, LogLevel};
+use crate::stats::format_bytes;
+use crate::term::{self, Stream};
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::Instant,
+};
+
+/// The width of the bar drawn by [`TerminalProgress`], in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Receives the progress of an operation.
+pub trait Progress {
+    /// Sets the amount of work the operation has in total, usually a number of bytes.
+    fn set_total(&mut self, total: u64);
+    /// Adds `delta` to the work done so far.
+    fn advance(&mut self, delta: u64);
+    /// Replaces the message shown with the progress, like the name of the operation.
+    fn message(&mut self, message: &str);
+    /// Marks the operation as finished. Calling it again has no effect.
+    fn finish(&mut self);
+}
+
+/// A [`Progress`] that discards everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn set_total(&mut self, _total: u64) {}
+    fn advance(&mut self, _delta: u64) {}
+    fn message(&mut self, _message: &str) {}
+    fn finish(&mut self) {}
+}
+
+/// A progress bar on standard error:
+///
+/// ```text
+/// Compress [===========>                  ]  38%  12.00 MiB / 31.50 MiB
+/// ```
+///
+/// The bar is redrawn at most every [`PROGRESS_INTERVAL`] and only once the operation
+/// has run that long, so short operations print nothing. Without a total, only the
+/// amount done is shown. The line is cleared once the operation finishes or the total
+/// is reached, so the log and the statistics that follow are not mixed with it.
+#[derive(Debug)]
+pub struct TerminalProgress {
+    message: String,
+    total: Option<u64>,
+    position: u64,
+    next_draw: Instant,
+    line_len: usize,
+    finished: bool,
+}
+
+impl TerminalProgress {
+    /// Creates a bar showing `message`.
+    pub fn new(message: &str) -> Self {
+        TerminalProgress {
+            message: message.to_string(),
+            total: None,
+            position: 0,
+            next_draw: Instant::now() + PROGRESS_INTERVAL,
+            line_len: 0,
+            finished: false,
+        }
+    }
+
+    /// Returns the line showing the current state.
+    fn line(&self) -> String {
+        let done = format_bytes(self.position as usize);
+        match self.total {
+            Some(total) if total > 0 => {
+                let fraction = (self.position as f64 / total as f64).min(1.0);
+                let filled = (fraction * BAR_WIDTH as f64) as usize;
+                let mut bar = "=".repeat(filled);
+                if filled < BAR_WIDTH {
+                    bar.push('>');
+                }
+                format!(
+                    "{} [{:<width$}] {:>3}%  {} / {}",
+                    self.message,
+                    bar,
+                    (fraction * 100.0) as u32,
+                    done,
+                    format_bytes(total as usize),
+                    width = BAR_WIDTH
+                )
+            }
+            _ => format!("{} {}", self.message, done),
+        }
+    }
+
+    /// Overwrites the current line with `line`.
+    fn draw(&mut self, line: &str) {
+        // Pad with spaces instead of using an escape sequence to clear the line, so the
+        // bar also works on consoles without them.
+        let padding = self.line_len.saturating_sub(line.chars().count());
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}{:padding$}\r", line, "");
+        let _ = stderr.flush();
+        self.line_len = line.chars().count();
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn set_total(&mut self, total: u64) {
+        self.total = Some(total);
+    }
+
+    fn advance(&mut self, delta: u64) {
+        self.position += delta;
+        if self.total.is_some_and(|total| self.position >= total) {
+            self.finish();
+            return;
+        }
+        let now = Instant::now();
+        if !self.finished && now >= self.next_draw {
+            self.next_draw = now + PROGRESS_INTERVAL;
+            let line = self.line();
+            self.draw(&line);
+        }
+    }
+
+    fn message(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+
+    fn finish(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            if self.line_len > 0 {
+                self.draw("");
+            }
+        }
+    }
+}
+
+impl Drop for TerminalProgress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Returns a [`TerminalProgress`] showing `message` if standard error is interactive and
+/// info messages are logged, or a [`NoProgress`] otherwise.
+pub fn terminal(message: &str, total: Option<u64>) -> Box<dyn Progress> {
+    if !term::interactive(Stream::Stderr) || !log::enabled(LogLevel::Info) {
+        return Box::new(NoProgress);
+    }
+    let mut progress = TerminalProgress::new(message);
+    if let Some(total) = total {
+        progress.set_total(total);
+    }
+    Box::new(progress)
+}
+
+/// A reader that advances a [`Progress`] by the number of bytes read, and finishes it
+/// when dropped.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Box<dyn Progress>,
+}
+
+impl<R> ProgressReader<R> {
+    /// Wraps `inner` so reads advance `progress`.
+    pub fn new(inner: R, progress: Box<dyn Progress>) -> Self {
+        ProgressReader { inner, progress }
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.progress.advance(len as u64);
+        Ok(len)
+    }
+}
+
+/// Seeking does not change the progress: work read again, like in a second pass over
+/// the input, counts again.
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        self.progress.finish();
+    }
+}
@@ -0,0 +1,368 @@
+//! The self-test protocol of the modules.
+//!
+//! A module can export a `module_selftest` function of type [`SelfTestFn`]. `purgepack
+//! test [module ...]` loads the modules, calls it instead of `module_startup` and prints
+//! the checks every module recorded in its [`SelfTestReport`] and sent to the
+//! [`SelfTestSink`] of the core:
+//!
+//! ```rust,ignore
+//! #[unsafe(no_mangle)]
+//! extern "C" fn module_selftest(_core: &core_header::CoreH, sink: &mut SelfTestSink) {
+//!     let mut report = SelfTestReport::new();
+//!     report.round_trip("default", &purgepack_fast_lz::FastLz::default());
+//!     report.send(sink);
+//! }
+//! ```
+//!
+//! The checks run on synthetic data from [`samples`], so they need no files and finish
+//! quickly. They cover the cases a codec gets wrong most often: empty and tiny inputs,
+//! long runs, text, incompressible data and structured samples.
+
+use crate::abi;
+use crate::codec::{Compressor, Decompressor};
+use crate::core_header::CoreH;
+use std::{
+    ffi::{c_char, c_void},
+    fmt::Display,
+    io::Cursor,
+    marker::PhantomData,
+    ptr,
+};
+
+/// The type of the `module_selftest` export of a module.
+pub type SelfTestFn = extern "C" fn(core: &CoreH, sink: &mut SelfTestSink);
+
+/// Where a module sends the checks of its self-test, across the module interface.
+///
+/// ```rust
+/// use shared_files::selftest::{SelfTestReport, SelfTestSink};
+///
+/// let mut module_report = SelfTestReport::new();
+/// module_report.check("addition", Ok::<(), String>(()));
+/// module_report.check("parsing", "x".parse::<u8>().map(|_| ()));
+///
+/// let mut core_report = SelfTestReport::new();
+/// module_report.send(&mut SelfTestSink::new(&mut core_report));
+/// assert_eq!(core_report.checks(), module_report.checks());
+/// ```
+#[repr(C)]
+pub struct SelfTestSink<'a> {
+    ctx: *mut c_void,
+    /// Records the check `name`, which failed with `failure` or passed if it is null.
+    record_f: extern "C" fn(ctx: *mut c_void, name: *const c_char, failure: *const c_char),
+    _report: PhantomData<&'a mut SelfTestReport>,
+}
+
+impl<'a> SelfTestSink<'a> {
+    /// Creates a sink that adds the checks it receives to `report`.
+    pub fn new(report: &'a mut SelfTestReport) -> Self {
+        SelfTestSink {
+            ctx: ptr::from_mut(report).cast(),
+            record_f: record_into_report,
+            _report: PhantomData,
+        }
+    }
+
+    /// Sends `check` to the receiving report.
+    pub fn record(&mut self, check: &CheckResult) {
+        let name = abi::to_c_string(&check.name);
+        let failure = check.failure.as_deref().map(abi::to_c_string);
+        (self.record_f)(
+            self.ctx,
+            name.as_ptr(),
+            failure
+                .as_ref()
+                .map_or(ptr::null(), |failure| failure.as_ptr()),
+        );
+    }
+}
+
+extern "C" fn record_into_report(ctx: *mut c_void, name: *const c_char, failure: *const c_char) {
+    // SAFETY: `ctx` is the report the sink was created from, which outlives it, and the
+    // strings come from `SelfTestSink::record`.
+    let report = unsafe { &mut *(ctx as *mut SelfTestReport) };
+    report.checks.push(CheckResult {
+        name: unsafe { abi::from_c_str(name) }.into_owned(),
+        failure: (!failure.is_null()).then(|| unsafe { abi::from_c_str(failure) }.into_owned()),
+    });
+}
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// What was checked, like "round trip default (text)".
+    pub name: String,
+    /// Why the check failed, or `None` if it passed.
+    pub failure: Option<String>,
+}
+
+impl CheckResult {
+    /// Returns `true` if the check passed.
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// The checks a module ran in its self-test.
+///
+/// ```rust
+/// use shared_files::selftest::SelfTestReport;
+///
+/// let mut report = SelfTestReport::new();
+/// report.check("addition", if 1 + 1 == 2 { Ok(()) } else { Err("wrong sum") });
+/// report.check("parsing", "x".parse::<u8>().map(|_| ()));
+/// assert!(!report.passed());
+/// assert_eq!(report.failed().count(), 1);
+/// assert_eq!(report.checks()[1].failure.as_deref(), Some("invalid digit found in string"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Creates a report without checks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the check `name`, which failed with the error of `result` if there is one.
+    pub fn check<E: Display>(&mut self, name: &str, result: Result<(), E>) {
+        self.checks.push(CheckResult {
+            name: name.to_string(),
+            failure: result.err().map(|e| e.to_string()),
+        });
+    }
+
+    /// Compresses and restores every sample of [`samples`] with `codec`, and records a
+    /// check per sample that the restored data matches the original.
+    ///
+    /// ```rust
+    /// use shared_files::codec::{CodecStats, Compressor, Decompressor};
+    /// use shared_files::selftest::SelfTestReport;
+    /// use std::io::{self, Read, Write};
+    ///
+    /// /// A codec that stores the data as it is.
+    /// struct Store;
+    ///
+    /// impl Compressor for Store {
+    ///     fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+    ///         let len = io::copy(reader, writer)?;
+    ///         Ok(CodecStats { input_len: len, output_len: len })
+    ///     }
+    /// }
+    ///
+    /// impl Decompressor for Store {
+    ///     fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+    ///         self.compress(reader, writer)
+    ///     }
+    /// }
+    ///
+    /// let mut report = SelfTestReport::new();
+    /// report.round_trip("store", &Store);
+    /// assert!(report.passed());
+    /// assert_eq!(report.checks()[0].name, "round trip store (empty)");
+    /// ```
+    pub fn round_trip<C: Compressor + Decompressor + ?Sized>(&mut self, name: &str, codec: &C) {
+        for (sample, data) in samples() {
+            let result = round_trip(codec, &data);
+            self.check(&format!("round trip {} ({})", name, sample), result);
+        }
+    }
+
+    /// Compresses every sample of [`samples`] with `codec` in memory and through
+    /// [`Compressor::compress_file`], and records a check per sample that both wrote the
+    /// same bytes.
+    pub fn streams<C: Compressor + ?Sized>(&mut self, name: &str, codec: &C) {
+        for (sample, data) in samples() {
+            let result = streams(codec, &data);
+            self.check(&format!("streaming {} ({})", name, sample), result);
+        }
+    }
+
+    /// Returns the checks in the order they were recorded.
+    pub fn checks(&self) -> &[CheckResult] {
+        &self.checks
+    }
+
+    /// Returns the checks that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|check| !check.passed())
+    }
+
+    /// Returns `true` if every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(CheckResult::passed)
+    }
+
+    /// Sends all checks to `sink`, at the end of `module_selftest`.
+    pub fn send(&self, sink: &mut SelfTestSink) {
+        for check in &self.checks {
+            sink.record(check);
+        }
+    }
+}
+
+/// Compresses and restores `data` with `codec` and compares the result.
+fn round_trip<C: Compressor + Decompressor + ?Sized>(codec: &C, data: &[u8]) -> Result<(), String> {
+    let mut compressed = Vec::new();
+    codec
+        .compress(&mut &data[..], &mut compressed)
+        .map_err(|e| format!("compressing failed: {}", e))?;
+    let mut restored = Vec::new();
+    codec
+        .decompress(&mut &compressed[..], &mut restored)
+        .map_err(|e| format!("restoring failed: {}", e))?;
+    if restored != data {
+        return Err(format!(
+            "the {} restored bytes differ from the {} original bytes",
+            restored.len(),
+            data.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Compresses `data` with `codec` in memory and from a file, and compares the outputs.
+fn streams<C: Compressor + ?Sized>(codec: &C, data: &[u8]) -> Result<(), String> {
+    let mut in_memory = Vec::new();
+    codec
+        .compress(&mut &data[..], &mut in_memory)
+        .map_err(|e| format!("compressing failed: {}", e))?;
+    let mut streamed = Cursor::new(Vec::new());
+    codec
+        .compress_file(&mut Cursor::new(data), &mut streamed)
+        .map_err(|e| format!("compressing the file failed: {}", e))?;
+    if streamed.into_inner() != in_memory {
+        return Err("the file was compressed to other bytes than in memory".to_string());
+    }
+    Ok(())
+}
+
+/// Compresses `data` with `codec`, checks that it is restored, and that the compressed
+/// file is rejected once it is cut short or a byte at one of the `positions` is changed.
+///
+/// `positions` gets the length of the compressed file. The file is cut to no bytes, to
+/// half of it and to all but its last byte.
+///
+/// # Errors
+///
+/// Returns what went wrong: a failed round trip, or the damage that was restored
+/// without an error.
+///
+/// ```rust
+/// use shared_files::codec::{CodecStats, Compressor, Decompressor};
+/// use shared_files::selftest;
+/// use std::io::{self, Read, Write};
+///
+/// /// A codec that stores the data behind its length.
+/// struct Store;
+///
+/// impl Compressor for Store {
+///     fn compress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+///         let mut data = Vec::new();
+///         reader.read_to_end(&mut data)?;
+///         writer.write_all(&(data.len() as u64).to_le_bytes())?;
+///         writer.write_all(&data)?;
+///         Ok(CodecStats { input_len: data.len() as u64, output_len: data.len() as u64 + 8 })
+///     }
+/// }
+///
+/// impl Decompressor for Store {
+///     fn decompress(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<CodecStats> {
+///         let mut len = [0; 8];
+///         reader.read_exact(&mut len)?;
+///         let len = u64::from_le_bytes(len);
+///         let mut data = Vec::new();
+///         reader.take(len).read_to_end(&mut data)?;
+///         if data.len() as u64 != len {
+///             return Err(io::ErrorKind::UnexpectedEof.into());
+///         }
+///         writer.write_all(&data)?;
+///         Ok(CodecStats { input_len: data.len() as u64 + 8, output_len: data.len() as u64 })
+///     }
+/// }
+///
+/// let text = selftest::sample("text").unwrap();
+/// // The length is checked, the data is not.
+/// assert!(selftest::rejects_damage(&Store, &text, |_| vec![0, 7]).is_ok());
+/// assert!(selftest::rejects_damage(&Store, &text, |len| vec![len / 2]).is_err());
+/// ```
+pub fn rejects_damage<C: Compressor + Decompressor + ?Sized>(
+    codec: &C,
+    data: &[u8],
+    positions: impl FnOnce(usize) -> Vec<usize>,
+) -> Result<(), String> {
+    let mut file = Vec::new();
+    codec
+        .compress(&mut &data[..], &mut file)
+        .map_err(|e| format!("compressing failed: {}", e))?;
+    let restore = |file: &[u8]| {
+        let mut restored = Vec::new();
+        codec
+            .decompress(&mut &file[..], &mut restored)
+            .map(|_| restored)
+    };
+    match restore(&file) {
+        Ok(restored) if restored == data => {}
+        Ok(_) => return Err("the restored bytes differ from the original bytes".to_string()),
+        Err(e) => return Err(format!("restoring failed: {}", e)),
+    }
+
+    for position in positions(file.len()) {
+        let mut damaged = file.clone();
+        damaged[position] ^= 0x55;
+        if restore(&damaged).is_ok() {
+            return Err(format!("a change of byte {} was restored", position));
+        }
+    }
+    for len in [0, file.len() / 2, file.len() - 1] {
+        if restore(&file[..len]).is_ok() {
+            return Err(format!("the first {} bytes were restored", len));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the synthetic samples the self-tests run on, with their names.
+///
+/// The samples are the same on every run, so a failure can be reproduced.
+pub fn samples() -> Vec<(&'static str, Vec<u8>)> {
+    let text = "The quick brown fox jumps over the lazy dog. Pack my box with five dozen \
+                liquor jugs! 0123456789\n"
+        .repeat(400)
+        .into_bytes();
+
+    // A xorshift generator, so the noise needs no dependency and does not change.
+    let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+    let noise = (0..65_536)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 56) as u8
+        })
+        .collect();
+
+    // A slowly rising 16-bit signal, like audio samples or sensor readings.
+    let ramp = (0..32_768u32)
+        .flat_map(|i| ((i * 3 + i / 7) as u16).to_le_bytes())
+        .collect();
+
+    vec![
+        ("empty", Vec::new()),
+        ("single byte", vec![0x42]),
+        ("zeros", vec![0; 100_000]),
+        ("text", text),
+        ("noise", noise),
+        ("16-bit ramp", ramp),
+    ]
+}
+
+/// Returns the sample of [`samples`] called `name`, or `None` if there is none.
+pub fn sample(name: &str) -> Option<Vec<u8>> {
+    samples()
+        .into_iter()
+        .find(|(sample, _)| *sample == name)
+        .map(|(_, data)| data)
+}
@@ -0,0 +1,239 @@
+//! Detection of well-known file formats by their magic numbers.
+//!
+//! The detect module classifies files by the table, and the codecs store blocks that
+//! start with the magic number of a compressed format as they are; see
+//! [`incompressible`](crate::incompressible).
+//!
+//! ```rust
+//! use shared_files::signature::{self, SignatureKind};
+//!
+//! let png = signature::identify(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+//! assert_eq!((png.name, png.kind), ("PNG image", SignatureKind::CompressedMedia));
+//! assert!(png.kind.is_compressed());
+//! assert!(signature::identify(b"plain text").is_none());
+//! ```
+
+/// The kind of content a file signature stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureKind {
+    /// A file written by a PurgePack module.
+    PurgePack,
+    /// A general-purpose compressed file or archive.
+    Compressed,
+    /// An image, audio, video or document format that already compresses its content.
+    CompressedMedia,
+    /// An image format that stores its pixels uncompressed.
+    RawImage,
+    /// An executable or object file.
+    Executable,
+}
+
+impl SignatureKind {
+    /// Returns whether the content of the format is compressed or encrypted already, so
+    /// coding it again gains nothing.
+    pub fn is_compressed(self) -> bool {
+        matches!(
+            self,
+            SignatureKind::PurgePack | SignatureKind::Compressed | SignatureKind::CompressedMedia
+        )
+    }
+}
+
+/// A magic number at a fixed offset of the file.
+pub struct Signature {
+    /// The offset of the magic number.
+    pub offset: usize,
+    /// The bytes of the magic number.
+    pub magic: &'static [u8],
+    /// The name of the format.
+    pub name: &'static str,
+    /// The kind of content the format holds.
+    pub kind: SignatureKind,
+}
+
+/// The formats recognized by their magic number, checked in order.
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        offset: 0,
+        magic: b"PPCB",
+        name: "PurgePack Compressed Binary",
+        kind: SignatureKind::PurgePack,
+    },
+    Signature {
+        offset: 0,
+        magic: b"PPDP",
+        name: "PurgePack Delta Patch",
+        kind: SignatureKind::PurgePack,
+    },
+    Signature {
+        offset: 0,
+        magic: b"PPSK",
+        name: "PurgePack Skippable Frame",
+        kind: SignatureKind::PurgePack,
+    },
+    Signature {
+        offset: 0,
+        magic: b"PPCE",
+        name: "PurgePack Crypt Envelope",
+        kind: SignatureKind::PurgePack,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0x1F, 0x8B],
+        name: "gzip",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: b"PK\x03\x04",
+        name: "ZIP archive",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: b"BZh",
+        name: "bzip2",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0xFD, b'7', b'z', b'X', b'Z', 0x00],
+        name: "xz",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0x28, 0xB5, 0x2F, 0xFD],
+        name: "Zstandard",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0x04, 0x22, 0x4D, 0x18],
+        name: "LZ4 frame",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: &[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C],
+        name: "7-Zip archive",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: b"Rar!\x1A\x07",
+        name: "RAR archive",
+        kind: SignatureKind::Compressed,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A],
+        name: "PNG image",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0xFF, 0xD8, 0xFF],
+        name: "JPEG image",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: b"GIF8",
+        name: "GIF image",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 8,
+        magic: b"WEBP",
+        name: "WebP image",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: b"ID3",
+        name: "MP3 audio",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: b"fLaC",
+        name: "FLAC audio",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: b"OggS",
+        name: "Ogg media",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 4,
+        magic: b"ftyp",
+        name: "MP4/QuickTime media",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: b"%PDF",
+        name: "PDF document",
+        kind: SignatureKind::CompressedMedia,
+    },
+    Signature {
+        offset: 0,
+        magic: b"BM",
+        name: "BMP image",
+        kind: SignatureKind::RawImage,
+    },
+    Signature {
+        offset: 0,
+        magic: b"P5",
+        name: "PGM image",
+        kind: SignatureKind::RawImage,
+    },
+    Signature {
+        offset: 0,
+        magic: b"P6",
+        name: "PPM image",
+        kind: SignatureKind::RawImage,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0x7F, b'E', b'L', b'F'],
+        name: "ELF executable",
+        kind: SignatureKind::Executable,
+    },
+    Signature {
+        offset: 0,
+        magic: b"MZ",
+        name: "Windows executable",
+        kind: SignatureKind::Executable,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0xCF, 0xFA, 0xED, 0xFE],
+        name: "Mach-O executable",
+        kind: SignatureKind::Executable,
+    },
+    Signature {
+        offset: 0,
+        magic: &[0xCE, 0xFA, 0xED, 0xFE],
+        name: "Mach-O executable",
+        kind: SignatureKind::Executable,
+    },
+    Signature {
+        offset: 0,
+        magic: b"\0asm",
+        name: "WebAssembly module",
+        kind: SignatureKind::Executable,
+    },
+];
+
+/// Returns the first format whose magic number is found in `prefix`.
+pub fn identify(prefix: &[u8]) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(|signature| {
+        prefix
+            .get(signature.offset..signature.offset + signature.magic.len())
+            .is_some_and(|bytes| bytes == signature.magic)
+    })
+}
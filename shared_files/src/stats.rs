@@ -20,6 +20,18 @@
 //!     if mandatory fields are missing.
 //! * **Formatting**: Includes the `format_bytes` helper function and custom `Display`
 //!     implementations for clear, human-readable terminal output of all collected data.
+//! * **Machine-Readable Export**: [`CompressionStats`] implements `serde::Serialize` and
+//!   can be rendered as JSON or CSV with [`CompressionStats::render`], selected by a
+//!   [`StatsFormat`], so scripts can ingest the results without scraping the text.
+//! * **Batch Summaries**: The [`AggregateStats`] struct accumulates the results of many
+//!   files and reports the totals, the size-weighted ratio, the overall throughput and
+//!   the slowest files.
+//! * **Run Comparison**: [`CompressionStats::from_json`] reads an exported report back, and
+//!   [`CompressionStats::compare`] shows how the ratio, the speed and every section changed
+//!   between two runs, for tracking regressions across versions.
+//! * **Throughput Over Time**: A [`SampledReader`] from [`StatsTimer::sample_reader`]
+//!   records how many bytes were read at regular intervals, which shows I/O stalls and
+//!   slow phases as a compact series in the output.
 //!
 //! ## Example Usage: Required and Optional Timing
 //!
@@ -112,8 +124,16 @@
 //!     // println!("{}", stats_minimal);
 //! }
 //! ```
+use crate::json;
+use crate::term::{self, Stream, Style};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::error::Error;
 use std::fmt::{self, Display};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 const KIB: usize = 1024;
 const MIB: usize = KIB * 1024;
@@ -172,7 +192,7 @@ const TIB: usize = GIB * 1024;
 /// // Over 1 TiB (5 * 1024^4)
 /// assert_eq!(format_bytes(5 * TIB), "5.00 TiB");
 /// ```
-fn format_bytes(bytes: usize) -> String {
+pub(crate) fn format_bytes(bytes: usize) -> String {
     if bytes >= TIB {
         format!("{:.2} TiB", bytes as f64 / TIB as f64)
     } else if bytes >= GIB {
@@ -220,11 +240,13 @@ impl Error for BuilderError {
 /// This structure encapsulates metadata (algorithm used, version) and
 /// performance metrics (lengths, time, ratio, speed) related to a single
 /// processing task.
-#[derive(Debug, Clone)] // Added Clone for idiomatic use, assuming it's intended
+///
+/// When serialized, durations are written as seconds (`duration_secs`).
+#[derive(Debug, Clone, Serialize, Deserialize)] // Added Clone for idiomatic use, assuming it's intended
 pub struct CompressionStats {
     // --- Input and Metadata Fields ---
     /// The human-readable name of the algorithm used (e.g., "Run Length Encoding" or "Huffman Encoding").
-    pub algorithm_name: &'static str,
+    pub algorithm_name: Cow<'static, str>,
     /// A unique numerical identifier for the algorithm.
     pub algorithm_id: u8,
     /// The specific version of the algorithm used for this run.
@@ -236,6 +258,11 @@ pub struct CompressionStats {
     /// (Compressed size for compression, uncompressed size for decompression).
     pub processed_len: usize,
     /// The total time taken for the entire process.
+    #[serde(
+        rename = "duration_secs",
+        serialize_with = "serialize_secs",
+        deserialize_with = "deserialize_secs"
+    )]
     pub duration: Duration,
     /// True if the process was compression, false if it was decompression.
     pub is_compression: bool,
@@ -243,6 +270,13 @@ pub struct CompressionStats {
     /// A list of timed steps within the overall process, providing a detailed
     /// breakdown of time consumption.
     pub sections: Vec<SectionStats>,
+    /// The peak memory use of the process during the operation (in bytes), if it was
+    /// measured. Requires the `memory-stats` feature.
+    #[serde(rename = "peak_memory_bytes")]
+    pub peak_memory: Option<usize>,
+    /// The number of bytes read over time, sampled at regular intervals. Empty if the
+    /// throughput was not sampled.
+    pub throughput: Vec<ThroughputSample>,
 
     // --- Calculated Fields ---
     /// The compression ratio factor, calculated as `uncompressed_len / compressed_len`.
@@ -250,6 +284,7 @@ pub struct CompressionStats {
     /// A value of 2.0 means the output size is half of the original (2x compression).
     pub compression_ratio_factor: f64,
     /// The processing speed, calculated in Mebibytes per second (MiB/s).
+    #[serde(deserialize_with = "deserialize_speed")]
     pub speed_mib_s: f64,
     /// The raw difference in bytes: `uncompressed_len - compressed_len`.
     ///
@@ -264,14 +299,47 @@ pub struct CompressionStats {
 /// A struct to hold the name and duration for a specific processing step.
 ///
 /// Used primarily within the [`CompressionStats::sections`] field.
-#[derive(Debug, Clone)] // Added Clone for consistency
+#[derive(Debug, Clone, Serialize, Deserialize)] // Added Clone for consistency
 pub struct SectionStats {
     /// The descriptive name of the step (e.g., "Hashing" or "Header Write").
     pub name: String,
     /// The time taken for this specific step.
+    #[serde(
+        rename = "duration_secs",
+        serialize_with = "serialize_secs",
+        deserialize_with = "deserialize_secs"
+    )]
     pub duration: Duration,
 }
 
+/// Serializes a [`Duration`] as a floating point number of seconds, which is easier to
+/// consume than serde's default `{ secs, nanos }` pair.
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Reads a [`Duration`] written by [`serialize_secs`].
+fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let secs = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+}
+
+/// Reads a speed, which JSON stores as `null` if it could not be measured.
+fn deserialize_speed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+}
+
+/// Returns the speed of processing `len` bytes in `duration` in MiB/s, or infinity if
+/// the duration is too short to measure.
+fn speed_mib_s(len: usize, duration: Duration) -> f64 {
+    let duration_secs = duration.as_secs_f64();
+    if duration_secs == 0.0 {
+        f64::INFINITY
+    } else {
+        (len as f64 / (1024.0 * 1024.0)) / duration_secs
+    }
+}
+
 impl SectionStats {
     /// Creates a new [`SectionStats`] instance.
     ///
@@ -371,14 +439,22 @@ pub struct StatsTimer {
     start_time: Instant,
     /// A vector of all completed subsection statistics.
     sections: Vec<SectionStats>,
+    /// The sampler shared with the readers returned by [`StatsTimer::sample_reader`].
+    sampler: Option<ThroughputSampler>,
 }
 
 impl StatsTimer {
     /// Initializes and starts the main timer. This is the initial timestamp for the entire process.
+    ///
+    /// With the `memory-stats` feature, this also resets the peak memory use of the
+    /// process, see [`StatsTimer::peak_memory`].
     pub fn new() -> Self {
+        #[cfg(feature = "memory-stats")]
+        crate::memory::reset_peak();
         StatsTimer {
             start_time: Instant::now(),
             sections: Vec::new(),
+            sampler: None,
         }
     }
 
@@ -421,6 +497,41 @@ impl StatsTimer {
     pub fn end(self) -> (Duration, Vec<SectionStats>) {
         (self.start_time.elapsed(), self.sections)
     }
+
+    /// Returns the peak memory use of the process in bytes since the timer started.
+    ///
+    /// Returns `None` without the `memory-stats` feature, or if the platform does not
+    /// report it. See the `memory` module for the details per platform.
+    pub fn peak_memory(&self) -> Option<usize> {
+        #[cfg(feature = "memory-stats")]
+        return crate::memory::peak_memory();
+        #[cfg(not(feature = "memory-stats"))]
+        None
+    }
+
+    /// Wraps `reader` so the number of bytes read through it is sampled every `interval`.
+    ///
+    /// All readers wrapped by the same timer count towards the same series, which
+    /// [`StatsTimer::throughput`] returns.
+    pub fn sample_reader<R: Read>(&mut self, reader: R, interval: Duration) -> SampledReader<R> {
+        let start_time = self.start_time;
+        let sampler = self
+            .sampler
+            .get_or_insert_with(|| ThroughputSampler::new(start_time, interval));
+        SampledReader {
+            inner: reader,
+            sampler: Some(sampler.clone()),
+        }
+    }
+
+    /// Returns the throughput samples recorded so far, ending with the current total.
+    ///
+    /// The list is empty if no reader was wrapped with [`StatsTimer::sample_reader`].
+    pub fn throughput(&self) -> Vec<ThroughputSample> {
+        self.sampler
+            .as_ref()
+            .map_or(Vec::new(), |sampler| sampler.samples())
+    }
 }
 
 /// A wrapper struct that holds either a real StatsTimer or nothing (None).
@@ -483,6 +594,142 @@ impl OptinalStatsTimer {
         self.0
             .map_or((Duration::from_secs(0), Vec::new()), |t| t.end())
     }
+
+    /// Returns the peak memory use since the timer started, or `None` if the timer is
+    /// disabled or the peak is not measured. See [`StatsTimer::peak_memory`].
+    pub fn peak_memory(&self) -> Option<usize> {
+        self.0.as_ref().and_then(|t| t.peak_memory())
+    }
+
+    /// Wraps `reader` so its throughput is sampled every `interval`, see
+    /// [`StatsTimer::sample_reader`].
+    ///
+    /// If the timer is disabled, the returned reader only passes the reads through.
+    pub fn sample_reader<R: Read>(&mut self, reader: R, interval: Duration) -> SampledReader<R> {
+        match self.0.as_mut() {
+            Some(t) => t.sample_reader(reader, interval),
+            None => SampledReader {
+                inner: reader,
+                sampler: None,
+            },
+        }
+    }
+
+    /// Returns the throughput samples, or an empty list if the timer is disabled. See
+    /// [`StatsTimer::throughput`].
+    pub fn throughput(&self) -> Vec<ThroughputSample> {
+        self.0.as_ref().map_or(Vec::new(), |t| t.throughput())
+    }
+}
+
+/// The time between two throughput samples the modules use.
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of bytes read up to a point in time, one point of the throughput series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThroughputSample {
+    /// The time since the timer started.
+    #[serde(
+        rename = "elapsed_secs",
+        serialize_with = "serialize_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub elapsed: Duration,
+    /// The total number of bytes read up to this point.
+    pub bytes: u64,
+}
+
+/// The state shared by a [`StatsTimer`] and the readers it wrapped.
+#[derive(Debug)]
+struct SamplerState {
+    /// The start of the timer, which the elapsed times are relative to.
+    start_time: Instant,
+    /// The time between two samples.
+    interval: Duration,
+    /// The time the next sample is due.
+    next_sample: Instant,
+    /// The total number of bytes read.
+    bytes: u64,
+    /// The samples taken so far.
+    samples: Vec<ThroughputSample>,
+}
+
+/// Records the number of bytes read at regular intervals.
+///
+/// Samples are only taken when data is read, so a read that stalls shows up as a sample
+/// that covers a longer time with few bytes.
+#[derive(Debug, Clone)]
+struct ThroughputSampler(Arc<Mutex<SamplerState>>);
+
+impl ThroughputSampler {
+    fn new(start_time: Instant, interval: Duration) -> Self {
+        ThroughputSampler(Arc::new(Mutex::new(SamplerState {
+            start_time,
+            interval,
+            next_sample: start_time + interval,
+            bytes: 0,
+            samples: Vec::new(),
+        })))
+    }
+
+    /// Adds `bytes` to the total and takes a sample if one is due.
+    fn record(&self, bytes: usize) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        state.bytes += bytes as u64;
+        let now = Instant::now();
+        if now >= state.next_sample {
+            let sample = ThroughputSample {
+                elapsed: now - state.start_time,
+                bytes: state.bytes,
+            };
+            state.samples.push(sample);
+            state.next_sample = now + state.interval;
+        }
+    }
+
+    /// Returns the samples taken, followed by the current total if it was not sampled yet.
+    fn samples(&self) -> Vec<ThroughputSample> {
+        let state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut samples = state.samples.clone();
+        if samples.last().map_or(0, |sample| sample.bytes) < state.bytes {
+            samples.push(ThroughputSample {
+                elapsed: state.start_time.elapsed(),
+                bytes: state.bytes,
+            });
+        }
+        samples
+    }
+}
+
+/// A reader that counts the bytes read through it for the throughput series of a
+/// [`StatsTimer`]. Created by [`StatsTimer::sample_reader`].
+pub struct SampledReader<R> {
+    inner: R,
+    /// `None` if statistics are disabled.
+    sampler: Option<ThroughputSampler>,
+}
+
+impl<R> SampledReader<R> {
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for SampledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(sampler) = &self.sampler {
+            sampler.record(read);
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for SampledReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
 }
 
 /// Builder for constructing [`CompressionStats`] using the method chaining pattern.
@@ -499,6 +746,23 @@ pub struct CompressionStatsBuilder {
     duration: Option<Duration>,
     is_compression: Option<bool>,
     sections: Vec<SectionStats>,
+    peak_memory: Option<usize>,
+    throughput: Vec<ThroughputSample>,
+}
+
+/// The raw inputs of [`CompressionStats`], once [`CompressionStatsBuilder::build`] has
+/// checked that every mandatory field is set.
+struct RawStats {
+    algorithm_name: &'static str,
+    algorithm_id: u8,
+    version_used: u8,
+    original_len: usize,
+    processed_len: usize,
+    duration: Duration,
+    is_compression: bool,
+    sections: Vec<SectionStats>,
+    peak_memory: Option<usize>,
+    throughput: Vec<ThroughputSample>,
 }
 
 impl CompressionStats {
@@ -506,16 +770,19 @@ impl CompressionStats {
     ///
     /// This method is called by [`CompressionStatsBuilder::build`] after all
     /// mandatory fields have been verified.
-    fn calculate_stats(
-        algorithm_name: &'static str,
-        algorithm_id: u8,
-        version_used: u8,
-        original_len: usize,
-        processed_len: usize,
-        duration: Duration,
-        is_compression: bool,
-        sections: Vec<SectionStats>,
-    ) -> Self {
+    fn calculate_stats(raw: RawStats) -> Self {
+        let RawStats {
+            algorithm_name,
+            algorithm_id,
+            version_used,
+            original_len,
+            processed_len,
+            duration,
+            is_compression,
+            sections,
+            peak_memory,
+            throughput,
+        } = raw;
         // --- LOGIC REMAINS UNCHANGED ---
         let (uncompressed_len, compressed_len) = if is_compression {
             (original_len, processed_len)
@@ -529,12 +796,7 @@ impl CompressionStats {
             uncompressed_len as f64 / compressed_len as f64
         };
 
-        let duration_secs = duration.as_secs_f64();
-        let speed_mib_s = if duration_secs == 0.0 {
-            f64::INFINITY
-        } else {
-            (uncompressed_len as f64 / (1024.0 * 1024.0)) / duration_secs
-        };
+        let speed_mib_s = speed_mib_s(uncompressed_len, duration);
 
         let raw_byte_difference = uncompressed_len as i64 - compressed_len as i64;
         let difference_bytes = raw_byte_difference.abs() as usize;
@@ -546,7 +808,7 @@ impl CompressionStats {
         };
 
         CompressionStats {
-            algorithm_name,
+            algorithm_name: Cow::Borrowed(algorithm_name),
             algorithm_id,
             version_used,
             original_len,
@@ -554,6 +816,8 @@ impl CompressionStats {
             duration,
             is_compression,
             sections,
+            peak_memory,
+            throughput,
             compression_ratio_factor,
             speed_mib_s,
             raw_byte_difference,
@@ -623,6 +887,20 @@ impl CompressionStatsBuilder {
         self.sections = sections;
         self
     }
+    /// Sets the peak memory use in bytes, usually from [`StatsTimer::peak_memory`].
+    ///
+    /// This field is optional. `None` means the peak was not measured.
+    pub fn peak_memory(mut self, peak_memory: Option<usize>) -> Self {
+        self.peak_memory = peak_memory;
+        self
+    }
+    /// Sets the throughput series, usually from [`StatsTimer::throughput`].
+    ///
+    /// This field is optional.
+    pub fn throughput(mut self, throughput: Vec<ThroughputSample>) -> Self {
+        self.throughput = throughput;
+        self
+    }
     /// Adds a single [`SectionStats`] entry to the internal list of sections.
     ///
     /// This method returns `Self` to allow for convenient method chaining.
@@ -640,41 +918,84 @@ impl CompressionStatsBuilder {
     ///
     /// Returns an `Err(BuilderError)` if any required field is missing.
     pub fn build(self) -> Result<CompressionStats, BuilderError> {
-        let name = self
-            .algorithm_name
-            .ok_or_else(|| BuilderError::MissingField("algorithm_name"))?;
-        let id = self
-            .algorithm_id
-            .ok_or_else(|| BuilderError::MissingField("algorithm_id"))?;
-        let version = self
-            .version_used
-            .ok_or_else(|| BuilderError::MissingField("version_used"))?;
-        let original = self
-            .original_len
-            .ok_or_else(|| BuilderError::MissingField("original_len"))?;
-        let processed = self
-            .processed_len
-            .ok_or_else(|| BuilderError::MissingField("processed_len"))?;
-        let duration = self
-            .duration
-            .ok_or_else(|| BuilderError::MissingField("duration"))?;
-        let is_comp = self
-            .is_compression
-            .ok_or_else(|| BuilderError::MissingField("is_compression"))?;
-
-        Ok(CompressionStats::calculate_stats(
-            name,
-            id,
-            version,
-            original,
-            processed,
-            duration,
-            is_comp,
-            self.sections,
-        ))
+        Ok(CompressionStats::calculate_stats(RawStats {
+            algorithm_name: self
+                .algorithm_name
+                .ok_or_else(|| BuilderError::MissingField("algorithm_name"))?,
+            algorithm_id: self
+                .algorithm_id
+                .ok_or_else(|| BuilderError::MissingField("algorithm_id"))?,
+            version_used: self
+                .version_used
+                .ok_or_else(|| BuilderError::MissingField("version_used"))?,
+            original_len: self
+                .original_len
+                .ok_or_else(|| BuilderError::MissingField("original_len"))?,
+            processed_len: self
+                .processed_len
+                .ok_or_else(|| BuilderError::MissingField("processed_len"))?,
+            duration: self
+                .duration
+                .ok_or_else(|| BuilderError::MissingField("duration"))?,
+            is_compression: self
+                .is_compression
+                .ok_or_else(|| BuilderError::MissingField("is_compression"))?,
+            sections: self.sections,
+            peak_memory: self.peak_memory,
+            throughput: self.throughput,
+        }))
     }
 }
 
+/// The most bars [`throughput_sparkline`] draws. Longer series are thinned out.
+const SPARKLINE_WIDTH: usize = 60;
+
+/// Draws the throughput between consecutive samples as a line of bars scaled to the
+/// highest throughput, and returns it with that throughput in MiB/s.
+///
+/// Returns `None` if there are fewer than two samples, which says nothing about how the
+/// throughput changed.
+fn throughput_sparkline(samples: &[ThroughputSample]) -> Option<(String, f64)> {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    if samples.len() < 2 {
+        return None;
+    }
+
+    // The bytes are totals, so skipping samples merges their intervals.
+    let step = samples.len().div_ceil(SPARKLINE_WIDTH);
+    let mut points: Vec<ThroughputSample> = samples.iter().copied().step_by(step).collect();
+    if points.last() != samples.last() {
+        points.extend(samples.last());
+    }
+
+    let mut previous = ThroughputSample {
+        elapsed: Duration::ZERO,
+        bytes: 0,
+    };
+    let mut rates = Vec::with_capacity(points.len());
+    for point in points {
+        let secs = (point.elapsed - previous.elapsed).as_secs_f64();
+        if secs > 0.0 {
+            rates.push((point.bytes - previous.bytes) as f64 / (1024.0 * 1024.0) / secs);
+        }
+        previous = point;
+    }
+
+    let peak_rate = rates.iter().copied().fold(0.0, f64::max);
+    let sparkline = rates
+        .iter()
+        .map(|rate| {
+            let level = if peak_rate > 0.0 {
+                (rate / peak_rate * (BARS.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+            BARS[level]
+        })
+        .collect();
+    Some((sparkline, peak_rate))
+}
+
 // --- Display Trait for CompressionStats ---
 impl Display for CompressionStats {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -696,12 +1017,20 @@ impl Display for CompressionStats {
         let raw_byte_difference_abs = self.raw_byte_difference.abs() as usize;
         let (savings_label, bytes_label) = if compressed_len < uncompressed_len {
             (
-                format!("Compression Savings :  {:.2}(%)", self.percentage_change),
+                term::paint(
+                    Stream::Stdout,
+                    Style::Good,
+                    format!("Compression Savings :  {:.2}(%)", self.percentage_change),
+                ),
                 "Space Saved:".to_string(),
             )
         } else if compressed_len > uncompressed_len {
             (
-                format!("File Bloat :          {:.2}(%)", self.percentage_change),
+                term::paint(
+                    Stream::Stdout,
+                    Style::Bad,
+                    format!("File Bloat :          {:.2}(%)", self.percentage_change),
+                ),
                 "Space Wasted:".to_string(),
             )
         } else {
@@ -712,7 +1041,16 @@ impl Display for CompressionStats {
         };
 
         // --- Summary Statistics ---
-        writeln!(f, "\n--- {} Statistics 📊 ---", title_name)?;
+        let title = format!(
+            "--- {} Statistics{} ---",
+            title_name,
+            term::emoji(Stream::Stdout, " 📊")
+        );
+        writeln!(
+            f,
+            "\n{}",
+            term::paint(Stream::Stdout, Style::Highlight, title)
+        )?;
         writeln!(f, "    Algorithm name:       {}", self.algorithm_name)?;
         writeln!(f, "    Algorithm ID:           {}", self.algorithm_id)?;
         writeln!(f, "    Version Used:         {}", self.version_used)?;
@@ -750,9 +1088,27 @@ impl Display for CompressionStats {
             self.duration.as_secs_f64()
         )?;
         write!(f, "    {:<21} {:.2} MiB/s", speed_name, self.speed_mib_s)?;
+        if let Some(peak_memory) = self.peak_memory {
+            write!(
+                f,
+                "\n    Peak Memory:          {}",
+                format_bytes(peak_memory)
+            )?;
+        }
+        if let Some((sparkline, peak_rate)) = throughput_sparkline(&self.throughput) {
+            write!(
+                f,
+                "\n    Throughput Over Time: {} (Peak {:.2} MiB/s)",
+                sparkline, peak_rate
+            )?;
+        }
 
         // --- Detailed Steps (Now using the SectionStats Display trait) ---
-        writeln!(f, "\n\n--- Detailed Processing Steps ⏱️ ---")?;
+        writeln!(
+            f,
+            "\n\n--- Detailed Processing Steps{} ---",
+            term::emoji(Stream::Stdout, " ⏱️")
+        )?;
         if self.sections.is_empty() {
             writeln!(f, "    (No detailed sections recorded)")?;
         } else {
@@ -764,3 +1120,604 @@ impl Display for CompressionStats {
         Ok(())
     }
 }
+
+/// The format statistics are printed in, selected with `--stats-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsFormat {
+    /// The human-readable report of the [`Display`] implementation.
+    #[default]
+    Text,
+    /// A single line of JSON, see [`CompressionStats::to_json`].
+    Json,
+    /// A header line and a row of comma-separated values, see [`CompressionStats::to_csv`].
+    Csv,
+}
+
+impl Display for StatsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StatsFormat::Text => "text",
+            StatsFormat::Json => "json",
+            StatsFormat::Csv => "csv",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for StatsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(StatsFormat::Text),
+            "json" => Ok(StatsFormat::Json),
+            "csv" => Ok(StatsFormat::Csv),
+            _ => Err(format!(
+                "Invalid stats format '{}'. Expected text, json or csv.",
+                s
+            )),
+        }
+    }
+}
+
+/// The columns written by [`CompressionStats::to_csv`], in order.
+const CSV_HEADER: &str = "algorithm_name,algorithm_id,version_used,original_len,processed_len,\
+duration_secs,is_compression,compression_ratio_factor,speed_mib_s,raw_byte_difference,\
+percentage_change,peak_memory_bytes,sections,throughput";
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl CompressionStats {
+    /// Renders the statistics in `format`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use shared_files::stats::{CompressionStatsBuilder, StatsFormat};
+    /// use std::time::Duration;
+    ///
+    /// let stats = CompressionStatsBuilder::new()
+    ///     .algorithm_name("Fast LZ")
+    ///     .algorithm_id(4)
+    ///     .version_used(1)
+    ///     .original_len(4096)
+    ///     .processed_len(1024)
+    ///     .duration(Duration::from_millis(2))
+    ///     .is_compression(true)
+    ///     .add_section("Coding", Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let json = stats.render(StatsFormat::Json);
+    /// assert!(json.starts_with(r#"{"algorithm_name":"Fast LZ","algorithm_id":4,"#));
+    /// assert!(json.contains(r#""compression_ratio_factor":4.0"#));
+    ///
+    /// let csv = stats.render(StatsFormat::Csv);
+    /// let row = csv.lines().nth(1).unwrap();
+    /// assert!(row.starts_with("Fast LZ,4,1,4096,1024,0.002,true,4,"));
+    /// assert!(row.ends_with(",75,,Coding=0.001,"));
+    /// ```
+    pub fn render(&self, format: StatsFormat) -> String {
+        match format {
+            StatsFormat::Text => self.to_string(),
+            StatsFormat::Json => self.to_json(),
+            StatsFormat::Csv => self.to_csv(),
+        }
+    }
+
+    /// Serializes the statistics as a single line of JSON.
+    ///
+    /// Durations are written as seconds. A speed that could not be measured (the
+    /// operation took no measurable time) is written as `null`.
+    pub fn to_json(&self) -> String {
+        json::to_json_line(self)
+    }
+
+    /// Serializes the statistics as a CSV header line followed by a single row.
+    ///
+    /// The sections are joined into the last column as `name=seconds` pairs separated
+    /// by `;`, and the throughput samples into the column after it as
+    /// `elapsed_seconds=bytes` pairs. The peak memory column is empty if it was not
+    /// measured.
+    pub fn to_csv(&self) -> String {
+        let sections = self
+            .sections
+            .iter()
+            .map(|section| format!("{}={}", section.name, section.duration.as_secs_f64()))
+            .collect::<Vec<_>>()
+            .join(";");
+        let peak_memory = self
+            .peak_memory
+            .map_or(String::new(), |peak_memory| peak_memory.to_string());
+        let throughput = self
+            .throughput
+            .iter()
+            .map(|sample| format!("{}={}", sample.elapsed.as_secs_f64(), sample.bytes))
+            .collect::<Vec<_>>()
+            .join(";");
+        format!(
+            "{}\n{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            CSV_HEADER,
+            csv_field(&self.algorithm_name),
+            self.algorithm_id,
+            self.version_used,
+            self.original_len,
+            self.processed_len,
+            self.duration.as_secs_f64(),
+            self.is_compression,
+            self.compression_ratio_factor,
+            self.speed_mib_s,
+            self.raw_byte_difference,
+            self.percentage_change,
+            peak_memory,
+            csv_field(&sections),
+            throughput
+        )
+    }
+}
+
+impl CompressionStats {
+    /// Reads statistics written by [`CompressionStats::to_json`], for example with
+    /// `--stats --stats-format json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` is not a valid statistics report.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Compares these statistics (the earlier run) with `other` (the later run).
+    ///
+    /// Sections are matched by name. The durations of sections that share a name are
+    /// added up, and the speed of a section is the uncompressed size divided by its
+    /// duration.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use shared_files::stats::CompressionStatsBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let run = |processed_len, millis| {
+    ///     CompressionStatsBuilder::new()
+    ///         .algorithm_name("Fast LZ")
+    ///         .algorithm_id(4)
+    ///         .version_used(1)
+    ///         .original_len(4096)
+    ///         .processed_len(processed_len)
+    ///         .duration(Duration::from_millis(millis))
+    ///         .is_compression(true)
+    ///         .add_section("Coding", Duration::from_millis(millis))
+    ///         .build()
+    ///         .unwrap()
+    /// };
+    ///
+    /// let comparison = run(1024, 4).compare(&run(2048, 2));
+    /// assert_eq!(comparison.compression_ratio_factor.percent(), Some(-50.0));
+    /// assert_eq!(comparison.speed_mib_s.percent(), Some(100.0));
+    /// assert_eq!(comparison.sections[0].name, "Coding");
+    /// assert_eq!(comparison.sections[0].duration_secs.percent(), Some(-50.0));
+    /// ```
+    pub fn compare(&self, other: &CompressionStats) -> StatsComparison {
+        let before_sections = self.section_totals();
+        let after_sections = other.section_totals();
+
+        let sections = before_sections
+            .iter()
+            .filter_map(|(name, before)| {
+                let (_, after) = after_sections.iter().find(|(other, _)| other == name)?;
+                Some(SectionComparison {
+                    name: name.clone(),
+                    duration_secs: Change::new(before.as_secs_f64(), after.as_secs_f64()),
+                    speed_mib_s: Change::new(
+                        speed_mib_s(self.uncompressed_len(), *before),
+                        speed_mib_s(other.uncompressed_len(), *after),
+                    ),
+                })
+            })
+            .collect();
+        let missing = |sections: &[(String, Duration)], other: &[(String, Duration)]| {
+            sections
+                .iter()
+                .filter(|(name, _)| !other.iter().any(|(other, _)| other == name))
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+
+        StatsComparison {
+            before_algorithm: format!("{} v{}", self.algorithm_name, self.version_used),
+            after_algorithm: format!("{} v{}", other.algorithm_name, other.version_used),
+            compression_ratio_factor: Change::new(
+                self.compression_ratio_factor,
+                other.compression_ratio_factor,
+            ),
+            speed_mib_s: Change::new(self.speed_mib_s, other.speed_mib_s),
+            duration_secs: Change::new(self.duration.as_secs_f64(), other.duration.as_secs_f64()),
+            processed_len: Change::new(self.processed_len as f64, other.processed_len as f64),
+            peak_memory: self
+                .peak_memory
+                .zip(other.peak_memory)
+                .map(|(before, after)| Change::new(before as f64, after as f64)),
+            sections,
+            only_before: missing(&before_sections, &after_sections),
+            only_after: missing(&after_sections, &before_sections),
+        }
+    }
+
+    /// Returns the size of the uncompressed side of the operation.
+    fn uncompressed_len(&self) -> usize {
+        if self.is_compression {
+            self.original_len
+        } else {
+            self.processed_len
+        }
+    }
+
+    /// Returns the total duration of every section name, in the order the names first
+    /// appear.
+    fn section_totals(&self) -> Vec<(String, Duration)> {
+        let mut totals: Vec<(String, Duration)> = Vec::new();
+        for section in &self.sections {
+            match totals.iter_mut().find(|(name, _)| *name == section.name) {
+                Some((_, total)) => *total += section.duration,
+                None => totals.push((section.name.clone(), section.duration)),
+            }
+        }
+        totals
+    }
+}
+
+/// A value of two runs compared by [`CompressionStats::compare`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    /// The value of the earlier run.
+    pub before: f64,
+    /// The value of the later run.
+    pub after: f64,
+}
+
+impl Change {
+    /// Creates a new [`Change`] from `before` to `after`.
+    pub fn new(before: f64, after: f64) -> Self {
+        Self { before, after }
+    }
+
+    /// Returns the change relative to the earlier run in percent, or `None` if the
+    /// earlier value is zero or either value could not be measured (is infinite).
+    pub fn percent(&self) -> Option<f64> {
+        if self.before == 0.0 || !self.before.is_finite() || !self.after.is_finite() {
+            return None;
+        }
+        Some((self.after - self.before) / self.before * 100.0)
+    }
+}
+
+impl Display for Change {
+    /// Formats the change as a signed percentage such as `+4.20%`, or `n/a`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.percent() {
+            Some(percent) => write!(f, "{:+.2}%", percent),
+            None => write!(f, "n/a"),
+        }
+    }
+}
+
+/// A section present in both runs compared by [`CompressionStats::compare`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionComparison {
+    /// The name of the section.
+    pub name: String,
+    /// The total duration of the section in seconds.
+    pub duration_secs: Change,
+    /// The speed of the section in MiB/s.
+    pub speed_mib_s: Change,
+}
+
+/// The differences between two runs, returned by [`CompressionStats::compare`].
+///
+/// The `Display` implementation prints every value of both runs with the change in
+/// percent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsComparison {
+    /// The algorithm name and version of the earlier run.
+    pub before_algorithm: String,
+    /// The algorithm name and version of the later run.
+    pub after_algorithm: String,
+    /// The compression ratio factor.
+    pub compression_ratio_factor: Change,
+    /// The overall speed in MiB/s.
+    pub speed_mib_s: Change,
+    /// The total duration in seconds.
+    pub duration_secs: Change,
+    /// The size of the output in bytes.
+    pub processed_len: Change,
+    /// The peak memory use in bytes, if both runs measured it.
+    pub peak_memory: Option<Change>,
+    /// The sections present in both runs, in the order of the earlier run.
+    pub sections: Vec<SectionComparison>,
+    /// The names of the sections only the earlier run has.
+    pub only_before: Vec<String>,
+    /// The names of the sections only the later run has.
+    pub only_after: Vec<String>,
+}
+
+impl Display for StatsComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ratio = &self.compression_ratio_factor;
+        let speed = &self.speed_mib_s;
+        let duration = &self.duration_secs;
+        let processed_len = &self.processed_len;
+
+        writeln!(
+            f,
+            "\n--- Statistics Comparison{} ---",
+            term::emoji(Stream::Stdout, " 📊")
+        )?;
+        writeln!(
+            f,
+            "    Algorithm:            {} -> {}",
+            self.before_algorithm, self.after_algorithm
+        )?;
+        writeln!(
+            f,
+            "    Compression Ratio:    {:.3}:1 -> {:.3}:1 ({})",
+            ratio.before, ratio.after, ratio
+        )?;
+        writeln!(
+            f,
+            "    Speed:                {:.2} -> {:.2} MiB/s ({})",
+            speed.before, speed.after, speed
+        )?;
+        writeln!(
+            f,
+            "    Processing Time:      {:.3} -> {:.3} seconds ({})",
+            duration.before, duration.after, duration
+        )?;
+        write!(
+            f,
+            "    Processed Size:       {} -> {} ({})",
+            format_bytes(processed_len.before as usize),
+            format_bytes(processed_len.after as usize),
+            processed_len
+        )?;
+        if let Some(peak_memory) = &self.peak_memory {
+            write!(
+                f,
+                "\n    Peak Memory:          {} -> {} ({})",
+                format_bytes(peak_memory.before as usize),
+                format_bytes(peak_memory.after as usize),
+                peak_memory
+            )?;
+        }
+
+        writeln!(
+            f,
+            "\n\n--- Section Comparison{} ---",
+            term::emoji(Stream::Stdout, " ⏱️")
+        )?;
+        if self.sections.is_empty() && self.only_before.is_empty() && self.only_after.is_empty() {
+            writeln!(f, "    (No detailed sections recorded)")?;
+        }
+        for section in &self.sections {
+            writeln!(
+                f,
+                "    - {:<30} {:.3} -> {:.3} seconds ({}), {:.2} -> {:.2} MiB/s ({})",
+                section.name,
+                section.duration_secs.before,
+                section.duration_secs.after,
+                section.duration_secs,
+                section.speed_mib_s.before,
+                section.speed_mib_s.after,
+                section.speed_mib_s
+            )?;
+        }
+        for name in &self.only_before {
+            writeln!(f, "    - {:<30} (only in the earlier run)", name)?;
+        }
+        for name in &self.only_after {
+            writeln!(f, "    - {:<30} (only in the later run)", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of files listed in the "Slowest Files" section of the text report.
+const SLOWEST_SHOWN: usize = 5;
+
+/// The statistics of a single file within an [`AggregateStats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct FileStats {
+    /// The name of the file, usually its path.
+    pub name: String,
+    /// The statistics of the operation on the file.
+    #[serde(flatten)]
+    pub stats: CompressionStats,
+}
+
+/// Accumulates the [`CompressionStats`] of many files, for example the files of a batch
+/// run, and summarizes them.
+///
+/// The ratio and the speed are weighted by size: the ratio is the total uncompressed
+/// length over the total compressed length, so a large file counts more than a small one,
+/// and the speed is the total uncompressed length over the total time.
+///
+/// # Example
+///
+/// ```rust
+/// use shared_files::stats::{AggregateStats, CompressionStatsBuilder};
+/// use std::time::Duration;
+///
+/// let mut aggregate = AggregateStats::new();
+/// for (name, original_len, processed_len, millis) in
+///     [("a.log", 3000, 1000, 30), ("b.log", 1000, 1000, 10)]
+/// {
+///     let stats = CompressionStatsBuilder::new()
+///         .algorithm_name("Fast LZ")
+///         .algorithm_id(4)
+///         .version_used(1)
+///         .original_len(original_len)
+///         .processed_len(processed_len)
+///         .duration(Duration::from_millis(millis))
+///         .is_compression(true)
+///         .build()
+///         .unwrap();
+///     aggregate.add(name, stats);
+/// }
+///
+/// assert_eq!(aggregate.files.len(), 2);
+/// assert_eq!(aggregate.total_uncompressed_len, 4000);
+/// assert_eq!(aggregate.total_compressed_len, 2000);
+/// assert_eq!(aggregate.compression_ratio_factor, 2.0);
+/// assert_eq!(aggregate.slowest(1)[0].name, "a.log");
+/// ```
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AggregateStats {
+    /// The statistics of every file, in the order they were added.
+    pub files: Vec<FileStats>,
+    /// The sum of the uncompressed lengths of all files (in bytes).
+    pub total_uncompressed_len: usize,
+    /// The sum of the compressed lengths of all files (in bytes).
+    pub total_compressed_len: usize,
+    /// The sum of the processing times of all files.
+    #[serde(rename = "total_duration_secs", serialize_with = "serialize_secs")]
+    pub total_duration: Duration,
+    /// The largest peak memory use of any file (in bytes), if it was measured.
+    #[serde(rename = "peak_memory_bytes")]
+    pub peak_memory: Option<usize>,
+
+    // --- Calculated Fields ---
+    /// The compression ratio over all files, `total_uncompressed_len / total_compressed_len`.
+    pub compression_ratio_factor: f64,
+    /// The overall speed in Mebibytes per second (MiB/s), based on the uncompressed length.
+    pub speed_mib_s: f64,
+}
+
+impl AggregateStats {
+    /// Creates an empty summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the statistics of the file `name` and updates the totals.
+    pub fn add(&mut self, name: &str, stats: CompressionStats) {
+        let (uncompressed_len, compressed_len) = if stats.is_compression {
+            (stats.original_len, stats.processed_len)
+        } else {
+            (stats.processed_len, stats.original_len)
+        };
+        self.total_uncompressed_len += uncompressed_len;
+        self.total_compressed_len += compressed_len;
+        self.total_duration += stats.duration;
+        self.peak_memory = self.peak_memory.max(stats.peak_memory);
+        self.files.push(FileStats {
+            name: name.to_string(),
+            stats,
+        });
+
+        self.compression_ratio_factor = if self.total_compressed_len == 0 {
+            0.0
+        } else {
+            self.total_uncompressed_len as f64 / self.total_compressed_len as f64
+        };
+        self.speed_mib_s = speed_mib_s(self.total_uncompressed_len, self.total_duration);
+    }
+
+    /// Returns up to `count` files that took the longest, the slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<&FileStats> {
+        let mut files: Vec<&FileStats> = self.files.iter().collect();
+        files.sort_by_key(|file| Reverse(file.stats.duration));
+        files.truncate(count);
+        files
+    }
+
+    /// Renders the summary in `format`.
+    ///
+    /// JSON contains the totals and the statistics of every file. CSV has a row per file
+    /// with the columns of [`CompressionStats::to_csv`] and the file name in front.
+    pub fn render(&self, format: StatsFormat) -> String {
+        match format {
+            StatsFormat::Text => self.to_string(),
+            StatsFormat::Json => json::to_json_line(self),
+            StatsFormat::Csv => {
+                let mut csv = format!("name,{}", CSV_HEADER);
+                for file in &self.files {
+                    // Skip the header line of the single file CSV.
+                    let row = file.stats.to_csv();
+                    let row = row.split_once('\n').map_or("", |(_, row)| row);
+                    csv.push_str(&format!("\n{},{}", csv_field(&file.name), row));
+                }
+                csv
+            }
+        }
+    }
+}
+
+impl Display for AggregateStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "\n--- Aggregate Statistics{} ---",
+            term::emoji(Stream::Stdout, " 📊")
+        )?;
+        writeln!(f, "    Files:                {}", self.files.len())?;
+        writeln!(
+            f,
+            "    Uncompressed Size:    {}",
+            format_bytes(self.total_uncompressed_len)
+        )?;
+        writeln!(
+            f,
+            "    Compressed Size:      {}",
+            format_bytes(self.total_compressed_len)
+        )?;
+        writeln!(
+            f,
+            "    Compression Ratio:    {:.3}:1 (Weighted by Size)",
+            self.compression_ratio_factor
+        )?;
+        writeln!(
+            f,
+            "    Processing Time:      {:.3} seconds",
+            self.total_duration.as_secs_f64()
+        )?;
+        write!(f, "    Throughput:           {:.2} MiB/s", self.speed_mib_s)?;
+        if let Some(peak_memory) = self.peak_memory {
+            write!(
+                f,
+                "\n    Peak Memory:          {}",
+                format_bytes(peak_memory)
+            )?;
+        }
+
+        writeln!(
+            f,
+            "\n\n--- Slowest Files{} ---",
+            term::emoji(Stream::Stdout, " ⏱️")
+        )?;
+        if self.files.is_empty() {
+            writeln!(f, "    (No files recorded)")?;
+        } else {
+            for file in self.slowest(SLOWEST_SHOWN) {
+                writeln!(
+                    f,
+                    "    - {:<30} {:.3} seconds ({:.3}:1, {:.2} MiB/s)",
+                    file.name,
+                    file.stats.duration.as_secs_f64(),
+                    file.stats.compression_ratio_factor,
+                    file.stats.speed_mib_s
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Colors and interactive output on the terminal.
+//!
+//! The core and the modules color what they print for people through [`paint`]: errors in
+//! red, warnings in yellow, and ratios in green for savings or red for bloat, through
+//! [`ratio`]. Whether a stream gets colors is decided here, so every part of PurgePack
+//! agrees on it:
+//!
+//! 1. `--color <auto|always|never>` of the core, which it hands on in
+//!    [`COLOR`](crate::overrides::COLOR) (`PURGEPACK_COLOR`),
+//! 2. `NO_COLOR`, which turns colors off if it is set and not empty (see
+//!    <https://no-color.org>),
+//! 3. `auto`: colors only if the stream is a terminal and `TERM` is not `dumb`.
+//!
+//! Progress bars, emoji and prompts are only for people at a terminal, so they depend on
+//! [`interactive`]: a stream is interactive if it is a terminal and `TERM` is not `dumb`,
+//! which leaves them out in CI jobs, cron jobs and pipes.
+//! [`INTERACTIVE`](crate::overrides::INTERACTIVE) (`PURGEPACK_INTERACTIVE`) turns them on
+//! or off regardless.
+//!
+//! ```rust
+//! use shared_files::term::{self, Stream, Style};
+//!
+//! // Colors are decided per stream, so a pipe gets plain text.
+//! println!("{}", term::paint(Stream::Stdout, Style::Good, "done"));
+//! ```
+
+use crate::overrides;
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    str::FromStr,
+};
+
+/// When colors are used, from `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Colors on terminals only.
+    #[default]
+    Auto,
+    /// Colors even into files and pipes.
+    Always,
+    /// No colors at all.
+    Never,
+}
+
+impl Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        })
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "Invalid color choice '{}'. Expected auto, always or never.",
+                s
+            )),
+        }
+    }
+}
+
+/// A stream text is printed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// Standard output, which gets the results.
+    Stdout,
+    /// Standard error, which gets the log and the progress.
+    Stderr,
+}
+
+impl Stream {
+    /// Returns `true` if the stream is a terminal other than a dumb one.
+    pub fn is_terminal(self) -> bool {
+        let terminal = match self {
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        };
+        terminal && env::var_os("TERM").is_none_or(|term| term != "dumb")
+    }
+}
+
+/// What a piece of text means, which decides its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// A failure, in red.
+    Error,
+    /// A warning or something left out, in yellow.
+    Warn,
+    /// A success or a saving, in green.
+    Good,
+    /// A loss, like an output larger than its input, in red.
+    Bad,
+    /// A heading or a value worth a look, in bold.
+    Highlight,
+    /// A detail of little interest, dimmed.
+    Dim,
+}
+
+impl Style {
+    /// Returns the SGR escape sequence starting the style.
+    fn code(self) -> &'static str {
+        match self {
+            Style::Error | Style::Bad => "\x1b[31m",
+            Style::Warn => "\x1b[33m",
+            Style::Good => "\x1b[32m",
+            Style::Highlight => "\x1b[1m",
+            Style::Dim => "\x1b[2m",
+        }
+    }
+}
+
+/// The escape sequence ending a style.
+const RESET: &str = "\x1b[0m";
+
+/// Returns the color choice in effect: [`COLOR`](crate::overrides::COLOR), or `never`
+/// if `NO_COLOR` is set, or `auto`.
+///
+/// An invalid [`COLOR`](crate::overrides::COLOR) counts as `auto`; the core rejects it
+/// when it starts.
+pub fn choice() -> ColorChoice {
+    if let Some(choice) = overrides::var(overrides::COLOR) {
+        return choice.parse().unwrap_or_default();
+    }
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return ColorChoice::Never;
+    }
+    ColorChoice::Auto
+}
+
+/// Returns `true` if text printed to `stream` gets colors.
+pub fn colored(stream: Stream) -> bool {
+    match choice() {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => stream.is_terminal(),
+    }
+}
+
+/// Returns `true` if a person is likely watching `stream`, so progress bars, emoji and
+/// prompts are in place: [`INTERACTIVE`](crate::overrides::INTERACTIVE) if it is set, or
+/// else whether `stream` is a terminal.
+pub fn interactive(stream: Stream) -> bool {
+    match overrides::var(overrides::INTERACTIVE) {
+        Some(value) => overrides::is_on(&value),
+        None => stream.is_terminal(),
+    }
+}
+
+/// Returns `emoji`, or an empty string if `stream` is not [`interactive`]. Put the space
+/// in front of an emoji into `emoji`, so nothing is left over without it.
+pub fn emoji(stream: Stream, emoji: &'static str) -> &'static str {
+    if interactive(stream) { emoji } else { "" }
+}
+
+/// Returns `text` in `style`, or as it is if `stream` gets no colors.
+///
+/// Pad the text before painting it, since the escape sequences count for the width.
+pub fn paint(stream: Stream, style: Style, text: impl Display) -> String {
+    if colored(stream) {
+        format!("{}{}{}", style.code(), text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Returns `text` about an output of `percent` of its input in green for a saving, red
+/// for bloat, or as it is if the size did not change.
+pub fn ratio(stream: Stream, percent: f64, text: impl Display) -> String {
+    if percent < 100.0 {
+        paint(stream, Style::Good, text)
+    } else if percent > 100.0 {
+        paint(stream, Style::Bad, text)
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,137 @@
+//! LEB128 varints and zigzag coding of signed values.
+//!
+//! A varint stores an unsigned value in 7 bits per byte, least significant group first,
+//! with the high bit set on every byte except the last, so small values take a single
+//! byte. Signed values are zigzag-mapped first, so small negative values stay small too:
+//!
+//! ```rust
+//! use shared_files::varint::{self, read_varint, write_varint, zigzag_decode, zigzag_encode};
+//!
+//! let mut encoded = Vec::new();
+//! for value in [0i64, -1, 63, -64, 300] {
+//!     write_varint(&mut encoded, zigzag_encode(value)).unwrap();
+//! }
+//! assert_eq!(encoded, [0x00, 0x01, 0x7E, 0x7F, 0xD8, 0x04]);
+//!
+//! let mut reader = encoded.as_slice();
+//! for value in [0i64, -1, 63, -64, 300] {
+//!     assert_eq!(zigzag_decode(read_varint(&mut reader).unwrap()), value);
+//! }
+//! assert_eq!(varint::encoded_len(300), 2);
+//! ```
+
+use crate::error::PurgePackError;
+use std::io::{self, Read, Write};
+
+/// The longest encoding of a `u64`, in bytes.
+pub const MAX_LEN: usize = 10;
+
+/// Maps a signed value to an unsigned one so that values close to zero (positive or
+/// negative) become small numbers: 0 -> 0, -1 -> 1, 1 -> 2, -2 -> 3, ...
+///
+/// ```rust
+/// use shared_files::varint::zigzag_encode;
+///
+/// assert_eq!(zigzag_encode(0), 0);
+/// assert_eq!(zigzag_encode(-1), 1);
+/// assert_eq!(zigzag_encode(1), 2);
+/// assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+/// ```
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Reverses [`zigzag_encode`].
+///
+/// ```rust
+/// use shared_files::varint::{zigzag_decode, zigzag_encode};
+///
+/// for value in [0i64, -1, 1, -64, 63, i64::MIN, i64::MAX] {
+///     assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+/// }
+/// ```
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Returns the number of bytes [`write_varint`] writes for `value`.
+///
+/// ```rust
+/// use shared_files::varint::encoded_len;
+///
+/// assert_eq!(encoded_len(0), 1);
+/// assert_eq!(encoded_len(127), 1);
+/// assert_eq!(encoded_len(128), 2);
+/// assert_eq!(encoded_len(u64::MAX), 10);
+/// ```
+pub fn encoded_len(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).div_ceil(7).max(1)
+}
+
+/// Writes `value` as an unsigned LEB128 varint.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if writing fails.
+///
+/// ```rust
+/// use shared_files::varint::write_varint;
+///
+/// let mut encoded = Vec::new();
+/// write_varint(&mut encoded, 300).unwrap();
+/// assert_eq!(encoded, [0xAC, 0x02]);
+/// ```
+pub fn write_varint<W: Write + ?Sized>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut encoded = [0u8; MAX_LEN];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            encoded[len] = byte;
+            len += 1;
+            break;
+        }
+        encoded[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&encoded[..len])
+}
+
+/// Reads an unsigned LEB128 varint written by [`write_varint`].
+///
+/// # Errors
+///
+/// Returns an `io::Error` of kind `UnexpectedEof` if the input ends inside the varint,
+/// or a [`PurgePackError::CorruptData`] error if the varint is longer than [`MAX_LEN`]
+/// bytes or does not fit into 64 bits.
+///
+/// ```rust
+/// use shared_files::varint::read_varint;
+/// use std::io;
+///
+/// let mut max = [0xFF; 10];
+/// max[9] = 0x01;
+/// assert_eq!(read_varint(&mut &max[..]).unwrap(), u64::MAX);
+/// max[9] = 0x02;
+/// assert!(read_varint(&mut &max[..]).is_err());
+/// let short = read_varint(&mut &[0x80][..]).unwrap_err();
+/// assert_eq!(short.kind(), io::ErrorKind::UnexpectedEof);
+/// ```
+pub fn read_varint<R: Read + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    let mut value: u64 = 0;
+    let mut byte = [0u8; 1];
+    for index in 0..MAX_LEN {
+        reader.read_exact(&mut byte)?;
+        let payload = (byte[0] & 0x7F) as u64;
+        // The tenth byte may only contribute the single remaining bit.
+        if index == MAX_LEN - 1 && payload > 1 {
+            break;
+        }
+        value |= payload << (7 * index);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(PurgePackError::corrupt("Invalid varint: the value does not fit in 64 bits.").into())
+}
@@ -0,0 +1,108 @@
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use shared_files::budget;
+use shared_files::cli::{self, CliError, OverwriteArgs, Validate};
+use std::path::PathBuf;
+
+/// Arguments of the `split` command.
+#[derive(Debug, Clone, Args)]
+#[command(group(ArgGroup::new("layout").required(true).args(["parts", "part_size"])))]
+pub struct SplitArgs {
+    /// The path to the file to split.
+    pub input_file: PathBuf,
+    /// The directory the parts and the manifest are written to. Defaults to the directory
+    /// of the input file.
+    pub output_dir: Option<PathBuf>,
+    /// Splits the file into this many parts of equal size (the last one may be smaller).
+    #[arg(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..=MAX_PARTS as i64))]
+    pub parts: Option<u32>,
+    /// Splits the file into parts of this size (the last one may be smaller). Accepts the
+    /// suffixes K, M and G for KiB, MiB and GiB, e.g. `25M`.
+    #[arg(short = 'b', long, value_parser = parse_part_size)]
+    pub part_size: Option<u64>,
+}
+
+/// Arguments of the `join` command.
+#[derive(Debug, Clone, Args)]
+pub struct JoinArgs {
+    /// The path to the manifest written by `split`. The parts are read from its directory.
+    pub manifest: PathBuf,
+    /// The path where the joined file will be written.
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub overwrite: OverwriteArgs,
+}
+
+/// The largest number of parts accepted by `--parts`.
+pub const MAX_PARTS: u32 = 9999;
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Splits a file into parts and writes a manifest of them.
+    #[clap(alias = "s")]
+    Split(SplitArgs),
+    /// Joins the parts listed in a manifest, verifying every part and the whole file.
+    #[clap(alias = "j")]
+    Join(JoinArgs),
+}
+
+/// The main command line argument structure for the Split Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "File Splitter and Joiner Utility.",
+    long_about = "Splits any file into a number of parts or into parts of a fixed size, with a manifest holding the order and the SHA-256 of every part, and joins them again with verification.",
+    after_help = "
+    EXAMPLES:
+    # 1. Split a compressed file into parts of at most 25 MiB for an upload limit
+    split_tool.exe split backup.ppcb --part-size 25M
+
+    # 2. Split a file into 4 parts in another directory
+    split_tool.exe s backup.ppcb parts/ --parts 4
+
+    # 3. Join the parts again
+    split_tool.exe join parts/backup.ppcb.ppsm backup.ppcb
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file or manifest exists and is a file.
+    /// 2. The output directory, or the parent directory of the output file, exists and is
+    ///    a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        match &self.command {
+            Commands::Split(args) => {
+                cli::check_input(&args.input_file)?;
+                if let Some(output_dir) = &args.output_dir {
+                    cli::check_dir(output_dir)?;
+                }
+            }
+            Commands::Join(args) => {
+                cli::check_input(&args.manifest)?;
+                cli::check_output(&args.output_file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a part size with an optional K, M or G suffix.
+fn parse_part_size(value: &str) -> Result<u64, String> {
+    match budget::parse_size(value)? {
+        0 => Err("the part size must be at least 1 byte".to_string()),
+        size => Ok(size),
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
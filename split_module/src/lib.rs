@@ -0,0 +1,440 @@
+//! Splitting files into parts and joining them again.
+//!
+//! `split` cuts a file into a given number of parts or into parts of a given size, named
+//! after the file with a counter (`backup.ppcb.001`, `backup.ppcb.002`, ...), and writes
+//! a manifest (`backup.ppcb.ppsm`) listing the parts in order with their SHA-256 (see the
+//! `manifest` module). `join` checks that every part is present and intact while it
+//! concatenates them, and checks the joined file against the SHA-256 of the original.
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+mod cli_parse;
+mod manifest;
+use manifest::{Entry, Manifest};
+use sha2::{Digest, Sha256};
+use shared_files::abi;
+use shared_files::cli::{self, CliError};
+use shared_files::core_header::{self};
+use shared_files::output::SafeWriter;
+use shared_files::progress::{self, ProgressReader};
+use shared_files::{log_debug, log_error, log_info};
+use std::ffi::c_char;
+
+/// The file extension for PurgePack split manifests (PPSM).
+const MANIFEST_EXTENSION: &str = "ppsm";
+/// The smallest number of digits in the counter of a part name.
+const MIN_COUNTER_DIGITS: usize = 3;
+/// The size of the buffer the data is copied through.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("split", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Split(args) => {
+                log_info!("Split: Input: {}", args.input_file.display());
+                match split_file(&args) {
+                    Ok(()) => log_info!("Split: Success"),
+                    Err(e) => {
+                        log_error!("Split: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Join(args) => {
+                log_info!(
+                    "Join: Manifest: {}, Output: {}",
+                    args.manifest.display(),
+                    args.output_file.display()
+                );
+                match args
+                    .overwrite
+                    .policy()
+                    .resolve(&args.output_file)
+                    .and_then(|output_file| join_file(&args.manifest, &output_file))
+                {
+                    Ok(()) => log_info!("Join: Success"),
+                    Err(e) => {
+                        log_error!("Join: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("split", core.log_level);
+    log_debug!("Split module shutting down.");
+}
+
+/// Returns `bytes` as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Copies exactly `len` bytes from `reader` to `writer`, feeding them to every hasher.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, and an `UnexpectedEof` error if
+/// `reader` ends early.
+fn copy_hashed<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    len: u64,
+    buffer: &mut [u8],
+    hashers: &mut [&mut Sha256],
+) -> io::Result<()> {
+    let mut remaining = len;
+    while remaining > 0 {
+        let want = buffer.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let read = reader.read(&mut buffer[..want])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        for hasher in hashers.iter_mut() {
+            hasher.update(&buffer[..read]);
+        }
+        writer.write_all(&buffer[..read])?;
+        remaining -= read as u64;
+    }
+    Ok(())
+}
+
+/// Splits the input file of `args` into parts and writes their manifest.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or if the input file shrinks
+/// while it is split.
+fn split_file(args: &cli_parse::SplitArgs) -> io::Result<()> {
+    let input = File::open(&args.input_file)?;
+    let len = input.metadata()?.len();
+    let mut reader = BufReader::new(ProgressReader::new(
+        input,
+        progress::terminal("Split", Some(len)),
+    ));
+    let file_name = args
+        .input_file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output_dir = match &args.output_dir {
+        Some(output_dir) => output_dir.as_path(),
+        None => args.input_file.parent().unwrap_or(Path::new("")),
+    };
+
+    let part_len = match (args.parts, args.part_size) {
+        (Some(parts), _) => len.div_ceil(parts as u64).max(1),
+        (None, Some(part_size)) => part_size,
+        (None, None) => unreachable!("clap requires --parts or --part-size"),
+    };
+    let count = len.div_ceil(part_len).max(1);
+    if let Some(parts) = args.parts
+        && count < parts as u64
+    {
+        log_info!(
+            "Split: The file only has {} bytes, so it is split into {} parts.",
+            len,
+            count
+        );
+    }
+    let digits = MIN_COUNTER_DIGITS.max(count.to_string().len());
+
+    let mut manifest = Manifest::default();
+    let mut file_hasher = Sha256::new();
+    let mut buffer = vec![0u8; BUFFER_SIZE.min(part_len.try_into().unwrap_or(usize::MAX))];
+    for index in 1..=count {
+        let name = format!("{}.{:0digits$}", file_name, index, digits = digits);
+        let this_len = part_len.min(len - (index - 1) * part_len);
+        let mut part_hasher = Sha256::new();
+        let mut writer = BufWriter::new(SafeWriter::create(&output_dir.join(&name))?);
+        copy_hashed(
+            &mut reader,
+            &mut writer,
+            this_len,
+            &mut buffer,
+            &mut [&mut file_hasher, &mut part_hasher],
+        )
+        .map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                io::Error::new(e.kind(), "The input file shrank while it was split.")
+            } else {
+                e
+            }
+        })?;
+        writer.into_inner()?.commit()?;
+        log_info!("Split: Wrote {} ({} bytes)", name, this_len);
+        manifest.parts.push(Entry {
+            name,
+            len: this_len,
+            sha256: hex(&part_hasher.finalize()),
+        });
+    }
+    manifest.file = Some(Entry {
+        name: file_name.clone(),
+        len,
+        sha256: hex(&file_hasher.finalize()),
+    });
+
+    let manifest_path = output_dir.join(format!("{}.{}", file_name, MANIFEST_EXTENSION));
+    manifest.write(&manifest_path)?;
+    log_info!("Split: Manifest written to {}", manifest_path.display());
+    Ok(())
+}
+
+/// Joins the parts listed in `manifest_path` into `output_file`.
+///
+/// Every part is checked for presence first, then for its length and SHA-256 while it is
+/// copied, and the joined file is checked against the SHA-256 of the original. The output
+/// is written through a [`SafeWriter`], so it only appears if every check passes.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, a `NotFound` error listing the
+/// missing parts, and an `InvalidData` error if the manifest is malformed or a part or
+/// the joined file does not match it.
+fn join_file(manifest_path: &Path, output_file: &Path) -> io::Result<()> {
+    let manifest = Manifest::parse(&fs::read_to_string(manifest_path)?)?;
+    let parts_dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let missing: Vec<&str> = manifest
+        .parts
+        .iter()
+        .filter(|part| !parts_dir.join(&part.name).is_file())
+        .map(|part| part.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Missing parts: {}", missing.join(", ")),
+        ));
+    }
+
+    let mut writer = BufWriter::new(SafeWriter::create(output_file)?);
+    join_parts(&manifest, parts_dir, &mut writer)?;
+    writer.into_inner()?.commit()?;
+    Ok(())
+}
+
+/// Copies the parts of `manifest` from `parts_dir` to `writer`, checking every one.
+fn join_parts<W: Write>(manifest: &Manifest, parts_dir: &Path, writer: &mut W) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut file_hasher = Sha256::new();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    for part in &manifest.parts {
+        let input = File::open(parts_dir.join(&part.name))?;
+        let actual_len = input.metadata()?.len();
+        if actual_len != part.len {
+            return Err(invalid(format!(
+                "Part {} has {} bytes, but {} were expected.",
+                part.name, actual_len, part.len
+            )));
+        }
+        let mut part_hasher = Sha256::new();
+        copy_hashed(
+            &mut BufReader::new(input),
+            writer,
+            part.len,
+            &mut buffer,
+            &mut [&mut file_hasher, &mut part_hasher],
+        )?;
+        if hex(&part_hasher.finalize()) != part.sha256 {
+            return Err(invalid(format!(
+                "Part {} is corrupted: its SHA-256 does not match the manifest.",
+                part.name
+            )));
+        }
+        log_info!("Join: Verified {} ({} bytes)", part.name, part.len);
+    }
+    writer.flush()?;
+
+    let file = manifest
+        .file
+        .as_ref()
+        .expect("parse requires the file line");
+    let joined_len: u64 = manifest.parts.iter().map(|part| part.len).sum();
+    if joined_len != file.len || hex(&file_hasher.finalize()) != file.sha256 {
+        return Err(invalid(format!(
+            "The joined data does not match {}: the manifest may list the wrong parts.",
+            file.name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, process};
+
+    /// A directory in the temporary directory of the system, removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("purgepack-split-{}-{}", process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            Dir(dir)
+        }
+
+        /// Writes `data` to `data.bin`, splits it into parts of `part_size` bytes and
+        /// returns the path of the manifest.
+        fn split(&self, data: &[u8], part_size: u64) -> PathBuf {
+            let input_file = self.0.join("data.bin");
+            fs::write(&input_file, data).unwrap();
+            split_file(&cli_parse::SplitArgs {
+                input_file,
+                output_dir: None,
+                parts: None,
+                part_size: Some(part_size),
+            })
+            .unwrap();
+            self.0.join("data.bin.ppsm")
+        }
+
+        fn part(&self, index: usize) -> PathBuf {
+            self.0.join(format!("data.bin.{:03}", index))
+        }
+
+        fn output(&self) -> PathBuf {
+            self.0.join("joined.bin")
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    /// Joins the parts of `manifest` and returns the kind of the error, checking that no
+    /// output was left behind.
+    fn join_error(dir: &Dir, manifest: &Path) -> io::ErrorKind {
+        let error = join_file(manifest, &dir.output()).unwrap_err();
+        assert!(!dir.output().exists(), "{}", error);
+        error.kind()
+    }
+
+    #[test]
+    fn joins_the_parts_into_the_original_file() {
+        let dir = Dir::new("round-trip");
+        for (len, part_size) in [(0, 10), (1, 10), (10, 10), (10_000, 1000), (10_001, 1000)] {
+            let original = data(len);
+            let manifest = dir.split(&original, part_size);
+            let parts = len.div_ceil(part_size as usize).max(1);
+            assert!(dir.part(parts).is_file());
+            assert!(!dir.part(parts + 1).exists());
+            join_file(&manifest, &dir.output()).unwrap();
+            assert_eq!(fs::read(dir.output()).unwrap(), original, "{} bytes", len);
+            for index in 1..=parts {
+                fs::remove_file(dir.part(index)).unwrap();
+            }
+            fs::remove_file(dir.output()).unwrap();
+        }
+    }
+
+    #[test]
+    fn splits_into_the_given_number_of_parts() {
+        let dir = Dir::new("parts");
+        let original = data(1000);
+        let input_file = dir.0.join("data.bin");
+        fs::write(&input_file, &original).unwrap();
+        split_file(&cli_parse::SplitArgs {
+            input_file,
+            output_dir: None,
+            parts: Some(3),
+            part_size: None,
+        })
+        .unwrap();
+        let lens: Vec<u64> = (1..=3)
+            .map(|index| fs::metadata(dir.part(index)).unwrap().len())
+            .collect();
+        assert_eq!(lens, [334, 334, 332]);
+        join_file(&dir.0.join("data.bin.ppsm"), &dir.output()).unwrap();
+        assert_eq!(fs::read(dir.output()).unwrap(), original);
+    }
+
+    #[test]
+    fn refuses_to_join_with_a_missing_part() {
+        let dir = Dir::new("missing");
+        let manifest = dir.split(&data(3000), 1000);
+        fs::remove_file(dir.part(2)).unwrap();
+        let error = join_file(&manifest, &dir.output()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("data.bin.002"), "{}", error);
+        assert!(!dir.output().exists());
+    }
+
+    #[test]
+    fn refuses_to_join_reordered_parts() {
+        let dir = Dir::new("reordered");
+        let manifest = dir.split(&data(3000), 1000);
+
+        // Parts swapped on disk no longer match their entries in the manifest.
+        let first = fs::read(dir.part(1)).unwrap();
+        let second = fs::read(dir.part(2)).unwrap();
+        fs::write(dir.part(1), &second).unwrap();
+        fs::write(dir.part(2), &first).unwrap();
+        assert_eq!(join_error(&dir, &manifest), io::ErrorKind::InvalidData);
+        fs::write(dir.part(1), &first).unwrap();
+        fs::write(dir.part(2), &second).unwrap();
+
+        // Parts swapped in the manifest each match, but the joined file does not.
+        let text = fs::read_to_string(&manifest).unwrap();
+        let mut lines: Vec<&str> = text.lines().collect();
+        let first_part = lines
+            .iter()
+            .position(|line| line.starts_with("part "))
+            .unwrap();
+        lines.swap(first_part, first_part + 1);
+        fs::write(&manifest, lines.join("\n")).unwrap();
+        assert_eq!(join_error(&dir, &manifest), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn refuses_to_join_a_corrupted_part() {
+        let dir = Dir::new("corrupted");
+        let manifest = dir.split(&data(3000), 1000);
+        let part = fs::read(dir.part(2)).unwrap();
+
+        let mut flipped = part.clone();
+        flipped[500] ^= 0x01;
+        fs::write(dir.part(2), &flipped).unwrap();
+        assert_eq!(join_error(&dir, &manifest), io::ErrorKind::InvalidData);
+
+        fs::write(dir.part(2), &part[..999]).unwrap();
+        assert_eq!(join_error(&dir, &manifest), io::ErrorKind::InvalidData);
+
+        fs::write(dir.part(2), &part).unwrap();
+        join_file(&manifest, &dir.output()).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+//! The split manifest: a text file listing the original file and its parts in order.
+//!
+//! ```text
+//! # PurgePack split manifest
+//! version 1
+//! file notes.txt 1048600 <SHA-256 of the whole file>
+//! part notes.txt.001 524288 <SHA-256 of the part>
+//! part notes.txt.002 524288 <SHA-256 of the part>
+//! part notes.txt.003 24 <SHA-256 of the part>
+//! ```
+//!
+//! Part names are file names in the directory of the manifest, so the manifest and its
+//! parts can be moved together. Lines starting with `#` are comments.
+
+use shared_files::output::SafeWriter;
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// The version of the manifest layout written by this build.
+const MANIFEST_VERSION: u32 = 1;
+
+/// A file and its SHA-256, as listed in the manifest.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    /// The file name.
+    pub(crate) name: String,
+    /// The length in bytes.
+    pub(crate) len: u64,
+    /// The SHA-256 as lowercase hex.
+    pub(crate) sha256: String,
+}
+
+/// The original file and its parts.
+#[derive(Debug, Default)]
+pub(crate) struct Manifest {
+    /// The original file.
+    pub(crate) file: Option<Entry>,
+    /// The parts, in the order they are joined.
+    pub(crate) parts: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Writes the manifest to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if writing fails.
+    pub(crate) fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(SafeWriter::create(path)?);
+        writeln!(writer, "# PurgePack split manifest")?;
+        writeln!(writer, "version {}", MANIFEST_VERSION)?;
+        if let Some(file) = &self.file {
+            writeln!(writer, "file {} {} {}", file.name, file.len, file.sha256)?;
+        }
+        for part in &self.parts {
+            writeln!(writer, "part {} {} {}", part.name, part.len, part.sha256)?;
+        }
+        writer.into_inner()?.commit()?;
+        Ok(())
+    }
+
+    /// Parses the text of a manifest.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error naming the first malformed line, or if the manifest
+    /// uses a newer version, lacks the `file` line or lists no parts.
+    pub(crate) fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut manifest = Manifest::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || invalid(format!("Line {} of the manifest is malformed.", index + 1));
+            let (keyword, rest) = line.split_once(' ').ok_or_else(malformed)?;
+            match keyword {
+                "version" => {
+                    let version: u32 = rest.parse().map_err(|_| malformed())?;
+                    if version == 0 || version > MANIFEST_VERSION {
+                        return Err(invalid(format!(
+                            "Unsupported manifest version {}. This build supports up to version {}.",
+                            version, MANIFEST_VERSION
+                        )));
+                    }
+                }
+                "file" if manifest.file.is_none() => {
+                    manifest.file = Some(parse_entry(rest).ok_or_else(malformed)?);
+                }
+                "part" => manifest
+                    .parts
+                    .push(parse_entry(rest).ok_or_else(malformed)?),
+                _ => return Err(malformed()),
+            }
+        }
+        if manifest.file.is_none() || manifest.parts.is_empty() {
+            return Err(invalid(
+                "The manifest must name the original file and at least one part.".to_string(),
+            ));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Parses `<name> <length> <sha256>`. The name may contain spaces, so the other two
+/// fields are taken from the end.
+fn parse_entry(fields: &str) -> Option<Entry> {
+    let (rest, sha256) = fields.rsplit_once(' ')?;
+    let (name, len) = rest.rsplit_once(' ')?;
+    let is_sha256 = sha256.len() == 64 && sha256.bytes().all(|byte| byte.is_ascii_hexdigit());
+    // A part name with a path separator could point outside the manifest's directory.
+    let is_plain_name = !name.is_empty() && !name.contains(['/', '\\']) && name != "..";
+    if !is_sha256 || !is_plain_name {
+        return None;
+    }
+    Some(Entry {
+        name: name.to_string(),
+        len: len.parse().ok()?,
+        sha256: sha256.to_ascii_lowercase(),
+    })
+}
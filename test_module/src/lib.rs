@@ -1,11 +1,17 @@
 use shared_files::core_header::{self};
+use shared_files::log_info;
+use std::ffi::c_char;
+
+shared_files::export_module_abi!();
 
 #[unsafe(no_mangle)]
-extern "C" fn module_startup(_core: &core_header::CoreH, _args: &mut Vec<String>) {
-    println!("Hello world!");
+extern "C" fn module_startup(core: &core_header::CoreH, _argc: usize, _argv: *const *const c_char) {
+    shared_files::log::init("test", core.log_level);
+    log_info!("Hello world!");
 }
 
 #[unsafe(no_mangle)]
-extern "C" fn module_shutdown(_core: &core_header::CoreH) {
-    println!("Goodbye world!");
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("test", core.log_level);
+    log_info!("Goodbye world!");
 }
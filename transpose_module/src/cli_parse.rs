@@ -0,0 +1,77 @@
+use clap::{Args, Parser, Subcommand};
+use purgepack_transpose::MAX_RECORD_SIZE;
+use shared_files::cli::CommonArgs;
+use shared_files::cli::{self, CliError, Validate};
+
+/// Arguments that only apply to the forward transform.
+///
+/// The inverse transform reads these settings back from the file header.
+#[derive(Debug, Clone, Args)]
+pub struct TransformArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The size in bytes of a record. Byte N of every record ends up in column N.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..=MAX_RECORD_SIZE as i64))]
+    pub record_size: u32,
+}
+
+/// The main operations available for the utility.
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Transposes a file of fixed-size records into column-major order.
+    #[clap(alias = "t")]
+    Transform(TransformArgs),
+    /// Restores a file written by `transform`.
+    #[clap(alias = "i")]
+    Inverse(CommonArgs),
+}
+
+/// The main command line argument structure for the Transpose Utility.
+/// This delegates all responsibility to the subcommand since there are no global options.
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Columnar Transpose Utility.",
+    long_about = "A transform for arrays of fixed-size records: the input is read as a table with one record per row and written column by column, so the bytes of every field end up next to each other. Run an entropy coder or the range coder on the output.",
+    after_help = "
+    EXAMPLES:
+    # 1. Transpose an array of 16-byte structs
+    transpose_tool.exe transform samples.bin samples.ppcb --record-size 16
+
+    # 2. Transpose, show statistics and entropy-code the result
+    transpose_tool.exe t samples.bin samples.ppcb -r 16 -s
+    range_coder_tool.exe c samples.ppcb samples.pprc
+
+    # 3. Restore
+    transpose_tool.exe i samples.ppcb samples.bin
+"
+)]
+pub struct CliArgs {
+    /// The operation and its associated arguments.
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Validate for CliArgs {
+    /// Validates the command line arguments after parsing, specifically ensuring:
+    /// 1. The input file exists and is a file.
+    /// 2. The parent directory for the output file exists and is a directory.
+    fn validate(&self) -> Result<(), CliError> {
+        let common = match &self.command {
+            Commands::Transform(args) => &args.common,
+            Commands::Inverse(args) => args,
+        };
+
+        // --- Input File Validation ---
+        cli::check_input(&common.input_file)?;
+
+        // --- Output Directory Validation ---
+        cli::check_output(&common.output_file)
+    }
+}
+
+/// Allows for parsing command line arguments and validating them.
+pub fn parse_args(args: &[String]) -> Result<CliArgs, CliError> {
+    cli::parse_args(args)
+}
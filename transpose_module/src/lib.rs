@@ -0,0 +1,305 @@
+//! A columnar transpose of fixed-size records.
+//!
+//! The codec itself lives in the `purgepack_transpose` crate. This module handles the
+//! command line, the files and the statistics.
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+mod cli_parse;
+use purgepack_transpose::Transpose;
+use purgepack_transpose::{FORMAT_VERSION, MODULE_ID};
+use shared_files::abi::{self, RawSeekReader, RawSeekWriter, ServiceResult};
+use shared_files::cancel::{CancellableReader, is_cancelled_error};
+use shared_files::cli::{self, CliError};
+use shared_files::codec::{Compressor, Decompressor};
+use shared_files::core_header::{self};
+use shared_files::error::ExitStatus;
+use shared_files::events::{self, Event};
+use shared_files::output::{Overwrite, SafeWriter};
+use shared_files::progress::{self, ProgressReader};
+use shared_files::selftest::{SelfTestReport, SelfTestSink};
+use shared_files::stats::{DEFAULT_SAMPLE_INTERVAL, StatsFormat};
+use shared_files::{log_debug, log_error, log_info, log_warn};
+use std::ffi::c_char;
+
+/// The file extension for PurgePack Compressed Binary (PPCB) files.
+const FILE_EXTENSION: &str = "ppcb";
+
+shared_files::export_module_abi!();
+
+/// The main entry point for the module when it is started.
+///
+/// Parses and validates the command line, runs the requested operation and reports
+/// its result.
+#[unsafe(no_mangle)]
+extern "C" fn module_startup(core: &core_header::CoreH, argc: usize, argv: *const *const c_char) {
+    shared_files::log::init("transpose", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let mut args = unsafe { abi::args_from_c(argc, argv) };
+    args.insert(0, "dummy_program_name".to_string());
+    match cli_parse::parse_args(&args) {
+        Ok(args) => match args.command {
+            cli_parse::Commands::Transform(args) => {
+                log_info!(
+                    "Transform: Input: {}, Output: {}, Record size: {}",
+                    args.common.input_file.display(),
+                    args.common.output_file.display(),
+                    args.record_size
+                );
+                match transform_file(
+                    core,
+                    &args.common.input_file,
+                    args.common.output_file,
+                    args.record_size,
+                    args.common.stats.format(),
+                    args.common.overwrite.policy(),
+                ) {
+                    Ok(()) => log_info!("Transform: Success"),
+                    Err(e) if is_cancelled_error(&e) => {
+                        log_warn!("Transform: Cancelled");
+                        core_header::report_status(core, ExitStatus::Cancelled);
+                    }
+                    Err(e) => {
+                        log_error!("Transform: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+            cli_parse::Commands::Inverse(args) => {
+                log_info!(
+                    "Inverse: Input: {}, Output: {}",
+                    args.input_file.display(),
+                    args.output_file.display()
+                );
+                match inverse_file(
+                    core,
+                    &args.input_file,
+                    &args.output_file,
+                    args.stats.format(),
+                    args.overwrite.policy(),
+                ) {
+                    Ok(()) => log_info!("Inverse: Success"),
+                    Err(e) if is_cancelled_error(&e) => {
+                        log_warn!("Inverse: Cancelled");
+                        core_header::report_status(core, ExitStatus::Cancelled);
+                    }
+                    Err(e) => {
+                        log_error!("Inverse: {}", e);
+                        core_header::report_error(core, &e);
+                    }
+                }
+            }
+        },
+        Err(CliError::ClapError(e)) => {
+            log_error!("Error during argument parsing:\n{}", e);
+            core_header::report_status(core, cli::clap_exit_status(&e));
+        }
+        Err(e) => {
+            log_error!("Error during argument validation: {}", e);
+            core_header::report_status(core, e.exit_status());
+        }
+    }
+}
+
+/// Registers the pipeline step of the module with the core.
+#[unsafe(no_mangle)]
+extern "C" fn module_register(core: &core_header::CoreH) {
+    core_header::register_step(core, env!("CARGO_PKG_NAME"), pipeline_step);
+}
+
+/// Runs a step of a pipeline of the core, like `transform --record-size 16`, on the
+/// streams the core hands over instead of files.
+extern "C" fn pipeline_step(
+    core: &core_header::CoreH,
+    argc: usize,
+    argv: *const *const c_char,
+    input: &mut RawSeekReader,
+    output: &mut RawSeekWriter,
+    result: &mut ServiceResult,
+) -> bool {
+    shared_files::log::init("transpose", core.log_level);
+    // SAFETY: the core passes `argc` valid C strings.
+    let args = unsafe { abi::args_from_c(argc, argv) };
+    abi::serve_step(core, input, output, result, |reader, writer| {
+        let mut reader = CancellableReader::new(reader, core);
+        match cli::parse_step::<cli_parse::CliArgs>(&args)?.command {
+            cli_parse::Commands::Transform(args) => Transpose {
+                record_size: args.record_size,
+            }
+            .compress_file(&mut reader, writer),
+            // The record size is read back from the header.
+            cli_parse::Commands::Inverse(_) => {
+                Transpose { record_size: 1 }.decompress(&mut reader, writer)
+            }
+        }
+    })
+}
+
+/// The shutdown function for the module.
+#[unsafe(no_mangle)]
+extern "C" fn module_shutdown(core: &core_header::CoreH) {
+    shared_files::log::init("transpose", core.log_level);
+    log_debug!("Transpose module shutting down.");
+}
+
+/// Runs round trips of the codec on synthetic data for `purgepack test`.
+#[unsafe(no_mangle)]
+extern "C" fn module_selftest(_core: &core_header::CoreH, sink: &mut SelfTestSink) {
+    let mut report = SelfTestReport::new();
+    for record_size in [1, 4, 12] {
+        report.round_trip(
+            &format!("records of {} bytes", record_size),
+            &Transpose { record_size },
+        );
+    }
+    report.send(sink);
+}
+
+/// Transposes the records of `input_file` into `output_file`.
+///
+/// # Arguments
+///
+/// * `input_file` - The path to the source file.
+/// * `output_file` - The path to the destination file. The `.ppcb` extension is added if
+///   it has no extension.
+/// * `record_size` - The size in bytes of a record.
+/// * `stats` - The format to print statistics in, or `None` to skip them.
+/// * `overwrite` - What happens if the output file exists already.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails.
+fn transform_file(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    mut output_file: PathBuf,
+    record_size: u32,
+    stats: Option<StatsFormat>,
+    overwrite: Overwrite,
+) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
+    if output_file.extension().is_none() {
+        output_file.set_extension(FILE_EXTENSION);
+        log_info!(
+            "Transform: Automatic extension '{}' placed on output file: {}",
+            FILE_EXTENSION,
+            output_file.display()
+        );
+    }
+    let input = File::open(input_file)?;
+    let original_len = input.metadata()?.len();
+    let mut reader = BufReader::new(CancellableReader::new(
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress::terminal("Compress", Some(original_len)),
+        ),
+        core,
+    ));
+    let output_file = overwrite.resolve(&output_file)?;
+    let mut writer = BufWriter::new(SafeWriter::create(&output_file)?);
+
+    let t_coding = main_timer.start_section("Transpose");
+    purgepack_transpose::compress(&mut reader, &mut writer, original_len, record_size)?;
+    main_timer.add_section(t_coding);
+
+    if original_len % record_size as u64 != 0 {
+        log_info!(
+            "Transform: Note: The input is not a whole number of records. The last {} bytes are stored as they are.",
+            original_len % record_size as u64
+        );
+    }
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(core, main_timer, &output, original_len, true, format)?;
+    }
+    Ok(())
+}
+
+/// Restores the original data of `input_file` into `output_file`.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if reading or writing fails, or an `InvalidData` error if the
+/// header is invalid, the data is truncated or the restored data does not match the
+/// checksum.
+fn inverse_file(
+    core: &core_header::CoreH,
+    input_file: &Path,
+    output_file: &Path,
+    stats: Option<StatsFormat>,
+    overwrite: Overwrite,
+) -> io::Result<()> {
+    let mut main_timer = shared_files::stats::OptinalStatsTimer::new(stats.is_some());
+    let input = File::open(input_file)?;
+    let compressed_len = input.metadata()?.len();
+    let mut reader = BufReader::new(CancellableReader::new(
+        ProgressReader::new(
+            main_timer.sample_reader(input, DEFAULT_SAMPLE_INTERVAL),
+            progress::terminal("Decompress", Some(compressed_len)),
+        ),
+        core,
+    ));
+    let output_file = &overwrite.resolve(output_file)?;
+    let mut writer = BufWriter::new(SafeWriter::create(output_file)?);
+
+    let t_header = main_timer.start_section("Header Read");
+    let header = purgepack_transpose::read_header(&mut reader)?;
+    main_timer.add_section(t_header);
+
+    let t_decoding = main_timer.start_section("Inverse Transpose");
+    purgepack_transpose::decompress(&mut reader, &mut writer, &header)?;
+    main_timer.add_section(t_decoding);
+
+    let output = writer.into_inner()?.commit()?;
+    if let Some(format) = stats {
+        print_statistics(core, main_timer, &output, compressed_len, false, format)?;
+    }
+    Ok(())
+}
+
+/// Prints the statistics of a finished operation and emits them as a `stats` event.
+///
+/// # Arguments
+///
+/// * `core` - The core the `stats` event is emitted to.
+/// * `main_timer` - The timer holding the sections of the operation.
+/// * `output` - The output file, whose length is the processed length.
+/// * `input_len` - The length of the input file.
+/// * `is_compression` - Whether the operation was the forward transform.
+fn print_statistics(
+    core: &core_header::CoreH,
+    main_timer: shared_files::stats::OptinalStatsTimer,
+    output: &File,
+    input_len: u64,
+    is_compression: bool,
+    format: StatsFormat,
+) -> io::Result<()> {
+    let peak_memory = main_timer.peak_memory();
+    let throughput = main_timer.throughput();
+    let (total_duration, sections) = main_timer.end();
+    let output_len = output.metadata()?.len();
+    let calculated_stats = shared_files::stats::CompressionStatsBuilder::new()
+        .algorithm_name("Columnar Transpose")
+        .algorithm_id(MODULE_ID)
+        .version_used(FORMAT_VERSION)
+        .original_len(input_len as usize)
+        .processed_len(output_len as usize)
+        .duration(total_duration)
+        .is_compression(is_compression)
+        .sections(sections)
+        .peak_memory(peak_memory)
+        .throughput(throughput)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build stats: {}", e));
+    println!("{}", calculated_stats.render(format));
+    events::emit(
+        core,
+        &Event::Stats {
+            stats: &calculated_stats,
+        },
+    );
+    Ok(())
+}